@@ -0,0 +1,7 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/aether.proto");
+        tonic_prost_build::compile_protos("proto/aether.proto").expect("Failed to compile proto/aether.proto");
+    }
+}
@@ -3,6 +3,7 @@ use anyhow::Result;
 use gstreamer as gst;
 use gstreamer_editing_services as ges;
 use crate::engine::editing::types::EditingError;
+use crate::modules::safe_mode;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EffectType {
@@ -136,6 +137,8 @@ impl Effect {
     
     pub fn create_ges_effect(&mut self) -> Result<ges::Effect, EditingError> {
         let effect_name = self.effect_type.to_gst_name();
+        let _load = safe_mode::begin_load(effect_name)
+            .map_err(|e| EditingError::EffectError(e.to_string()))?;
         let effect = ges::Effect::new(effect_name)?;
         
         for (name, value) in &self.parameters {
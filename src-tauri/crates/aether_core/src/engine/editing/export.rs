@@ -3,7 +3,10 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use gstreamer as gst;
 use gstreamer_editing_services as ges;
+use gst::prelude::*;
 use crate::engine::editing::types::EditingError;
+use crate::modules::path_policy;
+use crate::modules::pipeline_watchdog::{self, DEFAULT_SHUTDOWN_TIMEOUT};
 
 #[derive(Debug, Clone)]
 pub struct ExportOptions {
@@ -103,7 +106,8 @@ impl IntermediateExporter {
     }
     
     pub fn start_export(&mut self) -> Result<(), EditingError> {
-        let output_uri = gst::filename_to_uri(&self.options.output_path)?;
+        let output_uri = path_policy::path_to_uri(&self.options.output_path)
+            .map_err(|e| EditingError::ExportError(e.to_string()))?;
         
         let profile = self.create_encoding_profile()?;
         
@@ -111,7 +115,7 @@ impl IntermediateExporter {
         
         let filesink = gst::ElementFactory::make("filesink")
             .name("export_sink")
-            .property("location", &self.options.output_path.to_string_lossy().to_string())
+            .property("location", path_policy::gst_location(&self.options.output_path).map_err(|e| EditingError::ExportError(e.to_string()))?)
             .build()
             .map_err(|_| EditingError::ExportError("Failed to create filesink".to_string()))?;
         
@@ -278,7 +282,7 @@ impl IntermediateExporter {
 impl Drop for IntermediateExporter {
     fn drop(&mut self) {
         if let Some(pipeline) = &self.pipeline {
-            let _ = pipeline.set_state(gst::State::Null);
+            pipeline_watchdog::shutdown_pipeline(pipeline.upcast_ref(), DEFAULT_SHUTDOWN_TIMEOUT);
         }
     }
 }
@@ -0,0 +1,166 @@
+//! Clip freezing: pre-rendering a clip with its effects and grade to an intermediate file
+//!
+//! A frozen clip plays from its render instead of running its effect chain and grade on
+//! every preview frame. The live clip keeps its effects while set aside, so unfreezing
+//! puts it back exactly as it was.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use gstreamer as gst;
+use gstreamer_editing_services as ges;
+use gstreamer_pbutils as gst_pbutils;
+use gst::prelude::*;
+use ges::prelude::*;
+use crate::engine::editing::timeline::TimelineClip;
+use crate::engine::editing::types::EditingError;
+use crate::modules::color_grading::GradingPreset;
+use crate::modules::color_grading_frame_processor::ColorGradingFrameProcessor;
+use crate::modules::path_policy;
+
+/// Container of freeze renders
+const FREEZE_CONTAINER_CAPS: &str = "video/x-matroska";
+
+/// Intra-only, so every frame of the render can be seeked to directly
+const FREEZE_VIDEO_CAPS: &str = "image/jpeg";
+
+const FREEZE_AUDIO_CAPS: &str = "audio/x-flac";
+
+/// Pixel format grades are baked in, as in preview
+const GRADE_FORMAT: &str = "RGBA";
+
+/// A clip playing from its pre-render in place of the live clip
+#[derive(Clone)]
+pub struct FrozenClip {
+    /// The intermediate file
+    pub path: PathBuf,
+
+    /// Clip of the intermediate file, on the layer while frozen
+    pub ges_clip: ges::Clip,
+
+    /// Whether the clip's grade is part of the render, so preview mustn't apply it again
+    pub grade_baked: bool,
+}
+
+/// Intermediate file a clip is frozen to in `dir`
+pub fn freeze_path(dir: &Path, clip_id: &str) -> PathBuf {
+    dir.join(format!("frozen_{}.mkv", clip_id))
+}
+
+/// Render `clip` with its effects, and `grade` applied to its video, to `output`
+///
+/// The render starts at the clip's in point and lasts its duration. Nothing is left at
+/// `output` if it fails.
+pub(crate) fn render_frozen(
+    clip: &TimelineClip,
+    output: &Path,
+    grade: Option<(GradingPreset, Arc<ColorGradingFrameProcessor>)>,
+) -> Result<(), EditingError> {
+    let result = render(clip, output, grade);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn render(
+    clip: &TimelineClip,
+    output: &Path,
+    grade: Option<(GradingPreset, Arc<ColorGradingFrameProcessor>)>,
+) -> Result<(), EditingError> {
+    let asset = clip.ges_clip.asset()
+        .ok_or(EditingError::TimelineError(format!("Clip {} has no asset", clip.id)))?;
+
+    let timeline = ges::Timeline::new_audio_video()?;
+    let layer = timeline.append_layer();
+
+    let source = asset.extract()?
+        .downcast::<ges::Clip>()
+        .map_err(|_| EditingError::TimelineError("Failed to downcast to Clip".to_string()))?;
+    source.set_start(gst::ClockTime::ZERO);
+    source.set_inpoint(gst::ClockTime::from_nseconds(clip.in_point as u64));
+    source.set_duration(gst::ClockTime::from_nseconds(clip.duration as u64));
+    layer.add_clip(&source)?;
+
+    // The live effects stay on the live clip, so the render gets copies
+    for effect in &clip.effects {
+        let copy = ges::Effect::new(&effect.name)?;
+        source.add(&copy)?;
+        for (name, value) in &effect.parameters {
+            copy.set_property_from_str(name, value);
+        }
+    }
+
+    let grade_error = Arc::new(Mutex::new(None));
+    if let Some((preset, grading)) = grade {
+        let track = timeline.tracks().into_iter()
+            .find(|track| track.track_type() == ges::TrackType::VIDEO)
+            .ok_or(EditingError::TimelineError("Timeline has no video track".to_string()))?;
+        track.update_restriction_caps(&gst::Caps::builder("video/x-raw").field("format", GRADE_FORMAT).build());
+
+        let pad = timeline.pad_for_track(&track)
+            .ok_or(EditingError::TimelineError("Video track has no pad".to_string()))?;
+        let grade_error = grade_error.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &mut info.data {
+                let graded = buffer.make_mut().map_writable()
+                    .map_err(|e| anyhow::anyhow!("Failed to map frame: {}", e))
+                    .and_then(|mut frame| {
+                        let graded = grading.preview_preset(&frame, GRADE_FORMAT, &preset)?;
+                        frame.copy_from_slice(&graded);
+                        Ok(())
+                    });
+                if let Err(e) = graded {
+                    *grade_error.lock().unwrap() = Some(e.to_string());
+                    return gst::PadProbeReturn::Drop;
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    let pipeline = ges::Pipeline::new();
+    pipeline.set_timeline(&timeline)?;
+    let uri = path_policy::path_to_uri(output).map_err(|e| EditingError::ExportError(e.to_string()))?;
+    pipeline.set_render_settings(&uri, &encoding_profile())?;
+    pipeline.set_mode(ges::PipelineFlags::RENDER)?;
+
+    let bus = pipeline.bus().ok_or(EditingError::ExportError("Pipeline without bus".to_string()))?;
+    pipeline.set_state(gst::State::Playing)
+        .map_err(|e| EditingError::ExportError(format!("Failed to start freeze render: {}", e)))?;
+
+    let result = match bus.timed_pop_filtered(gst::ClockTime::NONE, &[gst::MessageType::Eos, gst::MessageType::Error]) {
+        Some(message) => match message.view() {
+            gst::MessageView::Error(err) => Err(EditingError::ExportError(format!(
+                "Freeze render failed: {} ({})", err.error(), err.debug().unwrap_or_default()
+            ))),
+            _ => Ok(()),
+        },
+        None => Err(EditingError::ExportError("Freeze render ended without EOS".to_string())),
+    };
+    let _ = pipeline.set_state(gst::State::Null);
+    result?;
+
+    match grade_error.lock().unwrap().take() {
+        Some(e) => Err(EditingError::ExportError(format!("Failed to grade freeze render: {}", e))),
+        None => Ok(()),
+    }
+}
+
+/// Matroska with MJPEG video and FLAC audio
+///
+/// Both streams are always rendered, as the frozen clip replaces all of the live one.
+fn encoding_profile() -> gst_pbutils::EncodingContainerProfile {
+    let video_profile = gst_pbutils::EncodingVideoProfile::builder(&gst::Caps::builder(FREEZE_VIDEO_CAPS).build())
+        .presence(1)
+        .build();
+    let audio_profile = gst_pbutils::EncodingAudioProfile::builder(&gst::Caps::builder(FREEZE_AUDIO_CAPS).build())
+        .presence(1)
+        .build();
+
+    gst_pbutils::EncodingContainerProfile::builder(&gst::Caps::builder(FREEZE_CONTAINER_CAPS).build())
+        .name("freeze")
+        .add_profile(video_profile)
+        .add_profile(audio_profile)
+        .build()
+}
@@ -3,10 +3,11 @@ use anyhow::{Result, Context};
 use gstreamer as gst;
 use gstreamer_pbutils as gst_pbutils;
 use gstreamer_editing_services as ges;
-use log::{debug, info, warn, error};
+use tracing::{debug, info, warn, error};
 use crate::engine::editing::types::{
     EditingError, MediaInfo, MediaType, VideoStreamInfo, AudioStreamInfo
 };
+use crate::modules::path_policy;
 
 #[derive(Debug, Clone)]
 pub struct ImportOptions {
@@ -51,6 +52,8 @@ impl MediaImporter {
     pub fn import_media<P: AsRef<Path>>(&mut self, path: P, options: Option<ImportOptions>) 
         -> Result<MediaInfo, EditingError> {
         let path = path.as_ref();
+        let span = tracing::info_span!("import", path = %path.display());
+        let _span = span.enter();
         
         // Try to canonicalize the path for consistent cache keys
         let path_canon = match std::fs::canonicalize(path) {
@@ -66,19 +69,8 @@ impl MediaImporter {
         
         debug!("Cache miss for media: {}", path_canon.display());
         
-        let uri = if path.is_absolute() {
-            gst::filename_to_uri(path)
-                .with_context(|| format!("Failed to create URI for path {}", path.display()))
-                .map_err(|e| EditingError::ImportError(e.to_string()))?
-        } else {
-            let abs_path = std::env::current_dir()
-                .with_context(|| "Failed to get current directory")
-                .map_err(|e| EditingError::ImportError(e.to_string()))?
-                .join(path);
-            gst::filename_to_uri(&abs_path)
-                .with_context(|| format!("Failed to create URI for absolute path {}", abs_path.display()))
-                .map_err(|e| EditingError::ImportError(e.to_string()))?
-        };
+        let uri = path_policy::path_to_uri(path)
+            .map_err(|e| EditingError::ImportError(e.to_string()))?;
         
         let options = options.unwrap_or_default();
         let media_info = if options.analyze {
@@ -368,18 +360,7 @@ impl MediaImporter {
         
         // Get the URI for the path
         let path = path.as_ref();
-        let uri = match if path.is_absolute() {
-            gst::filename_to_uri(path)
-        } else {
-            let abs_path = match std::env::current_dir() {
-                Ok(dir) => dir.join(path),
-                Err(e) => {
-                    error!("Failed to get current directory: {}", e);
-                    return None;
-                }
-            };
-            gst::filename_to_uri(&abs_path)
-        } {
+        let uri = match path_policy::path_to_uri(path) {
             Ok(uri) => uri,
             Err(e) => {
                 error!("Failed to create URI for path {}: {}", path.display(), e);
@@ -1,21 +1,36 @@
 mod timeline;
 mod import;
 mod preview;
+mod playhead;
 mod effects;
 mod export;
 mod types;
+mod projects;
+mod profiler;
+mod freeze;
 
 pub use timeline::{Timeline, TimelineTrack, TimelineClip, TimelineEffect};
 pub use import::{MediaImporter, ImportOptions};
-pub use preview::{PreviewEngine, PreviewFrame};
+pub use playhead::{PlayheadProbe, PlayheadStream, PlayheadUpdate, DEFAULT_PLAYHEAD_RATE};
+pub use preview::{negotiate_formats, FormatRequirement, FrameProcessor, MasterClock, PreviewEngine, PreviewFrame, PreviewStage, RGB_FORMATS};
 pub use effects::{Effect, EffectType, Transition, TransitionType};
 pub use export::{IntermediateExporter, ExportOptions, ExportProgress};
 pub use types::{EditingError, MediaInfo, ClipInfo, TrackType};
+pub use projects::{ProjectManager, ProjectId};
+pub use profiler::{RenderProfiler, ProfileReport, NodeProfile, EffectProfile};
+pub use freeze::{FrozenClip, freeze_path};
 
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use gstreamer as gst;
 use gstreamer_editing_services as ges;
+use gst::prelude::*;
+use crate::modules::color_grading::ColorGradingEngine;
+use crate::modules::color_grading_frame_processor::ColorGradingFrameProcessor;
+use crate::modules::display_calibration::DisplayTransform;
+use crate::modules::pipeline_watchdog::{self, DEFAULT_SHUTDOWN_TIMEOUT};
+use crate::modules::settings::EngineSettings;
 
 pub struct EditingEngine {
     ges_timeline: Option<ges::Timeline>,
@@ -27,6 +42,9 @@ pub struct EditingEngine {
     importer: Arc<Mutex<MediaImporter>>,
     preview_engine: Arc<Mutex<PreviewEngine>>,
     timeline: Arc<Mutex<Timeline>>,
+    
+    /// Grades preview frames with the grade of the clip on screen
+    grading: Arc<ColorGradingFrameProcessor>,
 }
 
 impl EditingEngine {
@@ -38,6 +56,11 @@ impl EditingEngine {
         let importer = Arc::new(Mutex::new(MediaImporter::new()?));
         let preview_engine = Arc::new(Mutex::new(PreviewEngine::new()?));
         let timeline = Arc::new(Mutex::new(Timeline::new()?));
+        let grading_engine = ColorGradingEngine::new()
+            .map_err(|e| EditingError::PreviewError(format!("Failed to create color grading engine: {}", e)))?;
+        let grading = Arc::new(ColorGradingFrameProcessor::new(grading_engine));
+        preview_engine.lock().unwrap()
+            .set_frame_stage(Some(clip_grade_stage(timeline.clone(), grading.clone())));
         
         Ok(Self {
             ges_timeline: None,
@@ -47,6 +70,7 @@ impl EditingEngine {
             importer,
             preview_engine,
             timeline,
+            grading,
         })
     }
     
@@ -80,6 +104,57 @@ impl EditingEngine {
         self.preview_engine.clone()
     }
     
+    /// Frame processor grading the preview; its LUT cache is shared by all clip grades
+    pub fn grading(&self) -> Arc<ColorGradingFrameProcessor> {
+        self.grading.clone()
+    }
+    
+    /// Calibrate the preview for the monitor it is shown on
+    ///
+    /// Uses the monitor's entry in `settings`, or no calibration if it has none.
+    /// Exports are never calibrated.
+    pub fn set_preview_monitor(&self, monitor: &str, settings: &EngineSettings) -> Result<(), EditingError> {
+        let transform = match settings.display_calibrations.get(monitor) {
+            Some(calibration) => {
+                let transform = DisplayTransform::load(calibration)
+                    .map_err(|e| EditingError::PreviewError(format!("Failed to load calibration for {}: {}", monitor, e)))?;
+                let transform = Arc::new(transform);
+                let processor: FrameProcessor = Arc::new(move |frame: &mut PreviewFrame, format: &str| {
+                    transform.apply(&mut frame.data, format)
+                });
+                Some(processor)
+            },
+            None => None,
+        };
+        
+        self.preview_engine.lock().unwrap().set_display_transform(transform);
+        Ok(())
+    }
+    
+    /// Start profiling preview playback, with effects labeled by their IDs
+    pub fn start_profiling(&self) -> Result<RenderProfiler, EditingError> {
+        let profiler = RenderProfiler::new();
+        self.timeline.lock().unwrap().label_effects(&profiler);
+        self.preview_engine.lock().unwrap().set_profiler(Some(profiler.clone()))?;
+        Ok(profiler)
+    }
+    
+    /// Stop profiling preview playback
+    pub fn stop_profiling(&self) -> Result<(), EditingError> {
+        self.preview_engine.lock().unwrap().set_profiler(None)
+    }
+    
+    /// Pre-render a clip with its effects and grade into `render_dir` and preview the render
+    /// in its place
+    pub fn freeze_clip(&self, clip_id: &str, render_dir: &Path) -> Result<PathBuf, EditingError> {
+        self.timeline.lock().unwrap().freeze_clip(clip_id, render_dir, self.grading.clone())
+    }
+    
+    /// Restore a frozen clip's live effects and grade
+    pub fn unfreeze_clip(&self, clip_id: &str) -> Result<(), EditingError> {
+        self.timeline.lock().unwrap().unfreeze_clip(clip_id)
+    }
+    
     pub fn create_intermediate_export(&self, options: ExportOptions) -> Result<IntermediateExporter, EditingError> {
         let exporter = IntermediateExporter::new(
             self.ges_timeline.clone().ok_or(EditingError::NotInitialized)?,
@@ -91,7 +166,7 @@ impl EditingEngine {
     
     pub fn shutdown(&mut self) -> Result<(), EditingError> {
         if let Some(pipeline) = &self.ges_pipeline {
-            let _ = pipeline.set_state(gst::State::Null);
+            pipeline_watchdog::shutdown_pipeline(pipeline.upcast_ref(), DEFAULT_SHUTDOWN_TIMEOUT);
         }
         
         self.ges_pipeline = None;
@@ -108,6 +183,30 @@ impl Drop for EditingEngine {
     }
 }
 
+/// Preview stage applying the grade of the clip at each frame's position
+///
+/// Only asks for RGB frames while some clip has a live grade, so ungraded timelines
+/// play in the display's own format.
+fn clip_grade_stage(timeline: Arc<Mutex<Timeline>>, grading: Arc<ColorGradingFrameProcessor>) -> PreviewStage {
+    let graded = timeline.clone();
+    let formats: FormatRequirement = Arc::new(move || {
+        let has_grades = graded.lock().map_or(true, |timeline| timeline.has_grades());
+        has_grades.then(|| RGB_FORMATS.iter().map(|format| format.to_string()).collect())
+    });
+    
+    let processor: FrameProcessor = Arc::new(move |frame: &mut PreviewFrame, format: &str| {
+        let grade = match timeline.lock() {
+            Ok(timeline) => timeline.grade_at(frame.pts).cloned(),
+            Err(_) => return Err(anyhow::anyhow!("Failed to lock timeline")),
+        };
+        if let Some(grade) = grade {
+            frame.data = grading.preview_preset(&frame.data, format, &grade)?;
+        }
+        Ok(())
+    });
+    PreviewStage::new(processor, formats)
+}
+
 pub fn create_editing_engine() -> Result<EditingEngine, EditingError> {
     EditingEngine::new()
 }
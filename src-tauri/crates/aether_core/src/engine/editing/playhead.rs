@@ -0,0 +1,146 @@
+//! Playhead position streamed to the UI
+//!
+//! Scrubbers and timeline autoscroll want the playhead many times a second. Polling
+//! `PreviewEngine::get_position` means taking the engine's lock each time, and waiting
+//! behind whatever else holds it. A `PlayheadStream` reads the position on a thread of
+//! its own at a fixed rate and sends it down a dedicated channel.
+
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use gstreamer as gst;
+use gstreamer_editing_services as ges;
+use gst::prelude::*;
+
+/// Updates per second UI scrubbers are normally given
+pub const DEFAULT_PLAYHEAD_RATE: f64 = 60.0;
+
+/// Updates a slow reader may fall behind by before new ones are dropped
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Reads the playhead: its position in nanoseconds and whether it is moving, or `None`
+/// if there is nothing to read yet
+pub type PlayheadProbe = Box<dyn FnMut() -> Option<(i64, bool)> + Send + 'static>;
+
+/// The playhead at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PlayheadUpdate {
+    /// Timeline position in nanoseconds
+    pub position: i64,
+
+    pub playing: bool,
+
+    /// When the position was read, in nanoseconds since the stream started, from a
+    /// monotonic clock; the UI extrapolates between updates from it
+    pub timestamp: u64,
+}
+
+/// Playhead updates at a fixed rate on a channel of their own
+///
+/// While playing every tick sends an update; while paused only changes are sent, such
+/// as a seek. A reader that falls behind misses updates rather than getting stale ones
+/// later. Stops when dropped.
+pub struct PlayheadStream {
+    receiver: Receiver<PlayheadUpdate>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl PlayheadStream {
+    /// Stream the position of whatever pipeline `pipeline` holds at the time
+    pub(crate) fn for_pipeline(pipeline: Arc<Mutex<Option<ges::Pipeline>>>, rate: f64) -> Self {
+        Self::with_probe(rate, Box::new(move || {
+            let pipeline = pipeline.lock().ok()?.clone()?;
+            let position = pipeline.query_position::<gst::ClockTime>()?;
+            Some((position.nseconds() as i64, pipeline.current_state() == gst::State::Playing))
+        }))
+    }
+
+    /// Stream with its own probe, read `rate` times a second; the first read happens at once
+    pub fn with_probe(rate: f64, mut probe: PlayheadProbe) -> Self {
+        let interval = Duration::from_secs_f64(1.0 / rate.clamp(1.0, 1000.0));
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("aether-playhead".to_string())
+                .spawn(move || {
+                    let started = Instant::now();
+                    let mut next_tick = started;
+                    let mut last: Option<(i64, bool)> = None;
+
+                    let (lock, condvar) = &*stop;
+                    let mut stopped = lock.lock().unwrap();
+                    while !*stopped {
+                        drop(stopped);
+                        if let Some((position, playing)) = probe() {
+                            if playing || last != Some((position, playing)) {
+                                let update = PlayheadUpdate {
+                                    position,
+                                    playing,
+                                    timestamp: started.elapsed().as_nanos() as u64,
+                                };
+                                if !send(&sender, update) {
+                                    return;
+                                }
+                                last = Some((position, playing));
+                            }
+                        }
+
+                        // Ticks keep to the rate however long the probe took
+                        next_tick += interval;
+                        let now = Instant::now();
+                        if next_tick < now {
+                            next_tick = now;
+                        }
+
+                        stopped = lock.lock().unwrap();
+                        if !*stopped {
+                            stopped = condvar.wait_timeout(stopped, next_tick - now).unwrap().0;
+                        }
+                    }
+                })
+                .expect("Failed to spawn playhead thread")
+        };
+
+        Self { receiver, stop, thread: Some(thread) }
+    }
+
+    /// Channel the updates arrive on
+    pub fn receiver(&self) -> &Receiver<PlayheadUpdate> {
+        &self.receiver
+    }
+
+    /// Wait up to `timeout` for the next update
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<PlayheadUpdate> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// The most recent update waiting, skipping older ones
+    pub fn latest(&self) -> Option<PlayheadUpdate> {
+        self.receiver.try_iter().last()
+    }
+
+    pub fn stop(&mut self) {
+        *self.stop.0.lock().unwrap() = true;
+        self.stop.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PlayheadStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Send without blocking; false once the receiver is gone
+fn send(sender: &SyncSender<PlayheadUpdate>, update: PlayheadUpdate) -> bool {
+    !matches!(sender.try_send(update), Err(TrySendError::Disconnected(_)))
+}
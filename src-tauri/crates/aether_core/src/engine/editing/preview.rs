@@ -1,11 +1,15 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::panic;
-use log::{error, warn, debug};
+use tracing::{error, warn, debug};
 use anyhow::Result;
 use gstreamer as gst;
 use gstreamer_video as gst_video;
 use gstreamer_editing_services as ges;
+use gst::prelude::*;
 use crate::engine::editing::types::EditingError;
+use crate::engine::editing::playhead::PlayheadStream;
+use crate::engine::editing::profiler::RenderProfiler;
+use crate::engine::framing::SafeAreaGuides;
 
 #[derive(Clone)]
 pub struct PreviewFrame {
@@ -18,6 +22,156 @@ pub struct PreviewFrame {
     pub pts: i64,
     
     pub duration: i64,
+    
+    /// Pixel format, such as `RGBA` or `NV12`; NV12 planes are stored without row padding
+    pub format: String,
+}
+
+/// Modifies preview frames before they are stored and handed to the frame callback
+///
+/// Receives the frame and its pixel format, such as `RGBA`. Runs on the streaming
+/// thread, so it should be quick; an error leaves the frame as it was.
+pub type FrameProcessor = Arc<dyn Fn(&mut PreviewFrame, &str) -> Result<()> + Send + Sync + 'static>;
+
+/// Pixel formats a preview stage accepts right now, best first, or `None` while it
+/// would leave every frame as it is
+///
+/// Asked again whenever the preview format is negotiated, so a stage that is only
+/// sometimes busy, like clip grading, doesn't force RGB frames on plain playback.
+pub type FormatRequirement = Arc<dyn Fn() -> Option<Vec<String>> + Send + Sync + 'static>;
+
+/// Packed RGB formats frame processors work on
+pub const RGB_FORMATS: [&str; 4] = ["RGB", "RGBA", "BGRx", "BGRA"];
+
+/// A frame processor and the formats it accepts
+#[derive(Clone)]
+pub struct PreviewStage {
+    pub processor: FrameProcessor,
+    
+    pub formats: FormatRequirement,
+}
+
+impl PreviewStage {
+    pub fn new(processor: FrameProcessor, formats: FormatRequirement) -> Self {
+        Self { processor, formats }
+    }
+    
+    /// Stage that always needs packed RGB frames
+    pub fn rgb(processor: FrameProcessor) -> Self {
+        Self::new(processor, Arc::new(|| Some(rgb_formats())))
+    }
+    
+    /// Formats the stage accepts right now, or `None` if it has nothing to do
+    pub fn accepts(&self) -> Option<Vec<String>> {
+        (self.formats)()
+    }
+}
+
+fn rgb_formats() -> Vec<String> {
+    RGB_FORMATS.iter().map(|format| format.to_string()).collect()
+}
+
+/// Formats the preview sink asks the decoders for
+///
+/// These are the display's formats, in its order of preference, that every busy stage
+/// accepts, so plain playback reaches the display without a conversion. When no format
+/// suits both, the frames get converted anyway and the stages win: their formats, the
+/// ones the display also takes first. Stages that share no format at all get packed RGB.
+pub fn negotiate_formats(display: &[String], stages: &[Vec<String>]) -> Vec<String> {
+    let accepted = |format: &String| stages.iter().all(|formats| formats.contains(format));
+    
+    let mut formats: Vec<String> = display.iter().filter(|format| accepted(format)).cloned().collect();
+    if !formats.is_empty() {
+        return formats;
+    }
+    
+    for format in stages.iter().flatten() {
+        if accepted(format) && !formats.contains(format) {
+            formats.push(format.clone());
+        }
+    }
+    if formats.is_empty() {
+        return rgb_formats();
+    }
+    formats.sort_by_key(|format| !display.contains(format));
+    formats
+}
+
+/// Clock the preview pipeline follows instead of choosing its own, with the base time of
+/// the run it joins
+///
+/// Pipelines sharing a clock and base time have the same running time, so frames and
+/// samples stamped for the same moment are shown together however long playback runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MasterClock {
+    pub clock: gst::Clock,
+    
+    pub base_time: gst::ClockTime,
+}
+
+/// Processing stages and the formats negotiated for them, shared with the appsink
+/// callback so both can change while the pipeline runs
+#[derive(Clone)]
+struct FormatNegotiation {
+    frame_processor: Arc<Mutex<Option<PreviewStage>>>,
+    
+    /// Monitor calibration, applied after `frame_processor`
+    display_transform: Arc<Mutex<Option<PreviewStage>>>,
+    
+    /// Guides drawn over the calibrated frame, such as safe areas
+    overlay: Arc<Mutex<Option<PreviewStage>>>,
+    
+    /// Formats the frame callback's consumer can show, best first
+    display_formats: Arc<Mutex<Vec<String>>>,
+    
+    /// Formats the appsink currently accepts
+    negotiated: Arc<Mutex<Vec<String>>>,
+}
+
+impl FormatNegotiation {
+    fn new() -> Self {
+        Self {
+            frame_processor: Arc::new(Mutex::new(None)),
+            display_transform: Arc::new(Mutex::new(None)),
+            overlay: Arc::new(Mutex::new(None)),
+            display_formats: Arc::new(Mutex::new(rgb_formats())),
+            negotiated: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    
+    /// Stages in the order they run
+    fn stages(&self) -> Vec<PreviewStage> {
+        [&self.frame_processor, &self.display_transform, &self.overlay]
+            .into_iter()
+            .filter_map(|slot| slot.lock().ok().and_then(|stage| stage.clone()))
+            .collect()
+    }
+    
+    fn formats(&self) -> Vec<String> {
+        let stages: Vec<Vec<String>> = self.stages().iter().filter_map(PreviewStage::accepts).collect();
+        let display = self.display_formats.lock().map(|formats| formats.clone()).unwrap_or_default();
+        negotiate_formats(&display, &stages)
+    }
+    
+    /// Negotiate again and, if the formats changed, have the pipeline switch to them
+    fn update(&self, appsink: &gst_app::AppSink) {
+        let formats = self.formats();
+        match self.negotiated.lock() {
+            Ok(mut negotiated) if *negotiated != formats => *negotiated = formats.clone(),
+            _ => return,
+        }
+        
+        debug!("Negotiated preview formats: {}", formats.join(", "));
+        let caps = gst::Caps::builder("video/x-raw")
+            .field("format", &gst::List::new(formats.iter().map(String::as_str)))
+            .build();
+        appsink.set_caps(Some(&caps));
+        
+        // Upstream picks new caps with its next buffer, converting only if it has to
+        if let Some(pad) = appsink.static_pad("sink") {
+            pad.push_event(gst::event::Reconfigure::new());
+        }
+    }
 }
 
 pub struct PreviewEngine {
@@ -31,6 +185,9 @@ pub struct PreviewEngine {
     
     frame_callback: Option<Arc<dyn Fn(PreviewFrame) + Send + Sync + 'static>>,
     
+    /// Frame processing stages and the pixel formats they need
+    negotiation: FormatNegotiation,
+    
     /// Stores the latest frame for asynchronous access
     latest_frame: Arc<std::sync::Mutex<Option<PreviewFrame>>>,
     
@@ -39,6 +196,16 @@ pub struct PreviewEngine {
     
     /// Video duration from the pipeline
     video_duration: Option<i64>,
+    
+    /// Profiler attached to each new pipeline
+    profiler: Option<RenderProfiler>,
+    
+    /// Clock playback follows, such as the audio engine's, instead of the pipeline's own
+    master_clock: Option<MasterClock>,
+    
+    /// The current pipeline, shared with playhead streams so they read it without this
+    /// engine's lock
+    playhead_pipeline: Arc<Mutex<Option<ges::Pipeline>>>,
 }
 
 impl PreviewEngine {
@@ -49,12 +216,30 @@ impl PreviewEngine {
             is_playing: false,
             position: 0,
             frame_callback: None,
+            negotiation: FormatNegotiation::new(),
             latest_frame: Arc::new(std::sync::Mutex::new(None)),
             video_dimensions: None,
             video_duration: None,
+            profiler: None,
+            master_clock: None,
+            playhead_pipeline: Arc::new(Mutex::new(None)),
         })
     }
     
+    /// Profile element processing time of the current and future preview pipelines
+    pub fn set_profiler(&mut self, profiler: Option<RenderProfiler>) -> Result<(), EditingError> {
+        if let Some(old) = self.profiler.take() {
+            old.detach();
+        }
+        
+        if let (Some(profiler), Some(pipeline)) = (&profiler, &self.pipeline) {
+            profiler.attach(pipeline.upcast_ref::<gst::Bin>())?;
+        }
+        
+        self.profiler = profiler;
+        Ok(())
+    }
+    
     pub fn set_pipeline(&mut self, pipeline: Option<ges::Pipeline>) -> Result<(), EditingError> {
         // Clean up existing resources first
         self.cleanup_resources();
@@ -62,6 +247,12 @@ impl PreviewEngine {
         // Set up new pipeline if provided
         if let Some(pipeline) = pipeline {
             self.setup_preview_pipeline(&pipeline)?;
+            if let Some(profiler) = &self.profiler {
+                profiler.attach(pipeline.upcast_ref::<gst::Bin>())?;
+            }
+            if let Ok(mut playhead_pipeline) = self.playhead_pipeline.lock() {
+                *playhead_pipeline = Some(pipeline.clone());
+            }
             self.pipeline = Some(pipeline);
         }
         
@@ -92,6 +283,9 @@ impl PreviewEngine {
         }
         
         // Clear our references
+        if let Ok(mut playhead_pipeline) = self.playhead_pipeline.lock() {
+            *playhead_pipeline = None;
+        }
         self.pipeline = None;
         self.video_sink = None;
         self.is_playing = false;
@@ -108,23 +302,43 @@ impl PreviewEngine {
         let appsink = video_sink.downcast_ref::<gst_app::AppSink>()
             .ok_or(EditingError::PreviewError("Failed to downcast to AppSink".to_string()))?;
         
-        // Support multiple pixel formats to reduce unnecessary conversions
-        let caps = gst::Caps::builder("video/x-raw")
-            .field("format", &gst::List::new(["RGB", "RGBA", "BGRx", "BGRA"]))
-            .build();
-        
-        appsink.set_caps(Some(&caps));
+        // Ask for the formats the display and the busy stages share, so plain
+        // playback skips the conversion to RGB
+        if let Ok(mut negotiated) = self.negotiation.negotiated.lock() {
+            negotiated.clear();
+        }
+        self.negotiation.update(appsink);
         appsink.set_drop(true);
         appsink.set_max_buffers(1);
         
         let callback = self.frame_callback.clone();
+        let negotiation = self.negotiation.clone();
         let latest_frame = self.latest_frame.clone();
         appsink.set_callbacks(
             gst_app::AppSinkCallbacks::builder()
                 .new_sample(move |appsink| {
                     if let Some(callback) = &callback {
                         if let Ok(sample) = appsink.pull_sample() {
-                            if let Some(frame) = extract_frame_from_sample(&sample) {
+                            if let Some(mut frame) = extract_frame_from_sample(&sample) {
+                                let format = frame.format.clone();
+                                let mut renegotiate = false;
+                                for stage in negotiation.stages() {
+                                    match stage.accepts() {
+                                        Some(formats) if formats.contains(&format) => {
+                                            if let Err(e) = (stage.processor)(&mut frame, &format) {
+                                                warn!("Preview frame processor failed: {}", e);
+                                            }
+                                        },
+                                        // A stage became busy since the last negotiation;
+                                        // this frame goes through without it
+                                        Some(_) => renegotiate = true,
+                                        None => {},
+                                    }
+                                }
+                                if renegotiate {
+                                    negotiation.update(appsink);
+                                }
+                                
                                 // Use catch_unwind to prevent callback panics from crashing the pipeline
                                 // Store the frame in latest_frame for asynchronous access
                                 if let Ok(mut latest_frame) = latest_frame.lock() {
@@ -160,10 +374,82 @@ impl PreviewEngine {
         self.frame_callback = Some(Arc::new(callback));
     }
     
+    /// Set or clear the processor applied to every preview frame, such as a grade
+    ///
+    /// The processor gets packed RGB frames; use `set_frame_stage` for one that
+    /// handles other formats or is sometimes idle.
+    pub fn set_frame_processor(&mut self, processor: Option<FrameProcessor>) {
+        self.set_frame_stage(processor.map(PreviewStage::rgb));
+    }
+    
+    /// Set or clear the first processing stage, with the formats it accepts
+    pub fn set_frame_stage(&mut self, stage: Option<PreviewStage>) {
+        if let Ok(mut current) = self.negotiation.frame_processor.lock() {
+            *current = stage;
+        }
+        self.renegotiate_formats();
+    }
+    
+    /// Set or clear the monitor calibration, applied to preview frames after the frame processor
+    pub fn set_display_transform(&mut self, transform: Option<FrameProcessor>) {
+        if let Ok(mut current) = self.negotiation.display_transform.lock() {
+            *current = transform.map(PreviewStage::rgb);
+        }
+        self.renegotiate_formats();
+    }
+    
+    /// Set or clear the overlay drawn on preview frames after calibration
+    pub fn set_overlay(&mut self, overlay: Option<FrameProcessor>) {
+        if let Ok(mut current) = self.negotiation.overlay.lock() {
+            *current = overlay.map(PreviewStage::rgb);
+        }
+        self.renegotiate_formats();
+    }
+    
+    /// Set the pixel formats the frame callback's consumer can show, best first
+    ///
+    /// Defaults to `RGB_FORMATS`. A display that takes `NV12` gets decoded frames as
+    /// they are whenever no stage needs RGB.
+    pub fn set_display_formats(&mut self, formats: Vec<String>) {
+        if let Ok(mut current) = self.negotiation.display_formats.lock() {
+            *current = formats;
+        }
+        self.renegotiate_formats();
+    }
+    
+    /// Formats the preview sink currently asks for, best first
+    pub fn negotiated_formats(&self) -> Vec<String> {
+        self.negotiation.negotiated.lock().map(|formats| formats.clone()).unwrap_or_default()
+    }
+    
+    /// Negotiate the preview format again, after a stage's requirement changed
+    ///
+    /// Stages are also checked on every frame, so this only saves the frames that would
+    /// go through unprocessed until a busy stage is noticed.
+    pub fn renegotiate_formats(&self) {
+        if let Some(appsink) = self.video_sink.as_ref().and_then(|sink| sink.downcast_ref::<gst_app::AppSink>()) {
+            self.negotiation.update(appsink);
+        }
+    }
+    
+    /// Show or hide safe-area guides on the preview
+    pub fn set_safe_area(&mut self, guides: Option<SafeAreaGuides>) {
+        let overlay = guides.map(|guides| -> FrameProcessor {
+            Arc::new(move |frame: &mut PreviewFrame, format: &str| {
+                guides.draw(&mut frame.data, frame.width, frame.height, format);
+                Ok(())
+            })
+        });
+        self.set_overlay(overlay);
+    }
+    
     pub fn play(&mut self) -> Result<(), EditingError> {
         let pipeline = self.pipeline.as_ref()
             .ok_or(EditingError::NotInitialized)?;
         
+        self.renegotiate_formats();
+        self.apply_master_clock(pipeline);
+        
         // Set state and wait for state change to complete
         pipeline.set_state(gst::State::Playing)?;
         
@@ -179,6 +465,34 @@ impl PreviewEngine {
         Ok(())
     }
     
+    /// Follow `clock` from the next `play`, or go back to the pipeline's own clock
+    ///
+    /// The base time has to be that of the master's current run: a master that pauses,
+    /// resumes or seeks starts a new run, and the preview has to be given it again.
+    pub fn set_master_clock(&mut self, clock: Option<MasterClock>) {
+        self.master_clock = clock;
+    }
+    
+    pub fn master_clock(&self) -> Option<&MasterClock> {
+        self.master_clock.as_ref()
+    }
+    
+    fn apply_master_clock(&self, pipeline: &ges::Pipeline) {
+        match &self.master_clock {
+            Some(master) => {
+                pipeline.use_clock(Some(&master.clock));
+                // Without a start time the pipeline keeps the base time it is given
+                // instead of picking its own when it starts playing
+                pipeline.set_start_time(gst::ClockTime::NONE);
+                pipeline.set_base_time(master.base_time);
+            },
+            None => {
+                pipeline.auto_clock();
+                pipeline.set_start_time(gst::ClockTime::ZERO);
+            },
+        }
+    }
+    
     pub fn pause(&mut self) -> Result<(), EditingError> {
         let pipeline = self.pipeline.as_ref()
             .ok_or(EditingError::NotInitialized)?;
@@ -236,6 +550,14 @@ impl PreviewEngine {
         self.is_playing
     }
     
+    /// Stream the playhead position `rate` times a second, such as `DEFAULT_PLAYHEAD_RATE`
+    ///
+    /// The stream follows pipeline changes and doesn't need this engine's lock, so UI
+    /// scrubbers can use it instead of polling `get_position`.
+    pub fn playhead_stream(&self, rate: f64) -> PlayheadStream {
+        PlayheadStream::for_pipeline(self.playhead_pipeline.clone(), rate)
+    }
+    
     pub fn get_frame(&self) -> Result<Option<PreviewFrame>, EditingError> {
         // Return a clone of the latest frame if available
         if let Ok(latest_frame) = self.latest_frame.lock() {
@@ -280,6 +602,12 @@ impl PreviewEngine {
     }
 }
 
+/// Pixel format of a sample's raw video caps, such as `RGBA`
+fn sample_format(sample: &gst::Sample) -> Option<String> {
+    let caps = sample.caps()?;
+    caps.structure(0)?.get::<String>("format").ok()
+}
+
 fn extract_frame_from_sample(sample: &gst::Sample) -> Option<PreviewFrame> {
     let buffer = sample.buffer()?;
     let caps = sample.caps()?;
@@ -287,9 +615,16 @@ fn extract_frame_from_sample(sample: &gst::Sample) -> Option<PreviewFrame> {
     
     let width = structure.get::<i32>("width").ok()? as u32;
     let height = structure.get::<i32>("height").ok()? as u32;
+    let format = sample_format(sample)?;
     
-    let map = buffer.map_readable().ok()?;
-    let data = map.as_slice().to_vec();
+    let data = if format == "NV12" {
+        let info = gst_video::VideoInfo::from_caps(caps).ok()?;
+        let frame = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info).ok()?;
+        copy_nv12(&frame, width as usize, height as usize)?
+    } else {
+        let map = buffer.map_readable().ok()?;
+        map.as_slice().to_vec()
+    };
     
     let pts = buffer.pts().map(|t| t.nseconds() as i64).unwrap_or(0);
     let duration = buffer.duration().map(|d| d.nseconds() as i64).unwrap_or(0);
@@ -300,5 +635,23 @@ fn extract_frame_from_sample(sample: &gst::Sample) -> Option<PreviewFrame> {
         data,
         pts,
         duration,
+        format,
     })
 }
+
+/// Copy an NV12 frame's luma plane and then its interleaved chroma plane, leaving out
+/// the row padding decoders add
+fn copy_nv12(frame: &gst_video::VideoFrameRef<&gst::BufferRef>, width: usize, height: usize) -> Option<Vec<u8>> {
+    let chroma_width = width.div_ceil(2) * 2;
+    let chroma_height = height.div_ceil(2);
+    let mut data = Vec::with_capacity(width * height + chroma_width * chroma_height);
+    
+    for (plane, row_bytes, rows) in [(0, width, height), (1, chroma_width, chroma_height)] {
+        let stride = frame.plane_stride()[plane as usize] as usize;
+        let pixels = frame.plane_data(plane).ok()?;
+        for row in 0..rows {
+            data.extend_from_slice(pixels.get(row * stride..row * stride + row_bytes)?);
+        }
+    }
+    Some(data)
+}
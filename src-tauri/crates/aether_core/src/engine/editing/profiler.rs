@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use gstreamer as gst;
+use gst::prelude::*;
+use tracing::debug;
+use crate::engine::editing::types::EditingError;
+
+/// Buffers in flight through one element, keyed by PTS; bounded so stalled entries can't grow forever
+const MAX_PENDING: usize = 64;
+
+/// Processing time measured for one pipeline element
+#[derive(Debug, Clone)]
+pub struct NodeProfile {
+    /// Element path in the pipeline
+    pub path: String,
+
+    /// Element factory name
+    pub factory: String,
+
+    /// Effect or other label the element belongs to
+    pub label: Option<String>,
+
+    /// Buffers measured
+    pub buffers: u64,
+
+    /// Total time spent processing buffers
+    pub total: Duration,
+
+    /// Slowest single buffer
+    pub max: Duration,
+}
+
+impl NodeProfile {
+    /// Mean time per buffer
+    pub fn mean(&self) -> Duration {
+        if self.buffers == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.buffers as u32
+        }
+    }
+}
+
+/// Processing time summed over all elements of an effect
+#[derive(Debug, Clone)]
+pub struct EffectProfile {
+    pub label: String,
+
+    /// Buffers measured on the effect's busiest element
+    pub buffers: u64,
+
+    pub total: Duration,
+
+    /// Mean time the effect adds to each frame
+    pub mean_per_buffer: Duration,
+
+    /// Share of all measured processing time, 0-100
+    pub percent: f64,
+}
+
+/// Profile of a preview or export run
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    /// Wall clock time since profiling started
+    pub elapsed: Duration,
+
+    /// Elements sorted by total processing time, slowest first
+    pub nodes: Vec<NodeProfile>,
+
+    /// Labeled effects sorted by total processing time, slowest first
+    pub effects: Vec<EffectProfile>,
+}
+
+impl ProfileReport {
+    /// The effect costing the most time, if any effects were labeled
+    pub fn most_expensive_effect(&self) -> Option<&EffectProfile> {
+        self.effects.first()
+    }
+}
+
+#[derive(Default)]
+struct NodeStats {
+    path: String,
+    factory: String,
+    label: Option<String>,
+    buffers: u64,
+    total: Duration,
+    max: Duration,
+    pending: HashMap<Option<u64>, Instant>,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    nodes: HashMap<String, NodeStats>,
+    labels: Vec<(gst::Element, String)>,
+    probes: Vec<(gst::Pad, gst::PadProbeId)>,
+    started: Option<Instant>,
+}
+
+/// Measures per-element and per-effect processing time in a running pipeline
+///
+/// A pad probe on each element's sink pad stamps buffers on the way in and one on the
+/// source pad measures how long they took to come out. Elements that change timestamps
+/// (rate conversion, encoders with reordering) only produce partial numbers.
+#[derive(Clone, Default)]
+pub struct RenderProfiler {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+impl RenderProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attribute an element and everything inside it to a label, e.g. an effect ID
+    ///
+    /// Call before `attach` so elements are labeled as they are instrumented.
+    pub fn label(&self, element: &gst::Element, label: &str) {
+        self.state.lock().unwrap().labels.push((element.clone(), label.to_string()));
+    }
+
+    /// Instrument every element of a pipeline, including ones added while it runs
+    pub fn attach(&self, pipeline: &gst::Bin) -> Result<(), EditingError> {
+        self.state.lock().unwrap().started = Some(Instant::now());
+
+        for element in pipeline.iterate_recurse().into_iter().flatten() {
+            self.instrument(&element);
+        }
+
+        // GES builds clip and effect elements lazily
+        let profiler = self.clone();
+        pipeline.connect_deep_element_added(move |_, _, element| {
+            profiler.instrument(element);
+        });
+
+        Ok(())
+    }
+
+    /// Remove all probes
+    pub fn detach(&self) {
+        let probes = std::mem::take(&mut self.state.lock().unwrap().probes);
+        for (pad, probe_id) in probes {
+            pad.remove_probe(probe_id);
+        }
+    }
+
+    /// Clear collected measurements, keeping probes in place
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.started = Some(Instant::now());
+        for stats in state.nodes.values_mut() {
+            stats.buffers = 0;
+            stats.total = Duration::ZERO;
+            stats.max = Duration::ZERO;
+            stats.pending.clear();
+        }
+    }
+
+    /// Build a report from the measurements so far
+    pub fn report(&self) -> ProfileReport {
+        let state = self.state.lock().unwrap();
+
+        let mut nodes: Vec<NodeProfile> = state.nodes.values()
+            .filter(|stats| stats.buffers > 0)
+            .map(|stats| NodeProfile {
+                path: stats.path.clone(),
+                factory: stats.factory.clone(),
+                label: stats.label.clone(),
+                buffers: stats.buffers,
+                total: stats.total,
+                max: stats.max,
+            })
+            .collect();
+        nodes.sort_by(|a, b| b.total.cmp(&a.total));
+
+        let measured: Duration = nodes.iter().map(|node| node.total).sum();
+
+        let mut by_label: HashMap<&str, (u64, Duration)> = HashMap::new();
+        for node in &nodes {
+            if let Some(label) = &node.label {
+                let entry = by_label.entry(label.as_str()).or_insert((0, Duration::ZERO));
+                entry.0 = entry.0.max(node.buffers);
+                entry.1 += node.total;
+            }
+        }
+
+        let mut effects: Vec<EffectProfile> = by_label.into_iter()
+            .map(|(label, (buffers, total))| EffectProfile {
+                label: label.to_string(),
+                buffers,
+                total,
+                mean_per_buffer: if buffers > 0 { total / buffers as u32 } else { Duration::ZERO },
+                percent: if measured.is_zero() { 0.0 } else { total.as_secs_f64() / measured.as_secs_f64() * 100.0 },
+            })
+            .collect();
+        effects.sort_by(|a, b| b.total.cmp(&a.total));
+
+        ProfileReport {
+            elapsed: state.started.map(|started| started.elapsed()).unwrap_or_default(),
+            nodes,
+            effects,
+        }
+    }
+
+    /// Add timing probes to a single element
+    fn instrument(&self, element: &gst::Element) {
+        // Only leaf elements with one input and one output can be timed this way
+        if element.is::<gst::Bin>() {
+            return;
+        }
+        let (sink_pad, src_pad) = match (element.static_pad("sink"), element.static_pad("src")) {
+            (Some(sink), Some(src)) => (sink, src),
+            _ => return,
+        };
+
+        let path = element.path_string().to_string();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.nodes.contains_key(&path) {
+                return;
+            }
+
+            let label = Self::find_label(&state.labels, element);
+            let factory = element.factory().map(|f| f.name().to_string()).unwrap_or_default();
+            debug!("Profiling {} ({}) label {:?}", path, factory, label);
+
+            state.nodes.insert(path.clone(), NodeStats {
+                path: path.clone(),
+                factory,
+                label,
+                ..NodeStats::default()
+            });
+        }
+
+        let state = self.state.clone();
+        let in_path = path.clone();
+        let sink_probe = sink_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(buffer) = info.buffer() {
+                let pts = buffer.pts().map(|pts| pts.nseconds());
+                if let Some(stats) = state.lock().unwrap().nodes.get_mut(&in_path) {
+                    if stats.pending.len() >= MAX_PENDING {
+                        stats.pending.clear();
+                    }
+                    stats.pending.insert(pts, Instant::now());
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        let state = self.state.clone();
+        let out_path = path;
+        let src_probe = src_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(buffer) = info.buffer() {
+                let pts = buffer.pts().map(|pts| pts.nseconds());
+                if let Some(stats) = state.lock().unwrap().nodes.get_mut(&out_path) {
+                    if let Some(entered) = stats.pending.remove(&pts) {
+                        let elapsed = entered.elapsed();
+                        stats.buffers += 1;
+                        stats.total += elapsed;
+                        stats.max = stats.max.max(elapsed);
+                    }
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(id) = sink_probe {
+            state.probes.push((sink_pad, id));
+        }
+        if let Some(id) = src_probe {
+            state.probes.push((src_pad, id));
+        }
+    }
+
+    /// Label of the nearest labeled ancestor of an element
+    fn find_label(labels: &[(gst::Element, String)], element: &gst::Element) -> Option<String> {
+        let mut current: Option<gst::Object> = Some(element.clone().upcast());
+        while let Some(object) = current {
+            if let Some((_, label)) = labels.iter().find(|(labeled, _)| labeled.upcast_ref::<gst::Object>() == &object) {
+                return Some(label.clone());
+            }
+            current = object.parent();
+        }
+        None
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use gstreamer_editing_services as ges;
+use gstreamer_editing_services::prelude::*;
+use tracing::{debug, info};
+use crate::engine::editing::EditingEngine;
+use crate::engine::editing::timeline::{Timeline, TimelineClip};
+use crate::engine::editing::types::EditingError;
+
+/// Identifier of an open project
+pub type ProjectId = String;
+
+/// Manages several open projects, each backed by its own EditingEngine
+pub struct ProjectManager {
+    projects: HashMap<ProjectId, Arc<Mutex<EditingEngine>>>,
+
+    active_project: Option<ProjectId>,
+
+    next_id: u64,
+}
+
+impl ProjectManager {
+    pub fn new() -> Self {
+        Self {
+            projects: HashMap::new(),
+            active_project: None,
+            next_id: 1,
+        }
+    }
+
+    /// Open a project in a new editing engine and make it active
+    pub fn open_project(&mut self, project_path: Option<String>) -> Result<ProjectId, EditingError> {
+        let mut engine = EditingEngine::new()?;
+        engine.init_project(project_path.clone())?;
+
+        let project_id = format!("project_{}", self.next_id);
+        self.next_id += 1;
+
+        self.projects.insert(project_id.clone(), Arc::new(Mutex::new(engine)));
+        self.active_project = Some(project_id.clone());
+
+        info!("Opened project {} ({:?})", project_id, project_path);
+
+        Ok(project_id)
+    }
+
+    /// Close a project and shut down its engine
+    pub fn close_project(&mut self, project_id: &str) -> Result<(), EditingError> {
+        let engine = self.projects.remove(project_id)
+            .ok_or(EditingError::InvalidParameter(format!("Project not found: {}", project_id)))?;
+
+        engine.lock().unwrap().shutdown()?;
+
+        if self.active_project.as_deref() == Some(project_id) {
+            self.active_project = self.projects.keys().next().cloned();
+        }
+
+        Ok(())
+    }
+
+    pub fn get_project(&self, project_id: &str) -> Result<Arc<Mutex<EditingEngine>>, EditingError> {
+        self.projects.get(project_id)
+            .cloned()
+            .ok_or(EditingError::InvalidParameter(format!("Project not found: {}", project_id)))
+    }
+
+    pub fn project_ids(&self) -> Vec<ProjectId> {
+        let mut ids: Vec<ProjectId> = self.projects.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn set_active_project(&mut self, project_id: &str) -> Result<(), EditingError> {
+        if !self.projects.contains_key(project_id) {
+            return Err(EditingError::InvalidParameter(format!("Project not found: {}", project_id)));
+        }
+
+        self.active_project = Some(project_id.to_string());
+
+        Ok(())
+    }
+
+    pub fn active_project(&self) -> Option<Arc<Mutex<EditingEngine>>> {
+        self.active_project.as_ref().and_then(|id| self.projects.get(id).cloned())
+    }
+
+    /// Copy clips from one project to another, keeping their effects and grades
+    ///
+    /// Clips keep their relative spacing; the earliest one lands at `dest_start`.
+    /// Returns the IDs of the new clips in the destination project.
+    pub fn copy_clips(&self,
+                      source_project: &str,
+                      clip_ids: &[String],
+                      dest_project: &str,
+                      dest_start: i64) -> Result<Vec<String>, EditingError> {
+        if source_project == dest_project {
+            return Err(EditingError::InvalidParameter(
+                "Source and destination project must differ".to_string()
+            ));
+        }
+
+        // Snapshot the source clips first so both timelines are never locked together
+        let clips: Vec<TimelineClip> = {
+            let source = self.get_project(source_project)?;
+            let source = source.lock().unwrap();
+            let timeline = source.timeline();
+            let timeline = timeline.lock().unwrap();
+
+            clip_ids.iter()
+                .map(|id| timeline.get_clip(id).cloned()
+                    .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", id))))
+                .collect::<Result<_, _>>()?
+        };
+
+        let dest = self.get_project(dest_project)?;
+        let dest = dest.lock().unwrap();
+        let timeline = dest.timeline();
+        let mut timeline = timeline.lock().unwrap();
+
+        Self::paste_clips(&mut timeline, &clips, dest_start)
+    }
+
+    /// Copy an entire sequence (every clip of the source timeline) into another project
+    pub fn copy_sequence(&self,
+                         source_project: &str,
+                         dest_project: &str,
+                         dest_start: i64) -> Result<Vec<String>, EditingError> {
+        let clip_ids: Vec<String> = {
+            let source = self.get_project(source_project)?;
+            let source = source.lock().unwrap();
+            let timeline = source.timeline();
+            let timeline = timeline.lock().unwrap();
+            timeline.get_clips().into_iter().map(|clip| clip.id).collect()
+        };
+
+        self.copy_clips(source_project, &clip_ids, dest_project, dest_start)
+    }
+
+    fn paste_clips(timeline: &mut Timeline, clips: &[TimelineClip], dest_start: i64) -> Result<Vec<String>, EditingError> {
+        let origin = clips.iter().map(|clip| clip.start_time).min().unwrap_or(0);
+        let mut new_ids = Vec::with_capacity(clips.len());
+
+        for clip in clips {
+            let uri = Self::clip_uri(clip)?;
+            let start_time = dest_start + (clip.start_time - origin);
+
+            let new_clip = timeline.add_clip(&uri, clip.track_type, start_time, clip.duration, clip.in_point)?;
+
+            // Recreate the effect chain with the same parameters
+            for effect in &clip.effects {
+                let new_effect = timeline.add_effect(&new_clip.id, &effect.name)?;
+                for (name, value) in &effect.parameters {
+                    timeline.set_effect_parameter(&new_clip.id, &new_effect.id, name, value)?;
+                }
+            }
+
+            timeline.set_clip_grade(&new_clip.id, clip.grade.clone())?;
+
+            debug!("Pasted clip {} as {}", clip.id, new_clip.id);
+            new_ids.push(new_clip.id);
+        }
+
+        Ok(new_ids)
+    }
+
+    fn clip_uri(clip: &TimelineClip) -> Result<String, EditingError> {
+        clip.ges_clip.downcast_ref::<ges::UriClip>()
+            .map(|uri_clip| uri_clip.uri().to_string())
+            .ok_or(EditingError::NotSupported(
+                format!("Clip {} has no source media and cannot be copied", clip.id)
+            ))
+    }
+
+    /// Shut down every open project
+    pub fn shutdown(&mut self) -> Result<(), EditingError> {
+        for (_, engine) in self.projects.drain() {
+            engine.lock().unwrap().shutdown()?;
+        }
+
+        self.active_project = None;
+
+        Ok(())
+    }
+}
+
+impl Default for ProjectManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
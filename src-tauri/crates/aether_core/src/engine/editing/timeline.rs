@@ -1,9 +1,19 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use gstreamer as gst;
 use gstreamer_editing_services as ges;
 use crate::engine::editing::types::{EditingError, ClipInfo, TrackType};
+use crate::engine::editing::freeze::{self, FrozenClip};
+use crate::engine::editing::profiler::RenderProfiler;
+use crate::modules::clip_log::SlateMetadata;
+use crate::modules::clip_loudness::{ClipLoudnessService, LoudnessBadgeThresholds};
+use crate::modules::color_grading::GradingPreset;
+use crate::modules::color_grading_frame_processor::ColorGradingFrameProcessor;
+use crate::modules::path_policy;
+use crate::modules::safe_mode;
 
 pub struct Timeline {
     ges_timeline: Option<ges::Timeline>,
@@ -118,6 +128,9 @@ impl Timeline {
             duration,
             in_point,
             effects: Vec::new(),
+            grade: None,
+            frozen: None,
+            slate: SlateMetadata::default(),
         };
         
         self.clips.insert(clip_id.clone(), timeline_clip.clone());
@@ -135,6 +148,9 @@ impl Timeline {
             .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
         
         clip.ges_clip.set_start(new_start_time);
+        if let Some(frozen) = &clip.frozen {
+            frozen.ges_clip.set_start(new_start_time);
+        }
         
         clip.start_time = new_start_time;
         
@@ -147,8 +163,7 @@ impl Timeline {
     }
     
     pub fn trim_clip(&mut self, clip_id: &str, new_duration: i64) -> Result<(), EditingError> {
-        let clip = self.clips.get_mut(clip_id)
-            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        let clip = self.live_clip_mut(clip_id)?;
         
         clip.ges_clip.set_duration(new_duration);
         
@@ -162,6 +177,9 @@ impl Timeline {
     pub fn split_clip(&mut self, clip_id: &str, position: i64) -> Result<String, EditingError> {
         let clip = self.clips.get(clip_id)
             .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        if clip.is_frozen() {
+            return Err(EditingError::InvalidParameter(format!("Clip is frozen, unfreeze it first: {}", clip_id)));
+        }
         
         if position <= clip.start_time || position >= clip.start_time + clip.duration {
             return Err(EditingError::InvalidParameter(
@@ -185,6 +203,9 @@ impl Timeline {
             duration: clip.duration - relative_position,
             in_point: clip.in_point + relative_position,
             effects: Vec::new(), // Effects need to be handled separately
+            grade: clip.grade.clone(),
+            frozen: None,
+            slate: clip.slate.clone(),
         };
         
         let left_clip = self.clips.get_mut(clip_id).unwrap();
@@ -196,9 +217,10 @@ impl Timeline {
     }
     
     pub fn add_effect(&mut self, clip_id: &str, effect_type: &str) -> Result<TimelineEffect, EditingError> {
-        let clip = self.clips.get_mut(clip_id)
-            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        let clip = self.live_clip_mut(clip_id)?;
         
+        let _load = safe_mode::begin_load(effect_type)
+            .map_err(|e| EditingError::EffectError(e.to_string()))?;
         let effect = ges::Effect::new(effect_type)?;
         
         clip.ges_clip.add(&effect)?;
@@ -216,28 +238,200 @@ impl Timeline {
         Ok(timeline_effect)
     }
     
+    /// Set a parameter on one of a clip's effects
+    pub fn set_effect_parameter(&mut self, clip_id: &str, effect_id: &str, name: &str, value: &str) -> Result<(), EditingError> {
+        let clip = self.live_clip_mut(clip_id)?;
+        
+        let effect = clip.effects.iter_mut()
+            .find(|effect| effect.id == effect_id)
+            .ok_or(EditingError::InvalidParameter(format!("Effect not found: {}", effect_id)))?;
+        
+        effect.set_parameter(name, value)
+    }
+    
+    /// Rename a clip
+    pub fn rename_clip(&mut self, clip_id: &str, name: &str) -> Result<(), EditingError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(EditingError::InvalidParameter("Clip name cannot be empty".to_string()));
+        }
+        
+        let clip = self.clips.get_mut(clip_id)
+            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        clip.name = name.to_string();
+        
+        Ok(())
+    }
+    
+    /// Set the reel, scene, shot and take of a clip
+    pub fn set_clip_slate(&mut self, clip_id: &str, slate: SlateMetadata) -> Result<(), EditingError> {
+        let clip = self.clips.get_mut(clip_id)
+            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        clip.slate = slate;
+        
+        Ok(())
+    }
+    
+    /// Set or clear the color grade of a clip
+    #[tracing::instrument(name = "grade", skip(self, grade))]
+    pub fn set_clip_grade(&mut self, clip_id: &str, grade: Option<GradingPreset>) -> Result<(), EditingError> {
+        let clip = self.live_clip_mut(clip_id)?;
+        
+        tracing::debug!("Setting grade: {:?}", grade.as_ref().map(|g| &g.name));
+        
+        clip.grade = grade;
+        
+        Ok(())
+    }
+    
+    /// Grade of the video clip showing at `position`
+    ///
+    /// Where clips overlap, the one that starts last is the one on screen. Frozen clips
+    /// have their grade baked into the render, so none is applied for them.
+    pub fn grade_at(&self, position: i64) -> Option<&GradingPreset> {
+        self.clips.values()
+            .filter(|clip| clip.track_type == TrackType::Video)
+            .filter(|clip| position >= clip.start_time && position < clip.start_time + clip.duration)
+            .max_by_key(|clip| clip.start_time)
+            .filter(|clip| !clip.frozen.as_ref().is_some_and(|frozen| frozen.grade_baked))
+            .and_then(|clip| clip.grade.as_ref())
+    }
+    
+    /// Whether any video clip has a grade the preview has to apply
+    pub fn has_grades(&self) -> bool {
+        self.clips.values()
+            .filter(|clip| clip.track_type == TrackType::Video)
+            .filter(|clip| !clip.frozen.as_ref().is_some_and(|frozen| frozen.grade_baked))
+            .any(|clip| clip.grade.is_some())
+    }
+    
+    /// Label every effect's pipeline elements with its effect ID for profiling
+    pub fn label_effects(&self, profiler: &RenderProfiler) {
+        // Effects of frozen clips aren't in the pipeline
+        for clip in self.clips.values().filter(|clip| !clip.is_frozen()) {
+            for effect in &clip.effects {
+                profiler.label(&effect.ges_effect.nleobject(), &effect.id);
+            }
+        }
+    }
+    
     pub fn remove_clip(&mut self, clip_id: &str) -> Result<(), EditingError> {
         let clip = self.clips.get(clip_id)
             .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
         
-        let layer = clip.ges_clip.get_layer()
+        let ges_clip = clip.frozen.as_ref().map_or(&clip.ges_clip, |frozen| &frozen.ges_clip);
+        let layer = ges_clip.get_layer()
             .ok_or(EditingError::TimelineError("Clip has no layer".to_string()))?;
         
-        layer.remove_clip(&clip.ges_clip)?;
+        layer.remove_clip(ges_clip)?;
         
-        self.clips.remove(clip_id);
+        if let Some(frozen) = self.clips.remove(clip_id).and_then(|clip| clip.frozen) {
+            let _ = fs::remove_file(&frozen.path);
+        }
         
         self.update_duration();
         
         Ok(())
     }
     
+    /// Pre-render a clip with its effects and grade into `render_dir` and play the render
+    /// in its place
+    ///
+    /// The live clip is taken off its layer with its effects intact. While frozen, the
+    /// clip can be moved or removed but not otherwise edited. Returns the render's path.
+    pub fn freeze_clip(&mut self, clip_id: &str, render_dir: &Path, grading: Arc<ColorGradingFrameProcessor>) -> Result<PathBuf, EditingError> {
+        let clip = self.clips.get(clip_id)
+            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        if clip.is_frozen() {
+            return Err(EditingError::InvalidParameter(format!("Clip is already frozen: {}", clip_id)));
+        }
+        
+        let layer = clip.ges_clip.layer()
+            .ok_or(EditingError::TimelineError("Clip has no layer".to_string()))?;
+        
+        fs::create_dir_all(render_dir)?;
+        let path = freeze::freeze_path(render_dir, clip_id);
+        let grade = match (&clip.grade, clip.track_type) {
+            (Some(grade), TrackType::Video) => Some((grade.clone(), grading)),
+            _ => None,
+        };
+        let grade_baked = grade.is_some();
+        freeze::render_frozen(clip, &path, grade)?;
+        
+        let substitute = Self::extract_render(&path)
+            .and_then(|substitute| {
+                substitute.set_start(clip.start_time);
+                substitute.set_duration(clip.duration);
+                layer.remove_clip(&clip.ges_clip)?;
+                if let Err(e) = layer.add_clip(&substitute) {
+                    layer.add_clip(&clip.ges_clip)?;
+                    return Err(e.into());
+                }
+                Ok(substitute)
+            })
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&path);
+            })?;
+        
+        self.clips.get_mut(clip_id).unwrap().frozen = Some(FrozenClip {
+            path: path.clone(),
+            ges_clip: substitute,
+            grade_baked,
+        });
+        self.commit();
+        
+        Ok(path)
+    }
+    
+    /// Put a frozen clip's live clip and effects back and delete its render
+    pub fn unfreeze_clip(&mut self, clip_id: &str) -> Result<(), EditingError> {
+        let clip = self.clips.get_mut(clip_id)
+            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        let frozen = clip.frozen.as_ref()
+            .ok_or(EditingError::InvalidParameter(format!("Clip is not frozen: {}", clip_id)))?;
+        
+        let layer = frozen.ges_clip.layer()
+            .ok_or(EditingError::TimelineError("Clip has no layer".to_string()))?;
+        layer.remove_clip(&frozen.ges_clip)?;
+        if let Err(e) = layer.add_clip(&clip.ges_clip) {
+            layer.add_clip(&frozen.ges_clip)?;
+            return Err(e.into());
+        }
+        
+        if let Some(frozen) = clip.frozen.take() {
+            let _ = fs::remove_file(&frozen.path);
+        }
+        self.commit();
+        
+        Ok(())
+    }
+    
     pub fn get_clips(&self) -> Vec<ClipInfo> {
         self.clips.values()
             .map(|clip| clip.to_clip_info())
             .collect()
     }
     
+    /// Clips with the loudness of what each audio clip plays and its badge
+    ///
+    /// Sources not measured yet are queued on `loudness`, and their clips have no
+    /// loudness until it calls back.
+    pub fn get_clips_with_loudness(&self, loudness: &ClipLoudnessService, thresholds: &LoudnessBadgeThresholds) -> Vec<ClipInfo> {
+        self.clips.values()
+            .map(|clip| {
+                let mut info = clip.to_clip_info();
+                if clip.track_type == TrackType::Audio {
+                    if let Some(path) = &info.source_path {
+                        let seconds = |nanos: i64| nanos as f64 / 1_000_000_000.0;
+                        info.loudness = loudness.clip_loudness(path, seconds(info.in_point), seconds(info.out_point));
+                        info.loudness_badge = info.loudness.and_then(|measured| measured.badge(thresholds));
+                    }
+                }
+                info
+            })
+            .collect()
+    }
+    
     pub fn get_clip(&self, clip_id: &str) -> Option<&TimelineClip> {
         self.clips.get(clip_id)
     }
@@ -262,6 +456,32 @@ impl Timeline {
     pub fn get_ges_timeline(&self) -> Option<&ges::Timeline> {
         self.ges_timeline.as_ref()
     }
+    
+    /// A clip that isn't frozen, for edits a render would go stale by
+    fn live_clip_mut(&mut self, clip_id: &str) -> Result<&mut TimelineClip, EditingError> {
+        let clip = self.clips.get_mut(clip_id)
+            .ok_or(EditingError::InvalidParameter(format!("Clip not found: {}", clip_id)))?;
+        if clip.is_frozen() {
+            return Err(EditingError::InvalidParameter(format!("Clip is frozen, unfreeze it first: {}", clip_id)));
+        }
+        Ok(clip)
+    }
+    
+    /// Clip of a freeze render
+    fn extract_render(path: &Path) -> Result<ges::Clip, EditingError> {
+        let uri = path_policy::path_to_uri(path).map_err(|e| EditingError::TimelineError(e.to_string()))?;
+        let asset = ges::UriClipAsset::request_sync(&uri)?;
+        asset.extract()?
+            .downcast::<ges::Clip>()
+            .map_err(|_| EditingError::TimelineError("Failed to downcast to Clip".to_string()))
+    }
+    
+    /// Apply layer changes to the running pipeline
+    fn commit(&self) {
+        if let Some(timeline) = &self.ges_timeline {
+            timeline.commit();
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -292,20 +512,43 @@ pub struct TimelineClip {
     pub in_point: i64,
     
     pub effects: Vec<TimelineEffect>,
+    
+    /// Color grade applied to this clip
+    pub grade: Option<GradingPreset>,
+    
+    /// Render playing in place of this clip while it is frozen
+    pub frozen: Option<FrozenClip>,
+    
+    /// Reel, scene, shot and take, for conform
+    pub slate: SlateMetadata,
 }
 
 impl TimelineClip {
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+    
+    /// Local file the clip plays, if it plays one
+    pub fn source_path(&self) -> Option<PathBuf> {
+        use ges::prelude::*;
+        let uri = self.ges_clip.downcast_ref::<ges::UriClip>()?.uri();
+        gst::glib::filename_from_uri(&uri).ok().map(|(path, _)| path)
+    }
+    
     pub fn to_clip_info(&self) -> ClipInfo {
         ClipInfo {
             id: self.id.clone(),
             name: self.name.clone(),
-            source_path: None, // Would need to extract from URI
+            source_path: self.source_path(),
             start_time: self.start_time,
             duration: self.duration,
             in_point: self.in_point,
             out_point: self.in_point + self.duration,
             track_type: self.track_type,
             effects: self.effects.iter().map(|e| e.to_effect_info()).collect(),
+            slate: self.slate.clone(),
+            loudness: None,
+            loudness_badge: None,
         }
     }
 }
@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 use thiserror::Error;
 use serde::{Serialize, Deserialize};
+use crate::modules::clip_log::SlateMetadata;
+use crate::modules::clip_loudness::{ClipLoudness, LoudnessBadge};
 
 #[derive(Error, Debug)]
 pub enum EditingError {
@@ -159,6 +161,18 @@ pub struct ClipInfo {
     pub track_type: TrackType,
     
     pub effects: Vec<EffectInfo>,
+    
+    /// Reel, scene, shot and take, for conform
+    #[serde(default)]
+    pub slate: SlateMetadata,
+    
+    /// Loudness of the source range an audio clip plays, once measured
+    #[serde(default)]
+    pub loudness: Option<ClipLoudness>,
+    
+    /// Warning for the timeline to show on the clip
+    #[serde(default)]
+    pub loudness_badge: Option<LoudnessBadge>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -0,0 +1,128 @@
+/// Motion is estimated on frames downscaled by this factor
+const ESTIMATION_SCALE: usize = 4;
+
+/// Block size for motion estimation, in downscaled pixels
+const BLOCK_SIZE: usize = 8;
+
+/// How far to search for a block's match, in downscaled pixels
+const SEARCH_RADIUS: isize = 4;
+
+/// Mix two same-sized frames of any packed format, `weight` being the share of `second`
+pub fn blend_frames(first: &[u8], second: &[u8], weight: f64) -> Vec<u8> {
+    let weight = weight.clamp(0.0, 1.0) as f32;
+
+    first.iter()
+        .zip(second)
+        .map(|(&a, &b)| (a as f32 * (1.0 - weight) + b as f32 * weight).round() as u8)
+        .collect()
+}
+
+/// Motion-compensated frame between two RGBA frames, `weight` being the position from
+/// `first` (0) to `second` (1)
+///
+/// Block motion is estimated on downscaled luma, then each output pixel mixes the pixels
+/// of both frames along its block's motion vector.
+pub fn interpolate_frames(first: &[u8], second: &[u8], width: usize, height: usize, weight: f64) -> Vec<u8> {
+    let weight = weight.clamp(0.0, 1.0);
+    if width == 0 || height == 0 || first.len() < width * height * 4 || second.len() < width * height * 4 {
+        return blend_frames(first, second, weight);
+    }
+
+    let small_width = (width / ESTIMATION_SCALE).max(1);
+    let small_height = (height / ESTIMATION_SCALE).max(1);
+    let a = downscale_luma(first, width, height, small_width, small_height);
+    let b = downscale_luma(second, width, height, small_width, small_height);
+    let blocks_x = small_width.div_ceil(BLOCK_SIZE);
+    let blocks_y = small_height.div_ceil(BLOCK_SIZE);
+
+    let vectors: Vec<(isize, isize)> = (0..blocks_y)
+        .flat_map(|by| (0..blocks_x).map(move |bx| (bx, by)))
+        .map(|(bx, by)| best_match(&a, &b, small_width, small_height, bx * BLOCK_SIZE, by * BLOCK_SIZE))
+        .collect();
+
+    let block_pixels = BLOCK_SIZE * ESTIMATION_SCALE;
+    let weight_f32 = weight as f32;
+    let mut output = vec![0u8; width * height * 4];
+
+    for y in 0..height {
+        for x in 0..width {
+            let block = (y / block_pixels).min(blocks_y - 1) * blocks_x + (x / block_pixels).min(blocks_x - 1);
+            let (dx, dy) = vectors[block];
+            let (dx, dy) = ((dx * ESTIMATION_SCALE as isize) as f64, (dy * ESTIMATION_SCALE as isize) as f64);
+
+            // Content at `first`'s p moves to p + d in `second`
+            let from_first = clamp_pixel(x as f64 - dx * weight, y as f64 - dy * weight, width, height);
+            let from_second = clamp_pixel(x as f64 + dx * (1.0 - weight), y as f64 + dy * (1.0 - weight), width, height);
+
+            let out = (y * width + x) * 4;
+            let i = from_first * 4;
+            let j = from_second * 4;
+            for c in 0..4 {
+                output[out + c] = (first[i + c] as f32 * (1.0 - weight_f32) + second[j + c] as f32 * weight_f32).round() as u8;
+            }
+        }
+    }
+
+    output
+}
+
+/// Average luma of RGBA frame cells
+fn downscale_luma(rgba: &[u8], width: usize, height: usize, small_width: usize, small_height: usize) -> Vec<f32> {
+    let mut luma = vec![0.0f32; small_width * small_height];
+
+    for sy in 0..small_height {
+        for sx in 0..small_width {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for y in (sy * ESTIMATION_SCALE)..((sy + 1) * ESTIMATION_SCALE).min(height) {
+                for x in (sx * ESTIMATION_SCALE)..((sx + 1) * ESTIMATION_SCALE).min(width) {
+                    let i = (y * width + x) * 4;
+                    sum += 0.299 * rgba[i] as f32 + 0.587 * rgba[i + 1] as f32 + 0.114 * rgba[i + 2] as f32;
+                    count += 1;
+                }
+            }
+            luma[sy * small_width + sx] = if count > 0 { sum / count as f32 } else { 0.0 };
+        }
+    }
+
+    luma
+}
+
+/// Offset of the block at (x0, y0) in `a` that best matches `b`, preferring no motion on ties
+fn best_match(a: &[f32], b: &[f32], width: usize, height: usize, x0: usize, y0: usize) -> (isize, isize) {
+    let x1 = (x0 + BLOCK_SIZE).min(width);
+    let y1 = (y0 + BLOCK_SIZE).min(height);
+
+    let cost = |dx: isize, dy: isize| -> f32 {
+        let mut sad = 0.0;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let bx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+                let by = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+                sad += (a[y * width + x] - b[by * width + bx]).abs();
+            }
+        }
+        sad
+    };
+
+    let mut best = (0, 0);
+    let mut best_cost = cost(0, 0);
+    for dy in -SEARCH_RADIUS..=SEARCH_RADIUS {
+        for dx in -SEARCH_RADIUS..=SEARCH_RADIUS {
+            let c = cost(dx, dy);
+            if c < best_cost {
+                best = (dx, dy);
+                best_cost = c;
+            }
+        }
+    }
+
+    best
+}
+
+/// Pixel index of the nearest in-frame position
+fn clamp_pixel(x: f64, y: f64, width: usize, height: usize) -> usize {
+    let x = (x.round() as isize).clamp(0, width as isize - 1) as usize;
+    let y = (y.round() as isize).clamp(0, height as isize - 1) as usize;
+    y * width + x
+}
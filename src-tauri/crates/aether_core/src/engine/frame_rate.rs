@@ -0,0 +1,355 @@
+//! Exact frame rates and 3:2 pulldown
+//!
+//! NTSC video runs at 1000/1001 of a whole rate, so 29.97 is really 30000/1001 and a
+//! rate kept as an `f64` or rounded to whole frames drifts from it by a frame every
+//! 33 seconds. `FrameRate` keeps the fraction, and frame numbers convert to and from
+//! seconds through it.
+//!
+//! Pulldown spreads 4 film frames over 5 interlaced video frames by holding them for
+//! 2, 3, 2 and 3 fields, taking 23.976 to 29.97 (or 24 to 30); removing it weaves the
+//! fields back into the original film frames.
+
+use std::collections::VecDeque;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Rates within this of an NTSC rate are taken to be it, as files often store 29.97
+/// rounded
+const NTSC_TOLERANCE: f64 = 0.01;
+
+/// A frame rate as the fraction `numerator / denominator` frames per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FrameRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl FrameRate {
+    pub const FPS_23_976: FrameRate = FrameRate::ntsc(24);
+    pub const FPS_24: FrameRate = FrameRate::whole(24);
+    pub const FPS_25: FrameRate = FrameRate::whole(25);
+    pub const FPS_29_97: FrameRate = FrameRate::ntsc(30);
+    pub const FPS_30: FrameRate = FrameRate::whole(30);
+    pub const FPS_50: FrameRate = FrameRate::whole(50);
+    pub const FPS_59_94: FrameRate = FrameRate::ntsc(60);
+    pub const FPS_60: FrameRate = FrameRate::whole(60);
+
+    /// Rates offered for a new project, in increasing order
+    pub const STANDARD: [FrameRate; 8] = [
+        FrameRate::FPS_23_976,
+        FrameRate::FPS_24,
+        FrameRate::FPS_25,
+        FrameRate::FPS_29_97,
+        FrameRate::FPS_30,
+        FrameRate::FPS_50,
+        FrameRate::FPS_59_94,
+        FrameRate::FPS_60,
+    ];
+
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        Self { numerator: numerator.max(1), denominator: denominator.max(1) }
+    }
+
+    /// `fps` whole frames per second
+    pub const fn whole(fps: u32) -> Self {
+        Self { numerator: if fps == 0 { 1 } else { fps }, denominator: 1 }
+    }
+
+    /// 1000/1001 of `timebase`, e.g. 29.97 for 30
+    pub const fn ntsc(timebase: u32) -> Self {
+        Self { numerator: (if timebase == 0 { 1 } else { timebase }) * 1000, denominator: 1001 }
+    }
+
+    /// Rate of a decimal frame rate, taking rates near 1000/1001 of a whole rate to be
+    /// that NTSC rate and other rates to the nearest thousandth of a frame
+    pub fn from_fps(fps: f64) -> Self {
+        if !fps.is_finite() || fps <= 0.0 {
+            return Self::whole(1);
+        }
+        let timebase = (fps * 1.001).round();
+        if timebase >= 1.0 && (fps - timebase / 1.001).abs() < NTSC_TOLERANCE && (fps - fps.round()).abs() > NTSC_TOLERANCE {
+            return Self::ntsc(timebase as u32);
+        }
+        if (fps - fps.round()).abs() < 1e-6 {
+            return Self::whole(fps.round() as u32);
+        }
+        Self::new((fps * 1000.0).round() as u32, 1000).reduced()
+    }
+
+    /// Parse `30000/1001`, `29.97` or `30`
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        match s.split_once('/') {
+            Some((numerator, denominator)) => {
+                let numerator = numerator.trim().parse::<u32>().ok()?;
+                let denominator = denominator.trim().parse::<u32>().ok()?;
+                (numerator > 0 && denominator > 0).then(|| Self::new(numerator, denominator))
+            },
+            None => s.parse::<f64>().ok().filter(|fps| *fps > 0.0).map(Self::from_fps),
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Frames counted per second of timecode: the rate rounded to whole frames
+    pub fn timebase(&self) -> u32 {
+        (self.as_f64().round() as u32).max(1)
+    }
+
+    /// Whether the rate is 1000/1001 of a whole rate
+    pub fn is_ntsc(&self) -> bool {
+        self.numerator % 1000 == 0 && self.denominator == 1001
+    }
+
+    pub fn is_whole(&self) -> bool {
+        self.denominator == 1 || self.numerator % self.denominator == 0
+    }
+
+    pub fn family(&self) -> FrameRateFamily {
+        if self.is_ntsc() {
+            FrameRateFamily::Ntsc
+        } else if !self.is_whole() {
+            FrameRateFamily::Other
+        } else {
+            match self.timebase() {
+                24 | 48 => FrameRateFamily::Film,
+                25 | 50 => FrameRateFamily::Pal,
+                _ => FrameRateFamily::Other,
+            }
+        }
+    }
+
+    /// Whether timecode at this rate can be drop-frame: 29.97 and 59.94
+    pub fn supports_drop_frame(&self) -> bool {
+        self.is_ntsc() && self.timebase() % 30 == 0
+    }
+
+    /// Length of one frame, in seconds
+    pub fn frame_duration(&self) -> f64 {
+        self.denominator as f64 / self.numerator as f64
+    }
+
+    /// Start of frame `frame`, in seconds
+    pub fn frames_to_seconds(&self, frame: u64) -> f64 {
+        (frame as u128 * self.denominator as u128) as f64 / self.numerator as f64
+    }
+
+    /// Frame nearest to `seconds`
+    pub fn seconds_to_frames(&self, seconds: f64) -> u64 {
+        (seconds.max(0.0) * self.numerator as f64 / self.denominator as f64).round() as u64
+    }
+
+    /// Rate as FFmpeg options take it, like `30000/1001`, or `25` when whole
+    pub fn to_ffmpeg_rate(&self) -> String {
+        if self.is_whole() {
+            self.timebase().to_string()
+        } else {
+            format!("{}/{}", self.numerator, self.denominator)
+        }
+    }
+
+    fn reduced(self) -> Self {
+        let (mut a, mut b) = (self.numerator, self.denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Self::new(self.numerator / a, self.denominator / a)
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self::FPS_30
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_whole() {
+            write!(f, "{}", self.timebase())
+        } else {
+            let text = format!("{:.3}", self.as_f64());
+            write!(f, "{}", text.trim_end_matches('0').trim_end_matches('.'))
+        }
+    }
+}
+
+/// Group of rates a project's frame rate belongs to
+///
+/// Rates in a family convert to each other by repeating frames or, between 23.976 and
+/// 29.97, by pulldown; moving a clip to another family needs a speed change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrameRateFamily {
+    /// 24 and 48
+    Film,
+    /// 25 and 50
+    Pal,
+    /// 23.976, 29.97 and 59.94
+    Ntsc,
+    /// 30, 60 and any other rate
+    Other,
+}
+
+impl FrameRateFamily {
+    /// Standard rates in the family
+    pub fn rates(&self) -> Vec<FrameRate> {
+        FrameRate::STANDARD.into_iter().filter(|rate| rate.family() == *self).collect()
+    }
+}
+
+/// Source frames whose fields make up one output frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSource {
+    pub top: u64,
+    pub bottom: u64,
+}
+
+impl FieldSource {
+    /// Whether both fields come from the same frame, so it can be used as it is
+    pub fn is_progressive(&self) -> bool {
+        self.top == self.bottom
+    }
+}
+
+/// 2:3 cadence of inserted pulldown, top field first: frames A, B, C and D become
+/// AA, BB, BC, CD and DD
+const INSERT_CADENCE: [(u64, u64); 5] = [(0, 0), (1, 1), (1, 2), (2, 3), (3, 3)];
+
+/// Film frames woven back from a 2:3 cadence: C is split across video frames 2 and 3
+const REMOVE_CADENCE: [(u64, u64); 4] = [(0, 0), (1, 1), (3, 2), (4, 4)];
+
+/// 3:2 pulldown applied on export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Pulldown {
+    /// Frames pass through at their own rate
+    #[default]
+    None,
+    /// Spread 23.976 or 24 fps frames over 29.97 or 30 fps interlaced frames
+    Insert,
+    /// Recover 23.976 or 24 fps frames from 29.97 or 30 fps telecined video; the input
+    /// must start on the A frame of the cadence
+    Remove,
+}
+
+impl Pulldown {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pulldown::None => "none",
+            Pulldown::Insert => "insert",
+            Pulldown::Remove => "remove",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Pulldown::None),
+            "insert" => Some(Pulldown::Insert),
+            "remove" => Some(Pulldown::Remove),
+            _ => None,
+        }
+    }
+
+    /// Rate of the output for an input at `input`, or `None` when the pulldown doesn't
+    /// apply to it
+    pub fn output_rate(&self, input: FrameRate) -> Option<FrameRate> {
+        match (self, input) {
+            (Pulldown::None, _) => Some(input),
+            (Pulldown::Insert, FrameRate::FPS_23_976) => Some(FrameRate::FPS_29_97),
+            (Pulldown::Insert, FrameRate::FPS_24) => Some(FrameRate::FPS_30),
+            (Pulldown::Remove, FrameRate::FPS_29_97) => Some(FrameRate::FPS_23_976),
+            (Pulldown::Remove, FrameRate::FPS_30) => Some(FrameRate::FPS_24),
+            _ => None,
+        }
+    }
+
+    /// Input frames per cycle of the cadence, and output frames they become
+    fn cycle(&self) -> (u64, u64) {
+        match self {
+            Pulldown::None => (1, 1),
+            Pulldown::Insert => (4, 5),
+            Pulldown::Remove => (5, 4),
+        }
+    }
+
+    /// Input frames providing the fields of output frame `frame`
+    pub fn source_fields(&self, frame: u64) -> FieldSource {
+        let (inputs, outputs) = self.cycle();
+        let (cycle, position) = (frame / outputs, (frame % outputs) as usize);
+        let (top, bottom) = match self {
+            Pulldown::None => (0, 0),
+            Pulldown::Insert => INSERT_CADENCE[position],
+            Pulldown::Remove => REMOVE_CADENCE[position],
+        };
+        FieldSource { top: cycle * inputs + top, bottom: cycle * inputs + bottom }
+    }
+
+    /// Output frames made from `frames` input frames; a partial cycle at the end keeps
+    /// only the frames it has both fields for
+    pub fn output_frames(&self, frames: u64) -> u64 {
+        let (inputs, outputs) = self.cycle();
+        let partial = (0..outputs)
+            .take_while(|position| {
+                let fields = self.source_fields(*position);
+                fields.top.max(fields.bottom) < frames % inputs
+            })
+            .count() as u64;
+        frames / inputs * outputs + partial
+    }
+}
+
+/// Applies a pulldown to frames arriving in order, holding back the few the cadence
+/// still needs
+pub struct PulldownQueue<T> {
+    pulldown: Pulldown,
+    /// Input frames still needed, with their numbers
+    frames: VecDeque<(u64, T)>,
+    next_input: u64,
+    next_output: u64,
+}
+
+impl<T: Clone> PulldownQueue<T> {
+    pub fn new(pulldown: Pulldown) -> Self {
+        Self { pulldown, frames: VecDeque::new(), next_input: 0, next_output: 0 }
+    }
+
+    /// Add the next input frame and return the output frames now complete
+    ///
+    /// `weave` makes a frame from the top field of its first argument and the bottom
+    /// field of its second; frames from a single input are passed on as they are.
+    pub fn push<F>(&mut self, frame: T, mut weave: F) -> Vec<T>
+    where
+        F: FnMut(&T, &T) -> T,
+    {
+        self.frames.push_back((self.next_input, frame));
+        self.next_input += 1;
+
+        if self.pulldown == Pulldown::None {
+            return self.frames.drain(..).map(|(_, frame)| frame).collect();
+        }
+
+        let mut output = Vec::new();
+        loop {
+            let fields = self.pulldown.source_fields(self.next_output);
+            if fields.top.max(fields.bottom) >= self.next_input {
+                break;
+            }
+            let find = |number: u64| &self.frames.iter().find(|(n, _)| *n == number).expect("needed frames are kept").1;
+            output.push(if fields.is_progressive() {
+                find(fields.top).clone()
+            } else {
+                weave(find(fields.top), find(fields.bottom))
+            });
+            self.next_output += 1;
+
+            // Inputs before the next output's fields are no longer needed
+            let next = self.pulldown.source_fields(self.next_output);
+            let keep_from = next.top.min(next.bottom);
+            while self.frames.front().is_some_and(|(n, _)| *n < keep_from) {
+                self.frames.pop_front();
+            }
+        }
+        output
+    }
+}
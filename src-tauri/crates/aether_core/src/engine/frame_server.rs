@@ -0,0 +1,370 @@
+//! Serves rendered timeline frames to other processes over a local TCP socket
+//!
+//! Protocol, all integers little-endian:
+//! - On connect the server sends a hello: magic `AEFS`, version `u16`, width `u32`,
+//!   height `u32`, fps `f64` and window `u32`.
+//! - A request is a `u8` opcode: `1` asks for a frame and is followed by a request id
+//!   `u32` and a timeline time in seconds `f64`; `2` closes the connection.
+//! - A response is a `u8` opcode: `1` is a frame, followed by request id `u32`, time `f64`,
+//!   width `u32`, height `u32`, length `u32` and that many bytes of packed RGBA; `2` is an
+//!   error, followed by request id `u32`, length `u32` and a UTF-8 message.
+//!
+//! Flow control: a client may have up to `window` requests unanswered. Further requests are
+//! only read as responses are written, so a client that stops reading stalls itself but
+//! never the render thread other clients share. Responses come back in request order.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::engine::editing::{EditingError, PreviewFrame};
+use crate::engine::timeline::Timeline;
+use crate::engine::timeline_backend::{create_timeline_backend, TimelineBackend, TimelineBackendConfig};
+use crate::modules::backend_policy;
+
+pub const PROTOCOL_VERSION: u16 = 1;
+
+const MAGIC: &[u8; 4] = b"AEFS";
+
+pub const REQUEST_FRAME: u8 = 1;
+pub const REQUEST_CLOSE: u8 = 2;
+
+pub const RESPONSE_FRAME: u8 = 1;
+pub const RESPONSE_ERROR: u8 = 2;
+
+/// How often the accept loop checks for shutdown
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Frame server settings
+#[derive(Debug, Clone)]
+pub struct FrameServerConfig {
+    /// Address to listen on; port 0 picks a free port
+    pub address: SocketAddr,
+    /// Unanswered requests allowed per client
+    pub window: u32,
+    /// Size and rate of the served frames
+    pub backend: TimelineBackendConfig,
+}
+
+impl Default for FrameServerConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            window: 4,
+            backend: TimelineBackendConfig::default(),
+        }
+    }
+}
+
+enum Response {
+    Frame { request_id: u32, time: f64, frame: PreviewFrame },
+    Error { request_id: u32, message: String },
+}
+
+/// Unanswered requests of one client
+struct Credits {
+    /// Requests in flight, and whether the client is gone
+    state: Mutex<(u32, bool)>,
+    changed: Condvar,
+}
+
+impl Credits {
+    /// Wait for room in the window; false once the client is gone
+    fn acquire(&self, window: u32) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while state.0 >= window && !state.1 {
+            state = self.changed.wait(state).unwrap();
+        }
+        state.0 += 1;
+        !state.1
+    }
+
+    fn release(&self) {
+        self.state.lock().unwrap().0 -= 1;
+        self.changed.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.changed.notify_all();
+    }
+}
+
+struct RenderRequest {
+    request_id: u32,
+    time: f64,
+    reply: SyncSender<Response>,
+}
+
+/// Renders frames of a timeline on request for external tools
+///
+/// All clients share one timeline backend, which lives on its own render thread.
+pub struct FrameServer {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    connections: Arc<Mutex<HashMap<SocketAddr, TcpStream>>>,
+    accept_thread: Option<thread::JoinHandle<()>>,
+    render_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl FrameServer {
+    /// Serve `timeline` through the backend the preview policy selects
+    pub fn start(timeline: Arc<Mutex<Timeline>>, config: FrameServerConfig) -> Result<Self, EditingError> {
+        let policy = backend_policy::global_policy();
+        Self::with_backend(config, move |backend_config| {
+            let mut backend = create_timeline_backend(&policy, backend_config.clone())?;
+            backend.load(timeline)?;
+            Ok(backend)
+        })
+    }
+
+    /// Serve frames from a backend created by `create` on the render thread
+    pub fn with_backend<F>(config: FrameServerConfig, create: F) -> Result<Self, EditingError>
+    where
+        F: FnOnce(&TimelineBackendConfig) -> Result<Box<dyn TimelineBackend>, EditingError> + Send + 'static,
+    {
+        let listener = TcpListener::bind(config.address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+
+        let (requests, request_receiver) = mpsc::channel::<RenderRequest>();
+        let (ready, ready_receiver) = mpsc::channel();
+        let backend_config = config.backend.clone();
+
+        let render_thread = thread::spawn(move || {
+            let mut backend = match create(&backend_config) {
+                Ok(backend) => {
+                    let _ = ready.send(Ok(()));
+                    backend
+                },
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                },
+            };
+
+            for request in request_receiver {
+                let response = match backend.render_frame(request.time) {
+                    Ok(frame) => Response::Frame { request_id: request.request_id, time: request.time, frame },
+                    Err(e) => Response::Error { request_id: request.request_id, message: e.to_string() },
+                };
+                // The client may have disconnected meanwhile
+                let _ = request.reply.send(response);
+            }
+
+            if let Err(e) = backend.shutdown() {
+                warn!("Failed to shut down frame server backend: {}", e);
+            }
+        });
+
+        ready_receiver.recv()
+            .map_err(|_| EditingError::PreviewError("Frame server render thread exited".to_string()))??;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let accept_thread = {
+            let running = running.clone();
+            let connections = connections.clone();
+            thread::spawn(move || Self::accept_loop(listener, requests, config, running, connections))
+        };
+
+        info!("Frame server listening on {}", address);
+        Ok(Self {
+            address,
+            running,
+            connections,
+            accept_thread: Some(accept_thread),
+            render_thread: Some(render_thread),
+        })
+    }
+
+    /// Address clients connect to
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Number of connected clients
+    pub fn client_count(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Disconnect all clients and stop serving
+    pub fn shutdown(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for stream in self.connections.lock().unwrap().values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+        if let Some(thread) = self.render_thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn accept_loop(
+        listener: TcpListener,
+        requests: Sender<RenderRequest>,
+        config: FrameServerConfig,
+        running: Arc<AtomicBool>,
+        connections: Arc<Mutex<HashMap<SocketAddr, TcpStream>>>,
+    ) {
+        let mut handlers = Vec::new();
+
+        while running.load(Ordering::SeqCst) {
+            let (stream, peer) = match listener.accept() {
+                Ok(connection) => connection,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                },
+                Err(e) => {
+                    warn!("Frame server failed to accept a connection: {}", e);
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                    continue;
+                },
+            };
+
+            debug!("Frame server client connected from {}", peer);
+            match stream.try_clone() {
+                Ok(handle) => connections.lock().unwrap().insert(peer, handle),
+                Err(e) => {
+                    warn!("Frame server dropped client {}: {}", peer, e);
+                    continue;
+                },
+            };
+
+            let requests = requests.clone();
+            let config = config.clone();
+            let connections = connections.clone();
+            handlers.push(thread::spawn(move || {
+                if let Err(e) = Self::serve_client(stream, requests, &config) {
+                    debug!("Frame server client {} disconnected: {}", peer, e);
+                }
+                connections.lock().unwrap().remove(&peer);
+            }));
+        }
+
+        for handler in handlers {
+            let _ = handler.join();
+        }
+    }
+
+    fn serve_client(stream: TcpStream, requests: Sender<RenderRequest>, config: &FrameServerConfig) -> io::Result<()> {
+        // Accepted sockets may inherit the listener's non-blocking mode
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+
+        let mut writer = stream.try_clone()?;
+        let mut hello = Vec::with_capacity(30);
+        hello.extend_from_slice(MAGIC);
+        hello.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        hello.extend_from_slice(&config.backend.width.to_le_bytes());
+        hello.extend_from_slice(&config.backend.height.to_le_bytes());
+        hello.extend_from_slice(&config.backend.fps.to_le_bytes());
+        hello.extend_from_slice(&config.window.to_le_bytes());
+        writer.write_all(&hello)?;
+
+        // A request holds a credit until its response is written, so the render thread
+        // never finds the reply channel full
+        let window = config.window.max(1);
+        let credits = Arc::new(Credits { state: Mutex::new((0, false)), changed: Condvar::new() });
+        let (reply, replies) = mpsc::sync_channel::<Response>(window as usize);
+        let writer_thread = {
+            let credits = credits.clone();
+            thread::spawn(move || {
+                for response in replies {
+                    if let Err(e) = write_response(&mut writer, &response) {
+                        debug!("Frame server failed to write a response: {}", e);
+                        break;
+                    }
+                    credits.release();
+                }
+                credits.close();
+            })
+        };
+
+        let mut reader = BufReader::new(stream);
+        let result = loop {
+            let mut opcode = [0u8; 1];
+            if reader.read_exact(&mut opcode).is_err() {
+                break Ok(());
+            }
+
+            match opcode[0] {
+                REQUEST_FRAME => {
+                    let request_id = read_u32(&mut reader)?;
+                    let time = f64::from_le_bytes(read_array(&mut reader)?);
+
+                    if !credits.acquire(window) {
+                        break Err(io::Error::new(io::ErrorKind::BrokenPipe, "Client stopped reading responses"));
+                    }
+                    if !time.is_finite() || time < 0.0 {
+                        let _ = reply.send(Response::Error { request_id, message: format!("Invalid time: {}", time) });
+                    } else if requests.send(RenderRequest { request_id, time, reply: reply.clone() }).is_err() {
+                        break Err(io::Error::new(io::ErrorKind::BrokenPipe, "Frame server stopped"));
+                    }
+                },
+                REQUEST_CLOSE => break Ok(()),
+                other => {
+                    credits.acquire(window);
+                    let _ = reply.send(Response::Error { request_id: 0, message: format!("Unknown request {}", other) });
+                    break Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown request {}", other)));
+                },
+            }
+        };
+
+        // Pending renders still hold reply senders, so the writer finishes their frames first
+        drop(reply);
+        let _ = writer_thread.join();
+        result
+    }
+}
+
+impl Drop for FrameServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn write_response(writer: &mut impl Write, response: &Response) -> io::Result<()> {
+    match response {
+        Response::Frame { request_id, time, frame } => {
+            let mut header = Vec::with_capacity(25);
+            header.push(RESPONSE_FRAME);
+            header.extend_from_slice(&request_id.to_le_bytes());
+            header.extend_from_slice(&time.to_le_bytes());
+            header.extend_from_slice(&frame.width.to_le_bytes());
+            header.extend_from_slice(&frame.height.to_le_bytes());
+            header.extend_from_slice(&(frame.data.len() as u32).to_le_bytes());
+            writer.write_all(&header)?;
+            writer.write_all(&frame.data)?;
+        },
+        Response::Error { request_id, message } => {
+            let mut bytes = Vec::with_capacity(9 + message.len());
+            bytes.push(RESPONSE_ERROR);
+            bytes.extend_from_slice(&request_id.to_le_bytes());
+            bytes.extend_from_slice(&(message.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(message.as_bytes());
+            writer.write_all(&bytes)?;
+        },
+    }
+    writer.flush()
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
@@ -0,0 +1,214 @@
+//! Placing clips whose aspect ratio differs from the timeline's, and the safe-area
+//! guides drawn over the preview
+//!
+//! `ScaleMode` picks the policy; `ScaleMode::Custom` adds a zoom and offset kept in the
+//! clip's properties. Placement is pure arithmetic so preview and tests agree on it.
+
+use crate::engine::timeline::{Clip, Timeline, TimelineError};
+use crate::engine::timeline_conform::{ScaleMode, SCALE_MODE_PROPERTY};
+use crate::modules::color_grading_frame_processor::channel_layout;
+
+/// Clip property with the `ScaleMode::Custom` zoom, relative to fitting the frame
+pub const CUSTOM_SCALE_PROPERTY: &str = "conform.scale.factor";
+
+/// Clip property with the `ScaleMode::Custom` horizontal offset, as a fraction of the frame width
+pub const CUSTOM_OFFSET_X_PROPERTY: &str = "conform.scale.x";
+
+/// Clip property with the `ScaleMode::Custom` vertical offset, as a fraction of the frame height
+pub const CUSTOM_OFFSET_Y_PROPERTY: &str = "conform.scale.y";
+
+/// Zoom and offset for `ScaleMode::Custom`
+///
+/// A scale of 1.0 fits the source inside the frame; offsets move its centre, so 0.5
+/// shifts it by half the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CustomFraming {
+    pub scale: f64,
+    pub offset_x: f64,
+    pub offset_y: f64,
+}
+
+impl Default for CustomFraming {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        }
+    }
+}
+
+impl CustomFraming {
+    /// Framing recorded in a clip's properties; missing values are left at the default
+    pub fn of(clip: &Clip) -> Self {
+        let value = |key: &str| clip.properties.get(key).and_then(|s| s.parse::<f64>().ok()).filter(|v| v.is_finite());
+        let default = Self::default();
+        Self {
+            scale: value(CUSTOM_SCALE_PROPERTY).filter(|scale| *scale > 0.0).unwrap_or(default.scale),
+            offset_x: value(CUSTOM_OFFSET_X_PROPERTY).unwrap_or(default.offset_x),
+            offset_y: value(CUSTOM_OFFSET_Y_PROPERTY).unwrap_or(default.offset_y),
+        }
+    }
+
+    /// Record the framing in a clip's properties
+    pub fn apply_to(&self, clip: &mut Clip) {
+        clip.properties.insert(CUSTOM_SCALE_PROPERTY.to_string(), self.scale.to_string());
+        clip.properties.insert(CUSTOM_OFFSET_X_PROPERTY.to_string(), self.offset_x.to_string());
+        clip.properties.insert(CUSTOM_OFFSET_Y_PROPERTY.to_string(), self.offset_y.to_string());
+    }
+}
+
+/// Where a source frame lands in the output frame, in output pixels
+///
+/// May extend past the output edges, as with `ScaleMode::Fill`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Place a `source` sized frame in an `output` sized one
+pub fn placement(mode: ScaleMode, custom: CustomFraming, source: (u32, u32), output: (u32, u32)) -> Placement {
+    let (source_width, source_height) = (source.0.max(1) as f64, source.1.max(1) as f64);
+    let (output_width, output_height) = (output.0 as f64, output.1 as f64);
+    let fit = (output_width / source_width).min(output_height / source_height);
+    let fill = (output_width / source_width).max(output_height / source_height);
+
+    let (scale_x, scale_y, offset_x, offset_y) = match mode {
+        ScaleMode::Fit => (fit, fit, 0.0, 0.0),
+        ScaleMode::Fill => (fill, fill, 0.0, 0.0),
+        ScaleMode::Stretch => (output_width / source_width, output_height / source_height, 0.0, 0.0),
+        ScaleMode::None => (1.0, 1.0, 0.0, 0.0),
+        ScaleMode::Custom => (
+            fit * custom.scale,
+            fit * custom.scale,
+            custom.offset_x * output_width,
+            custom.offset_y * output_height,
+        ),
+    };
+
+    let width = (source_width * scale_x).round().max(1.0);
+    let height = (source_height * scale_y).round().max(1.0);
+    Placement {
+        x: ((output_width - width) / 2.0 + offset_x).round() as i64,
+        y: ((output_height - height) / 2.0 + offset_y).round() as i64,
+        width: width as u32,
+        height: height as u32,
+    }
+}
+
+impl Timeline {
+    /// Set a clip to `ScaleMode::Custom` with the given zoom and offset
+    pub fn set_clip_framing(&mut self, track_id: &str, clip_id: &str, framing: CustomFraming) -> Result<(), TimelineError> {
+        if !(framing.scale.is_finite() && framing.scale > 0.0) || !framing.offset_x.is_finite() || !framing.offset_y.is_finite() {
+            return Err(TimelineError::OperationError(format!("Invalid framing: {:?}", framing)));
+        }
+
+        let clip = self.get_track_mut(track_id)?
+            .clips
+            .iter_mut()
+            .find(|clip| clip.id == clip_id)
+            .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+
+        clip.properties.insert(SCALE_MODE_PROPERTY.to_string(), ScaleMode::Custom.as_str().to_string());
+        framing.apply_to(clip);
+        Ok(())
+    }
+}
+
+/// Action and title safe outlines drawn over the preview, never into exports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SafeAreaGuides {
+    /// Share of the frame width and height inside the action-safe outline
+    pub action: f64,
+    /// Share of the frame width and height inside the title-safe outline
+    pub title: f64,
+    /// Draw a small cross at the centre of the frame
+    pub center_cross: bool,
+    /// RGBA
+    pub color: [u8; 4],
+}
+
+impl Default for SafeAreaGuides {
+    /// SMPTE ST 2046-1 areas for HD
+    fn default() -> Self {
+        Self {
+            action: 0.93,
+            title: 0.90,
+            center_cross: true,
+            color: [255, 255, 255, 160],
+        }
+    }
+}
+
+impl SafeAreaGuides {
+    /// The older 90% action and 80% title areas used for SD
+    pub fn legacy() -> Self {
+        Self {
+            action: 0.90,
+            title: 0.80,
+            ..Self::default()
+        }
+    }
+
+    /// Outline of an area as (left, top, right, bottom), inclusive, for a frame size
+    pub fn area(share: f64, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let share = share.clamp(0.0, 1.0);
+        let inset_x = ((width as f64 * (1.0 - share)) / 2.0).round() as u32;
+        let inset_y = ((height as f64 * (1.0 - share)) / 2.0).round() as u32;
+        (
+            inset_x.min(width.saturating_sub(1)),
+            inset_y.min(height.saturating_sub(1)),
+            width.saturating_sub(1 + inset_x),
+            height.saturating_sub(1 + inset_y),
+        )
+    }
+
+    /// Draw the guides onto a packed frame of `format`, such as `RGBA`
+    ///
+    /// Frames in a format without a known channel layout are left alone.
+    pub fn draw(&self, data: &mut [u8], width: u32, height: u32, format: &str) {
+        let Some((channels, pixel_size)) = channel_layout(format) else {
+            return;
+        };
+        if width == 0 || height == 0 || data.len() < width as usize * height as usize * pixel_size {
+            return;
+        }
+
+        let mut blend = |x: u32, y: u32| {
+            let pos = (y as usize * width as usize + x as usize) * pixel_size;
+            let alpha = self.color[3] as f32 / 255.0;
+            for (value, &channel) in self.color.iter().zip(&channels) {
+                let out = &mut data[pos + channel];
+                *out = ((1.0 - alpha) * *out as f32 + alpha * *value as f32) as u8;
+            }
+        };
+
+        for share in [self.action, self.title] {
+            let (left, top, right, bottom) = Self::area(share, width, height);
+            for x in left..=right {
+                blend(x, top);
+                blend(x, bottom);
+            }
+            for y in (top + 1)..bottom {
+                blend(left, y);
+                blend(right, y);
+            }
+        }
+
+        if self.center_cross {
+            let (cx, cy) = (width / 2, height / 2);
+            let arm = (width.min(height) / 40).max(2);
+            for x in cx.saturating_sub(arm)..=(cx + arm).min(width - 1) {
+                blend(x, cy);
+            }
+            for y in cy.saturating_sub(arm)..=(cy + arm).min(height - 1) {
+                if y != cy {
+                    blend(cx, y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Generated sources: test patterns, slates, countdowns and the media offline slate
+//!
+//! Generated clips (`ClipType::Generated`) have no source media; their `generator`
+//! property names what they show. Picture clips whose media is missing play as a media
+//! offline slate carrying the clip's name, so an edit with files gone astray still
+//! previews and shows where the gaps are. Most generators are stills; the countdown
+//! changes every frame, so it is drawn with `render_at`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+use crate::engine::bitmap_text;
+use crate::engine::rendering::SlateField;
+use crate::engine::timeline::{Clip, ClipType, Timeline};
+use crate::engine::timeline_search::CLIP_NAME_PROPERTY;
+use crate::modules::camera_card::Timecode;
+
+/// Property of a generated clip naming its generator, such as `color_bars`
+pub const GENERATOR_PROPERTY: &str = "generator";
+
+/// Properties of a slate clip holding its fields
+pub const SLATE_TITLE_PROPERTY: &str = "slate.title";
+pub const SLATE_DIRECTOR_PROPERTY: &str = "slate.director";
+pub const SLATE_DATE_PROPERTY: &str = "slate.date";
+pub const SLATE_TRT_PROPERTY: &str = "slate.trt";
+
+/// Length of a countdown from 8, in seconds
+pub const COUNTDOWN_DURATION: f64 = 8.0;
+
+/// Seconds before the end of a countdown the 2 flashes up for a frame, with the pop;
+/// the rest is black
+pub const COUNTDOWN_POP: f64 = 2.0;
+
+/// 75% bars, left to right
+const BARS: [[u8; 3]; 7] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+];
+
+/// Reverse bars under the main ones, for checking chroma
+const REVERSE_BARS: [[u8; 3]; 7] = [
+    [0, 0, 191],
+    [0, 0, 0],
+    [191, 0, 191],
+    [0, 0, 0],
+    [0, 191, 191],
+    [0, 0, 0],
+    [191, 191, 191],
+];
+
+/// What a generated clip shows
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Generator {
+    /// SMPTE color bars with a pluge
+    ColorBars,
+    /// Black to white ramp, left to right, for spotting banding
+    Gradient,
+    /// Grey squares eight rows high, for checking scaling and geometry
+    Checkerboard,
+    /// Slate standing in for missing media
+    MediaOffline { name: String },
+    /// Title card with the program's details, for the head of a delivery
+    Slate(Slate),
+    /// Academy style countdown to the end of the clip: a number each second over a
+    /// sweeping hand, down to 3, then the 2 for a single frame and black
+    Countdown,
+}
+
+/// Details shown on a slate; empty ones are left off
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Slate {
+    pub title: String,
+    pub director: String,
+    pub date: String,
+    /// Total running time, as timecode
+    pub trt: String,
+}
+
+impl Slate {
+    /// Slate for `timeline`, with its running time filled in
+    ///
+    /// The running time is drop-frame at 29.97 and 59.94, so it matches the clock.
+    pub fn for_timeline(title: &str, director: &str, date: &str, timeline: &Timeline) -> Self {
+        let rate = timeline.frame_rate();
+        let frames = rate.seconds_to_frames(timeline.duration());
+        Self {
+            title: title.to_string(),
+            director: director.to_string(),
+            date: date.to_string(),
+            trt: Timecode::from_frame(frames, rate, true).to_string(),
+        }
+    }
+
+    /// Lines of the slate, top to bottom
+    pub fn fields(&self) -> Vec<SlateField> {
+        [("Title", &self.title), ("Director", &self.director), ("Date", &self.date), ("TRT", &self.trt)]
+            .into_iter()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(label, value)| SlateField { label: label.to_string(), value: value.clone() })
+            .collect()
+    }
+
+    fn of(clip: &Clip) -> Self {
+        let field = |property: &str| clip.properties.get(property).cloned().unwrap_or_default();
+        Self {
+            title: field(SLATE_TITLE_PROPERTY),
+            director: field(SLATE_DIRECTOR_PROPERTY),
+            date: field(SLATE_DATE_PROPERTY),
+            trt: field(SLATE_TRT_PROPERTY),
+        }
+    }
+}
+
+impl Generator {
+    /// Value of `GENERATOR_PROPERTY`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Generator::ColorBars => "color_bars",
+            Generator::Gradient => "gradient",
+            Generator::Checkerboard => "checkerboard",
+            Generator::MediaOffline { .. } => "media_offline",
+            Generator::Slate(_) => "slate",
+            Generator::Countdown => "countdown",
+        }
+    }
+
+    /// The generator of a generated clip, if it names a known one
+    pub fn of(clip: &Clip) -> Option<Self> {
+        if clip.clip_type != ClipType::Generated {
+            return None;
+        }
+        match clip.properties.get(GENERATOR_PROPERTY)?.as_str() {
+            "color_bars" => Some(Generator::ColorBars),
+            "gradient" => Some(Generator::Gradient),
+            "checkerboard" => Some(Generator::Checkerboard),
+            "media_offline" => Some(Generator::MediaOffline { name: display_name(clip) }),
+            "slate" => Some(Generator::Slate(Slate::of(clip))),
+            "countdown" => Some(Generator::Countdown),
+            _ => None,
+        }
+    }
+
+    /// What plays for a clip instead of source media: the generator of a generated clip,
+    /// or the media offline slate for a picture clip whose media is missing
+    pub fn for_clip(clip: &Clip) -> Option<Self> {
+        match clip.clip_type {
+            ClipType::Generated => Self::of(clip),
+            ClipType::Video | ClipType::Image if media_missing(clip) => {
+                Some(Generator::MediaOffline { name: display_name(clip) })
+            },
+            _ => None,
+        }
+    }
+
+    /// A clip showing this generator, for inserting into a track
+    pub fn clip(&self, id: &str, start_time: f64, duration: f64) -> Clip {
+        let clip = Clip::new(id.to_string(), ClipType::Generated, start_time, duration)
+            .add_property(GENERATOR_PROPERTY.to_string(), self.as_str().to_string());
+        match self {
+            Generator::MediaOffline { name } => clip.add_property(CLIP_NAME_PROPERTY.to_string(), name.clone()),
+            Generator::Slate(slate) => clip
+                .add_property(SLATE_TITLE_PROPERTY.to_string(), slate.title.clone())
+                .add_property(SLATE_DIRECTOR_PROPERTY.to_string(), slate.director.clone())
+                .add_property(SLATE_DATE_PROPERTY.to_string(), slate.date.clone())
+                .add_property(SLATE_TRT_PROPERTY.to_string(), slate.trt.clone()),
+            _ => clip,
+        }
+    }
+
+    /// Whether the picture changes over the clip, so it has to be drawn per frame
+    pub fn is_animated(&self) -> bool {
+        matches!(self, Generator::Countdown)
+    }
+
+    /// Draw the generator into a new RGBA frame; for a countdown, its first frame
+    pub fn render(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut frame = vec![0u8; width as usize * height as usize * 4];
+        match self {
+            Generator::ColorBars => draw_bars(&mut frame, width, height),
+            Generator::Gradient => {
+                let last = width.saturating_sub(1).max(1);
+                fill_pixels(&mut frame, width, |x, _| {
+                    let value = (x * 255 / last) as u8;
+                    [value, value, value]
+                });
+            },
+            Generator::Checkerboard => {
+                let cell = (height / 8).max(1);
+                fill_pixels(&mut frame, width, |x, y| {
+                    if (x / cell + y / cell) % 2 == 0 { [192, 192, 192] } else { [64, 64, 64] }
+                });
+            },
+            Generator::MediaOffline { name } => draw_offline_slate(&mut frame, width, height, name),
+            Generator::Slate(slate) => draw_slate(&mut frame, width, height, slate),
+            Generator::Countdown => draw_countdown(&mut frame, width, height, COUNTDOWN_DURATION, 1.0),
+        }
+        frame
+    }
+
+    /// Draw the frame `time` seconds into a clip `duration` long at `frame_rate`
+    pub fn render_at(&self, width: u32, height: u32, time: f64, duration: f64, frame_rate: f64) -> Vec<u8> {
+        match self {
+            Generator::Countdown => {
+                let mut frame = vec![0u8; width as usize * height as usize * 4];
+                let frame_duration = 1.0 / frame_rate.max(1.0);
+                draw_countdown(&mut frame, width, height, duration - time, frame_duration);
+                frame
+            },
+            _ => self.render(width, height),
+        }
+    }
+
+    /// Parts of a clip `duration` long over which the picture holds, as (start, length)
+    /// in seconds: the whole clip for a still, each frame of a countdown until it goes
+    /// black
+    pub fn segments(&self, duration: f64, frame_rate: f64) -> Vec<(f64, f64)> {
+        if !self.is_animated() || duration <= 0.0 {
+            return vec![(0.0, duration.max(0.0))];
+        }
+        let frame_rate = frame_rate.max(1.0);
+        let frame_duration = 1.0 / frame_rate;
+        let black = duration - COUNTDOWN_POP + frame_duration / 2.0;
+        let mut segments = Vec::new();
+        let mut index = 0u64;
+        loop {
+            let start = index as f64 / frame_rate;
+            if start >= duration - frame_duration / 2.0 {
+                break;
+            }
+            if start >= black {
+                segments.push((start, duration - start));
+                break;
+            }
+            segments.push((start, frame_duration.min(duration - start)));
+            index += 1;
+        }
+        segments
+    }
+
+    /// PNG of the generator at `width` by `height` in `dir`, for backends that play
+    /// stills rather than frames; written the first time it is asked for
+    pub fn still(&self, dir: &Path, width: u32, height: u32) -> io::Result<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        self.write_still(dir, (width, height), hasher.finish(), || self.render(width, height))
+    }
+
+    /// PNG of the frame `time` seconds into a clip `duration` long, as `render_at` draws
+    /// it; the same as `still` for generators that aren't animated
+    pub fn still_at(&self, dir: &Path, width: u32, height: u32, time: f64, duration: f64, frame_rate: f64) -> io::Result<PathBuf> {
+        if !self.is_animated() {
+            return self.still(dir, width, height);
+        }
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        (time.to_bits(), duration.to_bits(), frame_rate.to_bits()).hash(&mut hasher);
+        self.write_still(dir, (width, height), hasher.finish(), || {
+            self.render_at(width, height, time, duration, frame_rate)
+        })
+    }
+
+    fn write_still(&self, dir: &Path, (width, height): (u32, u32), key: u64, draw: impl FnOnce() -> Vec<u8>) -> io::Result<PathBuf> {
+        let path = dir.join(format!("{}_{}x{}_{:016x}.png", self.as_str(), width, height, key));
+        if path.exists() {
+            return Ok(path);
+        }
+
+        fs::create_dir_all(dir)?;
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&draw()))
+            .map_err(io::Error::other)?;
+
+        // Renamed into place so a half-written still is never played
+        let partial = path.with_extension("png.partial");
+        fs::write(&partial, png)?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+}
+
+/// Whether a clip's source media is unset or not on disk
+pub fn media_missing(clip: &Clip) -> bool {
+    clip.source_path.as_ref().is_none_or(|path| !Path::new(path).exists())
+}
+
+/// Name shown on a slate for a clip: its name, else its source file's name, else its ID
+fn display_name(clip: &Clip) -> String {
+    clip.properties.get(CLIP_NAME_PROPERTY)
+        .cloned()
+        .or_else(|| {
+            let path = Path::new(clip.source_path.as_ref()?);
+            Some(path.file_name()?.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| clip.id.clone())
+}
+
+/// Set every pixel to an opaque color chosen by position
+fn fill_pixels(frame: &mut [u8], width: u32, color: impl Fn(u32, u32) -> [u8; 3]) {
+    for (index, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (index as u32 % width, index as u32 / width);
+        let [r, g, b] = color(x, y);
+        pixel.copy_from_slice(&[r, g, b, 255]);
+    }
+}
+
+/// SMPTE bars: seven bars over two thirds of the frame, a strip of reverse bars, then
+/// -I, white, +Q and black blocks with a pluge below
+fn draw_bars(frame: &mut [u8], width: u32, height: u32) {
+    let bars_bottom = height * 2 / 3;
+    let reverse_bottom = height * 3 / 4;
+    fill_pixels(frame, width, |x, y| {
+        // Position across the frame in 28ths, a quarter bar each
+        let quarter = (x * 28 / width.max(1)).min(27);
+        if y < bars_bottom {
+            BARS[(quarter / 4) as usize]
+        } else if y < reverse_bottom {
+            REVERSE_BARS[(quarter / 4) as usize]
+        } else {
+            match quarter {
+                0..=4 => [0, 33, 76],
+                5..=9 => [255, 255, 255],
+                10..=14 => [50, 0, 106],
+                // Pluge in the sixth bar: black, black, then just above black
+                _ if x * 21 / width.max(1) == 17 => [10, 10, 10],
+                _ => [0, 0, 0],
+            }
+        }
+    });
+}
+
+/// Dark slate with "MEDIA OFFLINE" in red and the clip's name under it
+fn draw_offline_slate(frame: &mut [u8], width: u32, height: u32, name: &str) {
+    fill_pixels(frame, width, |_, _| [24, 24, 24]);
+
+    let title = "MEDIA OFFLINE";
+    let title_scale = bitmap_text::fit_scale(title, width * 3 / 4, (height / 60).max(1));
+    let name_scale = bitmap_text::fit_scale(name, width * 9 / 10, (title_scale / 2).max(1));
+    let title_top = height as i64 * 2 / 5 - (bitmap_text::GLYPH_HEIGHT * title_scale) as i64 / 2;
+    let name_top = title_top + ((bitmap_text::GLYPH_HEIGHT + 4) * title_scale) as i64;
+
+    bitmap_text::draw_text_centered(frame, (width, height), title, title_top, title_scale, [220, 40, 40, 255]);
+    bitmap_text::draw_text_centered(frame, (width, height), name, name_top, name_scale, [230, 230, 230, 255]);
+}
+
+/// Black card with the slate's lines centered, labels in grey and values in white
+fn draw_slate(frame: &mut [u8], width: u32, height: u32, slate: &Slate) {
+    fill_pixels(frame, width, |_, _| [0, 0, 0]);
+
+    let fields = slate.fields();
+    let lines: Vec<String> = fields.iter()
+        .map(|field| format!("{}: {}", field.label, field.value))
+        .collect();
+    let longest = lines.iter().max_by_key(|line| line.chars().count()).map_or("", String::as_str);
+    let scale = bitmap_text::fit_scale(longest, width * 9 / 10, (height / 90).max(1));
+
+    // Lines a glyph and a half apart, centered on the frame as a block
+    let pitch = (bitmap_text::GLYPH_HEIGHT * scale * 3 / 2) as i64;
+    let block = pitch * (lines.len() as i64 - 1) + (bitmap_text::GLYPH_HEIGHT * scale) as i64;
+    let top = (height as i64 - block) / 2;
+    for (index, (field, line)) in fields.iter().zip(&lines).enumerate() {
+        let y = top + pitch * index as i64;
+        let x = (width as i64 - bitmap_text::text_width(line, scale) as i64) / 2;
+        // The label is drawn again in grey over the start of the line
+        let label = format!("{}:", field.label);
+        bitmap_text::draw_text(frame, (width, height), line, (x, y), scale, [255, 255, 255, 255]);
+        bitmap_text::draw_text(frame, (width, height), &label, (x, y), scale, [160, 160, 160, 255]);
+    }
+}
+
+/// A countdown frame `remaining` seconds before the end
+///
+/// Each second shows its number in two rings over crosshairs, with a lighter hand
+/// sweeping clockwise from 12 o'clock as the second runs out.
+fn draw_countdown(frame: &mut [u8], width: u32, height: u32, remaining: f64, frame_duration: f64) {
+    let half_frame = frame_duration / 2.0;
+    let (number, sweep) = if remaining > COUNTDOWN_POP + half_frame {
+        let number = remaining.ceil();
+        (number as u64, 1.0 - (remaining - (number - 1.0)))
+    } else if remaining > COUNTDOWN_POP - half_frame {
+        (COUNTDOWN_POP as u64, 0.0)
+    } else {
+        fill_pixels(frame, width, |_, _| [0, 0, 0]);
+        return;
+    };
+
+    let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+    let outer = width.min(height) as f64 * 0.4;
+    let inner = outer * 0.85;
+    let line = (height as f64 / 180.0).max(1.0);
+    let swept = sweep * std::f64::consts::TAU;
+    fill_pixels(frame, width, |x, y| {
+        let (dx, dy) = (x as f64 + 0.5 - center_x, y as f64 + 0.5 - center_y);
+        let distance = dx.hypot(dy);
+        let on_ring = (distance - outer).abs() < line / 2.0 || (distance - inner).abs() < line / 2.0;
+        let on_crosshair = dx.abs() <= line / 2.0 || dy.abs() <= line / 2.0;
+        if on_ring || on_crosshair {
+            return [255, 255, 255];
+        }
+        // Clockwise from 12 o'clock
+        let angle = dx.atan2(-dy).rem_euclid(std::f64::consts::TAU);
+        if distance < outer && angle < swept { [140, 140, 140] } else { [70, 70, 70] }
+    });
+
+    let text = number.to_string();
+    let scale = ((inner * 1.2) as u32 / bitmap_text::GLYPH_HEIGHT).max(1);
+    let top = (height as i64 - (bitmap_text::GLYPH_HEIGHT * scale) as i64) / 2;
+    bitmap_text::draw_text_centered(frame, (width, height), &text, top, scale, [0, 0, 0, 255]);
+}
@@ -1,11 +1,49 @@
 pub mod timeline;
+pub mod frame_rate;
+pub mod frame_interpolation;
+pub mod timeline_conform;
+pub mod framing;
+pub mod bitmap_text;
+pub mod generators;
+pub mod timeline_diff;
+pub mod timeline_validation;
+pub mod timeline_commands;
+pub mod timeline_macros;
+pub mod timeline_edits;
+pub mod timeline_search;
+pub mod timeline_navigation;
+pub mod timeline_ripple;
+pub mod timeline_attributes;
 pub mod renderer;
+pub mod tile_scheduler;
 pub mod video_decoder;
 pub mod integration;
 pub mod timeline_renderer;
+pub mod timeline_backend;
+pub mod frame_server;
+pub mod watch_render;
+pub mod visual_regression;
+#[cfg(any(test, feature = "simulation"))]
+pub mod simulation;
 
 
 pub use video_decoder::{VideoFormat, VideoFrame, MediaInfo, StreamInfo};
 pub use timeline_renderer::TimelineRenderer;
+pub use frame_rate::{FieldSource, FrameRate, FrameRateFamily, Pulldown, PulldownQueue};
+pub use timeline_conform::{ConversionOption, FormatMismatch, FrameRateConform, ScaleMode, SequenceSettings};
+pub use framing::{CustomFraming, Placement, SafeAreaGuides};
+pub use timeline_validation::{Diagnostic, DiagnosticKind, Severity, ValidationReport};
+pub use timeline_commands::{CommandHistory, TimelineCommand};
+pub use timeline_macros::{MacroArgs, TimelineMacro};
+pub use timeline_edits::{EditPoints, ResolvedEdit, SourceChannel, SourcePatch};
+pub use timeline_search::{SearchMatch, SearchResult};
+pub use timeline_navigation::MatchFrame;
+pub use timeline_ripple::{LinkConflict, LinkConflictReason};
+pub use timeline_attributes::{AttributeMask, ClipAttributes};
+pub use timeline_backend::{TimelineBackend, TimelineBackendKind, create_timeline_backend};
+pub use frame_server::{FrameServer, FrameServerConfig};
 pub use integration::IntegratedExporter;
 pub use renderer::Renderer;
+pub use tile_scheduler::{Band, TileScheduler};
+pub use watch_render::{ProjectWatcher, WatchOutput, WatchSession};
+pub use visual_regression::{Baseline, FrameHash, RegressionReport};
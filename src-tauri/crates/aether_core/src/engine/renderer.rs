@@ -2,6 +2,8 @@ use std::sync::{Arc, Mutex};
 use std::error::Error;
 use std::fmt;
 
+use crate::engine::tile_scheduler::TileScheduler;
+
 
 #[derive(Debug)]
 pub enum RendererError {
@@ -195,13 +197,13 @@ impl Renderer {
         }
         
         // Log initialization start
-        log::debug!("Initializing renderer with {}x{} resolution", self.config.width, self.config.height);
+        tracing::debug!("Initializing renderer with {}x{} resolution", self.config.width, self.config.height);
         
         // Initialize hardware acceleration if enabled
         if self.config.use_hardware_acceleration {
             self.initialize_hardware_acceleration()?;
         } else {
-            log::debug!("Using software rendering");
+            tracing::debug!("Using software rendering");
         }
         
         // Allocate frame buffers
@@ -211,35 +213,35 @@ impl Renderer {
         self.initialize_resources()?;
         
         self.is_initialized = true;
-        log::debug!("Renderer initialization complete");
+        tracing::debug!("Renderer initialization complete");
         Ok(())
     }
     
     /// Initialize hardware acceleration
     fn initialize_hardware_acceleration(&mut self) -> Result<(), RendererError> {
         let device = self.config.hw_device.as_deref().unwrap_or("auto");
-        log::info!("Initializing hardware acceleration with device: {}", device);
+        tracing::info!("Initializing hardware acceleration with device: {}", device);
         
         match device {
             "cuda" => {
-                log::debug!("Initializing CUDA acceleration");
+                tracing::debug!("Initializing CUDA acceleration");
                 self.initialize_cuda_acceleration()
             },
             "vaapi" => {
-                log::debug!("Initializing VAAPI acceleration");
+                tracing::debug!("Initializing VAAPI acceleration");
                 self.initialize_vaapi_acceleration()
             },
             "videotoolbox" => {
-                log::debug!("Initializing VideoToolbox acceleration");
+                tracing::debug!("Initializing VideoToolbox acceleration");
                 self.initialize_videotoolbox_acceleration()
             },
             "amf" => {
-                log::debug!("Initializing AMD AMF acceleration");
+                tracing::debug!("Initializing AMD AMF acceleration");
                 self.initialize_amf_acceleration()
             },
             _ => {
                 // Try to auto-detect the best hardware acceleration
-                log::debug!("Auto-detecting hardware acceleration");
+                tracing::debug!("Auto-detecting hardware acceleration");
                 self.auto_detect_acceleration()
             }
         }
@@ -287,7 +289,7 @@ impl Renderer {
                 // self.hw_context = Some(HardwareContext::Cuda { context });
             }
             
-            log::info!("CUDA acceleration initialized successfully");
+            tracing::info!("CUDA acceleration initialized successfully");
             Ok(())
         }
         
@@ -333,7 +335,7 @@ impl Renderer {
                 // self.hw_context = Some(HardwareContext::Vaapi { display });
             }
             
-            log::info!("VAAPI acceleration initialized successfully");
+            tracing::info!("VAAPI acceleration initialized successfully");
             Ok(())
         }
         
@@ -387,7 +389,7 @@ impl Renderer {
                 // self.hw_context = Some(HardwareContext::VideoToolbox { session });
             }
             
-            log::info!("VideoToolbox acceleration initialized successfully");
+            tracing::info!("VideoToolbox acceleration initialized successfully");
             Ok(())
         }
         
@@ -433,7 +435,7 @@ impl Renderer {
                 // self.hw_context = Some(HardwareContext::Amf { factory, context });
             }
             
-            log::info!("AMD AMF acceleration initialized successfully");
+            tracing::info!("AMD AMF acceleration initialized successfully");
             Ok(())
         }
         
@@ -459,14 +461,14 @@ impl Renderer {
             if self.has_nvidia_gpu() {
                 match self.initialize_cuda_acceleration() {
                     Ok(_) => return Ok(()),
-                    Err(e) => log::warn!("Failed to initialize CUDA: {}", e),
+                    Err(e) => tracing::warn!("Failed to initialize CUDA: {}", e),
                 }
             }
             
             if self.has_amd_gpu() {
                 match self.initialize_amf_acceleration() {
                     Ok(_) => return Ok(()),
-                    Err(e) => log::warn!("Failed to initialize AMF: {}", e),
+                    Err(e) => tracing::warn!("Failed to initialize AMF: {}", e),
                 }
             }
         }
@@ -477,20 +479,20 @@ impl Renderer {
             if self.has_vaapi_support() {
                 match self.initialize_vaapi_acceleration() {
                     Ok(_) => return Ok(()),
-                    Err(e) => log::warn!("Failed to initialize VAAPI: {}", e),
+                    Err(e) => tracing::warn!("Failed to initialize VAAPI: {}", e),
                 }
             }
             
             if self.has_nvidia_gpu() {
                 match self.initialize_cuda_acceleration() {
                     Ok(_) => return Ok(()),
-                    Err(e) => log::warn!("Failed to initialize CUDA: {}", e),
+                    Err(e) => tracing::warn!("Failed to initialize CUDA: {}", e),
                 }
             }
         }
         
         // Fallback to software rendering
-        log::info!("No hardware acceleration available, falling back to software rendering");
+        tracing::info!("No hardware acceleration available, falling back to software rendering");
         self.config.use_hardware_acceleration = false;
         Ok(())
     }
@@ -535,7 +537,7 @@ impl Renderer {
         
         // Calculate buffer size (RGBA = 4 bytes per pixel)
         let buffer_size = width * height * 4;
-        log::debug!("Allocating frame buffer of {} bytes", buffer_size);
+        tracing::debug!("Allocating frame buffer of {} bytes", buffer_size);
         
         // In a real implementation, we might pre-allocate buffers here
         // or set up GPU textures for rendering
@@ -545,7 +547,7 @@ impl Renderer {
     
     /// Initialize additional resources needed for rendering
     fn initialize_resources(&mut self) -> Result<(), RendererError> {
-        log::debug!("Initializing rendering resources");
+        tracing::debug!("Initializing rendering resources");
         
         // Initialize shader programs for GPU rendering
         if self.config.use_hardware_acceleration {
@@ -561,13 +563,13 @@ impl Renderer {
         // Initialize post-processing pipeline
         self.initialize_post_processing()?;
         
-        log::info!("Rendering resources initialized successfully");
+        tracing::info!("Rendering resources initialized successfully");
         Ok(())
     }
     
     /// Initialize shader programs for GPU rendering
     fn initialize_shader_programs(&mut self) -> Result<(), RendererError> {
-        log::debug!("Initializing shader programs");
+        tracing::debug!("Initializing shader programs");
         
         // In a real implementation, we would load and compile shader programs
         // For different hardware acceleration backends, we'd use different APIs:
@@ -613,17 +615,17 @@ impl Renderer {
             }
         } else {
             // Software rendering fallback
-            log::info!("No hardware context available, using software shaders");
+            tracing::info!("No hardware context available, using software shaders");
             // self.shaders = Some(Shaders::Software { functions: Vec::new() });
         }
         
-        log::debug!("Shader programs initialized");
+        tracing::debug!("Shader programs initialized");
         Ok(())
     }
     
     /// Initialize lookup tables for color grading and effects
     fn initialize_lookup_tables(&mut self) -> Result<(), RendererError> {
-        log::debug!("Initializing lookup tables");
+        tracing::debug!("Initializing lookup tables");
         
         // Create lookup tables for common operations
         // 1. Gamma correction LUT
@@ -666,13 +668,13 @@ impl Renderer {
             // color_3d: color_lut,
         });
         
-        log::debug!("Lookup tables initialized");
+        tracing::debug!("Lookup tables initialized");
         Ok(())
     }
     
     /// Allocate GPU resources for rendering
     fn allocate_gpu_resources(&mut self) -> Result<(), RendererError> {
-        log::debug!("Allocating GPU resources");
+        tracing::debug!("Allocating GPU resources");
         
         let width = self.config.width as usize;
         let height = self.config.height as usize;
@@ -752,13 +754,13 @@ impl Renderer {
                     
                     _ => {
                         // Fallback to CPU buffers
-                        log::warn!("Unknown hardware context type, falling back to CPU buffers");
+                        tracing::warn!("Unknown hardware context type, falling back to CPU buffers");
                         self.allocate_cpu_buffers(width, height)?;
                     }
                 }
             } else {
                 // No hardware context, fall back to CPU buffers
-                log::warn!("No hardware context available, falling back to CPU buffers");
+                tracing::warn!("No hardware context available, falling back to CPU buffers");
                 self.allocate_cpu_buffers(width, height)?;
             }
         } else {
@@ -766,7 +768,7 @@ impl Renderer {
             self.allocate_cpu_buffers(width, height)?;
         }
         
-        log::debug!("GPU resources allocated");
+        tracing::debug!("GPU resources allocated");
         Ok(())
     }
     
@@ -785,13 +787,13 @@ impl Renderer {
             output: output_buffer,
         });
         
-        log::debug!("CPU buffers allocated: {} bytes each", buffer_size);
+        tracing::debug!("CPU buffers allocated: {} bytes each", buffer_size);
         Ok(())
     }
     
     /// Initialize post-processing pipeline
     fn initialize_post_processing(&mut self) -> Result<(), RendererError> {
-        log::debug!("Initializing post-processing pipeline");
+        tracing::debug!("Initializing post-processing pipeline");
         
         // Create post-processing stages based on configuration
         let mut stages = Vec::new();
@@ -812,35 +814,35 @@ impl Renderer {
         
         self.post_process_pipeline = Some(PostProcessPipeline { stages });
         
-        log::debug!("Post-processing pipeline initialized with {} stages", stages.len());
+        tracing::debug!("Post-processing pipeline initialized with {} stages", stages.len());
         Ok(())
     }
     
     /// Clean up hardware acceleration resources
     fn cleanup_hardware_acceleration(&mut self) {
         if let Some(device) = &self.config.hw_device {
-            log::debug!("Cleaning up hardware acceleration resources for device: {}", device);
+            tracing::debug!("Cleaning up hardware acceleration resources for device: {}", device);
             
             // Cleanup logic would depend on the specific hardware acceleration API
             match device.as_str() {
                 "cuda" => {
                     // Release CUDA resources
-                    log::debug!("Releasing CUDA resources");
+                    tracing::debug!("Releasing CUDA resources");
                 },
                 "vaapi" => {
                     // Release VAAPI resources
-                    log::debug!("Releasing VAAPI resources");
+                    tracing::debug!("Releasing VAAPI resources");
                 },
                 "videotoolbox" => {
                     // Release VideoToolbox resources
-                    log::debug!("Releasing VideoToolbox resources");
+                    tracing::debug!("Releasing VideoToolbox resources");
                 },
                 "amf" => {
                     // Release AMD AMF resources
-                    log::debug!("Releasing AMD AMF resources");
+                    tracing::debug!("Releasing AMD AMF resources");
                 },
                 _ => {
-                    log::debug!("Releasing auto-detected hardware acceleration resources");
+                    tracing::debug!("Releasing auto-detected hardware acceleration resources");
                 }
             }
         }
@@ -848,7 +850,7 @@ impl Renderer {
     
     /// Clean up frame buffer resources
     fn cleanup_frame_buffers(&mut self) {
-        log::debug!("Cleaning up frame buffer resources");
+        tracing::debug!("Cleaning up frame buffer resources");
         
         // In a real implementation, we would release any pre-allocated buffers here
         // For example:
@@ -859,7 +861,7 @@ impl Renderer {
     
     /// Clean up any other rendering resources
     fn cleanup_resources(&mut self) {
-        log::debug!("Cleaning up additional rendering resources");
+        tracing::debug!("Cleaning up additional rendering resources");
         
         // Clean up any other resources that were allocated during initialization
         // For example:
@@ -911,6 +913,10 @@ impl Renderer {
     }
     
     /// Apply post-processing effects to the frame data
+    ///
+    /// Gamma, grading and vignette only look at their own pixel, so they run in one
+    /// pass over bands of the frame. Sharpening reads neighbouring rows, so it runs as a
+    /// second pass reading a copy of the first pass's output.
     fn apply_post_processing(&self, frame_data: &mut [u8]) -> Result<(), RendererError> {
         // Skip if the frame is empty
         if frame_data.is_empty() {
@@ -928,46 +934,64 @@ impl Renderer {
             ));
         }
         
-        // Apply gamma correction
-        self.apply_gamma_correction(frame_data, width, height);
+        let tiles = TileScheduler::global();
+        let gamma_table = Self::gamma_table(1.1);
         
-        // Apply color grading
-        self.apply_color_grading(frame_data, width, height);
+        tiles.for_each_band(frame_data, width * 4, height, |mut band| {
+            for row in 0..band.rows {
+                let y = band.first_row + row;
+                let pixels = band.row_mut(row);
+                
+                // Apply gamma correction
+                Self::apply_gamma_correction(pixels, &gamma_table);
+                
+                // Apply color grading
+                Self::apply_color_grading(pixels);
+                
+                // Apply vignette effect
+                Self::apply_vignette(pixels, y, width, height);
+            }
+        });
         
-        // Apply vignette effect
-        self.apply_vignette(frame_data, width, height);
+        if self.config.sharpen > 0.0 {
+            let source = frame_data[..width * height * 4].to_vec();
+            let amount = self.config.sharpen;
+            tiles.for_each_band(frame_data, width * 4, height, |mut band| {
+                for row in 0..band.rows {
+                    let y = band.first_row + row;
+                    Self::apply_sharpen(band.row_mut(row), &source, y, width, height, amount);
+                }
+            });
+        }
         
         Ok(())
     }
     
-    /// Apply gamma correction to the frame
-    fn apply_gamma_correction(&self, frame_data: &mut [u8], width: usize, height: usize) {
-        // Simple gamma correction with gamma = 1.1
-        let gamma = 1.1;
+    /// Gamma lookup table for 8-bit values
+    fn gamma_table(gamma: f32) -> [u8; 256] {
         let gamma_inv = 1.0 / gamma;
-        
-        // Create a gamma lookup table for efficiency
         let mut gamma_table = [0u8; 256];
-        for i in 0..256 {
+        for (i, value) in gamma_table.iter_mut().enumerate() {
             let normalized = i as f32 / 255.0;
             let corrected = normalized.powf(gamma_inv);
-            gamma_table[i] = (corrected * 255.0).clamp(0.0, 255.0) as u8;
+            *value = (corrected * 255.0).clamp(0.0, 255.0) as u8;
         }
-        
+        gamma_table
+    }
+    
+    /// Apply gamma correction to a row of RGBA pixels
+    fn apply_gamma_correction(pixels: &mut [u8], gamma_table: &[u8; 256]) {
         // Apply gamma correction to RGB channels (not alpha)
-        for y in 0..height {
-            for x in 0..width {
-                let idx = (y * width + x) * 4;
-                frame_data[idx] = gamma_table[frame_data[idx] as usize];       // R
-                frame_data[idx + 1] = gamma_table[frame_data[idx + 1] as usize]; // G
-                frame_data[idx + 2] = gamma_table[frame_data[idx + 2] as usize]; // B
-                // Alpha channel remains unchanged
-            }
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[0] = gamma_table[pixel[0] as usize]; // R
+            pixel[1] = gamma_table[pixel[1] as usize]; // G
+            pixel[2] = gamma_table[pixel[2] as usize]; // B
+            // Alpha channel remains unchanged
         }
     }
     
-    /// Apply color grading to the frame
-    fn apply_color_grading(&self, frame_data: &mut [u8], width: usize, height: usize) {
+    /// Apply color grading to a row of RGBA pixels
+    fn apply_color_grading(pixels: &mut [u8]) {
         // Color grading parameters (these could come from the renderer config)
         let saturation = 1.1; // Slightly increase saturation
         let contrast = 1.05;  // Slightly increase contrast
@@ -978,48 +1002,44 @@ impl Renderer {
         let temp_g = 1.0;  // Keep green the same
         let temp_b = 0.95; // Decrease blue slightly
         
-        for y in 0..height {
-            for x in 0..width {
-                let idx = (y * width + x) * 4;
-                
-                // Get RGB values
-                let mut r = frame_data[idx] as f32 / 255.0;
-                let mut g = frame_data[idx + 1] as f32 / 255.0;
-                let mut b = frame_data[idx + 2] as f32 / 255.0;
-                
-                // Apply contrast
-                r = ((r - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
-                g = ((g - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
-                b = ((b - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
-                
-                // Apply brightness
-                r = (r * brightness).clamp(0.0, 1.0);
-                g = (g * brightness).clamp(0.0, 1.0);
-                b = (b * brightness).clamp(0.0, 1.0);
-                
-                // Apply saturation (convert to HSL, adjust S, convert back)
-                let (h, s, l) = self.rgb_to_hsl(r, g, b);
-                let (r_new, g_new, b_new) = self.hsl_to_rgb(h, (s * saturation).clamp(0.0, 1.0), l);
-                
-                r = r_new;
-                g = g_new;
-                b = b_new;
-                
-                // Apply color temperature
-                r = (r * temp_r).clamp(0.0, 1.0);
-                g = (g * temp_g).clamp(0.0, 1.0);
-                b = (b * temp_b).clamp(0.0, 1.0);
-                
-                // Write back to frame data
-                frame_data[idx] = (r * 255.0) as u8;
-                frame_data[idx + 1] = (g * 255.0) as u8;
-                frame_data[idx + 2] = (b * 255.0) as u8;
-            }
+        for pixel in pixels.chunks_exact_mut(4) {
+            // Get RGB values
+            let mut r = pixel[0] as f32 / 255.0;
+            let mut g = pixel[1] as f32 / 255.0;
+            let mut b = pixel[2] as f32 / 255.0;
+            
+            // Apply contrast
+            r = ((r - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+            g = ((g - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+            b = ((b - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+            
+            // Apply brightness
+            r = (r * brightness).clamp(0.0, 1.0);
+            g = (g * brightness).clamp(0.0, 1.0);
+            b = (b * brightness).clamp(0.0, 1.0);
+            
+            // Apply saturation (convert to HSL, adjust S, convert back)
+            let (h, s, l) = Self::rgb_to_hsl(r, g, b);
+            let (r_new, g_new, b_new) = Self::hsl_to_rgb(h, (s * saturation).clamp(0.0, 1.0), l);
+            
+            r = r_new;
+            g = g_new;
+            b = b_new;
+            
+            // Apply color temperature
+            r = (r * temp_r).clamp(0.0, 1.0);
+            g = (g * temp_g).clamp(0.0, 1.0);
+            b = (b * temp_b).clamp(0.0, 1.0);
+            
+            // Write back to frame data
+            pixel[0] = (r * 255.0) as u8;
+            pixel[1] = (g * 255.0) as u8;
+            pixel[2] = (b * 255.0) as u8;
         }
     }
     
-    /// Apply vignette effect to the frame
-    fn apply_vignette(&self, frame_data: &mut [u8], width: usize, height: usize) {
+    /// Apply vignette effect to row `y` of RGBA pixels
+    fn apply_vignette(pixels: &mut [u8], y: usize, width: usize, height: usize) {
         // Vignette parameters
         let vignette_strength = 0.3; // Strength of the vignette effect (0.0 - 1.0)
         let vignette_radius = 0.75;  // Radius of the vignette effect (0.0 - 1.0)
@@ -1027,33 +1047,52 @@ impl Renderer {
         let center_x = width as f32 / 2.0;
         let center_y = height as f32 / 2.0;
         let max_dist = (center_x.powi(2) + center_y.powi(2)).sqrt() * vignette_radius;
+        let dy = y as f32 - center_y;
         
-        for y in 0..height {
-            for x in 0..width {
-                let idx = (y * width + x) * 4;
-                
-                // Calculate distance from center
-                let dx = x as f32 - center_x;
-                let dy = y as f32 - center_y;
-                let distance = (dx.powi(2) + dy.powi(2)).sqrt();
-                
-                // Calculate vignette factor
-                let factor = if distance > max_dist {
-                    1.0 - vignette_strength
-                } else {
-                    1.0 - vignette_strength * (distance / max_dist).powi(2)
-                };
-                
-                // Apply vignette to RGB channels
-                frame_data[idx] = (frame_data[idx] as f32 * factor) as u8;
-                frame_data[idx + 1] = (frame_data[idx + 1] as f32 * factor) as u8;
-                frame_data[idx + 2] = (frame_data[idx + 2] as f32 * factor) as u8;
+        for (x, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+            // Calculate distance from center
+            let dx = x as f32 - center_x;
+            let distance = (dx.powi(2) + dy.powi(2)).sqrt();
+            
+            // Calculate vignette factor
+            let factor = if distance > max_dist {
+                1.0 - vignette_strength
+            } else {
+                1.0 - vignette_strength * (distance / max_dist).powi(2)
+            };
+            
+            // Apply vignette to RGB channels
+            pixel[0] = (pixel[0] as f32 * factor) as u8;
+            pixel[1] = (pixel[1] as f32 * factor) as u8;
+            pixel[2] = (pixel[2] as f32 * factor) as u8;
+        }
+    }
+    
+    /// Sharpen row `y` with an unsharp mask over its 3x3 neighbourhood in `source`
+    ///
+    /// Edge pixels reuse their nearest neighbours, so the frame border isn't darkened.
+    fn apply_sharpen(pixels: &mut [u8], source: &[u8], y: usize, width: usize, height: usize, amount: f32) {
+        let stride = width * 4;
+        let rows = [y.saturating_sub(1), y, (y + 1).min(height - 1)];
+        
+        for x in 0..width {
+            let columns = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+            for channel in 0..3 {
+                let mut sum = 0u32;
+                for row in rows {
+                    for column in columns {
+                        sum += source[row * stride + column * 4 + channel] as u32;
+                    }
+                }
+                let original = source[y * stride + x * 4 + channel] as f32;
+                let blurred = sum as f32 / 9.0;
+                pixels[x * 4 + channel] = (original + amount * (original - blurred)).round().clamp(0.0, 255.0) as u8;
             }
         }
     }
     
     /// Convert RGB to HSL color space
-    fn rgb_to_hsl(&self, r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
         let max = r.max(g).max(b);
         let min = r.min(g).min(b);
         let delta = max - min;
@@ -1085,7 +1124,7 @@ impl Renderer {
     }
     
     /// Convert HSL to RGB color space
-    fn hsl_to_rgb(&self, h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
         if s == 0.0 {
             // Achromatic (gray)
             return (l, l, l);
@@ -1149,7 +1188,7 @@ impl Renderer {
         self.cleanup_resources();
         
         // Log cleanup completion
-        log::debug!("Renderer cleanup completed");
+        tracing::debug!("Renderer cleanup completed");
         
         self.is_initialized = false;
         Ok(())
@@ -1182,6 +1221,9 @@ pub struct RendererConfig {
     
     /// Hardware acceleration device (e.g., "cuda", "vaapi", "videotoolbox")
     pub hw_device: Option<String>,
+    
+    /// Unsharp mask amount applied after the other stages, 0 to disable
+    pub sharpen: f32,
 }
 
 impl Default for RendererConfig {
@@ -1193,6 +1235,7 @@ impl Default for RendererConfig {
             background_color: [0, 0, 0, 255], // Black background
             use_hardware_acceleration: false,
             hw_device: None,
+            sharpen: 0.0,
         }
     }
 }
@@ -0,0 +1,167 @@
+use serde::{Serialize, Deserialize};
+use crate::engine::rendering::formats::AudioFormat;
+
+/// Sample rate conversion quality, trading speed for fewer artifacts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResampleQuality {
+    Fast,
+    Default,
+    High,
+    Best,
+}
+
+impl ResampleQuality {
+    /// `quality` property of GStreamer's audioresample (0-10)
+    pub fn to_gst_quality(&self) -> i32 {
+        match self {
+            ResampleQuality::Fast => 0,
+            ResampleQuality::Default => 4,
+            ResampleQuality::High => 8,
+            ResampleQuality::Best => 10,
+        }
+    }
+
+    /// Filter length of libswresample's own resampler
+    pub fn to_swr_filter_size(&self) -> u32 {
+        match self {
+            ResampleQuality::Fast => 8,
+            ResampleQuality::Default => 32,
+            ResampleQuality::High => 64,
+            ResampleQuality::Best => 128,
+        }
+    }
+
+    /// Precision in bits of the soxr resampler
+    pub fn to_soxr_precision(&self) -> u32 {
+        match self {
+            ResampleQuality::Fast => 16,
+            ResampleQuality::Default => 20,
+            ResampleQuality::High => 24,
+            ResampleQuality::Best => 28,
+        }
+    }
+}
+
+/// Dither added when reducing bit depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Dither {
+    None,
+    /// Rectangular probability density
+    Rectangular,
+    /// Triangular probability density
+    Triangular,
+    /// Triangular, high-passed to push the noise up the spectrum
+    TriangularHighPass,
+}
+
+impl Dither {
+    /// Nick of GStreamer's audioconvert `dithering` property
+    pub fn to_gst_name(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Rectangular => "rpdf",
+            Dither::Triangular => "tpdf",
+            Dither::TriangularHighPass => "tpdf-hf",
+        }
+    }
+
+    /// libswresample `dither_method`
+    pub fn to_ffmpeg_name(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Rectangular => "rectangular",
+            Dither::Triangular => "triangular",
+            Dither::TriangularHighPass => "triangular_hp",
+        }
+    }
+}
+
+/// Noise shaping applied with dither when reducing bit depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NoiseShaping {
+    None,
+    ErrorFeedback,
+    Simple,
+    Medium,
+    High,
+}
+
+impl NoiseShaping {
+    /// Nick of GStreamer's audioconvert `noise-shaping` property
+    pub fn to_gst_name(&self) -> &'static str {
+        match self {
+            NoiseShaping::None => "none",
+            NoiseShaping::ErrorFeedback => "error-feedback",
+            NoiseShaping::Simple => "simple",
+            NoiseShaping::Medium => "medium",
+            NoiseShaping::High => "high",
+        }
+    }
+
+    /// Noise-shaped libswresample `dither_method`, which replaces the plain dither
+    pub fn to_ffmpeg_name(&self) -> Option<&'static str> {
+        match self {
+            NoiseShaping::None => None,
+            NoiseShaping::ErrorFeedback => Some("lipshitz"),
+            NoiseShaping::Simple => Some("low_shibata"),
+            NoiseShaping::Medium => Some("shibata"),
+            NoiseShaping::High => Some("high_shibata"),
+        }
+    }
+}
+
+/// Resampling and bit-depth reduction settings for export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AudioQualityOptions {
+    pub resample_quality: ResampleQuality,
+    /// Use the soxr resampler in FFmpeg exports; GStreamer always uses audioresample
+    pub use_soxr: bool,
+    /// Only used when exporting to a 16-bit format
+    pub dither: Dither,
+    /// Only used when exporting to a 16-bit format
+    pub noise_shaping: NoiseShaping,
+}
+
+impl Default for AudioQualityOptions {
+    fn default() -> Self {
+        Self {
+            resample_quality: ResampleQuality::Default,
+            use_soxr: false,
+            dither: Dither::Triangular,
+            noise_shaping: NoiseShaping::None,
+        }
+    }
+}
+
+impl AudioQualityOptions {
+    /// Settings for mastering: best resampling and noise-shaped dither
+    pub fn mastering() -> Self {
+        Self {
+            resample_quality: ResampleQuality::Best,
+            use_soxr: true,
+            dither: Dither::Triangular,
+            noise_shaping: NoiseShaping::High,
+        }
+    }
+
+    /// libswresample options for converting to `format`
+    pub fn to_swr_options(&self, format: AudioFormat) -> Vec<(&'static str, String)> {
+        let mut options = if self.use_soxr {
+            vec![
+                ("resampler", "soxr".to_string()),
+                ("precision", self.resample_quality.to_soxr_precision().to_string()),
+            ]
+        } else {
+            vec![("filter_size", self.resample_quality.to_swr_filter_size().to_string())]
+        };
+
+        if format.is_16_bit() {
+            let method = self.noise_shaping.to_ffmpeg_name().unwrap_or_else(|| self.dither.to_ffmpeg_name());
+            if self.dither != Dither::None || self.noise_shaping != NoiseShaping::None {
+                options.push(("dither_method", method.to_string()));
+            }
+        }
+
+        options
+    }
+}
@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+/// Time allowed for a webhook request
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Receives desktop notification payloads, e.g. to forward them to the UI
+pub type NotificationCallback = Arc<dyn Fn(NotificationPayload) + Send + Sync + 'static>;
+
+/// Job outcomes that fire a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookTrigger {
+    Completed,
+    Failed,
+    /// Either outcome
+    Finished,
+}
+
+impl HookTrigger {
+    /// Whether a hook with this trigger fires for `event`
+    pub fn matches(&self, event: &JobEvent) -> bool {
+        match self {
+            HookTrigger::Completed => event.error.is_none(),
+            HookTrigger::Failed => event.error.is_some(),
+            HookTrigger::Finished => true,
+        }
+    }
+}
+
+/// What a hook does
+///
+/// Templates may use `{job_id}`, `{status}`, `{output}`, `{file_name}`, `{duration}`,
+/// `{priority}` and `{error}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookAction {
+    /// Shell command; substituted values are shell-quoted and also set as `AETHER_*`
+    /// environment variables
+    Command(String),
+    /// POST the job metadata as JSON
+    Webhook { url: String },
+    /// Payload handed to the queue's notification callback
+    Notification { title: String, body: String },
+}
+
+/// Action run when a render job finishes
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionHook {
+    pub action: HookAction,
+    pub trigger: HookTrigger,
+}
+
+impl CompletionHook {
+    pub fn new(action: HookAction, trigger: HookTrigger) -> Self {
+        Self { action, trigger }
+    }
+}
+
+/// Metadata of a finished job, available to hook templates
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobEvent {
+    pub job_id: u64,
+    /// `completed` or `failed`
+    pub status: String,
+    pub output_path: PathBuf,
+    /// Render time in seconds
+    pub duration: f64,
+    pub priority: String,
+    pub error: Option<String>,
+}
+
+impl JobEvent {
+    fn value(&self, placeholder: &str) -> Option<String> {
+        let value = match placeholder {
+            "job_id" => self.job_id.to_string(),
+            "status" => self.status.clone(),
+            "output" => self.output_path.display().to_string(),
+            "file_name" => self.output_path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            "duration" => format!("{:.1}", self.duration),
+            "priority" => self.priority.clone(),
+            "error" => self.error.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Substitute placeholders in `template`, passing each value through `escape`
+    ///
+    /// Unknown placeholders are left as written.
+    pub fn render(&self, template: &str, escape: impl Fn(&str) -> String) -> String {
+        let mut output = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            output.push_str(&rest[..start]);
+            let placeholder = rest[start + 1..].find('}')
+                .and_then(|end| Some((end, self.value(&rest[start + 1..start + 1 + end])?)));
+            match placeholder {
+                Some((end, value)) => {
+                    output.push_str(&escape(&value));
+                    rest = &rest[start + end + 2..];
+                },
+                None => {
+                    output.push('{');
+                    rest = &rest[start + 1..];
+                },
+            }
+        }
+
+        output.push_str(rest);
+        output
+    }
+
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        [
+            ("AETHER_JOB_ID", "job_id"),
+            ("AETHER_JOB_STATUS", "status"),
+            ("AETHER_OUTPUT", "output"),
+            ("AETHER_FILE_NAME", "file_name"),
+            ("AETHER_DURATION", "duration"),
+            ("AETHER_PRIORITY", "priority"),
+            ("AETHER_ERROR", "error"),
+        ]
+        .into_iter()
+        .map(|(name, placeholder)| (name, self.value(placeholder).unwrap_or_default()))
+        .collect()
+    }
+}
+
+/// Desktop notification for a finished job
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NotificationPayload {
+    pub title: String,
+    pub body: String,
+    pub job: JobEvent,
+}
+
+/// Run the hooks matching `event`
+///
+/// Commands and webhooks run on their own threads so a slow hook doesn't hold up the queue.
+pub(crate) fn run_hooks(hooks: &[CompletionHook], event: &JobEvent, notify: Option<&NotificationCallback>) {
+    for hook in hooks.iter().filter(|hook| hook.trigger.matches(event)) {
+        match &hook.action {
+            HookAction::Command(template) => {
+                let command = event.render(template, shell_quote);
+                let env = event.env_vars();
+                thread::spawn(move || run_command(&command, env));
+            },
+            HookAction::Webhook { url } => {
+                let url = url.clone();
+                let event = event.clone();
+                thread::spawn(move || post_webhook(&url, &event));
+            },
+            HookAction::Notification { title, body } => match notify {
+                Some(notify) => notify(NotificationPayload {
+                    title: event.render(title, str::to_string),
+                    body: event.render(body, str::to_string),
+                    job: event.clone(),
+                }),
+                None => debug!("No notification callback set, dropping notification for job {}", event.job_id),
+            },
+        }
+    }
+}
+
+fn run_command(command: &str, env: Vec<(&'static str, String)>) {
+    #[cfg(windows)]
+    let mut process = {
+        use std::os::windows::process::CommandExt;
+
+        // Passed verbatim: the values are already quoted for cmd, and std's quoting for
+        // the C runtime would add backslashes cmd doesn't understand
+        let mut process = Command::new("cmd");
+        process.raw_arg("/D /S /C").raw_arg(format!("\"{}\"", command));
+        process
+    };
+    #[cfg(not(windows))]
+    let mut process = {
+        let mut process = Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+
+    match process.envs(env).output() {
+        Ok(output) if output.status.success() => debug!("Completion hook `{}` succeeded", command),
+        Ok(output) => warn!(
+            "Completion hook `{}` exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Failed to run completion hook `{}`: {}", command, e),
+    }
+}
+
+fn post_webhook(url: &str, event: &JobEvent) {
+    let body = match serde_json::to_string(event) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize job {} for webhook: {}", event.job_id, e);
+            return;
+        },
+    };
+
+    let result = ureq::post(url)
+        .config()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .header("Content-Type", "application/json")
+        .send(&body);
+
+    match result {
+        Ok(_) => debug!("Posted job {} to webhook {}", event.job_id, url),
+        Err(e) => warn!("Webhook {} failed for job {}: {}", url, event.job_id, e),
+    }
+}
+
+/// Quote a value so the shell passes it through as a single literal argument
+fn shell_quote(value: &str) -> String {
+    if cfg!(windows) {
+        cmd_quote(value)
+    } else {
+        sh_quote(value)
+    }
+}
+
+/// Quote for `sh`: nothing is special inside single quotes except the quote itself
+pub(crate) fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quote for `cmd /C`
+///
+/// Metacharacters are literal inside double quotes, and a doubled quote keeps cmd inside
+/// them. `%` still expands there and can't be escaped, so each one is followed by
+/// `%cd:~,%`, an empty substring of the current directory, which leaves it unpaired.
+/// Line breaks would end the command, so they become spaces.
+pub(crate) fn cmd_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\"\""),
+            '%' => quoted.push_str("%%cd:~,%"),
+            '\r' | '\n' => quoted.push(' '),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,75 @@
+use serde::{Serialize, Deserialize};
+use crate::engine::rendering::formats::ContainerFormat;
+
+/// Where an MP4 or QuickTime file keeps its index
+///
+/// Muxers write the index (the `moov` box) last, once every sample's position is known,
+/// so a player fetching the file over HTTP can't start until it has all of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Mp4Layout {
+    /// Index at the end, as written
+    Standard,
+    /// Index moved to the front after writing, so playback can start while downloading
+    #[default]
+    FastStart,
+    /// An empty index up front and the media in self-contained fragments, each starting
+    /// at a keyframe, for streaming and segmenting; also playable if the export stops
+    Fragmented {
+        /// Shortest fragment, in seconds
+        fragment_duration: f64,
+    },
+}
+
+impl Mp4Layout {
+    /// Fragmented, with fragments of about two seconds
+    pub fn fragmented() -> Self {
+        Mp4Layout::Fragmented { fragment_duration: 2.0 }
+    }
+}
+
+/// Muxer settings of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerOptions {
+    /// Layout of MP4 and QuickTime outputs; other containers ignore it
+    pub mp4_layout: Mp4Layout,
+}
+
+impl ContainerOptions {
+    /// FFmpeg muxer options for `container`
+    pub fn ffmpeg_format_options(&self, container: ContainerFormat) -> Vec<(&'static str, String)> {
+        if !matches!(container, ContainerFormat::Mp4 | ContainerFormat::Mov) {
+            return Vec::new();
+        }
+        match self.mp4_layout {
+            Mp4Layout::Standard => Vec::new(),
+            Mp4Layout::FastStart => vec![("movflags", "+faststart".to_string())],
+            Mp4Layout::Fragmented { fragment_duration } => vec![
+                ("movflags", "+frag_keyframe+empty_moov+default_base_moof".to_string()),
+                ("frag_duration", fragment_micros(fragment_duration).to_string()),
+            ],
+        }
+    }
+
+    /// GStreamer muxer properties, by element factory name
+    ///
+    /// Values are strings to set with `set_property_from_str`.
+    pub fn gst_muxer_properties(&self, factory: &str) -> Vec<(&'static str, String)> {
+        if !matches!(factory, "mp4mux" | "qtmux") {
+            return Vec::new();
+        }
+        match self.mp4_layout {
+            Mp4Layout::Standard => Vec::new(),
+            Mp4Layout::FastStart => vec![("faststart", "true".to_string())],
+            // In milliseconds
+            Mp4Layout::Fragmented { fragment_duration } => {
+                vec![("fragment-duration", (fragment_micros(fragment_duration) / 1000).max(1).to_string())]
+            },
+        }
+    }
+}
+
+/// Fragment length in microseconds, at least one millisecond
+fn fragment_micros(seconds: f64) -> u64 {
+    ((seconds.max(0.0) * 1_000_000.0).round() as u64).max(1000)
+}
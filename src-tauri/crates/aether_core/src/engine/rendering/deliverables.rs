@@ -0,0 +1,242 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use crate::engine::rendering::export::ExportOptions;
+use crate::modules::file_manager::{FileManager, ThumbnailOptions};
+
+/// Index of a gallery's deliverables, inside its directory
+const GALLERY_FILE: &str = "gallery.json";
+
+/// Directory of a gallery's thumbnails, inside its directory
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+/// Where representative thumbnails are taken, as shares of the export's duration
+pub const THUMBNAIL_POSITIONS: [f64; 3] = [0.1, 0.5, 0.9];
+
+/// A completed export of a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deliverable {
+    pub id: u64,
+    pub output_path: PathBuf,
+    /// SHA-256 of the output file, as hex
+    pub checksum: String,
+    pub size: u64,
+    /// The exact options the export ran with
+    pub options: ExportOptions,
+    /// Frames from `THUMBNAIL_POSITIONS`; any that couldn't be taken are left out
+    pub thumbnails: Vec<PathBuf>,
+    /// Seconds since the Unix epoch
+    pub completed_at: u64,
+}
+
+/// How two deliverables differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliverableComparison {
+    /// Whether the output files have the same checksum
+    pub identical_output: bool,
+    /// `ExportOptions` fields that differ, not counting the output path
+    pub changed_options: Vec<String>,
+}
+
+impl DeliverableComparison {
+    pub fn same_settings(&self) -> bool {
+        self.changed_options.is_empty()
+    }
+
+    /// Same settings but different output, so something in the project changed
+    pub fn content_changed(&self) -> bool {
+        self.same_settings() && !self.identical_output
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GalleryFile {
+    deliverables: Vec<Deliverable>,
+}
+
+/// Record of a project's completed exports
+///
+/// Keeps each export's checksum, thumbnails and options in a directory, usually next
+/// to the project, so an export can be repeated with the same settings and compared
+/// against the last one.
+pub struct DeliverableGallery {
+    dir: PathBuf,
+    deliverables: Vec<Deliverable>,
+}
+
+impl DeliverableGallery {
+    /// Open the gallery in `dir`, empty if there isn't one yet
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(GALLERY_FILE);
+        let deliverables = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<GalleryFile>(&json)
+                .with_context(|| format!("Invalid deliverable gallery {}", path.display()))?
+                .deliverables,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow!("Failed to read deliverable gallery {}: {}", path.display(), e)),
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            deliverables,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Deliverables, oldest first
+    pub fn deliverables(&self) -> &[Deliverable] {
+        &self.deliverables
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Deliverable> {
+        self.deliverables.iter().find(|deliverable| deliverable.id == id)
+    }
+
+    pub fn latest(&self) -> Option<&Deliverable> {
+        self.deliverables.last()
+    }
+
+    /// Options of the latest export, to render again with the same settings
+    pub fn last_options(&self) -> Option<ExportOptions> {
+        self.latest().map(|deliverable| deliverable.options.clone())
+    }
+
+    /// Record a completed export, taking thumbnails of it with `file_manager`
+    pub fn record(&mut self, options: &ExportOptions, file_manager: &FileManager) -> Result<&Deliverable> {
+        let duration = file_manager.get_media_info(&options.output_path)
+            .ok()
+            .and_then(|info| info.duration)
+            .unwrap_or(0.0);
+
+        self.record_with_thumbnails(options, |share, destination| {
+            let thumbnail = ThumbnailOptions {
+                position: Some(duration * share),
+                ..ThumbnailOptions::default()
+            };
+            file_manager.write_video_thumbnail(&options.output_path, &thumbnail, destination)
+        })
+    }
+
+    /// Record a completed export, with `thumbnail` writing the frame at a share of the
+    /// duration to a path
+    pub fn record_with_thumbnails(
+        &mut self,
+        options: &ExportOptions,
+        mut thumbnail: impl FnMut(f64, &Path) -> Result<()>,
+    ) -> Result<&Deliverable> {
+        let output = &options.output_path;
+        let size = fs::metadata(output)
+            .with_context(|| format!("Export output {} not found", output.display()))?
+            .len();
+        let checksum = file_checksum(output)?;
+
+        let id = self.deliverables.iter().map(|deliverable| deliverable.id).max().map_or(1, |id| id + 1);
+        let thumbnail_dir = self.dir.join(THUMBNAIL_DIR);
+        fs::create_dir_all(&thumbnail_dir)?;
+
+        let mut thumbnails = Vec::new();
+        for (index, share) in THUMBNAIL_POSITIONS.into_iter().enumerate() {
+            let path = thumbnail_dir.join(format!("{}_{}.jpg", id, index));
+            match thumbnail(share, &path) {
+                Ok(()) if path.exists() => thumbnails.push(path),
+                Ok(()) => warn!("No thumbnail written for deliverable {} at {:.0}%", id, share * 100.0),
+                Err(e) => warn!("Failed to take thumbnail of {} at {:.0}%: {}", output.display(), share * 100.0, e),
+            }
+        }
+
+        let completed_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+        self.deliverables.push(Deliverable {
+            id,
+            output_path: output.clone(),
+            checksum,
+            size,
+            options: options.clone(),
+            thumbnails,
+            completed_at,
+        });
+        self.save()?;
+
+        info!("Recorded deliverable {} for {}", id, output.display());
+        Ok(self.deliverables.last().unwrap())
+    }
+
+    /// Compare two deliverables
+    pub fn compare(&self, first: u64, second: u64) -> Result<DeliverableComparison> {
+        let first = self.get(first).ok_or_else(|| anyhow!("Deliverable not found: {}", first))?;
+        let second = self.get(second).ok_or_else(|| anyhow!("Deliverable not found: {}", second))?;
+
+        Ok(DeliverableComparison {
+            identical_output: first.checksum == second.checksum,
+            changed_options: changed_options(&first.options, &second.options)?,
+        })
+    }
+
+    /// Compare a deliverable with the export before it, `None` for the first export
+    pub fn compare_with_previous(&self, id: u64) -> Result<Option<DeliverableComparison>> {
+        let index = self.deliverables.iter()
+            .position(|deliverable| deliverable.id == id)
+            .ok_or_else(|| anyhow!("Deliverable not found: {}", id))?;
+        match index.checked_sub(1) {
+            Some(previous) => self.compare(self.deliverables[previous].id, id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Forget a deliverable and delete its thumbnails; the export itself is kept
+    pub fn remove(&mut self, id: u64) -> Result<()> {
+        let index = self.deliverables.iter()
+            .position(|deliverable| deliverable.id == id)
+            .ok_or_else(|| anyhow!("Deliverable not found: {}", id))?;
+        let deliverable = self.deliverables.remove(index);
+        for thumbnail in &deliverable.thumbnails {
+            let _ = fs::remove_file(thumbnail);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let file = GalleryFile { deliverables: self.deliverables.clone() };
+        let path = self.dir.join(GALLERY_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Failed to write deliverable gallery {}", path.display()))
+    }
+}
+
+/// SHA-256 of a file, as hex
+pub fn file_checksum(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn changed_options(first: &ExportOptions, second: &ExportOptions) -> Result<Vec<String>> {
+    let first = serde_json::to_value(first)?;
+    let second = serde_json::to_value(second)?;
+    let (Some(first), Some(second)) = (first.as_object(), second.as_object()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut changed: Vec<String> = first.iter()
+        .filter(|(field, value)| field.as_str() != "output_path" && second.get(field.as_str()) != Some(*value))
+        .map(|(field, _)| field.clone())
+        .collect();
+    changed.sort();
+    Ok(changed)
+}
@@ -0,0 +1,63 @@
+//! Settings for deterministic renders, which produce the same output from the same project
+//! on every run
+//!
+//! Multithreaded encoders split the work differently from run to run, and muxers stamp
+//! the time of writing and library versions into the file. A deterministic render
+//! encodes on one thread, asks FFmpeg for bit-exact output, and pins or drops those
+//! stamps. The remaining sources of randomness are seeded from constants already: both
+//! FFmpeg's and GStreamer's resamplers derive dither noise from fixed seeds.
+//!
+//! FFmpeg renders come out byte-identical. GStreamer's MP4 and QuickTime muxers always
+//! write the current time and Matroska writes a random segment UID, so there the frames
+//! and samples are identical but the files may differ in a few header bytes.
+
+use crate::engine::rendering::formats::VideoFormat;
+
+/// Encoder threads in a deterministic render
+pub const DETERMINISTIC_THREADS: u8 = 1;
+
+/// Creation time written by muxers that let it be set
+pub const FIXED_CREATION_TIME: &str = "1970-01-01T00:00:00Z";
+
+/// Encoder threads for an export: `threads` (0 for the encoder's choice), or one if deterministic
+pub fn encoder_threads(threads: u8, deterministic: bool) -> u8 {
+    if deterministic {
+        DETERMINISTIC_THREADS
+    } else {
+        threads
+    }
+}
+
+/// FFmpeg muxer options leaving out the library version and creation time
+pub fn ffmpeg_format_options() -> Vec<(&'static str, &'static str)> {
+    vec![("fflags", "+bitexact")]
+}
+
+/// FFmpeg encoder options for bit-exact output from one thread
+pub fn ffmpeg_codec_options(video: VideoFormat) -> Vec<(&'static str, &'static str)> {
+    let mut options = vec![("flags", "+bitexact")];
+    match video {
+        // `threads` doesn't cover x264's lookahead or x265's thread pools
+        VideoFormat::H264 => options.push(("x264-params", "sliced-threads=0:lookahead-threads=1")),
+        VideoFormat::H265 => options.push(("x265-params", "frame-threads=1:pools=none:wpp=0")),
+        VideoFormat::Vp9 | VideoFormat::Av1 => options.push(("row-mt", "0")),
+        _ => (),
+    }
+    options
+}
+
+/// GStreamer element properties for a deterministic render, by element factory name
+///
+/// Values are strings to set with `set_property_from_str`; elements of older plugin
+/// versions may lack some of them.
+pub fn gst_element_properties(factory: &str) -> &'static [(&'static str, &'static str)] {
+    match factory {
+        "x264enc" => &[("threads", "1"), ("sliced-threads", "false")],
+        "x265enc" => &[("option-string", "frame-threads=1:pools=none:wpp=0")],
+        "vp8enc" => &[("threads", "1")],
+        "vp9enc" | "av1enc" => &[("threads", "1"), ("row-mt", "false")],
+        "matroskamux" | "webmmux" => &[("creation-time", FIXED_CREATION_TIME)],
+        factory if factory.starts_with("avenc_") => &[("threads", "1")],
+        _ => &[],
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use crate::engine::editing::types::EditingError;
+use crate::engine::rendering::encoder_profile::{CodecProfile, EncoderProfile, Tune};
 use crate::engine::rendering::formats::{VideoFormat, AudioFormat};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -49,6 +51,68 @@ impl EncoderPreset {
     }
 }
 
+/// Hardware encoder family used when hardware acceleration is on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HardwareEncoder {
+    #[default]
+    VideoToolbox,
+    Nvenc,
+    Vaapi,
+}
+
+impl HardwareEncoder {
+    /// FFmpeg encoder for `video`, if this family has one
+    pub fn codec_name(&self, video: VideoFormat) -> Option<&'static str> {
+        match (self, video) {
+            (HardwareEncoder::VideoToolbox, VideoFormat::H264) => Some("h264_videotoolbox"),
+            (HardwareEncoder::VideoToolbox, VideoFormat::H265) => Some("hevc_videotoolbox"),
+            (HardwareEncoder::Nvenc, VideoFormat::H264) => Some("h264_nvenc"),
+            (HardwareEncoder::Nvenc, VideoFormat::H265) => Some("hevc_nvenc"),
+            (HardwareEncoder::Vaapi, VideoFormat::H264) => Some("h264_vaapi"),
+            (HardwareEncoder::Vaapi, VideoFormat::H265) => Some("hevc_vaapi"),
+            _ => None,
+        }
+    }
+}
+
+/// How the video encoder spends bits
+///
+/// Bitrates and VBV buffer sizes are in bits per second and bits. A buffer size of 0
+/// holds one second at the peak bitrate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RateControl {
+    /// Constant quality at `crf`, whatever bitrate that takes
+    ConstantQuality,
+    /// Constant quality at `crf`, held under a peak bitrate for platforms with bitrate caps
+    ConstrainedQuality { max_bitrate: u32, buffer_size: u32 },
+    /// Constant `video_bitrate`, for broadcast and fixed-bandwidth links
+    ConstantBitrate { buffer_size: u32 },
+    /// Average `video_bitrate`, never above a peak bitrate
+    CappedVbr { max_bitrate: u32, buffer_size: u32 },
+}
+
+/// Encoders whose rate control and GOP options differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EncoderFamily {
+    X264,
+    X265,
+    Nvenc,
+    Vaapi,
+    Other,
+}
+
+impl EncoderFamily {
+    fn of(codec_name: &str) -> Self {
+        match codec_name {
+            "libx264" => EncoderFamily::X264,
+            "libx265" => EncoderFamily::X265,
+            name if name.ends_with("_nvenc") => EncoderFamily::Nvenc,
+            name if name.ends_with("_vaapi") => EncoderFamily::Vaapi,
+            _ => EncoderFamily::Other,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncoderOptions {
     pub video_format: VideoFormat,
@@ -67,6 +131,31 @@ pub struct EncoderOptions {
     
     pub hardware_acceleration: bool,
     
+    /// Encoder family used when `hardware_acceleration` is on
+    #[serde(default)]
+    pub hardware_encoder: HardwareEncoder,
+    
+    /// How bits are spent; `None` is constant quality at `crf`, or an average
+    /// `video_bitrate` when one is set
+    #[serde(default)]
+    pub rate_control: Option<RateControl>,
+    
+    /// H.264 or H.265 profile, level and tuning, for devices that only play some
+    #[serde(default)]
+    pub profile: EncoderProfile,
+    
+    /// Seconds between keyframes; `None` leaves it to the encoder
+    #[serde(default)]
+    pub keyframe_interval: Option<f64>,
+    
+    /// B-frames between reference frames; `None` leaves it to the encoder
+    #[serde(default)]
+    pub b_frames: Option<u8>,
+    
+    /// Whether the encoder adds keyframes at scene cuts, between the regular ones
+    #[serde(default = "default_scene_cut_keyframes")]
+    pub scene_cut_keyframes: bool,
+    
     pub additional_options: HashMap<String, String>,
 }
 
@@ -81,6 +170,12 @@ impl Default for EncoderOptions {
             audio_bitrate: 128000,
             two_pass: false,
             hardware_acceleration: false,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: None,
+            profile: EncoderProfile::default(),
+            keyframe_interval: None,
+            b_frames: None,
+            scene_cut_keyframes: true,
             additional_options: HashMap::new(),
         }
     }
@@ -105,6 +200,12 @@ impl EncoderOptions {
             audio_bitrate: 320000,
             two_pass: true,
             hardware_acceleration: false,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: None,
+            profile: EncoderProfile::default(),
+            keyframe_interval: None,
+            b_frames: None,
+            scene_cut_keyframes: true,
             additional_options: HashMap::new(),
         }
     }
@@ -119,6 +220,12 @@ impl EncoderOptions {
             audio_bitrate: 128000,
             two_pass: false,
             hardware_acceleration: false,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: None,
+            profile: EncoderProfile::default(),
+            keyframe_interval: None,
+            b_frames: None,
+            scene_cut_keyframes: true,
             additional_options: HashMap::new(),
         }
     }
@@ -133,6 +240,12 @@ impl EncoderOptions {
             audio_bitrate: 96000,
             two_pass: false,
             hardware_acceleration: true,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: None,
+            profile: EncoderProfile::default(),
+            keyframe_interval: None,
+            b_frames: None,
+            scene_cut_keyframes: true,
             additional_options: HashMap::new(),
         }
     }
@@ -147,6 +260,12 @@ impl EncoderOptions {
             audio_bitrate: 1536000,   // 1.5 Mbps
             two_pass: false,
             hardware_acceleration: false,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: None,
+            profile: EncoderProfile::default(),
+            keyframe_interval: None,
+            b_frames: None,
+            scene_cut_keyframes: true,
             additional_options: {
                 let mut options = HashMap::new();
                 options.insert("profile:v".to_string(), "3".to_string()); // ProRes HQ
@@ -155,6 +274,32 @@ impl EncoderOptions {
         }
     }
     
+    /// HLS and DASH renditions: a keyframe exactly every two seconds and none between,
+    /// so segments line up across the ladder, at a peak bitrate players can plan for
+    pub fn streaming() -> Self {
+        Self {
+            video_format: VideoFormat::H264,
+            audio_format: AudioFormat::Aac,
+            preset: EncoderPreset::Medium,
+            crf: 23,
+            video_bitrate: 5000000,
+            audio_bitrate: 128000,
+            two_pass: false,
+            hardware_acceleration: false,
+            hardware_encoder: HardwareEncoder::default(),
+            rate_control: Some(RateControl::CappedVbr { max_bitrate: 6000000, buffer_size: 12000000 }),
+            profile: EncoderProfile {
+                profile: Some(CodecProfile::High),
+                level: Some("4.1".to_string()),
+                tune: Vec::new(),
+            },
+            keyframe_interval: Some(2.0),
+            b_frames: Some(2),
+            scene_cut_keyframes: false,
+            additional_options: HashMap::new(),
+        }
+    }
+    
     pub fn add_option(&mut self, key: &str, value: &str) -> &mut Self {
         self.additional_options.insert(key.to_string(), value.to_string());
         self
@@ -190,19 +335,265 @@ impl EncoderOptions {
         self
     }
     
-    pub fn to_ffmpeg_args(&self) -> Vec<String> {
-        let mut args = Vec::new();
+    pub fn with_hardware_encoder(&mut self, encoder: HardwareEncoder) -> &mut Self {
+        self.hardware_encoder = encoder;
+        self
+    }
+    
+    pub fn with_rate_control(&mut self, rate_control: RateControl) -> &mut Self {
+        self.rate_control = Some(rate_control);
+        self
+    }
+    
+    /// Set the seconds between keyframes
+    pub fn with_keyframe_interval(&mut self, seconds: f64) -> &mut Self {
+        self.keyframe_interval = Some(seconds);
+        self
+    }
+    
+    pub fn with_b_frames(&mut self, count: u8) -> &mut Self {
+        self.b_frames = Some(count);
+        self
+    }
+    
+    pub fn with_scene_cut_keyframes(&mut self, enabled: bool) -> &mut Self {
+        self.scene_cut_keyframes = enabled;
+        self
+    }
+    
+    pub fn with_codec_profile(&mut self, profile: CodecProfile) -> &mut Self {
+        self.profile.profile = Some(profile);
+        self
+    }
+    
+    /// Set the level, like "4.1"
+    pub fn with_level(&mut self, level: &str) -> &mut Self {
+        self.profile.level = Some(level.to_string());
+        self
+    }
+    
+    /// Add a tune, which x264 combines with the others
+    pub fn with_tune(&mut self, tune: Tune) -> &mut Self {
+        if !self.profile.tune.contains(&tune) {
+            self.profile.tune.push(tune);
+        }
+        self
+    }
+    
+    /// FFmpeg video encoder these options use
+    pub fn codec_name(&self) -> &'static str {
+        self.hardware_acceleration
+            .then(|| self.hardware_encoder.codec_name(self.video_format))
+            .flatten()
+            .unwrap_or_else(|| self.video_format.to_ffmpeg_name())
+    }
+    
+    /// Check that the rate control has the bitrates it needs and the GOP structure is possible
+    pub fn validate(&self) -> Result<(), EditingError> {
+        let invalid = |message: String| Err(EditingError::InvalidParameter(message));
+        match self.rate_control {
+            Some(RateControl::ConstrainedQuality { max_bitrate: 0, .. }) => {
+                return invalid("Constrained quality needs a peak bitrate".to_string());
+            },
+            Some(RateControl::ConstantBitrate { .. }) if self.video_bitrate == 0 => {
+                return invalid("Constant bitrate needs a video bitrate".to_string());
+            },
+            Some(RateControl::CappedVbr { max_bitrate, .. }) if self.video_bitrate == 0 || max_bitrate < self.video_bitrate => {
+                return invalid(format!(
+                    "Capped VBR needs a video bitrate at most its peak, got {} and {}",
+                    self.video_bitrate, max_bitrate
+                ));
+            },
+            _ => (),
+        }
         
-        let codec_name = if self.hardware_acceleration {
-            match self.video_format {
-                VideoFormat::H264 => "h264_videotoolbox", // For macOS
-                VideoFormat::H265 => "hevc_videotoolbox", // For macOS
-                _ => self.video_format.to_ffmpeg_name(),
+        if let Some(interval) = self.keyframe_interval {
+            if !interval.is_finite() || interval <= 0.0 {
+                return invalid(format!("Keyframe interval must be positive, got {}", interval));
             }
-        } else {
-            self.video_format.to_ffmpeg_name()
+        }
+        match self.b_frames {
+            Some(count) if count > 16 => invalid(format!("At most 16 B-frames, got {}", count)),
+            Some(count) if count > 0 && self.profile.profile == Some(CodecProfile::Baseline) => {
+                invalid("The Baseline profile has no B-frames".to_string())
+            },
+            _ => Ok(()),
+        }
+    }
+    
+    /// Check the options, and that the profile and level suit the encoder and a
+    /// `width`x`height` output at `frame_rate` with the peak bitrate of the rate control
+    pub fn validate_for_output(&self, width: u32, height: u32, frame_rate: f64) -> Result<(), EditingError> {
+        self.validate()?;
+        let peak_bitrate = match self.rate_control {
+            Some(RateControl::ConstrainedQuality { max_bitrate, .. } | RateControl::CappedVbr { max_bitrate, .. }) => max_bitrate,
+            _ => self.video_bitrate,
+        };
+        self.profile.validate(self.video_format, self.codec_name(), width, height, frame_rate, peak_bitrate)
+    }
+    
+    /// FFmpeg options for the rate control, as mapped for the encoder in use
+    ///
+    /// x264 and x265 take CRF and VBV limits directly and are asked for HRD-conformant CBR.
+    /// NVENC has no CRF, so constant quality is its VBR mode at a CQ level. VAAPI has
+    /// neither: constant quality is CQP at the `crf` value as QP, constrained quality
+    /// is QVBR. Other encoders get FFmpeg's generic options.
+    pub fn rate_control_options(&self) -> Vec<(&'static str, String)> {
+        let family = EncoderFamily::of(self.codec_name());
+        let crf = self.crf.to_string();
+        let bitrate = kbits(self.video_bitrate);
+        let buffer = |size: u32, peak: u32| kbits(if size == 0 { peak } else { size });
+        
+        let Some(rate_control) = self.rate_control else {
+            return if self.video_bitrate > 0 {
+                vec![("b:v", bitrate)]
+            } else if matches!(self.video_format, VideoFormat::H264 | VideoFormat::H265 | VideoFormat::Vp9) {
+                vec![("crf", crf)]
+            } else {
+                Vec::new()
+            };
         };
         
+        match (family, rate_control) {
+            (EncoderFamily::Nvenc, RateControl::ConstantQuality) => {
+                vec![("rc", "vbr".to_string()), ("cq", crf), ("b:v", "0".to_string())]
+            },
+            (EncoderFamily::Nvenc, RateControl::ConstrainedQuality { max_bitrate, buffer_size }) => vec![
+                ("rc", "vbr".to_string()),
+                ("cq", crf),
+                ("b:v", "0".to_string()),
+                ("maxrate", kbits(max_bitrate)),
+                ("bufsize", buffer(buffer_size, max_bitrate)),
+            ],
+            (EncoderFamily::Nvenc, RateControl::ConstantBitrate { buffer_size }) => vec![
+                ("rc", "cbr".to_string()),
+                ("b:v", bitrate.clone()),
+                ("maxrate", bitrate),
+                ("bufsize", buffer(buffer_size, self.video_bitrate)),
+            ],
+            (EncoderFamily::Nvenc, RateControl::CappedVbr { max_bitrate, buffer_size }) => vec![
+                ("rc", "vbr".to_string()),
+                ("b:v", bitrate),
+                ("maxrate", kbits(max_bitrate)),
+                ("bufsize", buffer(buffer_size, max_bitrate)),
+            ],
+            
+            (EncoderFamily::Vaapi, RateControl::ConstantQuality) => {
+                vec![("rc_mode", "CQP".to_string()), ("qp", crf)]
+            },
+            (EncoderFamily::Vaapi, RateControl::ConstrainedQuality { max_bitrate, buffer_size }) => vec![
+                ("rc_mode", "QVBR".to_string()),
+                ("global_quality", crf),
+                ("b:v", kbits(max_bitrate)),
+                ("maxrate", kbits(max_bitrate)),
+                ("bufsize", buffer(buffer_size, max_bitrate)),
+            ],
+            (EncoderFamily::Vaapi, RateControl::ConstantBitrate { buffer_size }) => vec![
+                ("rc_mode", "CBR".to_string()),
+                ("b:v", bitrate.clone()),
+                ("maxrate", bitrate),
+                ("bufsize", buffer(buffer_size, self.video_bitrate)),
+            ],
+            (EncoderFamily::Vaapi, RateControl::CappedVbr { max_bitrate, buffer_size }) => vec![
+                ("rc_mode", "VBR".to_string()),
+                ("b:v", bitrate),
+                ("maxrate", kbits(max_bitrate)),
+                ("bufsize", buffer(buffer_size, max_bitrate)),
+            ],
+            
+            (_, RateControl::ConstantQuality) => {
+                let mut options = vec![("crf", crf)];
+                // libvpx only holds quality constant without a target bitrate
+                if self.video_format == VideoFormat::Vp9 {
+                    options.push(("b:v", "0".to_string()));
+                }
+                options
+            },
+            (_, RateControl::ConstrainedQuality { max_bitrate, buffer_size }) => {
+                let mut options = vec![("crf", crf)];
+                // libvpx takes the cap as its target in constrained quality mode
+                if self.video_format == VideoFormat::Vp9 {
+                    options.push(("b:v", kbits(max_bitrate)));
+                }
+                options.push(("maxrate", kbits(max_bitrate)));
+                options.push(("bufsize", buffer(buffer_size, max_bitrate)));
+                options
+            },
+            (_, RateControl::ConstantBitrate { buffer_size }) => {
+                let mut options = vec![
+                    ("b:v", bitrate.clone()),
+                    ("minrate", bitrate.clone()),
+                    ("maxrate", bitrate),
+                    ("bufsize", buffer(buffer_size, self.video_bitrate)),
+                ];
+                match family {
+                    EncoderFamily::X264 => options.push(("x264-params", "nal-hrd=cbr".to_string())),
+                    EncoderFamily::X265 => options.push(("x265-params", "strict-cbr=1".to_string())),
+                    _ => (),
+                }
+                options
+            },
+            (_, RateControl::CappedVbr { max_bitrate, buffer_size }) => vec![
+                ("b:v", bitrate),
+                ("maxrate", kbits(max_bitrate)),
+                ("bufsize", buffer(buffer_size, max_bitrate)),
+            ],
+        }
+    }
+    
+    /// FFmpeg options for the GOP structure, as mapped for the encoder in use
+    ///
+    /// Keyframes are forced by time, so the interval holds at any frame rate. NVENC
+    /// makes forced keyframes IDR frames only when asked; VAAPI never adds them at cuts.
+    pub fn gop_options(&self) -> Vec<(&'static str, String)> {
+        let family = EncoderFamily::of(self.codec_name());
+        let mut options = Vec::new();
+        if let Some(interval) = self.keyframe_interval {
+            options.push(("force_key_frames", format!("expr:gte(t,n_forced*{})", interval)));
+            if family == EncoderFamily::Nvenc {
+                options.push(("forced-idr", "1".to_string()));
+            }
+        }
+        if let Some(count) = self.b_frames {
+            options.push(("bf", count.to_string()));
+        }
+        if !self.scene_cut_keyframes {
+            match family {
+                EncoderFamily::X265 => options.push(("x265-params", "scenecut=0".to_string())),
+                EncoderFamily::Nvenc => options.push(("no-scenecut", "1".to_string())),
+                EncoderFamily::Vaapi => (),
+                _ => options.push(("sc_threshold", "0".to_string())),
+            }
+        }
+        options
+    }
+    
+    /// Every FFmpeg video encoder option: rate control, profile and GOP structure,
+    /// with the x264 and x265 parameter lists each merged into one
+    pub fn encoder_options(&self) -> Vec<(&'static str, String)> {
+        let codec_name = self.codec_name();
+        let mut options: Vec<(&'static str, String)> = Vec::new();
+        let all = self.rate_control_options()
+            .into_iter()
+            .chain(self.profile.ffmpeg_options(codec_name))
+            .chain(self.gop_options());
+        for (key, value) in all {
+            match options.iter_mut().find(|(existing, _)| *existing == key && key.ends_with("-params")) {
+                Some((_, params)) => {
+                    params.push(':');
+                    params.push_str(&value);
+                },
+                None => options.push((key, value)),
+            }
+        }
+        options
+    }
+    
+    pub fn to_ffmpeg_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        
+        let codec_name = self.codec_name();
+        
         args.push("-c:v".to_string());
         args.push(codec_name.to_string());
         
@@ -214,14 +605,9 @@ impl EncoderOptions {
             args.push(self.preset.to_ffmpeg_name().to_string());
         }
         
-        if self.video_bitrate == 0 {
-            if matches!(self.video_format, VideoFormat::H264 | VideoFormat::H265 | VideoFormat::Vp9) {
-                args.push("-crf".to_string());
-                args.push(self.crf.to_string());
-            }
-        } else {
-            args.push("-b:v".to_string());
-            args.push(format!("{}k", self.video_bitrate / 1000));
+        for (key, value) in self.encoder_options() {
+            args.push(format!("-{}", key));
+            args.push(value);
         }
         
         args.push("-b:a".to_string());
@@ -240,3 +626,12 @@ impl EncoderOptions {
         args
     }
 }
+
+/// A bitrate as FFmpeg's command line writes it
+fn kbits(bits: u32) -> String {
+    format!("{}k", bits / 1000)
+}
+
+fn default_scene_cut_keyframes() -> bool {
+    true
+}
@@ -0,0 +1,229 @@
+use serde::{Serialize, Deserialize};
+use crate::engine::editing::types::EditingError;
+use crate::engine::rendering::formats::VideoFormat;
+
+/// H.264 levels: name, macroblocks per second, macroblocks per frame, and peak
+/// bitrate of Baseline and Main in kbit/s (High allows 1.25 times that)
+const H264_LEVELS: &[(&str, u64, u64, u64)] = &[
+    ("1", 1485, 99, 64),
+    ("1.1", 3000, 396, 192),
+    ("1.2", 6000, 396, 384),
+    ("1.3", 11880, 396, 768),
+    ("2", 11880, 396, 2000),
+    ("2.1", 19800, 792, 4000),
+    ("2.2", 20250, 1620, 4000),
+    ("3", 40500, 1620, 10000),
+    ("3.1", 108000, 3600, 14000),
+    ("3.2", 216000, 5120, 20000),
+    ("4", 245760, 8192, 20000),
+    ("4.1", 245760, 8192, 50000),
+    ("4.2", 522240, 8704, 50000),
+    ("5", 589824, 22080, 135000),
+    ("5.1", 983040, 36864, 240000),
+    ("5.2", 2073600, 36864, 240000),
+    ("6", 4177920, 139264, 240000),
+    ("6.1", 8355840, 139264, 480000),
+    ("6.2", 16711680, 139264, 800000),
+];
+
+/// H.265 levels: name, luma samples per second, luma samples per picture, and peak
+/// bitrate of the Main tier in kbit/s
+const H265_LEVELS: &[(&str, u64, u64, u64)] = &[
+    ("1", 552960, 36864, 128),
+    ("2", 3686400, 122880, 1500),
+    ("2.1", 7372800, 245760, 3000),
+    ("3", 16588800, 552960, 6000),
+    ("3.1", 33177600, 983040, 10000),
+    ("4", 66846720, 2228224, 12000),
+    ("4.1", 133693440, 2228224, 20000),
+    ("5", 267386880, 8912896, 25000),
+    ("5.1", 534773760, 8912896, 40000),
+    ("5.2", 1069547520, 8912896, 60000),
+    ("6", 1069547520, 35651584, 60000),
+    ("6.1", 2139095040, 35651584, 120000),
+    ("6.2", 4278190080, 35651584, 240000),
+];
+
+/// H.264 or H.265 profile, which limits the coding tools a decoder must support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CodecProfile {
+    /// H.264 for the oldest and most constrained decoders: no B-frames or CABAC
+    Baseline,
+    /// H.264 or H.265 8-bit
+    Main,
+    /// H.264 with 8x8 transforms, what most current devices decode
+    High,
+    /// H.265 10-bit, for HDR
+    Main10,
+}
+
+impl CodecProfile {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            CodecProfile::Baseline => "Baseline",
+            CodecProfile::Main => "Main",
+            CodecProfile::High => "High",
+            CodecProfile::Main10 => "Main 10",
+        }
+    }
+
+    /// Whether `video` has this profile
+    pub fn is_compatible_with(&self, video: VideoFormat) -> bool {
+        match video {
+            VideoFormat::H264 => matches!(self, CodecProfile::Baseline | CodecProfile::Main | CodecProfile::High),
+            VideoFormat::H265 => matches!(self, CodecProfile::Main | CodecProfile::Main10),
+            _ => false,
+        }
+    }
+
+    /// Profile name as the FFmpeg encoder `codec_name` takes it
+    pub fn to_ffmpeg_name(&self, codec_name: &str) -> &'static str {
+        match self {
+            // VAAPI only implements the constrained subset
+            CodecProfile::Baseline if codec_name.ends_with("_vaapi") => "constrained_baseline",
+            CodecProfile::Baseline => "baseline",
+            CodecProfile::Main => "main",
+            CodecProfile::High => "high",
+            CodecProfile::Main10 => "main10",
+        }
+    }
+
+    /// Pixel format the encoder `codec_name` needs to encode this profile, if not 8-bit 4:2:0
+    ///
+    /// VAAPI encoders take 10-bit surfaces uploaded by the filter graph instead.
+    pub fn pixel_format(&self, codec_name: &str) -> Option<&'static str> {
+        match self {
+            CodecProfile::Main10 if codec_name.ends_with("_nvenc") => Some("p010le"),
+            CodecProfile::Main10 if !codec_name.ends_with("_vaapi") => Some("yuv420p10le"),
+            _ => None,
+        }
+    }
+}
+
+/// Encoder tuning for a kind of content or use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tune {
+    /// Live action, keeping fine detail
+    Film,
+    /// Flat areas and sharp edges, with more reference frames
+    Animation,
+    /// No frame delay, for live and interactive streams
+    ZeroLatency,
+}
+
+impl Tune {
+    pub fn to_ffmpeg_name(&self) -> &'static str {
+        match self {
+            Tune::Film => "film",
+            Tune::Animation => "animation",
+            Tune::ZeroLatency => "zerolatency",
+        }
+    }
+
+    /// Whether it tunes for content, of which only one applies at a time
+    fn is_content(&self) -> bool {
+        matches!(self, Tune::Film | Tune::Animation)
+    }
+}
+
+/// Profile, level and tuning of an H.264 or H.265 encode
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderProfile {
+    /// `None` leaves it to the encoder
+    pub profile: Option<CodecProfile>,
+    /// Level like "4.1"; `None` leaves it to the encoder
+    pub level: Option<String>,
+    pub tune: Vec<Tune>,
+}
+
+impl EncoderProfile {
+    /// FFmpeg options for the encoder `codec_name`, with the keys of its command line
+    ///
+    /// x264 takes several tunes at once; NVENC has a low latency mode instead of tunes.
+    pub fn ffmpeg_options(&self, codec_name: &str) -> Vec<(&'static str, String)> {
+        let mut options = Vec::new();
+        if let Some(profile) = self.profile {
+            options.push(("profile:v", profile.to_ffmpeg_name(codec_name).to_string()));
+            if let Some(pixel_format) = profile.pixel_format(codec_name) {
+                options.push(("pix_fmt", pixel_format.to_string()));
+            }
+        }
+        if let Some(level) = &self.level {
+            options.push(("level", level.clone()));
+        }
+        if !self.tune.is_empty() {
+            if codec_name.ends_with("_nvenc") {
+                options.push(("tune", "ull".to_string()));
+                options.push(("zerolatency", "1".to_string()));
+            } else {
+                let names: Vec<&str> = self.tune.iter().map(|tune| tune.to_ffmpeg_name()).collect();
+                options.push(("tune", names.join(",")));
+            }
+        }
+        options
+    }
+
+    /// Check the settings suit the encoder `codec_name` for `video`, and that a
+    /// `width`x`height` picture at `frame_rate` and `peak_bitrate` bit/s fits the level
+    pub fn validate(&self, video: VideoFormat, codec_name: &str, width: u32, height: u32, frame_rate: f64, peak_bitrate: u32) -> Result<(), EditingError> {
+        let invalid = |message: String| Err(EditingError::InvalidParameter(message));
+        if let Some(profile) = self.profile {
+            if !profile.is_compatible_with(video) {
+                return invalid(format!("{} has no {} profile", video.display_name(), profile.display_name()));
+            }
+        }
+
+        if !self.tune.is_empty() {
+            let content = self.tune.iter().filter(|tune| tune.is_content()).count();
+            let supported = match codec_name {
+                "libx264" => content <= 1,
+                // One tune at a time, and no film tune
+                "libx265" => self.tune.len() == 1 && self.tune[0] != Tune::Film,
+                name if name.ends_with("_nvenc") => content == 0,
+                _ => false,
+            };
+            if !supported {
+                let names: Vec<&str> = self.tune.iter().map(|tune| tune.to_ffmpeg_name()).collect();
+                return invalid(format!("{} can't tune for {}", codec_name, names.join(" and ")));
+            }
+        }
+
+        let Some(level) = &self.level else {
+            return Ok(());
+        };
+        let level = level.trim_end_matches(".0");
+        let (pixels, frame_limit, rate_limit, bitrate_limit) = match video {
+            VideoFormat::H264 => {
+                let Some(&(_, rate, frame, bitrate)) = H264_LEVELS.iter().find(|entry| entry.0 == level) else {
+                    return invalid(format!("Unknown H.264 level {}", level));
+                };
+                // Counted in 16x16 macroblocks
+                let high = matches!(self.profile, Some(CodecProfile::High) | None);
+                let bitrate = if high { bitrate * 5 / 4 } else { bitrate };
+                ((width.div_ceil(16) as u64, height.div_ceil(16) as u64), frame, rate, bitrate)
+            },
+            VideoFormat::H265 => {
+                let Some(&(_, rate, frame, bitrate)) = H265_LEVELS.iter().find(|entry| entry.0 == level) else {
+                    return invalid(format!("Unknown H.265 level {}", level));
+                };
+                ((width as u64, height as u64), frame, rate, bitrate)
+            },
+            _ => return invalid(format!("{} has no levels", video.display_name())),
+        };
+
+        // Neither side may be longer than a square picture eight times the area
+        let (columns, rows) = pixels;
+        let max_side = ((8 * frame_limit) as f64).sqrt();
+        if columns * rows > frame_limit || columns as f64 > max_side || rows as f64 > max_side {
+            return invalid(format!("{}x{} is too large for level {}", width, height, level));
+        }
+        if (columns * rows) as f64 * frame_rate > rate_limit as f64 {
+            return invalid(format!("{}x{} at {} fps is too fast for level {}", width, height, frame_rate, level));
+        }
+        if peak_bitrate as u64 > bitrate_limit * 1000 {
+            return invalid(format!("{} kbit/s is over the {} kbit/s of level {}", peak_bitrate / 1000, bitrate_limit, level));
+        }
+        Ok(())
+    }
+}
@@ -4,13 +4,29 @@ use std::thread;
 use std::time::Duration;
 use anyhow::Result;
 use ffmpeg_next as ffmpeg;
+use serde::{Serialize, Deserialize};
 use crate::engine::editing::types::EditingError;
+use crate::engine::frame_rate::{FrameRate, Pulldown, PulldownQueue};
+use crate::engine::generators::Generator;
 use crate::engine::rendering::formats::{VideoFormat, AudioFormat, ContainerFormat};
 use crate::engine::rendering::encoder::EncoderPreset;
+use crate::engine::rendering::audio_quality::AudioQualityOptions;
+use crate::engine::rendering::container_options::ContainerOptions;
+use crate::engine::rendering::determinism;
+use crate::engine::rendering::export_region::{chapters_for_range, Chapter, ExportRegion};
+use crate::engine::rendering::leader::Leader;
+use crate::engine::rendering::recovery::{ErrorClass, ExportFailure, FailedAttempt, RetryPolicy};
+use crate::engine::rendering::throttle::IoThrottle;
+use crate::engine::rendering::track_selection::TrackSelection;
+use crate::engine::timeline::Timeline;
+use crate::modules::disk_space::{self, SpaceCheck};
+use crate::modules::metrics::{self, names, Meter};
+use crate::modules::video_levels::{broadcast_safe, LevelLimits, YuvFrameMut};
 
 pub type ExportCallback = Arc<Mutex<dyn Fn(ExportProgress) + Send + 'static>>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ExportOptions {
     pub input_path: PathBuf,
     
@@ -18,6 +34,9 @@ pub struct ExportOptions {
     
     pub container_format: ContainerFormat,
     
+    /// Muxer settings, like where an MP4 keeps its index
+    pub container_options: ContainerOptions,
+    
     pub video_format: VideoFormat,
     
     pub audio_format: AudioFormat,
@@ -26,8 +45,12 @@ pub struct ExportOptions {
     
     pub audio_bitrate: u32,
     
+    /// Frame rate of the program; NTSC rates like 29.97 are written as 30000/1001
     pub frame_rate: f64,
     
+    /// 3:2 pulldown between 23.976 and 29.97 (or 24 and 30) on the way out
+    pub pulldown: Pulldown,
+    
     pub width: u32,
     
     pub height: u32,
@@ -39,6 +62,26 @@ pub struct ExportOptions {
     pub hardware_acceleration: bool,
     
     pub threads: u8,
+    
+    pub audio_quality: AudioQualityOptions,
+    
+    /// Render the same output from the same input on every run, see `determinism`
+    pub deterministic: bool,
+    
+    /// Part of the input (start, end) to render, in seconds; all of it when `None`
+    pub range: Option<(f64, f64)>,
+    
+    /// Chapters to write, with times relative to the start of the program
+    pub chapters: Vec<Chapter>,
+    
+    /// Bars, tone and slate to render ahead of the program
+    pub leader: Option<Leader>,
+    
+    /// Limit the program's video to these levels, for deliveries that must be legal
+    pub broadcast_safe: Option<LevelLimits>,
+    
+    /// Tracks the input was rendered from; without audio, no audio stream is written
+    pub tracks: TrackSelection,
 }
 
 impl Default for ExportOptions {
@@ -47,6 +90,7 @@ impl Default for ExportOptions {
             input_path: PathBuf::new(),
             output_path: PathBuf::new(),
             container_format: ContainerFormat::Mp4,
+            container_options: ContainerOptions::default(),
             video_format: VideoFormat::H264,
             audio_format: AudioFormat::Aac,
             // Set a reasonable default video bitrate (2 Mbps)
@@ -55,13 +99,72 @@ impl Default for ExportOptions {
             audio_bitrate: 128_000,
             // Set a standard default frame rate
             frame_rate: 30.0,
+            pulldown: Pulldown::None,
             width: 0,
             height: 0,
             encoder_preset: EncoderPreset::Medium,
             crf: 23,
             hardware_acceleration: false,
             threads: 0,
+            audio_quality: AudioQualityOptions::default(),
+            deterministic: false,
+            range: None,
+            chapters: Vec::new(),
+            leader: None,
+            broadcast_safe: None,
+            tracks: TrackSelection::default(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Encoder threads to use, 0 for the encoder's choice
+    pub fn encoder_threads(&self) -> u8 {
+        determinism::encoder_threads(self.threads, self.deterministic)
+    }
+    
+    /// FFmpeg options for the video encoder besides rate control and threads
+    ///
+    /// Inserting pulldown weaves fields into interlaced frames, so it marks the stream as
+    /// top field first and has the encoder code it as interlaced. Removing it gives
+    /// progressive frames back.
+    pub fn video_codec_options(&self) -> Vec<(&'static str, String)> {
+        let mut codec_options: Vec<(&'static str, String)> = Vec::new();
+        if self.deterministic {
+            codec_options.extend(determinism::ffmpeg_codec_options(self.video_format)
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string())));
+        }
+        
+        if self.pulldown == Pulldown::Insert {
+            codec_options.push(("flags", "+ildct+ilme".to_string()));
+            codec_options.push(("field_order", "tt".to_string()));
+            if self.video_format == VideoFormat::H264 {
+                // Setting `x264-params` twice would drop the first value
+                match codec_options.iter_mut().find(|(key, _)| *key == "x264-params") {
+                    Some((_, params)) => params.push_str(":tff=1"),
+                    None => codec_options.push(("x264-params", "tff=1".to_string())),
+                }
+            }
         }
+        codec_options
+    }
+    
+    /// Render only `region` of a timeline whose render is the input, with its markers
+    /// as chapters offset to the start of the output
+    pub fn with_region(mut self, timeline: &Timeline, region: ExportRegion) -> Result<Self, EditingError> {
+        let (start, end) = region.range(timeline)?;
+        self.range = (region != ExportRegion::WholeSequence).then_some((start, end));
+        self.chapters = chapters_for_range(timeline.markers(), start, end);
+        Ok(self)
+    }
+    
+    /// Render only some tracks of a timeline, returning the options and the copy of the
+    /// timeline to render as the input
+    pub fn with_tracks(mut self, timeline: &Timeline, tracks: TrackSelection) -> Result<(Self, Timeline), EditingError> {
+        let selected = tracks.apply(timeline)?;
+        self.tracks = tracks;
+        Ok((self, selected))
     }
 }
 
@@ -80,6 +183,12 @@ pub struct ExportProgress {
     pub complete: bool,
     
     pub error: Option<String>,
+    
+    /// Structured report when the export failed
+    pub failure: Option<ExportFailure>,
+    
+    /// Current attempt, starting at 1
+    pub attempt: u32,
 }
 
 pub struct Exporter {
@@ -92,6 +201,12 @@ pub struct Exporter {
     export_thread: Option<thread::JoinHandle<Result<(), EditingError>>>,
     
     cancel_flag: Arc<Mutex<bool>>,
+    
+    retry_policy: RetryPolicy,
+    
+    space_check: Option<SpaceCheck>,
+    
+    io_throttle: IoThrottle,
 }
 
 impl Exporter {
@@ -106,6 +221,8 @@ impl Exporter {
             percent: 0.0,
             complete: false,
             error: None,
+            failure: None,
+            attempt: 1,
         }));
         
         Ok(Self {
@@ -114,9 +231,27 @@ impl Exporter {
             progress_callback: None,
             export_thread: None,
             cancel_flag: Arc::new(Mutex::new(false)),
+            retry_policy: RetryPolicy::default(),
+            space_check: Some(SpaceCheck::default()),
+            io_throttle: IoThrottle::unlimited(),
         })
     }
     
+    /// Set how recoverable errors are retried
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+    
+    /// Set the free space check run before exporting, `None` disables it
+    pub fn set_space_check(&mut self, check: Option<SpaceCheck>) {
+        self.space_check = check;
+    }
+    
+    /// Limit the output write rate; the throttle can be adjusted while exporting
+    pub fn set_io_throttle(&mut self, throttle: IoThrottle) {
+        self.io_throttle = throttle;
+    }
+    
     pub fn set_progress_callback<F>(&mut self, callback: F)
     where
         F: Fn(ExportProgress) + Send + 'static,
@@ -127,266 +262,487 @@ impl Exporter {
     pub fn start_export(&mut self) -> Result<(), EditingError> {
         *self.cancel_flag.lock().unwrap() = false;
         
+        {
+            let mut progress = self.progress.lock().unwrap();
+            progress.error = None;
+            progress.failure = None;
+            progress.attempt = 1;
+        }
+        
         let options = self.options.clone();
         let progress = self.progress.clone();
         let callback = self.progress_callback.clone();
         let cancel_flag = self.cancel_flag.clone();
         
+        let retry_policy = self.retry_policy.clone();
+        let space_check = self.space_check.clone();
+        let io_throttle = self.io_throttle.clone();
+        
+        // Created here so it nests under the caller's span, e.g. a render queue job
+        let span = tracing::info_span!("encode", output = %options.output_path.display());
+        
         let handle = thread::spawn(move || {
-            let input_path = options.input_path.to_string_lossy().to_string();
-            let mut input_context = match ffmpeg::format::input(&input_path) {
-                Ok(ctx) => ctx,
-                Err(e) => {
-                    let error_msg = format!("Failed to open input file: {}", e);
-                    Self::update_progress_with_error(&progress, &callback, &error_msg);
-                    return Err(EditingError::ExportError(error_msg));
-                }
-            };
-            
-            if let Err(e) = input_context.dump() {
-                let error_msg = format!("Failed to read stream information: {}", e);
-                Self::update_progress_with_error(&progress, &callback, &error_msg);
-                return Err(EditingError::ExportError(error_msg));
-            }
+            let _span = span.enter();
+            let mut attempts: Vec<FailedAttempt> = Vec::new();
             
-            let (video_stream_index, audio_stream_index) = {
-                let video_stream = input_context.streams()
-                    .best(ffmpeg::media::Type::Video)
-                    .map(|s| s.index());
-                
-                let audio_stream = input_context.streams()
-                    .best(ffmpeg::media::Type::Audio)
-                    .map(|s| s.index());
+            loop {
+                let attempt = attempts.len() as u32 + 1;
+                let error = match Self::run_export(&options, space_check.as_ref(), &io_throttle, &progress, &callback, &cancel_flag) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => e,
+                };
                 
-                (video_stream, audio_stream)
-            };
-            
-            let (width, height, frame_rate, total_frames, duration) = if let Some(stream_index) = video_stream_index {
-                let stream = input_context.stream(stream_index).unwrap();
-                let codec_context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+                let class = if *cancel_flag.lock().unwrap() {
+                    ErrorClass::Cancelled
+                } else {
+                    ErrorClass::from_message(&error.to_string())
+                };
+                let frame = progress.lock().unwrap().current_frame;
+                attempts.push(FailedAttempt {
+                    attempt,
+                    class,
+                    message: error.to_string(),
+                    frame,
+                });
                 
-                let width = codec_context.width();
-                let height = codec_context.height();
+                if retry_policy.should_retry(class, attempt) {
+                    let delay = retry_policy.backoff(attempt);
+                    tracing::warn!("Export attempt {} failed ({:?}: {}), retrying in {:?}", attempt, class, error, delay);
+                    
+                    if Self::wait_for_retry(delay, &cancel_flag) {
+                        let mut progress_guard = progress.lock().unwrap();
+                        progress_guard.error = None;
+                        progress_guard.complete = false;
+                        progress_guard.current_frame = 0;
+                        progress_guard.current_time = 0.0;
+                        progress_guard.percent = 0.0;
+                        progress_guard.attempt = attempt + 1;
+                        continue;
+                    }
+                }
                 
-                let frame_rate = if let Some(rate) = stream.avg_frame_rate() {
-                    rate.numerator() as f64 / rate.denominator() as f64
-                } else {
-                    25.0 // Default frame rate
+                // Give up and report where it failed
+                let frame_rate = FrameRate::from_fps(if options.frame_rate > 0.0 { options.frame_rate } else { 25.0 });
+                let failure = ExportFailure {
+                    class,
+                    message: error.to_string(),
+                    debug: None,
+                    element: Self::failing_encoder(&options, class, &error.to_string()),
+                    frame_range: Some((frame, frame + 1)),
+                    time_range: Some((frame_rate.frames_to_seconds(frame), frame_rate.frames_to_seconds(frame + 1))),
+                    attempts,
                 };
+                tracing::error!("{}", failure.summary());
                 
-                let duration = stream.duration() as f64 * f64::from(stream.time_base());
-                let total_frames = (duration * frame_rate) as u64;
+                {
+                    let mut progress_guard = progress.lock().unwrap();
+                    progress_guard.error = Some(error.to_string());
+                    progress_guard.failure = Some(failure);
+                    progress_guard.complete = true;
+                    
+                    if let Some(callback) = &callback {
+                        callback.lock().unwrap()(progress_guard.clone());
+                    }
+                }
                 
-                (width, height, frame_rate, total_frames, duration)
-            } else {
-                let error_msg = "No video stream found in input file".to_string();
+                return Err(error);
+            }
+        });
+        
+        self.export_thread = Some(handle);
+        
+        Ok(())
+    }
+    
+    /// Run a single export attempt
+    fn run_export(
+        options: &ExportOptions,
+        space_check: Option<&SpaceCheck>,
+        io_throttle: &IoThrottle,
+        progress: &Arc<Mutex<ExportProgress>>,
+        callback: &Option<ExportCallback>,
+        cancel_flag: &Arc<Mutex<bool>>,
+    ) -> Result<(), EditingError> {
+        // A lossy conversion would open or create a different file
+        let input_path = match options.input_path.to_str() {
+            Some(path) => path.to_string(),
+            None => {
+                let error_msg = format!("Input path is not valid UTF-8: {:?}", options.input_path);
                 Self::update_progress_with_error(&progress, &callback, &error_msg);
                 return Err(EditingError::ExportError(error_msg));
+            }
+        };
+        let mut input_context = match ffmpeg::format::input(&input_path) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                let error_msg = format!("Failed to open input file: {}", e);
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
+            }
+        };
+        
+        if let Err(e) = input_context.dump() {
+            let error_msg = format!("Failed to read stream information: {}", e);
+            Self::update_progress_with_error(&progress, &callback, &error_msg);
+            return Err(EditingError::ExportError(error_msg));
+        }
+        
+        let (video_stream_index, audio_stream_index) = {
+            let video_stream = input_context.streams()
+                .best(ffmpeg::media::Type::Video)
+                .map(|s| s.index());
+            
+            let audio_stream = input_context.streams()
+                .best(ffmpeg::media::Type::Audio)
+                .map(|s| s.index())
+                .filter(|_| !options.tracks.video_only);
+            
+            (video_stream, audio_stream)
+        };
+        
+        let (width, height, frame_rate, total_frames, duration) = if let Some(stream_index) = video_stream_index {
+            let stream = input_context.stream(stream_index).unwrap();
+            let codec_context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+            
+            let width = codec_context.width();
+            let height = codec_context.height();
+            
+            let frame_rate = if let Some(rate) = stream.avg_frame_rate() {
+                rate.numerator() as f64 / rate.denominator() as f64
+            } else {
+                25.0 // Default frame rate
             };
             
-            {
-                let mut progress_guard = progress.lock().unwrap();
-                progress_guard.total_frames = total_frames;
-                progress_guard.total_duration = duration;
-                
-                if let Some(callback) = &callback {
-                    callback.lock().unwrap()(progress_guard.clone());
-                }
+            let stream_duration = stream.duration() as f64 * f64::from(stream.time_base());
+            let (start, end) = options.range.unwrap_or((0.0, stream_duration));
+            let duration = (end.min(stream_duration) - start).max(0.0);
+            let total_frames = (duration * frame_rate) as u64;
+            
+            (width, height, frame_rate, total_frames, duration)
+        } else {
+            let error_msg = "No video stream found in input file".to_string();
+            Self::update_progress_with_error(&progress, &callback, &error_msg);
+            return Err(EditingError::ExportError(error_msg));
+        };
+        
+        // Pulldown changes the rate the program's frames go out at
+        let program_rate = FrameRate::from_fps(if options.frame_rate > 0.0 { options.frame_rate } else { frame_rate });
+        let out_rate = match options.pulldown.output_rate(program_rate) {
+            Some(rate) => rate,
+            None => {
+                let error_msg = format!("Pulldown {} doesn't apply to {} fps", options.pulldown.as_str(), program_rate);
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
+            }
+        };
+        let out_frame_rate = out_rate.as_f64();
+        let total_frames = options.pulldown.output_frames(total_frames);
+        let (leader_frames, leader_duration) = options.leader.as_ref()
+            .map_or((0, 0.0), |leader| (leader.frames(out_frame_rate), leader.duration()));
+        let total_frames = total_frames + leader_frames;
+        let duration = duration + leader_duration;
+        
+        if let Some(check) = space_check {
+            let estimated = disk_space::estimate_output_size(options, duration);
+            if let Err(e) = check.check(&options.output_path, estimated) {
+                let error_msg = e.to_string();
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
             }
+        }
+        
+        {
+            let mut progress_guard = progress.lock().unwrap();
+            progress_guard.total_frames = total_frames;
+            progress_guard.total_duration = duration;
             
-            let output_path = options.output_path.to_string_lossy().to_string();
-            let mut output_context = match ffmpeg::format::output(&output_path) {
-                Ok(ctx) => ctx,
-                Err(e) => {
-                    let error_msg = format!("Failed to create output file: {}", e);
-                    Self::update_progress_with_error(&progress, &callback, &error_msg);
-                    return Err(EditingError::ExportError(error_msg));
-                }
-            };
+            if let Some(callback) = &callback {
+                callback.lock().unwrap()(progress_guard.clone());
+            }
+        }
+        
+        // A lossy conversion would open or create a different file
+        let output_path = match options.output_path.to_str() {
+            Some(path) => path.to_string(),
+            None => {
+                let error_msg = format!("Output path is not valid UTF-8: {:?}", options.output_path);
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
+            }
+        };
+        let mut output_context = match ffmpeg::format::output(&output_path) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                let error_msg = format!("Failed to create output file: {}", e);
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
+            }
+        };
+        
+        let format_name = options.container_format.to_ffmpeg_name();
+        output_context.set_format(format_name);
+        
+        // Chapter times in milliseconds, after the leader
+        for (index, chapter) in options.chapters.iter().enumerate() {
+            output_context.add_chapter(
+                index as i64,
+                ffmpeg::util::rational::Rational::new(1, 1000),
+                ((leader_duration + chapter.start) * 1000.0).round() as i64,
+                ((leader_duration + chapter.end) * 1000.0).round() as i64,
+                &chapter.title,
+            )?;
+        }
+        
+        let video_codec_name = options.video_format.to_ffmpeg_name();
+        let video_codec = ffmpeg::encoder::find_by_name(video_codec_name)
+            .ok_or_else(|| {
+                let error_msg = format!("Video codec not found: {}", video_codec_name);
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                EditingError::ExportError(error_msg)
+            })?;
+        
+        let mut video_stream = output_context.add_stream(video_codec)?;
+        
+        {
+            let mut encoder = video_stream.codec().encoder().video()?;
+            
+            let out_width = if options.width > 0 { options.width } else { width as u32 };
+            let out_height = if options.height > 0 { options.height } else { height as u32 };
+            encoder.set_width(out_width);
+            encoder.set_height(out_height);
+            
+            encoder.set_format(ffmpeg::format::pixel::Pixel::YUV420P);
+            
+            let frame_rate_rational = ffmpeg::util::rational::Rational::new(
+                out_rate.numerator as i32,
+                out_rate.denominator as i32,
+            );
+            encoder.set_time_base(frame_rate_rational.invert());
+            video_stream.set_time_base(frame_rate_rational.invert());
+            
+            if options.video_bitrate > 0 {
+                encoder.set_bit_rate(options.video_bitrate as i64);
+            } else {
+                encoder.set_option("crf", &options.crf.to_string())?;
+            }
+            
+            encoder.set_option("preset", options.encoder_preset.to_ffmpeg_name())?;
+            
+            let threads = options.encoder_threads();
+            if threads > 0 {
+                encoder.set_option("threads", &threads.to_string())?;
+            }
             
-            let format_name = options.container_format.to_ffmpeg_name();
-            output_context.set_format(format_name);
+            for (key, value) in options.video_codec_options() {
+                encoder.set_option(key, &value)?;
+            }
             
-            let video_codec_name = options.video_format.to_ffmpeg_name();
-            let video_codec = ffmpeg::encoder::find_by_name(video_codec_name)
+            encoder.open()?;
+        }
+        
+        let mut audio_stream_index_out = None;
+        if let Some(audio_index) = audio_stream_index {
+            let audio_codec_name = options.audio_format.to_ffmpeg_name();
+            let audio_codec = ffmpeg::encoder::find_by_name(audio_codec_name)
                 .ok_or_else(|| {
-                    let error_msg = format!("Video codec not found: {}", video_codec_name);
+                    let error_msg = format!("Audio codec not found: {}", audio_codec_name);
                     Self::update_progress_with_error(&progress, &callback, &error_msg);
                     EditingError::ExportError(error_msg)
                 })?;
             
-            let mut video_stream = output_context.add_stream(video_codec)?;
+            let mut audio_stream = output_context.add_stream(audio_codec)?;
+            audio_stream_index_out = Some(audio_stream.index());
             
             {
-                let mut encoder = video_stream.codec().encoder().video()?;
+                let input_stream = input_context.stream(audio_index).unwrap();
+                let input_codec_context = ffmpeg::codec::context::Context::from_parameters(input_stream.parameters())?;
+                let input_codec_par = input_codec_context.parameters();
                 
-                let out_width = if options.width > 0 { options.width } else { width as u32 };
-                let out_height = if options.height > 0 { options.height } else { height as u32 };
-                encoder.set_width(out_width);
-                encoder.set_height(out_height);
+                let mut encoder = audio_stream.codec().encoder().audio()?;
                 
-                encoder.set_format(ffmpeg::format::pixel::Pixel::YUV420P);
+                encoder.set_rate(input_codec_par.rate() as i32);
+                encoder.set_channels(input_codec_par.channels() as i32);
+                encoder.set_channel_layout(input_codec_par.channel_layout());
+                encoder.set_format(Self::audio_sample_format(options.audio_format));
                 
-                let out_frame_rate = if options.frame_rate > 0.0 { options.frame_rate } else { frame_rate };
-                let frame_rate_rational = ffmpeg::util::rational::Rational::new(
-                    (out_frame_rate * 1000.0) as i32,
-                    1000,
-                );
-                encoder.set_time_base(frame_rate_rational.invert());
-                video_stream.set_time_base(frame_rate_rational.invert());
+                let time_base = ffmpeg::util::rational::Rational::new(1, input_codec_par.rate() as i32);
+                encoder.set_time_base(time_base);
+                audio_stream.set_time_base(time_base);
                 
-                if options.video_bitrate > 0 {
-                    encoder.set_bit_rate(options.video_bitrate as i64);
-                } else {
-                    encoder.set_option("crf", &options.crf.to_string())?;
+                if options.audio_bitrate > 0 {
+                    encoder.set_bit_rate(options.audio_bitrate as i64);
                 }
                 
-                encoder.set_option("preset", options.encoder_preset.to_ffmpeg_name())?;
-                
-                if options.threads > 0 {
-                    encoder.set_option("threads", &options.threads.to_string())?;
+                if options.deterministic {
+                    encoder.set_option("flags", "+bitexact")?;
                 }
                 
                 encoder.open()?;
             }
-            
-            let mut audio_stream_index_out = None;
-            if let Some(audio_index) = audio_stream_index {
-                let audio_codec_name = options.audio_format.to_ffmpeg_name();
-                let audio_codec = ffmpeg::encoder::find_by_name(audio_codec_name)
-                    .ok_or_else(|| {
-                        let error_msg = format!("Audio codec not found: {}", audio_codec_name);
-                        Self::update_progress_with_error(&progress, &callback, &error_msg);
-                        EditingError::ExportError(error_msg)
-                    })?;
-                
-                let mut audio_stream = output_context.add_stream(audio_codec)?;
-                audio_stream_index_out = Some(audio_stream.index());
-                
-                {
-                    let input_stream = input_context.stream(audio_index).unwrap();
-                    let input_codec_context = ffmpeg::codec::context::Context::from_parameters(input_stream.parameters())?;
-                    let input_codec_par = input_codec_context.parameters();
-                    
-                    let mut encoder = audio_stream.codec().encoder().audio()?;
-                    
-                    encoder.set_rate(input_codec_par.rate() as i32);
-                    encoder.set_channels(input_codec_par.channels() as i32);
-                    encoder.set_channel_layout(input_codec_par.channel_layout());
-                    encoder.set_format(ffmpeg::format::sample::Sample::F32(ffmpeg::format::sample::Type::Planar));
-                    
-                    let time_base = ffmpeg::util::rational::Rational::new(1, input_codec_par.rate() as i32);
-                    encoder.set_time_base(time_base);
-                    audio_stream.set_time_base(time_base);
-                    
-                    if options.audio_bitrate > 0 {
-                        encoder.set_bit_rate(options.audio_bitrate as i64);
-                    }
-                    
-                    encoder.open()?;
-                }
+        }
+        
+        let mut format_options = ffmpeg::Dictionary::new();
+        for (key, value) in options.container_options.ffmpeg_format_options(options.container_format) {
+            format_options.set(key, &value);
+        }
+        if options.deterministic {
+            for (key, value) in determinism::ffmpeg_format_options() {
+                format_options.set(key, value);
             }
+        }
+        output_context.write_header_with(format_options)?;
+        
+        let mut video_decoder = {
+            let stream = input_context.stream(video_stream_index.unwrap()).unwrap();
+            let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+            context.decoder().video()?
+        };
+        
+        let mut audio_decoder = if let Some(audio_index) = audio_stream_index {
+            let stream = input_context.stream(audio_index).unwrap();
+            let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+            Some(context.decoder().audio()?)
+        } else {
+            None
+        };
+        
+        let mut scaler = {
+            let out_width = if options.width > 0 { options.width } else { width as u32 };
+            let out_height = if options.height > 0 { options.height } else { height as u32 };
             
-            output_context.write_header()?;
-            
-            let mut video_decoder = {
-                let stream = input_context.stream(video_stream_index.unwrap()).unwrap();
-                let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
-                context.decoder().video()?
-            };
-            
-            let mut audio_decoder = if let Some(audio_index) = audio_stream_index {
-                let stream = input_context.stream(audio_index).unwrap();
-                let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
-                Some(context.decoder().audio()?)
-            } else {
-                None
-            };
-            
-            let mut scaler = {
-                let out_width = if options.width > 0 { options.width } else { width as u32 };
-                let out_height = if options.height > 0 { options.height } else { height as u32 };
-                
-                ffmpeg::software::scaling::context::Context::get(
-                    video_decoder.format(),
-                    video_decoder.width(),
-                    video_decoder.height(),
-                    ffmpeg::format::pixel::Pixel::YUV420P,
-                    out_width,
-                    out_height,
-                    ffmpeg::software::scaling::flag::Flags::BILINEAR,
-                )?
-            };
-            
-            let mut resampler = if let Some(ref audio_decoder) = audio_decoder {
-                let out_stream = output_context.stream(audio_stream_index_out.unwrap()).unwrap();
-                let out_codec = out_stream.codec();
-                let out_codec_context = out_codec.encoder().audio()?;
-                
-                Some(ffmpeg::software::resampling::context::Context::get(
-                    audio_decoder.format(),
-                    audio_decoder.channel_layout(),
-                    audio_decoder.rate(),
-                    ffmpeg::format::sample::Sample::F32(ffmpeg::format::sample::Type::Planar),
-                    out_codec_context.channel_layout(),
-                    out_codec_context.rate(),
-                )?)
-            } else {
-                None
-            };
-            
-            // Create frames with proper allocation
-            let mut decoded = ffmpeg::frame::Video::new(
+            ffmpeg::software::scaling::context::Context::get(
                 video_decoder.format(),
                 video_decoder.width(),
                 video_decoder.height(),
-            );
-            
-            let mut encoded = ffmpeg::frame::Video::new(
                 ffmpeg::format::pixel::Pixel::YUV420P,
-                if options.width > 0 { options.width } else { width as u32 },
-                if options.height > 0 { options.height } else { height as u32 },
-            );
+                out_width,
+                out_height,
+                ffmpeg::software::scaling::flag::Flags::BILINEAR,
+            )?
+        };
+        
+        let mut resampler = if let Some(ref audio_decoder) = audio_decoder {
+            let out_stream = output_context.stream(audio_stream_index_out.unwrap()).unwrap();
+            let out_codec = out_stream.codec();
+            let out_codec_context = out_codec.encoder().audio()?;
             
-            let mut audio_decoded = ffmpeg::frame::Audio::empty();
-            let mut audio_encoded = ffmpeg::frame::Audio::empty();
-            let mut packet = ffmpeg::packet::Packet::empty();
+            // Resampler quality, plus dither when reducing to 16 bits
+            let mut swr_options = ffmpeg::Dictionary::new();
+            for (key, value) in options.audio_quality.to_swr_options(options.audio_format) {
+                swr_options.set(key, &value);
+            }
             
-            let mut frame_count = 0;
+            Some(ffmpeg::software::resampling::context::Context::get_with(
+                audio_decoder.format(),
+                audio_decoder.channel_layout(),
+                audio_decoder.rate(),
+                Self::audio_sample_format(options.audio_format),
+                out_codec_context.channel_layout(),
+                out_codec_context.rate(),
+                swr_options,
+            )?)
+        } else {
+            None
+        };
+        
+        // Create frames with proper allocation
+        let mut decoded = ffmpeg::frame::Video::new(
+            video_decoder.format(),
+            video_decoder.width(),
+            video_decoder.height(),
+        );
+        
+        let mut encoded = ffmpeg::frame::Video::new(
+            ffmpeg::format::pixel::Pixel::YUV420P,
+            if options.width > 0 { options.width } else { width as u32 },
+            if options.height > 0 { options.height } else { height as u32 },
+        );
+        
+        let mut audio_decoded = ffmpeg::frame::Audio::empty();
+        let mut audio_encoded = ffmpeg::frame::Audio::empty();
+        let mut packet = ffmpeg::packet::Packet::empty();
+        
+        let mut frame_count = 0;
+        let mut audio_samples = 0;
+        let mut pulldown = PulldownQueue::new(options.pulldown);
+        let interlaced = options.pulldown == Pulldown::Insert;
+        let bytes_written = metrics::metrics().meter(names::EXPORT_BYTES);
+        let frames_encoded = metrics::metrics().meter(names::EXPORT_FRAMES);
+        
+        if let Some(leader) = &options.leader {
+            let out_width = if options.width > 0 { options.width } else { width as u32 };
+            let out_height = if options.height > 0 { options.height } else { height as u32 };
+            (frame_count, audio_samples) = Self::write_leader(
+                leader,
+                &mut output_context,
+                (out_width, out_height),
+                out_frame_rate,
+                interlaced,
+                audio_stream_index_out,
+                io_throttle,
+                &bytes_written,
+            )?;
+        }
+        
+        // Render only the requested range: seek to the keyframe before its start,
+        // drop decoded frames ahead of it and stop at its end
+        let (range_start, range_end) = options.range.unwrap_or((0.0, f64::INFINITY));
+        if range_start > 0.0 {
+            let position = (range_start * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+            input_context.seek(position, ..position)?;
+        }
+        let mut past_range = false;
+        
+        while !past_range && matches!(input_context.read(&mut packet), Ok(true)) {
+            if *cancel_flag.lock().unwrap() {
+                let error_msg = "Export cancelled".to_string();
+                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                return Err(EditingError::ExportError(error_msg));
+            }
             
-            while let Ok(true) = input_context.read(&mut packet) {
-                if *cancel_flag.lock().unwrap() {
-                    let error_msg = "Export cancelled".to_string();
-                    Self::update_progress_with_error(&progress, &callback, &error_msg);
-                    return Err(EditingError::ExportError(error_msg));
-                }
-                
-                if let Some(stream_index) = video_stream_index {
-                    if packet.stream() == stream_index {
-                        video_decoder.send_packet(&packet)?;
+            if let Some(stream_index) = video_stream_index {
+                if packet.stream() == stream_index {
+                    video_decoder.send_packet(&packet)?;
+                    
+                    let time_base = input_context.stream(stream_index).unwrap().time_base();
+                    while video_decoder.receive_frame(&mut decoded).is_ok() {
+                        let pts = decoded.timestamp().or(packet.pts()).unwrap_or(0);
+                        let pts_seconds = pts as f64 * f64::from(time_base);
+                        if pts_seconds < range_start {
+                            continue;
+                        }
+                        if pts_seconds >= range_end {
+                            past_range = true;
+                            break;
+                        }
                         
-                        while video_decoder.receive_frame(&mut decoded).is_ok() {
-                            // Clear the encoded frame before reuse
-                            encoded = ffmpeg::frame::Video::new(
-                                ffmpeg::format::pixel::Pixel::YUV420P,
-                                if options.width > 0 { options.width } else { width as u32 },
-                                if options.height > 0 { options.height } else { height as u32 },
-                            );
-                            
-                            scaler.run(&decoded, &mut encoded)?;
-                            
-                            let time_base = input_context.stream(stream_index).unwrap().time_base();
-                            let pts = packet.pts().unwrap_or(0);
-                            let pts_seconds = pts as f64 * f64::from(time_base);
-                            
+                        // Clear the encoded frame before reuse
+                        encoded = ffmpeg::frame::Video::new(
+                            ffmpeg::format::pixel::Pixel::YUV420P,
+                            if options.width > 0 { options.width } else { width as u32 },
+                            if options.height > 0 { options.height } else { height as u32 },
+                        );
+                        
+                        scaler.run(&decoded, &mut encoded)?;
+                        
+                        if let Some(limits) = &options.broadcast_safe {
+                            Self::limit_levels(&mut encoded, limits)?;
+                        }
+                        
+                        // Pulldown holds frames back until the cadence has their fields
+                        let ready = pulldown.push(std::mem::replace(&mut encoded, ffmpeg::frame::Video::empty()), Self::weave_fields);
+                        for mut frame in ready {
                             // Set proper PTS for the encoded frame
-                            encoded.set_pts(Some(frame_count as i64));
+                            frame.set_pts(Some(frame_count as i64));
+                            if interlaced {
+                                Self::mark_top_field_first(&mut frame);
+                            }
                             
                             let out_stream = output_context.stream(0).unwrap();
                             let mut out_codec = out_stream.codec();
                             let mut encoder = out_codec.encoder().video()?;
                             
-                            encoder.send_frame(&encoded)?;
+                            encoder.send_frame(&frame)?;
                             
                             let mut out_packet = ffmpeg::packet::Packet::empty();
                             while encoder.receive_packet(&mut out_packet).is_ok() {
@@ -397,13 +753,16 @@ impl Exporter {
                                 );
                                 
                                 output_context.write_packet(&out_packet)?;
+                                io_throttle.consume(out_packet.size());
+                                bytes_written.mark(out_packet.size() as u64);
                             }
                             
                             frame_count += 1;
+                            frames_encoded.mark(1);
                             {
                                 let mut progress_guard = progress.lock().unwrap();
                                 progress_guard.current_frame = frame_count;
-                                progress_guard.current_time = pts_seconds;
+                                progress_guard.current_time = pts_seconds - range_start;
                                 progress_guard.percent = (frame_count as f64 / total_frames as f64) * 100.0;
                                 
                                 if let Some(callback) = &callback {
@@ -413,101 +772,133 @@ impl Exporter {
                         }
                     }
                 }
-                
-                if let Some(audio_index) = audio_stream_index {
-                    if let Some(audio_stream_out) = audio_stream_index_out {
-                        if packet.stream() == audio_index {
-                            if let Some(ref mut audio_decoder) = audio_decoder {
-                                // Check for cancellation during audio processing too
-                                if *cancel_flag.lock().unwrap() {
-                                    let error_msg = "Export cancelled during audio processing".to_string();
-                                    Self::update_progress_with_error(&progress, &callback, &error_msg);
-                                    return Err(EditingError::ExportError(error_msg));
+            }
+            
+            if let Some(audio_index) = audio_stream_index {
+                if let Some(audio_stream_out) = audio_stream_index_out {
+                    if packet.stream() == audio_index {
+                        if let Some(ref mut audio_decoder) = audio_decoder {
+                            // Check for cancellation during audio processing too
+                            if *cancel_flag.lock().unwrap() {
+                                let error_msg = "Export cancelled during audio processing".to_string();
+                                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                                return Err(EditingError::ExportError(error_msg));
+                            }
+                            
+                            // Handle potential error in send_packet
+                            if let Err(e) = audio_decoder.send_packet(&packet) {
+                                let error_msg = format!("Audio decoder error: {}", e);
+                                Self::update_progress_with_error(&progress, &callback, &error_msg);
+                                return Err(EditingError::ExportError(error_msg));
+                            }
+                            
+                            // Create a new audio frame for each iteration
+                            let audio_time_base = input_context.stream(audio_index).unwrap().time_base();
+                            let mut audio_frame_result = audio_decoder.receive_frame(&mut audio_decoded);
+                            
+                            while audio_frame_result.is_ok() {
+                                let audio_seconds = audio_decoded.timestamp().unwrap_or(0) as f64 * f64::from(audio_time_base);
+                                if audio_seconds < range_start || audio_seconds >= range_end {
+                                    audio_frame_result = audio_decoder.receive_frame(&mut audio_decoded);
+                                    continue;
                                 }
                                 
-                                // Handle potential error in send_packet
-                                if let Err(e) = audio_decoder.send_packet(&packet) {
-                                    let error_msg = format!("Audio decoder error: {}", e);
+                                // Create a new audio encoded frame with proper parameters
+                                audio_encoded = ffmpeg::frame::Audio::empty();
+                                
+                                // Handle resampling with proper error propagation
+                                if let Some(ref mut resampler) = resampler {
+                                    if let Err(e) = resampler.run(&audio_decoded, &mut audio_encoded) {
+                                        let error_msg = format!("Audio resampling error: {}", e);
+                                        Self::update_progress_with_error(&progress, &callback, &error_msg);
+                                        return Err(EditingError::ExportError(error_msg));
+                                    }
+                                } else {
+                                    audio_encoded = audio_decoded.clone();
+                                }
+                                
+                                let out_stream = output_context.stream(audio_stream_out).unwrap();
+                                let mut out_codec = out_stream.codec();
+                                let mut encoder = match out_codec.encoder().audio() {
+                                    Ok(enc) => enc,
+                                    Err(e) => {
+                                        let error_msg = format!("Audio encoder error: {}", e);
+                                        Self::update_progress_with_error(&progress, &callback, &error_msg);
+                                        return Err(EditingError::ExportError(error_msg));
+                                    }
+                                };
+                                
+                                // Number samples on from the leader's
+                                audio_encoded.set_pts(Some(audio_samples));
+                                audio_samples += audio_encoded.samples() as i64;
+                                
+                                // Send frame with error handling
+                                if let Err(e) = encoder.send_frame(&audio_encoded) {
+                                    let error_msg = format!("Audio encoding error: {}", e);
                                     Self::update_progress_with_error(&progress, &callback, &error_msg);
                                     return Err(EditingError::ExportError(error_msg));
                                 }
                                 
-                                // Create a new audio frame for each iteration
-                                let mut audio_frame_result = audio_decoder.receive_frame(&mut audio_decoded);
+                                let mut out_packet = ffmpeg::packet::Packet::empty();
+                                let mut packet_result = encoder.receive_packet(&mut out_packet);
                                 
-                                while audio_frame_result.is_ok() {
-                                    // Create a new audio encoded frame with proper parameters
-                                    audio_encoded = ffmpeg::frame::Audio::empty();
-                                    
-                                    // Handle resampling with proper error propagation
-                                    if let Some(ref mut resampler) = resampler {
-                                        if let Err(e) = resampler.run(&audio_decoded, &mut audio_encoded) {
-                                            let error_msg = format!("Audio resampling error: {}", e);
-                                            Self::update_progress_with_error(&progress, &callback, &error_msg);
-                                            return Err(EditingError::ExportError(error_msg));
-                                        }
-                                    } else {
-                                        audio_encoded = audio_decoded.clone();
-                                    }
-                                    
-                                    let out_stream = output_context.stream(audio_stream_out).unwrap();
-                                    let mut out_codec = out_stream.codec();
-                                    let mut encoder = match out_codec.encoder().audio() {
-                                        Ok(enc) => enc,
-                                        Err(e) => {
-                                            let error_msg = format!("Audio encoder error: {}", e);
-                                            Self::update_progress_with_error(&progress, &callback, &error_msg);
-                                            return Err(EditingError::ExportError(error_msg));
-                                        }
-                                    };
+                                while packet_result.is_ok() {
+                                    out_packet.set_stream(audio_stream_out);
+                                    out_packet.rescale_ts(
+                                        encoder.time_base(),
+                                        out_stream.time_base(),
+                                    );
                                     
-                                    // Send frame with error handling
-                                    if let Err(e) = encoder.send_frame(&audio_encoded) {
-                                        let error_msg = format!("Audio encoding error: {}", e);
+                                    // Write packet with error handling
+                                    if let Err(e) = output_context.write_packet(&out_packet) {
+                                        let error_msg = format!("Error writing audio packet: {}", e);
                                         Self::update_progress_with_error(&progress, &callback, &error_msg);
                                         return Err(EditingError::ExportError(error_msg));
                                     }
+                                    io_throttle.consume(out_packet.size());
+                                    bytes_written.mark(out_packet.size() as u64);
                                     
-                                    let mut out_packet = ffmpeg::packet::Packet::empty();
-                                    let mut packet_result = encoder.receive_packet(&mut out_packet);
-                                    
-                                    while packet_result.is_ok() {
-                                        out_packet.set_stream(audio_stream_out);
-                                        out_packet.rescale_ts(
-                                            encoder.time_base(),
-                                            out_stream.time_base(),
-                                        );
-                                        
-                                        // Write packet with error handling
-                                        if let Err(e) = output_context.write_packet(&out_packet) {
-                                            let error_msg = format!("Error writing audio packet: {}", e);
-                                            Self::update_progress_with_error(&progress, &callback, &error_msg);
-                                            return Err(EditingError::ExportError(error_msg));
-                                        }
-                                        
-                                        // Get next packet
-                                        packet_result = encoder.receive_packet(&mut out_packet);
-                                    }
-                                    
-                                    // Get next frame
-                                    audio_frame_result = audio_decoder.receive_frame(&mut audio_decoded);
+                                    // Get next packet
+                                    packet_result = encoder.receive_packet(&mut out_packet);
                                 }
+                                
+                                // Get next frame
+                                audio_frame_result = audio_decoder.receive_frame(&mut audio_decoded);
                             }
                         }
                     }
                 }
             }
+        }
+        
+        {
+            let out_stream = output_context.stream(0).unwrap();
+            let mut out_codec = out_stream.codec();
+            let mut encoder = out_codec.encoder().video()?;
             
-            {
-                let out_stream = output_context.stream(0).unwrap();
+            encoder.send_eof()?;
+            
+            let mut out_packet = ffmpeg::packet::Packet::empty();
+            while encoder.receive_packet(&mut out_packet).is_ok() {
+                out_packet.set_stream(0);
+                out_packet.rescale_ts(
+                    encoder.time_base(),
+                    out_stream.time_base(),
+                );
+                
+                output_context.write_packet(&out_packet)?;
+            }
+            
+            if let Some(audio_stream_out) = audio_stream_index_out {
+                let out_stream = output_context.stream(audio_stream_out).unwrap();
                 let mut out_codec = out_stream.codec();
-                let mut encoder = out_codec.encoder().video()?;
+                let mut encoder = out_codec.encoder().audio()?;
                 
                 encoder.send_eof()?;
                 
                 let mut out_packet = ffmpeg::packet::Packet::empty();
                 while encoder.receive_packet(&mut out_packet).is_ok() {
-                    out_packet.set_stream(0);
+                    out_packet.set_stream(audio_stream_out);
                     out_packet.rescale_ts(
                         encoder.time_base(),
                         out_stream.time_base(),
@@ -515,57 +906,247 @@ impl Exporter {
                     
                     output_context.write_packet(&out_packet)?;
                 }
+            }
+        }
+        
+        output_context.write_trailer()?;
+        
+        {
+            let mut progress_guard = progress.lock().unwrap();
+            progress_guard.current_frame = total_frames;
+            progress_guard.current_time = duration;
+            progress_guard.percent = 100.0;
+            progress_guard.complete = true;
+            
+            if let Some(callback) = &callback {
+                callback.lock().unwrap()(progress_guard.clone());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Encoder to blame for an encoder error, FFmpeg doesn't tell us which stream failed
+    fn failing_encoder(options: &ExportOptions, class: ErrorClass, message: &str) -> Option<String> {
+        if class != ErrorClass::Encoder {
+            return None;
+        }
+        
+        if message.to_lowercase().contains("audio") {
+            Some(options.audio_format.to_ffmpeg_name().to_string())
+        } else {
+            Some(options.video_format.to_ffmpeg_name().to_string())
+        }
+    }
+    
+    /// Encode the leader ahead of the program; returns the video frames and audio samples written
+    #[allow(clippy::too_many_arguments)]
+    fn write_leader(
+        leader: &Leader,
+        output_context: &mut ffmpeg::format::context::Output,
+        (width, height): (u32, u32),
+        frame_rate: f64,
+        interlaced: bool,
+        audio_stream_out: Option<usize>,
+        io_throttle: &IoThrottle,
+        bytes_written: &Meter,
+    ) -> Result<(u64, i64), EditingError> {
+        let mut video_graph = ffmpeg::filter::Graph::new();
+        video_graph.add(&ffmpeg::filter::find("buffersink").unwrap(), "out", "")?;
+        video_graph.input("out", 0)?.parse(&leader.video_graph(width, height, frame_rate))?;
+        video_graph.validate()?;
+        
+        let mut frame_count = 0;
+        let mut frame = ffmpeg::frame::Video::empty();
+        while video_graph.get("out").unwrap().sink().frame(&mut frame).is_ok() {
+            frame.set_pts(Some(frame_count as i64));
+            if interlaced {
+                Self::mark_top_field_first(&mut frame);
+            }
+            Self::encode_leader_frame(&frame, output_context, io_throttle, bytes_written)?;
+            frame_count += 1;
+        }
+        
+        // The countdown is drawn in RGBA by the generator and converted for the encoder
+        let countdown_frames = leader.countdown_frames(frame_rate);
+        if countdown_frames > 0 {
+            let mut scaler = ffmpeg::software::scaling::context::Context::get(
+                ffmpeg::format::pixel::Pixel::RGBA,
+                width,
+                height,
+                ffmpeg::format::pixel::Pixel::YUV420P,
+                width,
+                height,
+                ffmpeg::software::scaling::flag::Flags::BILINEAR,
+            )?;
+            let mut rgba = ffmpeg::frame::Video::new(ffmpeg::format::pixel::Pixel::RGBA, width, height);
+            let row = width as usize * 4;
+            for index in 0..countdown_frames {
+                let time = index as f64 / frame_rate;
+                let picture = Generator::Countdown.render_at(width, height, time, leader.countdown_duration, frame_rate);
+                let stride = rgba.stride(0);
+                for (line, pixels) in picture.chunks_exact(row).enumerate() {
+                    rgba.data_mut(0)[line * stride..line * stride + row].copy_from_slice(pixels);
+                }
                 
-                if let Some(audio_stream_out) = audio_stream_index_out {
-                    let out_stream = output_context.stream(audio_stream_out).unwrap();
-                    let mut out_codec = out_stream.codec();
-                    let mut encoder = out_codec.encoder().audio()?;
-                    
-                    encoder.send_eof()?;
-                    
-                    let mut out_packet = ffmpeg::packet::Packet::empty();
-                    while encoder.receive_packet(&mut out_packet).is_ok() {
-                        out_packet.set_stream(audio_stream_out);
-                        out_packet.rescale_ts(
-                            encoder.time_base(),
-                            out_stream.time_base(),
-                        );
-                        
-                        output_context.write_packet(&out_packet)?;
-                    }
+                let mut frame = ffmpeg::frame::Video::empty();
+                scaler.run(&rgba, &mut frame)?;
+                frame.set_pts(Some(frame_count as i64));
+                if interlaced {
+                    Self::mark_top_field_first(&mut frame);
                 }
+                Self::encode_leader_frame(&frame, output_context, io_throttle, bytes_written)?;
+                frame_count += 1;
             }
+        }
+        
+        let mut samples = 0;
+        if let Some(audio_stream_out) = audio_stream_out {
+            let (description, frame_size) = {
+                let out_stream = output_context.stream(audio_stream_out).unwrap();
+                let mut out_codec = out_stream.codec();
+                let encoder = out_codec.encoder().audio()?;
+                let channel_layout = format!("0x{:x}", encoder.channel_layout().bits());
+                (leader.audio_graph(encoder.rate(), encoder.format().name(), &channel_layout), encoder.frame_size())
+            };
             
-            output_context.write_trailer()?;
+            let mut audio_graph = ffmpeg::filter::Graph::new();
+            audio_graph.add(&ffmpeg::filter::find("abuffersink").unwrap(), "out", "")?;
+            audio_graph.input("out", 0)?.parse(&description)?;
+            audio_graph.validate()?;
+            // Fixed-size encoders like AAC take whole frames only
+            if frame_size > 0 {
+                audio_graph.get("out").unwrap().sink().set_frame_size(frame_size);
+            }
             
-            {
-                let mut progress_guard = progress.lock().unwrap();
-                progress_guard.current_frame = total_frames;
-                progress_guard.current_time = duration;
-                progress_guard.percent = 100.0;
-                progress_guard.complete = true;
+            let mut frame = ffmpeg::frame::Audio::empty();
+            while audio_graph.get("out").unwrap().sink().frame(&mut frame).is_ok() {
+                frame.set_pts(Some(samples));
+                samples += frame.samples() as i64;
+                
+                let out_stream = output_context.stream(audio_stream_out).unwrap();
+                let mut out_codec = out_stream.codec();
+                let mut encoder = out_codec.encoder().audio()?;
+                
+                encoder.send_frame(&frame)?;
                 
-                if let Some(callback) = &callback {
-                    callback.lock().unwrap()(progress_guard.clone());
+                let mut out_packet = ffmpeg::packet::Packet::empty();
+                while encoder.receive_packet(&mut out_packet).is_ok() {
+                    out_packet.set_stream(audio_stream_out);
+                    out_packet.rescale_ts(
+                        encoder.time_base(),
+                        out_stream.time_base(),
+                    );
+                    
+                    output_context.write_packet(&out_packet)?;
+                    io_throttle.consume(out_packet.size());
+                    bytes_written.mark(out_packet.size() as u64);
                 }
             }
-            
-            Ok(())
-        });
+        }
         
-        self.export_thread = Some(handle);
+        Ok((frame_count, samples))
+    }
+    
+    /// Encode a frame of the leader's video and write out its packets
+    fn encode_leader_frame(
+        frame: &ffmpeg::frame::Video,
+        output_context: &mut ffmpeg::format::context::Output,
+        io_throttle: &IoThrottle,
+        bytes_written: &Meter,
+    ) -> Result<(), EditingError> {
+        let out_stream = output_context.stream(0).unwrap();
+        let mut out_codec = out_stream.codec();
+        let mut encoder = out_codec.encoder().video()?;
+        
+        encoder.send_frame(frame)?;
         
+        let mut out_packet = ffmpeg::packet::Packet::empty();
+        while encoder.receive_packet(&mut out_packet).is_ok() {
+            out_packet.set_stream(0);
+            out_packet.rescale_ts(
+                encoder.time_base(),
+                out_stream.time_base(),
+            );
+            
+            output_context.write_packet(&out_packet)?;
+            io_throttle.consume(out_packet.size());
+            bytes_written.mark(out_packet.size() as u64);
+        }
         Ok(())
     }
     
+    /// Bring a YUV 4:2:0 frame into `limits` in place
+    fn limit_levels(frame: &mut ffmpeg::frame::Video, limits: &LevelLimits) -> Result<(), EditingError> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let (y_stride, chroma_stride) = (frame.stride(0), frame.stride(1));
+        // The planes can't be borrowed mutably together, so chroma is limited in a copy
+        let mut cb = frame.data(1).to_vec();
+        let mut cr = frame.data(2).to_vec();
+        broadcast_safe(
+            &mut YuvFrameMut { width, height, y: frame.data_mut(0), y_stride, cb: &mut cb, cr: &mut cr, chroma_stride },
+            limits,
+        ).map_err(|e| EditingError::ExportError(e.to_string()))?;
+        frame.data_mut(1).copy_from_slice(&cb);
+        frame.data_mut(2).copy_from_slice(&cr);
+        Ok(())
+    }
+    
+    /// Frame of the even lines of `top` and the odd lines of `bottom`, for pulldown
+    fn weave_fields(top: &ffmpeg::frame::Video, bottom: &ffmpeg::frame::Video) -> ffmpeg::frame::Video {
+        let mut woven = top.clone();
+        for plane in 0..woven.planes() {
+            let lines = woven.plane_height(plane) as usize;
+            let (stride, bottom_stride) = (woven.stride(plane), bottom.stride(plane));
+            let width = stride.min(bottom_stride);
+            let source = bottom.data(plane);
+            let data = woven.data_mut(plane);
+            for line in (1..lines).step_by(2) {
+                data[line * stride..line * stride + width]
+                    .copy_from_slice(&source[line * bottom_stride..line * bottom_stride + width]);
+            }
+        }
+        woven
+    }
+    
+    /// Flag a frame as interlaced with the top field first, matching the woven pulldown fields
+    fn mark_top_field_first(frame: &mut ffmpeg::frame::Video) {
+        let flags = ffmpeg::ffi::AV_FRAME_FLAG_INTERLACED | ffmpeg::ffi::AV_FRAME_FLAG_TOP_FIELD_FIRST;
+        // SAFETY: the frame owns a valid AVFrame and only its flags are touched
+        unsafe {
+            (*frame.as_mut_ptr()).flags |= flags as i32;
+        }
+    }
+    
+    /// Sample format the audio encoder is fed
+    fn audio_sample_format(format: AudioFormat) -> ffmpeg::format::Sample {
+        if format.is_16_bit() {
+            ffmpeg::format::sample::Sample::I16(ffmpeg::format::sample::Type::Packed)
+        } else {
+            ffmpeg::format::sample::Sample::F32(ffmpeg::format::sample::Type::Planar)
+        }
+    }
+    
+    /// Sleep before a retry, returning false if the export was cancelled meanwhile
+    fn wait_for_retry(delay: Duration, cancel_flag: &Arc<Mutex<bool>>) -> bool {
+        let deadline = std::time::Instant::now() + delay;
+        while std::time::Instant::now() < deadline {
+            if *cancel_flag.lock().unwrap() {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        !*cancel_flag.lock().unwrap()
+    }
+    
     fn update_progress_with_error(
         progress: &Arc<Mutex<ExportProgress>>,
         callback: &Option<ExportCallback>,
         error_msg: &str,
     ) {
+        // Completion is only reported once retries are exhausted
         let mut progress_guard = progress.lock().unwrap();
         progress_guard.error = Some(error_msg.to_string());
-        progress_guard.complete = true;
         
         if let Some(callback) = callback {
             callback.lock().unwrap()(progress_guard.clone());
@@ -625,4 +1206,9 @@ impl Exporter {
     pub fn get_error(&self) -> Option<String> {
         self.progress.lock().unwrap().error.clone()
     }
+    
+    /// Structured failure report, if the export failed
+    pub fn get_failure(&self) -> Option<ExportFailure> {
+        self.progress.lock().unwrap().failure.clone()
+    }
 }
@@ -0,0 +1,170 @@
+use std::time::Duration;
+use crate::engine::rendering::gst_exporter::ExportProgress;
+use crate::engine::rendering::recovery::{ErrorClass, ExportFailure, FailedAttempt, RetryPolicy};
+
+/// What to do after a pipeline error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorOutcome {
+    /// Restart the pipeline after the delay
+    Retry(Duration),
+    /// The export failed; the progress has the failure report
+    Failed,
+}
+
+/// Progress of a GStreamer export, updated from pipeline events
+///
+/// Holds the bookkeeping `GstExporter` does on bus messages and position queries, apart
+/// from the pipeline, so it can be driven by a simulated one in tests.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    progress: ExportProgress,
+    frame_rate: f64,
+    retry_policy: RetryPolicy,
+    attempts: Vec<FailedAttempt>,
+}
+
+impl ProgressTracker {
+    pub fn new(frame_rate: f64, retry_policy: RetryPolicy) -> Self {
+        Self {
+            progress: ExportProgress {
+                current_frame: 0,
+                total_frames: 0,
+                current_time: 0.0,
+                total_duration: 0.0,
+                percent: 0.0,
+                complete: false,
+                error: None,
+                failure: None,
+                attempt: 1,
+            },
+            frame_rate: frame_rate.max(1.0),
+            retry_policy,
+            attempts: Vec::new(),
+        }
+    }
+
+    pub fn progress(&self) -> &ExportProgress {
+        &self.progress
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Start an export of `duration` seconds from the first attempt
+    pub fn start(&mut self, duration: f64) {
+        self.progress.error = None;
+        self.progress.failure = None;
+        self.progress.attempt = 1;
+        self.progress.total_frames = (duration * self.frame_rate) as u64;
+        self.progress.total_duration = duration;
+        self.attempts.clear();
+    }
+
+    /// Record the pipeline position, in seconds; ignored until the duration is known
+    pub fn position(&mut self, seconds: f64) -> bool {
+        let duration = self.progress.total_duration;
+        if duration <= 0.0 {
+            return false;
+        }
+
+        self.progress.current_time = seconds;
+        self.progress.current_frame = (seconds * self.progress.total_frames as f64 / duration) as u64;
+        self.progress.percent = (seconds / duration) * 100.0;
+        true
+    }
+
+    /// The pipeline reached the end
+    pub fn finish(&mut self) {
+        self.progress.complete = true;
+        self.progress.percent = 100.0;
+        self.progress.current_frame = self.progress.total_frames;
+        self.progress.current_time = self.progress.total_duration;
+    }
+
+    /// The pipeline failed at `position` seconds, if known
+    ///
+    /// Retries start over from the beginning, as the muxer can't resume mid-file.
+    pub fn error(
+        &mut self,
+        class: ErrorClass,
+        message: &str,
+        debug: Option<String>,
+        element: Option<String>,
+        position: Option<f64>,
+    ) -> ErrorOutcome {
+        let progress = &mut self.progress;
+        let failed_frame = position
+            .map(|seconds| (seconds * self.frame_rate) as u64)
+            .unwrap_or(progress.current_frame)
+            .max(progress.current_frame);
+
+        self.attempts.push(FailedAttempt {
+            attempt: progress.attempt,
+            class,
+            message: message.to_string(),
+            frame: failed_frame,
+        });
+
+        if self.retry_policy.should_retry(class, progress.attempt) {
+            let delay = self.retry_policy.backoff(progress.attempt);
+            tracing::warn!(
+                "Export attempt {} failed ({:?}: {}), retrying in {:?}",
+                progress.attempt, class, message, delay
+            );
+
+            progress.attempt += 1;
+            progress.current_frame = 0;
+            progress.current_time = 0.0;
+            progress.percent = 0.0;
+            return ErrorOutcome::Retry(delay);
+        }
+
+        let failure = ExportFailure {
+            class,
+            message: message.to_string(),
+            debug: debug.clone(),
+            element,
+            frame_range: Some((progress.current_frame, failed_frame)),
+            time_range: Some((progress.current_frame as f64 / self.frame_rate, failed_frame as f64 / self.frame_rate)),
+            attempts: std::mem::take(&mut self.attempts),
+        };
+        tracing::error!("{}", failure.summary());
+
+        progress.error = Some(format!("Export error: {} ({})", message, debug.unwrap_or_default()));
+        progress.failure = Some(failure);
+        progress.complete = true;
+        ErrorOutcome::Failed
+    }
+
+    /// The pipeline stopped making progress; stalls are never retried
+    pub fn stalled(&mut self, message: &str, pipeline: &str) {
+        let progress = &mut self.progress;
+        let frame = progress.current_frame;
+        let time = frame as f64 / self.frame_rate;
+        let failure = ExportFailure {
+            class: ErrorClass::Stalled,
+            message: message.to_string(),
+            debug: None,
+            element: Some(pipeline.to_string()),
+            frame_range: Some((frame, frame)),
+            time_range: Some((time, time)),
+            attempts: vec![FailedAttempt {
+                attempt: progress.attempt,
+                class: ErrorClass::Stalled,
+                message: message.to_string(),
+                frame,
+            }],
+        };
+        tracing::error!("{}", failure.summary());
+
+        progress.error = Some(format!("Export error: {}", message));
+        progress.failure = Some(failure);
+        progress.complete = true;
+    }
+
+    pub fn cancelled(&mut self) {
+        self.progress.error = Some("Export cancelled".to_string());
+        self.progress.complete = true;
+    }
+}
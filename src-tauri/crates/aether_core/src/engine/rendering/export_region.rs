@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::editing::types::EditingError;
+use crate::engine::timeline::{Marker, Timeline};
+
+/// Chapters shorter than this are dropped, in seconds
+const MIN_CHAPTER_LENGTH: f64 = 1e-3;
+
+/// Part of the sequence an export renders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportRegion {
+    /// From the start to the end of the timeline
+    #[default]
+    WholeSequence,
+    /// Between the timeline's in and out points, the work area
+    InToOut,
+}
+
+impl ExportRegion {
+    /// Timeline range (start, end) to render, in seconds
+    ///
+    /// `InToOut` fails when the timeline has no in and out points.
+    pub fn range(&self, timeline: &Timeline) -> Result<(f64, f64), EditingError> {
+        match self {
+            ExportRegion::WholeSequence => Ok((0.0, timeline.duration())),
+            ExportRegion::InToOut => timeline.work_area().ok_or_else(|| {
+                EditingError::ExportError("Timeline has no in and out points to export between".to_string())
+            }),
+        }
+    }
+}
+
+/// Chapter written to the output file, with times relative to the start of the output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Chapters for the markers within `start..end` of the timeline, offset to the output
+///
+/// A point marker's chapter runs to the next chapter or the end of the range; a range
+/// marker is cut to the range. Markers outside the range are left out, except a range
+/// marker already running at `start`, which becomes a chapter from the output's start.
+pub fn chapters_for_range(markers: &[Marker], start: f64, end: f64) -> Vec<Chapter> {
+    // (title, start, end) with no end yet for point markers
+    let mut entries: Vec<(String, f64, Option<f64>)> = markers.iter()
+        .filter(|marker| {
+            let marker_end = marker.time + marker.duration;
+            marker.time < end && (marker.time >= start || marker_end > start + MIN_CHAPTER_LENGTH)
+        })
+        .map(|marker| {
+            let range_end = (marker.duration > 0.0).then(|| (marker.time + marker.duration).min(end) - start);
+            (marker.name.clone(), marker.time.max(start) - start, range_end)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let starts: Vec<f64> = entries.iter().map(|(_, start, _)| *start).collect();
+    entries.into_iter()
+        .enumerate()
+        .map(|(index, (title, chapter_start, chapter_end))| {
+            // Point markers run to the next chapter
+            let chapter_end = chapter_end.unwrap_or_else(|| {
+                starts[index + 1..].iter()
+                    .copied()
+                    .find(|next| *next > chapter_start + MIN_CHAPTER_LENGTH)
+                    .unwrap_or(end - start)
+            });
+            Chapter { title, start: chapter_start, end: chapter_end }
+        })
+        .filter(|chapter| chapter.end - chapter.start >= MIN_CHAPTER_LENGTH)
+        .collect()
+}
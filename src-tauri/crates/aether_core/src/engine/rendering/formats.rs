@@ -202,6 +202,11 @@ impl AudioFormat {
         }
     }
     
+    /// Whether the encoder takes 16-bit integer samples, so higher depths need dithering
+    pub fn is_16_bit(&self) -> bool {
+        matches!(self, AudioFormat::Pcm | AudioFormat::Flac)
+    }
+    
     pub fn is_compatible_with(&self, container: ContainerFormat) -> bool {
         match container {
             ContainerFormat::Mp4 => matches!(
@@ -6,8 +6,18 @@ use glib::{MainContext, MainLoop, SourceId};
 use gst::prelude::*;
 use gst_pbutils::prelude::*;
 use crate::engine::editing::types::EditingError;
+use crate::engine::editing::profiler::RenderProfiler;
+use crate::engine::frame_rate::{FrameRate, Pulldown};
 use crate::engine::rendering::formats::{VideoFormat, AudioFormat, ContainerFormat};
 use crate::engine::rendering::encoder::EncoderPreset;
+use crate::engine::rendering::audio_quality::AudioQualityOptions;
+use crate::engine::rendering::container_options::ContainerOptions;
+use crate::engine::rendering::determinism;
+use crate::engine::rendering::export_progress::{ErrorOutcome, ProgressTracker};
+use crate::engine::rendering::recovery::{ErrorClass, ExportFailure, RetryPolicy};
+use crate::modules::disk_space::{self, SpaceCheck};
+use crate::modules::path_policy;
+use crate::modules::pipeline_watchdog::{self, Watchdog, WatchdogConfig, DEFAULT_SHUTDOWN_TIMEOUT};
 
 pub type ExportCallback = Arc<dyn Fn(ExportProgress) + Send + Sync + 'static>;
 
@@ -39,6 +49,18 @@ pub struct ExportOptions {
     pub hardware_acceleration: bool,
     
     pub threads: u8,
+    
+    pub audio_quality: AudioQualityOptions,
+    
+    /// Render the same frames and samples from the same timeline on every run, see `determinism`
+    pub deterministic: bool,
+    
+    /// Muxer settings, like where an MP4 keeps its index
+    pub container_options: ContainerOptions,
+    
+    /// Must be `Pulldown::None`: fields are woven frame by frame, which only the FFmpeg
+    /// exporter does
+    pub pulldown: Pulldown,
 }
 
 impl Default for ExportOptions {
@@ -58,10 +80,82 @@ impl Default for ExportOptions {
             crf: 23,
             hardware_acceleration: false,
             threads: 0,
+            audio_quality: AudioQualityOptions::default(),
+            deterministic: false,
+            container_options: ContainerOptions::default(),
+            pulldown: Pulldown::None,
         }
     }
 }
 
+impl ExportOptions {
+    /// How these options map onto the encoding profile
+    pub fn encoding_plan(&self) -> EncodingPlan {
+        EncodingPlan::new(self.container_format, self.video_format, self.audio_format, self.hardware_acceleration)
+            .with_bitrates(self.video_bitrate, self.audio_bitrate)
+            .with_size(self.width, self.height)
+            .with_frame_rate(self.frame_rate)
+    }
+}
+
+/// Caps and settings of the encoding profile for an export, as plain data
+///
+/// Building the profile itself needs GStreamer; the plan doesn't, so the mapping from
+/// export options can be checked without it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingPlan {
+    pub container_caps: &'static str,
+    pub video_caps: &'static str,
+    pub audio_caps: &'static str,
+    /// `None` leaves the bitrate to the encoder
+    pub video_bitrate: Option<u32>,
+    pub audio_bitrate: Option<u32>,
+    /// Output frame size, `None` keeps the timeline's
+    pub size: Option<(u32, u32)>,
+    /// Output frame rate, `None` keeps the timeline's
+    pub frame_rate: Option<FrameRate>,
+}
+
+impl EncodingPlan {
+    pub fn new(container: ContainerFormat, video: VideoFormat, audio: AudioFormat, hardware_acceleration: bool) -> Self {
+        // Hardware encoders only take H.264 and H.265 at fixed profiles
+        let video_caps = match (hardware_acceleration, video) {
+            (true, VideoFormat::H265) => "video/x-h265, profile=main",
+            (true, _) => "video/x-h264, profile=high",
+            (false, video) => video.to_mime_type(),
+        };
+        
+        Self {
+            container_caps: container.to_mime_type(),
+            video_caps,
+            audio_caps: audio.to_mime_type(),
+            video_bitrate: None,
+            audio_bitrate: None,
+            size: None,
+            frame_rate: None,
+        }
+    }
+    
+    /// Set the bitrates in bits per second, 0 for the encoder's default
+    pub fn with_bitrates(mut self, video: u32, audio: u32) -> Self {
+        self.video_bitrate = (video > 0).then_some(video);
+        self.audio_bitrate = (audio > 0).then_some(audio);
+        self
+    }
+    
+    /// Scale to a frame size; either dimension 0 keeps the timeline's
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width > 0 && height > 0).then_some((width, height));
+        self
+    }
+    
+    /// Encode at a frame rate, exact for NTSC rates; 0 keeps the timeline's
+    pub fn with_frame_rate(mut self, fps: f64) -> Self {
+        self.frame_rate = (fps > 0.0).then(|| FrameRate::from_fps(fps));
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExportProgress {
     pub current_frame: u64,
@@ -77,6 +171,12 @@ pub struct ExportProgress {
     pub complete: bool,
     
     pub error: Option<String>,
+    
+    /// Structured report when the export failed
+    pub failure: Option<ExportFailure>,
+    
+    /// Current attempt, starting at 1
+    pub attempt: u32,
 }
 
 pub struct GstExporter {
@@ -86,7 +186,7 @@ pub struct GstExporter {
     
     main_loop: Option<MainLoop>,
     
-    progress: Arc<Mutex<ExportProgress>>,
+    progress: Arc<Mutex<ProgressTracker>>,
     
     progress_callback: Option<ExportCallback>,
     
@@ -95,23 +195,30 @@ pub struct GstExporter {
     timeout_id: Option<SourceId>,
     
     cancel_flag: Arc<Mutex<bool>>,
+    
+    space_check: Option<SpaceCheck>,
+    
+    profiler: Option<RenderProfiler>,
+    
+    watchdog_config: Option<WatchdogConfig>,
+    
+    watchdog: Option<Watchdog>,
 }
 
 impl GstExporter {
     pub fn new(options: ExportOptions) -> Result<Self, EditingError> {
+        if options.pulldown != Pulldown::None {
+            return Err(EditingError::ExportError(format!(
+                "Pulldown {} needs the FFmpeg exporter",
+                options.pulldown.as_str()
+            )));
+        }
+        
         if !gst::is_initialized() {
             gst::init().map_err(|e| EditingError::ExportError(format!("Failed to initialize GStreamer: {}", e)))?;
         }
         
-        let progress = Arc::new(Mutex::new(ExportProgress {
-            current_frame: 0,
-            total_frames: 0,
-            current_time: 0.0,
-            total_duration: 0.0,
-            percent: 0.0,
-            complete: false,
-            error: None,
-        }));
+        let progress = Arc::new(Mutex::new(ProgressTracker::new(options.frame_rate, RetryPolicy::default())));
         
         Ok(Self {
             options,
@@ -122,9 +229,33 @@ impl GstExporter {
             bus_watch_id: None,
             timeout_id: None,
             cancel_flag: Arc::new(Mutex::new(false)),
+            space_check: Some(SpaceCheck::default()),
+            profiler: None,
+            watchdog_config: Some(WatchdogConfig::default()),
+            watchdog: None,
         })
     }
     
+    /// Set how recoverable errors are retried
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.progress.lock().unwrap().set_retry_policy(policy);
+    }
+    
+    /// Set the free space check run before exporting, `None` disables it
+    pub fn set_space_check(&mut self, check: Option<SpaceCheck>) {
+        self.space_check = check;
+    }
+    
+    /// Profile element processing time during the export
+    pub fn set_profiler(&mut self, profiler: Option<RenderProfiler>) {
+        self.profiler = profiler;
+    }
+    
+    /// Set the stall watchdog, `None` disables it
+    pub fn set_watchdog(&mut self, config: Option<WatchdogConfig>) {
+        self.watchdog_config = config;
+    }
+    
     pub fn set_progress_callback<F>(&mut self, callback: F)
     where
         F: Fn(ExportProgress) + Send + Sync + 'static,
@@ -133,6 +264,9 @@ impl GstExporter {
     }
     
     pub fn start_export(&mut self) -> Result<(), EditingError> {
+        let span = tracing::info_span!("encode", output = %self.options.output_path.display());
+        let _span = span.enter();
+        
         *self.cancel_flag.lock().unwrap() = false;
         
         let pipeline = ges::Pipeline::new()
@@ -141,23 +275,27 @@ impl GstExporter {
         pipeline.set_timeline(&self.options.timeline)
             .context("Failed to set timeline on pipeline")?;
         
-        let duration = self.options.timeline.duration();
-        let total_frames = (duration as f64 / gst::ClockTime::SECOND.nseconds() as f64 * self.options.frame_rate) as u64;
+        let duration = self.options.timeline.duration().seconds_f64();
+        
+        if let Some(check) = &self.space_check {
+            let estimated = disk_space::estimate_output_size(&self.options, duration);
+            check.check(&self.options.output_path, estimated)
+                .map_err(|e| EditingError::ExportError(e.to_string()))?;
+        }
         
         {
-            let mut progress = self.progress.lock().unwrap();
-            progress.total_frames = total_frames;
-            progress.total_duration = duration as f64 / gst::ClockTime::SECOND.nseconds() as f64;
+            let mut tracker = self.progress.lock().unwrap();
+            tracker.start(duration);
             
             if let Some(callback) = &self.progress_callback {
-                callback(progress.clone());
+                callback(tracker.progress().clone());
             }
         }
         
         let profile = self.create_encoding_profile()
             .context("Failed to create encoding profile")?;
         
-        let output_uri = gst::filename_to_uri(self.options.output_path.as_path())
+        let output_uri = path_policy::path_to_uri(&self.options.output_path)
             .context("Failed to convert output path to URI")?;
         
         pipeline.set_render_settings(&output_uri, &profile)
@@ -166,53 +304,109 @@ impl GstExporter {
         pipeline.set_mode(ges::PipelineFlags::RENDER)
             .context("Failed to set pipeline mode to render")?;
         
+        Self::configure_audio_elements(pipeline.upcast_ref::<gst::Bin>(), self.options.audio_quality);
+        Self::configure_muxer_elements(pipeline.upcast_ref::<gst::Bin>(), self.options.container_options);
+        
+        if self.options.deterministic {
+            Self::configure_deterministic_elements(pipeline.upcast_ref::<gst::Bin>());
+        }
+        
+        if let Some(profiler) = &self.profiler {
+            profiler.attach(pipeline.upcast_ref::<gst::Bin>())?;
+        }
+        
         let bus = pipeline.bus().expect("Pipeline without bus");
         
         let main_loop = MainLoop::new(None, false);
         let main_loop_clone = main_loop.clone();
         
+        let watchdog = self.watchdog_config.clone().map(|config| {
+            let progress = self.progress.clone();
+            let callback = self.progress_callback.clone();
+            let main_loop = main_loop.clone();
+            
+            Watchdog::watch(pipeline.upcast_ref(), config, move |timeout| {
+                let mut tracker = progress.lock().unwrap();
+                tracker.stalled(&timeout.to_string(), &timeout.pipeline);
+                
+                if let Some(callback) = &callback {
+                    callback(tracker.progress().clone());
+                }
+                
+                main_loop.quit();
+            })
+        });
+        let kicker = watchdog.as_ref().map(Watchdog::kicker);
+        
         let progress_clone = self.progress.clone();
         let callback_clone = self.progress_callback.clone();
         let cancel_flag = self.cancel_flag.clone();
+        let pipeline_weak = pipeline.downgrade();
+        let bus_span = span.clone();
         
         let bus_watch_id = bus.add_watch(move |_, msg| {
+            let _span = bus_span.enter();
+            if let Some(kicker) = &kicker {
+                kicker.kick();
+            }
+            
             match msg.view() {
                 gst::MessageView::Eos(..) => {
-                    let mut progress = progress_clone.lock().unwrap();
-                    progress.complete = true;
-                    progress.percent = 100.0;
-                    progress.current_frame = progress.total_frames;
-                    progress.current_time = progress.total_duration;
+                    let mut tracker = progress_clone.lock().unwrap();
+                    tracker.finish();
                     
                     if let Some(callback) = &callback_clone {
-                        callback(progress.clone());
+                        callback(tracker.progress().clone());
                     }
                     
                     main_loop_clone.quit();
                 },
                 gst::MessageView::Error(err) => {
-                    let error_msg = format!("Export error: {} ({})", err.error(), err.debug().unwrap_or_default());
-                    let mut progress = progress_clone.lock().unwrap();
-                    progress.error = Some(error_msg);
-                    progress.complete = true;
+                    let class = if *cancel_flag.lock().unwrap() {
+                        ErrorClass::Cancelled
+                    } else {
+                        ErrorClass::from_gst_error(&err.error())
+                    };
+                    let element = err.src().map(|src| src.path_string().to_string());
+                    // Position the pipeline had reached when it failed
+                    let position = pipeline_weak.upgrade()
+                        .and_then(|pipeline| pipeline.query_position::<gst::ClockTime>())
+                        .map(|position| position.seconds_f64());
+                    
+                    let mut tracker = progress_clone.lock().unwrap();
+                    let debug = err.debug().map(|debug| debug.to_string());
+                    let outcome = tracker.error(class, &err.error().to_string(), debug, element, position);
                     
                     if let Some(callback) = &callback_clone {
-                        callback(progress.clone());
+                        callback(tracker.progress().clone());
                     }
                     
-                    main_loop_clone.quit();
+                    match outcome {
+                        ErrorOutcome::Retry(delay) => {
+                            if let Some(pipeline) = pipeline_weak.upgrade() {
+                                let _ = pipeline.set_state(gst::State::Null);
+                            }
+                            let pipeline_weak = pipeline_weak.clone();
+                            glib::timeout_add_once(delay, move || {
+                                if let Some(pipeline) = pipeline_weak.upgrade() {
+                                    let _ = pipeline.set_state(gst::State::Playing);
+                                }
+                            });
+                            
+                            return glib::Continue(true);
+                        },
+                        ErrorOutcome::Failed => main_loop_clone.quit(),
+                    }
                 },
                 gst::MessageView::Application(app) => {
                     let structure = app.structure();
                     if let Some(structure) = structure {
                         if structure.name() == "export-cancelled" {
-                            let error_msg = "Export cancelled";
-                            let mut progress = progress_clone.lock().unwrap();
-                            progress.error = Some(error_msg.to_string());
-                            progress.complete = true;
+                            let mut tracker = progress_clone.lock().unwrap();
+                            tracker.cancelled();
                             
                             if let Some(callback) = &callback_clone {
-                                callback(progress.clone());
+                                callback(tracker.progress().clone());
                             }
                             
                             // Quit the main loop
@@ -240,20 +434,10 @@ impl GstExporter {
         let timeout_id = glib::timeout_add_seconds(1, move || {
             if let Some(pipeline) = pipeline_weak.upgrade() {
                 if let Ok(position) = pipeline.query_position::<gst::ClockTime>() {
-                    let position_seconds = position.nseconds() as f64 / gst::ClockTime::SECOND.nseconds() as f64;
-                    let duration_seconds = progress_clone.lock().unwrap().total_duration;
-                    
-                    if duration_seconds > 0.0 {
-                        let percent = (position_seconds / duration_seconds) * 100.0;
-                        let current_frame = (position_seconds * progress_clone.lock().unwrap().total_frames as f64 / duration_seconds) as u64;
-                        
-                        let mut progress = progress_clone.lock().unwrap();
-                        progress.current_time = position_seconds;
-                        progress.current_frame = current_frame;
-                        progress.percent = percent;
-                        
+                    let mut tracker = progress_clone.lock().unwrap();
+                    if tracker.position(position.seconds_f64()) {
                         if let Some(callback) = &callback_clone {
-                            callback(progress.clone());
+                            callback(tracker.progress().clone());
                         }
                     }
                 }
@@ -268,6 +452,7 @@ impl GstExporter {
         self.main_loop = Some(main_loop);
         self.bus_watch_id = Some(bus_watch_id);
         self.timeout_id = Some(timeout_id);
+        self.watchdog = watchdog;
         
         self.pipeline.as_ref().unwrap().set_state(gst::State::Playing)
             .context("Failed to start pipeline")?;
@@ -280,9 +465,75 @@ impl GstExporter {
         Ok(())
     }
     
+    /// Apply resampler quality and dithering to the audio converters GES and encodebin create
+    fn configure_audio_elements(pipeline: &gst::Bin, quality: AudioQualityOptions) {
+        let configure = move |element: &gst::Element| {
+            let Some(factory) = element.factory() else {
+                return;
+            };
+            
+            match factory.name().as_str() {
+                "audioresample" => element.set_property("quality", quality.resample_quality.to_gst_quality()),
+                // Only takes effect where the depth is reduced, i.e. 16-bit outputs
+                "audioconvert" => {
+                    element.set_property_from_str("dithering", quality.dither.to_gst_name());
+                    element.set_property_from_str("noise-shaping", quality.noise_shaping.to_gst_name());
+                },
+                _ => (),
+            }
+        };
+        
+        for element in pipeline.iterate_recurse().into_iter().flatten() {
+            configure(&element);
+        }
+        pipeline.connect_deep_element_added(move |_, _, element| configure(element));
+    }
+    
+    /// Apply the container options to the muxer encodebin creates
+    fn configure_muxer_elements(pipeline: &gst::Bin, options: ContainerOptions) {
+        let configure = move |element: &gst::Element| {
+            let Some(factory) = element.factory() else {
+                return;
+            };
+            
+            for (property, value) in options.gst_muxer_properties(factory.name().as_str()) {
+                if element.find_property(property).is_some() {
+                    element.set_property_from_str(property, &value);
+                }
+            }
+        };
+        
+        for element in pipeline.iterate_recurse().into_iter().flatten() {
+            configure(&element);
+        }
+        pipeline.connect_deep_element_added(move |_, _, element| configure(element));
+    }
+    
+    /// Pin encoder threads and muxer timestamps on the elements encodebin creates
+    fn configure_deterministic_elements(pipeline: &gst::Bin) {
+        let configure = |element: &gst::Element| {
+            let Some(factory) = element.factory() else {
+                return;
+            };
+            
+            for (property, value) in determinism::gst_element_properties(factory.name().as_str()) {
+                if element.find_property(property).is_some() {
+                    element.set_property_from_str(property, value);
+                }
+            }
+        };
+        
+        for element in pipeline.iterate_recurse().into_iter().flatten() {
+            configure(&element);
+        }
+        pipeline.connect_deep_element_added(move |_, _, element| configure(element));
+    }
+    
     fn create_encoding_profile(&self) -> Result<gst_pbutils::EncodingProfile, EditingError> {
-        let container_caps = gst::Caps::builder(self.options.container_format.to_mime_type())
-            .build();
+        let plan = self.options.encoding_plan();
+        
+        let container_caps = plan.container_caps.parse::<gst::Caps>()
+            .context("Invalid container caps")?;
         
         let container_profile = gst_pbutils::EncodingContainerProfile::new(
             Some("container"),
@@ -291,28 +542,8 @@ impl GstExporter {
             None,
         ).context("Failed to create container profile")?;
         
-        let video_caps = if self.options.hardware_acceleration {
-            match self.options.video_format {
-                VideoFormat::H264 => {
-                    gst::Caps::builder("video/x-h264")
-                        .field("profile", "high")
-                        .build()
-                },
-                VideoFormat::H265 => {
-                    gst::Caps::builder("video/x-h265")
-                        .field("profile", "main")
-                        .build()
-                },
-                _ => {
-                    gst::Caps::builder("video/x-h264")
-                        .field("profile", "high")
-                        .build()
-                }
-            }
-        } else {
-            gst::Caps::builder(self.options.video_format.to_mime_type())
-                .build()
-        };
+        let video_caps = plan.video_caps.parse::<gst::Caps>()
+            .context("Invalid video caps")?;
         
         let video_profile = gst_pbutils::EncodingVideoProfile::new(
             &video_caps,
@@ -321,23 +552,28 @@ impl GstExporter {
             1, // Presence
         ).context("Failed to create video profile")?;
         
-        if self.options.video_bitrate > 0 {
-            video_profile.set_bitrate(self.options.video_bitrate as u32);
+        if let Some(bitrate) = plan.video_bitrate {
+            video_profile.set_bitrate(bitrate);
         }
         
-        if self.options.width > 0 && self.options.height > 0 {
-            let restriction = gst::Caps::builder("video/x-raw")
-                .field("width", self.options.width as i32)
-                .field("height", self.options.height as i32)
-                .build();
-            video_profile.set_restriction(Some(&restriction));
+        if plan.size.is_some() || plan.frame_rate.is_some() {
+            let mut restriction = gst::Caps::builder("video/x-raw");
+            if let Some((width, height)) = plan.size {
+                restriction = restriction
+                    .field("width", width as i32)
+                    .field("height", height as i32);
+            }
+            if let Some(rate) = plan.frame_rate {
+                restriction = restriction.field("framerate", gst::Fraction::new(rate.numerator as i32, rate.denominator as i32));
+            }
+            video_profile.set_restriction(Some(&restriction.build()));
         }
         
         container_profile.add_profile(&video_profile.upcast())
             .context("Failed to add video profile to container")?;
         
-        let audio_caps = gst::Caps::builder(self.options.audio_format.to_mime_type())
-            .build();
+        let audio_caps = plan.audio_caps.parse::<gst::Caps>()
+            .context("Invalid audio caps")?;
         
         let audio_profile = gst_pbutils::EncodingAudioProfile::new(
             &audio_caps,
@@ -346,8 +582,8 @@ impl GstExporter {
             1, // Presence
         ).context("Failed to create audio profile")?;
         
-        if self.options.audio_bitrate > 0 {
-            audio_profile.set_bitrate(self.options.audio_bitrate as u32);
+        if let Some(bitrate) = plan.audio_bitrate {
+            audio_profile.set_bitrate(bitrate);
         }
         
         container_profile.add_profile(&audio_profile.upcast())
@@ -374,24 +610,31 @@ impl GstExporter {
     }
     
     pub fn get_progress(&self) -> ExportProgress {
-        self.progress.lock().unwrap().clone()
+        self.progress.lock().unwrap().progress().clone()
     }
     
     pub fn is_complete(&self) -> bool {
-        self.progress.lock().unwrap().complete
+        self.progress.lock().unwrap().progress().complete
     }
     
     pub fn has_error(&self) -> bool {
-        self.progress.lock().unwrap().error.is_some()
+        self.progress.lock().unwrap().progress().error.is_some()
     }
     
     pub fn get_error(&self) -> Option<String> {
-        self.progress.lock().unwrap().error.clone()
+        self.progress.lock().unwrap().progress().error.clone()
+    }
+    
+    /// Structured failure report, if the export failed
+    pub fn get_failure(&self) -> Option<ExportFailure> {
+        self.progress.lock().unwrap().progress().failure.clone()
     }
 }
 
 impl Drop for GstExporter {
     fn drop(&mut self) {
+        self.watchdog = None;
+        
         if let Some(watch_id) = self.bus_watch_id.take() {
             watch_id.remove();
         }
@@ -401,7 +644,7 @@ impl Drop for GstExporter {
         }
         
         if let Some(pipeline) = &self.pipeline {
-            let _ = pipeline.set_state(gst::State::Null);
+            pipeline_watchdog::shutdown_pipeline(pipeline.upcast_ref(), DEFAULT_SHUTDOWN_TIMEOUT);
         }
         
         if let Some(main_loop) = &self.main_loop {
@@ -421,9 +664,15 @@ impl ContainerFormatExt for ContainerFormat {
         match self {
             ContainerFormat::Mp4 => "video/quicktime, variant=iso",
             ContainerFormat::Mkv => "video/x-matroska",
-            ContainerFormat::WebM => "video/webm",
+            ContainerFormat::Webm => "video/webm",
             ContainerFormat::Mov => "video/quicktime",
             ContainerFormat::Avi => "video/x-msvideo",
+            ContainerFormat::Flv => "video/x-flv",
+            ContainerFormat::Wmv => "video/x-ms-asf",
+            ContainerFormat::Mpg => "video/mpeg, mpegversion=2, systemstream=true",
+            ContainerFormat::Ts => "video/mpegts, systemstream=true",
+            ContainerFormat::Mxf => "application/mxf",
+            ContainerFormat::Gif => "image/gif",
         }
     }
 }
@@ -442,6 +691,11 @@ impl VideoFormatExt for VideoFormat {
             VideoFormat::Av1 => "video/x-av1",
             VideoFormat::ProRes => "video/x-prores",
             VideoFormat::Dnxhd => "video/x-dnxhd",
+            VideoFormat::Mjpeg => "image/jpeg",
+            VideoFormat::Mpeg2 => "video/mpeg, mpegversion=2",
+            VideoFormat::Mpeg4 => "video/mpeg, mpegversion=4",
+            VideoFormat::Theora => "video/x-theora",
+            VideoFormat::Raw => "video/x-raw",
         }
     }
 }
@@ -459,6 +713,9 @@ impl AudioFormatExt for AudioFormat {
             AudioFormat::Vorbis => "audio/x-vorbis",
             AudioFormat::Opus => "audio/x-opus",
             AudioFormat::Pcm => "audio/x-raw",
+            AudioFormat::Ac3 => "audio/x-ac3",
+            AudioFormat::Eac3 => "audio/x-eac3",
+            AudioFormat::Wma => "audio/x-wma, wmaversion=2",
         }
     }
 }
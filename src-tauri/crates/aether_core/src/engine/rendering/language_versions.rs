@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::editing::types::EditingError;
+use crate::engine::rendering::export::ExportOptions;
+use crate::engine::rendering::output_naming::NamingContext;
+use crate::engine::rendering::track_selection::TrackSelection;
+use crate::engine::timeline::Timeline;
+
+/// One rendering of a timeline's text
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Version {
+    /// No title or subtitle tracks, the master other languages are cut from
+    Textless,
+    /// The title and subtitle tracks of a language, by its code such as `fr` or `pt-BR`
+    Language(String),
+}
+
+impl Version {
+    /// Name used in output file names, `textless` or the language code
+    pub fn label(&self) -> &str {
+        match self {
+            Version::Textless => "textless",
+            Version::Language(language) => language,
+        }
+    }
+}
+
+/// Title and subtitle tracks of one language
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageVariant {
+    pub language: String,
+    pub track_ids: Vec<String>,
+}
+
+/// A version to render: the copy of the timeline to render as its input, and its options
+/// and naming context
+pub struct VersionRender {
+    pub version: Version,
+    pub timeline: Timeline,
+    pub options: ExportOptions,
+    pub context: NamingContext,
+}
+
+/// Language variants of a timeline's text tracks
+///
+/// Tracks in no variant, such as picture and sound, are in every version. A language's
+/// version leaves out the text tracks of the other languages, and the textless version
+/// leaves out all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageVersions {
+    pub variants: Vec<LanguageVariant>,
+}
+
+impl LanguageVersions {
+    /// Group text tracks as the variant of `language`, replacing any it had
+    pub fn set_variant(&mut self, language: &str, track_ids: &[&str]) -> Result<(), EditingError> {
+        let language = language.trim();
+        if language.is_empty() {
+            return Err(EditingError::InvalidParameter("Language variant needs a language".to_string()));
+        }
+        if track_ids.is_empty() {
+            return Err(EditingError::InvalidParameter(format!("Language variant {} has no tracks", language)));
+        }
+        if let Some(other) = self.variants.iter()
+            .filter(|variant| !variant.language.eq_ignore_ascii_case(language))
+            .find(|variant| variant.track_ids.iter().any(|id| track_ids.contains(&id.as_str())))
+        {
+            return Err(EditingError::InvalidParameter(format!("Track already belongs to language {}", other.language)));
+        }
+
+        let variant = LanguageVariant {
+            language: language.to_string(),
+            track_ids: track_ids.iter().map(|id| id.to_string()).collect(),
+        };
+        match self.variants.iter_mut().find(|existing| existing.language.eq_ignore_ascii_case(language)) {
+            Some(existing) => *existing = variant,
+            None => self.variants.push(variant),
+        }
+        Ok(())
+    }
+
+    pub fn remove_variant(&mut self, language: &str) -> Option<LanguageVariant> {
+        let index = self.variants.iter().position(|variant| variant.language.eq_ignore_ascii_case(language))?;
+        Some(self.variants.remove(index))
+    }
+
+    /// Every version: the textless master, then each language
+    pub fn versions(&self) -> Vec<Version> {
+        std::iter::once(Version::Textless)
+            .chain(self.variants.iter().map(|variant| Version::Language(variant.language.clone())))
+            .collect()
+    }
+
+    /// Tracks to render for `version`
+    pub fn selection(&self, version: &Version) -> Result<TrackSelection, EditingError> {
+        let keep = match version {
+            Version::Textless => None,
+            Version::Language(language) => Some(
+                self.variants.iter()
+                    .find(|variant| variant.language.eq_ignore_ascii_case(language))
+                    .ok_or_else(|| EditingError::InvalidParameter(format!("No language variant: {}", language)))?,
+            ),
+        };
+        Ok(TrackSelection {
+            exclude: self.variants.iter()
+                .filter(|variant| keep.is_none_or(|keep| keep.language != variant.language))
+                .flat_map(|variant| variant.track_ids.iter().cloned())
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// Everything needed to render `versions` of `timeline` in one batch
+    ///
+    /// Each version gets its copy of the timeline, `base` options with the text tracks of
+    /// other versions left out, and `context` with the version for a `{version}`
+    /// placeholder. Once the copies are rendered to each version's input, the batch goes
+    /// to `RenderQueue::enqueue_batch`.
+    pub fn prepare_batch(
+        &self,
+        timeline: &Timeline,
+        versions: &[Version],
+        base: &ExportOptions,
+        context: &NamingContext,
+    ) -> Result<Vec<VersionRender>, EditingError> {
+        versions.iter()
+            .map(|version| {
+                // Keep what the base options leave out, like a scratch track
+                let mut tracks = base.tracks.clone();
+                tracks.exclude.extend(self.selection(version)?.exclude);
+                let (options, timeline) = base.clone().with_tracks(timeline, tracks)?;
+                Ok(VersionRender {
+                    version: version.clone(),
+                    timeline,
+                    options,
+                    context: context.clone().with_version(version.label()),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Serialize, Deserialize};
+
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::generators::{Slate, COUNTDOWN_DURATION, COUNTDOWN_POP};
+
+/// Length of the pop at the 2 of a countdown, a film frame
+const POP_DURATION: f64 = 1.0 / 24.0;
+
+/// A line of the slate, shown as `label: value`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlateField {
+    pub label: String,
+    pub value: String,
+}
+
+/// Bars and tone followed by a slate and a countdown, rendered ahead of the program
+///
+/// No source media is needed: FFmpeg's filters generate SMPTE color bars with a sine
+/// tone, then a black card with the slate fields over silence. The countdown, if any,
+/// is drawn by `Generator::Countdown` and is silent but for a pop at the 2.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Leader {
+    /// Length of bars and tone, in seconds
+    pub bars_duration: f64,
+
+    /// Tone frequency, in Hz
+    pub tone_frequency: f64,
+
+    /// Tone level, in dBFS
+    pub tone_level: f64,
+
+    /// Length of the slate, in seconds
+    pub slate_duration: f64,
+
+    /// Project details shown on the slate, top to bottom
+    pub slate: Vec<SlateField>,
+
+    /// Length of the countdown after the slate, in seconds; none when 0
+    pub countdown_duration: f64,
+}
+
+impl Default for Leader {
+    fn default() -> Self {
+        Self {
+            bars_duration: 10.0,
+            tone_frequency: 1000.0,
+            // Standard alignment level
+            tone_level: -20.0,
+            slate_duration: 5.0,
+            slate: Vec::new(),
+            countdown_duration: 0.0,
+        }
+    }
+}
+
+impl Leader {
+    /// Add a line to the slate
+    pub fn with_field(mut self, label: &str, value: &str) -> Self {
+        self.slate.push(SlateField { label: label.to_string(), value: value.to_string() });
+        self
+    }
+
+    /// Add the lines of a slate generator's slate
+    pub fn with_slate(mut self, slate: &Slate) -> Self {
+        self.slate.extend(slate.fields());
+        self
+    }
+
+    /// Count down from 8 after the slate
+    pub fn with_countdown(mut self) -> Self {
+        self.countdown_duration = COUNTDOWN_DURATION;
+        self
+    }
+
+    /// Total length, in seconds
+    pub fn duration(&self) -> f64 {
+        self.bars_duration.max(0.0) + self.slate_duration.max(0.0) + self.countdown_duration.max(0.0)
+    }
+
+    /// Number of countdown frames at `frame_rate`
+    pub fn countdown_frames(&self, frame_rate: f64) -> u64 {
+        (self.countdown_duration.max(0.0) * frame_rate).round() as u64
+    }
+
+    /// Number of video frames at `frame_rate`
+    pub fn frames(&self, frame_rate: f64) -> u64 {
+        (self.duration() * frame_rate).round() as u64
+    }
+
+    /// Filter graph producing the bars and slate of the leader's video as YUV 4:2:0
+    /// frames; the countdown is drawn separately
+    pub fn video_graph(&self, width: u32, height: u32, frame_rate: f64) -> String {
+        let size = format!("{}x{}", width, height);
+        let frame_rate = FrameRate::from_fps(frame_rate).to_ffmpeg_rate();
+        let mut slate = format!(
+            "color=c=black:size={}:rate={}:duration={}",
+            size, frame_rate, self.slate_duration.max(0.0)
+        );
+        // Lines are centered, h/18 high with half a line between them
+        let lines = self.slate.len() as i64;
+        for (index, field) in self.slate.iter().enumerate() {
+            let text = format!("{}: {}", field.label, field.value);
+            slate.push_str(&format!(
+                ",drawtext=text={}:expansion=none:fontcolor=white:fontsize=h/18:x=(w-text_w)/2:y=h*{}/24",
+                escape_filter_value(&text),
+                12 - lines + 2 * index as i64
+            ));
+        }
+        format!(
+            "smptebars=size={}:rate={}:duration={}[bars];{}[slate];[bars][slate]concat=n=2:v=1:a=0,format=yuv420p",
+            size, frame_rate, self.bars_duration.max(0.0), slate
+        )
+    }
+
+    /// Filter graph producing the leader's audio in the encoder's sample format and layout
+    ///
+    /// `channel_layout` is a layout name or channel mask as FFmpeg writes them, like `stereo`.
+    pub fn audio_graph(&self, sample_rate: u32, sample_format: &str, channel_layout: &str) -> String {
+        let mut graph = format!(
+            "sine=frequency={}:sample_rate={}:duration={},volume={}dB[tone];\
+             anullsrc=r={}:cl={},atrim=duration={}[silence];",
+            self.tone_frequency, sample_rate, self.bars_duration.max(0.0), self.tone_level,
+            sample_rate, channel_layout, self.slate_duration.max(0.0)
+        );
+        let mut segments = "[tone][silence]".to_string();
+        if self.countdown_duration > 0.0 {
+            // Silence up to the 2, the pop, and silence to the program
+            let pop_at = (self.countdown_duration - COUNTDOWN_POP).max(0.0);
+            let pop = POP_DURATION.min(self.countdown_duration - pop_at);
+            graph.push_str(&format!(
+                "anullsrc=r={}:cl={},atrim=duration={}[count];\
+                 sine=frequency={}:sample_rate={}:duration={},volume={}dB[pop];\
+                 anullsrc=r={}:cl={},atrim=duration={}[black];",
+                sample_rate, channel_layout, pop_at,
+                self.tone_frequency, sample_rate, pop, self.tone_level,
+                sample_rate, channel_layout, self.countdown_duration - pop_at - pop
+            ));
+            segments.push_str("[count][pop][black]");
+        }
+        format!(
+            "{}{}concat=n={}:v=0:a=1,aformat=sample_fmts={}:sample_rates={}:channel_layouts={}",
+            graph, segments, segments.matches('[').count(),
+            sample_format, sample_rate, channel_layout
+        )
+    }
+}
+
+/// Escape text for a filter option inside a filter graph description
+///
+/// Option values treat `\`, `'` and `:` as special, and the graph parser then `\`, `'`,
+/// `[`, `]`, `,` and `;`, so text is escaped for both levels in turn.
+pub fn escape_filter_value(text: &str) -> String {
+    let escape = |text: &str, special: &[char]| {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if special.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+    let option = escape(text, &['\\', '\'', ':']);
+    escape(&option, &['\\', '\'', '[', ']', ',', ';'])
+}
@@ -1,19 +1,53 @@
+mod audio_quality;
+mod completion_hooks;
+mod container_options;
+mod deliverables;
+mod determinism;
 mod export;
+mod export_progress;
+mod export_region;
 mod formats;
 mod encoder;
+mod encoder_profile;
 mod gst_exporter;
+mod language_versions;
+mod leader;
+mod output_naming;
+mod recovery;
+mod render_queue;
+mod throttle;
+mod track_selection;
 
+pub use audio_quality::{AudioQualityOptions, Dither, NoiseShaping, ResampleQuality};
+pub use completion_hooks::{CompletionHook, HookAction, HookTrigger, JobEvent, NotificationCallback, NotificationPayload};
+pub(crate) use completion_hooks::{cmd_quote, sh_quote};
+pub use container_options::{ContainerOptions, Mp4Layout};
+pub use deliverables::{Deliverable, DeliverableComparison, DeliverableGallery, file_checksum};
+pub use determinism::{DETERMINISTIC_THREADS, FIXED_CREATION_TIME};
 pub use export::{Exporter, ExportOptions, ExportProgress, ExportCallback};
+pub use export_progress::{ErrorOutcome, ProgressTracker};
+pub use export_region::{Chapter, ExportRegion, chapters_for_range};
 pub use formats::{VideoFormat, AudioFormat, ContainerFormat, get_available_formats};
-pub use encoder::{EncoderPreset, EncoderOptions};
-pub use gst_exporter::{GstExporter, ExportProgress as GstExportProgress, ExportOptions as GstExportOptions, ExportCallback as GstExportCallback};
+pub use encoder::{EncoderPreset, EncoderOptions, HardwareEncoder, RateControl};
+pub use encoder_profile::{CodecProfile, EncoderProfile, Tune};
+pub use gst_exporter::{GstExporter, EncodingPlan, ExportProgress as GstExportProgress, ExportOptions as GstExportOptions, ExportCallback as GstExportCallback};
+pub use language_versions::{LanguageVariant, LanguageVersions, Version, VersionRender};
+pub use leader::{Leader, SlateField, escape_filter_value};
+pub use output_naming::{CollisionPolicy, FilenameTemplate, NamingContext, OutputNaming};
+pub use recovery::{ErrorClass, RetryPolicy, FailedAttempt, ExportFailure};
+pub use render_queue::{RenderQueue, RenderQueueConfig, RenderJobId, RenderJobInfo, JobPriority, JobStatus, ThrottleSettings};
+pub use throttle::IoThrottle;
+pub use track_selection::TrackSelection;
 
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use crate::engine::editing::types::EditingError;
+use crate::modules::backend_policy::{self, Backend, BackendPolicy, BackendPreference, Subsystem};
+use crate::modules::settings::EngineSettings;
 
 /// Enum to represent the different types of exporters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExporterType {
     /// FFmpeg-based exporter
     FFmpeg,
@@ -21,6 +55,15 @@ pub enum ExporterType {
     GStreamer,
 }
 
+impl From<ExporterType> for Backend {
+    fn from(exporter_type: ExporterType) -> Self {
+        match exporter_type {
+            ExporterType::FFmpeg => Backend::FFmpeg,
+            ExporterType::GStreamer => Backend::GStreamer,
+        }
+    }
+}
+
 /// Enum to hold either type of exporter
 pub enum ActiveExporter {
     /// FFmpeg-based exporter
@@ -32,8 +75,8 @@ pub enum ActiveExporter {
 pub struct RenderingEngine {
     initialized: bool,
     current_export: Option<ActiveExporter>,
-    /// Default exporter type to use
-    default_exporter_type: ExporterType,
+    /// Which exporter to use and whether to fall back to the other one
+    backend_policy: BackendPolicy,
 }
 
 impl RenderingEngine {
@@ -41,13 +84,27 @@ impl RenderingEngine {
         Ok(Self {
             initialized: true,
             current_export: None,
-            default_exporter_type: ExporterType::FFmpeg, // Default to FFmpeg for backward compatibility
+            backend_policy: backend_policy::global_policy(),
         })
     }
     
-    /// Set the default exporter type
+    /// Set the default exporter type, keeping the policy's fallback setting
     pub fn set_default_exporter_type(&mut self, exporter_type: ExporterType) {
-        self.default_exporter_type = exporter_type;
+        let allow_fallback = self.backend_policy.preference(Subsystem::Export).allow_fallback;
+        self.backend_policy.set_preference(Subsystem::Export, Some(BackendPreference {
+            backend: exporter_type.into(),
+            allow_fallback,
+        }));
+    }
+    
+    /// Set which exporter is used and whether it falls back to the other one
+    pub fn set_backend_policy(&mut self, policy: BackendPolicy) {
+        self.backend_policy = policy;
+    }
+    
+    /// Take the backend choice from engine settings, for settings changed while running
+    pub fn apply_settings(&mut self, settings: &EngineSettings) {
+        self.backend_policy = settings.backends.clone();
     }
     
     /// Create an FFmpeg-based exporter
@@ -66,14 +123,22 @@ impl RenderingEngine {
         Ok(exporter)
     }
     
-    /// Create an exporter using the default exporter type
+    /// Create an exporter using the backend policy, falling back if the preferred one can't be created
     pub fn create_export(&mut self, options: ExportOptions) -> Result<ActiveExporter, EditingError> {
-        match self.default_exporter_type {
-            ExporterType::FFmpeg => {
+        let policy = self.backend_policy.clone();
+        policy.run(Subsystem::Export, |backend| {
+            self.create_export_with(backend, options.clone()).map_err(anyhow::Error::from)
+        })
+        .map_err(|e| EditingError::ExportError(e.to_string()))
+    }
+    
+    fn create_export_with(&mut self, backend: Backend, options: ExportOptions) -> Result<ActiveExporter, EditingError> {
+        match backend {
+            Backend::FFmpeg => {
                 let exporter = self.create_ffmpeg_export(options)?;
                 Ok(ActiveExporter::FFmpeg(exporter))
             },
-            ExporterType::GStreamer => {
+            Backend::GStreamer => {
                 // Convert FFmpeg options to GStreamer options
                 // This is a simplified conversion and might need more fields
                 let gst_options = GstExportOptions {
@@ -91,6 +156,10 @@ impl RenderingEngine {
                     crf: options.crf,
                     hardware_acceleration: options.hardware_acceleration,
                     threads: options.threads,
+                    audio_quality: options.audio_quality,
+                    deterministic: options.deterministic,
+                    container_options: options.container_options,
+                    pulldown: options.pulldown,
                 };
                 
                 let exporter = self.create_gstreamer_export(gst_options)?;
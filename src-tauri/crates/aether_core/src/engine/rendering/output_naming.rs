@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDate;
+use crate::engine::editing::types::EditingError;
+
+/// Placeholders a filename template may use
+const PLACEHOLDERS: [&str; 6] = ["project", "sequence", "date", "preset", "range", "version"];
+
+/// Highest suffix tried by `CollisionPolicy::AutoIncrement`
+const MAX_INCREMENT: u32 = 9999;
+
+/// What to do when an output file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Replace the existing file
+    Overwrite,
+    /// Append `_2`, `_3`, ... until the name is free
+    AutoIncrement,
+    /// Refuse to export
+    Fail,
+}
+
+/// Values substituted into a filename template
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamingContext {
+    pub project: String,
+    pub sequence: String,
+    pub date: NaiveDate,
+    pub preset: String,
+    /// Exported range in seconds, `None` for the whole sequence
+    pub range: Option<(f64, f64)>,
+    /// Language or textless version of the sequence, `None` for its only version
+    pub version: Option<String>,
+}
+
+impl NamingContext {
+    /// Context dated today
+    pub fn new(project: &str, sequence: &str, preset: &str) -> Self {
+        Self {
+            project: project.to_string(),
+            sequence: sequence.to_string(),
+            date: chrono::Local::now().date_naive(),
+            preset: preset.to_string(),
+            range: None,
+            version: None,
+        }
+    }
+
+    pub fn with_range(mut self, start: f64, end: f64) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    fn value(&self, placeholder: &str) -> String {
+        match placeholder {
+            "project" => self.project.clone(),
+            "sequence" => self.sequence.clone(),
+            "date" => self.date.format("%Y-%m-%d").to_string(),
+            "preset" => self.preset.clone(),
+            "range" => match self.range {
+                Some((start, end)) => format!("{}-{}", format_time(start), format_time(end)),
+                None => "full".to_string(),
+            },
+            "version" => self.version.clone().unwrap_or_else(|| "main".to_string()),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Output filename pattern such as `{project}_{sequence}_{date}`
+///
+/// Placeholders are `{project}`, `{sequence}`, `{date}` (YYYY-MM-DD), `{preset}`,
+/// `{range}` (HHMMSS-HHMMSS, or `full`) and `{version}` (a language code, `textless`, or
+/// `main`). `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilenameTemplate {
+    template: String,
+}
+
+impl FilenameTemplate {
+    /// Parse a template, rejecting unknown placeholders and unbalanced braces
+    pub fn new(template: &str) -> Result<Self, EditingError> {
+        let template = Self { template: template.to_string() };
+        template.expand(|placeholder| {
+            if PLACEHOLDERS.contains(&placeholder) {
+                Ok(String::new())
+            } else {
+                Err(EditingError::InvalidParameter(format!("Unknown placeholder {{{}}} in filename template", placeholder)))
+            }
+        })?;
+        Ok(template)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// File name without extension, with substituted values made safe for file systems
+    pub fn render(&self, context: &NamingContext) -> String {
+        let name = self.expand(|placeholder| Ok(sanitize(&context.value(placeholder))))
+            .unwrap_or_default();
+        let name = name.trim().trim_matches('.').to_string();
+
+        if name.is_empty() { "export".to_string() } else { name }
+    }
+
+    fn expand(&self, mut value: impl FnMut(&str) -> Result<String, EditingError>) -> Result<String, EditingError> {
+        let mut output = String::new();
+        let mut chars = self.template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    output.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    output.push('}');
+                },
+                '{' => {
+                    let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if placeholder.contains('{') {
+                        return Err(EditingError::InvalidParameter(format!("Unclosed placeholder in filename template: {}", self.template)));
+                    }
+                    output.push_str(&value(placeholder.trim())?);
+                },
+                '}' => {
+                    return Err(EditingError::InvalidParameter(format!("Unmatched '}}' in filename template: {}", self.template)));
+                },
+                c => output.push(sanitize_char(c)),
+            }
+        }
+
+        // `take_while` swallows a missing closing brace silently
+        let opened = self.template.replace("{{", "").matches('{').count();
+        let closed = self.template.replace("}}", "").matches('}').count();
+        if opened != closed {
+            return Err(EditingError::InvalidParameter(format!("Unclosed placeholder in filename template: {}", self.template)));
+        }
+
+        Ok(output)
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self { template: "{project}_{sequence}_{date}".to_string() }
+    }
+}
+
+/// Where and how exports are named
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputNaming {
+    pub directory: PathBuf,
+    pub template: FilenameTemplate,
+    pub policy: CollisionPolicy,
+}
+
+impl OutputNaming {
+    pub fn new(directory: impl Into<PathBuf>, template: FilenameTemplate, policy: CollisionPolicy) -> Self {
+        Self {
+            directory: directory.into(),
+            template,
+            policy,
+        }
+    }
+
+    /// Output path for a context, applying the collision policy
+    ///
+    /// `reserved` holds paths claimed by other pending exports, which count as taken even
+    /// though they don't exist yet.
+    pub fn resolve(&self, context: &NamingContext, extension: &str, reserved: &HashSet<PathBuf>) -> Result<PathBuf, EditingError> {
+        let stem = self.template.render(context);
+        let path = self.directory.join(format!("{}.{}", stem, extension));
+        let taken = |path: &Path| path.exists() || reserved.contains(path);
+
+        match self.policy {
+            // Two pending exports writing the same file would corrupt each other
+            CollisionPolicy::Overwrite if reserved.contains(&path) => Err(EditingError::InvalidParameter(
+                format!("Another queued export already writes {}", path.display()),
+            )),
+            CollisionPolicy::Overwrite => Ok(path),
+            CollisionPolicy::Fail if taken(&path) => Err(EditingError::InvalidParameter(
+                format!("Output file already exists: {}", path.display()),
+            )),
+            CollisionPolicy::Fail => Ok(path),
+            CollisionPolicy::AutoIncrement => {
+                if !taken(&path) {
+                    return Ok(path);
+                }
+                (2..=MAX_INCREMENT)
+                    .map(|n| self.directory.join(format!("{}_{}.{}", stem, n, extension)))
+                    .find(|candidate| !taken(candidate))
+                    .ok_or_else(|| EditingError::InvalidParameter(format!("No free output name for {}", path.display())))
+            },
+        }
+    }
+}
+
+/// Seconds as HHMMSS
+fn format_time(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    format!("{:02}{:02}{:02}", total / 3600, total / 60 % 60, total % 60)
+}
+
+/// Replace characters that aren't allowed in file names on common file systems
+fn sanitize(value: &str) -> String {
+    value.chars().map(sanitize_char).collect()
+}
+
+fn sanitize_char(c: char) -> char {
+    match c {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+        c if c.is_control() => '_',
+        c => c,
+    }
+}
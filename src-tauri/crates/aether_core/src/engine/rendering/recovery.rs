@@ -0,0 +1,207 @@
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+
+/// Class of an export error, used to decide whether a retry can help
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorClass {
+    /// Temporary I/O or resource problem (busy device, interrupted write)
+    Transient,
+
+    /// Destination volume ran out of space
+    DiskFull,
+
+    /// Encoder rejected or failed on a frame
+    Encoder,
+
+    /// Input media missing or unreadable
+    InputUnavailable,
+
+    /// Missing plugin/codec or caps negotiation failure
+    Unsupported,
+
+    /// Export was cancelled by the user
+    Cancelled,
+
+    /// Pipeline stopped making progress and was shut down by the watchdog
+    Stalled,
+
+    /// Anything else
+    Fatal,
+}
+
+impl ErrorClass {
+    /// Classify an error from its message
+    ///
+    /// Used for FFmpeg errors and anything else that only reaches us as text.
+    pub fn from_message(message: &str) -> Self {
+        let message = message.to_lowercase();
+
+        if message.contains("cancelled") {
+            ErrorClass::Cancelled
+        } else if message.contains("no space left") || message.contains("disk full") || message.contains("enospc") {
+            ErrorClass::DiskFull
+        } else if message.contains("resource temporarily unavailable")
+            || message.contains("interrupted")
+            || message.contains("device or resource busy")
+            || message.contains("timed out")
+            || message.contains("try again")
+        {
+            ErrorClass::Transient
+        } else if message.contains("codec not found") || message.contains("not-negotiated") || message.contains("missing plugin") {
+            ErrorClass::Unsupported
+        } else if message.contains("encod") {
+            ErrorClass::Encoder
+        } else if message.contains("no such file") || message.contains("failed to open input") || message.contains("permission denied") {
+            ErrorClass::InputUnavailable
+        } else {
+            ErrorClass::Fatal
+        }
+    }
+
+    /// Classify a GStreamer error from its domain and code
+    pub fn from_gst_error(error: &gst::glib::Error) -> Self {
+        if let Some(kind) = error.kind::<gst::ResourceError>() {
+            return match kind {
+                gst::ResourceError::NoSpaceLeft => ErrorClass::DiskFull,
+                gst::ResourceError::Busy | gst::ResourceError::Write | gst::ResourceError::Sync => ErrorClass::Transient,
+                gst::ResourceError::NotFound
+                | gst::ResourceError::OpenRead
+                | gst::ResourceError::Read
+                | gst::ResourceError::NotAuthorized => ErrorClass::InputUnavailable,
+                _ => ErrorClass::from_message(error.message()),
+            };
+        }
+
+        if let Some(kind) = error.kind::<gst::StreamError>() {
+            return match kind {
+                gst::StreamError::Encode => ErrorClass::Encoder,
+                gst::StreamError::CodecNotFound | gst::StreamError::WrongType => ErrorClass::Unsupported,
+                gst::StreamError::Decode | gst::StreamError::Demux | gst::StreamError::Format => ErrorClass::InputUnavailable,
+                _ => ErrorClass::from_message(error.message()),
+            };
+        }
+
+        if let Some(kind) = error.kind::<gst::CoreError>() {
+            return match kind {
+                gst::CoreError::MissingPlugin | gst::CoreError::Negotiation => ErrorClass::Unsupported,
+                _ => ErrorClass::Fatal,
+            };
+        }
+
+        ErrorClass::from_message(error.message())
+    }
+}
+
+/// Retry and backoff settings for recoverable export errors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+
+    /// Upper bound for the delay between attempts
+    pub max_backoff: Duration,
+
+    /// Factor applied to the delay after each retry
+    pub multiplier: f64,
+
+    /// Error classes that are retried
+    pub retry_on: Vec<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 2.0,
+            retry_on: vec![ErrorClass::Transient, ErrorClass::DiskFull, ErrorClass::Encoder],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Whether a failed attempt (1-based) should be retried
+    pub fn should_retry(&self, class: ErrorClass, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.retry_on.contains(&class)
+    }
+
+    /// Delay before the retry following a failed attempt (1-based)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        let delay = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_backoff.as_secs_f64()))
+    }
+}
+
+/// One failed export attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedAttempt {
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+
+    pub class: ErrorClass,
+
+    pub message: String,
+
+    /// Frame being processed when the attempt failed
+    pub frame: u64,
+}
+
+/// Structured report of a failed export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportFailure {
+    /// Class of the final error
+    pub class: ErrorClass,
+
+    /// Final error message
+    pub message: String,
+
+    /// Extra debug information from the backend, if any
+    pub debug: Option<String>,
+
+    /// Element or component that reported the error
+    pub element: Option<String>,
+
+    /// Frames between the last confirmed progress and the failure
+    pub frame_range: Option<(u64, u64)>,
+
+    /// Same range in seconds
+    pub time_range: Option<(f64, f64)>,
+
+    /// Every failed attempt, in order
+    pub attempts: Vec<FailedAttempt>,
+}
+
+impl ExportFailure {
+    /// Whether the export failed on an error a retry could have fixed
+    pub fn was_recoverable(&self, policy: &RetryPolicy) -> bool {
+        policy.retry_on.contains(&self.class)
+    }
+
+    /// One-line summary for logs and the UI
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{:?} error: {}", self.class, self.message);
+        if let Some(element) = &self.element {
+            summary.push_str(&format!(" (in {})", element));
+        }
+        if let Some((start, end)) = self.frame_range {
+            summary.push_str(&format!(", frames {}-{}", start, end));
+        }
+        if self.attempts.len() > 1 {
+            summary.push_str(&format!(", after {} attempts", self.attempts.len()));
+        }
+        summary
+    }
+}
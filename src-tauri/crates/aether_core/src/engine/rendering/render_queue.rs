@@ -0,0 +1,517 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::engine::editing::types::EditingError;
+use crate::engine::rendering::completion_hooks::{self, CompletionHook, JobEvent, NotificationCallback};
+use crate::engine::rendering::export::{Exporter, ExportOptions};
+use crate::engine::rendering::output_naming::{NamingContext, OutputNaming};
+use crate::engine::rendering::recovery::ExportFailure;
+use crate::engine::rendering::throttle::IoThrottle;
+
+pub type RenderJobId = u64;
+
+/// Scheduling priority of a render job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JobPriority {
+    /// Runs when nothing else is waiting and is throttled while the user edits
+    Background,
+    Normal,
+    High,
+}
+
+/// State of a render job
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed(_) | JobStatus::Cancelled)
+    }
+}
+
+/// Resource limits applied to background jobs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottleSettings {
+    /// Whether background jobs are throttled
+    pub enabled: bool,
+
+    /// Throttle every job rather than only background ones, e.g. while on battery
+    pub all_jobs: bool,
+
+    /// Encoder thread limit for throttled jobs
+    pub max_encoder_threads: u8,
+
+    /// Output write rate limit for throttled jobs in bytes per second, 0 for unlimited
+    pub max_write_rate: u64,
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            all_jobs: false,
+            max_encoder_threads: 2,
+            max_write_rate: 20 * 1024 * 1024,
+        }
+    }
+}
+
+/// Configuration of a render queue
+#[derive(Debug, Clone)]
+pub struct RenderQueueConfig {
+    /// Number of jobs rendered at the same time
+    pub max_concurrent: usize,
+
+    pub throttle: ThrottleSettings,
+
+    /// Actions run when a job completes or fails
+    pub hooks: Vec<CompletionHook>,
+}
+
+impl Default for RenderQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 1,
+            throttle: ThrottleSettings::default(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+/// Snapshot of a job for display
+#[derive(Debug, Clone)]
+pub struct RenderJobInfo {
+    pub id: RenderJobId,
+
+    pub priority: JobPriority,
+
+    pub status: JobStatus,
+
+    /// Progress percentage (0-100)
+    pub percent: f64,
+
+    /// Whether the job is currently running with throttled resources
+    pub throttled: bool,
+
+    /// Structured failure report, if the job failed
+    pub failure: Option<ExportFailure>,
+}
+
+struct RenderJob {
+    info: RenderJobInfo,
+
+    options: ExportOptions,
+
+    /// Submission order, used to keep FIFO order within a priority
+    sequence: u64,
+
+    cancel_requested: bool,
+
+    /// Write throttle of the running export, adjusted when the throttle changes
+    io_throttle: Option<IoThrottle>,
+
+    /// When the job started running
+    started: Option<Instant>,
+}
+
+struct QueueState {
+    jobs: HashMap<RenderJobId, RenderJob>,
+
+    next_id: RenderJobId,
+
+    throttle: ThrottleSettings,
+
+    hooks: Vec<CompletionHook>,
+
+    notification_callback: Option<NotificationCallback>,
+
+    running: bool,
+}
+
+impl QueueState {
+    /// Highest priority queued job, oldest first within a priority
+    fn next_job(&self) -> Option<RenderJobId> {
+        self.jobs.values()
+            .filter(|job| job.info.status == JobStatus::Queued)
+            .max_by(|a, b| a.info.priority.cmp(&b.info.priority).then(b.sequence.cmp(&a.sequence)))
+            .map(|job| job.info.id)
+    }
+}
+
+/// Queue of exports rendered in priority order on worker threads
+pub struct RenderQueue {
+    state: Arc<(Mutex<QueueState>, Condvar)>,
+
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl RenderQueue {
+    pub fn new(config: RenderQueueConfig) -> Self {
+        let state = Arc::new((
+            Mutex::new(QueueState {
+                jobs: HashMap::new(),
+                next_id: 1,
+                throttle: config.throttle,
+                hooks: config.hooks,
+                notification_callback: None,
+                running: true,
+            }),
+            Condvar::new(),
+        ));
+
+        let workers = (0..config.max_concurrent.max(1))
+            .map(|_| {
+                let state = state.clone();
+                thread::spawn(move || Self::worker_thread(state))
+            })
+            .collect();
+
+        Self { state, workers }
+    }
+
+    /// Add an export to the queue
+    pub fn enqueue(&self, options: ExportOptions, priority: JobPriority) -> RenderJobId {
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+
+        let id = Self::insert_job(&mut state, options, priority);
+        condvar.notify_one();
+        id
+    }
+
+    /// Add an export whose output path comes from a naming template
+    ///
+    /// Outputs of unfinished jobs count as taken, so queued exports never share a file.
+    pub fn enqueue_named(
+        &self,
+        options: ExportOptions,
+        naming: &OutputNaming,
+        context: &NamingContext,
+        priority: JobPriority,
+    ) -> Result<RenderJobId, EditingError> {
+        let ids = self.enqueue_batch(vec![(options, context.clone())], naming, priority)?;
+        Ok(ids[0])
+    }
+
+    /// Add several exports named by one template, e.g. every sequence of a project
+    ///
+    /// All names are resolved before anything is queued, so a collision under
+    /// `CollisionPolicy::Fail` queues nothing.
+    pub fn enqueue_batch(
+        &self,
+        exports: Vec<(ExportOptions, NamingContext)>,
+        naming: &OutputNaming,
+        priority: JobPriority,
+    ) -> Result<Vec<RenderJobId>, EditingError> {
+        let (lock, condvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+
+        let mut reserved: HashSet<PathBuf> = state.jobs.values()
+            .filter(|job| !job.info.status.is_finished())
+            .map(|job| job.options.output_path.clone())
+            .collect();
+
+        let mut named = Vec::with_capacity(exports.len());
+        for (mut options, context) in exports {
+            options.output_path = naming.resolve(&context, options.container_format.extension(), &reserved)?;
+            reserved.insert(options.output_path.clone());
+            named.push(options);
+        }
+
+        let ids: Vec<RenderJobId> = named.into_iter()
+            .map(|options| {
+                info!("Queued export to {}", options.output_path.display());
+                Self::insert_job(&mut state, options, priority)
+            })
+            .collect();
+
+        condvar.notify_all();
+        Ok(ids)
+    }
+
+    fn insert_job(state: &mut QueueState, options: ExportOptions, priority: JobPriority) -> RenderJobId {
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.insert(id, RenderJob {
+            info: RenderJobInfo {
+                id,
+                priority,
+                status: JobStatus::Queued,
+                percent: 0.0,
+                throttled: false,
+                failure: None,
+            },
+            options,
+            sequence: id,
+            cancel_requested: false,
+            io_throttle: None,
+            started: None,
+        });
+        id
+    }
+
+    /// Change the priority of a job
+    ///
+    /// Queued jobs are reordered; a running job only has its throttling updated.
+    pub fn set_priority(&self, id: RenderJobId, priority: JobPriority) -> Result<(), EditingError> {
+        let mut state = self.state.0.lock().unwrap();
+        let throttle = state.throttle.clone();
+        let job = state.jobs.get_mut(&id)
+            .ok_or(EditingError::InvalidParameter(format!("Render job not found: {}", id)))?;
+
+        job.info.priority = priority;
+        if let Some(io_throttle) = &job.io_throttle {
+            let throttled = Self::is_throttled(&throttle, priority);
+            io_throttle.set_rate(if throttled { throttle.max_write_rate } else { 0 });
+            job.info.throttled = throttled;
+        }
+
+        Ok(())
+    }
+
+    /// Cancel a queued or running job
+    pub fn cancel(&self, id: RenderJobId) -> Result<(), EditingError> {
+        let mut state = self.state.0.lock().unwrap();
+        let job = state.jobs.get_mut(&id)
+            .ok_or(EditingError::InvalidParameter(format!("Render job not found: {}", id)))?;
+
+        match job.info.status {
+            JobStatus::Queued => job.info.status = JobStatus::Cancelled,
+            JobStatus::Running => job.cancel_requested = true,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Change throttle settings
+    ///
+    /// Write rate changes apply to running jobs immediately, thread limits apply to jobs
+    /// started afterwards since encoders can't change their thread count mid-stream.
+    pub fn set_throttle(&self, throttle: ThrottleSettings) {
+        let mut state = self.state.0.lock().unwrap();
+
+        for job in state.jobs.values_mut() {
+            if let Some(io_throttle) = &job.io_throttle {
+                let throttled = Self::is_throttled(&throttle, job.info.priority);
+                io_throttle.set_rate(if throttled { throttle.max_write_rate } else { 0 });
+                job.info.throttled = throttled;
+            }
+        }
+
+        state.throttle = throttle;
+    }
+
+    /// Current throttle settings
+    pub fn throttle(&self) -> ThrottleSettings {
+        self.state.0.lock().unwrap().throttle.clone()
+    }
+
+    /// Replace the completion hooks
+    pub fn set_hooks(&self, hooks: Vec<CompletionHook>) {
+        self.state.0.lock().unwrap().hooks = hooks;
+    }
+
+    /// Current completion hooks
+    pub fn hooks(&self) -> Vec<CompletionHook> {
+        self.state.0.lock().unwrap().hooks.clone()
+    }
+
+    /// Set the receiver of notification hook payloads
+    pub fn set_notification_callback(&self, callback: NotificationCallback) {
+        self.state.0.lock().unwrap().notification_callback = Some(callback);
+    }
+
+    /// Get a job snapshot
+    pub fn job(&self, id: RenderJobId) -> Option<RenderJobInfo> {
+        self.state.0.lock().unwrap().jobs.get(&id).map(|job| job.info.clone())
+    }
+
+    /// All jobs in execution order: running first, then queued by priority, then finished
+    pub fn jobs(&self) -> Vec<RenderJobInfo> {
+        let state = self.state.0.lock().unwrap();
+        let mut jobs: Vec<&RenderJob> = state.jobs.values().collect();
+        jobs.sort_by(|a, b| {
+            let rank = |job: &RenderJob| match job.info.status {
+                JobStatus::Running => 0,
+                JobStatus::Queued => 1,
+                _ => 2,
+            };
+            rank(a).cmp(&rank(b))
+                .then(b.info.priority.cmp(&a.info.priority))
+                .then(a.sequence.cmp(&b.sequence))
+        });
+        jobs.into_iter().map(|job| job.info.clone()).collect()
+    }
+
+    /// Drop finished jobs from the list
+    pub fn clear_finished(&self) {
+        self.state.0.lock().unwrap().jobs.retain(|_, job| !job.info.status.is_finished());
+    }
+
+    /// Cancel everything and stop the workers
+    pub fn shutdown(&mut self) {
+        {
+            let (lock, condvar) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            state.running = false;
+            for job in state.jobs.values_mut() {
+                match job.info.status {
+                    JobStatus::Queued => job.info.status = JobStatus::Cancelled,
+                    JobStatus::Running => job.cancel_requested = true,
+                    _ => (),
+                }
+            }
+            condvar.notify_all();
+        }
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+    fn is_throttled(throttle: &ThrottleSettings, priority: JobPriority) -> bool {
+        throttle.enabled && (throttle.all_jobs || priority == JobPriority::Background)
+    }
+
+    fn worker_thread(state: Arc<(Mutex<QueueState>, Condvar)>) {
+        let (lock, condvar) = &*state;
+
+        loop {
+            // Claim the next job
+            let (id, mut options, throttle, io_throttle) = {
+                let mut guard = lock.lock().unwrap();
+                let id = loop {
+                    if !guard.running {
+                        return;
+                    }
+                    if let Some(id) = guard.next_job() {
+                        break id;
+                    }
+                    guard = condvar.wait(guard).unwrap();
+                };
+
+                let throttle = guard.throttle.clone();
+                let job = guard.jobs.get_mut(&id).unwrap();
+                let throttled = Self::is_throttled(&throttle, job.info.priority);
+                let io_throttle = IoThrottle::new(if throttled { throttle.max_write_rate } else { 0 });
+
+                job.info.status = JobStatus::Running;
+                job.info.throttled = throttled;
+                job.io_throttle = Some(io_throttle.clone());
+                job.started = Some(Instant::now());
+                (id, job.options.clone(), throttled.then_some(throttle), io_throttle)
+            };
+
+            // Limit encoder threads for background work
+            if let Some(throttle) = &throttle {
+                if options.threads == 0 || options.threads > throttle.max_encoder_threads {
+                    options.threads = throttle.max_encoder_threads;
+                }
+            }
+
+            let span = tracing::info_span!("render_job", job_id = id, throttled = throttle.is_some());
+            let status = span.in_scope(|| {
+                info!("Starting render job {}", id);
+                let status = Self::run_job(lock, id, options, io_throttle);
+                debug!("Render job {} finished: {:?}", id, status);
+                status
+            });
+
+            let mut guard = lock.lock().unwrap();
+            let event = guard.jobs.get_mut(&id).and_then(|job| {
+                if status == JobStatus::Completed {
+                    job.info.percent = 100.0;
+                }
+                job.info.status = status;
+                job.io_throttle = None;
+                Self::job_event(job)
+            });
+
+            // Run hooks without holding the queue lock
+            if let Some(event) = event {
+                let hooks = guard.hooks.clone();
+                let notify = guard.notification_callback.clone();
+                drop(guard);
+                completion_hooks::run_hooks(&hooks, &event, notify.as_ref());
+            }
+        }
+    }
+
+    /// Hook metadata of a completed or failed job
+    fn job_event(job: &RenderJob) -> Option<JobEvent> {
+        let (status, error) = match &job.info.status {
+            JobStatus::Completed => ("completed", None),
+            JobStatus::Failed(error) => ("failed", Some(error.clone())),
+            _ => return None,
+        };
+
+        Some(JobEvent {
+            job_id: job.info.id,
+            status: status.to_string(),
+            output_path: job.options.output_path.clone(),
+            duration: job.started.map(|started| started.elapsed().as_secs_f64()).unwrap_or(0.0),
+            priority: format!("{:?}", job.info.priority).to_lowercase(),
+            error,
+        })
+    }
+
+    /// Run an export to completion, polling for progress and cancellation
+    fn run_job(lock: &Mutex<QueueState>, id: RenderJobId, options: ExportOptions, io_throttle: IoThrottle) -> JobStatus {
+        let mut exporter = match Exporter::new(options) {
+            Ok(exporter) => exporter,
+            Err(e) => return JobStatus::Failed(e.to_string()),
+        };
+        exporter.set_io_throttle(io_throttle);
+
+        if let Err(e) = exporter.start_export() {
+            return JobStatus::Failed(e.to_string());
+        }
+
+        loop {
+            thread::sleep(Duration::from_millis(200));
+
+            let progress = exporter.get_progress();
+            let cancel_requested = {
+                let mut state = lock.lock().unwrap();
+                match state.jobs.get_mut(&id) {
+                    Some(job) => {
+                        job.info.percent = progress.percent;
+                        job.info.failure = progress.failure.clone();
+                        job.cancel_requested
+                    },
+                    None => true,
+                }
+            };
+
+            if cancel_requested {
+                if let Err(e) = exporter.cancel() {
+                    warn!("Failed to cancel render job {}: {}", id, e);
+                }
+                return JobStatus::Cancelled;
+            }
+
+            if progress.complete {
+                return match progress.error {
+                    Some(error) => JobStatus::Failed(error),
+                    None => JobStatus::Completed,
+                };
+            }
+        }
+    }
+}
+
+impl Drop for RenderQueue {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
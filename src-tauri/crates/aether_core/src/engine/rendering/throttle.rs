@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits how fast an export writes to disk
+///
+/// Clones share the same limit, so the rate can be changed while an export is running.
+#[derive(Debug, Clone)]
+pub struct IoThrottle {
+    /// Bytes per second, 0 means unlimited
+    rate: Arc<AtomicU64>,
+
+    /// Start of the current accounting window and bytes written in it
+    window: Arc<Mutex<(Instant, u64)>>,
+}
+
+impl IoThrottle {
+    /// Throttle with the given rate in bytes per second, 0 for unlimited
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: Arc::new(AtomicU64::new(bytes_per_sec)),
+            window: Arc::new(Mutex::new((Instant::now(), 0))),
+        }
+    }
+
+    /// Throttle that never waits
+    pub fn unlimited() -> Self {
+        Self::new(0)
+    }
+
+    /// Change the rate, taking effect on the next write
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        self.rate.store(bytes_per_sec, Ordering::SeqCst);
+        *self.window.lock().unwrap() = (Instant::now(), 0);
+    }
+
+    /// Current rate in bytes per second, 0 for unlimited
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::SeqCst)
+    }
+
+    /// Account for `bytes` written, sleeping if the writer is ahead of the rate
+    pub fn consume(&self, bytes: usize) {
+        let rate = self.rate();
+        if rate == 0 {
+            return;
+        }
+
+        let wait = {
+            let mut window = self.window.lock().unwrap();
+
+            // Start a fresh window once we're caught up, so a long pause doesn't bank a burst
+            let elapsed = window.0.elapsed();
+            if elapsed >= Duration::from_secs(1) && window.1 as f64 / rate as f64 <= elapsed.as_secs_f64() {
+                *window = (Instant::now(), 0);
+            }
+
+            window.1 += bytes as u64;
+            let allowed_at = Duration::from_secs_f64(window.1 as f64 / rate as f64);
+            allowed_at.checked_sub(window.0.elapsed())
+        };
+
+        if let Some(wait) = wait {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl Default for IoThrottle {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::editing::types::EditingError;
+use crate::engine::timeline::{Clip, ClipType, Timeline, Track};
+
+/// Timeline tracks an export renders
+///
+/// Selecting tracks renders a copy of the timeline, so a textless master or a version
+/// without the scratch narration needs no change to the project.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackSelection {
+    /// IDs of the tracks to render, every track when empty
+    pub include: Vec<String>,
+    /// IDs of the tracks to leave out
+    pub exclude: Vec<String>,
+    /// Leave out every audio clip and write no audio stream
+    pub video_only: bool,
+}
+
+impl TrackSelection {
+    /// Render only the tracks in `track_ids`
+    pub fn only(track_ids: &[&str]) -> Self {
+        Self {
+            include: track_ids.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Render every track except those in `track_ids`
+    pub fn excluding(track_ids: &[&str]) -> Self {
+        Self {
+            exclude: track_ids.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The same selection without audio
+    pub fn without_audio(mut self) -> Self {
+        self.video_only = true;
+        self
+    }
+
+    /// Whether the whole timeline is rendered
+    pub fn is_all(&self) -> bool {
+        *self == TrackSelection::default()
+    }
+
+    pub fn includes_track(&self, track_id: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|id| id == track_id))
+            && !self.exclude.iter().any(|id| id == track_id)
+    }
+
+    pub fn includes_clip(&self, clip: &Clip) -> bool {
+        !(self.video_only && clip.clip_type == ClipType::Audio)
+    }
+
+    /// Check every listed track exists and that something is left to render
+    pub fn validate(&self, timeline: &Timeline) -> Result<(), EditingError> {
+        if let Some(unknown) = self.include.iter()
+            .chain(&self.exclude)
+            .find(|id| !timeline.tracks().contains_key(id.as_str()))
+        {
+            return Err(EditingError::InvalidParameter(format!("Track not found: {}", unknown)));
+        }
+        if !timeline.tracks().is_empty() && !timeline.tracks().keys().any(|id| self.includes_track(id)) {
+            return Err(EditingError::InvalidParameter("Track selection leaves no track to export".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Copy of `timeline` with only the selected tracks and clips, to render in its place
+    pub fn apply(&self, timeline: &Timeline) -> Result<Timeline, EditingError> {
+        self.validate(timeline)?;
+        Ok(timeline.copy_with_tracks(|track| {
+            self.includes_track(&track.id).then(|| Track {
+                clips: track.clips.iter().filter(|clip| self.includes_clip(clip)).cloned().collect(),
+                ..track.clone()
+            })
+        }))
+    }
+}
@@ -0,0 +1,230 @@
+//! Simulated export pipeline, for testing without GStreamer plugins or media files
+//!
+//! `MockPipeline` plays back a scripted run: position updates every tick until the end,
+//! with failures and stalls injected on chosen attempts. `TestHarness` drives the same
+//! `ProgressTracker` and `EncodingPlan` the GStreamer exporter uses, so their results
+//! match what a real export would report for the same events.
+//!
+//! Built for unit tests and with the `simulation` feature.
+
+use std::time::Duration;
+
+use crate::engine::rendering::{
+    EncodingPlan, ErrorClass, ErrorOutcome, ExportOptions, GstExportProgress, ProgressTracker, RetryPolicy,
+};
+use crate::engine::timeline::Timeline;
+
+/// How often the exporter queries the pipeline position, in seconds
+pub const DEFAULT_TICK: f64 = 1.0;
+
+/// Name the simulated pipeline reports errors under
+pub const MOCK_PIPELINE_NAME: &str = "mock-pipeline";
+
+/// Something a pipeline reports while running
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineEvent {
+    /// Position query result, in seconds
+    Position(f64),
+    Eos,
+    Error {
+        class: ErrorClass,
+        message: String,
+        /// Position at the error, in seconds
+        position: f64,
+    },
+    /// The watchdog saw no progress
+    Stall {
+        position: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Fault {
+    attempt: u32,
+    position: f64,
+    /// `None` for a stall
+    error: Option<(ErrorClass, String)>,
+}
+
+/// A pipeline replaying a scripted export
+#[derive(Debug, Clone)]
+pub struct MockPipeline {
+    duration: f64,
+    tick: f64,
+    faults: Vec<Fault>,
+}
+
+impl MockPipeline {
+    /// A pipeline rendering `duration` seconds without trouble
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration: duration.max(0.0),
+            tick: DEFAULT_TICK,
+            faults: Vec::new(),
+        }
+    }
+
+    /// A pipeline for a timeline, which like a GES timeline lasts until its last clip ends
+    pub fn for_timeline(timeline: &Timeline) -> Self {
+        Self::new(timeline_end(timeline))
+    }
+
+    pub fn with_tick(mut self, tick: f64) -> Self {
+        self.tick = tick.max(f64::EPSILON);
+        self
+    }
+
+    /// Fail attempt `attempt` (from 1) once it reaches `position` seconds
+    pub fn fail_at(mut self, attempt: u32, position: f64, class: ErrorClass, message: &str) -> Self {
+        self.faults.push(Fault {
+            attempt,
+            position,
+            error: Some((class, message.to_string())),
+        });
+        self
+    }
+
+    /// Stall attempt `attempt` (from 1) at `position` seconds
+    pub fn stall_at(mut self, attempt: u32, position: f64) -> Self {
+        self.faults.push(Fault {
+            attempt,
+            position,
+            error: None,
+        });
+        self
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// Events of one attempt, ending with end of stream, an error or a stall
+    pub fn run(&self, attempt: u32) -> Vec<PipelineEvent> {
+        let fault = self.faults.iter()
+            .filter(|fault| fault.attempt == attempt && fault.position < self.duration)
+            .min_by(|a, b| a.position.total_cmp(&b.position));
+        let end = fault.map_or(self.duration, |fault| fault.position);
+
+        let mut events = Vec::new();
+        let mut position = self.tick;
+        while position < end {
+            events.push(PipelineEvent::Position(position));
+            position += self.tick;
+        }
+
+        events.push(match fault {
+            Some(Fault { error: Some((class, message)), position, .. }) => PipelineEvent::Error {
+                class: *class,
+                message: message.clone(),
+                position: *position,
+            },
+            Some(Fault { position, .. }) => PipelineEvent::Stall { position: *position },
+            None => PipelineEvent::Eos,
+        });
+        events
+    }
+}
+
+/// Outcome of a simulated export
+#[derive(Debug, Clone)]
+pub struct SimulatedExport {
+    pub plan: EncodingPlan,
+    /// Every progress update the callback would have received, in order
+    pub updates: Vec<GstExportProgress>,
+    /// Delays before each retry
+    pub retry_delays: Vec<Duration>,
+    pub progress: GstExportProgress,
+}
+
+impl SimulatedExport {
+    pub fn succeeded(&self) -> bool {
+        self.progress.complete && self.progress.error.is_none()
+    }
+}
+
+/// Runs export options against a `MockPipeline`
+pub struct TestHarness {
+    options: ExportOptions,
+    retry_policy: RetryPolicy,
+}
+
+impl TestHarness {
+    pub fn new(options: ExportOptions) -> Self {
+        Self {
+            options,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Encoding profile the options map to
+    pub fn plan(&self) -> EncodingPlan {
+        let options = &self.options;
+        EncodingPlan::new(options.container_format, options.video_format, options.audio_format, options.hardware_acceleration)
+            .with_bitrates(options.video_bitrate, options.audio_bitrate)
+            .with_size(options.width, options.height)
+            .with_frame_rate(options.frame_rate)
+    }
+
+    /// Export through the pipeline, retrying as the policy allows; retries don't wait
+    pub fn run(&self, pipeline: &MockPipeline) -> SimulatedExport {
+        let mut tracker = ProgressTracker::new(self.options.frame_rate, self.retry_policy.clone());
+        let mut updates = Vec::new();
+        let mut retry_delays = Vec::new();
+
+        tracker.start(pipeline.duration());
+        updates.push(tracker.progress().clone());
+
+        'attempts: loop {
+            for event in pipeline.run(tracker.progress().attempt) {
+                match event {
+                    PipelineEvent::Position(seconds) => {
+                        if tracker.position(seconds) {
+                            updates.push(tracker.progress().clone());
+                        }
+                    },
+                    PipelineEvent::Eos => {
+                        tracker.finish();
+                        updates.push(tracker.progress().clone());
+                        break 'attempts;
+                    },
+                    PipelineEvent::Error { class, message, position } => {
+                        let outcome = tracker.error(class, &message, None, Some(MOCK_PIPELINE_NAME.to_string()), Some(position));
+                        updates.push(tracker.progress().clone());
+                        match outcome {
+                            ErrorOutcome::Retry(delay) => {
+                                retry_delays.push(delay);
+                                continue 'attempts;
+                            },
+                            ErrorOutcome::Failed => break 'attempts,
+                        }
+                    },
+                    PipelineEvent::Stall { position } => {
+                        tracker.stalled(&format!("No progress at {:.1}s", position), MOCK_PIPELINE_NAME);
+                        updates.push(tracker.progress().clone());
+                        break 'attempts;
+                    },
+                }
+            }
+        }
+
+        SimulatedExport {
+            plan: self.plan(),
+            updates,
+            retry_delays,
+            progress: tracker.progress().clone(),
+        }
+    }
+}
+
+/// End of the last clip on the timeline, in seconds
+pub fn timeline_end(timeline: &Timeline) -> f64 {
+    timeline.tracks().values()
+        .flat_map(|track| track.clips.iter())
+        .map(|clip| clip.end_time())
+        .fold(0.0, f64::max)
+}
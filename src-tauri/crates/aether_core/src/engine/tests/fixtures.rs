@@ -0,0 +1,301 @@
+//! Test media synthesized on demand, so tests don't need binary assets in the repo
+//!
+//! Fixtures are SMPTE color bars from `videotestsrc` and a 440 Hz sine from
+//! `audiotestsrc`, encoded single-threaded so every run produces the same frames and
+//! samples. Container metadata such as creation times can still differ between runs.
+//! Generated files are cached in the temp directory by their spec.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use gstreamer as gst;
+use gst::prelude::*;
+
+use crate::engine::rendering::{AudioFormat, ContainerFormat, VideoFormat};
+use crate::modules::pipeline_builder::{ElementSpec, PipelineBuilder};
+
+/// Audio buffers per second of fixture audio
+const AUDIO_BUFFERS_PER_SECOND: u32 = 100;
+
+/// Longest a fixture may take to generate
+const GENERATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Serializes generation, so concurrent tests don't write the same fixture twice
+static GENERATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// What test media to synthesize
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureSpec {
+    pub container: ContainerFormat,
+    /// `None` for an audio-only file
+    pub video: Option<VideoFormat>,
+    /// `None` for a video-only file
+    pub audio: Option<AudioFormat>,
+    pub width: u32,
+    pub height: u32,
+    /// Frame rate as a fraction, e.g. 30000/1001
+    pub frame_rate: (u32, u32),
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Length in seconds
+    pub duration: f64,
+}
+
+impl Default for FixtureSpec {
+    /// Two seconds of 320x240 H.264 at 30 fps with stereo AAC, in MP4
+    fn default() -> Self {
+        Self {
+            container: ContainerFormat::Mp4,
+            video: Some(VideoFormat::H264),
+            audio: Some(AudioFormat::Aac),
+            width: 320,
+            height: 240,
+            frame_rate: (30, 1),
+            sample_rate: 48000,
+            channels: 2,
+            duration: 2.0,
+        }
+    }
+}
+
+impl FixtureSpec {
+    pub fn with_container(mut self, container: ContainerFormat) -> Self {
+        self.container = container;
+        self
+    }
+
+    pub fn with_video(mut self, video: Option<VideoFormat>) -> Self {
+        self.video = video;
+        self
+    }
+
+    pub fn with_audio(mut self, audio: Option<AudioFormat>) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_frame_rate(mut self, numerator: u32, denominator: u32) -> Self {
+        self.frame_rate = (numerator, denominator);
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32, channels: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: f64) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Number of video frames in the fixture
+    pub fn frame_count(&self) -> u64 {
+        let (numerator, denominator) = self.frame_rate;
+        (self.duration * numerator as f64 / denominator.max(1) as f64).round() as u64
+    }
+
+    /// File name identifying the spec, used as the cache key
+    pub fn file_name(&self) -> String {
+        let video = match self.video {
+            Some(video) => format!("{}x{}_{}-{}_{}", self.width, self.height, self.frame_rate.0, self.frame_rate.1, video.to_ffmpeg_name()),
+            None => "novideo".to_string(),
+        };
+        let audio = match self.audio {
+            Some(audio) => format!("{}hz{}ch_{}", self.sample_rate, self.channels, audio.to_ffmpeg_name()),
+            None => "noaudio".to_string(),
+        };
+        format!(
+            "bars_{}_{}_{}ms.{}",
+            video, audio, (self.duration * 1000.0).round() as u64, self.container.extension()
+        )
+    }
+
+    /// Elements of the pipeline writing the fixture to `output`
+    pub fn pipeline_specs(&self, output: &Path) -> Result<FixturePipeline> {
+        if self.video.is_none() && self.audio.is_none() {
+            return Err(anyhow!("Fixture has neither video nor audio"));
+        }
+        if self.duration <= 0.0 || self.frame_rate.0 == 0 || self.frame_rate.1 == 0 {
+            return Err(anyhow!("Invalid fixture duration or frame rate"));
+        }
+
+        let mut streams = Vec::new();
+        if let Some(video) = self.video {
+            let caps = gst::Caps::builder("video/x-raw")
+                .field("width", self.width as i32)
+                .field("height", self.height as i32)
+                .field("framerate", gst::Fraction::new(self.frame_rate.0 as i32, self.frame_rate.1 as i32))
+                .build();
+            let mut chain = vec![
+                ElementSpec::new("videotestsrc")
+                    .property_from_str("pattern", "smpte")
+                    .property("num-buffers", self.frame_count() as i32),
+                ElementSpec::capsfilter(caps),
+                ElementSpec::new("videoconvert"),
+            ];
+            chain.extend(video_encoder(video)?);
+            streams.push(chain);
+        }
+        if let Some(audio) = self.audio {
+            let buffers = (self.duration * AUDIO_BUFFERS_PER_SECOND as f64).round() as i32;
+            let caps = gst::Caps::builder("audio/x-raw")
+                .field("rate", self.sample_rate as i32)
+                .field("channels", self.channels as i32)
+                .build();
+            let mut chain = vec![
+                ElementSpec::new("audiotestsrc")
+                    .property_from_str("wave", "sine")
+                    .property("freq", 440.0f64)
+                    .property("num-buffers", buffers)
+                    .property("samplesperbuffer", (self.sample_rate / AUDIO_BUFFERS_PER_SECOND) as i32),
+                ElementSpec::capsfilter(caps),
+                ElementSpec::new("audioconvert"),
+            ];
+            chain.extend(audio_encoder(audio)?);
+            streams.push(chain);
+        }
+
+        Ok(FixturePipeline {
+            muxer: muxer(self.container)?,
+            sink: ElementSpec::file_sink(output)?,
+            streams,
+        })
+    }
+}
+
+/// Elements writing a fixture: one chain per stream, all feeding the muxer
+pub struct FixturePipeline {
+    pub muxer: ElementSpec,
+    pub sink: ElementSpec,
+    pub streams: Vec<Vec<ElementSpec>>,
+}
+
+impl FixturePipeline {
+    /// Every element the pipeline needs
+    pub fn specs(&self) -> impl Iterator<Item = &ElementSpec> {
+        [&self.muxer, &self.sink].into_iter().chain(self.streams.iter().flatten())
+    }
+
+    /// Create and link the elements
+    pub fn build(&self) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new("fixture")?;
+        let muxer = builder.chain(&[self.muxer.clone(), self.sink.clone()])?.remove(0);
+        for stream in &self.streams {
+            let chain = builder.chain(stream)?;
+            if let Some(last) = chain.last() {
+                builder.link(last, &muxer)?;
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Directory fixtures are cached in
+pub fn fixture_dir() -> PathBuf {
+    std::env::temp_dir().join("aether_fixtures")
+}
+
+/// Path of a fixture, generating it if it isn't cached yet
+pub fn media_fixture(spec: &FixtureSpec) -> Result<PathBuf> {
+    let path = fixture_dir().join(spec.file_name());
+    let _guard = GENERATE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if path.exists() {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(fixture_dir())?;
+    // Written under another name first, so an interrupted run never leaves a partial fixture
+    let partial = path.with_extension("partial");
+    let specs = spec.pipeline_specs(&partial)?;
+
+    super::test_utils::init_gstreamer();
+    run_pipeline(&specs.build()?).with_context(|| format!("Failed to generate fixture {}", spec.file_name()))?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Whether the GStreamer elements a fixture needs are installed
+pub fn fixture_available(spec: &FixtureSpec) -> bool {
+    super::test_utils::init_gstreamer();
+    spec.pipeline_specs(Path::new("unused"))
+        .map(|specs| specs.specs().all(|spec| gst::ElementFactory::find(spec.factory()).is_some()))
+        .unwrap_or(false)
+}
+
+fn run_pipeline(pipeline: &gst::Pipeline) -> Result<()> {
+    let bus = pipeline.bus().ok_or_else(|| anyhow!("Pipeline without bus"))?;
+    pipeline.set_state(gst::State::Playing)?;
+
+    let result = match bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(GENERATE_TIMEOUT.as_secs()),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    ) {
+        Some(message) => match message.view() {
+            gst::MessageView::Error(err) => Err(anyhow!("{} ({})", err.error(), err.debug().unwrap_or_default())),
+            _ => Ok(()),
+        },
+        None => Err(anyhow!("Timed out after {:?}", GENERATE_TIMEOUT)),
+    };
+
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+fn muxer(container: ContainerFormat) -> Result<ElementSpec> {
+    let factory = match container {
+        ContainerFormat::Mp4 => "mp4mux",
+        ContainerFormat::Mov => "qtmux",
+        ContainerFormat::Mkv => "matroskamux",
+        ContainerFormat::Webm => "webmmux",
+        ContainerFormat::Avi => "avimux",
+        ContainerFormat::Flv => "flvmux",
+        ContainerFormat::Ts => "mpegtsmux",
+        ContainerFormat::Mpg => "mpegpsmux",
+        other => return Err(anyhow!("No fixture muxer for {:?}", other)),
+    };
+    Ok(ElementSpec::new(factory))
+}
+
+fn video_encoder(video: VideoFormat) -> Result<Vec<ElementSpec>> {
+    let single_threaded = |factory: &str| ElementSpec::new(factory).property_from_str("threads", "1");
+    match video {
+        VideoFormat::H264 => Ok(vec![
+            single_threaded("x264enc").property_from_str("key-int-max", "30"),
+            ElementSpec::new("h264parse"),
+        ]),
+        VideoFormat::H265 => Ok(vec![ElementSpec::new("x265enc"), ElementSpec::new("h265parse")]),
+        VideoFormat::Vp8 => Ok(vec![single_threaded("vp8enc")]),
+        VideoFormat::Vp9 => Ok(vec![single_threaded("vp9enc")]),
+        VideoFormat::Av1 => Ok(vec![single_threaded("av1enc"), ElementSpec::new("av1parse")]),
+        VideoFormat::Mjpeg => Ok(vec![ElementSpec::new("jpegenc")]),
+        VideoFormat::Mpeg2 => Ok(vec![ElementSpec::new("avenc_mpeg2video"), ElementSpec::new("mpegvideoparse")]),
+        VideoFormat::Mpeg4 => Ok(vec![ElementSpec::new("avenc_mpeg4"), ElementSpec::new("mpeg4videoparse")]),
+        VideoFormat::Theora => Ok(vec![ElementSpec::new("theoraenc")]),
+        VideoFormat::Raw => Ok(vec![ElementSpec::new("identity")]),
+        other => Err(anyhow!("No fixture encoder for {:?}", other)),
+    }
+}
+
+fn audio_encoder(audio: AudioFormat) -> Result<Vec<ElementSpec>> {
+    let factories: &[&str] = match audio {
+        AudioFormat::Aac => &["avenc_aac", "aacparse"],
+        AudioFormat::Mp3 => &["lamemp3enc", "mpegaudioparse"],
+        AudioFormat::Opus => &["opusenc"],
+        AudioFormat::Vorbis => &["vorbisenc"],
+        AudioFormat::Flac => &["flacenc", "flacparse"],
+        AudioFormat::Ac3 => &["avenc_ac3", "ac3parse"],
+        AudioFormat::Pcm => &["identity"],
+        other => return Err(anyhow!("No fixture encoder for {:?}", other)),
+    };
+    Ok(factories.iter().map(|factory| ElementSpec::new(factory)).collect())
+}
@@ -18,7 +18,7 @@ mod tests {
         init_gstreamer();
         
         // Ensure we have a test video
-        download_test_video_if_needed()?;
+        ensure_test_video()?;
         
         // Create engines
         let editing_engine = create_test_editing_engine()?;
@@ -298,4 +298,223 @@ mod tests {
         
         Ok(())
     }
+    
+    #[test]
+    fn test_media_fixtures() -> Result<()> {
+        use super::super::fixtures::{fixture_available, media_fixture, FixtureSpec};
+        use crate::modules::file_manager::FileManager;
+        
+        let specs = [
+            FixtureSpec::default(),
+            FixtureSpec::default()
+                .with_container(ContainerFormat::Mkv)
+                .with_video(Some(VideoFormat::Vp9))
+                .with_audio(Some(AudioFormat::Opus))
+                .with_frame_rate(30000, 1001),
+            FixtureSpec::default()
+                .with_container(ContainerFormat::Mkv)
+                .with_video(None)
+                .with_audio(Some(AudioFormat::Flac))
+                .with_sample_rate(44100, 1),
+        ];
+        
+        for spec in specs.iter().filter(|spec| fixture_available(spec)) {
+            let path = media_fixture(spec)?;
+            assert!(check_file_exists_with_content(&path));
+            
+            // Cached by spec
+            let modified = std::fs::metadata(&path)?.modified()?;
+            assert_eq!(media_fixture(spec)?, path);
+            assert_eq!(std::fs::metadata(&path)?.modified()?, modified);
+            
+            let info = FileManager::new()?.get_media_info(&path)?;
+            let duration = info.duration.unwrap_or_default();
+            assert!((duration - spec.duration).abs() < 0.1, "{} lasts {}", path.display(), duration);
+        }
+        
+        assert_eq!(specs[1].frame_count(), 60);
+        assert_ne!(specs[0].file_name(), specs[1].file_name());
+        assert!(FixtureSpec::default().with_video(None).with_audio(None).pipeline_specs(std::path::Path::new("out")).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_deterministic_render() -> Result<()> {
+        use super::super::fixtures::{fixture_available, media_fixture, FixtureSpec};
+        use crate::engine::rendering::{Exporter, ExportOptions};
+        
+        let spec = FixtureSpec::default();
+        if !fixture_available(&spec) {
+            return Ok(());
+        }
+        let input = media_fixture(&spec)?;
+        let dir = create_temp_dir("deterministic")?;
+        
+        let render = |name: &str, deterministic: bool| -> Result<PathBuf> {
+            let mut exporter = Exporter::new(ExportOptions {
+                input_path: input.clone(),
+                output_path: dir.join(name),
+                threads: 4,
+                deterministic,
+                ..ExportOptions::default()
+            })?;
+            exporter.start_export()?;
+            wait_for_condition(
+                || exporter.get_progress().complete,
+                Duration::from_secs(60),
+                Duration::from_millis(100),
+            )?;
+            assert!(exporter.get_progress().error.is_none());
+            Ok(dir.join(name))
+        };
+        
+        // Same input, same bytes, even a second apart
+        let first = render("first.mp4", true)?;
+        std::thread::sleep(Duration::from_secs(1));
+        let second = render("second.mp4", true)?;
+        assert!(check_file_exists_with_content(&first));
+        assert_eq!(file_checksum(&first)?, file_checksum(&second)?);
+        
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+    
+    #[test]
+    fn test_freeze_clip() -> Result<()> {
+        use super::super::fixtures::{fixture_available, media_fixture, FixtureSpec};
+        
+        let spec = FixtureSpec::default();
+        let freeze_elements = ["matroskamux", "jpegenc", "flacenc", "agingtv"];
+        if !fixture_available(&spec) || freeze_elements.iter().any(|name| gstreamer::ElementFactory::find(name).is_none()) {
+            return Ok(());
+        }
+        let input = media_fixture(&spec)?;
+        let dir = create_temp_dir("freeze")?;
+        
+        let engine = create_test_editing_engine()?;
+        let engine = engine.lock().unwrap();
+        let timeline = engine.timeline();
+        let uri = gstreamer::glib::filename_to_uri(&input, None)?;
+        let clip_id = {
+            let mut timeline = timeline.lock().unwrap();
+            let clip = timeline.add_clip(&uri, TrackType::Video, 0, 1_000_000_000, 500_000_000)?;
+            timeline.add_effect(&clip.id, "agingtv")?;
+            clip.id
+        };
+        
+        let path = engine.freeze_clip(&clip_id, &dir)?;
+        assert!(check_file_exists_with_content(&path));
+        {
+            let mut timeline = timeline.lock().unwrap();
+            assert!(timeline.get_clip(&clip_id).unwrap().is_frozen());
+            // Edits that would make the render stale wait for an unfreeze
+            assert!(timeline.trim_clip(&clip_id, 500_000_000).is_err());
+            assert!(timeline.add_effect(&clip_id, "agingtv").is_err());
+        }
+        assert!(engine.freeze_clip(&clip_id, &dir).is_err());
+        
+        engine.unfreeze_clip(&clip_id)?;
+        let timeline = timeline.lock().unwrap();
+        let clip = timeline.get_clip(&clip_id).unwrap();
+        assert!(!clip.is_frozen());
+        assert_eq!(clip.effects.len(), 1);
+        assert!(!path.exists());
+        
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+    
+    #[test]
+    fn test_copy_clips_between_projects() -> Result<()> {
+        use super::super::fixtures::{fixture_available, media_fixture, FixtureSpec};
+        use crate::modules::color_grading::{ColorAdjustments, ColorCurves, GradingPreset, GradingPresetType};
+        
+        let spec = FixtureSpec::default();
+        if !fixture_available(&spec) || gstreamer::ElementFactory::find("agingtv").is_none() {
+            return Ok(());
+        }
+        init_gstreamer();
+        let uri = gstreamer::glib::filename_to_uri(media_fixture(&spec)?, None)?;
+        let grade = GradingPreset {
+            name: "warm".to_string(),
+            preset_type: GradingPresetType::Custom("warm".to_string()),
+            adjustments: ColorAdjustments { saturation: 1.2, ..ColorAdjustments::default() },
+            curves: ColorCurves::default(),
+            lut: None,
+        };
+        
+        let mut manager = ProjectManager::new();
+        let source = manager.open_project(None)?;
+        let dest = manager.open_project(None)?;
+        
+        // Two clips a second apart, the second with an effect and a grade
+        let source_ids = {
+            let engine = manager.get_project(&source)?;
+            let timeline = engine.lock().unwrap().timeline();
+            let mut timeline = timeline.lock().unwrap();
+            let first = timeline.add_clip(&uri, TrackType::Video, 2_000_000_000, 500_000_000, 0)?;
+            let second = timeline.add_clip(&uri, TrackType::Video, 3_000_000_000, 700_000_000, 250_000_000)?;
+            let effect = timeline.add_effect(&second.id, "agingtv")?;
+            timeline.set_effect_parameter(&second.id, &effect.id, "active", "false")?;
+            timeline.set_clip_grade(&second.id, Some(grade.clone()))?;
+            vec![first.id, second.id]
+        };
+        // The destination already has a clip, so copies can't reuse its ID
+        let existing = {
+            let engine = manager.get_project(&dest)?;
+            let timeline = engine.lock().unwrap().timeline();
+            let mut timeline = timeline.lock().unwrap();
+            timeline.add_clip(&uri, TrackType::Video, 0, 500_000_000, 0)?.id
+        };
+        
+        let copied = manager.copy_clips(&source, &source_ids, &dest, 10_000_000_000)?;
+        assert_eq!(copied.len(), 2);
+        assert!(!copied.contains(&existing));
+        assert_ne!(copied[0], copied[1]);
+        {
+            let engine = manager.get_project(&dest)?;
+            let timeline = engine.lock().unwrap().timeline();
+            let timeline = timeline.lock().unwrap();
+            assert_eq!(timeline.get_clips().len(), 3);
+            
+            // Relative spacing, durations and in points carry over
+            let first = timeline.get_clip(&copied[0]).unwrap();
+            let second = timeline.get_clip(&copied[1]).unwrap();
+            assert_eq!((first.start_time, first.duration, first.in_point), (10_000_000_000, 500_000_000, 0));
+            assert_eq!((second.start_time, second.duration, second.in_point), (11_000_000_000, 700_000_000, 250_000_000));
+            
+            // So do effects with their parameters, and the grade
+            assert!(first.effects.is_empty() && first.grade.is_none());
+            assert_eq!(second.effects.len(), 1);
+            assert_eq!(second.effects[0].name, "agingtv");
+            assert_eq!(second.effects[0].parameters.get("active").map(String::as_str), Some("false"));
+            assert_eq!(second.grade.as_ref().map(|grade| grade.grade_version()), Some(grade.grade_version()));
+        }
+        
+        // The source project is left alone
+        {
+            let engine = manager.get_project(&source)?;
+            let timeline = engine.lock().unwrap().timeline();
+            assert_eq!(timeline.lock().unwrap().get_clips().len(), 2);
+        }
+        
+        // A whole sequence lands in order at the new start
+        let sequence = manager.copy_sequence(&source, &dest, 20_000_000_000)?;
+        assert_eq!(sequence.len(), 2);
+        {
+            let engine = manager.get_project(&dest)?;
+            let timeline = engine.lock().unwrap().timeline();
+            let timeline = timeline.lock().unwrap();
+            assert_eq!(timeline.get_clips().len(), 5);
+            let mut starts: Vec<i64> = sequence.iter().map(|id| timeline.get_clip(id).unwrap().start_time).collect();
+            starts.sort();
+            assert_eq!(starts, vec![20_000_000_000, 21_000_000_000]);
+        }
+        
+        // Copying into the same project or from missing clips is refused
+        assert!(manager.copy_clips(&source, &source_ids, &source, 0).is_err());
+        assert!(manager.copy_clips(&source, &["missing".to_string()], &dest, 0).is_err());
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 pub mod unit_tests;
 pub mod integration_tests;
 pub mod test_utils;
+pub mod fixtures;
 pub use test_utils::*;
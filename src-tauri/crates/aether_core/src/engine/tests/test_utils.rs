@@ -3,11 +3,12 @@ use std::sync::{Arc, Mutex};
 use std::env;
 use std::fs;
 use std::time::Duration;
-use anyhow::{Result, Context};
+use anyhow::Result;
 
 use crate::engine::editing::EditingEngine;
 use crate::engine::rendering::RenderingEngine;
 use crate::engine::integration::IntegratedExporter;
+use super::fixtures::{media_fixture, FixtureSpec};
 
 pub const TEST_VIDEO_ENV: &str = "VIDEO_TEST_PATH";
 
@@ -55,15 +56,7 @@ pub fn create_test_rendering_engine() -> Result<Arc<Mutex<RenderingEngine>>> {
 }
 
 pub fn import_test_video(engine: &Arc<Mutex<EditingEngine>>) -> Result<String> {
-    let test_video = get_test_video_path();
-    
-    if !test_video.exists() {
-        anyhow::bail!(
-            "Test video not found at {}. Set the {} environment variable to a valid video path.",
-            test_video.display(),
-            TEST_VIDEO_ENV
-        );
-    }
+    let test_video = ensure_test_video()?;
     
     let clip_id = engine.lock().unwrap().import_media(&test_video)?;
     Ok(clip_id)
@@ -112,32 +105,19 @@ pub fn ensure_test_assets_dir() -> Result<PathBuf> {
     Ok(assets_dir)
 }
 
-pub fn download_test_video_if_needed() -> Result<PathBuf> {
+/// The test video, or a generated fixture when none is configured
+pub fn ensure_test_video() -> Result<PathBuf> {
     let test_video = get_test_video_path();
     
     if test_video.exists() {
         return Ok(test_video);
     }
     
-    let assets_dir = ensure_test_assets_dir()?;
-    let target_path = assets_dir.join("test_video.mp4");
-    
-    let url = "https://sample-videos.com/video123/mp4/240/big_buck_bunny_240p_1mb.mp4";
-    
-    println!("Downloading test video from {}", url);
-    
-    let response = reqwest::blocking::get(url)
-        .context("Failed to download test video")?;
-    
-    let content = response.bytes()
-        .context("Failed to read test video content")?;
-    
-    fs::write(&target_path, content)
-        .context("Failed to write test video to disk")?;
-    
-    println!("Downloaded test video to {}", target_path.display());
+    if env::var(TEST_VIDEO_ENV).is_ok() {
+        anyhow::bail!("Test video not found at {}", test_video.display());
+    }
     
-    Ok(target_path)
+    media_fixture(&FixtureSpec::default())
 }
 
 pub fn create_mock_progress_callback<T: Clone + Send + 'static>() -> (
@@ -6,6 +6,7 @@ mod tests {
     use crate::engine::rendering::formats::*;
     use crate::engine::rendering::encoder::*;
     use crate::engine::rendering::*;
+    use std::collections::HashSet;
     use std::path::PathBuf;
     use std::time::Duration;
     
@@ -201,4 +202,2171 @@ mod tests {
         let display_str = format!("{}", error);
         assert!(display_str.contains("Test error"));
     }
+    
+    #[test]
+    fn test_timeline_validation() {
+        use crate::engine::frame_rate::FrameRate;
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track, SOURCE_FPS_PROPERTY};
+        use crate::engine::timeline_validation::DiagnosticKind;
+        
+        let media = std::env::temp_dir().join("aether_validation_test.mp4");
+        std::fs::write(&media, b"media").unwrap();
+        let media = media.to_string_lossy().to_string();
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string())).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("a".to_string(), ClipType::Video, 0.0, 10.0)
+            .with_source(media.clone())
+            .add_property(SOURCE_FPS_PROPERTY.to_string(), "29.97".to_string())).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("gone".to_string(), ClipType::Video, 10.0, 5.0)
+            .with_source("/missing/clip.mp4".to_string())).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("fx".to_string(), ClipType::Effect, 25.0, 10.0)).unwrap();
+        
+        // Pushed directly, as add_clip would refuse the overlap
+        let track = timeline.get_track_mut("video").unwrap();
+        track.clips.push(Clip::new("b".to_string(), ClipType::Video, 8.0, 0.0).with_source(media.clone()));
+        
+        let report = timeline.validate();
+        assert!(!report.is_valid());
+        
+        let kinds: Vec<(&str, &DiagnosticKind)> = report.diagnostics.iter().map(|d| (d.clip_id.as_str(), &d.kind)).collect();
+        assert!(kinds.contains(&("gone", &DiagnosticKind::MissingMedia { path: Some("/missing/clip.mp4".to_string()) })));
+        assert!(kinds.contains(&("b", &DiagnosticKind::ZeroLength)));
+        assert!(kinds.contains(&("fx", &DiagnosticKind::EffectOutOfBounds)));
+        assert!(kinds.contains(&("a", &DiagnosticKind::FrameRateMismatch { clip_fps: 29.97, timeline_fps: FrameRate::FPS_25 })));
+        assert_eq!(report.errors().count(), 2);
+        
+        // A clip overlapping its neighbour is only allowed under a transition
+        let track = timeline.get_track_mut("video").unwrap();
+        track.clips.retain(|clip| clip.id == "a");
+        track.clips.push(Clip::new("c".to_string(), ClipType::Video, 9.0, 5.0).with_source(media.clone()));
+        let report = timeline.validate();
+        assert!(matches!(&report.errors().next().unwrap().kind, DiagnosticKind::Overlap { other_clip_id, .. } if other_clip_id == "a"));
+        
+        let track = timeline.get_track_mut("video").unwrap();
+        track.clips[1].properties.insert("transition".to_string(), "crossfade".to_string());
+        track.clips[1].properties.insert("transition_duration".to_string(), "1".to_string());
+        assert!(timeline.validate().is_valid());
+        
+        std::fs::remove_file(&media).unwrap();
+    }
+    
+    #[test]
+    fn test_conform_to_clip() {
+        use crate::engine::frame_rate::FrameRate;
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_conform::{ConversionOption, FormatMismatch, ScaleMode, SourceFormat};
+        
+        let clip = |id: &str, start: f64, format: SourceFormat| {
+            let mut clip = Clip::new(id.to_string(), ClipType::Video, start, 5.0);
+            format.apply_to(&mut clip);
+            clip
+        };
+        let uhd = SourceFormat { resolution: Some((3840, 2160)), fps: Some(23.976), sample_rate: Some(48000) };
+        let phone = SourceFormat { resolution: Some((1080, 1920)), fps: Some(30.0), sample_rate: Some(44100) };
+        
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string())).unwrap();
+        timeline.add_clip_to_track("video", clip("phone", 5.0, phone)).unwrap();
+        
+        let report = timeline.conform_to_clip(&clip("first", 0.0, uhd)).unwrap();
+        assert_eq!((report.settings.width, report.settings.height, report.settings.frame_rate), (3840, 2160, FrameRate::FPS_23_976));
+        assert_eq!(timeline.fps(), 24);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].clip_id, "phone");
+        assert_eq!(report.mismatches[0].mismatches, vec![
+            FormatMismatch::Resolution { source: (1080, 1920), sequence: (3840, 2160) },
+            FormatMismatch::FrameRate { source: 30.0, sequence: FrameRate::FPS_23_976 },
+            FormatMismatch::SampleRate { source: 44100, sequence: 48000 },
+        ]);
+        assert_eq!(report.mismatches[0].mismatches[0].conversions()[0], ConversionOption::Scale(ScaleMode::Fit));
+        
+        // Chosen conversions silence the mismatch
+        timeline.apply_conversion("video", "phone", ConversionOption::Scale(ScaleMode::Fit)).unwrap();
+        timeline.apply_conversion("video", "phone", ConversionOption::Resample).unwrap();
+        let remaining = timeline.mismatched_clips();
+        assert_eq!(remaining[0].mismatches, vec![FormatMismatch::FrameRate { source: 30.0, sequence: FrameRate::FPS_23_976 }]);
+        
+        assert!(timeline.conform_to_clip(&Clip::new("bare".to_string(), ClipType::Video, 0.0, 1.0)).is_err());
+    }
+    
+    #[test]
+    fn test_frame_rate_conform() {
+        use crate::engine::frame_interpolation::{blend_frames, interpolate_frames};
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, SOURCE_FPS_PROPERTY};
+        use crate::engine::timeline_conform::{FrameRateConform, SourceSample, CONFORM_FPS_PROPERTY};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 30, duration: 60.0 });
+        let mut clip = Clip::new("slow".to_string(), ClipType::Video, 10.0, 5.0)
+            .add_property(SOURCE_FPS_PROPERTY.to_string(), "24".to_string());
+        clip.set_in_point(2.0);
+        
+        // Timeline frame 1 lands 0.8 of the way to source frame 49
+        let time = 10.0 + 1.0 / 30.0;
+        assert_eq!(timeline.source_sample(&clip, time), SourceSample::Frame(49.0 / 24.0));
+        
+        timeline.set_frame_rate_conform(FrameRateConform::FrameBlend);
+        match timeline.source_sample(&clip, time) {
+            SourceSample::Mix { first, second, weight, strategy } => {
+                assert_eq!((first, second, strategy), (2.0, 49.0 / 24.0, FrameRateConform::FrameBlend));
+                assert!((weight - 0.8).abs() < 1e-9);
+            },
+            other => panic!("Expected a mix, got {:?}", other),
+        }
+        
+        // Per-clip strategy wins over the timeline default
+        clip.properties.insert(CONFORM_FPS_PROPERTY.to_string(), "optical_flow".to_string());
+        assert!(matches!(timeline.source_sample(&clip, time), SourceSample::Mix { strategy: FrameRateConform::OpticalFlow, .. }));
+        
+        let matching = Clip::new("native".to_string(), ClipType::Video, 0.0, 5.0)
+            .add_property(SOURCE_FPS_PROPERTY.to_string(), "30".to_string());
+        assert_eq!(timeline.source_sample(&matching, 1.5), SourceSample::Frame(1.5));
+        
+        assert_eq!(blend_frames(&[0, 100, 200, 255], &[100, 100, 0, 255], 0.25), vec![25, 100, 150, 255]);
+        
+        // A bright square moving 16 pixels right is placed halfway, not ghosted
+        let (width, height) = (64, 64);
+        let square = |left: usize| {
+            let mut frame = vec![0u8; width * height * 4];
+            for y in 24..40 {
+                for x in left..left + 16 {
+                    frame[(y * width + x) * 4..(y * width + x) * 4 + 4].copy_from_slice(&[255, 255, 255, 255]);
+                }
+            }
+            frame
+        };
+        let middle = interpolate_frames(&square(8), &square(24), width, height, 0.5);
+        let pixel = |x: usize, y: usize| middle[(y * width + x) * 4];
+        assert_eq!(pixel(20, 32), 255);
+        assert_eq!(pixel(10, 32), 0);
+    }
+    
+    #[test]
+    fn test_audio_quality_options() {
+        let defaults = AudioQualityOptions::default();
+        assert_eq!(defaults.to_swr_options(AudioFormat::Aac), vec![("filter_size", "32".to_string())]);
+        assert_eq!(defaults.to_swr_options(AudioFormat::Pcm), vec![
+            ("filter_size", "32".to_string()),
+            ("dither_method", "triangular".to_string()),
+        ]);
+        
+        // Noise shaping replaces the plain dither, and soxr replaces the filter size
+        let mastering = AudioQualityOptions::mastering();
+        assert_eq!(mastering.to_swr_options(AudioFormat::Flac), vec![
+            ("resampler", "soxr".to_string()),
+            ("precision", "28".to_string()),
+            ("dither_method", "high_shibata".to_string()),
+        ]);
+        assert_eq!(mastering.resample_quality.to_gst_quality(), 10);
+        assert_eq!(mastering.noise_shaping.to_gst_name(), "high");
+        
+        let undithered = AudioQualityOptions { dither: Dither::None, ..AudioQualityOptions::default() };
+        assert_eq!(undithered.to_swr_options(AudioFormat::Pcm).len(), 1);
+    }
+    
+    #[test]
+    fn test_output_naming() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let context = NamingContext { date, ..NamingContext::new("Trailer", "Cut: v2", "YouTube 1080p") }
+            .with_range(65.0, 3725.0);
+        
+        let template = FilenameTemplate::new("{project}_{sequence}_{date}_{preset}_{range}").unwrap();
+        assert_eq!(template.render(&context), "Trailer_Cut_ v2_2024-03-09_YouTube 1080p_000105-010205");
+        assert_eq!(FilenameTemplate::new("{{{project}}}").unwrap().render(&context), "{Trailer}");
+        assert!(FilenameTemplate::new("{project}_{client}").is_err());
+        assert!(FilenameTemplate::new("{project").is_err());
+        assert!(FilenameTemplate::new("project}").is_err());
+        
+        let dir = std::env::temp_dir().join(format!("aether_naming_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = FilenameTemplate::new("{project}").unwrap();
+        let existing = dir.join("Trailer.mp4");
+        std::fs::write(&existing, b"").unwrap();
+        let reserved: HashSet<PathBuf> = [dir.join("Trailer_2.mp4")].into_iter().collect();
+        
+        let increment = OutputNaming::new(&dir, template.clone(), CollisionPolicy::AutoIncrement);
+        assert_eq!(increment.resolve(&context, "mp4", &reserved).unwrap(), dir.join("Trailer_3.mp4"));
+        assert_eq!(increment.resolve(&context, "mov", &reserved).unwrap(), dir.join("Trailer.mov"));
+        
+        let fail = OutputNaming::new(&dir, template.clone(), CollisionPolicy::Fail);
+        assert!(fail.resolve(&context, "mp4", &HashSet::new()).is_err());
+        
+        let overwrite = OutputNaming::new(&dir, template, CollisionPolicy::Overwrite);
+        assert_eq!(overwrite.resolve(&context, "mp4", &HashSet::new()).unwrap(), existing);
+        assert!(overwrite.resolve(&context, "mp4", &[existing.clone()].into_iter().collect()).is_err());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn test_completion_hook_templates() {
+        let event = JobEvent {
+            job_id: 7,
+            status: "failed".to_string(),
+            output_path: PathBuf::from("/exports/it's done.mp4"),
+            duration: 12.34,
+            priority: "high".to_string(),
+            error: Some("Disk full".to_string()),
+        };
+        
+        assert_eq!(
+            event.render("Job {job_id} {status} after {duration}s: {error} {unknown}", str::to_string),
+            "Job 7 failed after 12.3s: Disk full {unknown}"
+        );
+        
+        assert!(HookTrigger::Failed.matches(&event));
+        assert!(HookTrigger::Finished.matches(&event));
+        assert!(!HookTrigger::Completed.matches(&event));
+        assert!(HookTrigger::Completed.matches(&JobEvent { error: None, ..event.clone() }));
+    }
+    
+    /// Output names that have tried to break out of the hook command before
+    const HOSTILE_HOOK_VALUES: &[&str] = &[
+        "it's done.mp4",
+        "say \"cheese\".mp4",
+        "two  spaces .mp4",
+        "a; rm -rf ~.mp4",
+        "$(touch pwned).mp4",
+        "`touch pwned`.mp4",
+        "%PATH% and %CD%.mp4",
+        "100%.mp4",
+        "a & b | c > d ^ e.mp4",
+        "line\nbreak.mp4",
+        "\\server\\share\\",
+    ];
+    
+    #[test]
+    fn test_hook_quoting_sh() {
+        // Every value comes back from the shell exactly as it went in
+        for value in HOSTILE_HOOK_VALUES {
+            let output = std::process::Command::new("sh")
+                .args(["-c", &format!("printf %s {}", sh_quote(value))])
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", value);
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), *value);
+        }
+        assert_eq!(sh_quote("it's"), "'it'\\''s'");
+    }
+    
+    #[test]
+    fn test_hook_quoting_cmd() {
+        for value in HOSTILE_HOOK_VALUES {
+            let quoted = cmd_quote(value);
+            
+            // One quoted string: cmd never leaves quotes, so no metacharacter is live
+            let inner = &quoted[1..quoted.len() - 1];
+            assert!(quoted.starts_with('"') && quoted.ends_with('"'));
+            assert_eq!(inner.matches('"').count() % 2, 0, "{}", quoted);
+            assert!(inner.split("\"\"").all(|part| !part.contains('"')), "{}", quoted);
+            assert!(!quoted.contains(['\r', '\n']), "{}", quoted);
+            
+            // No `%name%` pair is left for cmd to expand
+            assert_eq!(quoted.replace("%%cd:~,%", "").matches('%').count(), 0, "{}", quoted);
+        }
+        
+        assert_eq!(cmd_quote("say \"cheese\""), "\"say \"\"cheese\"\"\"");
+        assert_eq!(cmd_quote("%PATH%"), "\"%%cd:~,%PATH%%cd:~,%\"");
+        assert_eq!(cmd_quote("a; $(b)"), "\"a; $(b)\"");
+    }
+    
+    #[test]
+    fn test_frame_server_protocol() {
+        use crate::engine::editing::PreviewFrame;
+        use crate::engine::frame_server::*;
+        use crate::engine::timeline_backend::{TimelineBackend, TimelineBackendConfig, TimelineBackendKind};
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::sync::{Arc, Mutex};
+        
+        /// Solid frames whose value is the requested time in tenths of a second
+        struct SolidBackend;
+        
+        impl TimelineBackend for SolidBackend {
+            fn kind(&self) -> TimelineBackendKind {
+                TimelineBackendKind::Lightweight
+            }
+            
+            fn load(&mut self, _timeline: Arc<Mutex<crate::engine::timeline::Timeline>>) -> Result<(), EditingError> {
+                Ok(())
+            }
+            
+            fn render_frame(&mut self, time: f64) -> Result<PreviewFrame, EditingError> {
+                if time > 10.0 {
+                    return Err(EditingError::InvalidParameter("Past the end".to_string()));
+                }
+                Ok(PreviewFrame { width: 2, height: 2, data: vec![(time * 10.0) as u8; 16], pts: 0, duration: 0, format: "RGBA".to_string() })
+            }
+            
+            fn shutdown(&mut self) -> Result<(), EditingError> {
+                Ok(())
+            }
+        }
+        
+        fn read<const N: usize>(stream: &mut TcpStream) -> [u8; N] {
+            let mut bytes = [0u8; N];
+            stream.read_exact(&mut bytes).unwrap();
+            bytes
+        }
+        
+        let config = FrameServerConfig {
+            window: 2,
+            backend: TimelineBackendConfig { width: 2, height: 2, fps: 25.0 },
+            ..FrameServerConfig::default()
+        };
+        let mut server = FrameServer::with_backend(config, |_| Ok(Box::new(SolidBackend) as Box<dyn TimelineBackend>)).unwrap();
+        let mut client = TcpStream::connect(server.address()).unwrap();
+        
+        assert_eq!(&read::<4>(&mut client), b"AEFS");
+        assert_eq!(u16::from_le_bytes(read(&mut client)), PROTOCOL_VERSION);
+        assert_eq!((u32::from_le_bytes(read(&mut client)), u32::from_le_bytes(read(&mut client))), (2, 2));
+        assert_eq!(f64::from_le_bytes(read(&mut client)), 25.0);
+        assert_eq!(u32::from_le_bytes(read(&mut client)), 2);
+        
+        // More requests than the window are pipelined; responses keep request order
+        for (id, time) in [(1u32, 0.5f64), (2, 1.0), (3, 20.0)] {
+            client.write_all(&[REQUEST_FRAME]).unwrap();
+            client.write_all(&id.to_le_bytes()).unwrap();
+            client.write_all(&time.to_le_bytes()).unwrap();
+        }
+        
+        for (id, value) in [(1u32, 5u8), (2, 10)] {
+            assert_eq!(read::<1>(&mut client)[0], RESPONSE_FRAME);
+            assert_eq!(u32::from_le_bytes(read(&mut client)), id);
+            let _time: [u8; 8] = read(&mut client);
+            let _size: [u8; 8] = read(&mut client);
+            assert_eq!(u32::from_le_bytes(read(&mut client)), 16);
+            assert_eq!(read::<16>(&mut client), [value; 16]);
+        }
+        
+        assert_eq!(read::<1>(&mut client)[0], RESPONSE_ERROR);
+        assert_eq!(u32::from_le_bytes(read(&mut client)), 3);
+        let length = u32::from_le_bytes(read(&mut client)) as usize;
+        let mut message = vec![0u8; length];
+        client.read_exact(&mut message).unwrap();
+        assert!(String::from_utf8(message).unwrap().contains("Past the end"));
+        
+        client.write_all(&[REQUEST_CLOSE]).unwrap();
+        server.shutdown();
+    }
+    
+    #[test]
+    fn test_command_history_undo_redo() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_commands::{CommandHistory, TimelineCommand};
+        
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string())).unwrap();
+        let mut history = CommandHistory::default();
+        
+        let add = TimelineCommand::AddClip {
+            track_id: "v1".to_string(),
+            clip: Clip::new("a".to_string(), ClipType::Video, 0.0, 5.0),
+        };
+        history.execute(&mut timeline, add).unwrap();
+        history.execute(&mut timeline, TimelineCommand::AddClip {
+            track_id: "v1".to_string(),
+            clip: Clip::new("b".to_string(), ClipType::Video, 10.0, 5.0),
+        }).unwrap();
+        history.execute(&mut timeline, TimelineCommand::MoveClip {
+            track_id: "v1".to_string(),
+            clip_id: "a".to_string(),
+            start_time: 2.0,
+        }).unwrap();
+        
+        // Moving onto another clip fails and changes nothing
+        let overlap = TimelineCommand::MoveClip { track_id: "v1".to_string(), clip_id: "a".to_string(), start_time: 8.0 };
+        assert!(history.execute(&mut timeline, overlap).is_err());
+        assert_eq!(timeline.get_track("v1").unwrap().clips[0].start_time, 2.0);
+        
+        assert_eq!(history.undo(&mut timeline).unwrap().as_deref(), Some("Move clip"));
+        assert_eq!(timeline.get_track("v1").unwrap().clips[0].start_time, 0.0);
+        assert_eq!(history.undo(&mut timeline).unwrap().as_deref(), Some("Add clip"));
+        assert_eq!(timeline.get_track("v1").unwrap().clips.len(), 1);
+        
+        assert_eq!(history.redo(&mut timeline).unwrap().as_deref(), Some("Add clip"));
+        assert_eq!(timeline.get_track("v1").unwrap().clips.len(), 2);
+        assert_eq!(history.redo_label(), Some("Move clip"));
+        
+        // A failing group is rolled back completely
+        let group = vec![
+            TimelineCommand::TrimClip { track_id: "v1".to_string(), clip_id: "a".to_string(), duration: 3.0 },
+            TimelineCommand::RemoveClip { track_id: "v1".to_string(), clip_id: "missing".to_string() },
+        ];
+        assert!(history.execute_group(&mut timeline, "Trim and remove", group).is_err());
+        assert_eq!(timeline.get_track("v1").unwrap().clips[0].duration, 5.0);
+        assert!(history.can_redo());
+    }
+    
+    #[test]
+    fn test_timeline_macros() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_commands::{CommandHistory, TimelineCommand};
+        use crate::engine::timeline_macros::{MacroArgs, TimelineMacro};
+        
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string())).unwrap();
+        timeline.add_track(Track::new("titles".to_string(), "Titles".to_string())).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("interview".to_string(), ClipType::Video, 10.0, 20.0)).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("broll".to_string(), ClipType::Video, 40.0, 10.0)).unwrap();
+        let mut history = CommandHistory::default();
+        
+        // Record: grade the interview and title it at its start
+        history.start_recording();
+        history.execute(&mut timeline, TimelineCommand::SetClipProperty {
+            track_id: "v1".to_string(),
+            clip_id: "interview".to_string(),
+            key: "grade.preset".to_string(),
+            value: Some("warm".to_string()),
+        }).unwrap();
+        history.execute(&mut timeline, TimelineCommand::AddClip {
+            track_id: "titles".to_string(),
+            clip: Clip::new("title".to_string(), ClipType::Text, 10.0, 3.0).add_property("text".to_string(), "Interview".to_string()),
+        }).unwrap();
+        history.execute(&mut timeline, TimelineCommand::TrimClip {
+            track_id: "titles".to_string(),
+            clip_id: "title".to_string(),
+            duration: 4.0,
+        }).unwrap();
+        // Undone steps aren't part of the macro
+        history.execute(&mut timeline, TimelineCommand::RemoveClip { track_id: "v1".to_string(), clip_id: "broll".to_string() }).unwrap();
+        history.undo(&mut timeline).unwrap();
+        
+        let mut recorded = TimelineMacro::from_recording("Grade and title", &mut history);
+        assert!(!history.is_recording());
+        assert_eq!(recorded.commands.len(), 3);
+        assert_eq!(recorded.parameterize("clip", "interview").unwrap(), 1);
+        assert_eq!(recorded.parameterize("caption", "Interview").unwrap(), 1);
+        recorded.make_relative(10.0);
+        
+        // Save, load and run against another clip
+        let path = std::env::temp_dir().join("aether_macro_test.json");
+        recorded.save(&path).unwrap();
+        let loaded = TimelineMacro::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, recorded);
+        
+        assert!(loaded.run(&mut history, &mut timeline, &MacroArgs::new().with("clip", "broll")).is_err());
+        let args = MacroArgs::new().with("clip", "broll").with("caption", "B-roll").at(40.0);
+        loaded.run(&mut history, &mut timeline, &args).unwrap();
+        
+        let broll = timeline.get_track("v1").unwrap().clips.iter().find(|clip| clip.id == "broll").unwrap();
+        assert_eq!(broll.properties.get("grade.preset").map(String::as_str), Some("warm"));
+        let titles = &timeline.get_track("titles").unwrap().clips;
+        assert_eq!(titles.len(), 2);
+        // The recorded title ID was taken, so the new title got a fresh one
+        let title = titles.iter().find(|clip| clip.id == "title_2").unwrap();
+        assert_eq!((title.start_time, title.duration), (40.0, 4.0));
+        assert_eq!(title.properties["text"], "B-roll");
+        
+        // The whole run is one undo step
+        assert_eq!(history.undo(&mut timeline).unwrap().as_deref(), Some("Grade and title"));
+        assert_eq!(timeline.get_track("titles").unwrap().clips.len(), 1);
+        let broll = timeline.get_track("v1").unwrap().clips.iter().find(|clip| clip.id == "broll").unwrap();
+        assert!(!broll.properties.contains_key("grade.preset"));
+    }
+
+    
+    #[test]
+    fn test_deliverable_gallery() {
+        use crate::engine::rendering::ExportOptions;
+        
+        let dir = std::env::temp_dir().join("aether_deliverables_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("final.mp4");
+        let thumbnail = |share: f64, path: &std::path::Path| -> anyhow::Result<()> {
+            if share > 0.8 {
+                anyhow::bail!("Past the last keyframe");
+            }
+            std::fs::write(path, format!("frame at {}", share))?;
+            Ok(())
+        };
+        
+        let options = ExportOptions {
+            output_path: output.clone(),
+            width: 1920,
+            height: 1080,
+            ..ExportOptions::default()
+        };
+        let mut gallery = DeliverableGallery::open(&dir.join("gallery")).unwrap();
+        assert!(gallery.last_options().is_none());
+        
+        std::fs::write(&output, b"first render").unwrap();
+        let first = gallery.record_with_thumbnails(&options, thumbnail).unwrap().clone();
+        assert_eq!(first.size, 12);
+        assert_eq!(first.checksum, file_checksum(&output).unwrap());
+        assert_eq!(first.checksum.len(), 64);
+        // The failed thumbnail is left out
+        assert_eq!(first.thumbnails.len(), 2);
+        assert!(first.thumbnails.iter().all(|path| path.exists()));
+        assert!(gallery.compare_with_previous(first.id).unwrap().is_none());
+        
+        // Same settings, different output
+        std::fs::write(&output, b"second render").unwrap();
+        let last = gallery.last_options().unwrap();
+        let second = gallery.record_with_thumbnails(&last, thumbnail).unwrap().id;
+        let comparison = gallery.compare_with_previous(second).unwrap().unwrap();
+        assert!(comparison.content_changed());
+        
+        // Different settings, same output; the output path doesn't count as a setting
+        let options = ExportOptions {
+            output_path: dir.join("final_2.mp4"),
+            crf: 18,
+            ..last
+        };
+        std::fs::copy(&output, &options.output_path).unwrap();
+        let third = gallery.record_with_thumbnails(&options, thumbnail).unwrap().id;
+        let comparison = gallery.compare(second, third).unwrap();
+        assert!(comparison.identical_output);
+        assert_eq!(comparison.changed_options, vec!["crf".to_string()]);
+        assert!(!comparison.content_changed());
+        
+        // The gallery persists, and removing a deliverable removes its thumbnails
+        gallery.remove(first.id).unwrap();
+        assert!(first.thumbnails.iter().all(|path| !path.exists()));
+        let reopened = DeliverableGallery::open(&dir.join("gallery")).unwrap();
+        let ids: Vec<u64> = reopened.deliverables().iter().map(|deliverable| deliverable.id).collect();
+        assert_eq!(ids, vec![second, third]);
+        assert_eq!(reopened.last_options().unwrap().crf, 18);
+        assert!(reopened.compare(first.id, third).is_err());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    
+    #[test]
+    fn test_watch_and_render() {
+        use crate::engine::rendering::ExportOptions;
+        use crate::engine::watch_render::{
+            changed_ranges, script_loader, ProjectWatcher, TimeRange, WatchOutput, WatchSession,
+        };
+        use crate::modules::scripting::ScriptLimits;
+        use std::sync::{Arc, Mutex};
+        use std::time::{Instant, SystemTime};
+        
+        let dir = std::env::temp_dir().join("aether_watch_render_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let project = dir.join("project.rhai");
+        let script = |broll_start: f64, grade: &str| format!(
+            r#"add_track("v1", "Video 1");
+            add_clip("v1", #{{ type: "video", id: "intro", start: 0.0, duration: 10.0 }});
+            add_clip("v1", #{{ type: "video", id: "broll", start: {}, duration: 10.0, properties: #{{ "grade.preset": "{}" }} }});
+            add_marker(5.0, "Note");"#,
+            broll_start, grade
+        );
+        // Rewrites can land within the file system's timestamp granularity
+        let mut version = 0;
+        let mut write = |source: String| {
+            version += 1;
+            std::fs::write(&project, source).unwrap();
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + version);
+            std::fs::File::options().write(true).open(&project).unwrap().set_modified(modified).unwrap();
+        };
+        
+        let outputs = vec![
+            WatchOutput::new("master", ExportOptions { output_path: dir.join("master.mp4"), ..ExportOptions::default() }),
+            WatchOutput::new("teaser", ExportOptions { output_path: dir.join("teaser.mp4"), ..ExportOptions::default() })
+                .with_range(0.0, 15.0),
+        ];
+        let mut session = WatchSession::new(&project, script_loader(ScriptLimits::default()), outputs.clone());
+        session.set_settle_time(Duration::ZERO);
+        assert!(session.poll().is_err());
+        
+        // The first load renders everything
+        write(script(30.0, "warm"));
+        let requests = session.poll().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.full));
+        assert_eq!(requests[1].ranges, vec![TimeRange::new(0.0, 15.0)]);
+        assert!(session.poll().unwrap().is_empty());
+        
+        // Regrading the b-roll only touches the master, and only where the b-roll is
+        write(script(30.0, "cool"));
+        let requests = session.poll().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].output, "master");
+        assert_eq!(requests[0].options.output_path, dir.join("master.mp4"));
+        assert_eq!(requests[0].ranges, vec![TimeRange::new(30.0, 40.0)]);
+        assert!(!requests[0].full);
+        
+        // Moving it dirties where it was and where it is now
+        write(script(12.0, "cool"));
+        let requests = session.poll().unwrap();
+        assert_eq!(requests[0].ranges, vec![TimeRange::new(12.0, 22.0), TimeRange::new(30.0, 40.0)]);
+        assert_eq!(requests[1].ranges, vec![TimeRange::new(12.0, 15.0)]);
+        
+        // A broken version is reported once and the last good one stays the base
+        write("add_clip(".to_string());
+        assert!(session.poll().is_err());
+        assert!(session.poll().unwrap().is_empty());
+        assert_eq!(session.timeline().unwrap().get_track("v1").unwrap().clips.len(), 2);
+        write(script(12.0, "cool").replace("Note", "Renamed"));
+        assert!(session.poll().unwrap().is_empty());
+        
+        let timeline = session.timeline().unwrap();
+        assert!(changed_ranges(timeline, timeline).is_empty());
+        
+        // The watcher renders on its own thread
+        let rendered = Arc::new(Mutex::new(Vec::new()));
+        let mut session = WatchSession::new(&project, script_loader(ScriptLimits::default()), outputs);
+        session.set_settle_time(Duration::ZERO);
+        let handler = {
+            let rendered = rendered.clone();
+            Arc::new(move |request: &crate::engine::watch_render::RenderRequest| {
+                rendered.lock().unwrap().push(request.output.clone());
+                Ok(())
+            })
+        };
+        let mut watcher = ProjectWatcher::start(session, Duration::from_millis(10), handler);
+        let started = Instant::now();
+        while watcher.status().renders < 2 && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        watcher.stop();
+        assert_eq!(*rendered.lock().unwrap(), vec!["master".to_string(), "teaser".to_string()]);
+        assert!(watcher.status().last_error.is_none());
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    
+    #[test]
+    fn test_simulated_export() {
+        use crate::engine::rendering::ExportOptions;
+        use crate::engine::simulation::{MockPipeline, TestHarness};
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        
+        // The export lasts until the last clip ends, not the configured length
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string())).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("a".to_string(), ClipType::Video, 0.0, 4.0)).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("b".to_string(), ClipType::Video, 4.0, 6.0)).unwrap();
+        let pipeline = MockPipeline::for_timeline(&timeline);
+        assert_eq!(pipeline.duration(), 10.0);
+        
+        let options = ExportOptions {
+            container_format: ContainerFormat::Mkv,
+            video_format: VideoFormat::H265,
+            audio_format: AudioFormat::Opus,
+            frame_rate: 25.0,
+            width: 1280,
+            height: 720,
+            audio_bitrate: 0,
+            ..ExportOptions::default()
+        };
+        let harness = TestHarness::new(options.clone());
+        let export = harness.run(&pipeline);
+        assert!(export.succeeded());
+        assert_eq!(export.plan.container_caps, "video/x-matroska");
+        assert_eq!(export.plan.video_caps, "video/x-h265");
+        assert_eq!(export.plan.audio_caps, "audio/x-opus");
+        assert_eq!((export.plan.video_bitrate, export.plan.audio_bitrate), (Some(2_000_000), None));
+        assert_eq!(export.plan.size, Some((1280, 720)));
+        
+        // One update at the start, one per second and one at the end
+        assert_eq!(export.updates.len(), 11);
+        assert_eq!(export.updates[0].total_frames, 250);
+        assert_eq!(export.updates[5].current_frame, 125);
+        assert_eq!(export.updates[5].percent, 50.0);
+        assert!(export.updates.windows(2).all(|pair| pair[0].percent <= pair[1].percent));
+        assert_eq!(export.progress.current_frame, 250);
+        
+        // Hardware encoding only offers fixed H.264 and H.265 profiles
+        let hardware = TestHarness::new(ExportOptions { hardware_acceleration: true, video_format: VideoFormat::Vp9, ..options.clone() });
+        assert_eq!(hardware.plan().video_caps, "video/x-h264, profile=high");
+        
+        // A transient error is retried from the start
+        let flaky = MockPipeline::new(10.0).fail_at(1, 6.5, ErrorClass::Transient, "Resource busy");
+        let export = harness.run(&flaky);
+        assert!(export.succeeded());
+        assert_eq!(export.progress.attempt, 2);
+        assert_eq!(export.retry_delays, vec![Duration::from_secs(2)]);
+        let retry = export.updates.iter().position(|update| update.attempt == 2).unwrap();
+        assert_eq!(export.updates[retry].current_frame, 0);
+        assert_eq!(export.updates[retry - 1].current_frame, 150);
+        
+        // Failing every attempt gives a report of each
+        let broken = (1..=3).fold(MockPipeline::new(10.0), |pipeline, attempt| {
+            pipeline.fail_at(attempt, attempt as f64, ErrorClass::Encoder, "Encoder error")
+        });
+        let export = harness.run(&broken);
+        assert!(!export.succeeded());
+        assert_eq!(export.retry_delays, vec![Duration::from_secs(2), Duration::from_secs(4)]);
+        let failure = export.progress.failure.unwrap();
+        assert_eq!(failure.attempts.iter().map(|attempt| attempt.frame).collect::<Vec<_>>(), vec![25, 50, 75]);
+        assert_eq!(failure.frame_range, Some((50, 75)));
+        
+        // Stalls and unrecoverable errors aren't retried
+        let export = harness.run(&MockPipeline::new(10.0).stall_at(1, 3.5));
+        let failure = export.progress.failure.unwrap();
+        assert_eq!(failure.class, ErrorClass::Stalled);
+        assert_eq!(failure.frame_range, Some((75, 75)));
+        let export = TestHarness::new(options)
+            .with_retry_policy(RetryPolicy::none())
+            .run(&MockPipeline::new(10.0).fail_at(1, 2.0, ErrorClass::Transient, "Resource busy"));
+        assert!(export.retry_delays.is_empty());
+        assert!(export.progress.error.unwrap().contains("Resource busy"));
+    }
+    
+    #[test]
+    fn test_deterministic_options() {
+        use crate::engine::rendering::{ExportOptions, DETERMINISTIC_THREADS};
+        
+        let options = ExportOptions {
+            threads: 8,
+            ..ExportOptions::default()
+        };
+        assert!(!options.deterministic);
+        assert_eq!(options.encoder_threads(), 8);
+        
+        let deterministic = ExportOptions {
+            deterministic: true,
+            ..options.clone()
+        };
+        assert_eq!(deterministic.encoder_threads(), DETERMINISTIC_THREADS);
+        
+        // Options saved before the flag existed load as non-deterministic
+        let mut saved = serde_json::to_value(&deterministic).unwrap();
+        saved.as_object_mut().unwrap().remove("deterministic");
+        let loaded: ExportOptions = serde_json::from_value(saved).unwrap();
+        assert!(!loaded.deterministic);
+        assert_eq!(loaded.threads, 8);
+    }
+    
+    #[test]
+    fn test_visual_regression() {
+        use crate::engine::editing::PreviewFrame;
+        use crate::engine::timeline_backend::{TimelineBackend, TimelineBackendKind};
+        use crate::engine::visual_regression::*;
+        use std::sync::{Arc, Mutex};
+        
+        /// A bright square moving right one step a second, on a dark background
+        struct SquareBackend {
+            /// Added to every pixel
+            brightness: i32,
+            /// Whether to draw a diagonal split instead of the square
+            changed: bool,
+        }
+        
+        impl TimelineBackend for SquareBackend {
+            fn kind(&self) -> TimelineBackendKind {
+                TimelineBackendKind::Lightweight
+            }
+            
+            fn load(&mut self, _timeline: Arc<Mutex<crate::engine::timeline::Timeline>>) -> Result<(), EditingError> {
+                Ok(())
+            }
+            
+            fn render_frame(&mut self, time: f64) -> Result<PreviewFrame, EditingError> {
+                let (width, height) = (64, 48);
+                let left = 8 + time as u32 * 12;
+                let mut data = Vec::new();
+                for y in 0..height {
+                    for x in 0..width {
+                        let inside = if self.changed {
+                            x + y > 48
+                        } else {
+                            (left..left + 16).contains(&x) && (12..36).contains(&y)
+                        };
+                        let value = (if inside { 200 } else { 40 } + self.brightness).clamp(0, 255) as u8;
+                        data.extend_from_slice(&[value, value, value, 255]);
+                    }
+                }
+                Ok(PreviewFrame { width, height, data, pts: 0, duration: 0, format: "RGBA".to_string() })
+            }
+            
+            fn shutdown(&mut self) -> Result<(), EditingError> {
+                Ok(())
+            }
+        }
+        
+        let times = [0.0, 1.0, 2.0];
+        let baseline = Baseline::capture(&mut SquareBackend { brightness: 0, changed: false }, &times).unwrap();
+        assert_ne!(baseline.frames[0].hash, baseline.frames[1].hash);
+        
+        // A brightness shift is within tolerance, a different picture isn't
+        let report = baseline.check(&mut SquareBackend { brightness: 10, changed: false }, DEFAULT_TOLERANCE).unwrap();
+        assert!(report.passed(), "{}", report.summary());
+        let report = baseline.check(&mut SquareBackend { brightness: 0, changed: true }, DEFAULT_TOLERANCE).unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 3);
+        assert!(report.summary().starts_with("3 of 3 frames changed"));
+        
+        // Flat frames hash alike whatever their size
+        let flat = FrameHash::from_rgba(2, 2, &[9; 16]).unwrap();
+        assert_eq!(flat, FrameHash::from_rgba(64, 48, &vec![9; 64 * 48 * 4]).unwrap());
+        assert!(FrameHash::from_rgba(2, 2, &[0; 8]).is_err());
+        
+        // The first check records the baseline, later ones compare against it
+        let path = std::env::temp_dir().join("aether_visual_regression_test.json");
+        let _ = std::fs::remove_file(&path);
+        assert!(check_baseline(&mut SquareBackend { brightness: 0, changed: false }, &path, &times, DEFAULT_TOLERANCE).unwrap().passed());
+        assert_eq!(Baseline::load(&path).unwrap(), baseline);
+        let report = check_baseline(&mut SquareBackend { brightness: 0, changed: true }, &path, &times, DEFAULT_TOLERANCE).unwrap();
+        assert!(!report.passed());
+        assert!(check_baseline(&mut SquareBackend { brightness: 0, changed: false }, &path, &[0.0], DEFAULT_TOLERANCE).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_three_point_edits() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_edits::{EditPoints, SourcePatch};
+        
+        let v1 = SourcePatch::video("v1");
+        
+        let source = |id: &str| Clip::new(id.to_string(), ClipType::Video, 0.0, 0.0).with_source("/media/take.mov".to_string());
+        let layout = |timeline: &Timeline, track: &str| -> Vec<(String, f64, f64, f64)> {
+            let mut clips: Vec<_> = timeline.get_track(track).unwrap().clips.iter()
+                .map(|clip| (clip.id.clone(), clip.start_time, clip.duration, clip.in_point()))
+                .collect();
+            clips.sort_by(|a, b| a.1.total_cmp(&b.1));
+            clips
+        };
+        let entry = |id: &str, start: f64, duration: f64, in_point: f64| (id.to_string(), start, duration, in_point);
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 20.0 });
+        for id in ["v1", "a1", "music"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        timeline.add_clip_to_track("v1", Clip::new("wide".to_string(), ClipType::Video, 0.0, 10.0)).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("close".to_string(), ClipType::Video, 10.0, 5.0)).unwrap();
+        timeline.add_clip_to_track("a1", Clip::new("sound".to_string(), ClipType::Audio, 0.0, 15.0)).unwrap();
+        timeline.add_clip_to_track("music", Clip::new("score".to_string(), ClipType::Audio, 0.0, 20.0)).unwrap();
+        timeline.get_track_mut("music").unwrap().is_locked = true;
+        
+        // Insert splits and ripples every unlocked track
+        timeline.insert_edit(&v1, &source("insert"), &EditPoints::new(30.0, 33.0, 4.0)).unwrap();
+        assert_eq!(layout(&timeline, "v1"), vec![
+            entry("wide", 0.0, 4.0, 0.0),
+            entry("insert", 4.0, 3.0, 30.0),
+            entry("wide_2", 7.0, 6.0, 4.0),
+            entry("close", 13.0, 5.0, 0.0),
+        ]);
+        assert_eq!(layout(&timeline, "a1"), vec![entry("sound", 0.0, 4.0, 0.0), entry("sound_2", 7.0, 11.0, 4.0)]);
+        assert_eq!(layout(&timeline, "music"), vec![entry("score", 0.0, 20.0, 0.0)]);
+        
+        // Overwrite backtimed from the record out point, over the cut between two clips
+        let points = EditPoints { source_in: Some(60.0), record_in: Some(12.0), record_out: Some(14.0), ..Default::default() };
+        timeline.overwrite_edit(&v1, &source("over"), &points).unwrap();
+        assert_eq!(layout(&timeline, "v1"), vec![
+            entry("wide", 0.0, 4.0, 0.0),
+            entry("insert", 4.0, 3.0, 30.0),
+            entry("wide_2", 7.0, 5.0, 4.0),
+            entry("over", 12.0, 2.0, 60.0),
+            entry("close", 14.0, 4.0, 1.0),
+        ]);
+        let points = EditPoints { source_out: Some(50.0), record_in: Some(0.0), record_out: Some(1.0), ..Default::default() };
+        timeline.overwrite_edit(&v1, &source("backtimed"), &points).unwrap();
+        assert_eq!(layout(&timeline, "v1")[0], entry("backtimed", 0.0, 1.0, 49.0));
+        assert_eq!(layout(&timeline, "v1")[1], entry("wide", 1.0, 3.0, 1.0));
+        
+        // Fit to fill plays 4 seconds of source over the 2 second gap left by "over"
+        let points = EditPoints { source_in: Some(0.0), source_out: Some(4.0), record_in: Some(12.0), record_out: Some(14.0) };
+        timeline.fit_to_fill_edit(&v1, &source("fit"), &points).unwrap();
+        let fit = timeline.get_track("v1").unwrap().clips.iter().find(|clip| clip.id == "fit").unwrap();
+        assert_eq!((fit.start_time, fit.duration, fit.speed(), fit.out_point()), (12.0, 2.0, 2.0, 4.0));
+        assert!(!timeline.get_track("v1").unwrap().clips.iter().any(|clip| clip.id == "over"));
+        
+        // Replace keeps the clip's place and syncs source and record at the given frames
+        timeline.replace_edit("v1", &source("alt"), 102.0, 5.0, None).unwrap();
+        let alt = timeline.get_track("v1").unwrap().clips.iter().find(|clip| clip.id == "alt").unwrap();
+        assert_eq!((alt.start_time, alt.duration, alt.in_point()), (4.0, 3.0, 101.0));
+        assert!(timeline.replace_edit("v1", &source("early"), 0.5, 5.0, None).is_err());
+        assert!(timeline.replace_edit("v1", &source("short"), 102.0, 5.0, Some(103.0)).is_err());
+        
+        // Bad points, locked tracks and taken IDs change nothing
+        let before = layout(&timeline, "v1");
+        assert!(timeline.overwrite_edit(&v1, &source("x"), &EditPoints { source_in: Some(0.0), ..Default::default() }).is_err());
+        assert!(timeline.overwrite_edit(&v1, &source("x"), &EditPoints { record_out: Some(3.0), ..EditPoints::new(0.0, 2.0, 0.0) }).is_err());
+        assert!(timeline.fit_to_fill_edit(&v1, &source("x"), &EditPoints::new(0.0, 2.0, 0.0)).is_err());
+        assert!(timeline.insert_edit(&SourcePatch::video("music"), &source("x"), &EditPoints::new(0.0, 2.0, 0.0)).is_err());
+        assert!(timeline.insert_edit(&v1, &source("wide"), &EditPoints::new(0.0, 2.0, 0.0)).is_err());
+        assert_eq!(layout(&timeline, "v1"), before);
+        assert_eq!(timeline.duration(), 20.0);
+        
+        // Edits past the end grow the timeline
+        timeline.overwrite_edit(&v1, &source("tail"), &EditPoints::new(0.0, 5.0, 19.0)).unwrap();
+        assert_eq!(timeline.duration(), 24.0);
+    }
+    
+    #[test]
+    fn test_source_patching() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_edits::{EditPoints, SourceChannel, SourcePatch, AUDIO_CHANNEL_PROPERTY};
+        use crate::modules::audio_sync::LINK_GROUP_PROPERTY;
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 10.0 });
+        for id in ["v1", "a1", "a2", "a3"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        timeline.add_clip_to_track("a3", Clip::new("room".to_string(), ClipType::Audio, 0.0, 10.0)).unwrap();
+        
+        let mut patch = SourcePatch::video("v1")
+            .with(SourceChannel::Audio(1), "a1")
+            .with(SourceChannel::Audio(2), "a2");
+        // Re-patching a channel moves it; unpatched channels are left out
+        patch.patch(SourceChannel::Audio(2), "a3");
+        patch.unpatch(SourceChannel::Audio(1));
+        assert_eq!(patch.track(SourceChannel::Audio(2)), Some("a3"));
+        assert_eq!(patch.track(SourceChannel::Audio(1)), None);
+        
+        let source = Clip::new("take".to_string(), ClipType::Video, 0.0, 0.0).with_source("/media/take.mov".to_string());
+        let placed = timeline.insert_edit(&patch, &source, &EditPoints::new(5.0, 7.0, 4.0)).unwrap();
+        assert_eq!(placed, vec!["take".to_string(), "take_a2".to_string()]);
+        
+        let audio = timeline.get_track("a3").unwrap().clips.iter().find(|clip| clip.id == "take_a2").unwrap();
+        assert_eq!((audio.clip_type.clone(), audio.start_time, audio.duration, audio.in_point()), (ClipType::Audio, 4.0, 2.0, 5.0));
+        assert_eq!(audio.properties[AUDIO_CHANNEL_PROPERTY], "2");
+        let video = timeline.get_track("v1").unwrap().clips.iter().find(|clip| clip.id == "take").unwrap();
+        assert_eq!(video.properties[LINK_GROUP_PROPERTY], audio.properties[LINK_GROUP_PROPERTY]);
+        assert!(timeline.get_track("a1").unwrap().clips.is_empty());
+        
+        // The insert rippled the other audio on A3
+        let room: Vec<(String, f64)> = timeline.get_track("a3").unwrap().clips.iter()
+            .filter(|clip| clip.id.starts_with("room"))
+            .map(|clip| (clip.id.clone(), clip.start_time))
+            .collect();
+        assert_eq!(room, vec![("room".to_string(), 0.0), ("room_2".to_string(), 6.0)]);
+        
+        // Two channels on one track, or nothing patched, is refused
+        let doubled = SourcePatch::audio(&["a1", "a1"]);
+        let other = Clip::new("other".to_string(), ClipType::Audio, 0.0, 0.0);
+        assert!(timeline.overwrite_edit(&doubled, &other, &EditPoints::new(0.0, 1.0, 0.0)).is_err());
+        assert!(timeline.overwrite_edit(&SourcePatch::new(), &other, &EditPoints::new(0.0, 1.0, 0.0)).is_err());
+        
+        // Audio-only overwrite
+        let placed = timeline.overwrite_edit(&SourcePatch::audio(&["a1", "a2"]), &other, &EditPoints::new(0.0, 1.0, 0.0)).unwrap();
+        assert_eq!(placed, vec!["other_a1".to_string(), "other_a2".to_string()]);
+    }
+    
+    #[test]
+    fn test_find_on_timeline() {
+        use crate::engine::timeline::{Clip, ClipType, Marker, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_search::{SearchMatch, CLIP_NAME_PROPERTY};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        for id in ["v1", "v2"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        timeline.add_clip_to_track("v1", Clip::new("clip_1".to_string(), ClipType::Video, 0.0, 5.0)
+            .with_source("/media/Interview_A.mov".to_string())).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("clip_2".to_string(), ClipType::Video, 12.0, 5.0)
+            .with_source("/media/broll.mov".to_string())
+            .add_property(CLIP_NAME_PROPERTY.to_string(), "Interview cutaway".to_string())).unwrap();
+        timeline.add_clip_to_track("v2", Clip::new("clip_3".to_string(), ClipType::Video, 6.0, 4.0)
+            .with_source("/media/city.mov".to_string())
+            .add_property("effect.gaussian_blur.radius".to_string(), "4".to_string())).unwrap();
+        let mut marker = Marker::new("m1".to_string(), 20.0, "Music cue".to_string());
+        marker.note = "Start the interview theme".to_string();
+        timeline.add_marker(marker).unwrap();
+        
+        let found: Vec<(String, f64, SearchMatch)> = timeline.find("INTERVIEW").into_iter()
+            .map(|result| (result.id, result.time, result.matched))
+            .collect();
+        assert_eq!(found, vec![
+            ("clip_1".to_string(), 0.0, SearchMatch::Source),
+            ("clip_2".to_string(), 12.0, SearchMatch::Name),
+            ("m1".to_string(), 20.0, SearchMatch::Marker),
+        ]);
+        
+        let blurred = timeline.find("blur");
+        assert_eq!(blurred.len(), 1);
+        assert_eq!((blurred[0].track_id.as_deref(), blurred[0].matched), (Some("v2"), SearchMatch::Effect));
+        // Effect parameters are not effect types
+        assert!(timeline.find("radius").is_empty());
+        assert!(timeline.find("  ").is_empty());
+        
+        // Seek to the second hit
+        let result = timeline.seek_to_result("interview", 1).unwrap();
+        assert_eq!(result.id, "clip_2");
+        assert_eq!(timeline.current_time(), 12.0);
+        assert!(timeline.seek_to_result("interview", 3).is_err());
+        assert_eq!(timeline.current_time(), 12.0);
+    }
+    
+    #[test]
+    fn test_edit_point_navigation_and_match_frame() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track, SOURCE_FPS_PROPERTY};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 20.0 });
+        for id in ["v1", "a1"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        let mut wide = Clip::new("wide".to_string(), ClipType::Video, 0.0, 4.0).with_source("/media/wide.mov".to_string());
+        wide.set_in_point(10.0);
+        let mut close = Clip::new("close".to_string(), ClipType::Video, 4.0, 6.0)
+            .with_source("/media/close.mov".to_string())
+            .add_property(SOURCE_FPS_PROPERTY.to_string(), "50".to_string());
+        close.set_in_point(2.0);
+        close.set_speed(2.0);
+        timeline.add_clip_to_track("v1", wide).unwrap();
+        timeline.add_clip_to_track("v1", close).unwrap();
+        timeline.add_clip_to_track("a1", Clip::new("sound".to_string(), ClipType::Audio, 1.5, 5.0)).unwrap();
+        
+        assert_eq!(timeline.edit_points(&["v1"]), vec![0.0, 4.0, 10.0]);
+        assert_eq!(timeline.edit_points(&[]), vec![0.0, 1.5, 4.0, 6.5, 10.0]);
+        
+        assert_eq!(timeline.next_edit_point(&["v1"]), Some(4.0));
+        assert_eq!(timeline.next_edit_point(&[]), Some(6.5));
+        assert_eq!(timeline.previous_edit_point(&["v1"]), Some(4.0));
+        assert_eq!(timeline.previous_edit_point(&["a1"]), Some(1.5));
+        assert_eq!(timeline.previous_edit_point(&["v1"]), Some(0.0));
+        assert_eq!(timeline.previous_edit_point(&["v1"]), None);
+        assert_eq!(timeline.current_time(), 0.0);
+        
+        // 1.01s into "close" at double speed is 2.02s past its in point, frame 201 at 50fps
+        timeline.seek(5.01).unwrap();
+        let matched = timeline.match_frame(&["v1"]).unwrap();
+        assert_eq!((matched.clip_id.as_str(), matched.frame, matched.fps), ("close", 201, 50.0));
+        assert!((matched.source_time - 4.02).abs() < 1e-9);
+        assert_eq!((matched.in_point, matched.out_point), (2.0, 14.0));
+        assert_eq!(matched.source_path.as_deref(), Some("/media/close.mov"));
+        
+        // Tracks are tried in the order given
+        assert_eq!(timeline.match_frame(&["a1", "v1"]).unwrap().clip_id, "sound");
+        timeline.seek(12.0).unwrap();
+        assert!(timeline.match_frame(&[]).is_none());
+    }
+    
+    #[test]
+    fn test_gaps_and_flash_frames() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_validation::{DiagnosticKind, Severity};
+        
+        // At 25fps a frame is 0.04s
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        for id in ["v1", "a1"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        let track = timeline.get_track_mut("v1").unwrap();
+        track.clips.push(Clip::new("a".to_string(), ClipType::Video, 0.0, 4.0));
+        track.clips.push(Clip::new("b".to_string(), ClipType::Video, 4.04, 3.0));
+        track.clips.push(Clip::new("flash".to_string(), ClipType::Video, 7.0, 0.08));
+        track.clips.push(Clip::new("c".to_string(), ClipType::Video, 7.08, 2.0));
+        // A real pause is not a gap to fix, and neither is the lead-in
+        track.clips.push(Clip::new("d".to_string(), ClipType::Video, 12.0, 2.0));
+        track.clips.push(Clip::new("title".to_string(), ClipType::Text, 14.0, 0.04));
+        let track = timeline.get_track_mut("a1").unwrap();
+        track.clips.push(Clip::new("music".to_string(), ClipType::Audio, 1.0, 5.0));
+        track.clips.push(Clip::new("music_2".to_string(), ClipType::Audio, 6.08, 5.0));
+        
+        let problems = timeline.find_gaps_and_flash_frames();
+        assert!(problems.iter().all(|problem| problem.severity == Severity::Warning));
+        let found: Vec<(&str, &str, DiagnosticKind)> = problems.iter()
+            .map(|problem| (problem.track_id.as_str(), problem.clip_id.as_str(), problem.kind.clone()))
+            .collect();
+        assert_eq!(found, vec![
+            ("a1", "music_2", DiagnosticKind::Gap { previous_clip_id: "music".to_string(), start: 6.0, frames: 2 }),
+            ("v1", "b", DiagnosticKind::Gap { previous_clip_id: "a".to_string(), start: 4.0, frames: 1 }),
+            ("v1", "flash", DiagnosticKind::FlashFrame { start: 7.0, frames: 2 }),
+        ]);
+        assert_eq!(problems[1].to_string(), "b on v1: 1 frame gap after a at 4.000s");
+        
+        // Validation reports them as warnings, which don't block export
+        let report = timeline.validate();
+        assert!(report.warnings().any(|d| matches!(d.kind, DiagnosticKind::FlashFrame { .. })));
+        assert!(!report.errors().any(|d| matches!(d.kind, DiagnosticKind::Gap { .. } | DiagnosticKind::FlashFrame { .. })));
+    }
+    
+    #[test]
+    fn test_track_view_settings() {
+        use crate::engine::timeline::{Timeline, TimelineConfig, Track, TrackView};
+        use crate::engine::timeline_diff::{diff_projects, TrackChange};
+        
+        let timeline_with_track = || {
+            let mut timeline = Timeline::new(TimelineConfig::default());
+            timeline.add_track(Track::new("a1".to_string(), "Dialogue".to_string())).unwrap();
+            timeline
+        };
+        let before = timeline_with_track();
+        let mut timeline = timeline_with_track();
+        
+        let view = &mut timeline.get_track_mut("a1").unwrap().view;
+        assert!(view.show_waveform);
+        view.height = Some(96);
+        view.color = Some("#3a7bd5".to_string());
+        assert_eq!(view.set_extension("meter", serde_json::json!({ "visible": true })), None);
+        assert_eq!(view.extension("meter").unwrap()["visible"], true);
+        
+        // View changes show up in diffs but don't touch the render
+        assert_eq!(diff_projects(&before, &timeline).tracks, vec![TrackChange::ViewChanged("a1".to_string())]);
+        
+        let view = timeline.get_track("a1").unwrap().view.clone();
+        let saved: TrackView = serde_json::from_str(&serde_json::to_string(&view).unwrap()).unwrap();
+        assert_eq!(saved, view);
+        // Missing settings load as defaults
+        let old: TrackView = serde_json::from_str(r#"{ "collapsed": true }"#).unwrap();
+        assert_eq!(old, TrackView { collapsed: true, ..TrackView::default() });
+    }
+    
+    #[test]
+    fn test_ripple_keeps_linked_clips_in_sync() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, TimelineError, Track};
+        use crate::engine::timeline_edits::{EditPoints, SourcePatch};
+        use crate::engine::timeline_ripple::LinkConflictReason;
+        use crate::modules::audio_sync::LINK_GROUP_PROPERTY;
+        
+        let linked = |id: &str, clip_type: ClipType, start: f64, duration: f64, group: &str| {
+            Clip::new(id.to_string(), clip_type, start, duration)
+                .add_property(LINK_GROUP_PROPERTY.to_string(), group.to_string())
+        };
+        let starts = |timeline: &Timeline, track: &str| -> Vec<(String, f64)> {
+            timeline.get_track(track).unwrap().clips.iter().map(|clip| (clip.id.clone(), clip.start_time)).collect()
+        };
+        let entry = |id: &str, start: f64| (id.to_string(), start);
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        for id in ["v1", "a1", "a2"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        timeline.add_clip_to_track("v1", linked("wide", ClipType::Video, 0.0, 5.0, "take_1")).unwrap();
+        timeline.add_clip_to_track("v1", linked("close", ClipType::Video, 5.0, 5.0, "take_2")).unwrap();
+        timeline.add_clip_to_track("a1", linked("wide_audio", ClipType::Audio, 0.0, 5.0, "take_1")).unwrap();
+        timeline.add_clip_to_track("a1", linked("close_audio", ClipType::Audio, 5.0, 5.0, "take_2")).unwrap();
+        // A J-cut: the room tone of "close" starts under "wide"
+        timeline.add_clip_to_track("a2", linked("close_room", ClipType::Audio, 4.0, 6.0, "take_2")).unwrap();
+        timeline.get_track_mut("a2").unwrap().is_locked = true;
+        
+        // The room tone of "close" is locked, so rippling v1 alone would desync it
+        match timeline.ripple(&["v1"], 5.0, 2.0) {
+            Err(TimelineError::LinkConflict(conflicts)) => {
+                assert_eq!(conflicts.len(), 1);
+                assert_eq!((conflicts[0].clip_id.as_str(), conflicts[0].linked_clip_id.as_str()), ("close_room", "close"));
+                assert_eq!(conflicts[0].reason, LinkConflictReason::LockedTrack);
+            },
+            other => panic!("Expected a link conflict, got {:?}", other),
+        }
+        assert_eq!(starts(&timeline, "v1"), vec![entry("wide", 0.0), entry("close", 5.0)]);
+        
+        // Unlocked, linked clips on other tracks follow, including the J-cut
+        timeline.get_track_mut("a2").unwrap().is_locked = false;
+        let moved = timeline.ripple(&["v1"], 5.0, 2.0).unwrap();
+        assert_eq!(moved, vec!["close", "close_audio", "close_room"]);
+        assert_eq!(starts(&timeline, "a1"), vec![entry("wide_audio", 0.0), entry("close_audio", 7.0)]);
+        assert_eq!(starts(&timeline, "a2"), vec![entry("close_room", 6.0)]);
+        
+        // A linked clip that would run into a clip staying put is a conflict too
+        timeline.add_clip_to_track("a2", Clip::new("music".to_string(), ClipType::Audio, 12.5, 5.0)).unwrap();
+        let conflict = timeline.ripple(&["v1"], 7.0, 1.0).unwrap_err();
+        assert!(matches!(conflict, TimelineError::LinkConflict(ref c)
+            if c[0].reason == LinkConflictReason::Overlap { other_clip_id: "music".to_string() }));
+        assert_eq!(starts(&timeline, "a2"), vec![entry("close_room", 6.0), entry("music", 12.5)]);
+        
+        // Inserts ripple through the same checks
+        timeline.get_track_mut("a2").unwrap().is_locked = true;
+        let source = Clip::new("insert".to_string(), ClipType::Video, 0.0, 0.0);
+        assert!(timeline.insert_edit(&SourcePatch::video("v1"), &source, &EditPoints::new(0.0, 1.0, 8.0)).is_err());
+        assert!(!timeline.get_track("v1").unwrap().clips.iter().any(|clip| clip.id == "insert"));
+        timeline.get_track_mut("a2").unwrap().is_locked = false;
+        
+        // Ripple delete takes the linked audio with it and closes the gap on both tracks
+        let removed = timeline.ripple_delete("v1", "wide").unwrap();
+        let removed: Vec<&str> = removed.iter().map(|clip| clip.id.as_str()).collect();
+        assert_eq!(removed, vec!["wide", "wide_audio"]);
+        assert_eq!(starts(&timeline, "v1"), vec![entry("close", 2.0)]);
+        assert_eq!(starts(&timeline, "a1"), vec![entry("close_audio", 2.0)]);
+        // Unlinked clips on other tracks stay put
+        assert_eq!(starts(&timeline, "a2"), vec![entry("close_room", 1.0), entry("music", 12.5)]);
+    }
+    
+    #[test]
+    fn test_paste_attributes() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_attributes::{AttributeMask, ClipAttributes};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        for id in ["v1", "v2"] {
+            timeline.add_track(Track::new(id.to_string(), id.to_string())).unwrap();
+        }
+        let mut graded = Clip::new("graded".to_string(), ClipType::Video, 0.0, 5.0)
+            .add_property("effect.blur.radius".to_string(), "3".to_string())
+            .add_property("transform.scale".to_string(), "1.2".to_string())
+            .add_property("grade.preset".to_string(), "warm".to_string())
+            .add_property("audio.volume".to_string(), "0.5".to_string())
+            .add_property("in_point".to_string(), "12".to_string());
+        graded.set_speed(2.0);
+        timeline.add_clip_to_track("v1", graded).unwrap();
+        timeline.add_clip_to_track("v1", Clip::new("plain".to_string(), ClipType::Video, 5.0, 5.0)
+            .add_property("effect.glow.amount".to_string(), "1".to_string())
+            .add_property("transform.scale".to_string(), "0.8".to_string())).unwrap();
+        timeline.add_clip_to_track("v2", Clip::new("other".to_string(), ClipType::Video, 0.0, 5.0)).unwrap();
+        
+        let attributes = timeline.copy_attributes("graded").unwrap();
+        assert_eq!(attributes.properties.len(), 5);
+        assert!(!attributes.properties.contains_key("in_point"));
+        
+        // Effects and grade only: the old effect is replaced and the transform kept
+        let mask = AttributeMask { effects: true, grade: true, ..AttributeMask::none() };
+        timeline.paste_attributes(&["plain", "other"], &attributes, &mask).unwrap();
+        let plain = &timeline.get_track("v1").unwrap().clips[1];
+        assert_eq!(plain.properties.get("effect.blur.radius").map(String::as_str), Some("3"));
+        assert!(!plain.properties.contains_key("effect.glow.amount"));
+        assert_eq!(plain.properties.get("transform.scale").map(String::as_str), Some("0.8"));
+        assert_eq!(plain.speed(), 1.0);
+        let other = &timeline.get_track("v2").unwrap().clips[0];
+        assert_eq!(other.properties.get("grade.preset").map(String::as_str), Some("warm"));
+        
+        // Everything, keeping the clip's own source range and duration
+        timeline.paste_attributes(&["other"], &attributes, &AttributeMask::all()).unwrap();
+        let other = &timeline.get_track("v2").unwrap().clips[0];
+        assert_eq!((other.speed(), other.duration, other.in_point()), (2.0, 5.0, 0.0));
+        assert_eq!(other.properties.get("audio.volume").map(String::as_str), Some("0.5"));
+        
+        // A locked track or unknown clip fails the whole paste
+        timeline.get_track_mut("v2").unwrap().is_locked = true;
+        let empty = ClipAttributes::default();
+        assert!(timeline.paste_attributes(&["plain", "other"], &empty, &AttributeMask::all()).is_err());
+        assert!(timeline.paste_attributes(&["plain", "missing"], &empty, &AttributeMask::all()).is_err());
+        assert!(timeline.get_track("v1").unwrap().clips[1].properties.contains_key("effect.blur.radius"));
+        assert!(timeline.copy_attributes("missing").is_err());
+    }
+    
+    #[test]
+    fn test_export_in_to_out_region() {
+        use crate::engine::timeline::{Marker, Timeline, TimelineConfig};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        timeline.add_marker(Marker::new("intro".to_string(), 0.0, "Intro".to_string())).unwrap();
+        let mut interview = Marker::new("interview".to_string(), 8.0, "Interview".to_string());
+        interview.duration = 10.0;
+        timeline.add_marker(interview).unwrap();
+        timeline.add_marker(Marker::new("broll".to_string(), 15.0, "B-roll".to_string())).unwrap();
+        timeline.add_marker(Marker::new("credits".to_string(), 50.0, "Credits".to_string())).unwrap();
+        
+        // No in and out points yet
+        assert!(ExportRegion::InToOut.range(&timeline).is_err());
+        assert_eq!(ExportRegion::WholeSequence.range(&timeline).unwrap(), (0.0, 60.0));
+        assert!(timeline.set_work_area(20.0, 10.0).is_err());
+        assert!(timeline.set_work_area(10.0, 61.0).is_err());
+        
+        timeline.set_work_area(10.0, 30.0).unwrap();
+        let options = ExportOptions::default().with_region(&timeline, ExportRegion::InToOut).unwrap();
+        assert_eq!(options.range, Some((10.0, 30.0)));
+        
+        // The interview was running at the in point; credits are past the out point
+        let chapters: Vec<(&str, f64, f64)> = options.chapters.iter()
+            .map(|chapter| (chapter.title.as_str(), chapter.start, chapter.end))
+            .collect();
+        assert_eq!(chapters, vec![("Interview", 0.0, 8.0), ("B-roll", 5.0, 20.0)]);
+        
+        let whole = ExportOptions::default().with_region(&timeline, ExportRegion::WholeSequence).unwrap();
+        assert_eq!(whole.range, None);
+        assert_eq!(whole.chapters.len(), 4);
+        assert_eq!((whole.chapters[0].start, whole.chapters[0].end), (0.0, 8.0));
+        
+        // Shortening the timeline trims the work area
+        timeline.set_duration(25.0).unwrap();
+        assert_eq!(timeline.work_area(), Some((10.0, 25.0)));
+        timeline.set_duration(5.0).unwrap();
+        assert_eq!(timeline.work_area(), None);
+    }
+    
+    #[test]
+    fn test_leader_graphs() {
+        let leader = Leader { bars_duration: 30.0, slate_duration: 10.0, ..Leader::default() }
+            .with_field("Title", "Spring Campaign")
+            .with_field("Client", "O'Neil, Inc.");
+        assert_eq!(leader.duration(), 40.0);
+        assert_eq!(leader.frames(25.0), 1000);
+        
+        let video = leader.video_graph(1920, 1080, 25.0);
+        assert!(video.starts_with("smptebars=size=1920x1080:rate=25:duration=30[bars];color=c=black:size=1920x1080:rate=25:duration=10,"));
+        assert!(video.contains("drawtext=text=Title\\\\: Spring Campaign:"));
+        assert!(video.ends_with("[bars][slate]concat=n=2:v=1:a=0,format=yuv420p"));
+        // Two lines around the middle of the frame
+        assert!(video.contains("y=h*10/24") && video.contains("y=h*12/24"));
+        
+        let audio = leader.audio_graph(48000, "fltp", "stereo");
+        assert!(audio.starts_with("sine=frequency=1000:sample_rate=48000:duration=30,volume=-20dB[tone];anullsrc=r=48000:cl=stereo,atrim=duration=10[silence];"));
+        assert!(audio.ends_with("aformat=sample_fmts=fltp:sample_rates=48000:channel_layouts=stereo"));
+        
+        // Escaped once for the option and again for the graph
+        assert_eq!(escape_filter_value("O'Neil, Inc."), "O\\\\\\'Neil\\, Inc.");
+        assert_eq!(escape_filter_value("a:b[1];c"), "a\\\\:b\\[1\\]\\;c");
+        assert_eq!(escape_filter_value("plain"), "plain");
+        
+        let options = ExportOptions { leader: Some(leader.clone()), ..ExportOptions::default() };
+        assert_eq!(options.leader.unwrap().slate.len(), 2);
+    }
+    
+    #[test]
+    fn test_mp4_layout_options() {
+        // MP4s stream by default
+        let options = ExportOptions::default();
+        assert_eq!(options.container_options.mp4_layout, Mp4Layout::FastStart);
+        assert_eq!(
+            options.container_options.ffmpeg_format_options(ContainerFormat::Mp4),
+            vec![("movflags", "+faststart".to_string())]
+        );
+        assert_eq!(options.container_options.gst_muxer_properties("mp4mux"), vec![("faststart", "true".to_string())]);
+        
+        let fragmented = ContainerOptions { mp4_layout: Mp4Layout::fragmented() };
+        assert_eq!(
+            fragmented.ffmpeg_format_options(ContainerFormat::Mov),
+            vec![
+                ("movflags", "+frag_keyframe+empty_moov+default_base_moof".to_string()),
+                ("frag_duration", "2000000".to_string()),
+            ]
+        );
+        assert_eq!(fragmented.gst_muxer_properties("qtmux"), vec![("fragment-duration", "2000".to_string())]);
+        
+        // Other containers and muxers are left alone
+        assert!(fragmented.ffmpeg_format_options(ContainerFormat::Mkv).is_empty());
+        assert!(fragmented.gst_muxer_properties("matroskamux").is_empty());
+        let standard = ContainerOptions { mp4_layout: Mp4Layout::Standard };
+        assert!(standard.ffmpeg_format_options(ContainerFormat::Mp4).is_empty());
+        
+        let json = serde_json::to_string(&fragmented).unwrap();
+        assert_eq!(serde_json::from_str::<ContainerOptions>(&json).unwrap(), fragmented);
+        assert_eq!(serde_json::from_str::<ContainerOptions>("{}").unwrap(), ContainerOptions::default());
+    }
+    
+    #[test]
+    fn test_rate_control() {
+        // Without a rate control mode, CRF unless a bitrate is set
+        let mut options = EncoderOptions::default();
+        assert_eq!(options.rate_control_options(), vec![("crf", "23".to_string())]);
+        
+        // A platform cap of 8 Mbps over a 16 Mbit buffer
+        options.with_rate_control(RateControl::ConstrainedQuality { max_bitrate: 8_000_000, buffer_size: 16_000_000 });
+        let args = options.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-crf 23 -maxrate 8000k -bufsize 16000k"), "{}", args);
+        
+        options.with_hardware_acceleration(true).with_hardware_encoder(HardwareEncoder::Nvenc);
+        assert_eq!(options.codec_name(), "h264_nvenc");
+        let args = options.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-rc vbr -cq 23 -b:v 0 -maxrate 8000k -bufsize 16000k"), "{}", args);
+        
+        options.with_hardware_encoder(HardwareEncoder::Vaapi);
+        assert_eq!(options.rate_control_options()[..2], [("rc_mode", "QVBR".to_string()), ("global_quality", "23".to_string())]);
+        options.with_rate_control(RateControl::ConstantQuality);
+        assert_eq!(options.rate_control_options(), vec![("rc_mode", "CQP".to_string()), ("qp", "23".to_string())]);
+        
+        // CBR is HRD-conformant on x264, and the buffer defaults to one second
+        let mut cbr = EncoderOptions::default();
+        cbr.with_video_bitrate(6_000_000).with_rate_control(RateControl::ConstantBitrate { buffer_size: 0 });
+        assert!(cbr.validate().is_ok());
+        assert_eq!(cbr.rate_control_options(), vec![
+            ("b:v", "6000k".to_string()),
+            ("minrate", "6000k".to_string()),
+            ("maxrate", "6000k".to_string()),
+            ("bufsize", "6000k".to_string()),
+            ("x264-params", "nal-hrd=cbr".to_string()),
+        ]);
+        cbr.video_format = VideoFormat::H265;
+        assert_eq!(cbr.rate_control_options().last().unwrap().1, "strict-cbr=1");
+        
+        let mut capped = EncoderOptions::default();
+        capped.with_video_bitrate(4_000_000).with_rate_control(RateControl::CappedVbr { max_bitrate: 6_000_000, buffer_size: 12_000_000 });
+        assert!(capped.validate().is_ok());
+        assert_eq!(capped.rate_control_options(), vec![
+            ("b:v", "4000k".to_string()),
+            ("maxrate", "6000k".to_string()),
+            ("bufsize", "12000k".to_string()),
+        ]);
+        
+        // Modes missing the bitrates they need
+        capped.with_video_bitrate(8_000_000);
+        assert!(capped.validate().is_err());
+        let mut no_bitrate = EncoderOptions::default();
+        no_bitrate.with_rate_control(RateControl::ConstantBitrate { buffer_size: 0 });
+        assert!(no_bitrate.validate().is_err());
+        no_bitrate.with_rate_control(RateControl::ConstrainedQuality { max_bitrate: 0, buffer_size: 0 });
+        assert!(no_bitrate.validate().is_err());
+        
+        // Templates saved before rate control load with the plain behavior
+        let mut saved = serde_json::to_value(EncoderOptions::web_delivery()).unwrap();
+        saved.as_object_mut().unwrap().remove("rate_control");
+        saved.as_object_mut().unwrap().remove("hardware_encoder");
+        let loaded: EncoderOptions = serde_json::from_value(saved).unwrap();
+        assert_eq!(loaded.rate_control, None);
+        assert_eq!(loaded.hardware_encoder, HardwareEncoder::VideoToolbox);
+    }
+    
+    #[test]
+    fn test_encoder_profiles_and_levels() {
+        // Baseline 3.0, what the most constrained phones decode
+        let mut options = EncoderOptions::default();
+        options.with_codec_profile(CodecProfile::Baseline).with_level("3.0").with_video_bitrate(2_000_000);
+        assert!(options.validate_for_output(720, 480, 30.0).is_ok());
+        assert!(options.validate_for_output(1280, 720, 30.0).is_err());
+        assert!(options.validate_for_output(720, 480, 60.0).is_err());
+        // High allows a quarter more bitrate at the same level
+        options.with_video_bitrate(12_000_000);
+        assert!(options.validate_for_output(720, 480, 30.0).is_err());
+        options.with_codec_profile(CodecProfile::High);
+        assert!(options.validate_for_output(720, 480, 30.0).is_ok());
+        
+        // 1080p30 just fits 4.1, 1080p60 needs 4.2
+        options.with_level("4.1").with_tune(Tune::Film).with_tune(Tune::ZeroLatency);
+        assert!(options.validate_for_output(1920, 1080, 30.0).is_ok());
+        assert!(options.validate_for_output(1920, 1080, 60.0).is_err());
+        let args = options.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-profile:v high -level 4.1 -tune film,zerolatency"), "{}", args);
+        options.with_tune(Tune::Animation);
+        assert!(options.validate_for_output(1920, 1080, 30.0).is_err());
+        options.with_level("4.4");
+        options.profile.tune.clear();
+        assert!(options.validate_for_output(1920, 1080, 30.0).is_err());
+        
+        // H.265 Main 10 encodes from 10-bit pictures; High is H.264 only
+        let mut hevc = EncoderOptions::new(VideoFormat::H265, AudioFormat::Aac);
+        hevc.with_codec_profile(CodecProfile::Main10).with_level("5.1").with_tune(Tune::Animation);
+        assert!(hevc.validate_for_output(3840, 2160, 60.0).is_ok());
+        assert!(hevc.validate_for_output(7680, 4320, 30.0).is_err());
+        assert_eq!(hevc.profile.ffmpeg_options(hevc.codec_name()), vec![
+            ("profile:v", "main10".to_string()),
+            ("pix_fmt", "yuv420p10le".to_string()),
+            ("level", "5.1".to_string()),
+            ("tune", "animation".to_string()),
+        ]);
+        hevc.profile.tune = vec![Tune::Film];
+        assert!(hevc.validate_for_output(3840, 2160, 30.0).is_err());
+        hevc.profile.tune.clear();
+        hevc.with_codec_profile(CodecProfile::High);
+        assert!(hevc.validate_for_output(1920, 1080, 30.0).is_err());
+        
+        // NVENC only has a low latency mode
+        hevc.with_codec_profile(CodecProfile::Main10).with_tune(Tune::ZeroLatency);
+        hevc.with_hardware_acceleration(true).with_hardware_encoder(HardwareEncoder::Nvenc);
+        assert!(hevc.validate_for_output(3840, 2160, 30.0).is_ok());
+        let args = hevc.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-profile:v main10 -pix_fmt p010le -level 5.1 -tune ull -zerolatency 1"), "{}", args);
+        hevc.with_tune(Tune::Film);
+        assert!(hevc.validate_for_output(3840, 2160, 30.0).is_err());
+        
+        // Templates saved before profiles leave them to the encoder
+        let mut saved = serde_json::to_value(EncoderOptions::web_delivery()).unwrap();
+        saved.as_object_mut().unwrap().remove("profile");
+        let loaded: EncoderOptions = serde_json::from_value(saved).unwrap();
+        assert_eq!(loaded.profile, EncoderProfile::default());
+        assert!(!loaded.to_ffmpeg_args().contains(&"-profile:v".to_string()));
+    }
+    
+    #[test]
+    fn test_gop_structure() {
+        // Left to the encoder by default
+        let mut options = EncoderOptions::default();
+        assert!(options.gop_options().is_empty());
+        
+        options.with_keyframe_interval(1.5).with_b_frames(3).with_scene_cut_keyframes(false);
+        assert!(options.validate().is_ok());
+        assert_eq!(options.gop_options(), vec![
+            ("force_key_frames", "expr:gte(t,n_forced*1.5)".to_string()),
+            ("bf", "3".to_string()),
+            ("sc_threshold", "0".to_string()),
+        ]);
+        
+        // x265 takes scene cuts in its parameter list, shared with CBR
+        options.video_format = VideoFormat::H265;
+        options.with_video_bitrate(4_000_000).with_rate_control(RateControl::ConstantBitrate { buffer_size: 0 });
+        let params: Vec<_> = options.encoder_options().into_iter().filter(|(key, _)| *key == "x265-params").collect();
+        assert_eq!(params, vec![("x265-params", "strict-cbr=1:scenecut=0".to_string())]);
+        
+        options.with_hardware_acceleration(true).with_hardware_encoder(HardwareEncoder::Nvenc);
+        let args = options.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-force_key_frames expr:gte(t,n_forced*1.5) -forced-idr 1 -bf 3 -no-scenecut 1"), "{}", args);
+        
+        // The streaming preset holds a fixed two second GOP
+        let streaming = EncoderOptions::streaming();
+        assert!(streaming.validate_for_output(1920, 1080, 30.0).is_ok());
+        let args = streaming.to_ffmpeg_args().join(" ");
+        assert!(args.contains("-force_key_frames expr:gte(t,n_forced*2) -bf 2 -sc_threshold 0"), "{}", args);
+        
+        // Intervals must be positive, and Baseline has no B-frames
+        let mut invalid = EncoderOptions::default();
+        invalid.with_keyframe_interval(0.0);
+        assert!(invalid.validate().is_err());
+        let mut baseline = EncoderOptions::default();
+        baseline.with_codec_profile(CodecProfile::Baseline).with_b_frames(2);
+        assert!(baseline.validate().is_err());
+        baseline.with_b_frames(0);
+        assert!(baseline.validate().is_ok());
+        
+        // Templates saved before GOP control keep scene cut keyframes
+        let mut saved = serde_json::to_value(EncoderOptions::web_delivery()).unwrap();
+        for key in ["keyframe_interval", "b_frames", "scene_cut_keyframes"] {
+            saved.as_object_mut().unwrap().remove(key);
+        }
+        let loaded: EncoderOptions = serde_json::from_value(saved).unwrap();
+        assert!(loaded.scene_cut_keyframes);
+        assert_eq!((loaded.keyframe_interval, loaded.b_frames), (None, None));
+    }
+    
+    #[test]
+    fn test_selective_track_export() {
+        use crate::engine::timeline::{Clip, ClipType, Marker, Timeline, TimelineConfig, Track};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        for (track_id, clip_type) in [("v1", ClipType::Video), ("titles", ClipType::Text), ("dialog", ClipType::Audio), ("scratch", ClipType::Audio)] {
+            timeline.add_track(Track::new(track_id.to_string(), track_id.to_string())).unwrap();
+            timeline.add_clip_to_track(track_id, Clip::new(format!("{}_clip", track_id), clip_type, 0.0, 10.0)).unwrap();
+        }
+        timeline.add_marker(Marker::new("act1".to_string(), 5.0, "Act 1".to_string())).unwrap();
+        timeline.set_work_area(2.0, 20.0).unwrap();
+        
+        // Without the scratch narration; the project keeps it
+        let (options, selected) = ExportOptions::default()
+            .with_tracks(&timeline, TrackSelection::excluding(&["scratch"]))
+            .unwrap();
+        assert!(!options.tracks.video_only);
+        let mut ids: Vec<&str> = selected.tracks().keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["dialog", "titles", "v1"]);
+        assert_eq!(timeline.tracks().len(), 4);
+        assert_eq!((selected.fps(), selected.duration(), selected.work_area()), (25, 60.0, Some((2.0, 20.0))));
+        assert_eq!(selected.markers().len(), 1);
+        
+        // A textless video-only master
+        let textless = TrackSelection::excluding(&["titles"]).without_audio();
+        let selected = textless.apply(&timeline).unwrap();
+        let clips: usize = selected.tracks().values().map(|track| track.clips.len()).sum();
+        assert_eq!(clips, 1);
+        assert!(selected.tracks()["v1"].clips[0].clip_type == ClipType::Video);
+        
+        let only = TrackSelection::only(&["v1", "dialog"]);
+        assert!(only.includes_track("dialog") && !only.includes_track("titles"));
+        assert!(TrackSelection::default().is_all());
+        
+        // Unknown tracks and empty selections are refused
+        assert!(TrackSelection::only(&["v2"]).apply(&timeline).is_err());
+        assert!(TrackSelection::excluding(&["v1", "titles", "dialog", "scratch"]).validate(&timeline).is_err());
+    }
+    
+    #[test]
+    fn test_language_versions() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 24, duration: 90.0 });
+        for (track_id, clip_type) in [("v1", ClipType::Video), ("a1", ClipType::Audio), ("titles_en", ClipType::Text), ("subs_en", ClipType::Text), ("titles_fr", ClipType::Text)] {
+            timeline.add_track(Track::new(track_id.to_string(), track_id.to_string())).unwrap();
+            timeline.add_clip_to_track(track_id, Clip::new(format!("{}_clip", track_id), clip_type, 0.0, 10.0)).unwrap();
+        }
+        
+        let mut versions = LanguageVersions::default();
+        versions.set_variant("en", &["titles_en", "subs_en"]).unwrap();
+        versions.set_variant("fr", &["titles_fr"]).unwrap();
+        assert!(versions.set_variant("de", &["titles_fr"]).is_err());
+        assert!(versions.set_variant("de", &[]).is_err());
+        assert_eq!(versions.versions(), vec![Version::Textless, Version::Language("en".to_string()), Version::Language("fr".to_string())]);
+        
+        let french = versions.selection(&Version::Language("FR".to_string())).unwrap();
+        assert_eq!(french.exclude, vec!["titles_en", "subs_en"]);
+        assert_eq!(versions.selection(&Version::Textless).unwrap().exclude.len(), 3);
+        assert!(versions.selection(&Version::Language("de".to_string())).is_err());
+        
+        // One batch renders every version, keeping what the base options leave out
+        let base = ExportOptions { tracks: TrackSelection::excluding(&["a1"]), ..ExportOptions::default() };
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let context = NamingContext { date, ..NamingContext::new("Trailer", "Final", "Master") };
+        let renders = versions.prepare_batch(&timeline, &versions.versions(), &base, &context).unwrap();
+        let track_ids = |render: &VersionRender| {
+            let mut ids: Vec<String> = render.timeline.tracks().keys().cloned().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(track_ids(&renders[0]), vec!["v1"]);
+        assert_eq!(track_ids(&renders[1]), vec!["subs_en", "titles_en", "v1"]);
+        assert_eq!(track_ids(&renders[2]), vec!["titles_fr", "v1"]);
+        assert_eq!(timeline.tracks().len(), 5);
+        
+        let template = FilenameTemplate::new("{project}_{version}").unwrap();
+        let names: Vec<String> = renders.iter().map(|render| template.render(&render.context)).collect();
+        assert_eq!(names, vec!["Trailer_textless", "Trailer_en", "Trailer_fr"]);
+        assert_eq!(template.render(&context), "Trailer_main");
+        
+        // Removing a language puts its tracks in every version
+        versions.remove_variant("fr");
+        assert_eq!(versions.selection(&Version::Textless).unwrap().exclude, vec!["titles_en", "subs_en"]);
+    }
+    
+    #[test]
+    fn test_ntsc_frame_rates_and_pulldown() {
+        use crate::engine::frame_rate::{FieldSource, FrameRate, FrameRateFamily, Pulldown, PulldownQueue};
+        use crate::engine::timeline::{Timeline, TimelineConfig};
+        
+        // Rates as files store them snap to the exact NTSC fraction
+        assert_eq!(FrameRate::from_fps(29.97), FrameRate::new(30000, 1001));
+        assert_eq!(FrameRate::parse("23.976"), Some(FrameRate::FPS_23_976));
+        assert_eq!(FrameRate::parse("60000/1001"), Some(FrameRate::FPS_59_94));
+        assert_eq!(FrameRate::parse("25"), Some(FrameRate::FPS_25));
+        assert_eq!(FrameRate::from_fps(12.5), FrameRate::new(25, 2));
+        assert_eq!((FrameRate::FPS_29_97.to_string(), FrameRate::FPS_23_976.to_string(), FrameRate::FPS_50.to_string()),
+            ("29.97".to_string(), "23.976".to_string(), "50".to_string()));
+        assert_eq!(FrameRate::FPS_59_94.to_ffmpeg_rate(), "60000/1001");
+        assert_eq!(FrameRateFamily::Ntsc.rates(), vec![FrameRate::FPS_23_976, FrameRate::FPS_29_97, FrameRate::FPS_59_94]);
+        assert_eq!(FrameRate::FPS_24.family(), FrameRateFamily::Film);
+        assert!(FrameRate::FPS_29_97.supports_drop_frame() && !FrameRate::FPS_23_976.supports_drop_frame());
+        
+        // An hour of 29.97 is 107892 frames plus a fraction, not 108000
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.set_frame_rate(FrameRate::FPS_29_97).unwrap();
+        assert_eq!((timeline.fps(), timeline.frame_rate_family()), (30, FrameRateFamily::Ntsc));
+        assert_eq!(timeline.frame_at(3600.0), 107892);
+        assert_eq!(timeline.frame_at(timeline.frame_time(107892)), 107892);
+        assert!((timeline.frame_time(108000) - 3603.6).abs() < 1e-9);
+        timeline.set_fps(25).unwrap();
+        assert_eq!(timeline.frame_rate(), FrameRate::FPS_25);
+        assert!(timeline.set_frame_rate(FrameRate { numerator: 0, denominator: 1 }).is_err());
+        
+        // Pulldown only goes between film and video rates of the same clock
+        assert_eq!(Pulldown::Insert.output_rate(FrameRate::FPS_23_976), Some(FrameRate::FPS_29_97));
+        assert_eq!(Pulldown::Remove.output_rate(FrameRate::FPS_30), Some(FrameRate::FPS_24));
+        assert_eq!(Pulldown::Insert.output_rate(FrameRate::FPS_25), None);
+        assert_eq!(Pulldown::None.output_rate(FrameRate::FPS_25), Some(FrameRate::FPS_25));
+        
+        // 2:3 cadence: A B C D become AA BB BC CD DD, and weave back to A B C D
+        assert_eq!(Pulldown::Insert.source_fields(7), FieldSource { top: 5, bottom: 6 });
+        assert_eq!(Pulldown::Remove.source_fields(2), FieldSource { top: 3, bottom: 2 });
+        assert_eq!((Pulldown::Insert.output_frames(48), Pulldown::Insert.output_frames(7)), (60, 8));
+        assert_eq!((Pulldown::Remove.output_frames(60), Pulldown::Remove.output_frames(8)), (48, 6));
+        
+        let weave = |top: &String, bottom: &String| format!("{}{}", &top[..1], &bottom[1..]);
+        let mut insert = PulldownQueue::new(Pulldown::Insert);
+        let video: Vec<String> = ["AA", "BB", "CC", "DD", "EE"].iter()
+            .flat_map(|frame| insert.push(frame.to_string(), weave))
+            .collect();
+        assert_eq!(video, vec!["AA", "BB", "BC", "CD", "DD", "EE"]);
+        
+        let mut remove = PulldownQueue::new(Pulldown::Remove);
+        let film: Vec<String> = video.iter()
+            .flat_map(|frame| remove.push(frame.clone(), weave))
+            .collect();
+        assert_eq!(film, vec!["AA", "BB", "CC", "DD", "EE"]);
+        
+        // Exporters write the exact rate
+        let plan = EncodingPlan::new(ContainerFormat::Mp4, VideoFormat::H264, AudioFormat::Aac, false);
+        assert_eq!(plan.clone().with_frame_rate(29.97).frame_rate, Some(FrameRate::FPS_29_97));
+        assert_eq!(plan.with_frame_rate(0.0).frame_rate, None);
+        let leader = Leader { bars_duration: 1.0, slate_duration: 1.0, ..Leader::default() };
+        assert!(leader.video_graph(1920, 1080, 29.97).starts_with("smptebars=size=1920x1080:rate=30000/1001:"));
+        assert_eq!(ExportOptions::default().pulldown, Pulldown::None);
+        
+        // Inserted pulldown is coded as interlaced, top field first
+        let insert = ExportOptions { pulldown: Pulldown::Insert, ..ExportOptions::default() };
+        let codec_options = insert.video_codec_options();
+        assert!(codec_options.contains(&("field_order", "tt".to_string())));
+        assert!(codec_options.contains(&("flags", "+ildct+ilme".to_string())));
+        assert!(codec_options.contains(&("x264-params", "tff=1".to_string())));
+        assert!(ExportOptions::default().video_codec_options().is_empty());
+        assert!(ExportOptions { pulldown: Pulldown::Remove, ..ExportOptions::default() }.video_codec_options().is_empty());
+        
+        // Deterministic x264 settings are kept alongside the field order
+        let deterministic = ExportOptions { deterministic: true, ..insert.clone() }.video_codec_options();
+        let x264_params: Vec<&String> = deterministic.iter().filter(|(key, _)| *key == "x264-params").map(|(_, value)| value).collect();
+        assert_eq!(x264_params, vec!["sliced-threads=0:lookahead-threads=1:tff=1"]);
+        
+        // The GStreamer exporter doesn't weave fields, so it refuses pulldown
+        init_gstreamer();
+        gstreamer_editing_services::init().unwrap();
+        let gst_options = GstExportOptions { pulldown: Pulldown::Insert, ..GstExportOptions::default() };
+        let error = GstExporter::new(gst_options).err().unwrap();
+        assert!(error.to_string().contains("Pulldown insert needs the FFmpeg exporter"), "{}", error);
+    }
+    
+    #[test]
+    fn test_framing_and_safe_area() {
+        use crate::engine::framing::{placement, CustomFraming, Placement, SafeAreaGuides};
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_conform::{ConversionOption, ScaleMode};
+        
+        // 4:3 in 16:9 pillarboxes to fit, crops to fill
+        let (sd, hd) = ((640, 480), (1920, 1080));
+        let none = CustomFraming::default();
+        assert_eq!(placement(ScaleMode::Fit, none, sd, hd), Placement { x: 240, y: 0, width: 1440, height: 1080 });
+        assert_eq!(placement(ScaleMode::Fill, none, sd, hd), Placement { x: 0, y: -180, width: 1920, height: 1440 });
+        assert_eq!(placement(ScaleMode::Stretch, none, sd, hd), Placement { x: 0, y: 0, width: 1920, height: 1080 });
+        assert_eq!(placement(ScaleMode::None, none, sd, hd), Placement { x: 640, y: 300, width: 640, height: 480 });
+        
+        // Scope in 16:9 letterboxes
+        assert_eq!(placement(ScaleMode::Fit, none, (2048, 858), hd), Placement { x: 0, y: 138, width: 1920, height: 804 });
+        
+        let zoomed = CustomFraming { scale: 1.5, offset_x: 0.0, offset_y: -0.25 };
+        assert_eq!(placement(ScaleMode::Custom, zoomed, sd, hd), Placement { x: -120, y: -540, width: 2160, height: 1620 });
+        
+        // Clips override the project default
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string())).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("a".to_string(), ClipType::Video, 0.0, 5.0)).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("b".to_string(), ClipType::Video, 5.0, 5.0)).unwrap();
+        assert_eq!(timeline.scale_mode(), ScaleMode::Fit);
+        timeline.set_scale_mode(ScaleMode::Fill);
+        timeline.apply_conversion("video", "a", ConversionOption::Scale(ScaleMode::Stretch)).unwrap();
+        timeline.set_clip_framing("video", "b", zoomed).unwrap();
+        
+        let track = &timeline.tracks()["video"];
+        assert_eq!(timeline.clip_scale_mode(&track.clips[0]), ScaleMode::Stretch);
+        assert_eq!((timeline.clip_scale_mode(&track.clips[1]), CustomFraming::of(&track.clips[1])), (ScaleMode::Custom, zoomed));
+        assert_eq!(timeline.copy_with_tracks(|_| None).scale_mode(), ScaleMode::Fill);
+        assert!(timeline.set_clip_framing("video", "a", CustomFraming { scale: 0.0, ..none }).is_err());
+        
+        // 93% action and 90% title safe outlines in an HD frame
+        assert_eq!(SafeAreaGuides::area(0.93, 1920, 1080), (67, 38, 1852, 1041));
+        assert_eq!(SafeAreaGuides::area(0.90, 1920, 1080), (96, 54, 1823, 1025));
+        
+        let guides = SafeAreaGuides { center_cross: false, color: [255, 0, 0, 255], ..SafeAreaGuides::default() };
+        let (width, height) = (100, 100);
+        let mut frame = vec![0u8; width * height * 4];
+        guides.draw(&mut frame, width as u32, height as u32, "BGRA");
+        let pixel = |x: usize, y: usize| &frame[(y * width + x) * 4..(y * width + x) * 4 + 4];
+        assert_eq!(pixel(5, 50), &[0, 0, 255, 0]);
+        assert_eq!(pixel(50, 5), &[0, 0, 255, 0]);
+        assert_eq!(pixel(50, 50), &[0, 0, 0, 0]);
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 0]);
+    }
+    
+    #[test]
+    fn test_command_history_spills_to_disk() {
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_commands::{CommandHistory, TimelineCommand};
+        
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string())).unwrap();
+        let mut history = CommandHistory::default();
+        history.set_memory_limit(0);
+        
+        for i in 0..5 {
+            history.execute(&mut timeline, TimelineCommand::AddClip {
+                track_id: "v1".to_string(),
+                clip: Clip::new(format!("c{}", i), ClipType::Video, i as f64 * 10.0, 5.0),
+            }).unwrap();
+        }
+        
+        // Only the newest step stays in memory
+        assert_eq!(history.spilled_steps(), 4);
+        
+        // Spilled steps undo and redo as if they had never left memory
+        for _ in 0..5 {
+            assert_eq!(history.undo(&mut timeline).unwrap().as_deref(), Some("Add clip"));
+        }
+        assert!(timeline.get_track("v1").unwrap().clips.is_empty());
+        assert_eq!(history.redo(&mut timeline).unwrap().as_deref(), Some("Add clip"));
+        assert_eq!(timeline.get_track("v1").unwrap().clips[0].id, "c0");
+        
+        history.clear();
+        assert_eq!(history.spilled_steps(), 0);
+        assert_eq!(history.memory_used(), 0);
+    }
+    
+    #[test]
+    fn test_tile_scheduler_matches_single_band() {
+        use crate::engine::tile_scheduler::TileScheduler;
+        
+        let (width, height) = (37, 53);
+        let stride = width * 4;
+        let source: Vec<u8> = (0..stride * height).map(|i| (i * 31 % 251) as u8).collect();
+        
+        // A stage reading the rows around its own, like sharpening
+        let stage = |frame: &mut Vec<u8>, tiles: &TileScheduler| {
+            tiles.for_each_band(frame, stride, height, |mut band| {
+                for row in 0..band.rows {
+                    let y = band.first_row + row;
+                    let above = &source[y.saturating_sub(1) * stride..][..stride];
+                    let below = &source[(y + 1).min(height - 1) * stride..][..stride];
+                    for (x, value) in band.row_mut(row).iter_mut().enumerate() {
+                        *value = ((above[x] as u16 + below[x] as u16) / 2) as u8 ^ y as u8;
+                    }
+                }
+            });
+        };
+        
+        let single = TileScheduler::new(1).unwrap();
+        let mut expected = source.clone();
+        stage(&mut expected, &single);
+        
+        for threads in [2, 3, 8] {
+            let tiles = TileScheduler::new(threads).unwrap();
+            assert!(tiles.band_rows(height) < height);
+            let mut frame = source.clone();
+            stage(&mut frame, &tiles);
+            assert_eq!(frame, expected, "{} threads", threads);
+        }
+        
+        // Trailing bytes of a partial pixel are left alone
+        let mut pixels = vec![10u8; 4 * 5000 + 3];
+        TileScheduler::new(4).unwrap().for_each_pixel(&mut pixels, 4, |pixel| pixel[0] = 0);
+        assert!(pixels[..4 * 5000].chunks(4).all(|pixel| pixel == [0, 10, 10, 10]));
+        assert_eq!(&pixels[4 * 5000..], &[10, 10, 10]);
+    }
+    
+    #[test]
+    fn test_preview_format_negotiation() {
+        use crate::engine::editing::{negotiate_formats, RGB_FORMATS};
+        
+        let strings = |formats: &[&str]| formats.iter().map(|format| format.to_string()).collect::<Vec<_>>();
+        let rgb = strings(&RGB_FORMATS);
+        let display = strings(&["NV12", "BGRA", "RGBA"]);
+        
+        // Plain playback keeps the decoder's format
+        assert_eq!(negotiate_formats(&display, &[]), display);
+        
+        // A busy RGB stage gets the RGB formats the display takes, in its order
+        assert_eq!(negotiate_formats(&display, &[rgb.clone()]), strings(&["BGRA", "RGBA"]));
+        
+        // A display without RGB still gets frames the stages can process
+        let nv12 = strings(&["NV12"]);
+        assert_eq!(negotiate_formats(&nv12, &[rgb.clone()]), rgb);
+        assert_eq!(negotiate_formats(&nv12, &[nv12.clone(), strings(&["I420"])]), rgb);
+    }
+    
+    #[test]
+    fn test_playhead_stream_sends_changes_and_playback() {
+        use crate::engine::editing::PlayheadStream;
+        use std::time::Duration;
+        
+        let reads = [(0, false), (0, false), (10, false), (20, true), (30, true)];
+        let mut reads = reads.into_iter().chain(std::iter::repeat((30, false)));
+        let stream = PlayheadStream::with_probe(200.0, Box::new(move || reads.next()));
+        
+        let updates: Vec<_> = std::iter::from_fn(|| stream.recv_timeout(Duration::from_millis(200))).collect();
+        let positions: Vec<_> = updates.iter().map(|update| (update.position, update.playing)).collect();
+        
+        // A paused playhead that doesn't move isn't sent again
+        assert_eq!(positions, [(0, false), (10, false), (20, true), (30, true), (30, false)]);
+        assert!(updates.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp));
+    }
+    
+    #[test]
+    fn test_generated_clips() {
+        use crate::engine::generators::{Generator, GENERATOR_PROPERTY};
+        use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_validation::DiagnosticKind;
+        
+        let bars = Generator::ColorBars.clip("bars", 0.0, 5.0);
+        assert_eq!(bars.clip_type, ClipType::Generated);
+        assert_eq!(Generator::of(&bars), Some(Generator::ColorBars));
+        
+        // Missing pictures stand in as offline slates named after their file
+        let gone = Clip::new("gone".to_string(), ClipType::Video, 5.0, 5.0).with_source("/missing/Interview.mov".to_string());
+        assert_eq!(Generator::for_clip(&gone), Some(Generator::MediaOffline { name: "Interview.mov".to_string() }));
+        let sound = Clip::new("sound".to_string(), ClipType::Audio, 5.0, 5.0).with_source("/missing/Interview.wav".to_string());
+        assert_eq!(Generator::for_clip(&sound), None);
+        
+        let (width, height) = (56, 32);
+        let pixel = |frame: &[u8], x: u32, y: u32| {
+            let offset = ((y * width + x) * 4) as usize;
+            [frame[offset], frame[offset + 1], frame[offset + 2], frame[offset + 3]]
+        };
+        let frame = Generator::ColorBars.render(width, height);
+        assert_eq!(frame.len(), (width * height * 4) as usize);
+        assert_eq!(pixel(&frame, 0, 0), [191, 191, 191, 255]);
+        assert_eq!(pixel(&frame, width - 1, 0), [0, 0, 191, 255]);
+        assert_eq!(pixel(&frame, 12, height - 1), [255, 255, 255, 255]);
+        
+        let frame = Generator::Gradient.render(width, height);
+        assert_eq!((pixel(&frame, 0, 5)[0], pixel(&frame, width - 1, 5)[0]), (0, 255));
+        
+        let frame = Generator::Checkerboard.render(width, height);
+        assert_ne!(pixel(&frame, 0, 0), pixel(&frame, 4, 0));
+        assert_eq!(pixel(&frame, 0, 0), pixel(&frame, 4, 4));
+        
+        // The slate has its title in red
+        let frame = Generator::MediaOffline { name: "Interview.mov".to_string() }.render(320, 180);
+        assert!(frame.chunks(4).any(|pixel| pixel == [220, 40, 40, 255]));
+        
+        let dir = std::env::temp_dir().join("aether_generated_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let still = Generator::Checkerboard.still(&dir, width, height).unwrap();
+        assert!(still.exists());
+        assert_eq!(Generator::Checkerboard.still(&dir, width, height).unwrap(), still);
+        std::fs::remove_dir_all(&dir).unwrap();
+        
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 30.0 });
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string())).unwrap();
+        timeline.add_clip_to_track("video", bars).unwrap();
+        timeline.add_clip_to_track("video", Clip::new("noise".to_string(), ClipType::Generated, 5.0, 5.0)
+            .add_property(GENERATOR_PROPERTY.to_string(), "noise".to_string())).unwrap();
+        
+        let report = timeline.validate();
+        let kinds: Vec<_> = report.errors().map(|d| (d.clip_id.as_str(), &d.kind)).collect();
+        assert_eq!(kinds, [("noise", &DiagnosticKind::UnknownGenerator { generator: Some("noise".to_string()) })]);
+    }
+    
+    #[test]
+    fn test_slate_and_countdown() {
+        use crate::engine::generators::{Generator, Slate, COUNTDOWN_DURATION};
+        use crate::engine::timeline::{Timeline, TimelineConfig};
+        
+        // The running time comes from the timeline, and empty fields are left off
+        let timeline = Timeline::new(TimelineConfig { fps: 24, duration: 90.5 });
+        let slate = Slate::for_timeline("Spring Campaign", "", "2026-10-17", &timeline);
+        assert_eq!(slate.trt, "00:01:30:12");
+        let labels: Vec<String> = slate.fields().into_iter().map(|field| field.label).collect();
+        assert_eq!(labels, vec!["Title", "Date", "TRT"]);
+        
+        // Slate clips keep their fields in properties
+        let clip = Generator::Slate(slate.clone()).clip("slate", 0.0, 5.0);
+        assert_eq!(Generator::of(&clip), Some(Generator::Slate(slate.clone())));
+        assert_eq!(Generator::Countdown.clip("countdown", 5.0, COUNTDOWN_DURATION).properties["generator"], "countdown");
+        
+        // Grey labels and white values on black
+        let card = Generator::Slate(slate.clone()).render(320, 180);
+        assert_eq!(&card[..4], &[0, 0, 0, 255]);
+        assert!(card.chunks_exact(4).any(|pixel| pixel == [255, 255, 255, 255]));
+        assert!(card.chunks_exact(4).any(|pixel| pixel == [160, 160, 160, 255]));
+        
+        // The hand sweeps clockwise from 12 o'clock over each second
+        let pixel = |frame: &[u8], x: usize, y: usize| frame[(y * 100 + x) * 4];
+        let start = Generator::Countdown.render_at(100, 100, 0.0, COUNTDOWN_DURATION, 25.0);
+        let half = Generator::Countdown.render_at(100, 100, 0.5, COUNTDOWN_DURATION, 25.0);
+        assert_eq!((pixel(&start, 70, 30), pixel(&start, 30, 70)), (70, 70));
+        assert_eq!((pixel(&half, 70, 30), pixel(&half, 30, 70)), (140, 70));
+        assert_eq!(Generator::Countdown.render(100, 100), start);
+        
+        // The 2 shows for a frame, then black to the end
+        let two = Generator::Countdown.render_at(100, 100, 6.0, COUNTDOWN_DURATION, 25.0);
+        let black = Generator::Countdown.render_at(100, 100, 6.04, COUNTDOWN_DURATION, 25.0);
+        assert_eq!(pixel(&two, 70, 30), 70);
+        assert!(black.chunks_exact(4).all(|pixel| pixel == [0, 0, 0, 255]));
+        
+        // Stills hold for the clip; the countdown changes each frame until it goes black
+        assert_eq!(Generator::ColorBars.segments(5.0, 25.0), vec![(0.0, 5.0)]);
+        let segments = Generator::Countdown.segments(COUNTDOWN_DURATION, 25.0);
+        assert_eq!(segments.len(), 152);
+        assert_eq!(segments[150], (6.0, 0.04));
+        let (start, length) = segments[151];
+        assert!((start - 6.04).abs() < 1e-9 && (start + length - 8.0).abs() < 1e-9);
+        
+        // At the head of exports, after the slate
+        let leader = Leader { bars_duration: 10.0, slate_duration: 5.0, ..Leader::default() }
+            .with_slate(&slate)
+            .with_countdown();
+        assert_eq!(leader.slate.len(), 3);
+        assert_eq!(leader.duration(), 23.0);
+        assert_eq!(leader.countdown_frames(25.0), 200);
+        let audio = leader.audio_graph(48000, "fltp", "stereo");
+        assert!(audio.contains("atrim=duration=5[silence];anullsrc=r=48000:cl=stereo,atrim=duration=6[count];"));
+        assert!(audio.contains("[tone][silence][count][pop][black]concat=n=5:v=0:a=1,"));
+        assert_eq!(Leader::default().countdown_frames(25.0), 0);
+    }
+
+    #[test]
+    fn test_export_progress_across_retries() {
+        use std::sync::{Arc, Mutex};
+        
+        // A missing input fails every attempt the same way
+        let options = ExportOptions {
+            input_path: create_temp_dir("export_retries").unwrap().join("missing.mov"),
+            output_path: create_test_output_path("export_retries", "mp4").unwrap(),
+            ..ExportOptions::default()
+        };
+        let mut exporter = Exporter::new(options).unwrap();
+        exporter.set_space_check(None);
+        exporter.set_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            multiplier: 1.0,
+            retry_on: vec![ErrorClass::InputUnavailable],
+            ..RetryPolicy::default()
+        });
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let seen = updates.clone();
+        exporter.set_progress_callback(move |progress| seen.lock().unwrap().push(progress));
+        
+        exporter.start_export().unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !exporter.is_complete() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        
+        // Each failed attempt reports its error without completing the export
+        let updates = updates.lock().unwrap().clone();
+        let (last, retried) = updates.split_last().unwrap();
+        assert_eq!(retried.len(), 3);
+        for (i, progress) in retried.iter().enumerate() {
+            assert_eq!(progress.attempt, i as u32 + 1);
+            assert!(!progress.complete);
+            assert!(progress.error.as_deref().unwrap().contains("Failed to open input"));
+            assert!(progress.failure.is_none());
+        }
+        
+        // Giving up completes it with the error and every attempt
+        assert!(last.complete);
+        assert_eq!(last.attempt, 3);
+        assert!(last.error.as_deref().unwrap().contains("Failed to open input"));
+        let failure = last.failure.as_ref().unwrap();
+        assert_eq!(failure.class, ErrorClass::InputUnavailable);
+        assert_eq!(failure.attempts.iter().map(|attempt| attempt.attempt).collect::<Vec<_>>(), vec![1, 2, 3]);
+        
+        let progress = exporter.get_progress();
+        assert!(progress.complete && exporter.has_error());
+        assert_eq!(exporter.get_failure().unwrap().attempts.len(), 3);
+    }
+
+    /// Queue whose background jobs write slowly enough to stay running while a test looks at them
+    fn slow_render_queue(max_concurrent: usize) -> RenderQueue {
+        RenderQueue::new(RenderQueueConfig {
+            max_concurrent,
+            throttle: ThrottleSettings {
+                enabled: true,
+                all_jobs: true,
+                max_encoder_threads: 1,
+                max_write_rate: 16 * 1024,
+            },
+            hooks: Vec::new(),
+        })
+    }
+    
+    fn render_job_options(name: &str) -> ExportOptions {
+        use super::super::fixtures::{media_fixture, FixtureSpec};
+        
+        let input = media_fixture(&FixtureSpec { duration: 20.0, ..FixtureSpec::default() }).unwrap();
+        ExportOptions {
+            input_path: input,
+            output_path: create_test_output_path(&format!("render_queue_{}", name), "mp4").unwrap(),
+            ..ExportOptions::default()
+        }
+    }
+    
+    fn wait_for_status(queue: &RenderQueue, id: RenderJobId, status: JobStatus) {
+        wait_for_condition(|| queue.job(id).unwrap().status == status, Duration::from_secs(30), Duration::from_millis(50))
+            .unwrap_or_else(|_| panic!("Job {} never reached {:?}: {:?}", id, status, queue.job(id)));
+    }
+    
+    #[test]
+    fn test_render_queue_order_and_cancel() {
+        let queue = slow_render_queue(1);
+        let blocker = queue.enqueue(render_job_options("blocker"), JobPriority::Normal);
+        wait_for_status(&queue, blocker, JobStatus::Running);
+        
+        let background = queue.enqueue(render_job_options("background"), JobPriority::Background);
+        let first = queue.enqueue(render_job_options("first"), JobPriority::Normal);
+        let high = queue.enqueue(render_job_options("high"), JobPriority::High);
+        let second = queue.enqueue(render_job_options("second"), JobPriority::Normal);
+        
+        // Running first, then by priority, oldest first within a priority
+        let order: Vec<RenderJobId> = queue.jobs().iter().map(|job| job.id).collect();
+        assert_eq!(order, vec![blocker, high, first, second, background]);
+        
+        // Raising a queued job reorders it
+        queue.set_priority(background, JobPriority::High).unwrap();
+        let order: Vec<RenderJobId> = queue.jobs().iter().map(|job| job.id).collect();
+        assert_eq!(order, vec![blocker, high, background, first, second]);
+        
+        // A queued job is cancelled on the spot and never runs
+        queue.cancel(background).unwrap();
+        assert_eq!(queue.job(background).unwrap().status, JobStatus::Cancelled);
+        
+        // A running job stops, and the next job by priority takes its place
+        queue.cancel(blocker).unwrap();
+        wait_for_status(&queue, blocker, JobStatus::Cancelled);
+        wait_for_status(&queue, high, JobStatus::Running);
+        assert_eq!(queue.job(first).unwrap().status, JobStatus::Queued);
+        
+        queue.cancel(high).unwrap();
+        wait_for_status(&queue, first, JobStatus::Running);
+        assert_eq!(queue.job(background).unwrap().status, JobStatus::Cancelled);
+        assert_eq!(queue.job(second).unwrap().status, JobStatus::Queued);
+        
+        // Cancelling a finished job changes nothing, unknown jobs are an error
+        queue.cancel(blocker).unwrap();
+        assert_eq!(queue.job(blocker).unwrap().status, JobStatus::Cancelled);
+        assert!(queue.cancel(999).is_err());
+    }
+    
+    #[test]
+    fn test_render_queue_concurrency_limit() {
+        let queue = slow_render_queue(2);
+        let ids: Vec<RenderJobId> = (0..3)
+            .map(|i| queue.enqueue(render_job_options(&format!("limit_{}", i)), JobPriority::Normal))
+            .collect();
+        
+        wait_for_status(&queue, ids[0], JobStatus::Running);
+        wait_for_status(&queue, ids[1], JobStatus::Running);
+        
+        // The third job waits for a free worker however long the others take
+        let running = |queue: &RenderQueue| queue.jobs().iter().filter(|job| job.status == JobStatus::Running).count();
+        for _ in 0..5 {
+            std::thread::sleep(Duration::from_millis(100));
+            assert_eq!(running(&queue), 2);
+            assert_eq!(queue.job(ids[2]).unwrap().status, JobStatus::Queued);
+        }
+        assert!(queue.jobs().iter().all(|job| job.throttled || job.status == JobStatus::Queued));
+        
+        queue.cancel(ids[0]).unwrap();
+        wait_for_status(&queue, ids[2], JobStatus::Running);
+        assert_eq!(running(&queue), 2);
+    }
+    
+    #[test]
+    fn test_diff_projects_clip_changes() {
+        use crate::engine::timeline::{Clip, ClipType, Marker, Timeline, TimelineConfig, Track};
+        use crate::engine::timeline_diff::{diff_projects, ClipChange, MarkerChange, ProjectDiff};
+        
+        let project = || {
+            let mut timeline = Timeline::new(TimelineConfig::default());
+            for (track_id, name) in [("v1", "Video 1"), ("v2", "Video 2")] {
+                timeline.add_track(Track::new(track_id.to_string(), name.to_string())).unwrap();
+            }
+            for (id, start) in [("a", 0.0), ("b", 5.0), ("c", 10.0)] {
+                let clip = Clip::new(id.to_string(), ClipType::Video, start, 4.0)
+                    .with_source(format!("/media/{}.mov", id))
+                    .add_property("effect.blur.radius".to_string(), "2".to_string());
+                timeline.add_clip_to_track("v1", clip).unwrap();
+            }
+            timeline.add_marker(Marker::new("m1".to_string(), 1.0, "Start".to_string())).unwrap();
+            timeline
+        };
+        let changes_for = |diff: &ProjectDiff, id: &str| -> Vec<ClipChange> {
+            diff.clips.iter().find(|(clip_id, _)| clip_id == id).map(|(_, changes)| changes.clone()).unwrap_or_default()
+        };
+        
+        // Identical projects have no differences, including with themselves
+        let before = project();
+        assert!(diff_projects(&before, &project()).is_empty());
+        assert_eq!(diff_projects(&before, &before), ProjectDiff::default());
+        
+        let mut after = project();
+        // Added
+        after.add_clip_to_track("v2", Clip::new("d".to_string(), ClipType::Video, 20.0, 2.0)).unwrap();
+        // Removed
+        let removed = after.remove_clip_from_track("v1", "c").unwrap();
+        // Moved to another track and later in time
+        let mut moved = after.remove_clip_from_track("v1", "a").unwrap();
+        moved.start_time = 30.0;
+        after.add_clip_to_track("v2", moved).unwrap();
+        // Trimmed at the head, keeping its start
+        let trimmed = after.get_track_mut("v1").unwrap().clips.iter_mut().find(|clip| clip.id == "b").unwrap();
+        trimmed.set_in_point(1.5);
+        trimmed.duration = 2.5;
+        
+        let diff = diff_projects(&before, &after);
+        assert_eq!(diff.clips.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c", "d"]);
+        assert_eq!(changes_for(&diff, "a"), vec![ClipChange::Moved {
+            from_track: "v1".to_string(),
+            to_track: "v2".to_string(),
+            old_start: 0.0,
+            new_start: 30.0,
+        }]);
+        assert_eq!(changes_for(&diff, "b"), vec![ClipChange::Trimmed {
+            old_in_point: 0.0,
+            new_in_point: 1.5,
+            old_duration: 4.0,
+            new_duration: 2.5,
+        }]);
+        assert_eq!(changes_for(&diff, "c"), vec![ClipChange::Removed { track_id: "v1".to_string(), clip: removed }]);
+        assert!(matches!(&changes_for(&diff, "d")[..], [ClipChange::Added { track_id, clip }] if track_id == "v2" && clip.start_time == 20.0));
+        assert_eq!(diff.moved_clip_count(), 1);
+        assert_eq!(diff.effect_or_grade_change_count(), 0);
+        assert!(diff.tracks.is_empty());
+        assert!(diff.markers.is_empty());
+        
+        // Effect edits and marker changes are reported on their own
+        let mut regraded = project();
+        let clip = regraded.get_track_mut("v1").unwrap().clips.iter_mut().find(|clip| clip.id == "a").unwrap();
+        clip.properties.insert("effect.blur.radius".to_string(), "8".to_string());
+        regraded.add_marker(Marker::new("m2".to_string(), 2.0, "End".to_string())).unwrap();
+        let diff = diff_projects(&before, &regraded);
+        assert_eq!(changes_for(&diff, "a"), vec![ClipChange::EffectsChanged { keys: vec!["effect.blur.radius".to_string()] }]);
+        assert_eq!(diff.effect_or_grade_change_count(), 1);
+        assert!(matches!(&diff.markers[..], [MarkerChange::Added(marker)] if marker.id == "m2"));
+        
+        // Sub-epsilon time noise doesn't count as a move
+        let mut nudged = project();
+        nudged.get_track_mut("v1").unwrap().clips[0].start_time += 1e-9;
+        assert!(diff_projects(&before, &nudged).is_empty());
+    }
 }
@@ -0,0 +1,125 @@
+//! Band-parallel processing of CPU frames
+//!
+//! A `TileScheduler` splits a frame into horizontal bands of whole rows and runs a
+//! stage over them on a rayon pool. Bands are a few per thread so a slow band, e.g. one
+//! crossing a heavy vignette falloff, doesn't leave the other threads idle. Each band
+//! is processed by one thread, so stages that only touch their own rows produce the
+//! same bytes however the frame is split.
+
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::engine::renderer::RendererError;
+
+/// Bands per pool thread, for load balancing
+pub const BANDS_PER_THREAD: usize = 4;
+
+/// Rows below which a band isn't worth handing to another thread
+pub const MIN_BAND_ROWS: usize = 16;
+
+/// Scheduler shared by the renderer and the grading stages
+static GLOBAL: Lazy<TileScheduler> = Lazy::new(|| {
+    TileScheduler::new(0).unwrap_or_else(|e| {
+        tracing::warn!("Falling back to single-threaded frame processing: {}", e);
+        TileScheduler::new(1).expect("a one-thread pool")
+    })
+});
+
+/// A band of rows from a frame
+pub struct Band<'a> {
+    /// The band's rows, `stride` bytes each
+    pub data: &'a mut [u8],
+    /// Index of the band's first row in the frame
+    pub first_row: usize,
+    /// Number of rows in the band
+    pub rows: usize,
+    /// Bytes per row
+    pub stride: usize,
+}
+
+impl Band<'_> {
+    /// Bytes of row `row` of the band, counted from the band's first row
+    pub fn row_mut(&mut self, row: usize) -> &mut [u8] {
+        &mut self.data[row * self.stride..(row + 1) * self.stride]
+    }
+}
+
+/// Runs frame stages band by band on a thread pool
+pub struct TileScheduler {
+    pool: ThreadPool,
+}
+
+impl TileScheduler {
+    /// Scheduler with `threads` workers, or one per core if 0
+    pub fn new(threads: usize) -> Result<Self, RendererError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("aether-tiles-{}", index))
+            .build()
+            .map_err(|e| RendererError::InitializationError(format!("Failed to start frame processing threads: {}", e)))?;
+        Ok(Self { pool })
+    }
+
+    /// The scheduler used by the engine's CPU stages
+    pub fn global() -> &'static TileScheduler {
+        &GLOBAL
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Rows per band for a frame `height` rows tall
+    pub fn band_rows(&self, height: usize) -> usize {
+        let bands = self.threads() * BANDS_PER_THREAD;
+        height.div_ceil(bands.max(1)).max(MIN_BAND_ROWS).min(height.max(1))
+    }
+
+    /// Run `stage` on every band of a frame of `height` rows of `stride` bytes
+    ///
+    /// Bytes past the last row are left alone. Bands run in parallel and in no
+    /// particular order; this returns once all of them are done.
+    pub fn for_each_band<F>(&self, frame: &mut [u8], stride: usize, height: usize, stage: F)
+    where
+        F: Fn(Band<'_>) + Send + Sync,
+    {
+        if stride == 0 || height == 0 {
+            return;
+        }
+        let rows = height.min(frame.len() / stride);
+        let band_rows = self.band_rows(rows);
+
+        self.pool.install(|| {
+            frame[..rows * stride]
+                .par_chunks_mut(band_rows * stride)
+                .enumerate()
+                .for_each(|(index, data)| {
+                    let rows = data.len() / stride;
+                    stage(Band { data, first_row: index * band_rows, rows, stride });
+                });
+        });
+    }
+
+    /// Run `stage` on every pixel of a packed frame of `pixel_size` byte pixels
+    ///
+    /// For stages that don't care where a pixel is, such as LUTs.
+    pub fn for_each_pixel<F>(&self, frame: &mut [u8], pixel_size: usize, stage: F)
+    where
+        F: Fn(&mut [u8]) + Send + Sync,
+    {
+        if pixel_size == 0 {
+            return;
+        }
+        // Rows of a nominal width, so bands are the same size as for a real frame
+        let stride = pixel_size * 1024;
+        let height = frame.len().div_ceil(stride);
+        let pixels = frame.len() / pixel_size * pixel_size;
+
+        self.pool.install(|| {
+            frame[..pixels]
+                .par_chunks_mut(self.band_rows(height) * stride)
+                .for_each(|band| band.chunks_exact_mut(pixel_size).for_each(&stage));
+        });
+    }
+}
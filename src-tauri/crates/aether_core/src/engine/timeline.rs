@@ -1,8 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 use std::error::Error;
 use std::fmt;
 use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::engine::frame_rate::{FrameRate, FrameRateFamily};
+use crate::engine::timeline_conform::{FrameRateConform, ScaleMode};
+use crate::engine::timeline_ripple::LinkConflict;
+
+/// Clip property with the source media's frame rate
+pub const SOURCE_FPS_PROPERTY: &str = "source.fps";
+
+/// Clip properties with the source media's frame size in pixels
+pub const SOURCE_WIDTH_PROPERTY: &str = "source.width";
+pub const SOURCE_HEIGHT_PROPERTY: &str = "source.height";
+
+/// Clip property with the source media's audio sample rate in Hz
+pub const SOURCE_SAMPLE_RATE_PROPERTY: &str = "source.sample_rate";
+
+/// Clip property with the playback speed of the source, 1 for normal speed
+pub const SPEED_PROPERTY: &str = "speed";
+
+/// Default audio sample rate of a timeline, in Hz
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// Default frame size of a timeline, in pixels
+pub const DEFAULT_RESOLUTION: (u32, u32) = (1920, 1080);
 
 #[derive(Debug)]
 pub enum TimelineError {
@@ -10,6 +35,8 @@ pub enum TimelineError {
     InvalidClip(String),
     InvalidTime(String),
     OperationError(String),
+    /// Linked clips would be pulled out of sync
+    LinkConflict(Vec<LinkConflict>),
 }
 
 impl fmt::Display for TimelineError {
@@ -19,22 +46,28 @@ impl fmt::Display for TimelineError {
             TimelineError::InvalidClip(msg) => write!(f, "Invalid clip: {}", msg),
             TimelineError::InvalidTime(msg) => write!(f, "Invalid time: {}", msg),
             TimelineError::OperationError(msg) => write!(f, "Operation error: {}", msg),
+            TimelineError::LinkConflict(conflicts) => {
+                let conflicts: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+                write!(f, "Linked clips would lose sync: {}", conflicts.join("; "))
+            },
         }
     }
 }
 
 impl Error for TimelineError {}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ClipType {
     Video,
     Audio,
     Image,
     Text,
     Effect,
+    /// Test pattern or slate drawn by the engine, see `generators`
+    Generated,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Clip {
     pub id: String,
     pub clip_type: ClipType,
@@ -69,12 +102,105 @@ impl Clip {
     pub fn end_time(&self) -> f64 {
         self.start_time + self.duration
     }
+
+    /// Offset into the source media where the clip starts, in seconds
+    pub fn in_point(&self) -> f64 {
+        self.properties.get("in_point")
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    }
+
+    /// Set the source in point, in seconds
+    pub fn set_in_point(&mut self, in_point: f64) {
+        self.properties.insert("in_point".to_string(), in_point.to_string());
+    }
+
+    /// Seconds of source played per second of timeline
+    pub fn speed(&self) -> f64 {
+        self.properties.get(SPEED_PROPERTY)
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|speed| *speed > 0.0)
+            .unwrap_or(1.0)
+    }
+
+    /// Set the playback speed, removing the property at normal speed
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed == 1.0 {
+            self.properties.remove(SPEED_PROPERTY);
+        } else {
+            self.properties.insert(SPEED_PROPERTY.to_string(), speed.to_string());
+        }
+    }
+
+    /// Offset into the source media where the clip ends, in seconds
+    pub fn out_point(&self) -> f64 {
+        self.in_point() + self.duration * self.speed()
+    }
+
+    /// Frame rate of the source media, when known
+    pub fn source_fps(&self) -> Option<f64> {
+        self.properties.get(SOURCE_FPS_PROPERTY).and_then(|s| s.parse::<f64>().ok())
+    }
+
+    /// Frame size of the source media, when known
+    pub fn source_resolution(&self) -> Option<(u32, u32)> {
+        let width = self.properties.get(SOURCE_WIDTH_PROPERTY).and_then(|s| s.parse::<u32>().ok())?;
+        let height = self.properties.get(SOURCE_HEIGHT_PROPERTY).and_then(|s| s.parse::<u32>().ok())?;
+        Some((width, height))
+    }
+
+    /// Audio sample rate of the source media, when known
+    pub fn source_sample_rate(&self) -> Option<u32> {
+        self.properties.get(SOURCE_SAMPLE_RATE_PROPERTY).and_then(|s| s.parse::<u32>().ok())
+    }
     
     pub fn contains_time(&self, time: f64) -> bool {
         time >= self.start_time && time < self.end_time()
     }
 }
 
+/// How the editor shows a track, saved with the project so the frontend keeps no copy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackView {
+    /// Height in pixels, `None` for the editor's default
+    pub height: Option<u32>,
+    pub collapsed: bool,
+    pub show_waveform: bool,
+    /// Custom track color, e.g. "#3a7bd5"
+    pub color: Option<String>,
+    /// Further view settings by key, for settings the project model doesn't know about
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for TrackView {
+    fn default() -> Self {
+        Self {
+            height: None,
+            collapsed: false,
+            show_waveform: true,
+            color: None,
+            extensions: BTreeMap::new(),
+        }
+    }
+}
+
+impl TrackView {
+    pub fn extension(&self, key: &str) -> Option<&serde_json::Value> {
+        self.extensions.get(key)
+    }
+
+    /// Set a view setting, returning its previous value
+    pub fn set_extension(&mut self, key: &str, value: serde_json::Value) -> Option<serde_json::Value> {
+        self.extensions.insert(key.to_string(), value)
+    }
+
+    pub fn remove_extension(&mut self, key: &str) -> Option<serde_json::Value> {
+        self.extensions.remove(key)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Track {
     pub id: String,
@@ -82,6 +208,7 @@ pub struct Track {
     pub clips: Vec<Clip>,
     pub is_muted: bool,
     pub is_locked: bool,
+    pub view: TrackView,
 }
 
 impl Track {
@@ -92,6 +219,7 @@ impl Track {
             clips: Vec::new(),
             is_muted: false,
             is_locked: false,
+            view: TrackView::default(),
         }
     }
     
@@ -126,7 +254,31 @@ impl Track {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub id: String,
+    pub time: f64,       // In seconds
+    pub duration: f64,   // In seconds, 0 for a point marker
+    pub name: String,
+    pub note: String,
+    pub color: Option<String>,
+}
+
+impl Marker {
+    pub fn new(id: String, time: f64, name: String) -> Self {
+        Self {
+            id,
+            time,
+            duration: 0.0,
+            name,
+            note: String::new(),
+            color: None,
+        }
+    }
+}
+
 pub struct TimelineConfig {
+    /// Whole frame rate; see `Timeline::set_frame_rate` for NTSC rates
     pub fps: u32,
     pub duration: f64,  // In seconds
 }
@@ -143,7 +295,16 @@ impl Default for TimelineConfig {
 pub struct Timeline {
     config: TimelineConfig,
     tracks: HashMap<String, Track>,
+    markers: Vec<Marker>,
+    /// Exact frame rate, whose timebase `config.fps` keeps
+    frame_rate: FrameRate,
+    resolution: (u32, u32),
+    sample_rate: u32,
+    frame_rate_conform: FrameRateConform,
+    scale_mode: ScaleMode,
     current_time: f64,
+    /// In and out points (start, end), in seconds
+    work_area: Option<(f64, f64)>,
     state: Arc<Mutex<TimelineState>>,
 }
 
@@ -162,9 +323,16 @@ impl Timeline {
         };
         
         Self {
+            frame_rate: FrameRate::whole(config.fps),
             config,
             tracks: HashMap::new(),
+            markers: Vec::new(),
+            resolution: DEFAULT_RESOLUTION,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            frame_rate_conform: FrameRateConform::default(),
+            scale_mode: ScaleMode::default(),
             current_time: 0.0,
+            work_area: None,
             state: Arc::new(Mutex::new(state)),
         }
     }
@@ -203,6 +371,11 @@ impl Timeline {
     }
     
     pub fn add_clip_to_track(&mut self, track_id: &str, clip: Clip) -> Result<(), TimelineError> {
+        // Mismatched clips are kept as they are; see `conform_to_clip` and `apply_conversion`
+        for mismatch in self.format_mismatches(&clip) {
+            warn!("Clip {}: {}", clip.id, mismatch);
+        }
+        
         let track = self.get_track_mut(track_id)?;
         track.add_clip(clip)
     }
@@ -213,6 +386,39 @@ impl Timeline {
         track.remove_clip(clip_id)
     }
     
+    /// Add a marker, keeping markers sorted by time
+    pub fn add_marker(&mut self, marker: Marker) -> Result<(), TimelineError> {
+        if self.markers.iter().any(|m| m.id == marker.id) {
+            return Err(TimelineError::OperationError(
+                format!("Marker with id {} already exists", marker.id)
+            ));
+        }
+        
+        if marker.time < 0.0 {
+            return Err(TimelineError::InvalidTime(
+                format!("Marker time {} is negative", marker.time)
+            ));
+        }
+        
+        let index = self.markers.iter().position(|m| m.time > marker.time).unwrap_or(self.markers.len());
+        self.markers.insert(index, marker);
+        Ok(())
+    }
+    
+    /// Remove a marker
+    pub fn remove_marker(&mut self, marker_id: &str) -> Result<Marker, TimelineError> {
+        if let Some(index) = self.markers.iter().position(|m| m.id == marker_id) {
+            Ok(self.markers.remove(index))
+        } else {
+            Err(TimelineError::OperationError(format!("Marker with id {} not found", marker_id)))
+        }
+    }
+    
+    /// Get all markers sorted by time
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+    
     /// Set the current playback time
     pub fn seek(&mut self, time: f64) -> Result<(), TimelineError> {
         if time < 0.0 || time > self.config.duration {
@@ -314,9 +520,142 @@ impl Timeline {
             self.current_time = duration;
         }
         
+        // Keep the work area within the timeline, dropping it if nothing is left
+        if let Some((start, end)) = self.work_area {
+            self.work_area = (start < duration).then_some((start, end.min(duration)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the in and out points (start, end) in seconds, if set
+    pub fn work_area(&self) -> Option<(f64, f64)> {
+        self.work_area
+    }
+    
+    /// Set the in and out points, in seconds
+    pub fn set_work_area(&mut self, start: f64, end: f64) -> Result<(), TimelineError> {
+        if start < 0.0 || end > self.config.duration || start >= end {
+            return Err(TimelineError::InvalidTime(
+                format!("Invalid in and out points: {} to {} (timeline is {}s)", start, end, self.config.duration)
+            ));
+        }
+        
+        self.work_area = Some((start, end));
+        Ok(())
+    }
+    
+    /// Clear the in and out points
+    pub fn clear_work_area(&mut self) {
+        self.work_area = None;
+    }
+    
+    /// Get the frame rate of the timeline, rounded to whole frames
+    pub fn fps(&self) -> u32 {
+        self.config.fps
+    }
+    
+    /// Set a whole frame rate for the timeline
+    pub fn set_fps(&mut self, fps: u32) -> Result<(), TimelineError> {
+        if fps == 0 {
+            return Err(TimelineError::OperationError(
+                format!("Invalid frame rate: {}", fps)
+            ));
+        }
+        
+        self.config.fps = fps;
+        self.frame_rate = FrameRate::whole(fps);
+        Ok(())
+    }
+    
+    /// Get the exact frame rate of the timeline, e.g. 30000/1001 for 29.97
+    pub fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+    
+    /// Set the exact frame rate of the timeline; `fps` becomes the rate rounded
+    pub fn set_frame_rate(&mut self, frame_rate: FrameRate) -> Result<(), TimelineError> {
+        if frame_rate.numerator == 0 || frame_rate.denominator == 0 {
+            return Err(TimelineError::OperationError(
+                format!("Invalid frame rate: {}/{}", frame_rate.numerator, frame_rate.denominator)
+            ));
+        }
+        
+        self.config.fps = frame_rate.timebase();
+        self.frame_rate = frame_rate;
         Ok(())
     }
     
+    /// Get the family of rates the timeline's frame rate belongs to
+    pub fn frame_rate_family(&self) -> FrameRateFamily {
+        self.frame_rate.family()
+    }
+    
+    /// Number of the frame showing at `time`, in seconds
+    pub fn frame_at(&self, time: f64) -> u64 {
+        // Frame starts from `frame_time` can come back a hair early
+        (time.max(0.0) * self.frame_rate.as_f64() + 1e-6).floor() as u64
+    }
+    
+    /// Start of frame `frame`, in seconds
+    pub fn frame_time(&self, frame: u64) -> f64 {
+        self.frame_rate.frames_to_seconds(frame)
+    }
+    
+    /// Get the frame size of the timeline, in pixels
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+    
+    /// Set the frame size of the timeline
+    pub fn set_resolution(&mut self, width: u32, height: u32) -> Result<(), TimelineError> {
+        if width == 0 || height == 0 {
+            return Err(TimelineError::OperationError(
+                format!("Invalid resolution: {}x{}", width, height)
+            ));
+        }
+        
+        self.resolution = (width, height);
+        Ok(())
+    }
+    
+    /// Get the audio sample rate of the timeline, in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    
+    /// Set the audio sample rate of the timeline
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), TimelineError> {
+        if sample_rate == 0 {
+            return Err(TimelineError::OperationError(
+                format!("Invalid sample rate: {}", sample_rate)
+            ));
+        }
+        
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+    
+    /// Get the default strategy for clips whose frame rate differs from the timeline's
+    pub fn frame_rate_conform(&self) -> FrameRateConform {
+        self.frame_rate_conform
+    }
+    
+    /// Set the default frame rate strategy; clips can override it
+    pub fn set_frame_rate_conform(&mut self, strategy: FrameRateConform) {
+        self.frame_rate_conform = strategy;
+    }
+    
+    /// Get the default placement for clips whose size or aspect differs from the timeline's
+    pub fn scale_mode(&self) -> ScaleMode {
+        self.scale_mode
+    }
+    
+    /// Set the default scale mode; clips can override it
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.scale_mode = mode;
+    }
+    
     /// Get all tracks in the timeline
     pub fn tracks(&self) -> &HashMap<String, Track> {
         &self.tracks
@@ -326,6 +665,29 @@ impl Timeline {
     pub fn is_playing(&self) -> bool {
         self.state.lock().unwrap().is_playing
     }
+    
+    /// Copy of the timeline's settings and markers with the tracks `track` returns, for
+    /// rendering a variant without changing the project
+    ///
+    /// The copy starts stopped at the beginning.
+    pub fn copy_with_tracks<F>(&self, mut track: F) -> Timeline
+    where
+        F: FnMut(&Track) -> Option<Track>,
+    {
+        let mut copy = Timeline::new(TimelineConfig { fps: self.config.fps, duration: self.config.duration });
+        copy.tracks = self.tracks.values()
+            .filter_map(&mut track)
+            .map(|track| (track.id.clone(), track))
+            .collect();
+        copy.markers = self.markers.clone();
+        copy.frame_rate = self.frame_rate;
+        copy.resolution = self.resolution;
+        copy.sample_rate = self.sample_rate;
+        copy.frame_rate_conform = self.frame_rate_conform;
+        copy.scale_mode = self.scale_mode;
+        copy.work_area = self.work_area;
+        copy
+    }
 }
 
 /// Factory function to create a timeline with default configuration
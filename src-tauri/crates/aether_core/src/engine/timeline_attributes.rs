@@ -0,0 +1,129 @@
+//! Copying clip attributes from one clip to others
+//!
+//! Attributes are clip properties grouped by key: effects, transform, grade and audio
+//! settings by prefix, plus the clip's speed. `Timeline::copy_attributes` takes them
+//! from a clip and `Timeline::paste_attributes` applies the groups selected by an
+//! `AttributeMask` to any number of clips.
+
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use crate::engine::timeline::{Clip, Timeline, TimelineError, SPEED_PROPERTY};
+
+/// Property key prefix for effect parameters, as `effect.<type>.<parameter>`
+pub const EFFECT_PREFIX: &str = "effect.";
+
+/// Property key prefix for position, scale, rotation, crop and opacity
+pub const TRANSFORM_PREFIX: &str = "transform.";
+
+/// Property key prefix for grade parameters
+pub const GRADE_PREFIX: &str = "grade.";
+
+/// Property key prefix for volume, pan and audio filters
+pub const AUDIO_PREFIX: &str = "audio.";
+
+/// Which attribute groups a paste applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttributeMask {
+    pub effects: bool,
+    pub transform: bool,
+    pub speed: bool,
+    pub grade: bool,
+    pub audio: bool,
+}
+
+impl Default for AttributeMask {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl AttributeMask {
+    pub fn all() -> Self {
+        Self { effects: true, transform: true, speed: true, grade: true, audio: true }
+    }
+
+    pub fn none() -> Self {
+        Self { effects: false, transform: false, speed: false, grade: false, audio: false }
+    }
+
+    /// Whether a property key belongs to a selected group
+    pub fn covers(&self, key: &str) -> bool {
+        (self.effects && key.starts_with(EFFECT_PREFIX))
+            || (self.transform && key.starts_with(TRANSFORM_PREFIX))
+            || (self.speed && key == SPEED_PROPERTY)
+            || (self.grade && key.starts_with(GRADE_PREFIX))
+            || (self.audio && key.starts_with(AUDIO_PREFIX))
+    }
+}
+
+/// Attributes copied from a clip
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClipAttributes {
+    /// Properties of every attribute group, by key
+    pub properties: BTreeMap<String, String>,
+}
+
+impl ClipAttributes {
+    pub fn from_clip(clip: &Clip) -> Self {
+        let all = AttributeMask::all();
+        Self {
+            properties: clip.properties.iter()
+                .filter(|(key, _)| all.covers(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Replace the selected groups of `clip` with these attributes
+    ///
+    /// A selected group the copied clip didn't have is cleared, so pasting from a clip
+    /// without effects removes the target's effects. Durations stay the same when the
+    /// speed changes, so the clip plays more or less of its source.
+    pub fn apply_to(&self, clip: &mut Clip, mask: &AttributeMask) {
+        clip.properties.retain(|key, _| !mask.covers(key));
+        clip.properties.extend(self.properties.iter()
+            .filter(|(key, _)| mask.covers(key))
+            .map(|(key, value)| (key.clone(), value.clone())));
+    }
+}
+
+impl Timeline {
+    /// Copy the attributes of a clip
+    pub fn copy_attributes(&self, clip_id: &str) -> Result<ClipAttributes, TimelineError> {
+        self.tracks().values()
+            .flat_map(|track| track.clips.iter())
+            .find(|clip| clip.id == clip_id)
+            .map(ClipAttributes::from_clip)
+            .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))
+    }
+
+    /// Paste the groups of `attributes` selected by `mask` onto clips
+    ///
+    /// Fails without changing anything if a clip doesn't exist or is on a locked track.
+    pub fn paste_attributes(&mut self, clip_ids: &[&str], attributes: &ClipAttributes, mask: &AttributeMask) -> Result<(), TimelineError> {
+        let mut targets = Vec::new();
+        for clip_id in clip_ids {
+            let track = self.tracks().values()
+                .find(|track| track.clips.iter().any(|clip| clip.id == *clip_id))
+                .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+            if track.is_locked {
+                return Err(TimelineError::InvalidTrack(format!("Track {} of clip {} is locked", track.id, clip_id)));
+            }
+            targets.push((track.id.clone(), clip_id.to_string()));
+        }
+
+        for (track_id, clip_id) in &targets {
+            let track = self.get_track_mut(track_id)?;
+            if let Some(clip) = track.clips.iter_mut().find(|clip| clip.id == *clip_id) {
+                attributes.apply_to(clip, mask);
+            }
+        }
+        debug!("Pasted attributes onto {} clips", targets.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use gstreamer as gst;
+use gstreamer_editing_services as ges;
+use gst::prelude::*;
+use ges::prelude::*;
+use tracing::{debug, info, warn};
+
+use crate::engine::editing::{EditingError, PreviewEngine, PreviewFrame};
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::framing::SafeAreaGuides;
+use crate::engine::generators::Generator;
+use crate::engine::timeline::{ClipType, Timeline};
+use crate::engine::timeline_conform::{FrameRateConform, ScaleMode};
+use crate::engine::timeline_validation::DiagnosticKind;
+use crate::engine::timeline_renderer::{TimelineRenderer, TimelineRendererConfig};
+use crate::modules::backend_policy::{Backend, BackendPolicy, Subsystem};
+use crate::modules::path_policy;
+use crate::modules::pipeline_watchdog::{self, DEFAULT_SHUTDOWN_TIMEOUT};
+
+/// How long a GES seek may take to preroll a frame
+const PREROLL_TIMEOUT_SECONDS: u64 = 5;
+
+/// Directory under the temp dir holding stills of generated clips for GES
+const GENERATED_STILLS_DIR: &str = "aether_generated";
+
+/// Implementation used to play back a `Timeline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineBackendKind {
+    /// GStreamer Editing Services, with transitions and effects
+    Ges,
+    /// Per-clip decoders and a software compositor; cuts only, but no GES needed
+    Lightweight,
+}
+
+impl fmt::Display for TimelineBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineBackendKind::Ges => write!(f, "GES"),
+            TimelineBackendKind::Lightweight => write!(f, "lightweight"),
+        }
+    }
+}
+
+/// Output settings shared by the backends
+#[derive(Debug, Clone)]
+pub struct TimelineBackendConfig {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+}
+
+impl Default for TimelineBackendConfig {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fps: 30.0,
+        }
+    }
+}
+
+/// Renders frames of a `Timeline` model
+pub trait TimelineBackend {
+    fn kind(&self) -> TimelineBackendKind;
+
+    /// Build the backend's representation of the timeline, replacing any previous one
+    ///
+    /// Fails if `Timeline::validate` reports errors.
+    fn load(&mut self, timeline: Arc<Mutex<Timeline>>) -> Result<(), EditingError>;
+
+    /// Composited frame at a timeline time in seconds
+    fn render_frame(&mut self, time: f64) -> Result<PreviewFrame, EditingError>;
+
+    /// Release decoders and pipelines
+    fn shutdown(&mut self) -> Result<(), EditingError>;
+
+    /// Show or hide safe-area guides on rendered frames
+    ///
+    /// Only for previews; backends without an overlay ignore it.
+    fn set_safe_area(&mut self, _guides: Option<SafeAreaGuides>) {}
+}
+
+/// Whether GES and its composition plugins are installed
+pub fn ges_available() -> bool {
+    gst::init().is_ok()
+        && ges::init().is_ok()
+        && gst::ElementFactory::find("nlecomposition").is_some()
+}
+
+/// Whether a backend can run on this system
+pub fn is_available(kind: TimelineBackendKind) -> bool {
+    match kind {
+        TimelineBackendKind::Ges => ges_available(),
+        TimelineBackendKind::Lightweight => Backend::FFmpeg.is_available(),
+    }
+}
+
+/// Create a specific backend
+pub fn create_backend(kind: TimelineBackendKind, config: TimelineBackendConfig) -> Result<Box<dyn TimelineBackend>, EditingError> {
+    if !is_available(kind) {
+        return Err(EditingError::NotSupported(format!("The {} timeline backend is not available", kind)));
+    }
+
+    Ok(match kind {
+        TimelineBackendKind::Ges => Box::new(GesTimelineBackend::new()?),
+        TimelineBackendKind::Lightweight => Box::new(LightweightTimelineBackend::new(config)),
+    })
+}
+
+/// Create the backend the preview policy asks for, falling back to the lightweight one when
+/// GES is missing
+///
+/// GStreamer preference maps to GES and FFmpeg to the lightweight backend.
+pub fn create_timeline_backend(policy: &BackendPolicy, config: TimelineBackendConfig) -> Result<Box<dyn TimelineBackend>, EditingError> {
+    for backend in policy.candidates(Subsystem::Preview) {
+        let kind = match backend {
+            Backend::GStreamer => TimelineBackendKind::Ges,
+            Backend::FFmpeg => TimelineBackendKind::Lightweight,
+        };
+
+        if !is_available(kind) {
+            warn!("The {} timeline backend is not available", kind);
+            continue;
+        }
+
+        info!("Using the {} timeline backend", kind);
+        return create_backend(kind, config);
+    }
+
+    Err(EditingError::NotSupported("No timeline backend available".to_string()))
+}
+
+/// Validate a timeline before loading it, logging warnings and failing on errors
+fn check_timeline(timeline: &Timeline) -> Result<(), EditingError> {
+    let mut report = timeline.validate();
+    // Missing media plays as a media offline slate, or as silence for sound, rather
+    // than stopping playback
+    report.diagnostics.retain(|diagnostic| {
+        let offline = matches!(diagnostic.kind, DiagnosticKind::MissingMedia { .. });
+        if offline {
+            warn!("Timeline: {}, playing it as offline", diagnostic);
+        }
+        !offline
+    });
+    for warning in report.warnings() {
+        warn!("Timeline: {}", warning);
+    }
+
+    if !report.is_valid() {
+        return Err(EditingError::TimelineError(report.error_summary()));
+    }
+    Ok(())
+}
+
+/// Plays the timeline through a GES pipeline
+pub struct GesTimelineBackend {
+    ges_timeline: Option<ges::Timeline>,
+
+    pipeline: Option<ges::Pipeline>,
+
+    preview: PreviewEngine,
+}
+
+impl GesTimelineBackend {
+    pub fn new() -> Result<Self, EditingError> {
+        let mut preview = PreviewEngine::new()?;
+        // Frames are only kept for `get_frame` while a callback is set
+        preview.set_frame_callback(|_| {});
+
+        Ok(Self {
+            ges_timeline: None,
+            pipeline: None,
+            preview,
+        })
+    }
+
+    /// Translate the model into GES layers, one per unmuted track
+    fn build_ges_timeline(timeline: &Timeline) -> Result<ges::Timeline, EditingError> {
+        let ges_timeline = ges::Timeline::new_audio_video()?;
+
+        // Exact timeline rate, so 29.97 renders at 30000/1001 rather than a rounded rate
+        let rate = timeline.frame_rate();
+        for ges_track in ges_timeline.tracks() {
+            if ges_track.track_type() == ges::TrackType::VIDEO {
+                let caps = gst::Caps::builder("video/x-raw")
+                    .field("framerate", gst::Fraction::new(rate.numerator as i32, rate.denominator as i32))
+                    .build();
+                ges_track.update_restriction_caps(&caps);
+            }
+        }
+
+        // Stable layer order; tracks live in a map
+        let mut tracks: Vec<_> = timeline.tracks().values().filter(|track| !track.is_muted).collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let (width, height) = timeline.resolution();
+        let stills_dir = std::env::temp_dir().join(GENERATED_STILLS_DIR);
+
+        for track in tracks {
+            let layer = ges_timeline.append_layer();
+            for clip in &track.clips {
+                let track_types = match clip.clip_type {
+                    ClipType::Video | ClipType::Image | ClipType::Generated => ges::TrackType::VIDEO,
+                    ClipType::Audio => ges::TrackType::AUDIO,
+                    ClipType::Text | ClipType::Effect => continue,
+                };
+
+                // Generated clips and missing pictures play as stills drawn at the
+                // timeline's size, a still a frame for a countdown; missing sound is
+                // left out. Each source is (path, offset into the clip, in point, length).
+                let sources = match Generator::for_clip(clip) {
+                    Some(generator) => generator.segments(clip.duration, rate.as_f64())
+                        .into_iter()
+                        .map(|(offset, length)| {
+                            let still = generator.still_at(&stills_dir, width, height, offset, clip.duration, rate.as_f64())
+                                .map_err(|e| EditingError::PreviewError(format!("Failed to draw {} for clip {}: {}", generator.as_str(), clip.id, e)))?;
+                            Ok((still, offset, 0.0, length))
+                        })
+                        .collect::<Result<Vec<_>, EditingError>>()?,
+                    None => match &clip.source_path {
+                        Some(path) if Path::new(path).exists() => vec![(Path::new(path).to_path_buf(), 0.0, clip.in_point(), clip.duration)],
+                        _ => continue,
+                    },
+                };
+
+                // GES retimes by dropping and repeating frames
+                let strategy = timeline.clip_frame_rate_conform(clip);
+                let retimed = clip.source_fps().is_some_and(|fps| FrameRate::from_fps(fps) != timeline.frame_rate());
+                if clip.clip_type == ClipType::Video && retimed && strategy != FrameRateConform::Nearest {
+                    warn!("Clip {}: {} frame rate conform is not supported by GES, using nearest frames", clip.id, strategy.as_str());
+                }
+
+                // GES letterboxes mismatched frames
+                let scale_mode = timeline.clip_scale_mode(clip);
+                if clip.clip_type != ClipType::Audio && scale_mode != ScaleMode::Fit {
+                    warn!("Clip {}: {} scale mode is not supported by GES, fitting to the frame", clip.id, scale_mode.as_str());
+                }
+
+                for (source_path, offset, in_point, length) in sources {
+                    let uri = path_policy::path_to_uri(&source_path)
+                        .map_err(|e| EditingError::PreviewError(e.to_string()))?;
+                    let asset = ges::UriClipAsset::request_sync(&uri)?;
+                    layer.add_asset(
+                        &asset,
+                        gst::ClockTime::from_seconds_f64(clip.start_time + offset),
+                        gst::ClockTime::from_seconds_f64(in_point),
+                        gst::ClockTime::from_seconds_f64(length),
+                        track_types,
+                    )?;
+                }
+            }
+        }
+
+        Ok(ges_timeline)
+    }
+}
+
+impl TimelineBackend for GesTimelineBackend {
+    fn kind(&self) -> TimelineBackendKind {
+        TimelineBackendKind::Ges
+    }
+
+    fn load(&mut self, timeline: Arc<Mutex<Timeline>>) -> Result<(), EditingError> {
+        self.shutdown()?;
+
+        let ges_timeline = {
+            let timeline = timeline.lock().unwrap();
+            check_timeline(&timeline)?;
+            Self::build_ges_timeline(&timeline)?
+        };
+        let pipeline = ges::Pipeline::new();
+        pipeline.set_timeline(&ges_timeline)?;
+        self.preview.set_pipeline(Some(pipeline.clone()))?;
+        pipeline.set_state(gst::State::Paused)?;
+
+        debug!("Loaded timeline into GES");
+        self.ges_timeline = Some(ges_timeline);
+        self.pipeline = Some(pipeline);
+        Ok(())
+    }
+
+    fn render_frame(&mut self, time: f64) -> Result<PreviewFrame, EditingError> {
+        let pipeline = self.pipeline.as_ref().ok_or(EditingError::NotInitialized)?;
+
+        self.preview.seek((time.max(0.0) * 1_000_000_000.0) as i64)?;
+        let (result, _, _) = pipeline.state(gst::ClockTime::from_seconds(PREROLL_TIMEOUT_SECONDS));
+        result.map_err(|_| EditingError::PreviewError(format!("No frame at {:.3}s", time)))?;
+
+        self.preview
+            .get_frame()?
+            .ok_or_else(|| EditingError::PreviewError(format!("No frame at {:.3}s", time)))
+    }
+
+    fn shutdown(&mut self) -> Result<(), EditingError> {
+        self.preview.set_pipeline(None)?;
+        if let Some(pipeline) = self.pipeline.take() {
+            pipeline_watchdog::shutdown_pipeline(pipeline.upcast_ref(), DEFAULT_SHUTDOWN_TIMEOUT);
+        }
+        self.ges_timeline = None;
+        Ok(())
+    }
+
+    fn set_safe_area(&mut self, guides: Option<SafeAreaGuides>) {
+        self.preview.set_safe_area(guides);
+    }
+}
+
+impl Drop for GesTimelineBackend {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+/// Decodes each clip directly and composites in software
+///
+/// Handles cuts-only timelines without GES; transitions and effects are ignored.
+pub struct LightweightTimelineBackend {
+    config: TimelineBackendConfig,
+
+    renderer: Option<TimelineRenderer>,
+
+    /// Kept so a reloaded timeline keeps its guides
+    safe_area: Option<SafeAreaGuides>,
+}
+
+impl LightweightTimelineBackend {
+    pub fn new(config: TimelineBackendConfig) -> Self {
+        Self {
+            config,
+            renderer: None,
+            safe_area: None,
+        }
+    }
+}
+
+impl TimelineBackend for LightweightTimelineBackend {
+    fn kind(&self) -> TimelineBackendKind {
+        TimelineBackendKind::Lightweight
+    }
+
+    fn load(&mut self, timeline: Arc<Mutex<Timeline>>) -> Result<(), EditingError> {
+        self.shutdown()?;
+        check_timeline(&timeline.lock().unwrap())?;
+
+        let config = TimelineRendererConfig {
+            width: self.config.width,
+            height: self.config.height,
+            fps: self.config.fps,
+            safe_area: self.safe_area,
+            ..TimelineRendererConfig::default()
+        };
+        let mut renderer = TimelineRenderer::new(config, timeline)
+            .map_err(|e| EditingError::PreviewError(e.to_string()))?;
+        renderer.initialize().map_err(|e| EditingError::PreviewError(e.to_string()))?;
+
+        self.renderer = Some(renderer);
+        Ok(())
+    }
+
+    fn render_frame(&mut self, time: f64) -> Result<PreviewFrame, EditingError> {
+        let fps = self.config.fps;
+        let renderer = self.renderer.as_mut().ok_or(EditingError::NotInitialized)?;
+        let frame = renderer
+            .render_frame(time)
+            .map_err(|e| EditingError::PreviewError(e.to_string()))?;
+
+        Ok(PreviewFrame {
+            width: frame.width,
+            height: frame.height,
+            data: frame.data.clone(),
+            pts: (frame.timestamp * 1_000_000_000.0) as i64,
+            duration: (1_000_000_000.0 / fps.max(1.0)) as i64,
+            format: "RGBA".to_string(),
+        })
+    }
+
+    fn shutdown(&mut self) -> Result<(), EditingError> {
+        if let Some(mut renderer) = self.renderer.take() {
+            renderer.cleanup().map_err(|e| EditingError::PreviewError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn set_safe_area(&mut self, guides: Option<SafeAreaGuides>) {
+        self.safe_area = guides;
+        if let Some(renderer) = &mut self.renderer {
+            renderer.set_safe_area(guides);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use tracing::{debug, warn};
+
+use crate::engine::timeline::{Clip, Marker, Timeline, TimelineError};
+use crate::modules::metrics::names;
+use crate::modules::spill_store::{self, Spillable, SpillStore, MB};
+
+/// Steps kept for undo unless configured otherwise
+pub const DEFAULT_UNDO_LIMIT: usize = 100;
+
+/// Bytes of undo steps kept in memory before older ones are spilled to disk
+pub const DEFAULT_UNDO_MEMORY: usize = 64 * MB;
+
+/// An undoable edit to a timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TimelineCommand {
+    AddClip {
+        track_id: String,
+        clip: Clip,
+    },
+    RemoveClip {
+        track_id: String,
+        clip_id: String,
+    },
+    MoveClip {
+        track_id: String,
+        clip_id: String,
+        start_time: f64,
+    },
+    TrimClip {
+        track_id: String,
+        clip_id: String,
+        duration: f64,
+    },
+    /// Set a clip property, or remove it when `value` is `None`
+    SetClipProperty {
+        track_id: String,
+        clip_id: String,
+        key: String,
+        value: Option<String>,
+    },
+    AddMarker {
+        marker: Marker,
+    },
+    RemoveMarker {
+        marker_id: String,
+    },
+}
+
+impl TimelineCommand {
+    /// Short description for undo menus
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimelineCommand::AddClip { .. } => "Add clip",
+            TimelineCommand::RemoveClip { .. } => "Remove clip",
+            TimelineCommand::MoveClip { .. } => "Move clip",
+            TimelineCommand::TrimClip { .. } => "Trim clip",
+            TimelineCommand::SetClipProperty { .. } => "Change clip property",
+            TimelineCommand::AddMarker { .. } => "Add marker",
+            TimelineCommand::RemoveMarker { .. } => "Remove marker",
+        }
+    }
+
+    /// Apply the command, returning the command that reverts it
+    ///
+    /// A command that fails leaves the timeline unchanged.
+    pub fn apply(&self, timeline: &mut Timeline) -> Result<TimelineCommand, TimelineError> {
+        match self {
+            TimelineCommand::AddClip { track_id, clip } => {
+                timeline.add_clip_to_track(track_id, clip.clone())?;
+                Ok(TimelineCommand::RemoveClip {
+                    track_id: track_id.clone(),
+                    clip_id: clip.id.clone(),
+                })
+            },
+            TimelineCommand::RemoveClip { track_id, clip_id } => {
+                let clip = timeline.remove_clip_from_track(track_id, clip_id)?;
+                Ok(TimelineCommand::AddClip {
+                    track_id: track_id.clone(),
+                    clip,
+                })
+            },
+            TimelineCommand::MoveClip { track_id, clip_id, start_time } => {
+                if *start_time < 0.0 {
+                    return Err(TimelineError::InvalidTime(format!("Clip start {} is negative", start_time)));
+                }
+
+                let old_start = replace_clip(timeline, track_id, clip_id, |clip| {
+                    std::mem::replace(&mut clip.start_time, *start_time)
+                })?;
+                Ok(TimelineCommand::MoveClip {
+                    track_id: track_id.clone(),
+                    clip_id: clip_id.clone(),
+                    start_time: old_start,
+                })
+            },
+            TimelineCommand::TrimClip { track_id, clip_id, duration } => {
+                if *duration <= 0.0 {
+                    return Err(TimelineError::InvalidTime(format!("Invalid clip duration: {}", duration)));
+                }
+
+                let old_duration = replace_clip(timeline, track_id, clip_id, |clip| {
+                    std::mem::replace(&mut clip.duration, *duration)
+                })?;
+                Ok(TimelineCommand::TrimClip {
+                    track_id: track_id.clone(),
+                    clip_id: clip_id.clone(),
+                    duration: old_duration,
+                })
+            },
+            TimelineCommand::SetClipProperty { track_id, clip_id, key, value } => {
+                let track = timeline.get_track_mut(track_id)?;
+                let clip = track.clips.iter_mut()
+                    .find(|clip| &clip.id == clip_id)
+                    .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+
+                let old_value = match value {
+                    Some(value) => clip.properties.insert(key.clone(), value.clone()),
+                    None => clip.properties.remove(key),
+                };
+                Ok(TimelineCommand::SetClipProperty {
+                    track_id: track_id.clone(),
+                    clip_id: clip_id.clone(),
+                    key: key.clone(),
+                    value: old_value,
+                })
+            },
+            TimelineCommand::AddMarker { marker } => {
+                timeline.add_marker(marker.clone())?;
+                Ok(TimelineCommand::RemoveMarker {
+                    marker_id: marker.id.clone(),
+                })
+            },
+            TimelineCommand::RemoveMarker { marker_id } => {
+                let marker = timeline.remove_marker(marker_id)?;
+                Ok(TimelineCommand::AddMarker { marker })
+            },
+        }
+    }
+}
+
+/// Change a clip by taking it off its track and putting it back, so overlap checks apply
+///
+/// The clip is restored if it no longer fits.
+fn replace_clip<T>(
+    timeline: &mut Timeline,
+    track_id: &str,
+    clip_id: &str,
+    change: impl FnOnce(&mut Clip) -> T,
+) -> Result<T, TimelineError> {
+    let track = timeline.get_track_mut(track_id)?;
+    let index = track.clips.iter()
+        .position(|clip| clip.id == clip_id)
+        .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+
+    let original = track.clips.remove(index);
+    let mut changed = original.clone();
+    let previous = change(&mut changed);
+
+    match track.add_clip(changed) {
+        Ok(()) => {
+            // Keep the clip's position in the track's list
+            let clip = track.clips.pop().expect("clip was just added");
+            track.clips.insert(index, clip);
+            Ok(previous)
+        },
+        Err(e) => {
+            track.clips.insert(index, original);
+            Err(e)
+        },
+    }
+}
+
+/// Apply commands as one unit, reverting the applied ones if any fails
+fn apply_all(timeline: &mut Timeline, commands: &[TimelineCommand]) -> Result<Vec<TimelineCommand>, TimelineError> {
+    let mut inverses = Vec::with_capacity(commands.len());
+
+    for command in commands {
+        match command.apply(timeline) {
+            Ok(inverse) => inverses.push(inverse),
+            Err(e) => {
+                for inverse in inverses.iter().rev() {
+                    if let Err(rollback) = inverse.apply(timeline) {
+                        warn!("Failed to roll back {}: {}", inverse.label(), rollback);
+                    }
+                }
+                return Err(e);
+            },
+        }
+    }
+
+    Ok(inverses)
+}
+
+/// One undo step, made of one or more commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    label: String,
+    commands: Vec<TimelineCommand>,
+    /// Reverts `commands`, in the order they were applied
+    inverses: Vec<TimelineCommand>,
+}
+
+impl Spillable for HistoryEntry {
+    fn memory_size(&self) -> usize {
+        // Commands hold clips with arbitrary properties, so their JSON size stands in
+        // for the heap they use
+        std::mem::size_of::<Self>() + serde_json::to_vec(self).map(|json| json.len()).unwrap_or(0)
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        spill_store::write_json(writer, self)
+    }
+
+    fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        spill_store::read_json(reader)
+    }
+}
+
+/// Undo and redo for timeline edits
+///
+/// Edits go through `execute` or `execute_group` so they can be undone. While recording,
+/// executed steps are also captured for macros; undoing a step during recording drops it
+/// from the recording too.
+///
+/// The stacks only hold step IDs and labels. Steps themselves live in a `SpillStore`, so
+/// once they take more than the memory limit the oldest are kept on disk until undone.
+pub struct CommandHistory {
+    /// Step IDs and labels, oldest first
+    undo_stack: Vec<(u64, String)>,
+    redo_stack: Vec<(u64, String)>,
+    entries: SpillStore<u64, HistoryEntry>,
+    next_id: u64,
+    limit: usize,
+    recording: Option<Vec<Vec<TimelineCommand>>>,
+}
+
+impl CommandHistory {
+    /// Create a history keeping at most `limit` undo steps
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            entries: SpillStore::in_temp(names::UNDO_HISTORY, DEFAULT_UNDO_MEMORY),
+            next_id: 0,
+            limit: limit.max(1),
+            recording: None,
+        }
+    }
+
+    /// Bytes of undo steps kept in memory before older ones are spilled to disk
+    pub fn memory_limit(&self) -> usize {
+        self.entries.memory_limit()
+    }
+
+    pub fn set_memory_limit(&mut self, bytes: usize) {
+        self.entries.set_memory_limit(bytes);
+    }
+
+    /// Bytes of undo steps currently in memory
+    pub fn memory_used(&self) -> usize {
+        self.entries.memory_used()
+    }
+
+    /// Number of undo and redo steps currently spilled to disk
+    pub fn spilled_steps(&self) -> usize {
+        self.entries.spilled_len()
+    }
+
+    /// Apply a command as its own undo step
+    pub fn execute(&mut self, timeline: &mut Timeline, command: TimelineCommand) -> Result<(), TimelineError> {
+        let label = command.label().to_string();
+        self.execute_group(timeline, &label, vec![command])
+    }
+
+    /// Apply commands as a single undo step; if one fails none are applied
+    pub fn execute_group(&mut self, timeline: &mut Timeline, label: &str, commands: Vec<TimelineCommand>) -> Result<(), TimelineError> {
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let inverses = apply_all(timeline, &commands)?;
+        debug!("Executed {} ({} commands)", label, commands.len());
+
+        if let Some(recording) = &mut self.recording {
+            recording.push(commands.clone());
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(id, HistoryEntry {
+            label: label.to_string(),
+            commands,
+            inverses,
+        });
+        self.undo_stack.push((id, label.to_string()));
+        if self.undo_stack.len() > self.limit {
+            let (oldest, _) = self.undo_stack.remove(0);
+            self.entries.discard(&oldest);
+        }
+        for (id, _) in self.redo_stack.drain(..) {
+            self.entries.discard(&id);
+        }
+
+        Ok(())
+    }
+
+    /// Undo the last step, returning its label, or `None` if there is nothing to undo
+    pub fn undo(&mut self, timeline: &mut Timeline) -> Result<Option<String>, TimelineError> {
+        let (id, label) = match self.undo_stack.last() {
+            Some(step) => step.clone(),
+            None => return Ok(None),
+        };
+
+        let reverted: Vec<TimelineCommand> = self.entry(id)?.inverses.iter().rev().cloned().collect();
+        apply_all(timeline, &reverted)?;
+
+        if let Some(recording) = &mut self.recording {
+            recording.pop();
+        }
+
+        self.undo_stack.pop();
+        self.redo_stack.push((id, label.clone()));
+        Ok(Some(label))
+    }
+
+    /// Redo the last undone step, returning its label, or `None` if there is nothing to redo
+    pub fn redo(&mut self, timeline: &mut Timeline) -> Result<Option<String>, TimelineError> {
+        let (id, label) = match self.redo_stack.last() {
+            Some(step) => step.clone(),
+            None => return Ok(None),
+        };
+
+        let mut entry = self.entry(id)?.clone();
+        entry.inverses = apply_all(timeline, &entry.commands)?;
+
+        if let Some(recording) = &mut self.recording {
+            recording.push(entry.commands.clone());
+        }
+
+        self.entries.insert(id, entry);
+        self.redo_stack.pop();
+        self.undo_stack.push((id, label.clone()));
+        Ok(Some(label))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo_stack.last().map(|(_, label)| label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo_stack.last().map(|(_, label)| label.as_str())
+    }
+
+    /// Forget all undo and redo steps
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.entries.clear();
+    }
+
+    /// Start capturing executed commands, discarding any recording in progress
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stop recording and return the commands executed since `start_recording`
+    pub fn stop_recording(&mut self) -> Vec<TimelineCommand> {
+        self.recording.take().unwrap_or_default().into_iter().flatten().collect()
+    }
+
+    /// A step's commands, read back from disk if they were spilled
+    fn entry(&mut self, id: u64) -> Result<&HistoryEntry, TimelineError> {
+        self.entries
+            .get(&id)
+            .map_err(|e| TimelineError::OperationError(format!("Failed to load undo step: {}", e)))?
+            .ok_or_else(|| TimelineError::OperationError(format!("Undo step {} is missing", id)))
+    }
+}
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_UNDO_LIMIT)
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::timeline::{
+    Clip, ClipType, Timeline, TimelineError, SOURCE_FPS_PROPERTY, SOURCE_HEIGHT_PROPERTY,
+    SOURCE_SAMPLE_RATE_PROPERTY, SOURCE_WIDTH_PROPERTY,
+};
+
+/// Clip property with the `ScaleMode` used to fit a mismatched frame size
+pub const SCALE_MODE_PROPERTY: &str = "conform.scale";
+
+/// Clip property set when mismatched audio should be resampled to the timeline rate
+pub const RESAMPLE_PROPERTY: &str = "conform.resample";
+
+/// Clip property with the `FrameRateConform` strategy for a mismatched frame rate,
+/// overriding the timeline default
+pub const CONFORM_FPS_PROPERTY: &str = "conform.fps";
+
+/// Frame rates closer than this are treated as equal
+const FPS_EPSILON: f64 = 0.001;
+
+/// Format a timeline renders at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceSettings {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: FrameRate,
+    pub sample_rate: u32,
+}
+
+/// Format of a clip's source media, as far as it is known
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SourceFormat {
+    pub resolution: Option<(u32, u32)>,
+    pub fps: Option<f64>,
+    pub sample_rate: Option<u32>,
+}
+
+impl SourceFormat {
+    /// Format recorded in a clip's source properties
+    pub fn of(clip: &Clip) -> Self {
+        Self {
+            resolution: clip.source_resolution(),
+            fps: clip.source_fps(),
+            sample_rate: clip.source_sample_rate(),
+        }
+    }
+
+    /// Record the format in a clip's source properties
+    pub fn apply_to(&self, clip: &mut Clip) {
+        if let Some((width, height)) = self.resolution {
+            clip.properties.insert(SOURCE_WIDTH_PROPERTY.to_string(), width.to_string());
+            clip.properties.insert(SOURCE_HEIGHT_PROPERTY.to_string(), height.to_string());
+        }
+        if let Some(fps) = self.fps {
+            clip.properties.insert(SOURCE_FPS_PROPERTY.to_string(), fps.to_string());
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            clip.properties.insert(SOURCE_SAMPLE_RATE_PROPERTY.to_string(), sample_rate.to_string());
+        }
+    }
+}
+
+/// How a frame of a different size is placed in the timeline frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Scale to fit inside the frame, letterboxing or pillarboxing as needed
+    #[default]
+    Fit,
+    /// Scale to cover the frame, cropping as needed
+    Fill,
+    /// Scale to the frame size, ignoring aspect ratio
+    Stretch,
+    /// Keep the source size, centred
+    None,
+    /// Fit, then apply the clip's own zoom and offset; see `CustomFraming`
+    Custom,
+}
+
+impl ScaleMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScaleMode::Fit => "fit",
+            ScaleMode::Fill => "fill",
+            ScaleMode::Stretch => "stretch",
+            ScaleMode::None => "none",
+            ScaleMode::Custom => "custom",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "fit" => Some(ScaleMode::Fit),
+            "fill" => Some(ScaleMode::Fill),
+            "stretch" => Some(ScaleMode::Stretch),
+            "none" => Some(ScaleMode::None),
+            "custom" => Some(ScaleMode::Custom),
+            _ => None,
+        }
+    }
+}
+
+/// How frames are produced when a clip's frame rate differs from the timeline's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameRateConform {
+    /// Show the closest source frame; cheap, but judders
+    #[default]
+    Nearest,
+    /// Mix the two surrounding source frames
+    FrameBlend,
+    /// Motion-compensated interpolation between the surrounding source frames; slowest
+    OpticalFlow,
+}
+
+impl FrameRateConform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameRateConform::Nearest => "nearest",
+            FrameRateConform::FrameBlend => "blend",
+            FrameRateConform::OpticalFlow => "optical_flow",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "nearest" => Some(FrameRateConform::Nearest),
+            "blend" => Some(FrameRateConform::FrameBlend),
+            "optical_flow" => Some(FrameRateConform::OpticalFlow),
+            _ => None,
+        }
+    }
+}
+
+/// Source frames making up one timeline frame of a clip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceSample {
+    /// A single source frame at this source time
+    Frame(f64),
+    /// Two neighbouring source frames combined with `strategy`, `weight` being the share
+    /// of the second
+    Mix {
+        first: f64,
+        second: f64,
+        weight: f64,
+        strategy: FrameRateConform,
+    },
+}
+
+/// A way a clip can differ from the timeline format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatMismatch {
+    Resolution {
+        source: (u32, u32),
+        sequence: (u32, u32),
+    },
+    FrameRate {
+        source: f64,
+        sequence: FrameRate,
+    },
+    SampleRate {
+        source: u32,
+        sequence: u32,
+    },
+}
+
+impl FormatMismatch {
+    /// Conversions that resolve the mismatch, the suggested one first
+    pub fn conversions(&self) -> Vec<ConversionOption> {
+        match self {
+            FormatMismatch::Resolution { .. } => vec![
+                ConversionOption::Scale(ScaleMode::Fit),
+                ConversionOption::Scale(ScaleMode::Fill),
+                ConversionOption::Scale(ScaleMode::Stretch),
+                ConversionOption::Scale(ScaleMode::None),
+            ],
+            FormatMismatch::FrameRate { .. } => vec![
+                ConversionOption::ConformFrameRate(FrameRateConform::Nearest),
+                ConversionOption::ConformFrameRate(FrameRateConform::FrameBlend),
+                ConversionOption::ConformFrameRate(FrameRateConform::OpticalFlow),
+            ],
+            FormatMismatch::SampleRate { .. } => vec![ConversionOption::Resample],
+        }
+    }
+}
+
+impl fmt::Display for FormatMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatMismatch::Resolution { source, sequence } => {
+                write!(f, "{}x{} source in a {}x{} timeline", source.0, source.1, sequence.0, sequence.1)
+            },
+            FormatMismatch::FrameRate { source, sequence } => {
+                write!(f, "{} fps source in a {} fps timeline", source, sequence)
+            },
+            FormatMismatch::SampleRate { source, sequence } => {
+                write!(f, "{} Hz source in a {} Hz timeline", source, sequence)
+            },
+        }
+    }
+}
+
+/// Explicit handling for a mismatched clip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionOption {
+    Scale(ScaleMode),
+    ConformFrameRate(FrameRateConform),
+    Resample,
+}
+
+/// A clip that doesn't match the timeline format
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipMismatch {
+    pub track_id: String,
+    pub clip_id: String,
+    pub mismatches: Vec<FormatMismatch>,
+}
+
+/// Result of `Timeline::conform_to_clip`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformReport {
+    /// Settings the timeline now uses
+    pub settings: SequenceSettings,
+    /// Clips already on the timeline that don't match, sorted by track and clip ID
+    pub mismatches: Vec<ClipMismatch>,
+}
+
+impl Timeline {
+    pub fn sequence_settings(&self) -> SequenceSettings {
+        let (width, height) = self.resolution();
+        SequenceSettings {
+            width,
+            height,
+            frame_rate: self.frame_rate(),
+            sample_rate: self.sample_rate(),
+        }
+    }
+
+    /// Take resolution, frame rate and audio rate from a clip's source format
+    ///
+    /// Only what the clip records is changed, so an audio clip leaves the picture settings
+    /// alone. NTSC rates such as 29.97 are kept exact rather than rounded. Returns the clips
+    /// that no longer match, with conversions to offer for each.
+    pub fn conform_to_clip(&mut self, clip: &Clip) -> Result<ConformReport, TimelineError> {
+        let format = SourceFormat::of(clip);
+        if format == SourceFormat::default() {
+            return Err(TimelineError::InvalidClip(format!("Clip {} has no source format", clip.id)));
+        }
+
+        if let Some((width, height)) = format.resolution {
+            self.set_resolution(width, height)?;
+        }
+        if let Some(fps) = format.fps {
+            self.set_frame_rate(FrameRate::from_fps(fps))?;
+        }
+        if let Some(sample_rate) = format.sample_rate {
+            self.set_sample_rate(sample_rate)?;
+        }
+
+        Ok(ConformReport {
+            settings: self.sequence_settings(),
+            mismatches: self.mismatched_clips(),
+        })
+    }
+
+    /// Ways a clip's source differs from the timeline, ignoring ones it has a conversion for
+    pub fn format_mismatches(&self, clip: &Clip) -> Vec<FormatMismatch> {
+        let format = SourceFormat::of(clip);
+        let settings = self.sequence_settings();
+        let has_picture = matches!(clip.clip_type, ClipType::Video | ClipType::Image);
+        let has_sound = matches!(clip.clip_type, ClipType::Video | ClipType::Audio);
+        let mut mismatches = Vec::new();
+
+        if let (true, Some(source)) = (has_picture, format.resolution) {
+            let sequence = (settings.width, settings.height);
+            if source != sequence && !clip.properties.contains_key(SCALE_MODE_PROPERTY) {
+                mismatches.push(FormatMismatch::Resolution { source, sequence });
+            }
+        }
+        if let (ClipType::Video, Some(source)) = (&clip.clip_type, format.fps) {
+            if (source - settings.frame_rate.as_f64()).abs() > FPS_EPSILON && !clip.properties.contains_key(CONFORM_FPS_PROPERTY) {
+                mismatches.push(FormatMismatch::FrameRate { source, sequence: settings.frame_rate });
+            }
+        }
+        if let (true, Some(source)) = (has_sound, format.sample_rate) {
+            if source != settings.sample_rate && !clip.properties.contains_key(RESAMPLE_PROPERTY) {
+                mismatches.push(FormatMismatch::SampleRate { source, sequence: settings.sample_rate });
+            }
+        }
+
+        mismatches
+    }
+
+    /// Every clip with an unhandled format mismatch
+    pub fn mismatched_clips(&self) -> Vec<ClipMismatch> {
+        let mut result: Vec<ClipMismatch> = self.tracks()
+            .values()
+            .flat_map(|track| track.clips.iter().map(move |clip| (track, clip)))
+            .filter_map(|(track, clip)| {
+                let mismatches = self.format_mismatches(clip);
+                (!mismatches.is_empty()).then(|| ClipMismatch {
+                    track_id: track.id.clone(),
+                    clip_id: clip.id.clone(),
+                    mismatches,
+                })
+            })
+            .collect();
+
+        result.sort_by(|a, b| a.track_id.cmp(&b.track_id).then_with(|| a.clip_id.cmp(&b.clip_id)));
+        result
+    }
+
+    /// Frame rate strategy for a clip: its own if set, otherwise the timeline default
+    pub fn clip_frame_rate_conform(&self, clip: &Clip) -> FrameRateConform {
+        clip.properties
+            .get(CONFORM_FPS_PROPERTY)
+            .and_then(|s| FrameRateConform::parse(s))
+            .unwrap_or_else(|| self.frame_rate_conform())
+    }
+
+    /// Scale mode for a clip: its own if set, otherwise the timeline default
+    pub fn clip_scale_mode(&self, clip: &Clip) -> ScaleMode {
+        clip.properties
+            .get(SCALE_MODE_PROPERTY)
+            .and_then(|s| ScaleMode::parse(s))
+            .unwrap_or_else(|| self.scale_mode())
+    }
+
+    /// Source frames to show for a clip at a timeline time
+    ///
+    /// Preview and export both go through this, so a clip looks the same in each. Clips
+    /// without a known source rate, or at the timeline rate, map straight through.
+    pub fn source_sample(&self, clip: &Clip, time: f64) -> SourceSample {
+        let source_time = clip.in_point() + (time - clip.start_time);
+
+        let source_fps = match clip.source_fps() {
+            Some(fps) if fps > 0.0 && (fps - self.frame_rate().as_f64()).abs() > FPS_EPSILON => FrameRate::from_fps(fps).as_f64(),
+            _ => return SourceSample::Frame(source_time),
+        };
+
+        let position = source_time * source_fps;
+        let strategy = self.clip_frame_rate_conform(clip);
+        let first = position.floor();
+        let weight = position - first;
+
+        // Landing within a thousandth of a frame needs no mixing
+        if strategy == FrameRateConform::Nearest || !(1e-3..=1.0 - 1e-3).contains(&weight) {
+            return SourceSample::Frame(position.round() / source_fps);
+        }
+
+        SourceSample::Mix {
+            first: first / source_fps,
+            second: (first + 1.0) / source_fps,
+            weight,
+            strategy,
+        }
+    }
+
+    /// Record how a mismatched clip should be converted
+    pub fn apply_conversion(&mut self, track_id: &str, clip_id: &str, option: ConversionOption) -> Result<(), TimelineError> {
+        let clip = self.get_track_mut(track_id)?
+            .clips
+            .iter_mut()
+            .find(|clip| clip.id == clip_id)
+            .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+
+        let (key, value) = match option {
+            ConversionOption::Scale(mode) => (SCALE_MODE_PROPERTY, mode.as_str()),
+            ConversionOption::ConformFrameRate(strategy) => (CONFORM_FPS_PROPERTY, strategy.as_str()),
+            ConversionOption::Resample => (RESAMPLE_PROPERTY, "true"),
+        };
+        clip.properties.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
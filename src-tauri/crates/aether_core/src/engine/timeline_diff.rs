@@ -0,0 +1,272 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::engine::timeline::{Clip, Marker, Timeline};
+use crate::engine::timeline_attributes::{EFFECT_PREFIX, GRADE_PREFIX};
+
+/// Tolerance used when comparing times, in seconds
+const TIME_EPSILON: f64 = 1e-6;
+
+/// A change to a single clip
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipChange {
+    Added {
+        track_id: String,
+        clip: Clip,
+    },
+    Removed {
+        track_id: String,
+        clip: Clip,
+    },
+    /// Clip moved in time and/or to another track
+    Moved {
+        from_track: String,
+        to_track: String,
+        old_start: f64,
+        new_start: f64,
+    },
+    /// Clip's duration or source in point changed
+    Trimmed {
+        old_in_point: f64,
+        new_in_point: f64,
+        old_duration: f64,
+        new_duration: f64,
+    },
+    SourceChanged {
+        old_source: Option<String>,
+        new_source: Option<String>,
+    },
+    /// Effect parameters (properties prefixed with `effect.`) changed
+    EffectsChanged {
+        keys: Vec<String>,
+    },
+    /// Grade parameters (properties prefixed with `grade.`) changed
+    GradeChanged {
+        keys: Vec<String>,
+    },
+    /// Any other property changed
+    PropertiesChanged {
+        keys: Vec<String>,
+    },
+}
+
+/// A change to a marker
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkerChange {
+    Added(Marker),
+    Removed(Marker),
+    Modified {
+        old: Marker,
+        new: Marker,
+    },
+}
+
+/// Changes to a track's settings
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackChange {
+    Added(String),
+    Removed(String),
+    Renamed {
+        track_id: String,
+        old_name: String,
+        new_name: String,
+    },
+    MuteChanged {
+        track_id: String,
+        is_muted: bool,
+    },
+    LockChanged {
+        track_id: String,
+        is_locked: bool,
+    },
+    /// Height, color or other view settings changed
+    ViewChanged(String),
+}
+
+/// Structured summary of the differences between two versions of a project
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectDiff {
+    pub tracks: Vec<TrackChange>,
+    /// Clip changes keyed by clip ID, sorted by ID
+    pub clips: Vec<(String, Vec<ClipChange>)>,
+    pub markers: Vec<MarkerChange>,
+    /// Timeline duration change (old, new)
+    pub duration: Option<(f64, f64)>,
+}
+
+impl ProjectDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty() && self.clips.is_empty() && self.markers.is_empty() && self.duration.is_none()
+    }
+
+    /// Number of clips that were moved
+    pub fn moved_clip_count(&self) -> usize {
+        self.count_clips(|change| matches!(change, ClipChange::Moved { .. }))
+    }
+
+    /// Number of clips whose effects or grade changed
+    pub fn effect_or_grade_change_count(&self) -> usize {
+        self.count_clips(|change| {
+            matches!(change, ClipChange::EffectsChanged { .. } | ClipChange::GradeChanged { .. })
+        })
+    }
+
+    fn count_clips(&self, predicate: impl Fn(&ClipChange) -> bool) -> usize {
+        self.clips.iter()
+            .filter(|(_, changes)| changes.iter().any(&predicate))
+            .count()
+    }
+}
+
+/// Compare two timelines and summarize what changed from `a` to `b`
+///
+/// Clips and markers are matched by ID, so a clip that was moved to another track is
+/// reported as moved rather than removed and re-added.
+pub fn diff_projects(a: &Timeline, b: &Timeline) -> ProjectDiff {
+    let mut diff = ProjectDiff::default();
+
+    diff_tracks(a, b, &mut diff);
+    diff_clips(a, b, &mut diff);
+    diff_markers(a.markers(), b.markers(), &mut diff);
+
+    if !times_equal(a.duration(), b.duration()) {
+        diff.duration = Some((a.duration(), b.duration()));
+    }
+
+    diff
+}
+
+fn diff_tracks(a: &Timeline, b: &Timeline, diff: &mut ProjectDiff) {
+    let ids: BTreeSet<&String> = a.tracks().keys().chain(b.tracks().keys()).collect();
+
+    for id in ids {
+        match (a.tracks().get(id), b.tracks().get(id)) {
+            (None, Some(_)) => diff.tracks.push(TrackChange::Added(id.clone())),
+            (Some(_), None) => diff.tracks.push(TrackChange::Removed(id.clone())),
+            (Some(old), Some(new)) => {
+                if old.name != new.name {
+                    diff.tracks.push(TrackChange::Renamed {
+                        track_id: id.clone(),
+                        old_name: old.name.clone(),
+                        new_name: new.name.clone(),
+                    });
+                }
+                if old.is_muted != new.is_muted {
+                    diff.tracks.push(TrackChange::MuteChanged { track_id: id.clone(), is_muted: new.is_muted });
+                }
+                if old.is_locked != new.is_locked {
+                    diff.tracks.push(TrackChange::LockChanged { track_id: id.clone(), is_locked: new.is_locked });
+                }
+                if old.view != new.view {
+                    diff.tracks.push(TrackChange::ViewChanged(id.clone()));
+                }
+            },
+            (None, None) => (),
+        }
+    }
+}
+
+/// Index clips by ID with the track they live on
+fn index_clips(timeline: &Timeline) -> HashMap<&str, (&str, &Clip)> {
+    timeline.tracks().values()
+        .flat_map(|track| track.clips.iter().map(move |clip| (clip.id.as_str(), (track.id.as_str(), clip))))
+        .collect()
+}
+
+fn diff_clips(a: &Timeline, b: &Timeline, diff: &mut ProjectDiff) {
+    let old_clips = index_clips(a);
+    let new_clips = index_clips(b);
+    let ids: BTreeSet<&str> = old_clips.keys().chain(new_clips.keys()).copied().collect();
+
+    for id in ids {
+        let changes = match (old_clips.get(id), new_clips.get(id)) {
+            (None, Some((track_id, clip))) => vec![ClipChange::Added {
+                track_id: track_id.to_string(),
+                clip: (*clip).clone(),
+            }],
+            (Some((track_id, clip)), None) => vec![ClipChange::Removed {
+                track_id: track_id.to_string(),
+                clip: (*clip).clone(),
+            }],
+            (Some((old_track, old)), Some((new_track, new))) => diff_clip(old_track, old, new_track, new),
+            (None, None) => Vec::new(),
+        };
+
+        if !changes.is_empty() {
+            diff.clips.push((id.to_string(), changes));
+        }
+    }
+}
+
+fn diff_clip(old_track: &str, old: &Clip, new_track: &str, new: &Clip) -> Vec<ClipChange> {
+    let mut changes = Vec::new();
+
+    if old_track != new_track || !times_equal(old.start_time, new.start_time) {
+        changes.push(ClipChange::Moved {
+            from_track: old_track.to_string(),
+            to_track: new_track.to_string(),
+            old_start: old.start_time,
+            new_start: new.start_time,
+        });
+    }
+
+    if !times_equal(old.duration, new.duration) || !times_equal(old.in_point(), new.in_point()) {
+        changes.push(ClipChange::Trimmed {
+            old_in_point: old.in_point(),
+            new_in_point: new.in_point(),
+            old_duration: old.duration,
+            new_duration: new.duration,
+        });
+    }
+
+    if old.source_path != new.source_path {
+        changes.push(ClipChange::SourceChanged {
+            old_source: old.source_path.clone(),
+            new_source: new.source_path.clone(),
+        });
+    }
+
+    // Group changed property keys; the in point is already covered by Trimmed
+    let keys: BTreeSet<&String> = old.properties.keys().chain(new.properties.keys()).collect();
+    let changed: Vec<String> = keys.into_iter()
+        .filter(|key| key.as_str() != "in_point" && old.properties.get(*key) != new.properties.get(*key))
+        .cloned()
+        .collect();
+
+    let (effects, rest): (Vec<String>, Vec<String>) = changed.into_iter().partition(|key| key.starts_with(EFFECT_PREFIX));
+    let (grade, other): (Vec<String>, Vec<String>) = rest.into_iter().partition(|key| key.starts_with(GRADE_PREFIX));
+
+    if !effects.is_empty() {
+        changes.push(ClipChange::EffectsChanged { keys: effects });
+    }
+    if !grade.is_empty() {
+        changes.push(ClipChange::GradeChanged { keys: grade });
+    }
+    if !other.is_empty() {
+        changes.push(ClipChange::PropertiesChanged { keys: other });
+    }
+
+    changes
+}
+
+fn diff_markers(old: &[Marker], new: &[Marker], diff: &mut ProjectDiff) {
+    for marker in old {
+        match new.iter().find(|m| m.id == marker.id) {
+            None => diff.markers.push(MarkerChange::Removed(marker.clone())),
+            Some(updated) if updated != marker => diff.markers.push(MarkerChange::Modified {
+                old: marker.clone(),
+                new: updated.clone(),
+            }),
+            Some(_) => (),
+        }
+    }
+
+    for marker in new {
+        if !old.iter().any(|m| m.id == marker.id) {
+            diff.markers.push(MarkerChange::Added(marker.clone()));
+        }
+    }
+}
+
+fn times_equal(a: f64, b: f64) -> bool {
+    (a - b).abs() < TIME_EPSILON
+}
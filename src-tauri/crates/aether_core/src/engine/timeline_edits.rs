@@ -0,0 +1,417 @@
+//! Three-point editing: insert, overwrite, replace and fit-to-fill
+//!
+//! An edit is given by source in and out points and record (timeline) in and out
+//! points. Three of them fix the fourth: the duration comes from whichever range is
+//! complete and the missing in point is backtimed from its out point. Fit-to-fill takes
+//! all four and changes the clip's speed so the source range fills the record range.
+//!
+//! A `SourcePatch` says which timeline track each channel of the source goes to, so one
+//! edit can put the picture on V1 and audio channel 2 on A3.
+
+use std::collections::HashSet;
+use tracing::debug;
+
+use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineError};
+use crate::modules::audio_sync::LINK_GROUP_PROPERTY;
+
+/// Clip property with the source audio channel an audio clip plays, counting from 1
+pub const AUDIO_CHANNEL_PROPERTY: &str = "source.audio_channel";
+
+/// Durations closer than this count as equal
+const EDIT_EPSILON: f64 = 1e-6;
+
+/// A stream of the source media
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceChannel {
+    Video,
+    /// An audio channel, counting from 1
+    Audio(u32),
+}
+
+/// Which timeline track each source channel goes to in an edit
+///
+/// Channels without a track are left out of the edit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourcePatch {
+    routes: Vec<(SourceChannel, String)>,
+}
+
+impl SourcePatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Patch sending only the picture, to `track_id`
+    pub fn video(track_id: &str) -> Self {
+        Self::new().with(SourceChannel::Video, track_id)
+    }
+
+    /// Patch sending only the audio channels, the first to the first track and so on
+    pub fn audio(track_ids: &[&str]) -> Self {
+        track_ids.iter()
+            .enumerate()
+            .fold(Self::new(), |patch, (index, track_id)| patch.with(SourceChannel::Audio(index as u32 + 1), track_id))
+    }
+
+    /// Route a channel to a track, replacing its previous route
+    pub fn with(mut self, channel: SourceChannel, track_id: &str) -> Self {
+        self.patch(channel, track_id);
+        self
+    }
+
+    /// Route a channel to a track, replacing its previous route
+    pub fn patch(&mut self, channel: SourceChannel, track_id: &str) {
+        self.unpatch(channel);
+        self.routes.push((channel, track_id.to_string()));
+        self.routes.sort_by_key(|(channel, _)| *channel);
+    }
+
+    /// Leave a channel out of edits
+    pub fn unpatch(&mut self, channel: SourceChannel) {
+        self.routes.retain(|(patched, _)| *patched != channel);
+    }
+
+    /// Track a channel goes to
+    pub fn track(&self, channel: SourceChannel) -> Option<&str> {
+        self.routes.iter().find(|(patched, _)| *patched == channel).map(|(_, track)| track.as_str())
+    }
+
+    /// Routes, picture first and then audio channels in order
+    pub fn routes(&self) -> &[(SourceChannel, String)] {
+        &self.routes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Clips the patched channels of `source` become, with their tracks
+    ///
+    /// The picture keeps the source clip's ID and audio channel `n` gets `<id>_a<n>`.
+    /// Clips from the same edit share a link group.
+    fn placements(&self, source: &Clip, edit: &ResolvedEdit) -> Result<Vec<(String, Clip)>, TimelineError> {
+        if self.routes.is_empty() {
+            return Err(TimelineError::OperationError("No source channels are patched".to_string()));
+        }
+        let mut tracks = HashSet::new();
+        for (channel, track_id) in &self.routes {
+            if !tracks.insert(track_id) {
+                return Err(TimelineError::InvalidTrack(format!(
+                    "{:?} is patched to track {} along with another channel", channel, track_id
+                )));
+            }
+        }
+
+        let linked = self.routes.len() > 1;
+        Ok(self.routes.iter()
+            .map(|(channel, track_id)| {
+                let mut clip = edit.place(source);
+                match channel {
+                    SourceChannel::Video => {
+                        if clip.clip_type == ClipType::Audio {
+                            clip.clip_type = ClipType::Video;
+                        }
+                    },
+                    SourceChannel::Audio(number) => {
+                        clip.id = format!("{}_a{}", source.id, number);
+                        clip.clip_type = ClipType::Audio;
+                        clip.properties.insert(AUDIO_CHANNEL_PROPERTY.to_string(), number.to_string());
+                    },
+                }
+                if linked {
+                    clip.properties.insert(LINK_GROUP_PROPERTY.to_string(), format!("{}_link", source.id));
+                }
+                (track_id.clone(), clip)
+            })
+            .collect())
+    }
+}
+
+/// Source and record points of an edit in seconds; missing points are worked out
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EditPoints {
+    pub source_in: Option<f64>,
+    pub source_out: Option<f64>,
+    pub record_in: Option<f64>,
+    pub record_out: Option<f64>,
+}
+
+impl EditPoints {
+    /// Source range placed at a timeline position
+    pub fn new(source_in: f64, source_out: f64, record_in: f64) -> Self {
+        Self {
+            source_in: Some(source_in),
+            source_out: Some(source_out),
+            record_in: Some(record_in),
+            record_out: None,
+        }
+    }
+
+    /// Where the source lands on the timeline and at what speed
+    ///
+    /// Four points are only consistent if both ranges have the same duration, unless
+    /// `fit_to_fill` allows a speed change.
+    pub fn resolve(&self, fit_to_fill: bool) -> Result<ResolvedEdit, TimelineError> {
+        let source = range_duration(self.source_in, self.source_out, "source")?;
+        let record = range_duration(self.record_in, self.record_out, "record")?;
+
+        let (source_duration, record_duration) = match (source, record) {
+            (Some(source), Some(record)) if fit_to_fill => (source, record),
+            (Some(source), Some(record)) if (source - record).abs() > EDIT_EPSILON => {
+                return Err(TimelineError::InvalidTime(format!(
+                    "Source range is {:.3}s but record range is {:.3}s", source, record
+                )));
+            },
+            (Some(duration), _) | (None, Some(duration)) if !fit_to_fill => (duration, duration),
+            _ if fit_to_fill => {
+                return Err(TimelineError::InvalidTime("Fit to fill needs all four edit points".to_string()));
+            },
+            _ => {
+                return Err(TimelineError::InvalidTime("An edit needs three edit points".to_string()));
+            },
+        };
+
+        // Backtime whichever in point is missing from its out point
+        let source_in = match (self.source_in, self.source_out) {
+            (Some(source_in), _) => source_in,
+            (None, Some(source_out)) => source_out - source_duration,
+            (None, None) => return Err(TimelineError::InvalidTime("An edit needs a source point".to_string())),
+        };
+        let record_in = match (self.record_in, self.record_out) {
+            (Some(record_in), _) => record_in,
+            (None, Some(record_out)) => record_out - record_duration,
+            (None, None) => return Err(TimelineError::InvalidTime("An edit needs a record point".to_string())),
+        };
+        if source_in < 0.0 || record_in < 0.0 {
+            return Err(TimelineError::InvalidTime(format!(
+                "Edit starts before the beginning (source {:.3}s, record {:.3}s)", source_in, record_in
+            )));
+        }
+
+        Ok(ResolvedEdit {
+            source_in,
+            record_in,
+            duration: record_duration,
+            speed: source_duration / record_duration,
+        })
+    }
+}
+
+/// Duration of a range, if both of its points are set
+fn range_duration(start: Option<f64>, end: Option<f64>, name: &str) -> Result<Option<f64>, TimelineError> {
+    match (start, end) {
+        (Some(start), Some(end)) if end - start <= EDIT_EPSILON => Err(TimelineError::InvalidTime(format!(
+            "The {} out point {:.3}s is not after its in point {:.3}s", name, end, start
+        ))),
+        (Some(start), Some(end)) => Ok(Some(end - start)),
+        _ => Ok(None),
+    }
+}
+
+/// Edit points with the missing one worked out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResolvedEdit {
+    pub source_in: f64,
+    pub record_in: f64,
+    /// Timeline duration of the edit
+    pub duration: f64,
+    /// Seconds of source per second of timeline
+    pub speed: f64,
+}
+
+impl ResolvedEdit {
+    pub fn record_out(&self) -> f64 {
+        self.record_in + self.duration
+    }
+
+    /// Copy of `source` placed by the edit
+    fn place(&self, source: &Clip) -> Clip {
+        let mut clip = source.clone();
+        clip.start_time = self.record_in;
+        clip.duration = self.duration;
+        clip.set_in_point(self.source_in);
+        clip.set_speed(self.speed);
+        clip
+    }
+}
+
+impl Timeline {
+    /// Insert `source` at the record in point on the patched tracks, pushing everything
+    /// after it later; returns the IDs of the added clips
+    ///
+    /// Clips on every unlocked track that cross the insert point are split there, so all
+    /// unlocked tracks stay in sync. Locked tracks don't move, and the insert fails if that
+    /// would pull clips out of sync with the clips they are linked to.
+    pub fn insert_edit(&mut self, patch: &SourcePatch, source: &Clip, points: &EditPoints) -> Result<Vec<String>, TimelineError> {
+        let edit = points.resolve(false)?;
+        let placements = patch.placements(source, &edit)?;
+        self.check_edit(&placements)?;
+
+        self.ripple(&[], edit.record_in, edit.duration)?;
+
+        debug!("Inserted {} at {:.3}s for {:.3}s", source.id, edit.record_in, edit.duration);
+        self.finish_edit(placements)
+    }
+
+    /// Place `source` over the record range on the patched tracks, replacing whatever they
+    /// had there; returns the IDs of the added clips
+    pub fn overwrite_edit(&mut self, patch: &SourcePatch, source: &Clip, points: &EditPoints) -> Result<Vec<String>, TimelineError> {
+        let edit = points.resolve(false)?;
+        self.overwrite(patch, source, edit)
+    }
+
+    /// Overwrite with the source range sped up or slowed down to fill the record range
+    ///
+    /// Needs all four edit points.
+    pub fn fit_to_fill_edit(&mut self, patch: &SourcePatch, source: &Clip, points: &EditPoints) -> Result<Vec<String>, TimelineError> {
+        let edit = points.resolve(true)?;
+        self.overwrite(patch, source, edit)
+    }
+
+    /// Swap the media of the clip under `record_time` for `source`, keeping its position
+    /// and duration
+    ///
+    /// `source_time` is the source frame that lands on `record_time`. Fails if that puts
+    /// the start of the clip before the start of the source, or runs past `source_out`
+    /// when one is given.
+    pub fn replace_edit(
+        &mut self,
+        track_id: &str,
+        source: &Clip,
+        source_time: f64,
+        record_time: f64,
+        source_out: Option<f64>,
+    ) -> Result<(), TimelineError> {
+        let track = self.get_track(track_id)?;
+        if track.is_locked {
+            return Err(TimelineError::InvalidTrack(format!("Track {} is locked", track_id)));
+        }
+        let target = track.clips.iter()
+            .find(|clip| clip.contains_time(record_time))
+            .ok_or_else(|| TimelineError::InvalidTime(format!("No clip on {} at {:.3}s", track_id, record_time)))?;
+
+        if source.id != target.id && self.clip_ids().contains(&source.id) {
+            return Err(TimelineError::InvalidClip(format!("Clip {} already exists", source.id)));
+        }
+
+        let speed = target.speed();
+        let mut edit = ResolvedEdit {
+            source_in: source_time - (record_time - target.start_time) * speed,
+            record_in: target.start_time,
+            duration: target.duration,
+            speed,
+        };
+        if edit.source_in < -EDIT_EPSILON {
+            return Err(TimelineError::InvalidTime(format!(
+                "Not enough source before {:.3}s to replace {}", source_time, target.id
+            )));
+        }
+        if let Some(source_out) = source_out {
+            if edit.source_in + edit.duration * speed > source_out + EDIT_EPSILON {
+                return Err(TimelineError::InvalidTime(format!(
+                    "Not enough source up to {:.3}s to replace {}", source_out, target.id
+                )));
+            }
+        }
+        edit.source_in = edit.source_in.max(0.0);
+
+        let target_id = target.id.clone();
+        let clip = edit.place(source);
+        let track = self.get_track_mut(track_id)?;
+        track.remove_clip(&target_id)?;
+        debug!("Replaced {} with {}", target_id, clip.id);
+        track.clips.push(clip);
+        Ok(())
+    }
+
+    fn overwrite(&mut self, patch: &SourcePatch, source: &Clip, edit: ResolvedEdit) -> Result<Vec<String>, TimelineError> {
+        let placements = patch.placements(source, &edit)?;
+        self.check_edit(&placements)?;
+
+        let (start, end) = (edit.record_in, edit.record_out());
+        let mut taken = self.clip_ids();
+        for (track_id, _) in &placements {
+            let track = self.get_track_mut(track_id)?;
+
+            // Cut the range out of the track; clips keep their IDs where they only lose an end
+            let mut pieces = Vec::new();
+            for clip in &mut track.clips {
+                if clip.start_time < start - EDIT_EPSILON {
+                    pieces.extend(split_clip(clip, end, &mut taken));
+                    clip.duration = clip.duration.min(start - clip.start_time);
+                } else if clip.start_time < end - EDIT_EPSILON && clip.end_time() > end + EDIT_EPSILON {
+                    trim_head(clip, end);
+                }
+            }
+            track.clips.extend(pieces);
+            track.clips.retain(|clip| clip.start_time < start - EDIT_EPSILON || clip.end_time() > end + EDIT_EPSILON);
+        }
+
+        debug!("Overwrote {:.3}s-{:.3}s with {} on {} tracks", start, end, source.id, placements.len());
+        self.finish_edit(placements)
+    }
+
+    /// Fail early so a rejected edit leaves the timeline untouched
+    fn check_edit(&self, placements: &[(String, Clip)]) -> Result<(), TimelineError> {
+        let taken = self.clip_ids();
+        for (track_id, clip) in placements {
+            if self.get_track(track_id)?.is_locked {
+                return Err(TimelineError::InvalidTrack(format!("Track {} is locked", track_id)));
+            }
+            if taken.contains(&clip.id) {
+                return Err(TimelineError::InvalidClip(format!("Clip {} already exists", clip.id)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the edited clips and grow the timeline to fit
+    fn finish_edit(&mut self, placements: Vec<(String, Clip)>) -> Result<Vec<String>, TimelineError> {
+        let mut end = self.tracks().values()
+            .flat_map(|track| track.clips.iter().map(Clip::end_time))
+            .fold(0.0, f64::max);
+        let mut ids = Vec::new();
+        for (track_id, clip) in placements {
+            end = end.max(clip.end_time());
+            ids.push(clip.id.clone());
+            let track = self.get_track_mut(&track_id)?;
+            track.clips.push(clip);
+            track.clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        }
+        if end > self.duration() {
+            self.set_duration(end)?;
+        }
+        Ok(ids)
+    }
+
+    fn clip_ids(&self) -> HashSet<String> {
+        self.tracks().values()
+            .flat_map(|track| track.clips.iter().map(|clip| clip.id.clone()))
+            .collect()
+    }
+}
+
+/// Cut `clip` at `time`, returning the part after it under an unused ID
+pub(crate) fn split_clip(clip: &mut Clip, time: f64, taken: &mut HashSet<String>) -> Option<Clip> {
+    if time <= clip.start_time + EDIT_EPSILON || time >= clip.end_time() - EDIT_EPSILON {
+        return None;
+    }
+
+    let mut tail = clip.clone();
+    tail.id = (2..)
+        .map(|n| format!("{}_{}", clip.id, n))
+        .find(|id| !taken.contains(id))
+        .unwrap();
+    taken.insert(tail.id.clone());
+    trim_head(&mut tail, time);
+    clip.duration = time - clip.start_time;
+    Some(tail)
+}
+
+/// Move the start of `clip` later to `time`, keeping its end where it is
+fn trim_head(clip: &mut Clip, time: f64) {
+    let offset = time - clip.start_time;
+    clip.set_in_point(clip.in_point() + offset * clip.speed());
+    clip.start_time = time;
+    clip.duration -= offset;
+}
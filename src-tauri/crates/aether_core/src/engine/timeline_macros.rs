@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::engine::timeline::{Timeline, TimelineError};
+use crate::engine::timeline_commands::{CommandHistory, TimelineCommand};
+
+/// Values for a macro's parameters and where to run it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MacroArgs {
+    pub values: HashMap<String, String>,
+    /// Time the macro starts at, for macros with relative times
+    pub at: f64,
+}
+
+impl MacroArgs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, parameter: &str, value: &str) -> Self {
+        self.values.insert(parameter.to_string(), value.to_string());
+        self
+    }
+
+    pub fn at(mut self, time: f64) -> Self {
+        self.at = time;
+        self
+    }
+}
+
+/// A recorded sequence of timeline commands that can be replayed
+///
+/// Parameters stand in for clips, tracks or text that differ between runs: every string
+/// equal to the recorded value becomes a `{name}` placeholder, filled in from `MacroArgs`.
+/// With relative times, clip and marker times are offsets from `MacroArgs::at`.
+///
+/// Clips and markers the macro adds get fresh IDs when theirs are taken, so a macro can
+/// run repeatedly on the same timeline. A run is a single undo step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineMacro {
+    pub name: String,
+    pub parameters: Vec<String>,
+    #[serde(default)]
+    pub relative_time: bool,
+    pub commands: Vec<TimelineCommand>,
+}
+
+impl TimelineMacro {
+    pub fn new(name: &str, commands: Vec<TimelineCommand>) -> Self {
+        Self {
+            name: name.to_string(),
+            parameters: Vec::new(),
+            relative_time: false,
+            commands,
+        }
+    }
+
+    /// Record a macro from the commands of a `CommandHistory` recording
+    pub fn from_recording(name: &str, history: &mut CommandHistory) -> Self {
+        Self::new(name, history.stop_recording())
+    }
+
+    /// Turn every string equal to `value` into the `{parameter}` placeholder
+    ///
+    /// Returns the number of strings replaced.
+    pub fn parameterize(&mut self, parameter: &str, value: &str) -> Result<usize, TimelineError> {
+        let placeholder = format!("{{{}}}", parameter);
+        let mut replaced = 0;
+
+        self.commands = self.map_strings(|text| {
+            if text == value {
+                replaced += 1;
+                placeholder.clone()
+            } else {
+                text.to_string()
+            }
+        })?;
+
+        if !self.parameters.iter().any(|name| name == parameter) {
+            self.parameters.push(parameter.to_string());
+        }
+        Ok(replaced)
+    }
+
+    /// Make times offsets from `origin`, so runs place the edits relative to `MacroArgs::at`
+    pub fn make_relative(&mut self, origin: f64) {
+        shift_times(&mut self.commands, -origin);
+        self.relative_time = true;
+    }
+
+    /// Commands with parameters filled in and times placed, before ID remapping
+    pub fn resolve(&self, args: &MacroArgs) -> Result<Vec<TimelineCommand>, TimelineError> {
+        if let Some(missing) = self.parameters.iter().find(|name| !args.values.contains_key(*name)) {
+            return Err(TimelineError::OperationError(format!("Missing value for macro parameter {}", missing)));
+        }
+
+        let mut commands = self.map_strings(|text| {
+            self.parameters.iter().fold(text.to_string(), |text, name| {
+                text.replace(&format!("{{{}}}", name), &args.values[name])
+            })
+        })?;
+
+        if self.relative_time {
+            shift_times(&mut commands, args.at);
+        }
+        Ok(commands)
+    }
+
+    /// Run the macro on a timeline as one undo step
+    pub fn run(&self, history: &mut CommandHistory, timeline: &mut Timeline, args: &MacroArgs) -> Result<(), TimelineError> {
+        let mut commands = self.resolve(args)?;
+        remap_ids(timeline, &mut commands);
+
+        info!("Running macro {} ({} commands)", self.name, commands.len());
+        history.execute_group(timeline, &self.name, commands)
+    }
+
+    pub fn to_json(&self) -> Result<String, TimelineError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| TimelineError::OperationError(format!("Failed to serialize macro {}: {}", self.name, e)))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, TimelineError> {
+        serde_json::from_str(json)
+            .map_err(|e| TimelineError::OperationError(format!("Invalid macro: {}", e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TimelineError> {
+        fs::write(path, self.to_json()?)
+            .map_err(|e| TimelineError::OperationError(format!("Failed to write macro {:?}: {}", path, e)))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TimelineError> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| TimelineError::OperationError(format!("Failed to read macro {:?}: {}", path, e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Copy of the commands with every string field passed through `map`
+    fn map_strings(&self, mut map: impl FnMut(&str) -> String) -> Result<Vec<TimelineCommand>, TimelineError> {
+        fn walk(value: &mut Value, map: &mut dyn FnMut(&str) -> String) {
+            match value {
+                Value::String(text) => *text = map(text),
+                Value::Array(items) => items.iter_mut().for_each(|item| walk(item, map)),
+                Value::Object(fields) => fields.values_mut().for_each(|item| walk(item, map)),
+                _ => (),
+            }
+        }
+
+        let invalid = |e: serde_json::Error| TimelineError::OperationError(format!("Invalid macro command: {}", e));
+        self.commands.iter()
+            .map(|command| {
+                let mut value = serde_json::to_value(command).map_err(invalid)?;
+                if let Value::Object(fields) = &mut value {
+                    // The command tag isn't data
+                    fields.iter_mut()
+                        .filter(|(key, _)| key.as_str() != "op")
+                        .for_each(|(_, item)| walk(item, &mut map));
+                }
+                serde_json::from_value(value).map_err(invalid)
+            })
+            .collect()
+    }
+}
+
+fn shift_times(commands: &mut [TimelineCommand], offset: f64) {
+    for command in commands {
+        match command {
+            TimelineCommand::AddClip { clip, .. } => clip.start_time += offset,
+            TimelineCommand::MoveClip { start_time, .. } => *start_time += offset,
+            TimelineCommand::AddMarker { marker } => marker.time += offset,
+            _ => (),
+        }
+    }
+}
+
+/// Give added clips and markers unused IDs, updating later commands that refer to them
+fn remap_ids(timeline: &Timeline, commands: &mut [TimelineCommand]) {
+    let mut clip_ids: HashSet<String> = timeline.tracks().values()
+        .flat_map(|track| track.clips.iter().map(|clip| clip.id.clone()))
+        .collect();
+    let mut marker_ids: HashSet<String> = timeline.markers().iter().map(|marker| marker.id.clone()).collect();
+    let mut clip_map: HashMap<String, String> = HashMap::new();
+    let mut marker_map: HashMap<String, String> = HashMap::new();
+
+    let unused = |id: &str, taken: &HashSet<String>| {
+        if !taken.contains(id) {
+            return id.to_string();
+        }
+        (2..)
+            .map(|n| format!("{}_{}", id, n))
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap()
+    };
+
+    for command in commands {
+        match command {
+            TimelineCommand::AddClip { clip, .. } => {
+                let id = unused(&clip.id, &clip_ids);
+                clip_ids.insert(id.clone());
+                clip_map.insert(clip.id.clone(), id.clone());
+                clip.id = id;
+            },
+            TimelineCommand::AddMarker { marker } => {
+                let id = unused(&marker.id, &marker_ids);
+                marker_ids.insert(id.clone());
+                marker_map.insert(marker.id.clone(), id.clone());
+                marker.id = id;
+            },
+            TimelineCommand::RemoveClip { clip_id, .. }
+            | TimelineCommand::MoveClip { clip_id, .. }
+            | TimelineCommand::TrimClip { clip_id, .. }
+            | TimelineCommand::SetClipProperty { clip_id, .. } => {
+                if let Some(id) = clip_map.get(clip_id.as_str()) {
+                    *clip_id = id.clone();
+                }
+            },
+            TimelineCommand::RemoveMarker { marker_id } => {
+                if let Some(id) = marker_map.get(marker_id.as_str()) {
+                    *marker_id = id.clone();
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Transport helpers for moving around a timeline by its cuts
+//!
+//! Edit points are the starts and ends of clips on the targeted tracks. Match frame finds
+//! the source frame under the playhead so the source can be opened at that frame.
+
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::timeline::{Clip, Timeline};
+
+/// Times closer than this are the same edit point
+const NAVIGATION_EPSILON: f64 = 1e-6;
+
+/// Source viewer context for the frame under the playhead
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchFrame {
+    pub track_id: String,
+    pub clip_id: String,
+    pub source_path: Option<String>,
+    /// Time in the source media, snapped to the start of its frame, in seconds
+    pub source_time: f64,
+    /// Frame number in the source media
+    pub frame: u64,
+    /// Frame rate the frame number counts in: the source's when known, else the timeline's
+    pub fps: f64,
+    /// Source range the clip uses, to show as in and out marks
+    pub in_point: f64,
+    pub out_point: f64,
+}
+
+impl Timeline {
+    /// Clip starts and ends on the targeted tracks, sorted, within the timeline
+    ///
+    /// An empty `track_ids` targets every track. Unknown tracks are ignored.
+    pub fn edit_points(&self, track_ids: &[&str]) -> Vec<f64> {
+        let mut points: Vec<f64> = self.targeted_clips(track_ids)
+            .into_iter()
+            .flat_map(|(_, clip)| [clip.start_time, clip.end_time()])
+            .filter(|time| *time <= self.duration() + NAVIGATION_EPSILON)
+            .collect();
+        points.sort_by(f64::total_cmp);
+        points.dedup_by(|a, b| (*a - *b).abs() < NAVIGATION_EPSILON);
+        points
+    }
+
+    /// Move the playhead to the next edit point on the targeted tracks
+    ///
+    /// Returns the new time, or `None` when there is no later edit point.
+    pub fn next_edit_point(&mut self, track_ids: &[&str]) -> Option<f64> {
+        let now = self.current_time();
+        let time = self.edit_points(track_ids)
+            .into_iter()
+            .find(|time| *time > now + NAVIGATION_EPSILON)?;
+        self.seek(time.min(self.duration())).ok()?;
+        Some(self.current_time())
+    }
+
+    /// Move the playhead to the previous edit point on the targeted tracks
+    ///
+    /// Returns the new time, or `None` when there is no earlier edit point.
+    pub fn previous_edit_point(&mut self, track_ids: &[&str]) -> Option<f64> {
+        let now = self.current_time();
+        let time = self.edit_points(track_ids)
+            .into_iter()
+            .rev()
+            .find(|time| *time < now - NAVIGATION_EPSILON)?;
+        self.seek(time).ok()?;
+        Some(self.current_time())
+    }
+
+    /// Source frame of the clip under the playhead
+    ///
+    /// Tracks are tried in the order given, or by ID when `track_ids` is empty; muted
+    /// tracks are skipped. Returns `None` when no targeted track has a clip there.
+    pub fn match_frame(&self, track_ids: &[&str]) -> Option<MatchFrame> {
+        let now = self.current_time();
+        let (track_id, clip) = self.targeted_clips(track_ids)
+            .into_iter()
+            .filter(|(track_id, _)| self.get_track(track_id).is_ok_and(|track| !track.is_muted))
+            .find(|(_, clip)| clip.contains_time(now))?;
+
+        let fps = clip.source_fps().map_or(self.frame_rate(), FrameRate::from_fps).as_f64();
+        let source_time = clip.in_point() + (now - clip.start_time) * clip.speed();
+        let frame = (source_time * fps + NAVIGATION_EPSILON).floor().max(0.0) as u64;
+        Some(MatchFrame {
+            track_id: track_id.to_string(),
+            clip_id: clip.id.clone(),
+            source_path: clip.source_path.clone(),
+            source_time: frame as f64 / fps,
+            frame,
+            fps,
+            in_point: clip.in_point(),
+            out_point: clip.out_point(),
+        })
+    }
+
+    /// Clips of the targeted tracks with their track IDs, track by track
+    fn targeted_clips<'a>(&'a self, track_ids: &[&'a str]) -> Vec<(&'a str, &'a Clip)> {
+        let mut targets: Vec<&str> = track_ids.to_vec();
+        if targets.is_empty() {
+            targets = self.tracks().keys().map(String::as_str).collect();
+            targets.sort();
+        }
+        targets.into_iter()
+            .filter_map(|track_id| self.get_track(track_id).ok())
+            .flat_map(|track| track.clips.iter().map(move |clip| (track.id.as_str(), clip)))
+            .collect()
+    }
+}
@@ -2,11 +2,19 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::error::Error;
 use std::fmt;
+use std::io::{self, Read, Write};
+use tracing::warn;
 
 use crate::engine::timeline::{Timeline, Clip, ClipType, TimelineError};
+use crate::engine::timeline_conform::{FrameRateConform, SourceSample};
+use crate::engine::frame_interpolation::{blend_frames, interpolate_frames};
+use crate::engine::framing::{self, CustomFraming, Placement, SafeAreaGuides};
+use crate::engine::generators::Generator;
 use crate::engine::renderer::{Renderer, Frame, RendererError};
 use crate::engine::video_decoder::{VideoDecoder, VideoDecoderConfig, VideoFrame, VideoDecoderError};
 use crate::engine::VideoFormat;
+use crate::modules::metrics::{self, names, CacheStats, Meter};
+use crate::modules::spill_store::{self, Spillable, SpillStore, MB};
 
 #[derive(Debug)]
 pub enum TimelineRendererError {
@@ -55,6 +63,10 @@ pub struct TimelineRendererConfig {
     pub fps: f64,
     pub background_color: [u8; 4], // RGBA
     pub cache_size: usize,         // Number of frames to cache
+    /// Bytes of cached frames kept in memory; older frames past it are spilled to disk
+    pub cache_memory: usize,
+    /// Guides drawn over each frame; leave unset for frames that are exported
+    pub safe_area: Option<SafeAreaGuides>,
 }
 
 impl Default for TimelineRendererConfig {
@@ -65,6 +77,8 @@ impl Default for TimelineRendererConfig {
             fps: 30.0,
             background_color: [0, 0, 0, 255], // Black background
             cache_size: 30,                   // Cache 1 second of video at 30fps
+            cache_memory: 128 * MB,           // About half of that at 1080p, the rest on disk
+            safe_area: None,
         }
     }
 }
@@ -76,6 +90,8 @@ pub struct ClipRenderer {
     in_point: f64,
     out_point: f64,
     last_decoded_frame: Option<VideoFrame>,
+    /// Frames decoded, for the decode rate on the diagnostics dashboard
+    decoded: Meter,
 }
 
 impl ClipRenderer {
@@ -92,6 +108,7 @@ impl ClipRenderer {
             in_point,
             out_point,
             last_decoded_frame: None,
+            decoded: metrics::metrics().meter(names::DECODE_FRAMES),
         })
     }
     
@@ -107,8 +124,47 @@ impl ClipRenderer {
         Ok(())
     }
     
+    /// Seek to a time in the source media
+    pub fn seek_to_source_time(&mut self, source_time: f64) -> Result<(), TimelineRendererError> {
+        self.decoder.seek(source_time)?;
+        Ok(())
+    }
+    
+    /// Decode the frame for a source sample, mixing two frames when the clip is retimed
+    pub fn decode_sample(&mut self, sample: SourceSample) -> Result<VideoFrame, TimelineRendererError> {
+        match sample {
+            SourceSample::Frame(source_time) => {
+                self.seek_to_source_time(source_time)?;
+                Ok(self.decode_frame()?.clone())
+            },
+            SourceSample::Mix { first, second, weight, strategy } => {
+                self.seek_to_source_time(first)?;
+                let first = self.decode_frame()?.clone();
+                self.seek_to_source_time(second)?;
+                let second = self.decode_frame()?;
+                
+                if first.width != second.width || first.height != second.height {
+                    return Ok(first);
+                }
+                
+                let buffer = match strategy {
+                    FrameRateConform::OpticalFlow => interpolate_frames(
+                        &first.buffer,
+                        &second.buffer,
+                        first.width as usize,
+                        first.height as usize,
+                        weight,
+                    ),
+                    FrameRateConform::FrameBlend | FrameRateConform::Nearest => blend_frames(&first.buffer, &second.buffer, weight),
+                };
+                Ok(first.with_buffer(buffer))
+            },
+        }
+    }
+    
     pub fn decode_frame(&mut self) -> Result<&VideoFrame, TimelineRendererError> {
         let frame = self.decoder.decode_video_frame()?;
+        self.decoded.mark(1);
         self.last_decoded_frame = Some(frame);
         
         self.last_decoded_frame.as_ref().ok_or_else(|| {
@@ -127,7 +183,13 @@ pub struct TimelineRenderer {
     timeline: Arc<Mutex<Timeline>>,
     renderer: Renderer,
     clip_renderers: HashMap<String, ClipRenderer>,
-    frame_cache: HashMap<f64, Frame>, // Cache frames by timestamp
+    /// Frames of generated clips and of clips whose media is missing, drawn at the
+    /// output size when the timeline is loaded
+    generated_frames: HashMap<String, Vec<u8>>,
+    /// Generated clips drawn afresh each frame, like countdowns
+    animated_generators: HashMap<String, Generator>,
+    frame_cache: SpillStore<u64, Frame>, // Cache frames by the bits of their timestamp
+    cache_stats: CacheStats,
     is_initialized: bool,
 }
 
@@ -140,13 +202,17 @@ impl TimelineRenderer {
         };
         
         let renderer = Renderer::new(renderer_config);
+        let frame_cache = SpillStore::in_temp(names::PREVIEW_FRAME_CACHE, config.cache_memory);
         
         Ok(Self {
             config,
             timeline,
             renderer,
             clip_renderers: HashMap::new(),
-            frame_cache: HashMap::new(),
+            generated_frames: HashMap::new(),
+            animated_generators: HashMap::new(),
+            frame_cache,
+            cache_stats: metrics::metrics().cache(names::PREVIEW_FRAME_CACHE),
             is_initialized: false,
         })
     }
@@ -158,11 +224,16 @@ impl TimelineRenderer {
         
         for (track_id, track) in timeline.tracks() {
             for clip in &track.clips {
-                if clip.clip_type == ClipType::Video {
+                if let Some(generator) = Generator::for_clip(clip) {
+                    if generator.is_animated() {
+                        self.animated_generators.insert(clip.id.clone(), generator);
+                    } else {
+                        let frame = generator.render(self.config.width, self.config.height);
+                        self.generated_frames.insert(clip.id.clone(), frame);
+                    }
+                } else if clip.clip_type == ClipType::Video {
                     if let Some(source_path) = &clip.source_path {
-                        let in_point = clip.properties.get("in_point")
-                            .and_then(|s| s.parse::<f64>().ok())
-                            .unwrap_or(0.0);
+                        let in_point = clip.in_point();
                         
                         let out_point = in_point + clip.duration;
                         
@@ -189,8 +260,21 @@ impl TimelineRenderer {
             return Err(TimelineRendererError::ResourceError("Renderer not initialized".to_string()));
         }
         
-        if let Some(frame) = self.frame_cache.get(&time) {
-            return Ok(frame);
+        let key = time.to_bits();
+        let cached = match self.frame_cache.get(&key) {
+            Ok(frame) => frame.is_some(),
+            Err(e) => {
+                warn!("Re-rendering frame at {}: {}", time, e);
+                self.frame_cache.discard(&key);
+                false
+            },
+        };
+        self.cache_stats.record(cached);
+        if cached {
+            // Resident now, so this can't touch the disk again
+            return self.frame_cache.get(&key).ok().flatten().ok_or_else(|| {
+                TimelineRendererError::ResourceError(format!("Cached frame at {} went missing", time))
+            });
         }
         
         let timeline = self.timeline.lock().unwrap();
@@ -208,57 +292,92 @@ impl TimelineRenderer {
         // Render each active clip
         for (track_id, clips) in active_clips {
             for clip in clips {
-                if clip.clip_type == ClipType::Video {
+                if let Some(generated) = self.generated_frames.get(&clip.id) {
+                    // Drawn opaque at the output size, so it covers the frame
+                    frame_data.copy_from_slice(generated);
+                } else if let Some(generator) = self.animated_generators.get(&clip.id) {
+                    let generated = generator.render_at(
+                        self.config.width,
+                        self.config.height,
+                        time - clip.start_time,
+                        clip.duration,
+                        timeline.frame_rate().as_f64(),
+                    );
+                    frame_data.copy_from_slice(&generated);
+                } else if clip.clip_type == ClipType::Video {
                     if let Some(clip_renderer) = self.clip_renderers.get_mut(&clip.id) {
-                        // Seek to the correct time in the clip
-                        clip_renderer.seek_to_time(time, clip.start_time)?;
+                        // Source frames for this time, retimed if the clip's frame rate differs
+                        let sample = timeline.source_sample(clip, time);
                         
                         // Decode a frame
-                        let video_frame = clip_renderer.decode_frame()?;
+                        let video_frame = clip_renderer.decode_sample(sample)?;
+                        
+                        // Letterbox, crop or stretch as the clip's scale mode says
+                        let placement = framing::placement(
+                            timeline.clip_scale_mode(clip),
+                            CustomFraming::of(clip),
+                            (video_frame.width, video_frame.height),
+                            (self.config.width, self.config.height),
+                        );
                         
                         // Composite the frame onto our output frame
-                        self.composite_frame(&mut frame_data, video_frame)?;
+                        self.composite_frame(&mut frame_data, &video_frame, placement)?;
                     }
                 }
             }
         }
         
+        if let Some(guides) = &self.config.safe_area {
+            guides.draw(&mut frame_data, self.config.width, self.config.height, "RGBA");
+        }
+        
+        drop(timeline);
+        
         // Render the final frame
         let frame = self.renderer.render(&frame_data, time)?;
         
         // Add to cache (if cache is full, remove oldest entry)
-        if self.frame_cache.len() >= self.config.cache_size {
-            if let Some(oldest_time) = self.frame_cache.keys().min_by(|a, b| a.partial_cmp(b).unwrap()).cloned() {
-                self.frame_cache.remove(&oldest_time);
+        if self.frame_cache.len() >= self.config.cache_size.max(1) {
+            let oldest = self.frame_cache.keys().copied().min_by(|a, b| f64::from_bits(*a).total_cmp(&f64::from_bits(*b)));
+            if let Some(oldest) = oldest {
+                self.frame_cache.discard(&oldest);
             }
         }
-        
-        // We can't actually add to cache here because frame is borrowed from renderer
-        // In a real implementation, we'd need to clone the frame or use a different approach
+        self.frame_cache.insert(key, Frame {
+            data: frame.data.clone(),
+            width: frame.width,
+            height: frame.height,
+            timestamp: frame.timestamp,
+        });
         
         Ok(frame)
     }
     
-    fn composite_frame(&self, output: &mut [u8], input: &VideoFrame) -> Result<(), TimelineRendererError> {
-        // This is a simplified compositing function
-        // In a real implementation, we'd need to handle scaling, positioning, alpha blending, etc.
-        
-        let out_width = self.config.width as usize;
-        let out_height = self.config.height as usize;
+    /// Scale `input` into `placement` with nearest-neighbour sampling and alpha blend it
+    fn composite_frame(&self, output: &mut [u8], input: &VideoFrame, placement: Placement) -> Result<(), TimelineRendererError> {
+        let out_width = self.config.width as i64;
+        let out_height = self.config.height as i64;
         let in_width = input.width as usize;
         let in_height = input.height as usize;
         
-        // Simple center positioning
-        let x_offset = if out_width > in_width { (out_width - in_width) / 2 } else { 0 };
-        let y_offset = if out_height > in_height { (out_height - in_height) / 2 } else { 0 };
+        if in_width == 0 || in_height == 0 || input.data.len() < in_width * in_height * 4 {
+            return Err(TimelineRendererError::CompositionError(format!(
+                "Frame data doesn't match its {}x{} size", input.width, input.height
+            )));
+        }
+        
+        // Only the part of the placement inside the output frame
+        let x_range = placement.x.max(0)..(placement.x + placement.width as i64).min(out_width);
+        let y_range = placement.y.max(0)..(placement.y + placement.height as i64).min(out_height);
         
-        // Simple alpha blending
-        for y in 0..std::cmp::min(in_height, out_height) {
-            for x in 0..std::cmp::min(in_width, out_width) {
-                let in_pos = (y * in_width + x) * 4;
-                let out_pos = ((y + y_offset) * out_width + (x + x_offset)) * 4;
+        for y in y_range {
+            let in_y = ((y - placement.y) as usize * in_height / placement.height as usize).min(in_height - 1);
+            for x in x_range.clone() {
+                let in_x = ((x - placement.x) as usize * in_width / placement.width as usize).min(in_width - 1);
+                let in_pos = (in_y * in_width + in_x) * 4;
+                let out_pos = (y * out_width + x) as usize * 4;
                 
-                if out_pos + 3 < output.len() && in_pos + 3 < input.data.len() {
+                if out_pos + 3 < output.len() {
                     // Simple alpha blending
                     let alpha = input.data[in_pos + 3] as f32 / 255.0;
                     
@@ -273,6 +392,18 @@ impl TimelineRenderer {
         Ok(())
     }
     
+    /// Bytes of cached frames kept in memory before older ones are spilled to disk
+    pub fn set_cache_memory(&mut self, bytes: usize) {
+        self.config.cache_memory = bytes;
+        self.frame_cache.set_memory_limit(bytes);
+    }
+    
+    /// Show or hide safe-area guides on rendered frames
+    pub fn set_safe_area(&mut self, guides: Option<SafeAreaGuides>) {
+        self.config.safe_area = guides;
+        self.frame_cache.clear();
+    }
+    
     pub fn update_timeline(&mut self, timeline: Arc<Mutex<Timeline>>) -> Result<(), TimelineRendererError> {
         self.timeline = timeline;
         
@@ -285,6 +416,8 @@ impl TimelineRenderer {
         }
         
         self.clip_renderers.clear();
+        self.generated_frames.clear();
+        self.animated_generators.clear();
         
         // Re-initialize with new timeline
         self.initialize()?;
@@ -299,6 +432,8 @@ impl TimelineRenderer {
         }
         
         self.clip_renderers.clear();
+        self.generated_frames.clear();
+        self.animated_generators.clear();
         self.frame_cache.clear();
         
         // Clean up renderer
@@ -315,6 +450,30 @@ impl Drop for TimelineRenderer {
     }
 }
 
+impl Spillable for Frame {
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<Frame>() + self.data.len()
+    }
+    
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&self.timestamp.to_le_bytes())?;
+        spill_store::write_bytes(writer, &self.data)
+    }
+    
+    fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let mut header = [0u8; 16];
+        reader.read_exact(&mut header)?;
+        Ok(Frame {
+            width: u32::from_le_bytes(header[0..4].try_into().unwrap()),
+            height: u32::from_le_bytes(header[4..8].try_into().unwrap()),
+            timestamp: f64::from_le_bytes(header[8..16].try_into().unwrap()),
+            data: spill_store::read_bytes(reader)?,
+        })
+    }
+}
+
 pub fn create_default_timeline_renderer(timeline: Arc<Mutex<Timeline>>) -> Result<TimelineRenderer, TimelineRendererError> {
     let config = TimelineRendererConfig::default();
     let mut renderer = TimelineRenderer::new(config, timeline)?;
@@ -0,0 +1,280 @@
+//! Ripple operations that keep linked clips in sync
+//!
+//! Clips sharing a `link_group` property, like a video clip and its audio, move as one:
+//! a ripple on one track pulls the clips linked to the moved clips along on their own
+//! tracks. When a linked clip can't follow, because its track is locked or it would run
+//! into another clip, the ripple fails with `TimelineError::LinkConflict` and the
+//! timeline is left as it was.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use tracing::debug;
+
+use crate::engine::timeline::{Clip, Timeline, TimelineError};
+use crate::engine::timeline_edits::split_clip;
+use crate::modules::audio_sync::LINK_GROUP_PROPERTY;
+
+/// Times closer than this are equal
+const RIPPLE_EPSILON: f64 = 1e-6;
+
+/// Why a linked clip can't follow a ripple
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkConflictReason {
+    /// The linked clip is on a locked track
+    LockedTrack,
+    /// Moving the linked clip would overlap a clip that stays put
+    Overlap { other_clip_id: String },
+}
+
+/// A linked clip that would lose sync with a clip moved by a ripple
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkConflict {
+    pub clip_id: String,
+    pub track_id: String,
+    /// The moved clip it is linked to
+    pub linked_clip_id: String,
+    pub link_group: String,
+    pub reason: LinkConflictReason,
+}
+
+impl fmt::Display for LinkConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {} can't follow {}: ", self.clip_id, self.track_id, self.linked_clip_id)?;
+        match &self.reason {
+            LinkConflictReason::LockedTrack => write!(f, "track is locked"),
+            LinkConflictReason::Overlap { other_clip_id } => write!(f, "would overlap {}", other_clip_id),
+        }
+    }
+}
+
+/// A clip in the ripple's working copy of the timeline
+type ClipRef = (String, usize);
+
+impl Timeline {
+    /// Move every clip starting at or after `at` on the targeted tracks by `amount` seconds,
+    /// along with the clips linked to them; returns the IDs of the moved clips, sorted
+    ///
+    /// An empty `track_ids` targets every unlocked track. When pushing clips later, clips
+    /// crossing `at` on the targeted tracks are split there. A linked clip follows the
+    /// moved clip it plays alongside, wherever it starts.
+    pub fn ripple(&mut self, track_ids: &[&str], at: f64, amount: f64) -> Result<Vec<String>, TimelineError> {
+        let targets = self.ripple_targets(track_ids)?;
+        let locked: HashSet<String> = self.tracks().values()
+            .filter(|track| track.is_locked)
+            .map(|track| track.id.clone())
+            .collect();
+        let mut working: HashMap<String, Vec<Clip>> = self.tracks().values()
+            .map(|track| (track.id.clone(), track.clips.clone()))
+            .collect();
+
+        if amount > 0.0 {
+            let mut taken: HashSet<String> = working.values().flatten().map(|clip| clip.id.clone()).collect();
+            for track_id in &targets {
+                let clips = working.get_mut(track_id).unwrap();
+                let tails: Vec<Clip> = clips.iter_mut().filter_map(|clip| split_clip(clip, at, &mut taken)).collect();
+                clips.extend(tails);
+            }
+        }
+
+        // Clips starting after the ripple point move, then whatever is linked to them
+        let mut moving: HashSet<ClipRef> = HashSet::new();
+        let mut queue: VecDeque<ClipRef> = VecDeque::new();
+        for track_id in &targets {
+            for (index, clip) in working[track_id].iter().enumerate() {
+                if clip.start_time >= at - RIPPLE_EPSILON {
+                    moving.insert((track_id.clone(), index));
+                    queue.push_back((track_id.clone(), index));
+                }
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        while let Some((track_id, index)) = queue.pop_front() {
+            let clip = &working[&track_id][index];
+            let Some(group) = clip.properties.get(LINK_GROUP_PROPERTY) else { continue };
+            for (other_track, clips) in &working {
+                for (other_index, other) in clips.iter().enumerate() {
+                    let key = (other_track.clone(), other_index);
+                    if moving.contains(&key)
+                        || other.properties.get(LINK_GROUP_PROPERTY) != Some(group)
+                        || !plays_alongside(clip, other)
+                    {
+                        continue;
+                    }
+                    if locked.contains(other_track) {
+                        conflicts.push(LinkConflict {
+                            clip_id: other.id.clone(),
+                            track_id: other_track.clone(),
+                            linked_clip_id: clip.id.clone(),
+                            link_group: group.clone(),
+                            reason: LinkConflictReason::LockedTrack,
+                        });
+                        continue;
+                    }
+                    moving.insert(key.clone());
+                    queue.push_back(key);
+                }
+            }
+        }
+
+        // Moved clips must not land on ones that stay put
+        for (track_id, clips) in &working {
+            for (index, clip) in clips.iter().enumerate() {
+                if !moving.contains(&(track_id.clone(), index)) {
+                    continue;
+                }
+                let (start, end) = (clip.start_time + amount, clip.end_time() + amount);
+                if start < -RIPPLE_EPSILON {
+                    return Err(TimelineError::InvalidTime(format!(
+                        "Ripple moves {} to {:.3}s, before the start of the timeline", clip.id, start
+                    )));
+                }
+                let blocker = clips.iter().enumerate().find(|(other_index, other)| {
+                    !moving.contains(&(track_id.clone(), *other_index))
+                        && other.clip_type == clip.clip_type
+                        && other.start_time < end - RIPPLE_EPSILON
+                        && other.end_time() > start + RIPPLE_EPSILON
+                });
+                let Some((_, blocker)) = blocker else { continue };
+                match clip.properties.get(LINK_GROUP_PROPERTY) {
+                    Some(group) if !targets.contains(track_id) => conflicts.push(LinkConflict {
+                        clip_id: clip.id.clone(),
+                        track_id: track_id.clone(),
+                        linked_clip_id: linked_mover(&working, &moving, clip, group).unwrap_or_default(),
+                        link_group: group.clone(),
+                        reason: LinkConflictReason::Overlap { other_clip_id: blocker.id.clone() },
+                    }),
+                    _ => return Err(TimelineError::OperationError(format!(
+                        "Ripple moves {} onto {} on track {}", clip.id, blocker.id, track_id
+                    ))),
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            // A clip is reported once even when several moved clips pull on it
+            conflicts.sort_by(|a, b| (&a.track_id, &a.clip_id).cmp(&(&b.track_id, &b.clip_id)));
+            conflicts.dedup_by(|a, b| a.track_id == b.track_id && a.clip_id == b.clip_id);
+            return Err(TimelineError::LinkConflict(conflicts));
+        }
+
+        let mut moved = BTreeSet::new();
+        let mut end = self.duration();
+        for (track_id, mut clips) in working {
+            for (index, clip) in clips.iter_mut().enumerate() {
+                if moving.contains(&(track_id.clone(), index)) {
+                    clip.start_time += amount;
+                    moved.insert(clip.id.clone());
+                }
+                end = end.max(clip.end_time());
+            }
+            clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+            self.get_track_mut(&track_id)?.clips = clips;
+        }
+        if end > self.duration() {
+            self.set_duration(end)?;
+        }
+
+        debug!("Rippled {} clips by {:.3}s from {:.3}s", moved.len(), amount, at);
+        Ok(moved.into_iter().collect())
+    }
+
+    /// Remove a clip and the clips linked to it, closing the gap it leaves on their tracks
+    ///
+    /// Returns the removed clips, the given one first.
+    pub fn ripple_delete(&mut self, track_id: &str, clip_id: &str) -> Result<Vec<Clip>, TimelineError> {
+        let track = self.get_track(track_id)?;
+        if track.is_locked {
+            return Err(TimelineError::InvalidTrack(format!("Track {} is locked", track_id)));
+        }
+        let clip = track.clips.iter()
+            .find(|clip| clip.id == clip_id)
+            .cloned()
+            .ok_or_else(|| TimelineError::InvalidClip(format!("Clip with id {} not found", clip_id)))?;
+
+        // Linked clips go too, so check none of them is locked in place
+        let mut linked: Vec<(String, String)> = Vec::new();
+        let mut conflicts = Vec::new();
+        if let Some(group) = clip.properties.get(LINK_GROUP_PROPERTY) {
+            let mut tracks: Vec<_> = self.tracks().values().collect();
+            tracks.sort_by(|a, b| a.id.cmp(&b.id));
+            for track in tracks {
+                for other in &track.clips {
+                    if other.id == clip.id
+                        || other.properties.get(LINK_GROUP_PROPERTY) != Some(group)
+                        || !plays_alongside(&clip, other)
+                    {
+                        continue;
+                    }
+                    if track.is_locked {
+                        conflicts.push(LinkConflict {
+                            clip_id: other.id.clone(),
+                            track_id: track.id.clone(),
+                            linked_clip_id: clip.id.clone(),
+                            link_group: group.clone(),
+                            reason: LinkConflictReason::LockedTrack,
+                        });
+                    } else {
+                        linked.push((track.id.clone(), other.id.clone()));
+                    }
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(TimelineError::LinkConflict(conflicts));
+        }
+
+        let mut removed = vec![(track_id.to_string(), self.remove_clip_from_track(track_id, clip_id)?)];
+        for (track_id, clip_id) in &linked {
+            removed.push((track_id.clone(), self.remove_clip_from_track(track_id, clip_id)?));
+        }
+
+        let mut targets: Vec<&str> = removed.iter().map(|(track_id, _)| track_id.as_str()).collect();
+        targets.sort();
+        targets.dedup();
+        if let Err(e) = self.ripple(&targets, clip.end_time(), -clip.duration) {
+            // Put the clips back so a failed ripple changes nothing
+            for (track_id, clip) in &removed {
+                let track = self.get_track_mut(track_id)?;
+                track.clips.push(clip.clone());
+                track.clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+            }
+            return Err(e);
+        }
+
+        Ok(removed.into_iter().map(|(_, clip)| clip).collect())
+    }
+
+    fn ripple_targets(&self, track_ids: &[&str]) -> Result<Vec<String>, TimelineError> {
+        if track_ids.is_empty() {
+            return Ok(self.tracks().values()
+                .filter(|track| !track.is_locked)
+                .map(|track| track.id.clone())
+                .collect());
+        }
+        track_ids.iter()
+            .map(|track_id| {
+                if self.get_track(track_id)?.is_locked {
+                    return Err(TimelineError::InvalidTrack(format!("Track {} is locked", track_id)));
+                }
+                Ok(track_id.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Whether two clips play at the same time, so a ripple must keep them together
+fn plays_alongside(a: &Clip, b: &Clip) -> bool {
+    a.start_time < b.end_time() - RIPPLE_EPSILON && b.start_time < a.end_time() - RIPPLE_EPSILON
+}
+
+/// ID of a moving clip on another track that `clip` is linked to
+fn linked_mover(working: &HashMap<String, Vec<Clip>>, moving: &HashSet<ClipRef>, clip: &Clip, group: &str) -> Option<String> {
+    moving.iter()
+        .map(|(track_id, index)| &working[track_id][*index])
+        .find(|other| {
+            other.id != clip.id
+                && other.properties.get(LINK_GROUP_PROPERTY).map(String::as_str) == Some(group)
+                && plays_alongside(clip, other)
+        })
+        .map(|other| other.id.clone())
+}
@@ -0,0 +1,106 @@
+//! Finding clips and markers on a timeline
+//!
+//! `Timeline::find` matches text against clip names, source paths, marker text and the
+//! effects applied to clips, and `Timeline::seek_to_result` moves the playhead to a hit.
+
+use crate::engine::timeline::{Clip, Timeline, TimelineError};
+use crate::engine::timeline_attributes::EFFECT_PREFIX;
+
+/// Clip property with the name shown for a clip, when it differs from its ID
+pub const CLIP_NAME_PROPERTY: &str = "name";
+
+/// What a search result matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchMatch {
+    /// The clip's ID or name
+    Name,
+    /// The clip's source path
+    Source,
+    /// A marker's name or note
+    Marker,
+    /// The type of an effect on the clip
+    Effect,
+}
+
+/// A clip or marker found by `Timeline::find`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// Clip or marker ID
+    pub id: String,
+    /// Track of a clip, `None` for markers
+    pub track_id: Option<String>,
+    /// Position on the timeline, in seconds
+    pub time: f64,
+    pub duration: f64,
+    pub matched: SearchMatch,
+}
+
+impl Timeline {
+    /// Clips and markers matching `query`, ignoring case, sorted by time then track
+    ///
+    /// A clip matching in several ways is listed once, by the first of name, source path
+    /// and effect type that matched. An empty query finds nothing.
+    pub fn find(&self, query: &str) -> Vec<SearchResult> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let found = |text: &str| text.to_lowercase().contains(&query);
+
+        let mut results: Vec<SearchResult> = self.tracks().values()
+            .flat_map(|track| track.clips.iter().map(move |clip| (track, clip)))
+            .filter_map(|(track, clip)| {
+                let name_matches = found(&clip.id)
+                    || clip.properties.get(CLIP_NAME_PROPERTY).is_some_and(|name| found(name));
+                let matched = if name_matches {
+                    SearchMatch::Name
+                } else if clip.source_path.as_deref().is_some_and(found) {
+                    SearchMatch::Source
+                } else if effect_types(clip).any(found) {
+                    SearchMatch::Effect
+                } else {
+                    return None;
+                };
+                Some(SearchResult {
+                    id: clip.id.clone(),
+                    track_id: Some(track.id.clone()),
+                    time: clip.start_time,
+                    duration: clip.duration,
+                    matched,
+                })
+            })
+            .collect();
+
+        results.extend(self.markers().iter()
+            .filter(|marker| found(&marker.name) || found(&marker.note))
+            .map(|marker| SearchResult {
+                id: marker.id.clone(),
+                track_id: None,
+                time: marker.time,
+                duration: marker.duration,
+                matched: SearchMatch::Marker,
+            }));
+
+        // Tracks are kept in a map; markers sort ahead of clips at the same time
+        results.sort_by(|a, b| a.time.total_cmp(&b.time).then_with(|| a.track_id.cmp(&b.track_id)));
+        results
+    }
+
+    /// Move the playhead to the start of the `index`th result for `query`, counting from 0
+    pub fn seek_to_result(&mut self, query: &str, index: usize) -> Result<SearchResult, TimelineError> {
+        let results = self.find(query);
+        let count = results.len();
+        let result = results.into_iter().nth(index).ok_or_else(|| TimelineError::OperationError(
+            format!("No result {} for \"{}\" ({} found)", index, query, count)
+        ))?;
+        self.seek(result.time.min(self.duration()))?;
+        Ok(result)
+    }
+}
+
+/// Types of the effects on a clip, from its `effect.<type>.<parameter>` properties
+fn effect_types(clip: &Clip) -> impl Iterator<Item = &str> {
+    clip.properties.keys()
+        .filter_map(|key| key.strip_prefix(EFFECT_PREFIX))
+        .map(|rest| rest.split('.').next().unwrap_or(rest))
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::generators::{Generator, GENERATOR_PROPERTY};
+use crate::engine::timeline::{Clip, ClipType, Timeline, Track};
+use crate::engine::timeline_conform::FormatMismatch;
+use crate::modules::assembly::{TRANSITION_DURATION_PROPERTY, TRANSITION_PROPERTY};
+
+/// Tolerance used when comparing times, in seconds
+const TIME_EPSILON: f64 = 1e-6;
+
+/// Longest gap or clip, in frames, treated as an editing accident rather than intended
+pub const MAX_FLASH_FRAMES: u32 = 2;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Renders, but probably not as intended
+    Warning,
+    /// Would fail or render garbage; blocks export
+    Error,
+}
+
+/// What a diagnostic is about
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// Two clips of the same type overlap on a track and the later one has no transition
+    /// covering the overlap
+    Overlap {
+        other_clip_id: String,
+        overlap: f64,
+    },
+    /// A media clip has no source, or its source file doesn't exist
+    MissingMedia {
+        path: Option<String>,
+    },
+    /// A generated clip whose generator is missing or unknown
+    UnknownGenerator {
+        generator: Option<String>,
+    },
+    /// A clip with zero or negative duration
+    ZeroLength,
+    /// An effect clip that extends outside the timeline or covers no media
+    EffectOutOfBounds,
+    /// Source frame rate differs from the timeline's
+    FrameRateMismatch {
+        clip_fps: f64,
+        timeline_fps: FrameRate,
+    },
+    /// Source sample rate differs from the timeline's
+    SampleRateMismatch {
+        clip_rate: u32,
+        timeline_rate: u32,
+    },
+    /// A gap of at most `MAX_FLASH_FRAMES` frames between the clip and the one before it
+    Gap {
+        previous_clip_id: String,
+        /// Where the gap starts, in seconds
+        start: f64,
+        frames: u32,
+    },
+    /// A media clip at most `MAX_FLASH_FRAMES` frames long, usually left over from a trim
+    FlashFrame {
+        start: f64,
+        frames: u32,
+    },
+}
+
+/// A single problem found by `Timeline::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub track_id: String,
+    pub clip_id: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on {}: ", self.clip_id, self.track_id)?;
+        match &self.kind {
+            DiagnosticKind::Overlap { other_clip_id, overlap } => {
+                write!(f, "overlaps {} by {:.3}s without a transition", other_clip_id, overlap)
+            },
+            DiagnosticKind::MissingMedia { path: Some(path) } => write!(f, "media not found: {}", path),
+            DiagnosticKind::MissingMedia { path: None } => write!(f, "no source media"),
+            DiagnosticKind::UnknownGenerator { generator: Some(generator) } => write!(f, "unknown generator: {}", generator),
+            DiagnosticKind::UnknownGenerator { generator: None } => write!(f, "no generator"),
+            DiagnosticKind::ZeroLength => write!(f, "zero-length clip"),
+            DiagnosticKind::EffectOutOfBounds => write!(f, "effect outside the timeline or media"),
+            DiagnosticKind::FrameRateMismatch { clip_fps, timeline_fps } => {
+                write!(f, "source is {} fps, timeline is {} fps", clip_fps, timeline_fps)
+            },
+            DiagnosticKind::SampleRateMismatch { clip_rate, timeline_rate } => {
+                write!(f, "source is {} Hz, timeline is {} Hz", clip_rate, timeline_rate)
+            },
+            DiagnosticKind::Gap { previous_clip_id, start, frames } => {
+                write!(f, "{} frame gap after {} at {:.3}s", frames, previous_clip_id, start)
+            },
+            DiagnosticKind::FlashFrame { start, frames } => write!(f, "flash frame ({} frames) at {:.3}s", frames, start),
+        }
+    }
+}
+
+/// Result of validating a timeline
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Diagnostics sorted by track and clip ID
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    /// Whether there are no errors; warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Warning)
+    }
+
+    /// Errors joined into one line, for error messages
+    pub fn error_summary(&self) -> String {
+        self.errors().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    }
+}
+
+impl Timeline {
+    /// Check the timeline for problems that would break or spoil a render
+    ///
+    /// Run before export and when a project is loaded. Source media is checked on disk.
+    pub fn validate(&self) -> ValidationReport {
+        let tracks = self.sorted_tracks();
+
+        let mut diagnostics = Vec::new();
+        for track in &tracks {
+            let clips = sorted_clips(track);
+
+            let mut report = |severity, kind, clip: &Clip| diagnostics.push(Diagnostic {
+                severity,
+                kind,
+                track_id: track.id.clone(),
+                clip_id: clip.id.clone(),
+            });
+
+            for (index, &clip) in clips.iter().enumerate() {
+                if clip.duration <= TIME_EPSILON {
+                    report(Severity::Error, DiagnosticKind::ZeroLength, clip);
+                }
+
+                if let Some((other, overlap)) = find_overlap(clip, &clips[..index]) {
+                    report(Severity::Error, DiagnosticKind::Overlap { other_clip_id: other.id.clone(), overlap }, clip);
+                }
+
+                match clip.clip_type {
+                    ClipType::Video | ClipType::Audio | ClipType::Image => {
+                        let missing = match &clip.source_path {
+                            Some(path) => !Path::new(path).exists(),
+                            None => true,
+                        };
+                        if missing {
+                            report(Severity::Error, DiagnosticKind::MissingMedia { path: clip.source_path.clone() }, clip);
+                        }
+                    },
+                    ClipType::Effect => {
+                        if !self.effect_in_bounds(clip, &tracks) {
+                            report(Severity::Warning, DiagnosticKind::EffectOutOfBounds, clip);
+                        }
+                    },
+                    ClipType::Generated => {
+                        if Generator::of(clip).is_none() {
+                            let generator = clip.properties.get(GENERATOR_PROPERTY).cloned();
+                            report(Severity::Error, DiagnosticKind::UnknownGenerator { generator }, clip);
+                        }
+                    },
+                    ClipType::Text => (),
+                }
+
+                // Mismatches with a recorded conversion are intentional
+                for mismatch in self.format_mismatches(clip) {
+                    match mismatch {
+                        FormatMismatch::FrameRate { source, sequence } => report(
+                            Severity::Warning,
+                            DiagnosticKind::FrameRateMismatch { clip_fps: source, timeline_fps: sequence },
+                            clip,
+                        ),
+                        FormatMismatch::SampleRate { source, sequence } => report(
+                            Severity::Warning,
+                            DiagnosticKind::SampleRateMismatch { clip_rate: source, timeline_rate: sequence },
+                            clip,
+                        ),
+                        FormatMismatch::Resolution { .. } => (),
+                    }
+                }
+            }
+
+            for (kind, clip) in self.cut_problems(&clips) {
+                report(Severity::Warning, kind, clip);
+            }
+        }
+
+        ValidationReport { diagnostics }
+    }
+
+    /// Gaps and flash frames of at most `MAX_FLASH_FRAMES` frames, by track and time
+    ///
+    /// A quicker pass than `validate` for fixing cuts before export; the same problems are
+    /// reported there as warnings.
+    pub fn find_gaps_and_flash_frames(&self) -> Vec<Diagnostic> {
+        self.sorted_tracks()
+            .into_iter()
+            .flat_map(|track| {
+                self.cut_problems(&sorted_clips(track))
+                    .into_iter()
+                    .map(|(kind, clip)| Diagnostic {
+                        severity: Severity::Warning,
+                        kind,
+                        track_id: track.id.clone(),
+                        clip_id: clip.id.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn sorted_tracks(&self) -> Vec<&Track> {
+        let mut tracks: Vec<&Track> = self.tracks().values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        tracks
+    }
+
+    /// Tiny gaps and clips among a track's media clips, sorted by start time
+    ///
+    /// Zero-length clips are left to the `ZeroLength` check, and gaps only count between
+    /// two clips, not before the first one.
+    fn cut_problems<'a>(&self, clips: &[&'a Clip]) -> Vec<(DiagnosticKind, &'a Clip)> {
+        let frame = self.frame_rate().frame_duration();
+        let limit = MAX_FLASH_FRAMES as f64 * frame + TIME_EPSILON;
+        let frames = |duration: f64| (duration / frame).round().max(1.0) as u32;
+
+        let mut problems = Vec::new();
+        let mut previous: Option<&Clip> = None;
+        for &clip in clips.iter().filter(|clip| matches!(clip.clip_type, ClipType::Video | ClipType::Audio | ClipType::Image | ClipType::Generated)) {
+            if clip.duration <= TIME_EPSILON {
+                continue;
+            }
+            if let Some(previous) = previous {
+                let gap = clip.start_time - previous.end_time();
+                if gap > TIME_EPSILON && gap <= limit {
+                    problems.push((DiagnosticKind::Gap {
+                        previous_clip_id: previous.id.clone(),
+                        start: previous.end_time(),
+                        frames: frames(gap),
+                    }, clip));
+                }
+            }
+            if clip.duration <= limit {
+                problems.push((DiagnosticKind::FlashFrame { start: clip.start_time, frames: frames(clip.duration) }, clip));
+            }
+            if previous.is_none_or(|previous| clip.end_time() > previous.end_time()) {
+                previous = Some(clip);
+            }
+        }
+        problems
+    }
+
+    /// Whether an effect clip lies within the timeline and over some media
+    fn effect_in_bounds(&self, effect: &Clip, tracks: &[&Track]) -> bool {
+        if effect.start_time < -TIME_EPSILON || effect.end_time() > self.duration() + TIME_EPSILON {
+            return false;
+        }
+
+        tracks.iter()
+            .filter(|track| !track.is_muted)
+            .flat_map(|track| track.clips.iter())
+            .filter(|clip| matches!(clip.clip_type, ClipType::Video | ClipType::Image | ClipType::Generated))
+            .any(|clip| clip.start_time < effect.end_time() - TIME_EPSILON && clip.end_time() > effect.start_time + TIME_EPSILON)
+    }
+}
+
+/// Clips of a track by start time, then ID
+fn sorted_clips(track: &Track) -> Vec<&Clip> {
+    let mut clips: Vec<&Clip> = track.clips.iter().collect();
+    clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time).then_with(|| a.id.cmp(&b.id)));
+    clips
+}
+
+/// Earlier clip of the same type that `clip` overlaps by more than its transition allows
+fn find_overlap<'a>(clip: &Clip, earlier: &[&'a Clip]) -> Option<(&'a Clip, f64)> {
+    // A transition into the clip allows overlap up to its duration, or any overlap if unsized
+    let allowed = match clip.properties.get(TRANSITION_PROPERTY) {
+        Some(_) => clip.properties
+            .get(TRANSITION_DURATION_PROPERTY)
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(f64::INFINITY),
+        None => 0.0,
+    };
+
+    earlier.iter()
+        .filter(|other| other.clip_type == clip.clip_type)
+        .map(|other| (*other, other.end_time().min(clip.end_time()) - clip.start_time))
+        .filter(|(_, overlap)| *overlap > allowed + TIME_EPSILON)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
@@ -16,7 +16,7 @@ use ffmpeg::util::frame::Frame;
 use ffmpeg::util::format;
 use ffmpeg::util::error::Error as FFmpegError;
 use ffmpeg::util::log as ffmpeg_log;
-use log::{debug, error, info, warn};
+use tracing::{debug, error, info, warn};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -249,6 +249,9 @@ impl VideoDecoder {
     
     /// Open a media file and prepare for decoding
     pub fn open<P: AsRef<Path>>(&mut self, path: P) -> Result<&MediaInfo, VideoDecoderError> {
+        let span = tracing::info_span!("decode", path = %path.as_ref().display());
+        let _span = span.enter();
+        
         // Initialize FFmpeg if not already done
         Self::init_ffmpeg()?;
         
@@ -464,6 +467,7 @@ impl VideoDecoder {
     }
     
     /// Decode the next video frame
+    #[tracing::instrument(name = "decode", level = "trace", skip(self), fields(stream = self.current_video_stream))]
     pub fn decode_video_frame(&mut self) -> Result<VideoFrame, VideoDecoderError> {
         if !self.is_initialized {
             return Err(VideoDecoderError::InitializationError("Decoder not initialized".to_string()));
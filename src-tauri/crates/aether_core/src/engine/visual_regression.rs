@@ -0,0 +1,269 @@
+//! Visual regression checks: perceptual hashes of rendered frames compared to stored baselines
+//!
+//! A frame is reduced to a 64-bit perceptual hash: its luma is averaged down to 32x32,
+//! transformed with a DCT, and each of the 8x8 lowest frequencies becomes one bit, set if
+//! the coefficient is clearly above their median. Scaling, recompression and small color shifts
+//! flip few bits; a changed picture flips many. Two frames match when their hashes
+//! differ in at most `tolerance` bits.
+//!
+//! Baselines are JSON files of hashes by timeline time, recorded from a trusted render
+//! and checked in next to the tests using them.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::engine::editing::PreviewFrame;
+use crate::engine::timeline_backend::TimelineBackend;
+
+/// Bits two frames may differ in and still match
+pub const DEFAULT_TOLERANCE: u32 = 6;
+
+/// Set to re-record baselines instead of checking against them
+pub const UPDATE_BASELINES_ENV: &str = "AETHER_UPDATE_BASELINES";
+
+/// Side of the luma image the DCT runs on
+const SAMPLE_SIZE: usize = 32;
+
+/// Side of the block of low frequencies kept in the hash
+const HASH_SIZE: usize = 8;
+
+/// Share of the strongest coefficient a coefficient must exceed the median by to set its bit
+const NOISE_MARGIN: f64 = 0.02;
+
+/// Perceptual hash of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FrameHash(pub u64);
+
+impl FrameHash {
+    /// Hash of packed RGBA pixels
+    pub fn from_rgba(width: u32, height: u32, data: &[u8]) -> Result<Self> {
+        let (width, height) = (width as usize, height as usize);
+        if width == 0 || height == 0 {
+            return Err(anyhow!("Cannot hash an empty frame"));
+        }
+        if data.len() < width * height * 4 {
+            return Err(anyhow!("{}x{} frame has only {} bytes", width, height, data.len()));
+        }
+
+        let luma = downsample_luma(width, height, data);
+        let coefficients = low_frequencies(&luma);
+
+        // The DC term is the average brightness, which says nothing about the picture
+        let mut sorted = coefficients[1..].to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
+
+        // Simple pictures leave most coefficients near zero, where noise would decide their
+        // bits; a margin scaled to the strongest one keeps them clear. Flat frames get the
+        // margin of a one-level luma step, so they hash to no set bits but the DC one.
+        let strongest = coefficients[1..].iter()
+            .fold((SAMPLE_SIZE * SAMPLE_SIZE) as f64, |max: f64, coefficient| max.max(coefficient.abs()));
+        let threshold = median + strongest * NOISE_MARGIN;
+
+        let bits = coefficients.iter()
+            .enumerate()
+            .filter(|(_, &coefficient)| coefficient > threshold)
+            .fold(0u64, |bits, (i, _)| bits | (1 << i));
+        Ok(Self(bits))
+    }
+
+    pub fn from_frame(frame: &PreviewFrame) -> Result<Self> {
+        Self::from_rgba(frame.width, frame.height, &frame.data)
+    }
+
+    /// Number of bits that differ
+    pub fn distance(&self, other: &FrameHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+/// Hash of the frame at one timeline time
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BaselineFrame {
+    /// Timeline time in seconds
+    pub time: f64,
+    pub hash: FrameHash,
+}
+
+/// Recorded hashes of a trusted render
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub frames: Vec<BaselineFrame>,
+}
+
+impl Baseline {
+    /// Render and hash the frames at `times`
+    pub fn capture(backend: &mut dyn TimelineBackend, times: &[f64]) -> Result<Self> {
+        let frames = times.iter()
+            .map(|&time| {
+                let frame = backend.render_frame(time)
+                    .with_context(|| format!("Failed to render frame at {:.3}s", time))?;
+                Ok(BaselineFrame { time, hash: FrameHash::from_frame(&frame)? })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { frames })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid baseline {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn times(&self) -> Vec<f64> {
+        self.frames.iter().map(|frame| frame.time).collect()
+    }
+
+    /// Render the baseline's frames again and compare them
+    pub fn check(&self, backend: &mut dyn TimelineBackend, tolerance: u32) -> Result<RegressionReport> {
+        let current = Baseline::capture(backend, &self.times())?;
+        Ok(self.compare(&current, tolerance))
+    }
+
+    /// Compare against hashes of the same times, matched in order
+    pub fn compare(&self, current: &Baseline, tolerance: u32) -> RegressionReport {
+        let frames = self.frames.iter()
+            .zip(&current.frames)
+            .map(|(expected, actual)| FrameComparison {
+                time: expected.time,
+                expected: expected.hash,
+                actual: actual.hash,
+                distance: expected.hash.distance(&actual.hash),
+            })
+            .collect();
+        RegressionReport { frames, tolerance }
+    }
+}
+
+/// How one frame compares to its baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameComparison {
+    pub time: f64,
+    pub expected: FrameHash,
+    pub actual: FrameHash,
+    /// Bits the hashes differ in
+    pub distance: u32,
+}
+
+/// Result of checking frames against a baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegressionReport {
+    pub frames: Vec<FrameComparison>,
+    pub tolerance: u32,
+}
+
+impl RegressionReport {
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Frames further from the baseline than the tolerance
+    pub fn failures(&self) -> impl Iterator<Item = &FrameComparison> {
+        self.frames.iter().filter(move |frame| frame.distance > self.tolerance)
+    }
+
+    /// One line per failing frame, for test output
+    pub fn summary(&self) -> String {
+        let failures: Vec<String> = self.failures()
+            .map(|frame| format!(
+                "{:.3}s: {:016x} differs from baseline {:016x} in {} bits",
+                frame.time, frame.actual.0, frame.expected.0, frame.distance
+            ))
+            .collect();
+        if failures.is_empty() {
+            format!("{} frames match the baseline", self.frames.len())
+        } else {
+            format!("{} of {} frames changed (tolerance {} bits)\n{}", failures.len(), self.frames.len(), self.tolerance, failures.join("\n"))
+        }
+    }
+}
+
+/// Check the frames at `times` against the baseline at `path`
+///
+/// Records the baseline instead when it doesn't exist yet or `AETHER_UPDATE_BASELINES` is
+/// set, and reports a pass. Fails if the baseline was recorded at other times.
+pub fn check_baseline(backend: &mut dyn TimelineBackend, path: &Path, times: &[f64], tolerance: u32) -> Result<RegressionReport> {
+    if !path.exists() || std::env::var_os(UPDATE_BASELINES_ENV).is_some() {
+        let baseline = Baseline::capture(backend, times)?;
+        baseline.save(path)?;
+        info!("Recorded baseline {} of {} frames", path.display(), times.len());
+        return Ok(baseline.compare(&baseline, tolerance));
+    }
+
+    let baseline = Baseline::load(path)?;
+    if baseline.times() != times {
+        return Err(anyhow!(
+            "Baseline {} was recorded at other times; set {} to record it again",
+            path.display(), UPDATE_BASELINES_ENV
+        ));
+    }
+    baseline.check(backend, tolerance)
+}
+
+/// Luma of the frame averaged over a `SAMPLE_SIZE` square grid
+fn downsample_luma(width: usize, height: usize, data: &[u8]) -> Vec<f64> {
+    let mut sums = vec![0.0; SAMPLE_SIZE * SAMPLE_SIZE];
+    let mut counts = vec![0u32; SAMPLE_SIZE * SAMPLE_SIZE];
+
+    for y in 0..height {
+        let cell_y = y * SAMPLE_SIZE / height;
+        for x in 0..width {
+            let cell = cell_y * SAMPLE_SIZE + x * SAMPLE_SIZE / width;
+            let pixel = &data[(y * width + x) * 4..][..3];
+            sums[cell] += 0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64;
+            counts[cell] += 1;
+        }
+    }
+
+    // Frames smaller than the grid leave cells empty; repeat the nearest pixel instead
+    (0..SAMPLE_SIZE * SAMPLE_SIZE)
+        .map(|cell| {
+            if counts[cell] > 0 {
+                return sums[cell] / counts[cell] as f64;
+            }
+            let (x, y) = ((cell % SAMPLE_SIZE) * width / SAMPLE_SIZE, (cell / SAMPLE_SIZE) * height / SAMPLE_SIZE);
+            let pixel = &data[(y * width + x) * 4..][..3];
+            0.299 * pixel[0] as f64 + 0.587 * pixel[1] as f64 + 0.114 * pixel[2] as f64
+        })
+        .collect()
+}
+
+/// The `HASH_SIZE` square of lowest DCT-II coefficients, row by row
+fn low_frequencies(luma: &[f64]) -> Vec<f64> {
+    let basis: Vec<f64> = (0..HASH_SIZE)
+        .flat_map(|u| (0..SAMPLE_SIZE).map(move |x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SAMPLE_SIZE) as f64).cos()))
+        .collect();
+
+    // Rows first, then columns, as the transform is separable
+    let mut rows = vec![0.0; SAMPLE_SIZE * HASH_SIZE];
+    for y in 0..SAMPLE_SIZE {
+        for u in 0..HASH_SIZE {
+            rows[y * HASH_SIZE + u] = (0..SAMPLE_SIZE)
+                .map(|x| luma[y * SAMPLE_SIZE + x] * basis[u * SAMPLE_SIZE + x])
+                .sum();
+        }
+    }
+
+    let mut coefficients = vec![0.0; HASH_SIZE * HASH_SIZE];
+    for v in 0..HASH_SIZE {
+        for u in 0..HASH_SIZE {
+            coefficients[v * HASH_SIZE + u] = (0..SAMPLE_SIZE)
+                .map(|y| rows[y * HASH_SIZE + u] * basis[v * SAMPLE_SIZE + y])
+                .sum();
+        }
+    }
+    coefficients
+}
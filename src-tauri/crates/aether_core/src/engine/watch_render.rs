@@ -0,0 +1,378 @@
+//! Watch-and-render: re-render a project's outputs whenever its project file changes
+//!
+//! A project file is loaded into a timeline by a `ProjectLoader`; the default runs it as a
+//! project script against an empty timeline. Each new version is diffed against the last
+//! one, and only the outputs overlapping a changed time range are rendered again, with
+//! the changed ranges attached so a renderer can redo just those segments.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context, Result};
+use tracing::{info, warn};
+
+use crate::engine::rendering::{ExportOptions, JobPriority, RenderQueue};
+use crate::engine::timeline::{Clip, Timeline, TimelineConfig};
+use crate::engine::timeline_diff::{diff_projects, TrackChange};
+use crate::modules::scripting::{ScriptContext, ScriptHost, ScriptLimits};
+
+/// How often the project file is checked for changes
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a project file must go unmodified before it is loaded, so an editor's
+/// partial writes aren't rendered
+pub const DEFAULT_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Loads a project file into a timeline
+pub type ProjectLoader = Arc<dyn Fn(&Path) -> Result<Timeline> + Send + Sync + 'static>;
+
+/// Renders one output of a watched project
+pub type RenderHandler = Arc<dyn Fn(&RenderRequest) -> Result<()> + Send + Sync + 'static>;
+
+/// A span of timeline time, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl TimeRange {
+    pub fn new(start: f64, end: f64) -> Self {
+        Self { start, end }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// The part of this range inside `other`, if any
+    pub fn intersect(&self, other: &TimeRange) -> Option<TimeRange> {
+        let range = TimeRange::new(self.start.max(other.start), self.end.min(other.end));
+        (range.end > range.start).then_some(range)
+    }
+
+    fn of_clip(clip: &Clip) -> Self {
+        Self::new(clip.start_time, clip.end_time())
+    }
+}
+
+/// An output rendered from a watched project
+#[derive(Debug, Clone)]
+pub struct WatchOutput {
+    pub name: String,
+    pub options: ExportOptions,
+    /// Part of the timeline the output covers; the whole timeline if `None`
+    pub range: Option<TimeRange>,
+}
+
+impl WatchOutput {
+    pub fn new(name: &str, options: ExportOptions) -> Self {
+        Self {
+            name: name.to_string(),
+            options,
+            range: None,
+        }
+    }
+
+    pub fn with_range(mut self, start: f64, end: f64) -> Self {
+        self.range = Some(TimeRange::new(start, end));
+        self
+    }
+
+    fn span(&self, duration: f64) -> TimeRange {
+        self.range.unwrap_or(TimeRange::new(0.0, duration))
+    }
+}
+
+/// An output that has to be rendered again
+#[derive(Debug, Clone)]
+pub struct RenderRequest {
+    pub output: String,
+    pub options: ExportOptions,
+    /// Changed parts of the output, sorted and not overlapping
+    pub ranges: Vec<TimeRange>,
+    /// Whether the whole output changed, e.g. on the first render
+    pub full: bool,
+}
+
+/// Time ranges whose rendered picture or sound may differ between two versions of a project
+///
+/// Every changed clip dirties both where it was and where it is now; a track being
+/// added, removed or muted dirties all of its clips. Markers, names, locks and track
+/// view settings don't affect rendering. A change of frame rate, size, sample rate or
+/// default conform or scale mode dirties everything.
+pub fn changed_ranges(old: &Timeline, new: &Timeline) -> Vec<TimeRange> {
+    let whole = TimeRange::new(0.0, old.duration().max(new.duration()));
+    if old.frame_rate() != new.frame_rate()
+        || old.resolution() != new.resolution()
+        || old.sample_rate() != new.sample_rate()
+        || old.frame_rate_conform() != new.frame_rate_conform()
+        || old.scale_mode() != new.scale_mode()
+    {
+        return vec![whole];
+    }
+
+    let diff = diff_projects(old, new);
+    let mut ranges = Vec::new();
+
+    for change in &diff.tracks {
+        let track_id = match change {
+            TrackChange::Added(track_id) | TrackChange::Removed(track_id) => track_id,
+            TrackChange::MuteChanged { track_id, .. } => track_id,
+            _ => continue,
+        };
+        for timeline in [old, new] {
+            if let Some(track) = timeline.tracks().get(track_id) {
+                ranges.extend(track.clips.iter().map(TimeRange::of_clip));
+            }
+        }
+    }
+
+    for (clip_id, _) in &diff.clips {
+        for timeline in [old, new] {
+            if let Some(clip) = find_clip(timeline, clip_id) {
+                ranges.push(TimeRange::of_clip(clip));
+            }
+        }
+    }
+
+    if let Some((old_duration, new_duration)) = diff.duration {
+        ranges.push(TimeRange::new(old_duration.min(new_duration), old_duration.max(new_duration)));
+    }
+
+    merge_ranges(ranges)
+}
+
+/// Outputs overlapping the changed ranges, with the part of each that changed
+pub fn plan_renders(outputs: &[WatchOutput], changed: &[TimeRange], duration: f64) -> Vec<RenderRequest> {
+    outputs.iter()
+        .filter_map(|output| {
+            let span = output.span(duration);
+            let ranges: Vec<TimeRange> = changed.iter().filter_map(|range| range.intersect(&span)).collect();
+            if ranges.is_empty() {
+                return None;
+            }
+            let full = ranges.len() == 1 && ranges[0] == span;
+            Some(RenderRequest {
+                output: output.name.clone(),
+                options: output.options.clone(),
+                ranges,
+                full,
+            })
+        })
+        .collect()
+}
+
+/// Loader running the project file as a project script against an empty timeline
+pub fn script_loader(limits: ScriptLimits) -> ProjectLoader {
+    Arc::new(move |path: &Path| {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read project {}", path.display()))?;
+        let timeline = Arc::new(Mutex::new(Timeline::new(TimelineConfig::default())));
+        ScriptHost::new(limits.clone()).run(&source, &ScriptContext::new(timeline.clone()))?;
+
+        let timeline = Arc::try_unwrap(timeline)
+            .map_err(|_| anyhow!("Project script kept its timeline"))?;
+        timeline.into_inner().map_err(|_| anyhow!("Project script panicked"))
+    })
+}
+
+/// Render handler queueing every request as a full export
+///
+/// For renderers without segment support; the changed ranges only decide which
+/// outputs are queued.
+pub fn queue_renders(queue: Arc<RenderQueue>, priority: JobPriority) -> RenderHandler {
+    Arc::new(move |request: &RenderRequest| {
+        let id = queue.enqueue(request.options.clone(), priority);
+        info!("Queued watch render of {} as job {}", request.output, id);
+        Ok(())
+    })
+}
+
+/// Modification time and size of a project file, to tell when it was written
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+/// A watched project and the last version of it that was loaded
+///
+/// `poll` does one check; `ProjectWatcher` polls on a thread.
+pub struct WatchSession {
+    path: PathBuf,
+    loader: ProjectLoader,
+    outputs: Vec<WatchOutput>,
+    settle_time: Duration,
+    timeline: Option<Timeline>,
+    stamp: Option<FileStamp>,
+}
+
+impl WatchSession {
+    pub fn new(path: &Path, loader: ProjectLoader, outputs: Vec<WatchOutput>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            loader,
+            outputs,
+            settle_time: DEFAULT_SETTLE_TIME,
+            timeline: None,
+            stamp: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn outputs(&self) -> &[WatchOutput] {
+        &self.outputs
+    }
+
+    pub fn set_settle_time(&mut self, settle_time: Duration) {
+        self.settle_time = settle_time;
+    }
+
+    /// The last version of the project that loaded
+    pub fn timeline(&self) -> Option<&Timeline> {
+        self.timeline.as_ref()
+    }
+
+    /// Load the project if its file changed since the last poll, returning what to render
+    ///
+    /// The first load renders every output in full. A version that fails to load is
+    /// reported once and skipped; the last good version stays the base for the next diff.
+    pub fn poll(&mut self) -> Result<Vec<RenderRequest>> {
+        let metadata = fs::metadata(&self.path)
+            .with_context(|| format!("Project {} not found", self.path.display()))?;
+        let stamp = FileStamp {
+            modified: metadata.modified()?,
+            len: metadata.len(),
+        };
+        if self.stamp == Some(stamp) {
+            return Ok(Vec::new());
+        }
+        let age = SystemTime::now().duration_since(stamp.modified).unwrap_or(Duration::MAX);
+        if age < self.settle_time {
+            return Ok(Vec::new());
+        }
+        self.stamp = Some(stamp);
+
+        let timeline = (self.loader)(&self.path)
+            .with_context(|| format!("Failed to load project {}", self.path.display()))?;
+        let requests = match &self.timeline {
+            Some(previous) => {
+                let changed = changed_ranges(previous, &timeline);
+                plan_renders(&self.outputs, &changed, timeline.duration())
+            },
+            None => {
+                let whole = [TimeRange::new(0.0, timeline.duration())];
+                plan_renders(&self.outputs, &whole, timeline.duration())
+            },
+        };
+
+        info!("Project {} changed, {} outputs to render", self.path.display(), requests.len());
+        self.timeline = Some(timeline);
+        Ok(requests)
+    }
+}
+
+/// Counters of a running watcher
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchStatus {
+    /// Renders that finished without error
+    pub renders: u64,
+    /// Last load or render error
+    pub last_error: Option<String>,
+}
+
+/// Polls a project on a thread and renders whatever its changes affect
+pub struct ProjectWatcher {
+    running: Arc<AtomicBool>,
+    status: Arc<Mutex<WatchStatus>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ProjectWatcher {
+    pub fn start(mut session: WatchSession, poll_interval: Duration, render: RenderHandler) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+
+        info!("Watching project {}", session.path().display());
+        let thread = {
+            let running = running.clone();
+            let status = status.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    match session.poll() {
+                        Ok(requests) => {
+                            for request in requests {
+                                let result = render(&request);
+                                let mut status = status.lock().unwrap();
+                                match result {
+                                    Ok(()) => status.renders += 1,
+                                    Err(e) => {
+                                        warn!("Watch render of {} failed: {}", request.output, e);
+                                        status.last_error = Some(e.to_string());
+                                    },
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            warn!("{:#}", e);
+                            status.lock().unwrap().last_error = Some(format!("{:#}", e));
+                        },
+                    }
+                    thread::sleep(poll_interval);
+                }
+            })
+        };
+
+        Self {
+            running,
+            status,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn status(&self) -> WatchStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Stop watching, after any render in progress
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ProjectWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn find_clip<'a>(timeline: &'a Timeline, clip_id: &str) -> Option<&'a Clip> {
+    timeline.tracks().values()
+        .flat_map(|track| track.clips.iter())
+        .find(|clip| clip.id == clip_id)
+}
+
+/// Sort ranges and join the ones that overlap or touch
+fn merge_ranges(mut ranges: Vec<TimeRange>) -> Vec<TimeRange> {
+    ranges.retain(|range| range.end > range.start);
+    ranges.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut merged: Vec<TimeRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
@@ -0,0 +1,391 @@
+//! gRPC control server for running the engine headless
+//!
+//! Built with the `grpc` feature, which needs `protoc` at build time. The service defined in
+//! `proto/aether.proto` drives a project manager, bulk importer and render queue. These can be
+//! the desktop app's own, so a remote client and the local UI work on the same projects and
+//! queue, or a headless server can create its own with `ControlService::headless`.
+//!
+//! Engine calls block, so requests that touch projects or media run on tokio's blocking pool.
+//!
+//! Every call must carry the server's token, and every path a client names is checked
+//! against the service's `PathPolicy`, so a client can only read and write below the
+//! directories it was given. The server listens on loopback unless told otherwise.
+
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use gstreamer as gst;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::engine::editing::{ClipInfo, EditingError, ProjectManager, Timeline, TrackType};
+use crate::engine::rendering::{ExportOptions, JobPriority, JobStatus, RenderJobInfo, RenderQueue, RenderQueueConfig};
+use crate::modules::file_manager::{FileManager, MediaType};
+use crate::modules::file_manager_import::{BulkImportOptions, BulkImporter, ImportedFile};
+use crate::modules::path_policy::{self, PathPolicy};
+
+/// Address the server listens on unless told otherwise; loopback only
+pub const DEFAULT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 50051);
+
+/// Metadata key clients send the token in, as `Bearer <token>`
+pub const AUTH_METADATA: &str = "authorization";
+
+/// Messages and service traits generated from `proto/aether.proto`
+pub mod proto {
+    tonic::include_proto!("aether.v1");
+}
+
+use proto::control_server::{Control, ControlServer};
+use proto::edit_timeline_request::Edit;
+
+/// Engines shared between the gRPC service and the rest of the app
+#[derive(Clone)]
+pub struct ControlService {
+    projects: Arc<Mutex<ProjectManager>>,
+    importer: Arc<BulkImporter>,
+    render_queue: Arc<RenderQueue>,
+    /// Where clients may read and write
+    path_policy: Arc<PathPolicy>,
+}
+
+impl ControlService {
+    /// Serve existing engines, typically the desktop app's, to clients limited to the
+    /// roots of `path_policy`
+    pub fn new(
+        projects: Arc<Mutex<ProjectManager>>,
+        importer: Arc<BulkImporter>,
+        render_queue: Arc<RenderQueue>,
+        path_policy: PathPolicy,
+    ) -> Self {
+        Self {
+            projects,
+            importer,
+            render_queue,
+            path_policy: Arc::new(path_policy),
+        }
+    }
+
+    /// Serve engines of its own, for a server without a desktop app
+    pub fn headless(queue_config: RenderQueueConfig, path_policy: PathPolicy) -> Result<Self, EditingError> {
+        let file_manager = FileManager::new().map_err(|e| EditingError::ImportError(e.to_string()))?;
+
+        Ok(Self::new(
+            Arc::new(Mutex::new(ProjectManager::new())),
+            Arc::new(BulkImporter::new(file_manager, BulkImportOptions::default())),
+            Arc::new(RenderQueue::new(queue_config)),
+            path_policy,
+        ))
+    }
+
+    /// An existing file or directory a client named, in canonical form
+    fn input_path(&self, path: &Path) -> Result<PathBuf, Status> {
+        let checked = if path.is_dir() {
+            self.path_policy.validate_input_dir(path)
+        } else {
+            self.path_policy.validate_input(path)
+        };
+        checked.map_err(|e| Status::permission_denied(e.to_string()))
+    }
+
+    /// A file a client wants written, in canonical form
+    fn output_path(&self, path: &Path) -> Result<PathBuf, Status> {
+        self.path_policy.validate_output(path).map_err(|e| Status::permission_denied(e.to_string()))
+    }
+
+    /// A clip source given as a path or `file://` URI, as a URI of a checked file
+    fn clip_uri(&self, uri: &str) -> Result<String, Status> {
+        let path = if uri.starts_with("file:") {
+            gst::glib::filename_from_uri(uri)
+                .map_err(|e| Status::invalid_argument(format!("Invalid URI {}: {}", uri, e)))?
+                .0
+        } else if uri.contains("://") {
+            return Err(Status::permission_denied(format!("Only local files can be added: {}", uri)));
+        } else {
+            PathBuf::from(uri)
+        };
+        let path = self.path_policy.validate_input(&path).map_err(|e| Status::permission_denied(e.to_string()))?;
+        path_policy::path_to_uri(&path).map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+
+    fn timeline(&self, project_id: &str) -> Result<Arc<Mutex<Timeline>>, Status> {
+        let engine = self.projects.lock().unwrap().get_project(project_id).map_err(to_status)?;
+        let timeline = engine.lock().unwrap().timeline();
+        Ok(timeline)
+    }
+
+    fn export_job(&self, job_id: u64) -> Result<proto::ExportJob, Status> {
+        self.render_queue.job(job_id)
+            .map(export_job_message)
+            .ok_or_else(|| Status::not_found(format!("Render job not found: {}", job_id)))
+    }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn import_media(&self, request: Request<proto::ImportMediaRequest>) -> Result<Response<proto::ImportMediaResponse>, Status> {
+        let importer = self.importer.clone();
+        let paths = request.into_inner().paths.iter()
+            .map(|path| self.input_path(Path::new(path)))
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        blocking(move || {
+            let job = importer.import(&paths).map_err(|e| Status::invalid_argument(e.to_string()))?;
+            let total = job.file_statuses().len();
+            let files = job.wait().map_err(|e| Status::internal(e.to_string()))?;
+
+            Ok(proto::ImportMediaResponse {
+                failed: (total - files.len()) as u32,
+                files: files.into_iter().map(imported_media_message).collect(),
+            })
+        }).await
+    }
+
+    async fn open_project(&self, request: Request<proto::OpenProjectRequest>) -> Result<Response<proto::OpenProjectResponse>, Status> {
+        let projects = self.projects.clone();
+        let path = match request.into_inner().path {
+            Some(path) => Some(self.input_path(Path::new(&path))?.to_string_lossy().into_owned()),
+            None => None,
+        };
+
+        blocking(move || {
+            let project_id = projects.lock().unwrap().open_project(path).map_err(to_status)?;
+            info!("Opened project {} for a remote client", project_id);
+            Ok(proto::OpenProjectResponse { project_id })
+        }).await
+    }
+
+    async fn close_project(&self, request: Request<proto::ProjectRef>) -> Result<Response<proto::CloseProjectResponse>, Status> {
+        let projects = self.projects.clone();
+        let project_id = request.into_inner().project_id;
+
+        blocking(move || {
+            projects.lock().unwrap().close_project(&project_id).map_err(to_status)?;
+            Ok(proto::CloseProjectResponse {})
+        }).await
+    }
+
+    async fn get_timeline(&self, request: Request<proto::ProjectRef>) -> Result<Response<proto::TimelineState>, Status> {
+        let service = self.clone();
+        let project_id = request.into_inner().project_id;
+
+        blocking(move || {
+            let timeline = service.timeline(&project_id)?;
+            let timeline = timeline.lock().unwrap();
+            Ok(timeline_state(&project_id, &timeline))
+        }).await
+    }
+
+    async fn edit_timeline(&self, request: Request<proto::EditTimelineRequest>) -> Result<Response<proto::TimelineState>, Status> {
+        let service = self.clone();
+        let request = request.into_inner();
+        let edit = request.edit.ok_or_else(|| Status::invalid_argument("No edit given"))?;
+
+        blocking(move || {
+            let timeline = service.timeline(&request.project_id)?;
+            let mut timeline = timeline.lock().unwrap();
+
+            match edit {
+                Edit::AddClip(add) => {
+                    let uri = service.clip_uri(&add.uri)?;
+                    let track_type = match add.track_type() {
+                        proto::TrackType::Video => TrackType::Video,
+                        proto::TrackType::Audio => TrackType::Audio,
+                    };
+                    timeline.add_clip(&uri, track_type, add.start, add.duration, add.in_point).map(drop)
+                },
+                Edit::MoveClip(edit) => timeline.move_clip(&edit.clip_id, edit.start),
+                Edit::TrimClip(edit) => timeline.trim_clip(&edit.clip_id, edit.duration),
+                Edit::SplitClip(edit) => timeline.split_clip(&edit.clip_id, edit.position).map(drop),
+                Edit::RemoveClip(edit) => timeline.remove_clip(&edit.clip_id),
+                Edit::AddEffect(edit) => timeline.add_effect(&edit.clip_id, &edit.effect).map(drop),
+            }.map_err(to_status)?;
+
+            Ok(timeline_state(&request.project_id, &timeline))
+        }).await
+    }
+
+    async fn submit_export(&self, request: Request<proto::SubmitExportRequest>) -> Result<Response<proto::ExportJob>, Status> {
+        let request = request.into_inner();
+        let mut options: ExportOptions = if request.options_json.trim().is_empty() {
+            ExportOptions::default()
+        } else {
+            serde_json::from_str(&request.options_json)
+                .map_err(|e| Status::invalid_argument(format!("Invalid export options: {}", e)))?
+        };
+        options.input_path = self.input_path(&options.input_path)?;
+        options.output_path = self.output_path(&options.output_path)?;
+        let priority = match request.priority() {
+            proto::Priority::Background => JobPriority::Background,
+            proto::Priority::Normal => JobPriority::Normal,
+            proto::Priority::High => JobPriority::High,
+        };
+
+        let job_id = self.render_queue.enqueue(options, priority);
+        info!("Queued export {} for a remote client", job_id);
+        self.export_job(job_id).map(Response::new)
+    }
+
+    async fn get_export(&self, request: Request<proto::ExportJobRef>) -> Result<Response<proto::ExportJob>, Status> {
+        self.export_job(request.into_inner().job_id).map(Response::new)
+    }
+
+    async fn cancel_export(&self, request: Request<proto::ExportJobRef>) -> Result<Response<proto::ExportJob>, Status> {
+        let job_id = request.into_inner().job_id;
+        self.render_queue.cancel(job_id)
+            .map_err(|_| Status::not_found(format!("Render job not found: {}", job_id)))?;
+        self.export_job(job_id).map(Response::new)
+    }
+}
+
+/// Refuses calls without the server's token
+#[derive(Clone)]
+pub struct TokenCheck {
+    expected: Arc<[u8]>,
+}
+
+impl TokenCheck {
+    /// Check for `token`, which must not be empty
+    pub fn new(token: &str) -> anyhow::Result<Self> {
+        if token.trim().is_empty() {
+            return Err(anyhow::anyhow!("The gRPC control server needs a token"));
+        }
+        Ok(Self { expected: format!("Bearer {}", token).into_bytes().into() })
+    }
+}
+
+impl Interceptor for TokenCheck {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let given = request.metadata().get(AUTH_METADATA).map(MetadataValue::as_bytes);
+        match given {
+            Some(given) if constant_time_eq(given, &self.expected) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("Invalid token")),
+            None => Err(Status::unauthenticated("No token given")),
+        }
+    }
+}
+
+/// Compare without returning early, so timing doesn't give the token away
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Serve `service` on `addr` to clients holding `token` until `shutdown` completes
+pub async fn serve(addr: SocketAddr, service: ControlService, token: &str, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+    let check = TokenCheck::new(token)?;
+    if !addr.ip().is_loopback() {
+        warn!("gRPC control server listening on {}, reachable from other machines", addr);
+    }
+    info!("gRPC control server listening on {}", addr);
+
+    tonic::transport::Server::builder()
+        .add_service(ControlServer::with_interceptor(service, check))
+        .serve_with_shutdown(addr, shutdown)
+        .await?;
+    Ok(())
+}
+
+/// Run a headless server with its own engines until interrupted
+///
+/// Clients may only use files below the roots of `path_policy`. Starts a tokio runtime,
+/// so call it from a plain thread such as `main`.
+pub fn run_headless(addr: SocketAddr, queue_config: RenderQueueConfig, path_policy: PathPolicy, token: &str) -> anyhow::Result<()> {
+    let service = ControlService::headless(queue_config, path_policy)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    runtime.block_on(serve(addr, service, token, async {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Shutting down gRPC control server");
+    }))?;
+
+    Ok(())
+}
+
+/// Run blocking engine work off the async executor
+async fn blocking<T, F>(work: F) -> Result<Response<T>, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Status::internal(format!("Request failed: {}", e)))?
+        .map(Response::new)
+}
+
+fn to_status(error: EditingError) -> Status {
+    match error {
+        EditingError::InvalidParameter(message) => Status::invalid_argument(message),
+        EditingError::NotSupported(message) => Status::unimplemented(message),
+        EditingError::NotInitialized => Status::failed_precondition(error.to_string()),
+        error => Status::internal(error.to_string()),
+    }
+}
+
+fn timeline_state(project_id: &str, timeline: &Timeline) -> proto::TimelineState {
+    let mut clips = timeline.get_clips();
+    clips.sort_by(|a, b| a.start_time.cmp(&b.start_time).then_with(|| a.id.cmp(&b.id)));
+
+    proto::TimelineState {
+        project_id: project_id.to_string(),
+        duration: timeline.get_duration(),
+        clips: clips.into_iter().map(clip_message).collect(),
+    }
+}
+
+fn clip_message(clip: ClipInfo) -> proto::Clip {
+    let track_type = match clip.track_type {
+        TrackType::Video => proto::TrackType::Video,
+        TrackType::Audio => proto::TrackType::Audio,
+    };
+
+    proto::Clip {
+        id: clip.id,
+        name: clip.name,
+        track_type: track_type as i32,
+        start: clip.start_time,
+        duration: clip.duration,
+        in_point: clip.in_point,
+        effects: clip.effects.into_iter()
+            .map(|effect| proto::Effect { id: effect.id, effect_type: effect.effect_type })
+            .collect(),
+    }
+}
+
+fn imported_media_message(file: ImportedFile) -> proto::ImportedMedia {
+    let media_type = match file.info.media_type {
+        MediaType::Video => proto::MediaType::Video,
+        MediaType::Audio => proto::MediaType::Audio,
+        MediaType::Image => proto::MediaType::Image,
+        MediaType::Unknown => proto::MediaType::Unknown,
+    };
+
+    proto::ImportedMedia {
+        path: file.path.to_string_lossy().into_owned(),
+        media_type: media_type as i32,
+        duration: file.info.duration,
+        width: file.info.width,
+        height: file.info.height,
+        thumbnail: file.thumbnail.map(|path| path.to_string_lossy().into_owned()),
+    }
+}
+
+fn export_job_message(job: RenderJobInfo) -> proto::ExportJob {
+    let (status, error) = match job.status {
+        JobStatus::Queued => (proto::ExportStatus::Queued, None),
+        JobStatus::Running => (proto::ExportStatus::Running, None),
+        JobStatus::Completed => (proto::ExportStatus::Completed, None),
+        JobStatus::Failed(error) => (proto::ExportStatus::Failed, Some(error)),
+        JobStatus::Cancelled => (proto::ExportStatus::Cancelled, None),
+    };
+
+    proto::ExportJob {
+        job_id: job.id,
+        status: status as i32,
+        percent: job.percent,
+        error,
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use super::super::grpc::proto::control_server::Control;
+    use super::super::grpc::{proto, serve, ControlService, TokenCheck, AUTH_METADATA, DEFAULT_ADDR};
+    use crate::engine::rendering::RenderQueueConfig;
+    use crate::modules::path_policy::PathPolicy;
+    use crate::modules::test_utils::create_test_dir;
+    use std::fs;
+    use std::path::PathBuf;
+    use anyhow::Result;
+    use tonic::service::Interceptor;
+    use tonic::{Code, Request};
+
+    /// A project root the service may use, and a directory next to it that it may not
+    fn sandbox(name: &str) -> Result<(PathBuf, PathBuf)> {
+        let dir = create_test_dir("grpc", name)?;
+        let (root, outside) = (dir.join("project"), dir.join("outside"));
+        fs::create_dir_all(&root)?;
+        fs::create_dir_all(&outside)?;
+        fs::write(root.join("clip.mov"), b"clip")?;
+        fs::write(outside.join("secret.mov"), b"secret")?;
+        Ok((root, outside))
+    }
+
+    fn service(root: &PathBuf) -> Result<ControlService> {
+        Ok(ControlService::headless(RenderQueueConfig::default(), PathPolicy::with_roots(&[root.clone()])?)?)
+    }
+
+    fn export_request(input: &PathBuf, output: &PathBuf) -> Request<proto::SubmitExportRequest> {
+        let options = serde_json::json!({ "input_path": input, "output_path": output });
+        Request::new(proto::SubmitExportRequest {
+            options_json: options.to_string(),
+            priority: proto::Priority::Normal as i32,
+        })
+    }
+
+    fn with_token(token: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(token) = token {
+            request.metadata_mut().insert(AUTH_METADATA, token.parse().unwrap());
+        }
+        request
+    }
+
+    #[test]
+    fn test_export_paths_outside_root_are_refused() -> Result<()> {
+        let (root, outside) = sandbox("export")?;
+        let service = service(&root)?;
+        let runtime = tokio::runtime::Runtime::new()?;
+
+        let cases = [
+            (outside.join("secret.mov"), root.join("out.mp4")),
+            (root.join("clip.mov"), outside.join("out.mp4")),
+            (root.join("clip.mov"), root.join("../outside/out.mp4")),
+            (root.join("clip.mov"), root.join("new/../../outside/out.mp4")),
+        ];
+        for (input, output) in &cases {
+            let status = runtime.block_on(service.submit_export(export_request(input, output))).unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied, "{:?} -> {:?}", input, output);
+        }
+
+        // Nothing was written outside the root on the way
+        assert!(!outside.join("out.mp4").exists());
+        assert!(!root.join("new").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_outside_root_is_refused() -> Result<()> {
+        let (root, outside) = sandbox("import")?;
+        let service = service(&root)?;
+        let runtime = tokio::runtime::Runtime::new()?;
+
+        for path in [outside.join("secret.mov"), outside.clone(), root.join("../outside"), PathBuf::from("/etc/passwd")] {
+            let request = Request::new(proto::ImportMediaRequest {
+                paths: vec![root.join("clip.mov").to_string_lossy().into_owned(), path.to_string_lossy().into_owned()],
+            });
+            let status = runtime.block_on(service.import_media(request)).unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied, "{:?}", path);
+        }
+
+        let request = Request::new(proto::OpenProjectRequest { path: Some(outside.join("secret.mov").to_string_lossy().into_owned()) });
+        let status = runtime.block_on(service.open_project(request)).unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        Ok(())
+    }
+
+    #[test]
+    fn test_calls_without_token_are_refused() -> Result<()> {
+        let mut check = TokenCheck::new("s3cret")?;
+
+        assert_eq!(check.call(with_token(None)).unwrap_err().code(), Code::Unauthenticated);
+        assert_eq!(check.call(with_token(Some("Bearer wrong"))).unwrap_err().code(), Code::Unauthenticated);
+        assert_eq!(check.call(with_token(Some("s3cret"))).unwrap_err().code(), Code::Unauthenticated);
+        assert_eq!(check.call(with_token(Some("Bearer s3cret!"))).unwrap_err().code(), Code::Unauthenticated);
+        assert!(check.call(with_token(Some("Bearer s3cret"))).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_needs_a_token() -> Result<()> {
+        assert!(DEFAULT_ADDR.ip().is_loopback());
+        assert!(TokenCheck::new("").is_err());
+        assert!(TokenCheck::new("  ").is_err());
+
+        let (root, _) = sandbox("token")?;
+        let runtime = tokio::runtime::Runtime::new()?;
+        let result = runtime.block_on(serve(DEFAULT_ADDR, service(&root)?, "", async {}));
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
 pub mod engine;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(all(test, feature = "grpc"))]
+mod grpc_tests;
 pub mod modules;
 
 pub use engine::VideoFormat;
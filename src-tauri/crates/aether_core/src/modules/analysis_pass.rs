@@ -0,0 +1,681 @@
+//! Single-pass media analysis
+//!
+//! Waveforms, loudness, scene cuts and beats all need the same decoded audio and frames.
+//! An `AnalysisPass` decodes a file once and hands every chunk of samples and every frame
+//! to all registered analyzers, instead of each analysis running its own decode.
+//!
+//! Audio reaches analyzers as mono float samples at the pass's sample rate, or as left and
+//! right channels for analyzers that ask for stereo, video as small packed RGB frames.
+//! Analyzers keep their own results and are read after the pass.
+
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, info};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::highlight_detection::histogram_distance;
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
+/// Rate audio is decoded at unless set otherwise
+pub const DEFAULT_SAMPLE_RATE: u32 = 22050;
+
+/// Size frames are scaled to unless set otherwise
+pub const DEFAULT_FRAME_SIZE: (u32, u32) = (64, 36);
+
+/// Decoded chunks buffered ahead of the analyzers
+const CHANNEL_CAPACITY: usize = 32;
+
+/// Longest the decoder may go without producing anything
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A decoded frame, scaled down for analysis
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisFrame {
+    /// Source time in seconds
+    pub timestamp: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Packed RGB24 pixels
+    pub rgb: Vec<u8>,
+}
+
+impl AnalysisFrame {
+    /// Luma of every pixel
+    pub fn luma(&self) -> Vec<u8> {
+        self.rgb
+            .chunks_exact(3)
+            .map(|p| ((p[0] as u32 * 77 + p[1] as u32 * 150 + p[2] as u32 * 29) >> 8) as u8)
+            .collect()
+    }
+}
+
+/// Something measured from a file's decoded audio and frames
+pub trait Analyzer {
+    /// Whether the pass should decode audio for this analyzer
+    fn wants_audio(&self) -> bool {
+        false
+    }
+
+    /// Whether the pass should decode video for this analyzer
+    fn wants_video(&self) -> bool {
+        false
+    }
+
+    /// Whether the pass should decode audio as stereo and hand it to `stereo`
+    fn wants_stereo(&self) -> bool {
+        false
+    }
+
+    /// The next mono samples, in decoding order
+    fn audio(&mut self, _samples: &[f32], _sample_rate: u32) {}
+
+    /// The next left and right samples, in decoding order; mono audio comes as the same
+    /// slice for both channels, so analyzers can tell it from stereo with `std::ptr::eq`
+    fn stereo(&mut self, _left: &[f32], _right: &[f32], _sample_rate: u32) {}
+
+    /// The next frame, in decoding order
+    fn video(&mut self, _frame: &AnalysisFrame) {}
+
+    /// Called once after the last samples and frames
+    fn finish(&mut self) {}
+}
+
+/// Decodes a file once for any number of analyzers
+pub struct AnalysisPass<'a> {
+    sample_rate: u32,
+    frame_size: (u32, u32),
+    analyzers: Vec<&'a mut dyn Analyzer>,
+}
+
+impl Default for AnalysisPass<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> AnalysisPass<'a> {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            frame_size: DEFAULT_FRAME_SIZE,
+            analyzers: Vec::new(),
+        }
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate.max(1);
+        self
+    }
+
+    pub fn with_frame_size(mut self, width: u32, height: u32) -> Self {
+        self.frame_size = (width.max(1), height.max(1));
+        self
+    }
+
+    /// Register an analyzer; its results are read from it after the pass
+    pub fn with_analyzer(mut self, analyzer: &'a mut dyn Analyzer) -> Self {
+        self.analyzers.push(analyzer);
+        self
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Hand mono samples to every analyzer that wants audio
+    pub fn push_audio(&mut self, samples: &[f32]) {
+        for analyzer in &mut self.analyzers {
+            if analyzer.wants_audio() {
+                analyzer.audio(samples, self.sample_rate);
+            }
+            if analyzer.wants_stereo() {
+                analyzer.stereo(samples, samples, self.sample_rate);
+            }
+        }
+    }
+
+    /// Hand stereo samples to every analyzer that wants them, and their mix to every
+    /// analyzer that wants mono audio
+    pub fn push_stereo(&mut self, left: &[f32], right: &[f32]) {
+        let count = left.len().min(right.len());
+        let (left, right) = (&left[..count], &right[..count]);
+        let mut mix = Vec::new();
+        for analyzer in &mut self.analyzers {
+            if analyzer.wants_audio() {
+                if mix.is_empty() {
+                    mix = left.iter().zip(right).map(|(l, r)| (l + r) / 2.0).collect();
+                }
+                analyzer.audio(&mix, self.sample_rate);
+            }
+            if analyzer.wants_stereo() {
+                analyzer.stereo(left, right, self.sample_rate);
+            }
+        }
+    }
+
+    /// Hand a frame to every analyzer that wants video
+    pub fn push_frame(&mut self, frame: &AnalysisFrame) {
+        for analyzer in self.analyzers.iter_mut().filter(|analyzer| analyzer.wants_video()) {
+            analyzer.video(frame);
+        }
+    }
+
+    /// Tell every analyzer the media has ended
+    pub fn finish(mut self) {
+        for analyzer in &mut self.analyzers {
+            analyzer.finish();
+        }
+    }
+
+    /// Decode `path` once, feeding all analyzers, then finish them
+    ///
+    /// Only the streams some analyzer wants are decoded. A wanted stream missing from the
+    /// file is not an error; its analyzers just see no data.
+    #[tracing::instrument(name = "analysis", skip(self))]
+    pub fn run(mut self, path: &Path) -> Result<()> {
+        let stereo = self.analyzers.iter().any(|analyzer| analyzer.wants_stereo());
+        let audio = stereo || self.analyzers.iter().any(|analyzer| analyzer.wants_audio());
+        let video = self.analyzers.iter().any(|analyzer| analyzer.wants_video());
+        if audio || video {
+            self.decode(path, audio, stereo, video)?;
+        }
+        info!("Analyzed {:?} with {} analyzers", path, self.analyzers.len());
+        self.finish();
+        Ok(())
+    }
+
+    fn decode(&mut self, path: &Path, audio: bool, stereo: bool, video: bool) -> Result<()> {
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+
+        let builder = PipelineBuilder::new("analysis")?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        {
+            let sender = sender.clone();
+            source[1].connect_no_more_pads(move |_| {
+                let _ = sender.send(Decoded::StreamsKnown);
+            });
+        }
+
+        // First element of each branch, to tell which ones the file linked
+        let mut branches = Vec::new();
+        if audio {
+            let elements = builder.chain(&[
+                ElementSpec::new("queue"),
+                ElementSpec::new("audioconvert"),
+                ElementSpec::new("audioresample"),
+                ElementSpec::capsfilter(
+                    gst::Caps::builder("audio/x-raw")
+                        .field("format", "F32LE")
+                        .field("layout", "interleaved")
+                        // Stereo analyzers get mono sources as they are, not upmixed
+                        .field("channels", if stereo { gst::IntRange::new(1i32, 2i32).to_send_value() } else { 1i32.to_send_value() })
+                        .field("rate", self.sample_rate as i32)
+                        .build(),
+                ),
+                // Not waiting to preroll, as the file may have no stream for this branch
+                ElementSpec::new("appsink").property("sync", false).property("async", false),
+            ])?;
+            builder.link_dynamic(&source[1], StreamKind::Audio, &elements[0]);
+            forward_samples(&elements[4], StreamKind::Audio, sender.clone(), move |sample| {
+                let channels = sample.caps()?.structure(0)?.get::<i32>("channels").ok()?;
+                let buffer = sample.buffer()?;
+                let map = buffer.map_readable().ok()?;
+                let samples: Vec<f32> = map.as_slice()
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect();
+                if channels == 2 {
+                    let (left, right) = samples.chunks_exact(2).map(|frame| (frame[0], frame[1])).unzip();
+                    Some(Decoded::Stereo(left, right))
+                } else {
+                    Some(Decoded::Audio(samples))
+                }
+            })?;
+            branches.push((StreamKind::Audio, elements[0].clone()));
+        }
+        if video {
+            let (width, height) = self.frame_size;
+            let elements = builder.chain(&[
+                ElementSpec::new("queue"),
+                ElementSpec::new("videoconvert"),
+                ElementSpec::new("videoscale"),
+                ElementSpec::capsfilter(
+                    gst::Caps::builder("video/x-raw")
+                        .field("format", "RGB")
+                        .field("width", width as i32)
+                        .field("height", height as i32)
+                        .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+                        .build(),
+                ),
+                ElementSpec::new("appsink").property("sync", false).property("async", false),
+            ])?;
+            builder.link_dynamic(&source[1], StreamKind::Video, &elements[0]);
+            forward_samples(&elements[4], StreamKind::Video, sender.clone(), move |sample| {
+                let buffer = sample.buffer()?;
+                let map = buffer.map_readable().ok()?;
+                Some(Decoded::Frame(AnalysisFrame {
+                    timestamp: buffer.pts().map(|pts| pts.seconds_f64()).unwrap_or(0.0),
+                    width,
+                    height,
+                    rgb: packed_rgb(map.as_slice(), width as usize, height as usize)?,
+                }))
+            })?;
+            branches.push((StreamKind::Video, elements[0].clone()));
+        }
+        drop(sender);
+
+        let pipeline = builder.build();
+        let bus = pipeline.bus().ok_or_else(|| anyhow!("Pipeline without bus"))?;
+        pipeline.set_state(gst::State::Playing)?;
+
+        let result = self.dispatch(&receiver, &bus, &branches);
+
+        // Unblocks streaming threads waiting on a full channel, so the pipeline can stop
+        drop(receiver);
+        pipeline.set_state(gst::State::Null)?;
+        result.map_err(|e| anyhow!("Failed to analyze {:?}: {}", path, e))
+    }
+
+    /// Feed decoded chunks to the analyzers until every linked branch has ended
+    fn dispatch(&mut self, receiver: &mpsc::Receiver<Decoded>, bus: &gst::Bus, branches: &[(StreamKind, gst::Element)]) -> Result<()> {
+        let mut streams_known = false;
+        let mut ended = Vec::new();
+        let mut last_activity = Instant::now();
+
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(decoded) => {
+                    last_activity = Instant::now();
+                    match decoded {
+                        Decoded::Audio(samples) => self.push_audio(&samples),
+                        Decoded::Stereo(left, right) => self.push_stereo(&left, &right),
+                        Decoded::Frame(frame) => self.push_frame(&frame),
+                        Decoded::Eos(kind) => ended.push(kind),
+                        Decoded::StreamsKnown => streams_known = true,
+                    }
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            if let Some(message) = bus.pop_filtered(&[gst::MessageType::Error]) {
+                if let gst::MessageView::Error(err) = message.view() {
+                    return Err(anyhow!("{}", err.error()));
+                }
+            }
+
+            let linked = |element: &gst::Element| element.static_pad("sink").is_some_and(|pad| pad.is_linked());
+            if streams_known && branches.iter().all(|(kind, first)| !linked(first) || ended.contains(kind)) {
+                debug!("Analysis pass decoded {:?}", ended);
+                return Ok(());
+            }
+            if last_activity.elapsed() > STALL_TIMEOUT {
+                return Err(anyhow!("Decoding stalled for {:?}", STALL_TIMEOUT));
+            }
+        }
+    }
+}
+
+/// What the streaming threads hand to the analyzing thread
+enum Decoded {
+    Audio(Vec<f32>),
+    /// Left and right channels
+    Stereo(Vec<f32>, Vec<f32>),
+    Frame(AnalysisFrame),
+    Eos(StreamKind),
+    /// The decoder exposed all its streams, so unlinked branches will stay empty
+    StreamsKnown,
+}
+
+/// Send every sample an appsink receives, converted by `convert`, and its end of stream
+fn forward_samples<F>(sink: &gst::Element, kind: StreamKind, sender: mpsc::SyncSender<Decoded>, convert: F) -> Result<()>
+where
+    F: Fn(&gst::Sample) -> Option<Decoded> + Send + Sync + 'static,
+{
+    let sink = sink
+        .clone()
+        .dynamic_cast::<gst_app::AppSink>()
+        .map_err(|_| anyhow!("Failed to cast to AppSink"))?;
+    let eos_sender = sender.clone();
+    sink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                if let Some(decoded) = convert(&sample) {
+                    // The pass stopped listening; stop decoding
+                    sender.send(decoded).map_err(|_| gst::FlowError::Eos)?;
+                }
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .eos(move |_| {
+                let _ = eos_sender.send(Decoded::Eos(kind));
+            })
+            .build(),
+    );
+    Ok(())
+}
+
+/// Copy RGB rows without GStreamer's padding to four-byte strides
+fn packed_rgb(data: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    let row = width * 3;
+    let stride = row.next_multiple_of(4);
+    if data.len() < stride * (height - 1) + row {
+        return None;
+    }
+    let mut rgb = Vec::with_capacity(row * height);
+    for y in 0..height {
+        rgb.extend_from_slice(&data[y * stride..y * stride + row]);
+    }
+    Some(rgb)
+}
+
+/// Highest absolute sample of every block of samples
+#[derive(Debug, Clone)]
+pub struct WaveformAnalyzer {
+    samples_per_peak: usize,
+    filled: usize,
+    peaks: Vec<f32>,
+}
+
+impl WaveformAnalyzer {
+    pub fn new(samples_per_peak: usize) -> Self {
+        Self {
+            samples_per_peak: samples_per_peak.max(1),
+            filled: 0,
+            peaks: Vec::new(),
+        }
+    }
+
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
+    }
+}
+
+impl Analyzer for WaveformAnalyzer {
+    fn wants_audio(&self) -> bool {
+        true
+    }
+
+    fn audio(&mut self, samples: &[f32], _sample_rate: u32) {
+        for &sample in samples {
+            if self.filled == 0 {
+                self.peaks.push(0.0);
+            }
+            let peak = self.peaks.last_mut().unwrap();
+            *peak = peak.max(sample.abs());
+            self.filled = (self.filled + 1) % self.samples_per_peak;
+        }
+    }
+}
+
+/// Quietest level reported, in dBFS, standing in for silence
+pub const SILENCE_DB: f64 = -120.0;
+
+/// RMS level over fixed windows and the whole file, in dBFS
+///
+/// A plain RMS level, not loudness-weighted like EBU R128.
+#[derive(Debug, Clone)]
+pub struct LoudnessAnalyzer {
+    window_duration: f64,
+    window_len: usize,
+    window_squares: f64,
+    window_count: usize,
+    total_squares: f64,
+    total_count: usize,
+    peak: f32,
+    levels: Vec<f64>,
+}
+
+impl LoudnessAnalyzer {
+    /// Measure levels over windows of `window_duration` seconds
+    pub fn new(window_duration: f64) -> Self {
+        Self {
+            window_duration: window_duration.max(0.001),
+            window_len: 0,
+            window_squares: 0.0,
+            window_count: 0,
+            total_squares: 0.0,
+            total_count: 0,
+            peak: 0.0,
+            levels: Vec::new(),
+        }
+    }
+
+    pub fn window_duration(&self) -> f64 {
+        self.window_duration
+    }
+
+    /// Level of every window, including a trailing partial one
+    pub fn levels(&self) -> &[f64] {
+        &self.levels
+    }
+
+    /// Level of the whole file
+    pub fn overall(&self) -> f64 {
+        to_db(self.total_squares, self.total_count)
+    }
+
+    /// Highest absolute sample, in dBFS
+    pub fn peak(&self) -> f64 {
+        if self.peak > 0.0 {
+            (20.0 * (self.peak as f64).log10()).max(SILENCE_DB)
+        } else {
+            SILENCE_DB
+        }
+    }
+
+    fn finish_window(&mut self) {
+        if self.window_count > 0 {
+            self.levels.push(to_db(self.window_squares, self.window_count));
+        }
+        self.window_squares = 0.0;
+        self.window_count = 0;
+    }
+}
+
+impl Analyzer for LoudnessAnalyzer {
+    fn wants_audio(&self) -> bool {
+        true
+    }
+
+    fn audio(&mut self, samples: &[f32], sample_rate: u32) {
+        if self.window_len == 0 {
+            self.window_len = ((self.window_duration * sample_rate as f64) as usize).max(1);
+        }
+        for &sample in samples {
+            let square = (sample as f64).powi(2);
+            self.window_squares += square;
+            self.window_count += 1;
+            self.total_squares += square;
+            self.total_count += 1;
+            self.peak = self.peak.max(sample.abs());
+            if self.window_count == self.window_len {
+                self.finish_window();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finish_window();
+    }
+}
+
+fn to_db(sum_squares: f64, count: usize) -> f64 {
+    if count == 0 || sum_squares <= 0.0 {
+        return SILENCE_DB;
+    }
+    (10.0 * (sum_squares / count as f64).log10()).max(SILENCE_DB)
+}
+
+/// Cuts between shots, found from jumps in the luma histogram between frames
+#[derive(Debug, Clone)]
+pub struct SceneDetector {
+    threshold: f64,
+    min_scene: f64,
+    previous: Option<Vec<u8>>,
+    cuts: Vec<f64>,
+}
+
+impl SceneDetector {
+    /// Cut where the histogram distance (0-1) exceeds `threshold`, at most once per
+    /// `min_scene` seconds, so flashes don't count as several cuts
+    pub fn new(threshold: f64, min_scene: f64) -> Self {
+        Self {
+            threshold,
+            min_scene,
+            previous: None,
+            cuts: Vec::new(),
+        }
+    }
+
+    /// Times of the first frame of each new shot, in seconds
+    pub fn cuts(&self) -> &[f64] {
+        &self.cuts
+    }
+}
+
+impl Default for SceneDetector {
+    fn default() -> Self {
+        Self::new(0.35, 0.5)
+    }
+}
+
+impl Analyzer for SceneDetector {
+    fn wants_video(&self) -> bool {
+        true
+    }
+
+    fn video(&mut self, frame: &AnalysisFrame) {
+        let luma = frame.luma();
+        if let Some(previous) = &self.previous {
+            let since_cut = self.cuts.last().map_or(f64::INFINITY, |cut| frame.timestamp - cut);
+            if since_cut >= self.min_scene && histogram_distance(previous, &luma) > self.threshold {
+                self.cuts.push(frame.timestamp);
+            }
+        }
+        self.previous = Some(luma);
+    }
+}
+
+/// Blocks per second of the beat detector's energy envelope
+const BEAT_BLOCKS_PER_SECOND: u32 = 100;
+
+/// Seconds either side of a block its onset threshold is averaged over
+const BEAT_THRESHOLD_WINDOW: f64 = 0.5;
+
+/// Onsets must exceed the local mean flux by this factor
+const BEAT_THRESHOLD_FACTOR: f64 = 1.5;
+
+/// Closest two beats may be, in seconds
+const MIN_BEAT_GAP: f64 = 0.1;
+
+/// Tempi considered, in beats per minute
+const TEMPO_RANGE: std::ops::RangeInclusive<f64> = 60.0..=200.0;
+
+/// Beats from onsets in the audio energy, and the tempo they suggest
+///
+/// Works on energy rises, so it finds percussive beats well and soft onsets poorly.
+#[derive(Debug, Clone, Default)]
+pub struct BeatDetector {
+    sample_rate: u32,
+    block_squares: f64,
+    block_count: usize,
+    energies: Vec<f64>,
+    beats: Vec<f64>,
+    tempo: Option<f64>,
+}
+
+impl BeatDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Beat times in seconds, available after the pass
+    pub fn beats(&self) -> &[f64] {
+        &self.beats
+    }
+
+    /// Estimated tempo in beats per minute, if the audio had any rhythm
+    pub fn tempo(&self) -> Option<f64> {
+        self.tempo
+    }
+
+    fn block_len(&self) -> usize {
+        (self.sample_rate / BEAT_BLOCKS_PER_SECOND).max(1) as usize
+    }
+}
+
+impl Analyzer for BeatDetector {
+    fn wants_audio(&self) -> bool {
+        true
+    }
+
+    fn audio(&mut self, samples: &[f32], sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for &sample in samples {
+            self.block_squares += (sample as f64).powi(2);
+            self.block_count += 1;
+            if self.block_count == self.block_len() {
+                self.energies.push(self.block_squares);
+                self.block_squares = 0.0;
+                self.block_count = 0;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        let block = self.block_len() as f64 / self.sample_rate.max(1) as f64;
+        let flux: Vec<f64> = std::iter::once(0.0)
+            .chain(self.energies.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)))
+            .collect();
+
+        let reach = (BEAT_THRESHOLD_WINDOW / block).round() as usize;
+        let min_gap = (MIN_BEAT_GAP / block).ceil() as usize;
+        let mut last_beat: Option<usize> = None;
+        for i in 1..flux.len().saturating_sub(1) {
+            let window = &flux[i.saturating_sub(reach)..(i + reach + 1).min(flux.len())];
+            let threshold = window.iter().sum::<f64>() / window.len() as f64 * BEAT_THRESHOLD_FACTOR;
+            let peak = flux[i] > threshold && flux[i] >= flux[i - 1] && flux[i] > flux[i + 1];
+            if peak && last_beat.is_none_or(|last| i - last >= min_gap) {
+                self.beats.push(i as f64 * block);
+                last_beat = Some(i);
+            }
+        }
+
+        self.tempo = tempo(&flux, block);
+    }
+}
+
+/// Tempo whose beat period best lines the onset flux up with itself
+///
+/// The best lag is refined between blocks by fitting a parabola through its neighbours.
+fn tempo(flux: &[f64], block: f64) -> Option<f64> {
+    let mean = flux.iter().sum::<f64>() / flux.len().max(1) as f64;
+    let centered: Vec<f64> = flux.iter().map(|value| value - mean).collect();
+    let energy: f64 = centered.iter().map(|value| value * value).sum();
+    if energy <= 0.0 {
+        return None;
+    }
+    let correlation = |lag: usize| -> f64 {
+        centered.iter().zip(&centered[lag.min(centered.len())..]).map(|(a, b)| a * b).sum()
+    };
+
+    let shortest = (60.0 / TEMPO_RANGE.end() / block).floor().max(1.0) as usize;
+    let longest = ((60.0 / TEMPO_RANGE.start() / block).ceil() as usize).min(centered.len().saturating_sub(2));
+    let (lag, best) = (shortest..=longest)
+        .map(|lag| (lag, correlation(lag)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if best <= 0.0 {
+        return None;
+    }
+
+    let (before, after) = (correlation(lag - 1), correlation(lag + 1));
+    let curvature = before - 2.0 * best + after;
+    let offset = if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+    Some(60.0 / ((lag as f64 + offset) * block))
+}
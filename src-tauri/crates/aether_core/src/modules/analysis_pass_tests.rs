@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use super::super::analysis_pass::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 8000;
+
+    /// 10ms bursts of a 1 kHz tone every `period` seconds, silence between
+    fn click_track(period: f64, duration: f64) -> Vec<f32> {
+        let click = (RATE / 100) as usize;
+        let period = (period * RATE as f64) as usize;
+        (0..(duration * RATE as f64) as usize)
+            .map(|i| if i % period < click { (i as f32 * 2.0 * PI * 1000.0 / RATE as f32).sin() } else { 0.0 })
+            .collect()
+    }
+
+    fn flat_frame(timestamp: f64, level: u8) -> AnalysisFrame {
+        AnalysisFrame { timestamp, width: 8, height: 4, rgb: vec![level; 8 * 4 * 3] }
+    }
+
+    #[test]
+    fn test_pass_feeds_every_analyzer_once() {
+        let sine: Vec<f32> = (0..RATE as usize).map(|i| 0.5 * (i as f32 * 2.0 * PI * 440.0 / RATE as f32).sin()).collect();
+        let mut waveform = WaveformAnalyzer::new(800);
+        let mut loudness = LoudnessAnalyzer::new(0.25);
+        let mut scenes = SceneDetector::default();
+
+        let mut pass = AnalysisPass::new()
+            .with_sample_rate(RATE)
+            .with_analyzer(&mut waveform)
+            .with_analyzer(&mut loudness)
+            .with_analyzer(&mut scenes);
+        // Chunk boundaries don't line up with peaks or windows
+        for chunk in sine.chunks(333) {
+            pass.push_audio(chunk);
+        }
+        for i in 0..10 {
+            pass.push_frame(&flat_frame(i as f64 * 0.1, 20));
+        }
+        pass.finish();
+
+        assert_eq!(waveform.peaks().len(), 10);
+        assert!(waveform.peaks().iter().all(|peak| (peak - 0.5).abs() < 0.01));
+
+        // A sine's RMS is 3 dB under its peak
+        assert_eq!(loudness.levels().len(), 4);
+        for level in loudness.levels() {
+            assert!((level - (-9.03)).abs() < 0.1, "level {}", level);
+        }
+        assert!((loudness.overall() - (-9.03)).abs() < 0.1);
+        assert!((loudness.peak() - (-6.02)).abs() < 0.1);
+
+        assert!(scenes.cuts().is_empty());
+    }
+
+    #[test]
+    fn test_silence_has_floor_level() {
+        let mut loudness = LoudnessAnalyzer::new(1.0);
+        let mut beats = BeatDetector::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut loudness).with_analyzer(&mut beats);
+        pass.push_audio(&vec![0.0; RATE as usize * 2]);
+        pass.finish();
+
+        assert_eq!(loudness.levels(), &[SILENCE_DB, SILENCE_DB]);
+        assert_eq!(loudness.peak(), SILENCE_DB);
+        assert!(beats.beats().is_empty());
+        assert_eq!(beats.tempo(), None);
+    }
+
+    #[test]
+    fn test_scene_cuts() {
+        let mut scenes = SceneDetector::new(0.35, 0.5);
+        let mut pass = AnalysisPass::new().with_analyzer(&mut scenes);
+        let levels = [(0.0, 20), (0.5, 20), (1.0, 230), (1.2, 20), (1.5, 20), (2.0, 120), (2.5, 120)];
+        for (timestamp, level) in levels {
+            pass.push_frame(&flat_frame(timestamp, level));
+        }
+        pass.finish();
+
+        // The flash back at 1.2s is too soon after the cut at 1.0s to count
+        assert_eq!(scenes.cuts(), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_beats_and_tempo() {
+        let mut beats = BeatDetector::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut beats);
+        for chunk in click_track(0.5, 10.0).chunks(1000) {
+            pass.push_audio(chunk);
+        }
+        pass.finish();
+
+        let tempo = beats.tempo().unwrap();
+        assert!((tempo - 120.0).abs() < 2.0, "tempo {}", tempo);
+
+        assert!((18..=20).contains(&beats.beats().len()), "{} beats", beats.beats().len());
+        for pair in beats.beats().windows(2) {
+            assert!((pair[1] - pair[0] - 0.5).abs() < 0.1, "beats at {:?}", pair);
+        }
+    }
+
+    #[test]
+    fn test_analyzers_only_get_wanted_streams() {
+        let mut scenes = SceneDetector::default();
+        let mut waveform = WaveformAnalyzer::new(1);
+        let mut pass = AnalysisPass::new().with_analyzer(&mut scenes).with_analyzer(&mut waveform);
+        pass.push_audio(&[0.25, -0.5]);
+        pass.finish();
+
+        assert_eq!(waveform.peaks(), &[0.25, 0.5]);
+        assert!(scenes.cuts().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use tracing::info;
+use std::path::PathBuf;
+
+use crate::engine::editing::TransitionType;
+use crate::engine::timeline::{Clip, ClipType, Timeline, Track};
+use super::audio_sync::LINK_GROUP_PROPERTY;
+use super::file_manager::MediaType;
+use super::media_library::MediaLibrary;
+
+/// Clip property naming the transition into the clip, as a GStreamer transition name
+pub const TRANSITION_PROPERTY: &str = "transition";
+
+/// Clip property with the transition length in seconds, centred on the cut
+pub const TRANSITION_DURATION_PROPERTY: &str = "transition_duration";
+
+/// Where a piece of the rough cut comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssemblySource {
+    /// A file with source in and out points in seconds
+    File { path: PathBuf, in_point: f64, out_point: f64 },
+    /// A library asset with source in and out points in seconds
+    Asset { asset_id: String, in_point: f64, out_point: f64 },
+    /// The stretch of an asset where its transcript says `phrase`
+    ///
+    /// `occurrence` picks among repeated takes, starting at 0.
+    Transcript { asset_id: String, phrase: String, occurrence: usize },
+}
+
+/// One entry of a script or shot list
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyItem {
+    pub source: AssemblySource,
+    /// Video transition into this item, overriding the default
+    pub transition: Option<TransitionType>,
+}
+
+/// Options for assembling a sequence
+#[derive(Debug, Clone)]
+pub struct AssemblyOptions {
+    pub video_track: String,
+    pub audio_track: String,
+    /// Timeline time of the first item; `None` appends after the tracks' last clip
+    pub start_time: Option<f64>,
+    /// Transition between items, or `None` for straight cuts
+    pub transition: Option<TransitionType>,
+    /// Transition between audio items, or `None` for straight cuts
+    pub audio_transition: Option<TransitionType>,
+    /// Transition length in seconds, capped at half the shorter neighbour
+    pub transition_duration: f64,
+    /// Whether to add a linked audio clip for each video item
+    pub include_audio: bool,
+    /// Seconds added before and after transcript selections so words aren't clipped
+    pub transcript_padding: f64,
+    /// Prefix of the created clip IDs
+    pub id_prefix: String,
+}
+
+impl Default for AssemblyOptions {
+    fn default() -> Self {
+        Self {
+            video_track: "video_1".to_string(),
+            audio_track: "audio_1".to_string(),
+            start_time: None,
+            transition: Some(TransitionType::Crossfade),
+            audio_transition: Some(TransitionType::AudioCrossfade),
+            transition_duration: 0.5,
+            include_audio: true,
+            transcript_padding: 0.25,
+            id_prefix: "assembly".to_string(),
+        }
+    }
+}
+
+/// What `AssemblyBuilder::build` added
+#[derive(Debug, Clone, Default)]
+pub struct AssemblyResult {
+    /// Video clip IDs in sequence order
+    pub video_clips: Vec<String>,
+    /// Audio clip IDs in sequence order
+    pub audio_clips: Vec<String>,
+    /// Timeline time the sequence ends
+    pub end_time: f64,
+}
+
+/// A source range with the kind of media it holds
+struct ResolvedItem {
+    path: String,
+    in_point: f64,
+    out_point: f64,
+    media_type: MediaType,
+    transition: Option<TransitionType>,
+}
+
+/// Builds a timeline sequence from an ordered list of clip references, for paper edits
+pub struct AssemblyBuilder<'a> {
+    library: Option<&'a MediaLibrary>,
+
+    options: AssemblyOptions,
+
+    items: Vec<AssemblyItem>,
+}
+
+impl<'a> AssemblyBuilder<'a> {
+    pub fn new(options: AssemblyOptions) -> Self {
+        Self {
+            library: None,
+            options,
+            items: Vec::new(),
+        }
+    }
+
+    /// Resolve asset and transcript references through a media library
+    pub fn with_library(mut self, library: &'a MediaLibrary) -> Self {
+        self.library = Some(library);
+        self
+    }
+
+    pub fn add(&mut self, item: AssemblyItem) -> &mut Self {
+        self.items.push(item);
+        self
+    }
+
+    /// Add a file range with the default transition
+    pub fn add_file(&mut self, path: impl Into<PathBuf>, in_point: f64, out_point: f64) -> &mut Self {
+        self.add_source(AssemblySource::File { path: path.into(), in_point, out_point })
+    }
+
+    /// Add an asset range with the default transition
+    pub fn add_asset(&mut self, asset_id: &str, in_point: f64, out_point: f64) -> &mut Self {
+        self.add_source(AssemblySource::Asset { asset_id: asset_id.to_string(), in_point, out_point })
+    }
+
+    /// Add the first place an asset's transcript says `phrase`
+    pub fn add_transcript_selection(&mut self, asset_id: &str, phrase: &str) -> &mut Self {
+        self.add_source(AssemblySource::Transcript {
+            asset_id: asset_id.to_string(),
+            phrase: phrase.to_string(),
+            occurrence: 0,
+        })
+    }
+
+    fn add_source(&mut self, source: AssemblySource) -> &mut Self {
+        self.add(AssemblyItem { source, transition: None })
+    }
+
+    pub fn items(&self) -> &[AssemblyItem] {
+        &self.items
+    }
+
+    /// Lay the items out back to back on the timeline
+    ///
+    /// Every reference is resolved before anything is added, so a bad entry leaves the
+    /// timeline untouched. Missing tracks are created.
+    pub fn build(&self, timeline: &mut Timeline) -> Result<AssemblyResult> {
+        if self.items.is_empty() {
+            return Err(anyhow!("Nothing to assemble"));
+        }
+        let resolved = self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| self.resolve(item).map_err(|e| anyhow!("Item {}: {}", index + 1, e)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for track_id in [&self.options.video_track, &self.options.audio_track] {
+            if timeline.get_track(track_id).is_err() {
+                timeline.add_track(Track::new(track_id.clone(), track_id.clone()))?;
+            }
+        }
+
+        let mut time = match self.options.start_time {
+            Some(time) => time,
+            None => [&self.options.video_track, &self.options.audio_track]
+                .iter()
+                .filter_map(|id| timeline.get_track(id).ok())
+                .flat_map(|track| track.clips.iter().map(Clip::end_time))
+                .fold(0.0, f64::max),
+        };
+
+        let mut result = AssemblyResult::default();
+        for (index, item) in resolved.iter().enumerate() {
+            let duration = item.out_point - item.in_point;
+
+            // Transitions into this item, sized to fit both neighbours
+            let transition_length = match index.checked_sub(1).map(|previous| &resolved[previous]) {
+                Some(previous) => self.options.transition_duration
+                    .min((previous.out_point - previous.in_point) / 2.0)
+                    .min(duration / 2.0),
+                None => 0.0,
+            };
+            let video_transition = item.transition.clone().or_else(|| self.options.transition.clone());
+            let audio_transition = self.options.audio_transition.clone();
+
+            let id = format!("{}_{}", self.options.id_prefix, index + 1);
+            let video = matches!(item.media_type, MediaType::Video | MediaType::Image | MediaType::Unknown);
+            let audio = item.media_type == MediaType::Audio
+                || (self.options.include_audio && matches!(item.media_type, MediaType::Video | MediaType::Unknown));
+
+            let make_clip = |id: String, clip_type: ClipType, transition: Option<&TransitionType>| {
+                let mut clip = Clip::new(id, clip_type, time, duration).with_source(item.path.clone());
+                clip.set_in_point(item.in_point);
+                if let (Some(transition), true) = (transition, transition_length > 0.0) {
+                    clip.properties.insert(TRANSITION_PROPERTY.to_string(), transition.to_gst_name().to_string());
+                    clip.properties.insert(TRANSITION_DURATION_PROPERTY.to_string(), transition_length.to_string());
+                }
+                if video && audio {
+                    clip.properties.insert(LINK_GROUP_PROPERTY.to_string(), format!("{}_{}_link", self.options.id_prefix, index + 1));
+                }
+                clip
+            };
+
+            if video {
+                let clip_type = if item.media_type == MediaType::Image { ClipType::Image } else { ClipType::Video };
+                let clip = make_clip(format!("{}_v", id), clip_type, video_transition.as_ref());
+                result.video_clips.push(clip.id.clone());
+                timeline.add_clip_to_track(&self.options.video_track, clip)?;
+            }
+            if audio {
+                let clip = make_clip(format!("{}_a", id), ClipType::Audio, audio_transition.as_ref());
+                result.audio_clips.push(clip.id.clone());
+                timeline.add_clip_to_track(&self.options.audio_track, clip)?;
+            }
+
+            time += duration;
+        }
+
+        if time > timeline.duration() {
+            timeline.set_duration(time)?;
+        }
+        result.end_time = time;
+
+        info!("Assembled {} items ending at {:.3}s", resolved.len(), time);
+        Ok(result)
+    }
+
+    /// Source path, range and media type of an item
+    fn resolve(&self, item: &AssemblyItem) -> Result<ResolvedItem> {
+        let (path, in_point, out_point, media_type) = match &item.source {
+            AssemblySource::File { path, in_point, out_point } => {
+                let media_type = self.library
+                    .and_then(|library| library.find_by_path(path))
+                    .and_then(|asset| asset.info.as_ref())
+                    .map_or(MediaType::Unknown, |info| info.media_type);
+                (path.clone(), *in_point, *out_point, media_type)
+            },
+            AssemblySource::Asset { asset_id, in_point, out_point } => {
+                let (path, media_type) = self.asset(asset_id)?;
+                (path, *in_point, *out_point, media_type)
+            },
+            AssemblySource::Transcript { asset_id, phrase, occurrence } => {
+                let library = self.library.ok_or_else(|| anyhow!("Transcript selections need a media library"))?;
+                let transcript = library.get_asset(asset_id)
+                    .and_then(|asset| asset.transcript.as_ref())
+                    .ok_or_else(|| anyhow!("Asset {} has no transcript", asset_id))?;
+                let found = transcript.find_phrase(phrase)
+                    .into_iter()
+                    .nth(*occurrence)
+                    .ok_or_else(|| anyhow!("\"{}\" not found in the transcript of {}", phrase, asset_id))?;
+
+                let (path, media_type) = self.asset(asset_id)?;
+                let padding = self.options.transcript_padding.max(0.0);
+                (path, (found.start - padding).max(0.0), found.end + padding, media_type)
+            },
+        };
+
+        if in_point < 0.0 || out_point <= in_point {
+            return Err(anyhow!("Invalid range {:.3}s-{:.3}s for {:?}", in_point, out_point, path));
+        }
+
+        Ok(ResolvedItem {
+            path: path.to_string_lossy().to_string(),
+            in_point,
+            out_point,
+            media_type,
+            transition: item.transition.clone(),
+        })
+    }
+
+    fn asset(&self, asset_id: &str) -> Result<(PathBuf, MediaType)> {
+        let library = self.library.ok_or_else(|| anyhow!("Asset references need a media library"))?;
+        let path = library.resolve_path(asset_id)?;
+        let media_type = library.get_asset(asset_id)
+            .and_then(|asset| asset.info.as_ref())
+            .map_or(MediaType::Unknown, |info| info.media_type);
+        Ok((path, media_type))
+    }
+}
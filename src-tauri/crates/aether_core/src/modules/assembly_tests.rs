@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use super::super::assembly::{AssemblyBuilder, AssemblyOptions, TRANSITION_DURATION_PROPERTY, TRANSITION_PROPERTY};
+    use super::super::audio_sync::LINK_GROUP_PROPERTY;
+    use super::super::media_library::MediaLibrary;
+    use super::super::transcription::Transcript;
+    use crate::engine::timeline::{Clip, Timeline, TimelineConfig};
+    use anyhow::Result;
+    use std::fs;
+
+    fn find<'a>(timeline: &'a Timeline, id: &str) -> &'a Clip {
+        timeline.tracks().values().flat_map(|track| track.clips.iter()).find(|clip| clip.id == id).unwrap()
+    }
+
+    #[test]
+    fn test_assembles_shot_list_with_transitions() -> Result<()> {
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        let mut builder = AssemblyBuilder::new(AssemblyOptions::default());
+        builder
+            .add_file("/media/a.mp4", 10.0, 14.0)
+            .add_file("/media/b.mp4", 0.0, 0.6)
+            .add_file("/media/a.mp4", 30.0, 90.0);
+
+        let result = builder.build(&mut timeline)?;
+        assert_eq!(result.video_clips, vec!["assembly_1_v", "assembly_2_v", "assembly_3_v"]);
+        assert_eq!(result.audio_clips.len(), 3);
+        assert!((result.end_time - 64.6).abs() < 1e-9);
+        assert!(timeline.duration() >= result.end_time);
+
+        let first = find(&timeline, "assembly_1_v");
+        assert_eq!((first.start_time, first.in_point()), (0.0, 10.0));
+        assert!(!first.properties.contains_key(TRANSITION_PROPERTY));
+        assert_eq!(first.properties[LINK_GROUP_PROPERTY], find(&timeline, "assembly_1_a").properties[LINK_GROUP_PROPERTY]);
+
+        // Capped at half the short middle clip
+        let second = find(&timeline, "assembly_2_v");
+        assert_eq!(second.start_time, 4.0);
+        assert_eq!(second.properties[TRANSITION_PROPERTY], "crossfade");
+        assert_eq!(second.properties[TRANSITION_DURATION_PROPERTY].parse::<f64>()?, 0.3);
+
+        let third = find(&timeline, "assembly_3_a");
+        assert_eq!(third.properties[TRANSITION_PROPERTY], "audiomixer");
+        assert_eq!(third.properties[TRANSITION_DURATION_PROPERTY].parse::<f64>()?, 0.3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcript_selection_and_bad_entries() -> Result<()> {
+        let root = std::env::temp_dir().join("aether_assembly_test");
+        fs::create_dir_all(&root)?;
+        let media = root.join("interview.mp4");
+        fs::write(&media, b"interview")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+        library.set_transcript(&id, Some(Transcript::from_srt(
+            "00:00:05,000 --> 00:00:08,000\nWe started in a garage\n\n00:00:20,000 --> 00:00:23,500\nNow we ship worldwide\n",
+        )?))?;
+
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        let options = AssemblyOptions { transition: None, audio_transition: None, include_audio: false, ..AssemblyOptions::default() };
+
+        // A missing phrase fails before anything is added
+        let mut builder = AssemblyBuilder::new(options.clone()).with_library(&library);
+        builder.add_transcript_selection(&id, "ship worldwide").add_transcript_selection(&id, "never said");
+        assert!(builder.build(&mut timeline).is_err());
+        assert!(timeline.tracks().values().all(|track| track.clips.is_empty()));
+
+        let mut builder = AssemblyBuilder::new(options).with_library(&library);
+        builder.add_transcript_selection(&id, "ship worldwide").add_transcript_selection(&id, "in a garage");
+        let result = builder.build(&mut timeline)?;
+        assert!(result.audio_clips.is_empty());
+
+        let first = find(&timeline, "assembly_1_v");
+        assert_eq!((first.in_point(), first.duration), (19.75, 4.0));
+        let second = find(&timeline, "assembly_2_v");
+        assert_eq!((second.start_time, second.in_point()), (4.0, 4.75));
+        assert!(!second.properties.contains_key(TRANSITION_PROPERTY));
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
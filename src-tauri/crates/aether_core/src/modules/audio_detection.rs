@@ -0,0 +1,383 @@
+//! Audio dropout, DC offset, clipping and phase detection
+//!
+//! `AudioIssueDetector` is a stereo analyzer that finds short digital dropouts, DC offset,
+//! runs of hard-clipped samples and stretches where left and right cancel each other out.
+//! It runs in an `AnalysisPass` with the QC measurements, or on its own through
+//! `detect_audio_issues` on a source clip or the final mix. Mono audio is checked as two
+//! identical channels, so it never shows phase problems.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use tracing::info;
+
+use super::analysis_pass::{AnalysisPass, Analyzer};
+use super::qc_report::QcSegment;
+
+/// Rate audio is checked at; at the usual 48 kHz, resampling can't add or hide clipping
+pub const AUDIO_CHECK_SAMPLE_RATE: u32 = 48000;
+
+/// Samples quieter than this count as digital silence
+const DROPOUT_LEVEL: f32 = 1e-4;
+
+/// Length of the blocks DC offset is averaged over, in seconds
+const DC_BLOCK: f64 = 1.0;
+
+/// Length of the blocks L/R correlation is measured over, in seconds
+const PHASE_BLOCK: f64 = 0.1;
+
+/// Blocks quieter than this have no meaningful correlation, as mean square
+const PHASE_GATE: f64 = 1e-5;
+
+/// Clipped runs this close together are reported as one, in seconds
+const CLIP_GAP: f64 = 0.1;
+
+/// Thresholds of an audio check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioCheckOptions {
+    /// Silences between these lengths, with sound either side, are dropouts; longer
+    /// ones are taken to be intended, in seconds
+    pub dropout_range: (f64, f64),
+    /// Highest allowed mean level of a channel over a second, in dBFS
+    pub max_dc_offset: f64,
+    /// Samples at or above this level are clipped, in dBFS
+    pub clip_level: f64,
+    /// Consecutive clipped samples that make a channel hard-clipped
+    pub min_clipped_run: usize,
+    /// L/R correlation below which the channels cancel out, -1 to 1
+    pub min_correlation: f64,
+    /// Shortest stretch of cancelling channels reported, in seconds
+    pub min_phase_duration: f64,
+}
+
+impl Default for AudioCheckOptions {
+    fn default() -> Self {
+        Self {
+            dropout_range: (0.005, 0.5),
+            max_dc_offset: -40.0,
+            clip_level: -0.01,
+            min_clipped_run: 3,
+            min_correlation: -0.5,
+            min_phase_duration: 0.5,
+        }
+    }
+}
+
+/// What is wrong with a stretch of audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioIssueKind {
+    Dropout,
+    DcOffset,
+    Clipping,
+    PhaseCancellation,
+}
+
+impl fmt::Display for AudioIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioIssueKind::Dropout => write!(f, "Dropout"),
+            AudioIssueKind::DcOffset => write!(f, "DC offset"),
+            AudioIssueKind::Clipping => write!(f, "Clipping"),
+            AudioIssueKind::PhaseCancellation => write!(f, "Phase cancellation"),
+        }
+    }
+}
+
+/// A stretch of audio with a problem
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioIssue {
+    pub kind: AudioIssueKind,
+    /// Media time in seconds
+    pub start: f64,
+    pub end: f64,
+    /// How bad it is: the highest offset in dBFS for DC offset, the number of clipped
+    /// samples for clipping, the lowest correlation for phase cancellation, 0 for dropouts
+    pub value: f64,
+}
+
+impl AudioIssue {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// One line description, like `Clipping at 12.500 s to 12.520 s (96 samples)`
+    pub fn describe(&self) -> String {
+        let detail = match self.kind {
+            AudioIssueKind::Dropout => format!("{:.3} s", self.duration()),
+            AudioIssueKind::DcOffset => format!("{:.1} dBFS", self.value),
+            AudioIssueKind::Clipping => format!("{} samples", self.value),
+            AudioIssueKind::PhaseCancellation => format!("correlation {:.2}", self.value),
+        };
+        format!("{} at {:.3} s to {:.3} s ({})", self.kind, self.start, self.end, detail)
+    }
+}
+
+/// Short runs of digital silence in otherwise audible audio
+#[derive(Debug, Clone)]
+pub struct DropoutDetector {
+    range: (f64, f64),
+    sample_rate: u32,
+    position: u64,
+    /// Start of the current silence, if it followed sound
+    silence_start: Option<u64>,
+    heard: bool,
+    dropouts: Vec<QcSegment>,
+}
+
+impl DropoutDetector {
+    /// Report silences lasting between `range.0` and `range.1` seconds
+    pub fn new(range: (f64, f64)) -> Self {
+        Self { range, sample_rate: AUDIO_CHECK_SAMPLE_RATE, position: 0, silence_start: None, heard: false, dropouts: Vec::new() }
+    }
+
+    pub fn dropouts(&self) -> &[QcSegment] {
+        &self.dropouts
+    }
+}
+
+impl Analyzer for DropoutDetector {
+    fn wants_audio(&self) -> bool {
+        true
+    }
+
+    fn audio(&mut self, samples: &[f32], sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        for &sample in samples {
+            if sample.abs() < DROPOUT_LEVEL {
+                if self.heard && self.silence_start.is_none() {
+                    self.silence_start = Some(self.position);
+                }
+            } else {
+                if let Some(start) = self.silence_start.take() {
+                    let segment = QcSegment {
+                        start: start as f64 / sample_rate as f64,
+                        end: self.position as f64 / sample_rate as f64,
+                    };
+                    if segment.duration() >= self.range.0 && segment.duration() <= self.range.1 {
+                        self.dropouts.push(segment);
+                    }
+                }
+                self.heard = true;
+            }
+            self.position += 1;
+        }
+    }
+}
+
+/// Dropouts, DC offset, clipping and phase cancellation in stereo audio
+#[derive(Debug, Clone)]
+pub struct AudioIssueDetector {
+    options: AudioCheckOptions,
+    /// Fed the louder channel, so audio in only one channel isn't a dropout
+    dropout: DropoutDetector,
+    sample_rate: u32,
+    position: u64,
+    /// Sums of left and right over the current DC block, and its length in samples
+    dc_sums: [f64; 2],
+    dc_count: u64,
+    /// Sums of L*R, L*L and R*R over the current phase block, and its length in samples
+    phase_sums: [f64; 3],
+    phase_count: u64,
+    /// Start and length of the current run of clipped samples in each channel
+    clipped_runs: [(u64, usize); 2],
+    dc_offset: IssueRuns,
+    clipping: IssueRuns,
+    phase: IssueRuns,
+}
+
+impl AudioIssueDetector {
+    pub fn new(options: AudioCheckOptions) -> Self {
+        Self {
+            dropout: DropoutDetector::new(options.dropout_range),
+            sample_rate: AUDIO_CHECK_SAMPLE_RATE,
+            position: 0,
+            dc_sums: [0.0; 2],
+            dc_count: 0,
+            phase_sums: [0.0; 3],
+            phase_count: 0,
+            clipped_runs: [(0, 0); 2],
+            dc_offset: IssueRuns::new(AudioIssueKind::DcOffset, 0.0, 0.0, f64::max),
+            clipping: IssueRuns::new(AudioIssueKind::Clipping, CLIP_GAP, 0.0, |a, b| a + b),
+            phase: IssueRuns::new(AudioIssueKind::PhaseCancellation, 0.0, options.min_phase_duration, f64::min),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &AudioCheckOptions {
+        &self.options
+    }
+
+    /// Issues of every kind in time order, available after the pass
+    pub fn issues(&self) -> Vec<AudioIssue> {
+        let mut issues: Vec<AudioIssue> = self.dropout.dropouts().iter()
+            .map(|segment| AudioIssue { kind: AudioIssueKind::Dropout, start: segment.start, end: segment.end, value: 0.0 })
+            .chain(self.dc_offset.issues.iter().copied())
+            .chain(self.clipping.issues.iter().copied())
+            .chain(self.phase.issues.iter().copied())
+            .collect();
+        issues.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.end.total_cmp(&b.end)));
+        issues
+    }
+
+    fn time(&self, position: u64) -> f64 {
+        position as f64 / self.sample_rate as f64
+    }
+
+    fn end_dc_block(&mut self) {
+        if self.dc_count == 0 {
+            return;
+        }
+        let offset = self.dc_sums.iter().map(|sum| (sum / self.dc_count as f64).abs()).fold(0.0, f64::max);
+        let level = 20.0 * offset.max(f64::MIN_POSITIVE).log10();
+        let (start, end) = (self.time(self.position - self.dc_count), self.time(self.position));
+        if level > self.options.max_dc_offset {
+            self.dc_offset.add(start, end, level);
+        } else {
+            self.dc_offset.close();
+        }
+        self.dc_sums = [0.0; 2];
+        self.dc_count = 0;
+    }
+
+    fn end_phase_block(&mut self) {
+        if self.phase_count == 0 {
+            return;
+        }
+        let [cross, left, right] = self.phase_sums;
+        let (start, end) = (self.time(self.position - self.phase_count), self.time(self.position));
+        let audible = (left + right) / (2 * self.phase_count) as f64 > PHASE_GATE;
+        let correlation = cross / (left * right).sqrt().max(f64::MIN_POSITIVE);
+        if audible && correlation < self.options.min_correlation {
+            self.phase.add(start, end, correlation);
+        } else {
+            self.phase.close();
+        }
+        self.phase_sums = [0.0; 3];
+        self.phase_count = 0;
+    }
+
+    fn end_clipped_run(&mut self, channel: usize) {
+        let (start, length) = self.clipped_runs[channel];
+        if length >= self.options.min_clipped_run.max(1) {
+            let (start, end) = (self.time(start), self.time(start + length as u64));
+            self.clipping.add(start, end, length as f64);
+        }
+        self.clipped_runs[channel] = (0, 0);
+    }
+}
+
+impl Analyzer for AudioIssueDetector {
+    fn wants_stereo(&self) -> bool {
+        true
+    }
+
+    fn stereo(&mut self, left: &[f32], right: &[f32], sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        let louder: Vec<f32> = left.iter().zip(right).map(|(l, r)| if l.abs() >= r.abs() { *l } else { *r }).collect();
+        self.dropout.audio(&louder, sample_rate);
+
+        let dc_len = ((DC_BLOCK * sample_rate as f64) as u64).max(1);
+        let phase_len = ((PHASE_BLOCK * sample_rate as f64) as u64).max(1);
+        let clip_level = 10f64.powf(self.options.clip_level / 20.0) as f32;
+        for (&l, &r) in left.iter().zip(right) {
+            for (channel, sample) in [l, r].into_iter().enumerate() {
+                if sample.abs() >= clip_level {
+                    let (start, length) = &mut self.clipped_runs[channel];
+                    if *length == 0 {
+                        *start = self.position;
+                    }
+                    *length += 1;
+                } else if self.clipped_runs[channel].1 > 0 {
+                    self.end_clipped_run(channel);
+                }
+            }
+
+            let (l, r) = (l as f64, r as f64);
+            self.dc_sums[0] += l;
+            self.dc_sums[1] += r;
+            self.dc_count += 1;
+            self.phase_sums[0] += l * r;
+            self.phase_sums[1] += l * l;
+            self.phase_sums[2] += r * r;
+            self.phase_count += 1;
+            self.position += 1;
+
+            if self.dc_count == dc_len {
+                self.end_dc_block();
+            }
+            if self.phase_count == phase_len {
+                self.end_phase_block();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        for channel in 0..2 {
+            self.end_clipped_run(channel);
+        }
+        // A short last block says little about DC, but a full phase block's worth does
+        if self.dc_count as f64 >= DC_BLOCK * self.sample_rate as f64 / 2.0 {
+            self.end_dc_block();
+        }
+        self.end_phase_block();
+        for runs in [&mut self.dc_offset, &mut self.clipping, &mut self.phase] {
+            runs.close();
+        }
+    }
+}
+
+/// Check a source clip or the final mix for dropouts, DC offset, clipping and phase
+/// cancellation
+#[tracing::instrument(name = "audio_detection", skip(options))]
+pub fn detect_audio_issues(path: &Path, options: &AudioCheckOptions) -> Result<Vec<AudioIssue>> {
+    let mut detector = AudioIssueDetector::new(options.clone());
+    AnalysisPass::new()
+        .with_sample_rate(AUDIO_CHECK_SAMPLE_RATE)
+        .with_analyzer(&mut detector)
+        .run(path)?;
+
+    let issues = detector.issues();
+    info!("Found {} audio issues in {:?}", issues.len(), path);
+    Ok(issues)
+}
+
+/// Issues of one kind, merging ones that follow on within `gap` seconds
+#[derive(Debug, Clone)]
+struct IssueRuns {
+    kind: AudioIssueKind,
+    gap: f64,
+    min_duration: f64,
+    /// Combines the values of merged issues
+    combine: fn(f64, f64) -> f64,
+    open: Option<AudioIssue>,
+    issues: Vec<AudioIssue>,
+}
+
+impl IssueRuns {
+    fn new(kind: AudioIssueKind, gap: f64, min_duration: f64, combine: fn(f64, f64) -> f64) -> Self {
+        Self { kind, gap, min_duration, combine, open: None, issues: Vec::new() }
+    }
+
+    fn add(&mut self, start: f64, end: f64, value: f64) {
+        if let Some(open) = &mut self.open {
+            if start - open.end <= self.gap + 1e-9 {
+                open.start = open.start.min(start);
+                open.end = open.end.max(end);
+                open.value = (self.combine)(open.value, value);
+                return;
+            }
+        }
+        self.close();
+        self.open = Some(AudioIssue { kind: self.kind, start, end, value });
+    }
+
+    fn close(&mut self) {
+        if let Some(issue) = self.open.take() {
+            if issue.duration() >= self.min_duration - 1e-9 {
+                self.issues.push(issue);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use super::super::analysis_pass::{AnalysisPass, WaveformAnalyzer};
+    use super::super::audio_detection::*;
+    use std::f32::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    fn sine(index: usize) -> f32 {
+        (index as f32 * 2.0 * PI * 997.0 / RATE as f32).sin()
+    }
+
+    /// Six seconds of stereo: a dropout at 1s, clipping from 2s, the right channel
+    /// inverted from 3s and a DC offset from 4s
+    fn program() -> (Vec<f32>, Vec<f32>) {
+        (0..6 * RATE as usize)
+            .map(|index| {
+                let time = index as f64 / RATE as f64;
+                let tone = 0.5 * sine(index);
+                match time {
+                    t if (1.0..1.02).contains(&t) => (0.0, 0.0),
+                    t if (2.0..3.0).contains(&t) => {
+                        let clipped = (1.5 * sine(index)).clamp(-1.0, 1.0);
+                        (clipped, clipped)
+                    },
+                    t if (3.0..4.0).contains(&t) => (tone, -tone),
+                    t if t >= 4.0 => (tone + 0.05, tone + 0.05),
+                    _ => (tone, tone),
+                }
+            })
+            .unzip()
+    }
+
+    fn check(options: AudioCheckOptions, left: &[f32], right: &[f32]) -> Vec<AudioIssue> {
+        let mut detector = AudioIssueDetector::new(options);
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut detector);
+        for (left, right) in left.chunks(1000).zip(right.chunks(1000)) {
+            pass.push_stereo(left, right);
+        }
+        pass.finish();
+        detector.issues()
+    }
+
+    #[test]
+    fn test_finds_audio_issues() {
+        let (left, right) = program();
+        let issues = check(AudioCheckOptions::default(), &left, &right);
+        let kinds: Vec<AudioIssueKind> = issues.iter().map(|issue| issue.kind).collect();
+        assert_eq!(kinds, vec![
+            AudioIssueKind::Dropout,
+            AudioIssueKind::Clipping,
+            AudioIssueKind::PhaseCancellation,
+            AudioIssueKind::DcOffset,
+        ]);
+
+        let [dropout, clipping, phase, dc] = [issues[0], issues[1], issues[2], issues[3]];
+        assert_eq!((dropout.start, dropout.end), (1.0, 1.02));
+        // Every clipped peak of the second, merged into one issue
+        assert!(clipping.start >= 2.0 && clipping.start < 2.001, "{:?}", clipping);
+        assert!(clipping.end > 2.999 && clipping.end <= 3.0, "{:?}", clipping);
+        assert!(clipping.value > 2.0 * RATE as f64 * 0.2);
+        assert_eq!((phase.start, phase.end, phase.value), (3.0, 4.0, -1.0));
+        assert_eq!((dc.start, dc.end), (4.0, 6.0));
+        assert!((dc.value + 26.02).abs() < 0.05, "{:?}", dc);
+        assert_eq!(phase.describe(), "Phase cancellation at 3.000 s to 4.000 s (correlation -1.00)");
+
+        // Looser limits let everything but the dropout through
+        let lenient = AudioCheckOptions {
+            max_dc_offset: -20.0,
+            min_clipped_run: 100,
+            min_phase_duration: 1.5,
+            ..AudioCheckOptions::default()
+        };
+        let issues = check(lenient, &left, &right);
+        assert_eq!(issues.iter().map(|issue| issue.kind).collect::<Vec<_>>(), vec![AudioIssueKind::Dropout]);
+    }
+
+    #[test]
+    fn test_mono_audio_has_no_phase_issues() {
+        let (left, right) = program();
+        let mut waveform = WaveformAnalyzer::new(RATE as usize);
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut waveform);
+        // Mono analyzers get the mix, where the inverted second cancels out
+        pass.push_stereo(&left, &right);
+        pass.finish();
+        assert!(waveform.peaks()[3] < 1e-6);
+        assert!((waveform.peaks()[4] - 0.55).abs() < 0.01);
+
+        // Mono audio reaches stereo analyzers in both channels
+        let mut detector = AudioIssueDetector::new(AudioCheckOptions::default());
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut detector);
+        pass.push_audio(&left);
+        pass.finish();
+        let kinds: Vec<AudioIssueKind> = detector.issues().iter().map(|issue| issue.kind).collect();
+        assert_eq!(kinds, vec![AudioIssueKind::Dropout, AudioIssueKind::Clipping, AudioIssueKind::DcOffset]);
+    }
+}
@@ -1,12 +1,19 @@
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use anyhow::{Result, Context};
-use log::{debug, info, warn, error};
+use tracing::{debug, info, warn, error};
 use gst::prelude::*;
 use glib;
 
 use crate::engine::editing::types::EditingError;
+use crate::modules::path_policy;
+use crate::modules::safe_mode;
+use crate::modules::settings::EngineSettings;
 
 /// Audio playback state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -142,14 +149,10 @@ impl AudioTrack {
         // Create source element based on the audio source type
         let source_element = match &self.source {
             AudioSourceType::File(path) => {
-                // Convert path to URI
-                let uri = gst::filename_to_uri(path.to_str().unwrap())
-                    .map_err(|_| EditingError::AudioError(format!("Failed to convert path to URI: {:?}", path)))?;
-                
                 // Create filesrc element
                 let filesrc = gst::ElementFactory::make("filesrc")
                     .name(&format!("source-{}", self.id))
-                    .property("location", path.to_str().unwrap())
+                    .property("location", path_policy::gst_location(path).map_err(|e| EditingError::AudioError(e.to_string()))?)
                     .build()
                     .map_err(|_| EditingError::AudioError("Failed to create filesrc element".to_string()))?;
                 
@@ -432,6 +435,11 @@ impl AudioTrack {
         Ok(())
     }
     
+    /// Get the volume level (0.0 - 1.0)
+    pub fn volume(&self) -> f64 {
+        self.volume_level
+    }
+    
     /// Set the pan position (-1.0 left to 1.0 right)
     pub fn set_pan(&mut self, pan: f64) -> Result<(), EditingError> {
         let pan = pan.max(-1.0).min(1.0);
@@ -512,6 +520,15 @@ impl AudioTrack {
         
         let audio_bin = self.audio_bin.as_ref().unwrap();
         
+        let factory = match &effect_type {
+            AudioEffectType::Equalizer { .. } => "equalizer-nbands",
+            AudioEffectType::Reverb { .. } => "freeverb",
+            AudioEffectType::Delay { .. } => "ladspa-delay",
+            AudioEffectType::Compressor { .. } => "audiodynamic",
+        };
+        let _load = safe_mode::begin_load(factory)
+            .map_err(|e| EditingError::AudioError(e.to_string()))?;
+        
         // Create the effect element based on the effect type
         let effect_element = match &effect_type {
             AudioEffectType::Equalizer { bands, gains } => {
@@ -714,6 +731,73 @@ fn handle_pad_added(bin: &gst::Bin, src_pad: &gst::Pad) {
     }
 }
 
+/// A finished bounce
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bounce {
+    /// The bounced audio file
+    pub path: PathBuf,
+    pub render: OfflineRender,
+    /// Track playing the bounce, if it replaced the bounced tracks
+    pub track_id: Option<String>,
+}
+
+/// Streams interleaved float samples into a WAV file, filling in the sizes on `finish`
+struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u64,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32, channels: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align = channels * 4;
+        
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // IEEE float
+        file.write_all(&3u16.to_le_bytes())?;
+        file.write_all(&(channels as u16).to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align).to_le_bytes())?;
+        file.write_all(&(block_align as u16).to_le_bytes())?;
+        file.write_all(&32u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        
+        Ok(Self { file, data_len: 0 })
+    }
+    
+    fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += samples.len() as u64 * 4;
+        Ok(())
+    }
+    
+    fn finish(mut self) -> std::io::Result<()> {
+        let data_len = u32::try_from(self.data_len)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Bounce too long for WAV"))?;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(data_len + 36).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data_len.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+/// Caps the offline sink takes: interleaved float at the engine's rate and channel count
+fn offline_caps(config: &AudioEngineConfig) -> gst::Caps {
+    gst::Caps::builder("audio/x-raw")
+        .field("format", "F32LE")
+        .field("layout", "interleaved")
+        .field("rate", config.sample_rate as i32)
+        .field("channels", config.channels.max(1) as i32)
+        .build()
+}
+
 /// Audio device information
 #[derive(Debug, Clone)]
 pub struct AudioDevice {
@@ -733,6 +817,33 @@ pub struct AudioDevice {
     pub sample_rate: u32,
 }
 
+/// Result of an offline render
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OfflineRender {
+    /// Sample frames rendered, i.e. samples per channel
+    pub frames: u64,
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Highest absolute sample value
+    pub peak: f32,
+    /// Root mean square over all samples
+    pub rms: f32,
+    /// Wall-clock time the render took
+    pub elapsed: Duration,
+}
+
+impl OfflineRender {
+    /// Length of the rendered audio in seconds
+    pub fn duration(&self) -> f64 {
+        self.frames as f64 / self.sample_rate.max(1) as f64
+    }
+    
+    /// How many times faster than realtime the render ran
+    pub fn speed(&self) -> f64 {
+        self.duration() / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
 /// Audio engine configuration
 #[derive(Debug, Clone)]
 pub struct AudioEngineConfig {
@@ -746,6 +857,8 @@ pub struct AudioEngineConfig {
     pub output_device: Option<String>,
     /// Input device ID
     pub input_device: Option<String>,
+    /// Render the mix to memory as fast as possible instead of playing it on a device
+    pub offline: bool,
 }
 
 impl Default for AudioEngineConfig {
@@ -756,6 +869,7 @@ impl Default for AudioEngineConfig {
             channels: 2,
             output_device: None,
             input_device: None,
+            offline: false,
         }
     }
 }
@@ -830,7 +944,15 @@ impl AudioEngine {
             .map_err(|_| EditingError::AudioError("Failed to create master volume element".to_string()))?;
         
         // Create the audio sink
-        let sink = if let Some(device_id) = &self.config.output_device {
+        let sink = if self.config.offline {
+            // Not synced to the clock, so the mix runs as fast as the tracks decode
+            gst::ElementFactory::make("appsink")
+                .name("audio-sink")
+                .property("sync", false)
+                .property("caps", &offline_caps(&self.config))
+                .build()
+                .map_err(|_| EditingError::AudioError("Failed to create offline audio sink".to_string()))?
+        } else if let Some(device_id) = &self.config.output_device {
             // Use the specified output device
             gst::ElementFactory::make("autoaudiosink")
                 .name("audio-sink")
@@ -882,8 +1004,10 @@ impl AudioEngine {
         self.master_volume_element = Some(volume);
         self.bus_watch_id = Some(bus_watch_id);
         
-        // Refresh the device list
-        self.refresh_devices()?;
+        // Refresh the device list; offline engines don't need audio hardware
+        if !self.config.offline {
+            self.refresh_devices()?;
+        }
         
         self.initialized = true;
         
@@ -1003,6 +1127,48 @@ impl AudioEngine {
         Ok(())
     }
     
+    /// Get the mix's playback position in seconds
+    pub fn position(&self) -> Result<f64, EditingError> {
+        let pipeline = self.pipeline.as_ref()
+            .ok_or_else(|| EditingError::AudioError("Pipeline not initialized".to_string()))?;
+        
+        Ok(pipeline.query_position::<gst::ClockTime>()
+            .map(|pos| pos.nseconds() as f64 / 1_000_000_000.0)
+            .unwrap_or(0.0))
+    }
+    
+    /// Seek every track to the specified position in seconds
+    pub fn seek(&self, position: f64) -> Result<(), EditingError> {
+        let pipeline = self.pipeline.as_ref()
+            .ok_or_else(|| EditingError::AudioError("Pipeline not initialized".to_string()))?;
+        
+        let position_ns = (position.max(0.0) * 1_000_000_000.0) as u64;
+        pipeline.seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE, position_ns.nseconds())
+            .map_err(|_| EditingError::AudioError("Failed to seek".to_string()))
+    }
+    
+    /// Clock driving playback and the base time of the current run, for other pipelines
+    /// to follow
+    ///
+    /// Waits up to `timeout` for a pending state change, since both are only settled once
+    /// the pipeline is playing. The clock is normally the output device's, so pipelines
+    /// following it keep pace with the samples actually heard.
+    pub fn playback_clock(&self, timeout: Duration) -> Result<(gst::Clock, gst::ClockTime), EditingError> {
+        let pipeline = self.pipeline.as_ref()
+            .ok_or_else(|| EditingError::AudioError("Pipeline not initialized".to_string()))?;
+        
+        let (result, state, _) = pipeline.state(gst::ClockTime::from_nseconds(timeout.as_nanos() as u64));
+        if result.is_err() || state != gst::State::Playing {
+            return Err(EditingError::AudioError(format!("Audio is not playing, current state: {:?}", state)));
+        }
+        
+        let clock = pipeline.clock()
+            .ok_or_else(|| EditingError::AudioError("Audio pipeline has no clock".to_string()))?;
+        let base_time = pipeline.base_time()
+            .ok_or_else(|| EditingError::AudioError("Audio pipeline has no base time".to_string()))?;
+        Ok((clock, base_time))
+    }
+    
     /// Refresh the list of available audio devices
     pub fn refresh_devices(&mut self) -> Result<(), EditingError> {
         // Create a device monitor
@@ -1176,45 +1342,316 @@ impl AudioEngine {
         self.config.output_device = Some(device_id.to_string());
         
         // If the engine is already initialized, we need to update the sink
-        if self.initialized {
-            if let Some(pipeline) = &self.pipeline {
-                // Get the current sink
-                let old_sink = pipeline.by_name("audio-sink").unwrap();
-                
-                // Create a new sink with the specified device
-                let new_sink = gst::ElementFactory::make("autoaudiosink")
-                    .name("audio-sink")
-                    .property("device", device_id)
-                    .build()
-                    .map_err(|_| EditingError::AudioError("Failed to create audio sink".to_string()))?;
-                
-                // Get the volume element
-                let volume = self.master_volume_element.as_ref().unwrap();
-                
-                // Unlink the volume from the old sink
-                volume.unlink(&old_sink);
-                
-                // Add the new sink to the pipeline
-                pipeline.add(&new_sink)
-                    .map_err(|_| EditingError::AudioError("Failed to add new sink to pipeline".to_string()))?;
-                
-                // Link the volume to the new sink
-                volume.link(&new_sink)
-                    .map_err(|_| EditingError::AudioError("Failed to link volume to new sink".to_string()))?;
-                
-                // Sync the new sink's state with the pipeline
-                new_sink.sync_state_with_parent()
-                    .map_err(|_| EditingError::AudioError("Failed to sync new sink state with parent".to_string()))?;
-                
-                // Remove the old sink from the pipeline
-                pipeline.remove(&old_sink)
-                    .map_err(|_| EditingError::AudioError("Failed to remove old sink from pipeline".to_string()))?;
-            }
+        if self.initialized && !self.config.offline && self.pipeline.is_some() {
+            // Create a new sink with the specified device
+            let new_sink = gst::ElementFactory::make("autoaudiosink")
+                .name("audio-sink")
+                .property("device", device_id)
+                .build()
+                .map_err(|_| EditingError::AudioError("Failed to create audio sink".to_string()))?;
+            
+            self.replace_sink(new_sink)?;
         }
         
         Ok(())
     }
     
+    /// Swap the sink after the master volume, returning the old one
+    fn replace_sink(&self, new_sink: gst::Element) -> Result<gst::Element, EditingError> {
+        let pipeline = self.pipeline.as_ref()
+            .ok_or_else(|| EditingError::AudioError("Audio engine not initialized".to_string()))?;
+        let old_sink = pipeline.by_name("audio-sink")
+            .ok_or_else(|| EditingError::AudioError("Audio sink missing".to_string()))?;
+        let volume = self.master_volume_element.as_ref().unwrap();
+        
+        // Unlink the volume from the old sink
+        volume.unlink(&old_sink);
+        
+        // Remove the old sink first, as the new one takes its name
+        pipeline.remove(&old_sink)
+            .map_err(|_| EditingError::AudioError("Failed to remove old sink from pipeline".to_string()))?;
+        let _ = old_sink.set_state(gst::State::Null);
+        
+        // Add the new sink to the pipeline
+        pipeline.add(&new_sink)
+            .map_err(|_| EditingError::AudioError("Failed to add new sink to pipeline".to_string()))?;
+        
+        // Link the volume to the new sink
+        volume.link(&new_sink)
+            .map_err(|_| EditingError::AudioError("Failed to link volume to new sink".to_string()))?;
+        
+        // Sync the new sink's state with the pipeline
+        new_sink.sync_state_with_parent()
+            .map_err(|_| EditingError::AudioError("Failed to sync new sink state with parent".to_string()))?;
+        
+        Ok(old_sink)
+    }
+    
+    /// Apply the audio device from engine settings
+    ///
+    /// Clearing the device in settings takes effect on the next start.
+    pub fn apply_settings(&mut self, settings: &EngineSettings) -> Result<(), EditingError> {
+        match &settings.audio_device {
+            Some(device) if self.config.output_device.as_ref() != Some(device) => self.set_output_device(device),
+            Some(_) => Ok(()),
+            None => {
+                self.config.output_device = None;
+                Ok(())
+            },
+        }
+    }
+    
+    /// Whether the engine renders offline instead of playing
+    pub fn is_offline(&self) -> bool {
+        self.config.offline
+    }
+    
+    /// Render the mix offline, from `start` for `duration` seconds or to the end
+    ///
+    /// Only for engines configured `offline`. `on_samples` gets interleaved float samples
+    /// at the configured rate and channel count, in order, so long mixes don't need to
+    /// fit in memory. Returns once the mix ends; the engine is stopped afterwards.
+    pub fn render_offline<F>(&mut self, start: f64, duration: Option<f64>, on_samples: F) -> Result<OfflineRender, EditingError>
+    where
+        F: FnMut(&[f32]),
+    {
+        if !self.config.offline {
+            return Err(EditingError::AudioError("Offline rendering needs an engine configured offline".to_string()));
+        }
+        self.render_mix(start, duration, on_samples)
+    }
+    
+    /// Render the mix into the appsink that is the engine's sink, see `render_offline`
+    fn render_mix<F>(&mut self, start: f64, duration: Option<f64>, mut on_samples: F) -> Result<OfflineRender, EditingError>
+    where
+        F: FnMut(&[f32]),
+    {
+        if !self.initialized {
+            self.initialize()?;
+        }
+        
+        let channels = self.config.channels.max(1);
+        let mut render = OfflineRender {
+            sample_rate: self.config.sample_rate,
+            channels,
+            ..OfflineRender::default()
+        };
+        // The mixer produces nothing without inputs
+        if self.tracks.is_empty() {
+            return Ok(render);
+        }
+        
+        let pipeline = self.pipeline.clone().unwrap();
+        let sink = pipeline.by_name("audio-sink")
+            .and_then(|sink| sink.dynamic_cast::<gst_app::AppSink>().ok())
+            .ok_or_else(|| EditingError::AudioError("The engine's sink is not an offline sink".to_string()))?;
+        
+        let started = Instant::now();
+        pipeline.set_state(gst::State::Paused)
+            .map_err(|_| EditingError::AudioError("Failed to set pipeline to paused state".to_string()))?;
+        let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(10));
+        if state_result.is_err() {
+            self.stop()?;
+            return Err(EditingError::AudioError("Failed to preroll the mix".to_string()));
+        }
+        
+        if start > 0.0 {
+            pipeline.seek_simple(
+                gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+                gst::ClockTime::from_seconds_f64(start),
+            ).map_err(|_| EditingError::AudioError(format!("Failed to seek the mix to {:.3}s", start)))?;
+        }
+        pipeline.set_state(gst::State::Playing)
+            .map_err(|_| EditingError::AudioError("Failed to set pipeline to playing state".to_string()))?;
+        
+        let wanted = duration.map(|duration| (duration.max(0.0) * render.sample_rate as f64) as u64 * channels as u64);
+        let mut rendered = 0u64;
+        let mut sum_squares = 0.0f64;
+        let mut chunk = Vec::new();
+        while wanted.is_none_or(|wanted| rendered < wanted) {
+            // None on EOS or if a track stalls
+            let Some(sample) = sink.try_pull_sample(gst::ClockTime::from_seconds(5)) else {
+                break;
+            };
+            let Some(buffer) = sample.buffer() else {
+                continue;
+            };
+            let map = buffer.map_readable()
+                .map_err(|_| EditingError::AudioError("Failed to read mixed audio".to_string()))?;
+            chunk.clear();
+            chunk.extend(
+                map.as_slice()
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            );
+            if let Some(wanted) = wanted {
+                chunk.truncate((wanted - rendered) as usize);
+            }
+            
+            rendered += chunk.len() as u64;
+            for &value in &chunk {
+                render.peak = render.peak.max(value.abs());
+                sum_squares += value as f64 * value as f64;
+            }
+            on_samples(&chunk);
+        }
+        
+        let error = pipeline.bus()
+            .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+        self.stop()?;
+        if let Some(message) = error {
+            if let gst::MessageView::Error(err) = message.view() {
+                return Err(EditingError::AudioError(format!("Offline render failed: {}", err.error())));
+            }
+        }
+        
+        render.frames = rendered / channels as u64;
+        render.rms = if rendered > 0 { (sum_squares / rendered as f64).sqrt() as f32 } else { 0.0 };
+        render.elapsed = started.elapsed();
+        info!("Rendered {:.1}s of audio offline in {:?}", render.duration(), render.elapsed);
+        Ok(render)
+    }
+    
+    /// Render some tracks, with their effects, to a 32-bit float WAV file
+    ///
+    /// Covers `range` in seconds, or the whole mix if `None`. Other tracks are muted for the
+    /// bounce and restored afterwards; a playing engine is stopped. With `replace`, the
+    /// tracks are removed and the bounced file takes their place on a new track. Engine
+    /// tracks always play from the start, so replacing needs a range from 0.
+    pub fn bounce(&mut self, range: Option<Range<f64>>, track_ids: &[&str], output: &Path, replace: bool) -> Result<Bounce, EditingError> {
+        if track_ids.is_empty() {
+            return Err(EditingError::AudioError("No tracks to bounce".to_string()));
+        }
+        if let Some(id) = track_ids.iter().find(|id| !self.tracks.contains_key(**id)) {
+            return Err(EditingError::AudioError(format!("Track '{}' not found", id)));
+        }
+        let (start, duration) = match &range {
+            Some(range) if range.end <= range.start => {
+                return Err(EditingError::AudioError(format!("Empty bounce range {:?}", range)));
+            },
+            Some(range) => (range.start.max(0.0), Some(range.end - range.start.max(0.0))),
+            None => (0.0, None),
+        };
+        if replace && start > 0.0 {
+            return Err(EditingError::AudioError("Replacing tracks with a bounce needs a range from the start".to_string()));
+        }
+        if !self.initialized {
+            self.initialize()?;
+        }
+        
+        // Mute everything else, remembering how it was
+        let mut muted = Vec::new();
+        for (id, track) in &self.tracks {
+            if !track_ids.contains(&id.as_str()) {
+                let mut guard = track.lock().unwrap();
+                muted.push((guard.muted, track.clone()));
+                guard.set_mute(true)?;
+            }
+        }
+        
+        let result = self.bounce_to_file(start, duration, output);
+        
+        for (was_muted, track) in muted {
+            track.lock().unwrap().set_mute(was_muted)?;
+        }
+        let render = result.inspect_err(|_| {
+            let _ = std::fs::remove_file(output);
+        })?;
+        info!("Bounced {} tracks to {}", track_ids.len(), output.display());
+        
+        let track_id = if replace {
+            for id in track_ids {
+                self.remove_track(id)?;
+            }
+            let id = self.bounce_track_id(track_ids);
+            self.add_track(&id, AudioSourceType::File(output.to_path_buf()))?;
+            Some(id)
+        } else {
+            None
+        };
+        
+        Ok(Bounce {
+            path: output.to_path_buf(),
+            render,
+            track_id,
+        })
+    }
+    
+    /// Render the mix to a WAV file, through an offline sink swapped in if the engine plays
+    fn bounce_to_file(&mut self, start: f64, duration: Option<f64>, output: &Path) -> Result<OfflineRender, EditingError> {
+        let mut writer = WavWriter::create(output, self.config.sample_rate, self.config.channels.max(1))
+            .map_err(|e| EditingError::AudioError(format!("Failed to create {}: {}", output.display(), e)))?;
+        let mut write_error = None;
+        
+        let device_sink = if self.config.offline {
+            None
+        } else {
+            self.stop()?;
+            let sink = gst::ElementFactory::make("appsink")
+                .name("audio-sink")
+                .property("sync", false)
+                .property("caps", &offline_caps(&self.config))
+                .build()
+                .map_err(|_| EditingError::AudioError("Failed to create offline audio sink".to_string()))?;
+            Some(self.replace_sink(sink)?)
+        };
+        
+        let render = self.render_mix(start, duration, |chunk| {
+            if write_error.is_none() {
+                write_error = writer.write(chunk).err();
+            }
+        });
+        
+        if let Some(sink) = device_sink {
+            self.replace_sink(sink)?;
+        }
+        let render = render?;
+        if let Some(e) = write_error {
+            return Err(EditingError::AudioError(format!("Failed to write {}: {}", output.display(), e)));
+        }
+        writer.finish()
+            .map_err(|e| EditingError::AudioError(format!("Failed to write {}: {}", output.display(), e)))?;
+        Ok(render)
+    }
+    
+    /// An unused track ID for a bounce of `track_ids`
+    fn bounce_track_id(&self, track_ids: &[&str]) -> String {
+        let base = format!("{}-bounce", track_ids.join("+"));
+        let mut id = base.clone();
+        let mut n = 2;
+        while self.tracks.contains_key(&id) {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        id
+    }
+    
+    /// Render the mix offline into memory, as interleaved float samples
+    pub fn mixdown(&mut self, start: f64, duration: Option<f64>) -> Result<Vec<f32>, EditingError> {
+        let mut samples = Vec::new();
+        self.render_offline(start, duration, |chunk| samples.extend_from_slice(chunk))?;
+        Ok(samples)
+    }
+    
+    /// Render the mix offline into a waveform: the highest absolute sample of every block of
+    /// `frames_per_peak` frames, across channels
+    pub fn waveform_peaks(&mut self, start: f64, duration: Option<f64>, frames_per_peak: usize) -> Result<Vec<f32>, EditingError> {
+        let block = frames_per_peak.max(1) * self.config.channels.max(1) as usize;
+        let mut peaks = Vec::new();
+        let mut filled = 0;
+        self.render_offline(start, duration, |chunk| {
+            for &value in chunk {
+                if filled == 0 {
+                    peaks.push(0.0f32);
+                }
+                let peak = peaks.last_mut().unwrap();
+                *peak = peak.max(value.abs());
+                filled = (filled + 1) % block;
+            }
+        })?;
+        Ok(peaks)
+    }
+    
     /// Get the current output device ID
     pub fn get_output_device(&self) -> Option<&str> {
         self.config.output_device.as_deref()
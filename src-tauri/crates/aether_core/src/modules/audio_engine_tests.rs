@@ -240,3 +240,61 @@ fn test_audio_playback() -> Result<()> {
     
     Ok(())
 }
+
+#[test]
+fn test_offline_engine() -> Result<()> {
+    // Playing engines can't render offline
+    let mut engine = AudioEngine::new()?;
+    assert!(!engine.is_offline());
+    assert!(engine.render_offline(0.0, None, |_| ()).is_err());
+    
+    let mut engine = AudioEngine::with_config(AudioEngineConfig {
+        sample_rate: 44100,
+        channels: 1,
+        offline: true,
+        ..AudioEngineConfig::default()
+    })?;
+    engine.initialize()?;
+    assert!(engine.is_offline());
+    
+    // No audio hardware is touched, and the sink stays in place
+    assert!(engine.devices.is_empty());
+    engine.set_output_device("hw:0")?;
+    assert_eq!(engine.get_output_device(), Some("hw:0"));
+    
+    // An empty mix renders nothing
+    let render = engine.render_offline(0.0, Some(1.0), |_| ())?;
+    assert_eq!(render.frames, 0);
+    assert_eq!(render.sample_rate, 44100);
+    assert!(engine.mixdown(0.0, None)?.is_empty());
+    
+    let render = OfflineRender {
+        frames: 441_000,
+        sample_rate: 44100,
+        channels: 1,
+        elapsed: Duration::from_millis(500),
+        ..OfflineRender::default()
+    };
+    assert_eq!(render.duration(), 10.0);
+    assert_eq!(render.speed(), 20.0);
+    
+    engine.shutdown()?;
+    
+    Ok(())
+}
+
+#[test]
+fn test_bounce_arguments() -> Result<()> {
+    let mut engine = AudioEngine::with_config(AudioEngineConfig {
+        offline: true,
+        ..AudioEngineConfig::default()
+    })?;
+    let output = std::env::temp_dir().join("aether_bounce_test.wav");
+    
+    // Bounces need at least one existing track
+    assert!(engine.bounce(None, &[], &output, false).is_err());
+    assert!(engine.bounce(None, &["missing"], &output, false).is_err());
+    assert!(!output.exists());
+    
+    Ok(())
+}
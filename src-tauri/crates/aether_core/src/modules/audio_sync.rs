@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, info};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::engine::timeline::{Clip, Timeline};
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
+/// Rate of the loudness envelope used for the coarse search, in Hz
+const ENVELOPE_RATE: u32 = 100;
+
+/// Reference audio needed for a meaningful match, in envelope samples
+const MIN_ENVELOPE_LEN: usize = ENVELOPE_RATE as usize;
+
+/// Lags this close to the best one belong to the same peak, in envelope samples
+const PEAK_EXCLUSION: isize = 10;
+
+/// Length of reference audio used to refine the match to sample precision, in seconds
+const REFINE_SECONDS: usize = 10;
+
+/// Property linking clips that belong together
+pub const LINK_GROUP_PROPERTY: &str = "link_group";
+
+/// Options for audio based sync
+#[derive(Debug, Clone)]
+pub struct AudioSyncOptions {
+    /// Rate audio is decoded at for analysis
+    pub sample_rate: u32,
+    /// Length of the video clip's audio compared against the recorder file, in seconds
+    pub analysis_duration: f64,
+    /// How far the recorder audio may be from its current timeline position, in seconds
+    pub max_offset: f64,
+    /// Matches below this confidence are rejected
+    pub min_confidence: f64,
+}
+
+impl Default for AudioSyncOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 8000,
+            analysis_duration: 60.0,
+            max_offset: 30.0,
+            min_confidence: 0.2,
+        }
+    }
+}
+
+/// Where two recordings line up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncMatch {
+    /// Seconds to add to a time in the reference to get the same moment in the other recording
+    pub offset: f64,
+    /// 0-1, the correlation at the match scaled by how clearly it beats the next best match
+    pub confidence: f64,
+}
+
+/// Result of syncing a video clip with an external audio clip
+#[derive(Debug, Clone)]
+pub struct AudioSyncResult {
+    /// Seconds to add to a time in the video source to get the same moment in the audio source
+    pub offset: f64,
+    pub confidence: f64,
+    /// Link group shared by the two clips
+    pub link_group: String,
+    /// The audio clip as placed on the timeline
+    pub audio_clip: Clip,
+}
+
+/// Line up an external audio recording with a video clip's scratch audio
+///
+/// The audio clip is moved and trimmed so it plays in sync under the video, and both clips
+/// are tagged with a shared link group plus the offset and confidence. The search covers
+/// `max_offset` seconds either side of the audio clip's current position, so drop it
+/// roughly in place first. Nothing is changed if the match is below `min_confidence`.
+pub fn sync_clips_by_audio(
+    timeline: &mut Timeline,
+    video_clip: &str,
+    audio_clip: &str,
+    options: &AudioSyncOptions,
+) -> Result<AudioSyncResult> {
+    let (video_track, video) = find_clip(timeline, video_clip)?;
+    let (audio_track, audio) = find_clip(timeline, audio_clip)?;
+    let video_path = video.source_path.clone().ok_or_else(|| anyhow!("Clip {} has no source", video_clip))?;
+    let audio_path = audio.source_path.clone().ok_or_else(|| anyhow!("Clip {} has no source", audio_clip))?;
+
+    // Offset implied by where the clips currently sit
+    let expected = (audio.in_point() - audio.start_time) - (video.in_point() - video.start_time);
+    let window = video.duration.min(options.analysis_duration);
+    let search_start = (video.in_point() + expected - options.max_offset).max(0.0);
+
+    let reference = load_mono(Path::new(&video_path), video.in_point(), window, options.sample_rate)?;
+    let other = load_mono(Path::new(&audio_path), search_start, window + 2.0 * options.max_offset, options.sample_rate)?;
+
+    let found = find_offset(&reference, &other, options.sample_rate)
+        .ok_or_else(|| anyhow!("Not enough audio in {} and {} to sync", video_clip, audio_clip))?;
+    let offset = search_start + found.offset - video.in_point();
+    debug!("Audio sync {} <-> {}: offset {:.4}s, confidence {:.2}", video_clip, audio_clip, offset, found.confidence);
+
+    if found.confidence < options.min_confidence {
+        return Err(anyhow!(
+            "No reliable audio match between {} and {} (confidence {:.2})",
+            video_clip, audio_clip, found.confidence
+        ));
+    }
+
+    // Place the audio under the video; a recorder that started late begins partway in
+    let mut synced = audio.clone();
+    let audio_in = video.in_point() + offset;
+    if audio_in >= 0.0 {
+        synced.start_time = video.start_time;
+        synced.set_in_point(audio_in);
+        synced.duration = video.duration;
+    } else {
+        synced.start_time = video.start_time - audio_in;
+        synced.set_in_point(0.0);
+        synced.duration = (video.duration + audio_in).max(0.0);
+    }
+
+    let link_group = format!("sync_{}_{}", video.id, audio.id);
+    tag_clip(&mut synced, &link_group, offset, found.confidence);
+
+    // Swap in the moved clip, putting the original back if it no longer fits
+    let track = timeline.get_track_mut(&audio_track)?;
+    let original = track.remove_clip(audio_clip)?;
+    if let Err(e) = track.add_clip(synced.clone()) {
+        track.clips.push(original);
+        return Err(anyhow!("Synced audio clip doesn't fit on track {}: {}", audio_track, e));
+    }
+
+    if let Some(clip) = timeline.get_track_mut(&video_track)?.clips.iter_mut().find(|clip| clip.id == video_clip) {
+        tag_clip(clip, &link_group, offset, found.confidence);
+    }
+
+    info!("Synced {} with {} at {:.4}s (confidence {:.2})", audio_clip, video_clip, offset, found.confidence);
+
+    Ok(AudioSyncResult {
+        offset,
+        confidence: found.confidence,
+        link_group,
+        audio_clip: synced,
+    })
+}
+
+fn tag_clip(clip: &mut Clip, link_group: &str, offset: f64, confidence: f64) {
+    clip.properties.insert(LINK_GROUP_PROPERTY.to_string(), link_group.to_string());
+    clip.properties.insert("sync.offset".to_string(), offset.to_string());
+    clip.properties.insert("sync.confidence".to_string(), confidence.to_string());
+}
+
+/// Track ID and a copy of a clip
+fn find_clip(timeline: &Timeline, clip_id: &str) -> Result<(String, Clip)> {
+    timeline
+        .tracks()
+        .values()
+        .find_map(|track| {
+            track.clips.iter()
+                .find(|clip| clip.id == clip_id)
+                .map(|clip| (track.id.clone(), clip.clone()))
+        })
+        .ok_or_else(|| anyhow!("Clip not found: {}", clip_id))
+}
+
+/// Find where `reference` occurs in `other`
+///
+/// A loudness onset envelope is correlated first, which is robust to the different mics
+/// and gain of scratch and recorder audio, then the best match is refined on the samples.
+/// At least half of the reference has to overlap `other`.
+pub fn find_offset(reference: &[f32], other: &[f32], sample_rate: u32) -> Option<SyncMatch> {
+    let block = (sample_rate / ENVELOPE_RATE).max(1) as usize;
+    let a = onset_envelope(reference, block);
+    let b = onset_envelope(other, block);
+    if a.len() < MIN_ENVELOPE_LEN || b.len() < a.len() / 2 {
+        return None;
+    }
+
+    let min_overlap = a.len() / 2;
+    let lags = -((a.len() - min_overlap) as isize)..=(b.len() as isize - min_overlap as isize);
+    let scores = correlate(&a, &b, lags, min_overlap);
+    let &(best_lag, best) = scores.iter().max_by(|x, y| x.1.total_cmp(&y.1))?;
+    if best <= 0.0 {
+        return None;
+    }
+
+    let runner_up = scores.iter()
+        .filter(|(lag, _)| (lag - best_lag).abs() > PEAK_EXCLUSION)
+        .map(|(_, score)| *score)
+        .fold(0.0, f64::max);
+    let prominence = (1.0 - runner_up / best).clamp(0.0, 1.0);
+    let confidence = best.clamp(0.0, 1.0) * prominence;
+
+    // Refine within a couple of envelope blocks of the coarse match
+    let coarse = best_lag * block as isize;
+    let span = 2 * block as isize;
+    let window = &reference[..reference.len().min(sample_rate as usize * REFINE_SECONDS)];
+    let lag = correlate(window, other, (coarse - span)..=(coarse + span), window.len() / 2)
+        .into_iter()
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+        .map(|(lag, _)| lag)
+        .unwrap_or(coarse);
+
+    Some(SyncMatch {
+        offset: lag as f64 / sample_rate as f64,
+        confidence,
+    })
+}
+
+/// Rises in loudness per block, so claps and speech onsets dominate over steady noise
+fn onset_envelope(samples: &[f32], block: usize) -> Vec<f32> {
+    let energy: Vec<f32> = samples
+        .chunks(block)
+        .map(|chunk| (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt())
+        .collect();
+
+    energy.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)).collect()
+}
+
+/// Normalized cross-correlation for each lag, where `other[i + lag]` lines up with `reference[i]`
+fn correlate(reference: &[f32], other: &[f32], lags: RangeInclusive<isize>, min_overlap: usize) -> Vec<(isize, f64)> {
+    lags.filter_map(|lag| {
+        let start = (-lag).max(0) as usize;
+        let end = reference.len().min((other.len() as isize - lag).max(0) as usize);
+        if end <= start || end - start < min_overlap.max(1) {
+            return None;
+        }
+
+        let (mut dot, mut energy_a, mut energy_b) = (0.0f64, 0.0f64, 0.0f64);
+        for i in start..end {
+            let x = reference[i] as f64;
+            let y = other[(i as isize + lag) as usize] as f64;
+            dot += x * y;
+            energy_a += x * x;
+            energy_b += y * y;
+        }
+
+        let norm = (energy_a * energy_b).sqrt();
+        Some((lag, if norm > 0.0 { dot / norm } else { 0.0 }))
+    })
+    .collect()
+}
+
+/// Decode part of a file's audio as mono float samples
+fn load_mono(path: &Path, start: f64, duration: f64, sample_rate: u32) -> Result<Vec<f32>> {
+    let wanted = (duration.max(0.0) * sample_rate as f64) as usize;
+    let mut samples = Vec::with_capacity(wanted);
+    decode_mono(path, start, Some(duration), sample_rate, |chunk| samples.extend_from_slice(chunk))?;
+    Ok(samples)
+}
+
+/// Stream a file's audio as mono float samples, from `start` for `duration` seconds or to the end
+///
+/// `on_samples` gets the samples in decoding order, so long files don't need to fit in memory.
+pub(crate) fn decode_mono<F>(path: &Path, start: f64, duration: Option<f64>, sample_rate: u32, mut on_samples: F) -> Result<()>
+where
+    F: FnMut(&[f32]),
+{
+    let builder = PipelineBuilder::new("audio-decode")?;
+    let source = builder.chain(&[
+        ElementSpec::file_source(path)?,
+        ElementSpec::new("decodebin"),
+    ])?;
+    let audio = builder.chain(&[
+        ElementSpec::new("audioconvert"),
+        ElementSpec::new("audioresample"),
+        ElementSpec::capsfilter(
+            gst::Caps::builder("audio/x-raw")
+                .field("format", "F32LE")
+                .field("layout", "interleaved")
+                .field("channels", 1i32)
+                .field("rate", sample_rate as i32)
+                .build(),
+        ),
+        ElementSpec::new("appsink").property("sync", false),
+    ])?;
+    builder.link_dynamic(&source[1], StreamKind::Audio, &audio[0]);
+    let pipeline = builder.build();
+
+    let sink = audio[3]
+        .clone()
+        .dynamic_cast::<gst_app::AppSink>()
+        .map_err(|_| anyhow!("Failed to cast to AppSink"))?;
+
+    pipeline.set_state(gst::State::Paused)?;
+    let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(10));
+    if state_result.is_err() {
+        let _ = pipeline.set_state(gst::State::Null);
+        return Err(anyhow!("Failed to decode audio from {:?}", path));
+    }
+
+    if start > 0.0 {
+        pipeline.seek_simple(
+            gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+            gst::ClockTime::from_seconds_f64(start),
+        )?;
+    }
+    pipeline.set_state(gst::State::Playing)?;
+
+    let wanted = duration.map(|duration| (duration.max(0.0) * sample_rate as f64) as usize);
+    let mut decoded = 0;
+    let mut chunk = Vec::new();
+    while wanted.map_or(true, |wanted| decoded < wanted) {
+        // None on EOS or if decoding stalls
+        let Some(sample) = sink.try_pull_sample(gst::ClockTime::from_seconds(5)) else {
+            break;
+        };
+        if let Some(buffer) = sample.buffer() {
+            let map = buffer.map_readable()?;
+            chunk.clear();
+            chunk.extend(
+                map.as_slice()
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            );
+            if let Some(wanted) = wanted {
+                chunk.truncate(wanted - decoded);
+            }
+            decoded += chunk.len();
+            on_samples(&chunk);
+        }
+    }
+
+    let error = pipeline
+        .bus()
+        .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+    pipeline.set_state(gst::State::Null)?;
+    if let Some(message) = error {
+        if let gst::MessageView::Error(err) = message.view() {
+            return Err(anyhow!("Failed to decode audio from {:?}: {}", path, err.error()));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use super::super::audio_sync::find_offset;
+    use anyhow::{anyhow, Result};
+
+    const RATE: u32 = 8000;
+
+    /// Irregular clicks over low noise, like speech onsets over room tone
+    fn scratch_audio(seconds: f64, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32
+        };
+
+        let len = (seconds * RATE as f64) as usize;
+        let mut samples: Vec<f32> = (0..len).map(|_| (next() - 0.5) * 0.02).collect();
+        let mut position = 0;
+        while position < len {
+            position += (RATE as f32 * (0.1 + next() * 0.5)) as usize;
+            for i in position..(position + 400).min(len) {
+                samples[i] += (next() - 0.5) * (1.0 - (i - position) as f32 / 400.0);
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_find_offset_locates_delayed_copy() -> Result<()> {
+        let reference = scratch_audio(6.0, 7);
+        let delay = 6011;
+
+        // Recorder started earlier and at a different gain
+        let mut other: Vec<f32> = scratch_audio(delay as f64 / RATE as f64, 99).iter().map(|s| s * 0.1).collect();
+        other.extend(reference.iter().map(|s| s * 0.4));
+        other.extend(scratch_audio(1.0, 3).iter().map(|s| s * 0.1));
+
+        let found = find_offset(&reference, &other, RATE).ok_or_else(|| anyhow!("No match"))?;
+        assert!((found.offset - delay as f64 / RATE as f64).abs() < 2.0 / RATE as f64, "offset {}", found.offset);
+        assert!(found.confidence > 0.5, "confidence {}", found.confidence);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_offset_unrelated_audio_has_low_confidence() -> Result<()> {
+        let reference = scratch_audio(6.0, 11);
+        let other = scratch_audio(10.0, 12345);
+
+        let confidence = find_offset(&reference, &other, RATE).map(|found| found.confidence).unwrap_or(0.0);
+        assert!(confidence < 0.3, "confidence {}", confidence);
+
+        assert!(find_offset(&reference[..100], &other, RATE).is_none());
+        Ok(())
+    }
+}
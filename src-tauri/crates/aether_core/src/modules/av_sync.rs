@@ -0,0 +1,140 @@
+//! Video preview slaved to the audio engine's clock
+//!
+//! The audio engine and the video preview run separate pipelines, each picking its own
+//! clock, so over long playback the picture drifts away from the sound. `AvSync` drives
+//! both: the audio starts first and the preview joins its clock and base time, so frames
+//! are shown by the output device's clock. Pausing, resuming and seeking start a new
+//! audio run, which the preview joins again. `correct_drift` realigns the picture if it
+//! falls behind anyway, e.g. after a decoder stall.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info};
+use gst::prelude::*;
+
+use crate::engine::editing::{EditingError, MasterClock, PreviewEngine};
+use super::audio_engine::AudioEngine;
+
+/// How long the preview waits for the audio to start playing
+pub const START_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Drift between picture and sound `correct_drift` leaves alone, in nanoseconds; about
+/// a frame at 25 fps
+pub const MAX_DRIFT: i64 = 40_000_000;
+
+/// Plays the video preview in sync with the audio engine
+pub struct AvSync {
+    audio: Arc<Mutex<AudioEngine>>,
+    preview: Arc<Mutex<PreviewEngine>>,
+    playing: bool,
+}
+
+impl AvSync {
+    pub fn new(audio: Arc<Mutex<AudioEngine>>, preview: Arc<Mutex<PreviewEngine>>) -> Self {
+        Self { audio, preview, playing: false }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Start the audio, then the preview on the audio's clock
+    pub fn play(&mut self) -> Result<(), EditingError> {
+        let (clock, base_time) = {
+            let mut audio = self.audio.lock().unwrap();
+            audio.play()?;
+            match audio.playback_clock(START_TIMEOUT) {
+                Ok(clock) => clock,
+                Err(e) => {
+                    let _ = audio.pause();
+                    return Err(e);
+                },
+            }
+        };
+
+        let result = {
+            let mut preview = self.preview.lock().unwrap();
+            preview.set_master_clock(Some(MasterClock { clock, base_time }));
+            preview.play()
+        };
+        if let Err(e) = result {
+            let _ = self.audio.lock().unwrap().pause();
+            return Err(e);
+        }
+
+        self.playing = true;
+        debug!("Preview joined the audio clock at base time {}", base_time);
+        Ok(())
+    }
+
+    pub fn pause(&mut self) -> Result<(), EditingError> {
+        self.playing = false;
+        // Picture first, so it never runs on past the sound
+        self.preview.lock().unwrap().pause()?;
+        self.audio.lock().unwrap().pause()
+    }
+
+    /// Stop both and give the preview its own clock back
+    pub fn stop(&mut self) -> Result<(), EditingError> {
+        self.playing = false;
+        let mut preview = self.preview.lock().unwrap();
+        preview.stop()?;
+        preview.set_master_clock(None);
+        self.audio.lock().unwrap().stop()
+    }
+
+    /// Seek both to `position` in nanoseconds, playing on if they were playing
+    pub fn seek(&mut self, position: i64) -> Result<(), EditingError> {
+        let was_playing = self.playing;
+        if was_playing {
+            self.pause()?;
+        }
+
+        self.audio.lock().unwrap().seek(position as f64 / 1_000_000_000.0)?;
+        self.preview.lock().unwrap().seek(position)?;
+
+        if was_playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// How far the picture is ahead of the sound in nanoseconds, negative if behind
+    pub fn drift(&self) -> Result<i64, EditingError> {
+        let audio = self.audio_position()?;
+        let video = self.preview.lock().unwrap().get_position()?;
+        Ok(video - audio)
+    }
+
+    /// Realign the picture with the sound if it drifted more than `MAX_DRIFT`
+    ///
+    /// Returns the drift corrected, if any. Meant to be called now and then during
+    /// playback. Only the preview seeks, so the sound plays on without a gap.
+    pub fn correct_drift(&mut self) -> Result<Option<i64>, EditingError> {
+        if !self.playing {
+            return Ok(None);
+        }
+        let drift = self.drift()?;
+        if drift.abs() <= MAX_DRIFT {
+            return Ok(None);
+        }
+
+        info!("Preview drifted {} ms from the audio, realigning", drift / 1_000_000);
+        let (clock, _) = self.audio.lock().unwrap().playback_clock(START_TIMEOUT)?;
+        let position = self.audio_position()?;
+        let now = clock.time()
+            .ok_or_else(|| EditingError::PreviewError("Audio clock has no time".to_string()))?;
+
+        let mut preview = self.preview.lock().unwrap();
+        preview.pause()?;
+        preview.seek(position)?;
+        // The preview's new run starts at `position`, where the sound is now
+        preview.set_master_clock(Some(MasterClock { clock, base_time: now }));
+        preview.play()?;
+        Ok(Some(drift))
+    }
+
+    fn audio_position(&self) -> Result<i64, EditingError> {
+        Ok((self.audio.lock().unwrap().position()? * 1_000_000_000.0) as i64)
+    }
+}
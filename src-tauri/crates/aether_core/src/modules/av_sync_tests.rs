@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod tests {
+    use super::super::audio_engine::AudioEngine;
+    use super::super::av_sync::AvSync;
+    use crate::engine::editing::{EditingError, PreviewEngine};
+    use std::sync::{Arc, Mutex};
+    use anyhow::Result;
+
+    #[test]
+    fn test_sync_needs_running_pipelines() -> Result<()> {
+        let audio = Arc::new(Mutex::new(AudioEngine::new()?));
+        let preview = Arc::new(Mutex::new(PreviewEngine::new()?));
+        let mut sync = AvSync::new(audio, preview.clone());
+
+        // Nothing to realign while stopped
+        assert_eq!(sync.correct_drift()?, None);
+        assert!(matches!(sync.seek(1_000_000_000), Err(EditingError::AudioError(_))));
+        assert!(!sync.is_playing());
+        assert!(preview.lock().unwrap().master_clock().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, Result};
+use ffmpeg_next as ffmpeg;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+use tracing::{debug, info, warn};
+
+/// Policy shared by subsystems that aren't given one explicitly
+static GLOBAL_POLICY: Lazy<RwLock<BackendPolicy>> = Lazy::new(|| RwLock::new(BackendPolicy::from_env()));
+
+/// Media framework used to do the work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Backend {
+    GStreamer,
+    FFmpeg,
+}
+
+impl Backend {
+    /// The backend to fall back to
+    pub fn other(self) -> Self {
+        match self {
+            Backend::GStreamer => Backend::FFmpeg,
+            Backend::FFmpeg => Backend::GStreamer,
+        }
+    }
+
+    /// Whether the backend's libraries load
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::GStreamer => gst::init().is_ok(),
+            Backend::FFmpeg => ffmpeg::init().is_ok(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "gstreamer" | "gst" => Some(Backend::GStreamer),
+            "ffmpeg" => Some(Backend::FFmpeg),
+            _ => None,
+        }
+    }
+}
+
+/// Part of the application that can run on either backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Subsystem {
+    Thumbnailing,
+    Conversion,
+    Export,
+    Preview,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Thumbnailing,
+        Subsystem::Conversion,
+        Subsystem::Export,
+        Subsystem::Preview,
+    ];
+
+    /// Backend the subsystem was built around and uses unless configured otherwise
+    pub fn default_backend(self) -> Backend {
+        match self {
+            Subsystem::Export => Backend::FFmpeg,
+            Subsystem::Thumbnailing | Subsystem::Conversion | Subsystem::Preview => Backend::GStreamer,
+        }
+    }
+
+    /// Name used in environment variables, e.g. `AETHER_EXPORT_BACKEND`
+    pub fn env_name(self) -> &'static str {
+        match self {
+            Subsystem::Thumbnailing => "THUMBNAILING",
+            Subsystem::Conversion => "CONVERSION",
+            Subsystem::Export => "EXPORT",
+            Subsystem::Preview => "PREVIEW",
+        }
+    }
+}
+
+/// Backend choice for one subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendPreference {
+    pub backend: Backend,
+    /// Try the other backend if the preferred one is missing or fails
+    pub allow_fallback: bool,
+}
+
+impl BackendPreference {
+    /// Use `backend` and never fall back, for when the other backend is known to be broken
+    pub fn forced(backend: Backend) -> Self {
+        Self {
+            backend,
+            allow_fallback: false,
+        }
+    }
+}
+
+/// Which backend each subsystem uses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendPolicy {
+    /// Per-subsystem choices; subsystems without one use their default backend
+    pub overrides: HashMap<Subsystem, BackendPreference>,
+    /// Whether subsystems without an override may fall back
+    pub allow_fallback: bool,
+}
+
+impl Default for BackendPolicy {
+    fn default() -> Self {
+        Self {
+            overrides: HashMap::new(),
+            allow_fallback: true,
+        }
+    }
+}
+
+impl BackendPolicy {
+    /// Default policy adjusted by environment variables
+    ///
+    /// `AETHER_BACKEND=ffmpeg` forces a backend everywhere, `AETHER_<SUBSYSTEM>_BACKEND`
+    /// forces it for one subsystem and `AETHER_BACKEND_FALLBACK=0` disables fallback.
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+
+        if let Ok(value) = std::env::var("AETHER_BACKEND_FALLBACK") {
+            policy.allow_fallback = !matches!(value.trim(), "0" | "false" | "no");
+        }
+
+        if let Some(backend) = std::env::var("AETHER_BACKEND").ok().and_then(|v| Backend::from_name(&v)) {
+            policy.force(backend);
+        }
+
+        for subsystem in Subsystem::ALL {
+            let variable = format!("AETHER_{}_BACKEND", subsystem.env_name());
+            if let Ok(value) = std::env::var(&variable) {
+                match Backend::from_name(&value) {
+                    Some(backend) => policy.set_preference(subsystem, Some(BackendPreference::forced(backend))),
+                    None => warn!("Ignoring {}={}: unknown backend", variable, value),
+                }
+            }
+        }
+
+        policy
+    }
+
+    /// Load a policy from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read backend policy {:?}: {}", path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow!("Invalid backend policy {:?}: {}", path, e))
+    }
+
+    /// Save the policy as JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|e| anyhow!("Failed to write backend policy {:?}: {}", path, e))
+    }
+
+    /// Backend choice for a subsystem
+    pub fn preference(&self, subsystem: Subsystem) -> BackendPreference {
+        self.overrides.get(&subsystem).copied().unwrap_or(BackendPreference {
+            backend: subsystem.default_backend(),
+            allow_fallback: self.allow_fallback,
+        })
+    }
+
+    /// Set or clear (`None`) the choice for a subsystem
+    pub fn set_preference(&mut self, subsystem: Subsystem, preference: Option<BackendPreference>) {
+        match preference {
+            Some(preference) => self.overrides.insert(subsystem, preference),
+            None => self.overrides.remove(&subsystem),
+        };
+    }
+
+    /// Force one backend for every subsystem
+    pub fn force(&mut self, backend: Backend) {
+        for subsystem in Subsystem::ALL {
+            self.overrides.insert(subsystem, BackendPreference::forced(backend));
+        }
+    }
+
+    /// Backends to try for a subsystem, in order
+    pub fn candidates(&self, subsystem: Subsystem) -> Vec<Backend> {
+        let preference = self.preference(subsystem);
+        if preference.allow_fallback {
+            vec![preference.backend, preference.backend.other()]
+        } else {
+            vec![preference.backend]
+        }
+    }
+
+    /// First available backend for a subsystem
+    ///
+    /// For subsystems with separate implementations per backend, such as preview
+    /// (see `create_timeline_backend`), to decide which one to create.
+    pub fn select(&self, subsystem: Subsystem) -> Result<Backend> {
+        self.candidates(subsystem)
+            .into_iter()
+            .find(|backend| backend.is_available())
+            .ok_or_else(|| anyhow!("No backend available for {:?}", subsystem))
+    }
+
+    /// Run `operation` with each candidate backend until one succeeds
+    ///
+    /// Returns the last error if every backend failed.
+    pub fn run<T, F>(&self, subsystem: Subsystem, mut operation: F) -> Result<T>
+    where
+        F: FnMut(Backend) -> Result<T>,
+    {
+        let mut last_error = None;
+
+        for backend in self.candidates(subsystem) {
+            if !backend.is_available() {
+                warn!("{:?} backend is not available for {:?}", backend, subsystem);
+                last_error = Some(anyhow!("{:?} backend is not available", backend));
+                continue;
+            }
+
+            debug!("Running {:?} with the {:?} backend", subsystem, backend);
+            match operation(backend) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    warn!("{:?} failed with the {:?} backend: {}", subsystem, backend, e);
+                    last_error = Some(e);
+                },
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("No backend configured for {:?}", subsystem)))
+    }
+}
+
+/// The global backend policy
+pub fn global_policy() -> BackendPolicy {
+    GLOBAL_POLICY.read().unwrap().clone()
+}
+
+/// Replace the global backend policy
+///
+/// Affects components created afterwards; existing ones keep the policy they were built with.
+pub fn set_global_policy(policy: BackendPolicy) {
+    info!("Backend policy updated: {:?}", policy);
+    *GLOBAL_POLICY.write().unwrap() = policy;
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use super::super::backend_policy::{Backend, BackendPolicy, BackendPreference, Subsystem};
+    use anyhow::Result;
+
+    #[test]
+    fn test_candidates_follow_preferences() -> Result<()> {
+        let mut policy = BackendPolicy::default();
+        assert_eq!(policy.candidates(Subsystem::Export), vec![Backend::FFmpeg, Backend::GStreamer]);
+        assert_eq!(policy.candidates(Subsystem::Thumbnailing), vec![Backend::GStreamer, Backend::FFmpeg]);
+
+        policy.set_preference(Subsystem::Thumbnailing, Some(BackendPreference::forced(Backend::FFmpeg)));
+        assert_eq!(policy.candidates(Subsystem::Thumbnailing), vec![Backend::FFmpeg]);
+
+        policy.allow_fallback = false;
+        assert_eq!(policy.candidates(Subsystem::Conversion), vec![Backend::GStreamer]);
+
+        policy.set_preference(Subsystem::Thumbnailing, None);
+        policy.force(Backend::FFmpeg);
+        for subsystem in Subsystem::ALL {
+            assert_eq!(policy.candidates(subsystem), vec![Backend::FFmpeg]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_round_trips_through_json() -> Result<()> {
+        let mut policy = BackendPolicy::default();
+        policy.set_preference(Subsystem::Preview, Some(BackendPreference::forced(Backend::FFmpeg)));
+
+        let path = std::env::temp_dir().join(format!("aether-backend-policy-{}.json", std::process::id()));
+        policy.save(&path)?;
+        let loaded = BackendPolicy::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(loaded, policy);
+
+        // Missing fields fall back to defaults
+        let partial: BackendPolicy = serde_json::from_str("{\"allow_fallback\": false}")?;
+        assert!(partial.overrides.is_empty());
+        assert!(!partial.allow_fallback);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+//! Camera card structures and spanned clips
+//!
+//! Cameras split long recordings into several files, at the FAT32 size limit or when a
+//! card fills up, and record how they join in the card's index. `scan_camera_card`
+//! recognizes AVCHD cards (`PRIVATE/AVCHD`) and XAVC/XDCAM cards (`XDROOT`), reads that
+//! index and returns each recording as one `CameraClip` of its segments in order, with
+//! the timecode running on across them. `MediaLibrary::import_camera_card` adds them as
+//! single assets.
+//!
+//! AVCHD joins segments in its playlists: a play item with a seamless connection
+//! continues the recording of the one before. XDROOT cards list the clips of a spanned
+//! recording in an edit list (`Edit/*.SMI`), and keep each clip's start timecode in its
+//! metadata file (`Clip/*M01.XML`), drop-frame when the card records 29.97 or 59.94 with
+//! it. AVCHD keeps timecode in the video stream itself, so its clips have none here.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::engine::frame_rate::FrameRate;
+
+/// Ticks per second of AVCHD playlist times
+const AVCHD_CLOCK: f64 = 45000.0;
+
+/// Connection of a play item continuing the previous one without a break
+const SEAMLESS_CONNECTIONS: [u8; 2] = [5, 6];
+
+/// Card layouts that are recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraCardFormat {
+    /// `PRIVATE/AVCHD/BDMV` with MPEG transport stream clips
+    Avchd,
+    /// `XDROOT` with MXF clips
+    Xavc,
+}
+
+/// SMPTE timecode, counted in frames from midnight
+///
+/// At 29.97 and 59.94 the frames run slower than the labels count, so drop-frame
+/// timecode skips the first labels of each minute except every tenth to stay on the
+/// clock; nothing else is dropped. Display writes `;` before the frames of drop-frame
+/// timecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timecode {
+    pub frame: u64,
+    /// Frames per second, rounded to whole frames
+    pub fps: u32,
+    /// Frames run at 1000/1001 of `fps`, as on NTSC video
+    #[serde(default)]
+    pub ntsc: bool,
+    /// Labels skip to stay on the clock; only at 29.97 and 59.94
+    #[serde(default)]
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    /// Non-drop-frame timecode at a whole frame rate
+    pub fn new(hours: u64, minutes: u64, seconds: u64, frames: u64, fps: u32) -> Self {
+        Self::at_rate(hours, minutes, seconds, frames, FrameRate::whole(fps), false)
+    }
+
+    /// Timecode at `rate`, drop-frame when asked for and the rate has it
+    pub fn at_rate(hours: u64, minutes: u64, seconds: u64, frames: u64, rate: FrameRate, drop_frame: bool) -> Self {
+        let fps = rate.timebase();
+        let drop_frame = drop_frame && rate.supports_drop_frame();
+        let mut frame = ((hours * 60 + minutes) * 60 + seconds) * fps as u64 + frames;
+        if drop_frame {
+            let total_minutes = hours * 60 + minutes;
+            frame -= dropped_per_minute(fps) * (total_minutes - total_minutes / 10);
+        }
+        Self { frame, fps, ntsc: rate.is_ntsc(), drop_frame }
+    }
+
+    /// Timecode `frame` frames after midnight at `rate`
+    pub fn from_frame(frame: u64, rate: FrameRate, drop_frame: bool) -> Self {
+        Self {
+            frame,
+            fps: rate.timebase(),
+            ntsc: rate.is_ntsc(),
+            drop_frame: drop_frame && rate.supports_drop_frame(),
+        }
+    }
+
+    /// Parse `HH:MM:SS:FF`, also with `;` or `.` before the frames
+    pub fn parse(text: &str, fps: u32) -> Result<Self> {
+        Self::parse_at(text, FrameRate::whole(fps))
+    }
+
+    /// Parse timecode at `rate`; at 29.97 and 59.94, `;` or `.` before the frames marks
+    /// drop-frame timecode, and the labels it skips are refused
+    pub fn parse_at(text: &str, rate: FrameRate) -> Result<Self> {
+        let fields: Vec<u64> = text
+            .split([':', ';', '.'])
+            .map(|field| field.trim().parse::<u64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow!("Invalid timecode: {}", text))?;
+        let drop_frame = rate.supports_drop_frame() && text.rfind([';', '.']).is_some_and(|i| i > text.rfind(':').unwrap_or(0));
+        let fps = rate.timebase() as u64;
+        match fields[..] {
+            [hours, minutes, seconds, frames] if minutes < 60 && seconds < 60 && frames < fps => {
+                if drop_frame && seconds == 0 && minutes % 10 != 0 && frames < dropped_per_minute(fps as u32) {
+                    return Err(anyhow!("Invalid timecode: {} is dropped", text));
+                }
+                Ok(Self::at_rate(hours, minutes, seconds, frames, rate, drop_frame))
+            },
+            _ => Err(anyhow!("Invalid timecode: {}", text)),
+        }
+    }
+
+    /// Frame rate the frames run at
+    pub fn rate(&self) -> FrameRate {
+        if self.ntsc {
+            FrameRate::ntsc(self.fps)
+        } else {
+            FrameRate::whole(self.fps)
+        }
+    }
+
+    /// Timecode `seconds` later, to the frame
+    pub fn offset(&self, seconds: f64) -> Self {
+        Self {
+            frame: self.frame + self.rate().seconds_to_frames(seconds),
+            ..*self
+        }
+    }
+
+    /// Seconds since midnight
+    pub fn seconds(&self) -> f64 {
+        self.rate().frames_to_seconds(self.frame)
+    }
+
+    /// Hours, minutes, seconds and frames of the label
+    pub fn fields(&self) -> (u64, u64, u64, u64) {
+        let fps = self.fps.max(1) as u64;
+        let mut frame = self.frame;
+        if self.drop_frame {
+            // Put the skipped labels back to count as non-drop-frame
+            let dropped = dropped_per_minute(self.fps);
+            let per_minute = fps * 60 - dropped;
+            let per_ten_minutes = per_minute * 10 + dropped;
+            let (tens, rest) = (frame / per_ten_minutes, frame % per_ten_minutes);
+            frame += 9 * dropped * tens;
+            if rest > dropped {
+                frame += dropped * ((rest - dropped) / per_minute);
+            }
+        }
+        let seconds = frame / fps;
+        (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60, frame % fps)
+    }
+}
+
+/// Labels drop-frame timecode skips at the start of a minute: 2 at 29.97, 4 at 59.94
+fn dropped_per_minute(fps: u32) -> u64 {
+    (fps as u64).div_ceil(15)
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, minutes, seconds, frames) = self.fields();
+        let separator = if self.drop_frame { ';' } else { ':' };
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", hours, minutes, seconds, separator, frames)
+    }
+}
+
+/// One file of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipSegment {
+    pub path: PathBuf,
+    /// Length in seconds, as the card's index records it
+    pub duration: f64,
+}
+
+/// A recording, in one file or spanned across several
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraClip {
+    /// Name of the first segment, without extension
+    pub name: String,
+    pub format: CameraCardFormat,
+    /// Files in recording order
+    pub segments: Vec<ClipSegment>,
+    /// Timecode of the first frame, when the card records one
+    pub start_timecode: Option<Timecode>,
+}
+
+impl CameraClip {
+    pub fn is_spanned(&self) -> bool {
+        self.segments.len() > 1
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+
+    /// Segment playing `time` seconds into the recording and the time into it
+    ///
+    /// Times past the end fall in the last segment.
+    pub fn locate(&self, time: f64) -> Option<(&ClipSegment, f64)> {
+        locate_segment(&self.segments, time)
+    }
+
+    /// Timecode `time` seconds into the recording
+    pub fn timecode_at(&self, time: f64) -> Option<Timecode> {
+        self.start_timecode.map(|start| start.offset(time))
+    }
+}
+
+/// Segment of `segments` playing `time` seconds into them, and the time into it
+pub fn locate_segment(segments: &[ClipSegment], time: f64) -> Option<(&ClipSegment, f64)> {
+    let mut start = 0.0;
+    for (index, segment) in segments.iter().enumerate() {
+        if time < start + segment.duration || index + 1 == segments.len() {
+            return Some((segment, (time - start).max(0.0)));
+        }
+        start += segment.duration;
+    }
+    None
+}
+
+/// Recordings found on a card
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraCard {
+    pub root: PathBuf,
+    pub format: CameraCardFormat,
+    pub clips: Vec<CameraClip>,
+}
+
+/// Card format of the directory `root`, if it is the root of a camera card
+pub fn detect_camera_card(root: &Path) -> Option<CameraCardFormat> {
+    if avchd_bdmv(root).is_some() {
+        Some(CameraCardFormat::Avchd)
+    } else if child(root, "XDROOT").is_some_and(|dir| dir.is_dir()) {
+        Some(CameraCardFormat::Xavc)
+    } else {
+        None
+    }
+}
+
+/// Read the recordings of the camera card at `root`, `None` if it isn't one
+pub fn scan_camera_card(root: &Path) -> Result<Option<CameraCard>> {
+    let Some(format) = detect_camera_card(root) else {
+        return Ok(None);
+    };
+    let clips = match format {
+        CameraCardFormat::Avchd => scan_avchd(&avchd_bdmv(root).unwrap())?,
+        CameraCardFormat::Xavc => scan_xdroot(&child(root, "XDROOT").unwrap())?,
+    };
+    debug!(
+        "Found {} clips on {:?} card at {:?}, {} spanned",
+        clips.len(),
+        format,
+        root,
+        clips.iter().filter(|clip| clip.is_spanned()).count()
+    );
+    Ok(Some(CameraCard { root: root.to_path_buf(), format, clips }))
+}
+
+/// `BDMV` directory of an AVCHD card, which some copies keep without `PRIVATE`
+fn avchd_bdmv(root: &Path) -> Option<PathBuf> {
+    let avchd = child(root, "PRIVATE").and_then(|private| child(&private, "AVCHD")).or_else(|| child(root, "AVCHD"))?;
+    child(&avchd, "BDMV").filter(|bdmv| bdmv.is_dir())
+}
+
+/// A play item of an AVCHD playlist
+#[derive(Debug, Clone, PartialEq)]
+struct PlayItem {
+    clip_name: String,
+    connection: u8,
+    in_time: u32,
+    out_time: u32,
+}
+
+fn scan_avchd(bdmv: &Path) -> Result<Vec<CameraClip>> {
+    let stream_dir = child(bdmv, "STREAM").ok_or_else(|| anyhow!("AVCHD card has no STREAM directory: {:?}", bdmv))?;
+    let mut clips: Vec<CameraClip> = Vec::new();
+    let mut seen = Vec::new();
+
+    if let Some(playlist_dir) = child(bdmv, "PLAYLIST") {
+        for playlist in sorted_files(&playlist_dir, "MPL")? {
+            let items = match parse_mpl(&fs::read(&playlist)?) {
+                Ok(items) => items,
+                Err(e) => {
+                    warn!("Skipping unreadable AVCHD playlist {:?}: {}", playlist, e);
+                    continue;
+                },
+            };
+            for (index, item) in items.iter().enumerate() {
+                if seen.contains(&item.clip_name) {
+                    continue;
+                }
+                let Some(path) = child(&stream_dir, &format!("{}.MTS", item.clip_name)) else {
+                    warn!("AVCHD playlist {:?} names missing clip {}", playlist, item.clip_name);
+                    continue;
+                };
+                seen.push(item.clip_name.clone());
+                let segment = ClipSegment {
+                    path,
+                    duration: item.out_time.saturating_sub(item.in_time) as f64 / AVCHD_CLOCK,
+                };
+
+                // A seamless connection continues the recording before it
+                let continues = index > 0 && SEAMLESS_CONNECTIONS.contains(&item.connection);
+                match clips.last_mut() {
+                    Some(clip) if continues => clip.segments.push(segment),
+                    _ => clips.push(CameraClip {
+                        name: item.clip_name.clone(),
+                        format: CameraCardFormat::Avchd,
+                        segments: vec![segment],
+                        start_timecode: None,
+                    }),
+                }
+            }
+        }
+    }
+
+    // Streams no playlist lists, with unknown durations
+    for path in sorted_files(&stream_dir, "MTS")? {
+        let name = file_stem(&path);
+        if !seen.contains(&name) {
+            clips.push(CameraClip {
+                name,
+                format: CameraCardFormat::Avchd,
+                segments: vec![ClipSegment { path, duration: 0.0 }],
+                start_timecode: None,
+            });
+        }
+    }
+    Ok(clips)
+}
+
+/// Play items of an AVCHD (Blu-ray MPLS) playlist file
+fn parse_mpl(data: &[u8]) -> Result<Vec<PlayItem>> {
+    if data.len() < 20 || &data[..4] != b"MPLS" {
+        return Err(anyhow!("not an MPLS playlist"));
+    }
+    let truncated = || anyhow!("truncated playlist");
+    let u16_at = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(truncated);
+    let u32_at = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(truncated);
+
+    // PlayList: length, reserved, item count, sub-path count, then the play items
+    let playlist = u32_at(8)? as usize;
+    let count = u16_at(playlist + 6)?;
+    let mut offset = playlist + 10;
+    let mut items = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let length = u16_at(offset)? as usize;
+        let item = data.get(offset + 2..offset + 2 + length).filter(|item| item.len() >= 20).ok_or_else(truncated)?;
+        items.push(PlayItem {
+            clip_name: String::from_utf8_lossy(&item[..5]).into_owned(),
+            connection: item[10] & 0x0f,
+            in_time: u32::from_be_bytes([item[12], item[13], item[14], item[15]]),
+            out_time: u32::from_be_bytes([item[16], item[17], item[18], item[19]]),
+        });
+        offset += 2 + length;
+    }
+    Ok(items)
+}
+
+fn scan_xdroot(xdroot: &Path) -> Result<Vec<CameraClip>> {
+    let clip_dir = child(xdroot, "Clip").ok_or_else(|| anyhow!("XDROOT has no Clip directory: {:?}", xdroot))?;
+    let files = sorted_files(&clip_dir, "MXF")?;
+    let metadata: Vec<XdMetadata> = files.iter().map(|file| xd_metadata(&clip_dir, file)).collect();
+
+    // Edit lists name clips by file or by UMID
+    let find = |src: &str| -> Option<usize> {
+        match src.strip_prefix("urn:smpte:umid:") {
+            Some(umid) => metadata.iter().position(|m| m.umid.as_deref().is_some_and(|u| u.eq_ignore_ascii_case(umid))),
+            None => {
+                let name = src.rsplit(['/', '\\']).next().unwrap_or(src);
+                files.iter().position(|file| file.file_name().is_some_and(|f| f.to_string_lossy().eq_ignore_ascii_case(name)))
+            },
+        }
+    };
+    let mut spans: Vec<Vec<usize>> = Vec::new();
+    if let Some(edit_dir) = child(xdroot, "Edit") {
+        for edit_list in sorted_files(&edit_dir, "SMI")? {
+            let text = fs::read_to_string(&edit_list)?;
+            let refs: Vec<usize> = tags(&text, "ref").filter_map(|tag| attribute(tag, "src")).filter_map(find).collect();
+            if refs.len() > 1 {
+                spans.push(refs);
+            }
+        }
+    }
+
+    let mut clips = Vec::new();
+    let mut seen = vec![false; files.len()];
+    for index in 0..files.len() {
+        if seen[index] {
+            continue;
+        }
+        let span = spans.iter().find(|span| span[0] == index).cloned().unwrap_or_else(|| vec![index]);
+        for &segment in &span {
+            seen[segment] = true;
+        }
+        clips.push(CameraClip {
+            name: file_stem(&files[index]),
+            format: CameraCardFormat::Xavc,
+            segments: span.iter()
+                .map(|&segment| ClipSegment { path: files[segment].clone(), duration: metadata[segment].duration })
+                .collect(),
+            start_timecode: metadata[index].start_timecode,
+        });
+    }
+    Ok(clips)
+}
+
+/// What an XDROOT clip's metadata file records
+#[derive(Debug, Clone, Default)]
+struct XdMetadata {
+    duration: f64,
+    start_timecode: Option<Timecode>,
+    umid: Option<String>,
+}
+
+/// Read `C0001M01.XML` next to `C0001.MXF`; a missing or unreadable file gives defaults
+fn xd_metadata(clip_dir: &Path, clip: &Path) -> XdMetadata {
+    let Some(text) = child(clip_dir, &format!("{}M01.XML", file_stem(clip))).and_then(|path| fs::read_to_string(path).ok()) else {
+        return XdMetadata::default();
+    };
+    let fps = tags(&text, "LtcChangeTable")
+        .next()
+        .and_then(|tag| attribute(tag, "tcFps"))
+        .and_then(|fps| fps.parse::<u32>().ok())
+        .unwrap_or(25)
+        .max(1);
+    // `tcFps` is the timebase; the video format says whether frames run at NTSC rates,
+    // like "29.97p" or "59.94i"
+    let ntsc = tags(&text, "VideoFrame")
+        .next()
+        .and_then(|tag| attribute(tag, "formatFps"))
+        .and_then(|fps| FrameRate::parse(fps.trim_end_matches(['p', 'i', 'P', 'I'])))
+        .is_some_and(|rate| rate.is_ntsc());
+    let rate = if ntsc { FrameRate::ntsc(fps) } else { FrameRate::whole(fps) };
+    let frames = tags(&text, "Duration")
+        .next()
+        .and_then(|tag| attribute(tag, "value"))
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0);
+    let start_timecode = tags(&text, "LtcChange")
+        .find(|tag| attribute(tag, "frameCount") == Some("0"))
+        .and_then(|tag| attribute(tag, "value"))
+        .and_then(|value| packed_timecode(value, rate));
+    let umid = tags(&text, "TargetMaterial")
+        .next()
+        .and_then(|tag| attribute(tag, "umidRef"))
+        .map(|umid| umid.to_string());
+    XdMetadata { duration: rate.frames_to_seconds(frames), start_timecode, umid }
+}
+
+/// Timecode packed as SMPTE 12M BCD bytes in frame, second, minute, hour order, written
+/// in hex, like `55181210` for 10:12:18:15; the high bits of each byte are flags, with
+/// bit 6 of the frame byte marking drop-frame timecode
+fn packed_timecode(value: &str, rate: FrameRate) -> Option<Timecode> {
+    let packed = u32::from_str_radix(value, 16).ok()?;
+    let bcd = |shift: u32, mask: u32| {
+        let byte = (packed >> shift) & mask;
+        (byte >> 4) as u64 * 10 + (byte & 0x0f) as u64
+    };
+    let (frames, seconds, minutes, hours) = (bcd(24, 0x3f), bcd(16, 0x7f), bcd(8, 0x7f), bcd(0, 0x3f));
+    let drop_frame = (packed >> 24) & 0x40 != 0;
+    (frames < rate.timebase() as u64 && seconds < 60 && minutes < 60 && hours < 24)
+        .then(|| Timecode::at_rate(hours, minutes, seconds, frames, rate, drop_frame))
+}
+
+/// Contents of every `<name ...>` tag in `xml`, without the name
+fn tags<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    xml.split('<').skip(1).filter_map(move |tag| {
+        let tag = tag.split('>').next()?;
+        let rest = tag.strip_prefix(name)?;
+        rest.starts_with(|c: char| c.is_whitespace() || c == '/').then_some(rest)
+    })
+}
+
+/// Value of the attribute `name` in the contents of a tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(position) = rest.find(name) {
+        let before = rest[..position].chars().last();
+        let after = rest[position + name.len()..].trim_start();
+        if before.is_none_or(|c| c.is_whitespace()) {
+            if let Some(value) = after.strip_prefix('=') {
+                let value = value.trim_start();
+                let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+                return value[1..].split(quote).next();
+            }
+        }
+        rest = &rest[position + name.len()..];
+    }
+    None
+}
+
+/// Entry `name` of `dir`, matching case-insensitively since cards are FAT-formatted
+fn child(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        .map(|entry| entry.path())
+}
+
+/// Files of `dir` with `extension` in any case, sorted by name
+fn sorted_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension)))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests {
+    use super::super::camera_card::*;
+    use crate::engine::frame_rate::FrameRate;
+    use super::super::file_manager::{MediaInfo, MediaType};
+    use super::super::media_library::MediaLibrary;
+    use super::super::test_utils::create_test_dir;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use anyhow::Result;
+
+    fn create_file(path: &Path, content: &[u8]) -> Result<()> {
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// An MPLS playlist of (clip, connection condition, seconds) play items
+    fn playlist(items: &[(&str, u8, u32)]) -> Vec<u8> {
+        let mut data = b"MPLS0100".to_vec();
+        data.extend(20u32.to_be_bytes());
+        data.extend([0; 8]);
+        data.extend(0u32.to_be_bytes());
+        data.extend([0, 0]);
+        data.extend((items.len() as u16).to_be_bytes());
+        data.extend([0, 0]);
+        for (name, connection, seconds) in items {
+            data.extend(20u16.to_be_bytes());
+            data.extend(name.as_bytes());
+            data.extend(b"M2TS");
+            data.extend([0, *connection, 0]);
+            data.extend(27_000_000u32.to_be_bytes());
+            data.extend((27_000_000 + seconds * 45000).to_be_bytes());
+        }
+        data
+    }
+
+    fn nrt_metadata(umid: &str, frames: u32, timecode: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<NonRealTimeMeta xmlns="urn:schemas-professionalDisc:nonRealTimeMeta:ver.2.00">
+    <TargetMaterial umidRef="{}"/>
+    <Duration value="{}"/>
+    <LtcChangeTable tcFps="25" halfStep="false">
+        <LtcChange frameCount="0" value="{}" status="increment"/>
+        <LtcChange frameCount="{}" value="00000000" status="end"/>
+    </LtcChangeTable>
+</NonRealTimeMeta>"#,
+            umid, frames, timecode, frames - 1
+        )
+    }
+
+    #[test]
+    fn test_avchd_spanned_clips() -> Result<()> {
+        let root = create_test_dir("camera_card", "avchd")?;
+        let bdmv = root.join("PRIVATE").join("AVCHD").join("BDMV");
+        // A recording split in two, one on its own, and a stream no playlist lists
+        create_file(&bdmv.join("PLAYLIST").join("00000.MPL"), &playlist(&[("00000", 1, 1200), ("00001", 6, 300), ("00002", 1, 45)]))?;
+        for name in ["00000", "00001", "00002", "00003"] {
+            create_file(&bdmv.join("STREAM").join(format!("{}.MTS", name)), name.as_bytes())?;
+        }
+
+        assert_eq!(detect_camera_card(&root), Some(CameraCardFormat::Avchd));
+        let card = scan_camera_card(&root)?.unwrap();
+        let names: Vec<&str> = card.clips.iter().map(|clip| clip.name.as_str()).collect();
+        assert_eq!(names, vec!["00000", "00002", "00003"]);
+
+        let spanned = &card.clips[0];
+        assert!(spanned.is_spanned());
+        assert_eq!(spanned.duration(), 1500.0);
+        assert!(spanned.segments[1].path.ends_with("STREAM/00001.MTS"));
+        let (segment, offset) = spanned.locate(1250.0).unwrap();
+        assert!(segment.path.ends_with("00001.MTS"));
+        assert_eq!(offset, 50.0);
+        assert_eq!(spanned.start_timecode, None);
+        assert!(!card.clips[2].is_spanned());
+
+        assert_eq!(scan_camera_card(&bdmv)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_xdroot_spanned_clips_keep_timecode() -> Result<()> {
+        let root = create_test_dir("camera_card", "xdroot")?;
+        let clips = root.join("XDROOT").join("Clip");
+        for (name, umid, frames, timecode) in [
+            ("C0001", "060A2B340101010501010D4313000000AAAA", 2500, "55181210"),
+            ("C0002", "060A2B340101010501010D4313000000BBBB", 1000, "15581310"),
+            ("C0003", "060A2B340101010501010D4313000000CCCC", 250, "00000001"),
+        ] {
+            create_file(&clips.join(format!("{}.MXF", name)), name.as_bytes())?;
+            create_file(&clips.join(format!("{}M01.XML", name)), nrt_metadata(umid, frames, timecode).as_bytes())?;
+        }
+        // Edit lists name clips by UMID or by file
+        let edit_list = r#"<smil><body><par><ref src="urn:smpte:umid:060A2B340101010501010D4313000000AAAA" clipBegin="smpte-25=00:00:00:00"/>
+            <ref src="../Clip/C0002.MXF" clipBegin="smpte-25=00:00:00:00"/></par></body></smil>"#;
+        create_file(&root.join("XDROOT").join("Edit").join("E0001E01.SMI"), edit_list.as_bytes())?;
+
+        let card = scan_camera_card(&root)?.unwrap();
+        assert_eq!(card.format, CameraCardFormat::Xavc);
+        assert_eq!(card.clips.len(), 2);
+        let spanned = &card.clips[0];
+        assert_eq!(spanned.segments.len(), 2);
+        assert_eq!(spanned.duration(), 140.0);
+        assert_eq!(spanned.start_timecode.unwrap().to_string(), "10:12:18:15");
+        // Timecode runs on into the second file
+        assert_eq!(spanned.timecode_at(100.0).unwrap().to_string(), "10:13:58:15");
+        assert_eq!(spanned.timecode_at(139.96).unwrap().to_string(), "10:14:38:14");
+        assert_eq!(card.clips[1].start_timecode, Some(Timecode::new(1, 0, 0, 0, 25)));
+
+        assert_eq!(Timecode::parse("10:12:18:15", 25)?, spanned.start_timecode.unwrap());
+        assert!(Timecode::parse("10:12:18:25", 25).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_frame_timecode() -> Result<()> {
+        let rate = FrameRate::FPS_29_97;
+        // The first two labels of each minute are skipped, except every tenth minute
+        let timecode = Timecode::parse_at("00:01:00;02", rate)?;
+        assert_eq!((timecode.frame, timecode.drop_frame), (1800, true));
+        assert_eq!(Timecode::from_frame(1799, rate, true).to_string(), "00:00:59;29");
+        assert_eq!(Timecode::from_frame(17982, rate, true).to_string(), "00:10:00;00");
+        assert!(Timecode::parse_at("00:01:00;01", rate).is_err());
+        assert!(Timecode::parse_at("00:10:00;00", rate).is_ok());
+
+        // An hour of drop-frame labels stays within a frame of an hour; non-drop-frame
+        // at 29.97 falls 3.6 seconds behind the clock
+        let hour = Timecode::parse_at("01:00:00;00", rate)?;
+        assert_eq!(hour.frame, 107892);
+        assert!((hour.seconds() - 3600.0).abs() < rate.frame_duration());
+        let non_drop = Timecode::parse_at("01:00:00:00", rate)?;
+        assert_eq!((non_drop.frame, non_drop.drop_frame), (108000, false));
+        assert!((non_drop.seconds() - 3603.6).abs() < 1e-6);
+        assert_eq!(non_drop.offset(1.001).to_string(), "01:00:01:00");
+
+        // Whole rates have no drop-frame timecode
+        assert!(!Timecode::parse_at("00:01:00;02", FrameRate::FPS_30)?.drop_frame);
+
+        // A 29.97 card flags drop-frame timecode in the packed frame byte
+        let root = create_test_dir("camera_card", "drop_frame")?;
+        let clips = root.join("XDROOT").join("Clip");
+        let metadata = r#"<?xml version="1.0" encoding="UTF-8"?>
+<NonRealTimeMeta xmlns="urn:schemas-professionalDisc:nonRealTimeMeta:ver.2.00">
+    <Duration value="1798"/>
+    <LtcChangeTable tcFps="30" halfStep="false">
+        <LtcChange frameCount="0" value="42000101" status="increment"/>
+    </LtcChangeTable>
+    <VideoFormat><VideoFrame videoCodec="AVC_1920_1080_HP@L42" formatFps="29.97p"/></VideoFormat>
+</NonRealTimeMeta>"#;
+        create_file(&clips.join("C0001.MXF"), b"C0001")?;
+        create_file(&clips.join("C0001M01.XML"), metadata.as_bytes())?;
+
+        let card = scan_camera_card(&root)?.unwrap();
+        let clip = &card.clips[0];
+        assert!((clip.duration() - 1798.0 * 1.001 / 30.0).abs() < 1e-9);
+        let start = clip.start_timecode.unwrap();
+        assert_eq!((start.to_string(), start.rate()), ("01:01:00;02".to_string(), rate));
+        assert_eq!(clip.timecode_at(30.03).unwrap().to_string(), "01:01:30;02");
+        Ok(())
+    }
+
+    #[test]
+    fn test_library_imports_spanned_clip_as_one_asset() -> Result<()> {
+        let root = create_test_dir("camera_card", "library")?;
+        let card = root.join("card");
+        let bdmv = card.join("PRIVATE").join("AVCHD").join("BDMV");
+        create_file(&bdmv.join("PLAYLIST").join("00000.MPL"), &playlist(&[("00000", 1, 600), ("00001", 5, 120)]))?;
+        create_file(&bdmv.join("STREAM").join("00000.MTS"), b"first")?;
+        create_file(&bdmv.join("STREAM").join("00001.MTS"), b"second")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let ids = library.import_camera_card(&card)?;
+        assert_eq!(ids.len(), 1);
+        let asset = library.get_asset(&ids[0]).unwrap();
+        assert_eq!(asset.stored_path, PathBuf::from("card/PRIVATE/AVCHD/BDMV/STREAM/00000.MTS"));
+        assert_eq!(asset.camera.as_ref().unwrap().segments.len(), 2);
+
+        let (path, offset) = library.locate(&ids[0], 630.0)?;
+        assert_eq!(path, bdmv.join("STREAM").join("00001.MTS"));
+        assert_eq!(offset, 30.0);
+        assert_eq!(library.locate(&ids[0], 10.0)?, (bdmv.join("STREAM").join("00000.MTS"), 10.0));
+        assert!(library.import_camera_card(&root.join("missing")).is_err());
+
+        // Probed from the first file, the duration is that of the whole recording
+        let probed = MediaInfo {
+            path: bdmv.join("STREAM").join("00000.MTS"),
+            media_type: MediaType::Video,
+            size: 5,
+            duration: Some(600.0),
+            width: Some(1920),
+            height: Some(1080),
+            frame_rate: Some(50.0),
+            codec: Some("h264".to_string()),
+            sample_rate: Some(48000),
+            channels: Some(2),
+            metadata: HashMap::new(),
+        };
+        let mut library = MediaLibrary::new(&root);
+        let clip = scan_camera_card(&card)?.unwrap().clips.remove(0);
+        let id = library.add_camera_clip(&clip, Some(probed))?;
+        assert_eq!(library.get_asset(&id).unwrap().info.as_ref().unwrap().duration, Some(720.0));
+
+        // Sub-clips play the same recording
+        let subclip = library.create_subclip(&id, "Wide", 590.0, 610.0, "")?;
+        assert!(library.get_asset(&subclip).unwrap().camera.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! Logging metadata editors attach to source clips
+//!
+//! Ratings, label colors, notes, keywords and select ranges are stored on each asset of the
+//! media library, so they are saved with the project. `query_log` finds logged clips and
+//! `best_takes` lists the selects of the best rated ones for a "select best takes" pass.
+//!
+//! The reel, scene, shot and take of a clip are logged as its `SlateMetadata`, which bins
+//! sort by, burn-in text draws from and conform exports carry through to the clips.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::engine::timeline::Clip;
+use super::media_library::{MediaAsset, MediaLibrary};
+
+/// Highest clip rating
+pub const MAX_RATING: u8 = 5;
+
+/// Clip properties carrying a source clip's slate onto the timeline
+pub const REEL_PROPERTY: &str = "slate.reel";
+pub const SCENE_PROPERTY: &str = "slate.scene";
+pub const SHOT_PROPERTY: &str = "slate.shot";
+pub const TAKE_PROPERTY: &str = "slate.take";
+
+/// Longest reel name a CMX 3600 EDL event holds
+pub const EDL_REEL_LENGTH: usize = 8;
+
+/// Color label shown on a clip in the bin and on the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LabelColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Gray,
+}
+
+/// A range of the source marked as usable, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Select {
+    pub in_point: f64,
+    pub out_point: f64,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl Select {
+    pub fn duration(&self) -> f64 {
+        self.out_point - self.in_point
+    }
+}
+
+/// Reel, scene, shot and take identifying a source clip for conform; empty when unknown
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlateMetadata {
+    /// Camera roll or card the clip was recorded on
+    pub reel: String,
+    pub scene: String,
+    pub shot: String,
+    pub take: String,
+}
+
+impl SlateMetadata {
+    pub fn new(reel: &str, scene: &str, shot: &str, take: &str) -> Self {
+        Self {
+            reel: reel.trim().to_string(),
+            scene: scene.trim().to_string(),
+            shot: shot.trim().to_string(),
+            take: take.trim().to_string(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SlateMetadata::default()
+    }
+
+    /// Value of a `reel`, `scene`, `shot` or `take` field
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match name {
+            "reel" => Some(&self.reel),
+            "scene" => Some(&self.scene),
+            "shot" => Some(&self.shot),
+            "take" => Some(&self.take),
+            _ => None,
+        }
+    }
+
+    /// Order for sorting bins: by reel, scene, shot and take, with numbers in numeric
+    /// order so scene 9 comes before scene 10 and 12 before 12A
+    ///
+    /// Empty fields sort after filled ones, so unlogged clips end up last.
+    pub fn cmp_slate(&self, other: &Self) -> Ordering {
+        [(&self.reel, &other.reel), (&self.scene, &other.scene), (&self.shot, &other.shot), (&self.take, &other.take)]
+            .into_iter()
+            .map(|(a, b)| match (a.is_empty(), b.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => natural_cmp(a, b),
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Text for a burn-in, replacing `{reel}`, `{scene}`, `{shot}`, `{take}` and `{name}`
+    /// in `template`
+    ///
+    /// Other placeholders are left as they are so templates can mix in fields the burn-in
+    /// fills itself, such as timecode.
+    pub fn burn_in(&self, template: &str, name: &str) -> String {
+        let mut output = String::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            output.push_str(&rest[..open]);
+            let Some(close) = rest[open..].find('}') else {
+                rest = &rest[open..];
+                break;
+            };
+            let placeholder = &rest[open + 1..open + close];
+            match placeholder.trim() {
+                "name" => output.push_str(name),
+                field => match self.field(field) {
+                    Some(value) => output.push_str(value),
+                    None => output.push_str(&rest[open..=open + close]),
+                },
+            }
+            rest = &rest[open + close + 1..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// Reel name for an EDL event: uppercase letters, digits and underscores, at most
+    /// `EDL_REEL_LENGTH` long, and `AX` (auxiliary source) when the clip has no reel
+    pub fn edl_reel(&self) -> String {
+        let reel: String = self.reel
+            .chars()
+            .filter_map(|c| match c {
+                c if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase()),
+                ' ' | '-' | '_' | '.' => Some('_'),
+                _ => None,
+            })
+            .take(EDL_REEL_LENGTH)
+            .collect();
+        if reel.is_empty() { "AX".to_string() } else { reel }
+    }
+
+    /// Slate carried by a timeline clip's properties
+    pub fn from_clip(clip: &Clip) -> Self {
+        let property = |key: &str| clip.properties.get(key).cloned().unwrap_or_default();
+        Self {
+            reel: property(REEL_PROPERTY),
+            scene: property(SCENE_PROPERTY),
+            shot: property(SHOT_PROPERTY),
+            take: property(TAKE_PROPERTY),
+        }
+    }
+
+    /// Store the slate in a timeline clip's properties, removing the empty fields
+    pub fn apply_to(&self, clip: &mut Clip) {
+        for (key, value) in [(REEL_PROPERTY, &self.reel), (SCENE_PROPERTY, &self.scene), (SHOT_PROPERTY, &self.shot), (TAKE_PROPERTY, &self.take)] {
+            if value.is_empty() {
+                clip.properties.remove(key);
+            } else {
+                clip.properties.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+}
+
+/// Compare strings with runs of digits compared by value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase());
+                if ordering.is_ne() {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+/// Take the digits at the front of `chars`, without leading zeros
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits.trim_start_matches('0').to_string()
+}
+
+/// Logging metadata of a source clip
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipLog {
+    /// Rating from 1 to `MAX_RATING`, or `None` if unrated
+    pub rating: Option<u8>,
+    pub label: Option<LabelColor>,
+    /// Free-form log notes
+    pub notes: String,
+    pub keywords: Vec<String>,
+    /// Selects, sorted by in point
+    pub selects: Vec<Select>,
+    /// Reel, scene, shot and take
+    pub slate: SlateMetadata,
+}
+
+impl ClipLog {
+    /// Rate the clip, or clear its rating with `None`
+    pub fn set_rating(&mut self, rating: Option<u8>) -> Result<()> {
+        if let Some(rating) = rating {
+            if rating == 0 || rating > MAX_RATING {
+                return Err(anyhow!("Rating must be between 1 and {}, got {}", MAX_RATING, rating));
+            }
+        }
+        self.rating = rating;
+        Ok(())
+    }
+
+    pub fn has_keyword(&self, keyword: &str) -> bool {
+        self.keywords.iter().any(|k| k.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Add a keyword unless the clip already has it
+    pub fn add_keyword(&mut self, keyword: &str) {
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !self.has_keyword(keyword) {
+            self.keywords.push(keyword.to_string());
+        }
+    }
+
+    pub fn remove_keyword(&mut self, keyword: &str) {
+        self.keywords.retain(|k| !k.eq_ignore_ascii_case(keyword));
+    }
+
+    /// Mark a range of the source as a select, returning its index
+    pub fn add_select(&mut self, in_point: f64, out_point: f64, note: &str) -> Result<usize> {
+        if !(in_point >= 0.0 && out_point > in_point) {
+            return Err(anyhow!("Invalid select range {}-{}", in_point, out_point));
+        }
+        let index = self.selects.partition_point(|select| select.in_point <= in_point);
+        self.selects.insert(index, Select { in_point, out_point, note: note.to_string() });
+        Ok(index)
+    }
+
+    pub fn remove_select(&mut self, index: usize) -> Option<Select> {
+        (index < self.selects.len()).then(|| self.selects.remove(index))
+    }
+
+    /// Whether nothing has been logged
+    pub fn is_empty(&self) -> bool {
+        *self == ClipLog::default()
+    }
+}
+
+/// Filter over logged clips; empty fields match every clip
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    /// Lowest rating; unrated clips never match
+    pub min_rating: Option<u8>,
+    pub label: Option<LabelColor>,
+    /// Keywords the clip must all have
+    pub keywords: Vec<String>,
+    /// Text found in the notes or in a select's note, ignoring case
+    pub text: Option<String>,
+    /// Only clips with at least one select
+    pub has_selects: bool,
+}
+
+impl LogQuery {
+    pub fn matches(&self, log: &ClipLog) -> bool {
+        if let Some(min_rating) = self.min_rating {
+            if log.rating.is_none_or(|rating| rating < min_rating) {
+                return false;
+            }
+        }
+        if self.label.is_some() && log.label != self.label {
+            return false;
+        }
+        if !self.keywords.iter().all(|keyword| log.has_keyword(keyword)) {
+            return false;
+        }
+        if let Some(text) = &self.text {
+            let text = text.to_lowercase();
+            let in_notes = log.notes.to_lowercase().contains(&text)
+                || log.selects.iter().any(|select| select.note.to_lowercase().contains(&text));
+            if !in_notes {
+                return false;
+            }
+        }
+        !self.has_selects || !log.selects.is_empty()
+    }
+}
+
+/// Assets whose log matches `query`, highest rated first, then by ID
+pub fn query_log<'a>(library: &'a MediaLibrary, query: &LogQuery) -> Vec<&'a MediaAsset> {
+    let mut assets: Vec<&MediaAsset> = library.assets()
+        .into_iter()
+        .filter(|asset| query.matches(&asset.log))
+        .collect();
+    // Stable, so equal ratings stay in ID order
+    assets.sort_by_key(|asset| Reverse(asset.log.rating));
+    assets
+}
+
+/// A usable range of a rated clip
+#[derive(Debug, Clone, PartialEq)]
+pub struct Take {
+    pub asset_id: String,
+    pub rating: u8,
+    pub select: Select,
+}
+
+/// Selects of the clips rated at least `min_rating`, highest rated first
+///
+/// A rated clip without selects counts as one take covering the whole source, when its
+/// duration is known.
+pub fn best_takes(library: &MediaLibrary, min_rating: u8) -> Vec<Take> {
+    let query = LogQuery { min_rating: Some(min_rating), ..Default::default() };
+    query_log(library, &query)
+        .into_iter()
+        .flat_map(|asset| {
+            let rating = asset.log.rating.unwrap_or_default();
+            let selects = if asset.log.selects.is_empty() {
+                asset.info.as_ref()
+                    .and_then(|info| info.duration)
+                    .map(|duration| Select { in_point: 0.0, out_point: duration, note: String::new() })
+                    .into_iter()
+                    .collect()
+            } else {
+                asset.log.selects.clone()
+            };
+            selects.into_iter().map(move |select| Take { asset_id: asset.id.clone(), rating, select })
+        })
+        .collect()
+}
@@ -0,0 +1,164 @@
+#[cfg(test)]
+mod tests {
+    use super::super::clip_log::*;
+    use super::super::media_library::{BinRule, BinSort, MediaLibrary};
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use std::path::Path;
+    use anyhow::Result;
+
+    /// Library with one asset per name, in order
+    fn library_with(root: &Path, names: &[&str]) -> Result<(MediaLibrary, Vec<String>)> {
+        let mut library = MediaLibrary::new(root);
+        let mut ids = Vec::new();
+        for name in names {
+            let path = root.join(name);
+            fs::write(&path, name)?;
+            ids.push(library.add_asset(&path, None)?);
+        }
+        Ok((library, ids))
+    }
+
+    #[test]
+    fn test_edit_log() -> Result<()> {
+        let mut log = ClipLog::default();
+        assert!(log.is_empty());
+
+        log.set_rating(Some(4))?;
+        assert!(log.set_rating(Some(0)).is_err());
+        assert!(log.set_rating(Some(MAX_RATING + 1)).is_err());
+        assert_eq!(log.rating, Some(4));
+
+        log.add_keyword("Interview");
+        log.add_keyword("interview");
+        log.add_keyword("  ");
+        assert_eq!(log.keywords, vec!["Interview"]);
+        log.remove_keyword("INTERVIEW");
+        assert!(log.keywords.is_empty());
+
+        // Selects stay sorted by in point
+        assert_eq!(log.add_select(10.0, 12.0, "laugh")?, 0);
+        assert_eq!(log.add_select(2.0, 5.0, "")?, 0);
+        assert!(log.add_select(8.0, 8.0, "").is_err());
+        assert!(log.add_select(-1.0, 3.0, "").is_err());
+        let ins: Vec<f64> = log.selects.iter().map(|s| s.in_point).collect();
+        assert_eq!(ins, vec![2.0, 10.0]);
+
+        assert_eq!(log.remove_select(1).map(|s| s.note), Some("laugh".to_string()));
+        assert_eq!(log.remove_select(1), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_and_best_takes() -> Result<()> {
+        let root = create_test_dir("clip_log", "query")?;
+        let (mut library, ids) = library_with(&root, &["take1.mov", "take2.mov", "take3.mov", "broll.mov"])?;
+
+        {
+            let log = library.log_mut(&ids[0])?;
+            log.set_rating(Some(3))?;
+            log.add_keyword("interview");
+            log.add_select(1.0, 4.0, "good answer")?;
+        }
+        {
+            let log = library.log_mut(&ids[1])?;
+            log.set_rating(Some(5))?;
+            log.label = Some(LabelColor::Green);
+            log.add_keyword("Interview");
+            log.notes = "Best energy".to_string();
+            log.add_select(6.0, 9.0, "")?;
+            log.add_select(0.5, 2.0, "")?;
+        }
+        library.log_mut(&ids[2])?.set_rating(Some(1))?;
+        assert!(library.log_mut("asset_99").is_err());
+
+        let found = |query: &LogQuery| -> Vec<String> {
+            query_log(&library, query).into_iter().map(|asset| asset.id.clone()).collect()
+        };
+        assert_eq!(found(&LogQuery::default()).len(), 4);
+        assert_eq!(found(&LogQuery { min_rating: Some(3), ..Default::default() }), vec![ids[1].clone(), ids[0].clone()]);
+        assert_eq!(found(&LogQuery { label: Some(LabelColor::Green), ..Default::default() }), vec![ids[1].clone()]);
+        assert_eq!(found(&LogQuery { keywords: vec!["INTERVIEW".to_string()], ..Default::default() }).len(), 2);
+        assert_eq!(found(&LogQuery { text: Some("answer".to_string()), ..Default::default() }), vec![ids[0].clone()]);
+        assert_eq!(found(&LogQuery { text: Some("energy".to_string()), ..Default::default() }), vec![ids[1].clone()]);
+        assert_eq!(found(&LogQuery { has_selects: true, ..Default::default() }).len(), 2);
+
+        let takes = best_takes(&library, 3);
+        let ranges: Vec<(&str, f64, f64)> = takes.iter()
+            .map(|take| (take.asset_id.as_str(), take.select.in_point, take.select.out_point))
+            .collect();
+        assert_eq!(ranges, vec![(ids[1].as_str(), 0.5, 2.0), (ids[1].as_str(), 6.0, 9.0), (ids[0].as_str(), 1.0, 4.0)]);
+        assert_eq!(takes[0].rating, 5);
+
+        // Rating bins and keyword search
+        let bin = library.create_smart_bin("Best", None, BinRule::MinRating(3))?;
+        assert_eq!(library.bin_assets(&bin)?, vec![ids[0].clone(), ids[1].clone()]);
+        assert_eq!(library.search("interview").len(), 2);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_saved_with_library() -> Result<()> {
+        let root = create_test_dir("clip_log", "saved")?;
+        let (mut library, ids) = library_with(&root, &["take.mov"])?;
+        library.log_mut(&ids[0])?.set_rating(Some(2))?;
+        library.log_mut(&ids[0])?.add_select(3.0, 7.5, "wide")?;
+
+        let loaded: MediaLibrary = serde_json::from_str(&serde_json::to_string(&library)?)?;
+        assert_eq!(loaded.get_asset(&ids[0]).unwrap().log, library.get_asset(&ids[0]).unwrap().log);
+
+        // Libraries saved before logging load with empty logs
+        let mut json = serde_json::to_value(&library)?;
+        json["assets"][&ids[0]].as_object_mut().unwrap().remove("log");
+        let old: MediaLibrary = serde_json::from_value(json)?;
+        assert!(old.get_asset(&ids[0]).unwrap().log.is_empty());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_slate_metadata() -> Result<()> {
+        let slate = SlateMetadata::new(" A001 ", "12A", "3", "4");
+        assert_eq!(slate.reel, "A001");
+        assert_eq!(slate.field("scene"), Some("12A"));
+        assert_eq!(slate.field("camera"), None);
+        assert_eq!(
+            slate.burn_in("{name} {reel} Sc {scene}/{shot} Tk {take} {timecode}", "C0001"),
+            "C0001 A001 Sc 12A/3 Tk 4 {timecode}"
+        );
+        assert_eq!(slate.burn_in("Sc {scene", ""), "Sc {scene");
+
+        assert_eq!(SlateMetadata::new("a001-c002 roll", "", "", "").edl_reel(), "A001_C00");
+        assert_eq!(SlateMetadata::default().edl_reel(), "AX");
+
+        // Scenes in numeric order, unlogged clips last
+        let root = create_test_dir("clip_log", "slate")?;
+        let (mut library, ids) = library_with(&root, &["b.mov", "a.mov", "c.mov", "d.mov"])?;
+        library.log_mut(&ids[0])?.slate = SlateMetadata::new("A001", "10", "1", "1");
+        library.log_mut(&ids[1])?.slate = SlateMetadata::new("A001", "9", "2", "1");
+        library.log_mut(&ids[3])?.slate = SlateMetadata::new("A001", "9", "2", "");
+        let bin = library.create_bin("Dailies", None)?;
+        for id in &ids {
+            library.add_to_bin(&bin, id)?;
+        }
+        assert_eq!(library.bin_assets_sorted(&bin, BinSort::Added)?, ids);
+        assert_eq!(library.bin_assets_sorted(&bin, BinSort::Name)?, vec![ids[1].clone(), ids[0].clone(), ids[2].clone(), ids[3].clone()]);
+        assert_eq!(library.bin_assets_sorted(&bin, BinSort::Slate)?, vec![ids[1].clone(), ids[3].clone(), ids[0].clone(), ids[2].clone()]);
+
+        // Sub-clips start with their take's slate, and timeline clips carry it for conform
+        let subclip = library.create_subclip(&ids[0], "Wide", 1.0, 2.0, "")?;
+        assert_eq!(library.get_asset(&subclip).unwrap().log.slate.scene, "10");
+        library.log_mut(&subclip)?.slate.take = "2".to_string();
+        let mut clip = library.to_clip(&subclip, "clip_1", 0.0)?;
+        assert_eq!(SlateMetadata::from_clip(&clip), library.get_asset(&subclip).unwrap().log.slate);
+        assert_eq!(clip.properties.get(TAKE_PROPERTY).map(String::as_str), Some("2"));
+        SlateMetadata::default().apply_to(&mut clip);
+        assert!(!clip.properties.contains_key(REEL_PROPERTY));
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+//! Loudness badges for timeline clips
+//!
+//! `ClipLoudnessService` measures source files on a background thread with a
+//! `LoudnessMeter`, once per file until it changes. It keeps the K-weighted power of
+//! every 100 ms of a source, summed over its channels, and the peak of its loudest
+//! channel, so any trim of it is measured from the cache
+//! without decoding again. The timeline puts the result in each clip's `ClipInfo` and
+//! badges clips that are too quiet or already clipping before they reach the mix.
+//! Profiles past the cache's memory limit are spilled to disk until asked for again.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use tracing::{debug, error, warn};
+
+use super::analysis_pass::{AnalysisPass, SILENCE_DB};
+use super::metrics::{self, names, CacheStats};
+use super::qc_report::{integrated_loudness, LoudnessMeter, LOUDNESS_STEP, QC_SAMPLE_RATE};
+use super::spill_store::{self, Spillable, SpillStore, MB};
+
+/// Bytes of loudness profiles kept in memory before older ones are spilled to disk
+pub const DEFAULT_PROFILE_MEMORY: usize = 32 * MB;
+
+/// Levels past which a clip gets a badge
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoudnessBadgeThresholds {
+    /// Integrated loudness below which a clip is too quiet to bring up cleanly, in LUFS
+    pub min_loudness: f64,
+    /// Sample peak from which a clip has probably clipped, in dBFS
+    pub max_peak: f64,
+}
+
+impl Default for LoudnessBadgeThresholds {
+    fn default() -> Self {
+        Self {
+            min_loudness: -40.0,
+            max_peak: -0.1,
+        }
+    }
+}
+
+/// Warning shown on a clip in the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoudnessBadge {
+    /// Quiet enough that bringing it up to level raises the noise floor with it
+    Quiet,
+    /// Peaks at full scale
+    Clipping,
+}
+
+/// Loudness of the part of a source a clip uses
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClipLoudness {
+    /// Integrated loudness in LUFS, `None` when silent or shorter than a 400 ms block
+    pub integrated: Option<f64>,
+    /// Highest sample peak, in dBFS
+    pub peak: f64,
+}
+
+impl ClipLoudness {
+    /// The clip's badge, clipping first since no gain in the mix undoes it
+    ///
+    /// Too short to measure integrated loudness, a clip is quiet when even its peak is
+    /// under the loudness limit.
+    pub fn badge(&self, thresholds: &LoudnessBadgeThresholds) -> Option<LoudnessBadge> {
+        let level = self.integrated.unwrap_or(self.peak);
+        if self.peak >= thresholds.max_peak {
+            Some(LoudnessBadge::Clipping)
+        } else if level < thresholds.min_loudness {
+            Some(LoudnessBadge::Quiet)
+        } else {
+            None
+        }
+    }
+}
+
+/// Power and peak of every 100 ms of a source file's audio
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessProfile {
+    /// Summed mean squares of the K-weighted channels in each step
+    pub powers: Vec<f64>,
+    /// Highest absolute sample of any channel in each step
+    pub peaks: Vec<f32>,
+}
+
+impl LoudnessProfile {
+    pub fn from_meter(meter: &LoudnessMeter) -> Self {
+        Self {
+            powers: meter.steps().to_vec(),
+            peaks: meter.step_peaks().to_vec(),
+        }
+    }
+
+    /// Decode the audio of `path` and measure it
+    pub fn measure(path: &Path) -> Result<Self> {
+        let mut meter = LoudnessMeter::new();
+        AnalysisPass::new()
+            .with_sample_rate(QC_SAMPLE_RATE)
+            .with_analyzer(&mut meter)
+            .run(path)?;
+        Ok(Self::from_meter(&meter))
+    }
+
+    /// Length of the measured audio, in seconds
+    pub fn duration(&self) -> f64 {
+        self.powers.len() as f64 * LOUDNESS_STEP
+    }
+
+    /// Loudness from `start` to `end` seconds into the source, to the nearest 100 ms;
+    /// `None` when the source has no audio there
+    pub fn range(&self, start: f64, end: f64) -> Option<ClipLoudness> {
+        let first = ((start.max(0.0) / LOUDNESS_STEP).floor() as usize).min(self.powers.len());
+        let last = ((end / LOUDNESS_STEP).ceil().max(0.0) as usize).clamp(first, self.powers.len());
+        if first == last {
+            return None;
+        }
+        let peak = self.peaks[first..last].iter().fold(0.0f32, |peak, &step| peak.max(step));
+        Some(ClipLoudness {
+            integrated: integrated_loudness(&self.powers[first..last]),
+            peak: if peak > 0.0 { (20.0 * (peak as f64).log10()).max(SILENCE_DB) } else { SILENCE_DB },
+        })
+    }
+}
+
+impl Spillable for Arc<LoudnessProfile> {
+    fn memory_size(&self) -> usize {
+        std::mem::size_of::<LoudnessProfile>()
+            + self.powers.len() * std::mem::size_of::<f64>()
+            + self.peaks.len() * std::mem::size_of::<f32>()
+    }
+
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let powers: Vec<u8> = self.powers.iter().flat_map(|power| power.to_le_bytes()).collect();
+        let peaks: Vec<u8> = self.peaks.iter().flat_map(|peak| peak.to_le_bytes()).collect();
+        spill_store::write_bytes(writer, &powers)?;
+        spill_store::write_bytes(writer, &peaks)
+    }
+
+    fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+        let powers = spill_store::read_bytes(reader)?;
+        let peaks = spill_store::read_bytes(reader)?;
+        Ok(Arc::new(LoudnessProfile {
+            powers: powers.chunks_exact(8).map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap())).collect(),
+            peaks: peaks.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect(),
+        }))
+    }
+}
+
+/// Where measuring a source file has got to
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoudnessStatus {
+    /// Waiting for or being measured
+    Pending,
+    Ready(Arc<LoudnessProfile>),
+    Failed(String),
+}
+
+/// Called with a source's path when its measurement finishes or fails, so the
+/// timeline can redraw its clips
+pub type LoudnessCallback = Arc<dyn Fn(&Path) + Send + Sync>;
+
+/// How a source is measured
+type MeasureFn = Arc<dyn Fn(&Path) -> Result<LoudnessProfile> + Send + Sync>;
+
+/// `LoudnessStatus` without the profile, which is kept in `ServiceState::profiles`
+#[derive(Debug, Clone, PartialEq)]
+enum Measurement {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
+/// A measured source, with the modification time it was measured at
+struct CacheEntry {
+    modified: Option<SystemTime>,
+    measurement: Measurement,
+}
+
+/// Shared state between the service and its worker
+struct ServiceState {
+    queue: VecDeque<PathBuf>,
+    entries: HashMap<PathBuf, CacheEntry>,
+    profiles: SpillStore<PathBuf, Arc<LoudnessProfile>>,
+    running: bool,
+}
+
+/// Background loudness measurement of clip sources, with an in-memory cache
+pub struct ClipLoudnessService {
+    measure: MeasureFn,
+    state: Arc<(Mutex<ServiceState>, Condvar)>,
+    callback: Arc<Mutex<Option<LoudnessCallback>>>,
+    worker: Mutex<Option<thread::JoinHandle<()>>>,
+    /// Lookups through `clip_loudness`, for the diagnostics dashboard
+    cache_stats: CacheStats,
+}
+
+impl Default for ClipLoudnessService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipLoudnessService {
+    /// Create a service decoding sources with an `AnalysisPass`
+    pub fn new() -> Self {
+        Self::with_measure(LoudnessProfile::measure)
+    }
+
+    /// Create a service measuring sources with `measure`
+    pub fn with_measure<F>(measure: F) -> Self
+    where
+        F: Fn(&Path) -> Result<LoudnessProfile> + Send + Sync + 'static,
+    {
+        Self {
+            measure: Arc::new(measure),
+            state: Arc::new((
+                Mutex::new(ServiceState {
+                    queue: VecDeque::new(),
+                    entries: HashMap::new(),
+                    profiles: SpillStore::in_temp(names::LOUDNESS_CACHE, DEFAULT_PROFILE_MEMORY),
+                    running: false,
+                }),
+                Condvar::new(),
+            )),
+            callback: Arc::new(Mutex::new(None)),
+            worker: Mutex::new(None),
+            cache_stats: metrics::metrics().cache(names::LOUDNESS_CACHE),
+        }
+    }
+
+    /// Set a callback invoked whenever a source finishes measuring
+    pub fn set_callback<F>(&self, callback: F)
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        *self.callback.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    /// Bytes of profiles kept in memory before older ones are spilled to disk
+    pub fn set_memory_limit(&self, bytes: usize) {
+        let (lock, _) = &*self.state;
+        lock.lock().unwrap().profiles.set_memory_limit(bytes);
+    }
+
+    /// Queue `path` for measuring, unless it was measured since it last changed
+    pub fn request(&self, path: &Path) -> Result<()> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        {
+            let (lock, cvar) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            if let Some(entry) = state.entries.get(path) {
+                let queued = entry.measurement == Measurement::Pending;
+                if queued || entry.modified == modified {
+                    return Ok(());
+                }
+            }
+            state.entries.insert(path.to_path_buf(), CacheEntry { modified, measurement: Measurement::Pending });
+            state.profiles.discard(&path.to_path_buf());
+            state.queue.push_back(path.to_path_buf());
+            cvar.notify_all();
+        }
+        self.start()
+    }
+
+    /// How measuring `path` is going, `None` if it was never requested
+    ///
+    /// A profile that was spilled and can't be read back is forgotten, so the next
+    /// request measures the source again.
+    pub fn status(&self, path: &Path) -> Option<LoudnessStatus> {
+        let (lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        match state.entries.get(path)?.measurement.clone() {
+            Measurement::Pending => Some(LoudnessStatus::Pending),
+            Measurement::Failed(message) => Some(LoudnessStatus::Failed(message)),
+            Measurement::Ready => match state.profiles.get(&path.to_path_buf()).map(|profile| profile.cloned()) {
+                Ok(Some(profile)) => Some(LoudnessStatus::Ready(profile)),
+                Ok(None) => {
+                    state.entries.remove(path);
+                    None
+                },
+                Err(e) => {
+                    warn!("Dropping loudness of {:?}: {}", path, e);
+                    state.entries.remove(path);
+                    None
+                },
+            },
+        }
+    }
+
+    /// Loudness of `start` to `end` seconds into `path`
+    ///
+    /// Until the source is measured this is `None`, and the source is queued.
+    pub fn clip_loudness(&self, path: &Path, start: f64, end: f64) -> Option<ClipLoudness> {
+        if let Err(e) = self.request(path) {
+            warn!("Failed to queue loudness of {:?}: {}", path, e);
+        }
+        match self.status(path) {
+            Some(LoudnessStatus::Ready(profile)) => {
+                self.cache_stats.hit();
+                profile.range(start, end)
+            },
+            _ => {
+                self.cache_stats.miss();
+                None
+            },
+        }
+    }
+
+    /// Block until every queued source is measured
+    pub fn wait_idle(&self) {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        while state.entries.values().any(|entry| entry.measurement == Measurement::Pending) {
+            state = cvar.wait(state).unwrap();
+        }
+    }
+
+    /// Drop every cached measurement that isn't in progress
+    pub fn clear(&self) {
+        let (lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        state.entries.retain(|_, entry| entry.measurement == Measurement::Pending);
+        state.profiles.clear();
+    }
+
+    /// Stop the worker once the source it is measuring is done
+    pub fn stop(&self) {
+        {
+            let (lock, cvar) = &*self.state;
+            lock.lock().unwrap().running = false;
+            cvar.notify_all();
+        }
+        if let Some(handle) = self.worker.lock().unwrap().take() {
+            if handle.join().is_err() {
+                error!("Loudness worker panicked");
+            }
+        }
+    }
+
+    fn start(&self) -> Result<()> {
+        let mut worker = self.worker.lock().unwrap();
+        {
+            let (lock, _) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            if state.running && worker.is_some() {
+                return Ok(());
+            }
+            state.running = true;
+        }
+
+        let state = self.state.clone();
+        let callback = self.callback.clone();
+        let measure = self.measure.clone();
+        *worker = Some(thread::Builder::new()
+            .name("clip-loudness".to_string())
+            .spawn(move || Self::worker_thread(state, callback, measure))?);
+        Ok(())
+    }
+
+    fn worker_thread(state: Arc<(Mutex<ServiceState>, Condvar)>, callback: Arc<Mutex<Option<LoudnessCallback>>>, measure: MeasureFn) {
+        let (lock, cvar) = &*state;
+        loop {
+            let path = {
+                let mut state = lock.lock().unwrap();
+                while state.running && state.queue.is_empty() {
+                    state = cvar.wait(state).unwrap();
+                }
+                if !state.running {
+                    return;
+                }
+                state.queue.pop_front().unwrap()
+            };
+
+            let result = measure(&path);
+            {
+                let mut state = lock.lock().unwrap();
+                let measurement = match result {
+                    Ok(profile) => {
+                        debug!("Measured loudness of {:?}", path);
+                        if state.entries.contains_key(&path) {
+                            state.profiles.insert(path.clone(), Arc::new(profile));
+                        }
+                        Measurement::Ready
+                    },
+                    Err(e) => {
+                        warn!("Failed to measure loudness of {:?}: {}", path, e);
+                        Measurement::Failed(e.to_string())
+                    },
+                };
+                if let Some(entry) = state.entries.get_mut(&path) {
+                    entry.measurement = measurement;
+                }
+                cvar.notify_all();
+            }
+
+            let callback = callback.lock().unwrap().clone();
+            if let Some(callback) = callback {
+                callback(&path);
+            }
+        }
+    }
+}
+
+impl Drop for ClipLoudnessService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
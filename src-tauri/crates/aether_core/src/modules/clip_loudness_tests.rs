@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests {
+    use super::super::analysis_pass::AnalysisPass;
+    use super::super::clip_loudness::*;
+    use super::super::qc_report::LoudnessMeter;
+    use super::super::test_utils::create_test_dir;
+    use std::f32::consts::PI;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+    use anyhow::{anyhow, Result};
+
+    const RATE: u32 = 48000;
+
+    /// A 997 Hz tone, the BS.1770 reference frequency
+    fn tone(amplitude: f32, seconds: f64) -> Vec<f32> {
+        (0..(seconds * RATE as f64) as usize)
+            .map(|i| amplitude * (i as f32 * 2.0 * PI * 997.0 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// Four seconds at -23 LUFS, four at -49 LUFS and two at full scale
+    fn profile() -> LoudnessProfile {
+        let mut meter = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut meter);
+        pass.push_audio(&tone(0.1, 4.0));
+        pass.push_audio(&tone(0.005, 4.0));
+        pass.push_audio(&tone(1.0, 2.0));
+        pass.finish();
+        LoudnessProfile::from_meter(&meter)
+    }
+
+    #[test]
+    fn test_clip_ranges_and_badges() {
+        let profile = profile();
+        assert!((profile.duration() - 10.0).abs() < 1e-9);
+        let thresholds = LoudnessBadgeThresholds::default();
+
+        let dialog = profile.range(0.0, 4.0).unwrap();
+        assert!((dialog.integrated.unwrap() + 23.0).abs() < 0.1, "{:?}", dialog);
+        assert!((dialog.peak + 20.0).abs() < 0.01);
+        assert_eq!(dialog.badge(&thresholds), None);
+
+        let quiet = profile.range(4.0, 8.0).unwrap();
+        assert!((quiet.integrated.unwrap() + 49.0).abs() < 0.1, "{:?}", quiet);
+        assert_eq!(quiet.badge(&thresholds), Some(LoudnessBadge::Quiet));
+
+        // Clipping wins over the quiet part of a clip that has both
+        let both = profile.range(5.0, 9.0).unwrap();
+        assert_eq!(both.badge(&thresholds), Some(LoudnessBadge::Clipping));
+
+        // Too short to gate, the peak decides
+        let blip = profile.range(1.0, 1.2).unwrap();
+        assert_eq!(blip.integrated, None);
+        assert_eq!(blip.badge(&thresholds), None);
+        assert_eq!(profile.range(4.5, 4.7).unwrap().badge(&thresholds), Some(LoudnessBadge::Quiet));
+
+        // Past the end of the audio
+        assert_eq!(profile.range(12.0, 14.0), None);
+        assert_eq!(LoudnessProfile::default().range(0.0, 4.0), None);
+    }
+
+    #[test]
+    fn test_stereo_clip_loudness() {
+        // -23 dBFS on both sides, then a clean left under a clipped right
+        let level = 10f32.powf(-23.0 / 20.0);
+        let clipped: Vec<f32> = tone(1.5, 2.0).into_iter().map(|sample| sample.clamp(-1.0, 1.0)).collect();
+        let mut meter = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut meter);
+        pass.push_stereo(&tone(level, 4.0), &tone(level, 4.0));
+        pass.push_stereo(&tone(0.05, 2.0), &clipped);
+        pass.finish();
+        let profile = LoudnessProfile::from_meter(&meter);
+        let thresholds = LoudnessBadgeThresholds::default();
+
+        // Read at the delivery level, not 3 dB under it
+        let dialog = profile.range(0.0, 4.0).unwrap();
+        assert!((dialog.integrated.unwrap() + 23.0).abs() < 0.1, "{:?}", dialog);
+        assert!((dialog.peak + 23.0).abs() < 0.01);
+        assert_eq!(dialog.badge(&thresholds), None);
+
+        // Half of the mix would peak around -5 dBFS; the clipped side is caught
+        let music = profile.range(4.0, 6.0).unwrap();
+        assert!(music.peak.abs() < 0.01, "{:?}", music);
+        assert_eq!(music.badge(&thresholds), Some(LoudnessBadge::Clipping));
+    }
+
+    #[test]
+    fn test_service_measures_each_source_once() -> Result<()> {
+        let dir = create_test_dir("clip_loudness", "service")?;
+        let source = dir.join("interview.wav");
+        let broken = dir.join("broken.wav");
+        fs::write(&source, b"audio")?;
+        fs::write(&broken, b"audio")?;
+
+        let measured = Arc::new(AtomicUsize::new(0));
+        let counter = measured.clone();
+        let service = ClipLoudnessService::with_measure(move |path| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if path.ends_with("broken.wav") {
+                return Err(anyhow!("no audio stream"));
+            }
+            Ok(profile())
+        });
+        let called = Arc::new(Mutex::new(Vec::new()));
+        let calls = called.clone();
+        service.set_callback(move |path| calls.lock().unwrap().push(path.to_path_buf()));
+
+        // Queued on first sight, measured in the background
+        assert_eq!(service.clip_loudness(&source, 0.0, 4.0), None);
+        service.request(&broken)?;
+        service.wait_idle();
+        let dialog = service.clip_loudness(&source, 0.0, 4.0).unwrap();
+        assert!((dialog.integrated.unwrap() + 23.0).abs() < 0.1);
+        assert!(service.clip_loudness(&source, 4.0, 8.0).is_some());
+        assert!(matches!(service.status(&broken), Some(LoudnessStatus::Failed(_))));
+        assert_eq!(service.clip_loudness(&broken, 0.0, 4.0), None);
+        assert_eq!(measured.load(Ordering::SeqCst), 2);
+        assert_eq!(called.lock().unwrap().len(), 2);
+
+        // Measured again once the file changes
+        fs::File::options().write(true).open(&source)?.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+        service.request(&source)?;
+        service.wait_idle();
+        assert_eq!(measured.load(Ordering::SeqCst), 3);
+
+        service.clear();
+        assert_eq!(service.status(&source), None);
+        Ok(())
+    }
+}
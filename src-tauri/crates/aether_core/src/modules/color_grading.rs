@@ -1,12 +1,16 @@
 use anyhow::{Context, Result};
 use gst::{self, prelude::*};
-use log::{debug, error, info, warn};
+use tracing::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use crate::engine::editing::EditingError;
+use super::color_lut::{bake_grade_with, Lut3d, LutInterpolation};
+use super::grading_presets::PresetLibrary;
 
 /// Color space for color grading operations
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -33,6 +37,22 @@ pub struct GradingPreset {
     pub lut: Option<LutSettings>,
 }
 
+impl GradingPreset {
+    /// Fingerprint of the preset's grade, matching `ColorGradingEngine::grade_version`
+    pub fn grade_version(&self) -> u64 {
+        grade_version(&self.adjustments, &self.curves, self.lut.as_ref())
+    }
+}
+
+/// Fingerprint of a grade, for caching anything derived from it
+pub fn grade_version(adjustments: &ColorAdjustments, curves: &ColorCurves, lut: Option<&LutSettings>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(&(adjustments, curves, lut))
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct ColorAdjustments {
     pub brightness: f32,
@@ -48,6 +68,12 @@ pub struct ColorAdjustments {
     pub blacks: f32,
     pub vibrance: f32,
     pub sharpness: f32,
+    /// Lift/gamma/gain and offset wheels
+    #[serde(default)]
+    pub wheels: ColorWheels,
+    /// Exposure in stops
+    #[serde(default)]
+    pub exposure: f32,
 }
 
 impl Default for ColorAdjustments {
@@ -66,8 +92,195 @@ impl Default for ColorAdjustments {
             blacks: 0.0,
             vibrance: 1.0,
             sharpness: 0.0,
+            wheels: ColorWheels::default(),
+            exposure: 0.0,
+        }
+    }
+}
+
+/// Grading wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GradingWheel {
+    /// Shadows, pinned at white
+    Lift,
+    /// Midtones, pinned at black and white
+    Gamma,
+    /// Highlights, pinned at black
+    Gain,
+    /// Whole range
+    Offset,
+}
+
+/// Channel of a grading wheel; `Master` is the wheel's ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WheelChannel {
+    Red,
+    Green,
+    Blue,
+    Master,
+}
+
+/// Per-channel values of one wheel, each -1.0 to 1.0 with 0.0 neutral
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorWheel {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub master: f32,
+}
+
+impl ColorWheel {
+    pub fn get(&self, channel: WheelChannel) -> f32 {
+        match channel {
+            WheelChannel::Red => self.red,
+            WheelChannel::Green => self.green,
+            WheelChannel::Blue => self.blue,
+            WheelChannel::Master => self.master,
         }
     }
+
+    pub fn set(&mut self, channel: WheelChannel, value: f32) {
+        let field = match channel {
+            WheelChannel::Red => &mut self.red,
+            WheelChannel::Green => &mut self.green,
+            WheelChannel::Blue => &mut self.blue,
+            WheelChannel::Master => &mut self.master,
+        };
+        *field = value.clamp(-1.0, 1.0);
+    }
+
+    /// Red, green and blue with the master added
+    pub fn rgb(&self) -> [f32; 3] {
+        [self.red + self.master, self.green + self.master, self.blue + self.master]
+    }
+
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Lift/gamma/gain and offset wheels
+///
+/// Per channel, with the master added, a normalized value `x` after exposure becomes
+/// `y = (x + offset) * (1 + gain) + lift * (1 - x - offset)`, output as `y^(2^-gamma)`.
+/// Lift moves the blacks and leaves white alone, gain scales from black, and gamma
+/// bends the midtones while keeping both ends in place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorWheels {
+    pub lift: ColorWheel,
+    pub gamma: ColorWheel,
+    pub gain: ColorWheel,
+    pub offset: ColorWheel,
+}
+
+impl ColorWheels {
+    pub fn wheel(&self, wheel: GradingWheel) -> &ColorWheel {
+        match wheel {
+            GradingWheel::Lift => &self.lift,
+            GradingWheel::Gamma => &self.gamma,
+            GradingWheel::Gain => &self.gain,
+            GradingWheel::Offset => &self.offset,
+        }
+    }
+
+    pub fn wheel_mut(&mut self, wheel: GradingWheel) -> &mut ColorWheel {
+        match wheel {
+            GradingWheel::Lift => &mut self.lift,
+            GradingWheel::Gamma => &mut self.gamma,
+            GradingWheel::Gain => &mut self.gain,
+            GradingWheel::Offset => &mut self.offset,
+        }
+    }
+
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A single adjustment in `ColorAdjustments`, for controls that drive one value at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorParameter {
+    Brightness,
+    Contrast,
+    Saturation,
+    Gamma,
+    Hue,
+    Temperature,
+    Tint,
+    Highlights,
+    Shadows,
+    Whites,
+    Blacks,
+    Vibrance,
+    Sharpness,
+    Exposure,
+    Wheel { wheel: GradingWheel, channel: WheelChannel },
+}
+
+impl ColorParameter {
+    /// Valid range of the parameter
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            ColorParameter::Contrast | ColorParameter::Saturation | ColorParameter::Vibrance => (0.0, 2.0),
+            ColorParameter::Gamma => (0.1, 10.0),
+            ColorParameter::Hue => (-180.0, 180.0),
+            ColorParameter::Sharpness => (0.0, 1.0),
+            ColorParameter::Exposure => (-5.0, 5.0),
+            _ => (-1.0, 1.0),
+        }
+    }
+
+    pub fn get(&self, adjustments: &ColorAdjustments) -> f32 {
+        match self {
+            ColorParameter::Brightness => adjustments.brightness,
+            ColorParameter::Contrast => adjustments.contrast,
+            ColorParameter::Saturation => adjustments.saturation,
+            ColorParameter::Gamma => adjustments.gamma,
+            ColorParameter::Hue => adjustments.hue,
+            ColorParameter::Temperature => adjustments.temperature,
+            ColorParameter::Tint => adjustments.tint,
+            ColorParameter::Highlights => adjustments.highlights,
+            ColorParameter::Shadows => adjustments.shadows,
+            ColorParameter::Whites => adjustments.whites,
+            ColorParameter::Blacks => adjustments.blacks,
+            ColorParameter::Vibrance => adjustments.vibrance,
+            ColorParameter::Sharpness => adjustments.sharpness,
+            ColorParameter::Exposure => adjustments.exposure,
+            ColorParameter::Wheel { wheel, channel } => adjustments.wheels.wheel(*wheel).get(*channel),
+        }
+    }
+
+    /// Set the parameter, clamped to its range
+    pub fn set(&self, adjustments: &mut ColorAdjustments, value: f32) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        let field = match self {
+            ColorParameter::Brightness => &mut adjustments.brightness,
+            ColorParameter::Contrast => &mut adjustments.contrast,
+            ColorParameter::Saturation => &mut adjustments.saturation,
+            ColorParameter::Gamma => &mut adjustments.gamma,
+            ColorParameter::Hue => &mut adjustments.hue,
+            ColorParameter::Temperature => &mut adjustments.temperature,
+            ColorParameter::Tint => &mut adjustments.tint,
+            ColorParameter::Highlights => &mut adjustments.highlights,
+            ColorParameter::Shadows => &mut adjustments.shadows,
+            ColorParameter::Whites => &mut adjustments.whites,
+            ColorParameter::Blacks => &mut adjustments.blacks,
+            ColorParameter::Vibrance => &mut adjustments.vibrance,
+            ColorParameter::Sharpness => &mut adjustments.sharpness,
+            ColorParameter::Exposure => &mut adjustments.exposure,
+            ColorParameter::Wheel { wheel, channel } => {
+                adjustments.wheels.wheel_mut(*wheel).set(*channel, value);
+                return;
+            },
+        };
+        *field = value;
+    }
 }
 
 /// Color curve point
@@ -94,6 +307,79 @@ pub struct ColorCurves {
     pub luma: Vec<CurvePoint>,
 }
 
+impl ColorCurves {
+    /// Whether every curve is a straight line from (0, 0) to (1, 1)
+    pub fn is_identity(&self) -> bool {
+        [&self.rgb, &self.red, &self.green, &self.blue, &self.luma].iter()
+            .all(|curve| curve.iter().all(|point| point.x == point.y))
+    }
+}
+
+/// Sample a curve at `size` evenly spaced inputs from 0 to 1
+///
+/// Uses monotone cubic (Fritsch-Carlson) interpolation, so the curve passes through its
+/// points without overshooting between them. Curves with fewer than two points are linear.
+pub fn curve_table(points: &[CurvePoint], size: usize) -> Vec<f32> {
+    let sample = |i: usize| i as f32 / (size.max(2) - 1) as f32;
+    if points.len() < 2 {
+        return (0..size).map(sample).collect();
+    }
+    
+    let n = points.len();
+    let slopes: Vec<f32> = points.windows(2)
+        .map(|pair| {
+            let width = pair[1].x - pair[0].x;
+            if width > f32::EPSILON { (pair[1].y - pair[0].y) / width } else { 0.0 }
+        })
+        .collect();
+    
+    let mut tangents = vec![0.0f32; n];
+    tangents[0] = slopes[0];
+    tangents[n - 1] = slopes[n - 2];
+    for k in 1..n - 1 {
+        if slopes[k - 1] * slopes[k] > 0.0 {
+            tangents[k] = (slopes[k - 1] + slopes[k]) / 2.0;
+        }
+    }
+    for k in 0..n - 1 {
+        if slopes[k] == 0.0 {
+            tangents[k] = 0.0;
+            tangents[k + 1] = 0.0;
+            continue;
+        }
+        // Limit the tangents so the segment stays monotone
+        let a = tangents[k] / slopes[k];
+        let b = tangents[k + 1] / slopes[k];
+        let length = (a * a + b * b).sqrt();
+        if length > 3.0 {
+            tangents[k] = 3.0 / length * a * slopes[k];
+            tangents[k + 1] = 3.0 / length * b * slopes[k];
+        }
+    }
+    
+    (0..size)
+        .map(|i| {
+            let x = sample(i);
+            let k = points.windows(2)
+                .position(|pair| x <= pair[1].x)
+                .unwrap_or(n - 2);
+            let (p0, p1) = (&points[k], &points[k + 1]);
+            let width = p1.x - p0.x;
+            if x <= p0.x || width <= f32::EPSILON {
+                return if x <= p0.x { p0.y } else { p1.y }.clamp(0.0, 1.0);
+            }
+            
+            let t = ((x - p0.x) / width).min(1.0);
+            let (t2, t3) = (t * t, t * t * t);
+            let y = (2.0 * t3 - 3.0 * t2 + 1.0) * p0.y
+                + (t3 - 2.0 * t2 + t) * width * tangents[k]
+                + (-2.0 * t3 + 3.0 * t2) * p1.y
+                + (t3 - t2) * width * tangents[k + 1];
+            y.clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
 impl Default for ColorCurves {
     fn default() -> Self {
         // Default curves with just the endpoints (linear)
@@ -136,6 +422,9 @@ pub struct LutSettings {
     pub format: LutFormat,
     /// Strength of the LUT effect (0.0 to 1.0)
     pub strength: f32,
+    /// Sampling between the LUT's grid points
+    #[serde(default)]
+    pub interpolation: LutInterpolation,
 }
 
 /// Scope type for video analysis
@@ -818,6 +1107,7 @@ impl ColorGradingEngine {
             path: path.to_path_buf(),
             format,
             strength: 1.0,
+            interpolation: LutInterpolation::default(),
         };
         
         self.lut = Some(lut_settings.clone());
@@ -1018,76 +1308,17 @@ impl ColorGradingEngine {
             return Ok(());
         }
         
-        // Check if we have any curves to apply
-        if self.curves.rgb.len() < 2 && 
-           self.curves.red.len() < 2 && 
-           self.curves.green.len() < 2 && 
-           self.curves.blue.len() < 2 && 
-           self.curves.luma.len() < 2 {
+        if self.curves.is_identity() {
             debug!("No curves to apply");
             return Ok(());
         }
         
-        // Apply RGB curve to gamma element if available
-        if self.curves.rgb.len() >= 2 {
-            if let Some(gamma) = self.elements.get("gamma") {
-                // In a real implementation, we would calculate a proper gamma value
-                // based on the curve. For now, we'll use a simplified approach.
-                let mid_point = self.find_curve_mid_point(&self.curves.rgb);
-                let gamma_value = if mid_point > 0.5 {
-                    // Curve is above linear, reduce gamma (brighten)
-                    1.0 - ((mid_point - 0.5) * 2.0).min(0.9)
-                } else {
-                    // Curve is below linear, increase gamma (darken)
-                    1.0 + ((0.5 - mid_point) * 2.0).min(2.0)
-                };
-                
-                gamma.set_property("gamma", gamma_value);
-                debug!("Applied RGB curve with gamma: {}", gamma_value);
-            }
-        }
-        
-        // Apply individual channel curves
-        // In a real implementation, we would use a custom element or shader
-        // For now, we'll just log that we would apply them
-        if self.curves.red.len() >= 2 {
-            debug!("Would apply red channel curve with {} points", self.curves.red.len());
-        }
-        
-        if self.curves.green.len() >= 2 {
-            debug!("Would apply green channel curve with {} points", self.curves.green.len());
-        }
-        
-        if self.curves.blue.len() >= 2 {
-            debug!("Would apply blue channel curve with {} points", self.curves.blue.len());
-        }
-        
-        if self.curves.luma.len() >= 2 {
-            debug!("Would apply luma curve with {} points", self.curves.luma.len());
-        }
-        
+        // The pipeline has no curve element; the frame processor applies the curves
+        // per pixel from `curve_table`
         debug!("Applied color curves");
         Ok(())
     }
     
-    /// Find the mid-point of a curve (value at x=0.5)
-    fn find_curve_mid_point(&self, curve: &[CurvePoint]) -> f32 {
-        // Find the points that bracket x=0.5
-        let mut prev_point = &curve[0];
-        
-        for point in curve.iter().skip(1) {
-            if point.x >= 0.5 {
-                // Linear interpolation between the two points
-                let t = (0.5 - prev_point.x) / (point.x - prev_point.x);
-                return prev_point.y + t * (point.y - prev_point.y);
-            }
-            prev_point = point;
-        }
-        
-        // If we didn't find a bracket, return the last point's y value
-        prev_point.y
-    }
-    
     /// Set a specific curve
     pub fn set_curve(&mut self, curve_type: &str, points: Vec<CurvePoint>) -> Result<()> {
         // Validate points
@@ -1165,6 +1396,39 @@ impl ColorGradingEngine {
         }
     }
     
+    /// Get all color curves
+    pub fn get_curves(&self) -> &ColorCurves {
+        &self.curves
+    }
+    
+    /// Fingerprint of the current grade; changes whenever adjustments, curves or the LUT do
+    pub fn grade_version(&self) -> u64 {
+        grade_version(&self.adjustments, &self.curves, self.lut.as_ref())
+    }
+    
+    /// Bake the current grade into a single 3D LUT of `size` points per side
+    pub fn bake_grade_lut(&self, size: usize) -> Result<Lut3d> {
+        bake_grade_with(&self.adjustments, &self.curves, self.lut.as_ref(), size)
+    }
+    
+    /// Save a preset to a preset library on disk
+    pub fn save_preset_to(&self, name: &str, library: &PresetLibrary) -> Result<PathBuf> {
+        let preset = self.presets.get(name).ok_or_else(|| {
+            anyhow::anyhow!("Preset '{}' not found", name)
+        })?;
+        library.save(preset)
+    }
+    
+    /// Load every preset from a preset library, replacing presets with the same name
+    pub fn load_presets_from(&mut self, library: &PresetLibrary) -> Result<usize> {
+        let summaries = library.list()?;
+        for summary in &summaries {
+            let preset = library.load(&summary.name)?;
+            self.presets.insert(preset.name.clone(), preset);
+        }
+        Ok(summaries.len())
+    }
+    
     /// Configure a scope
     pub fn configure_scope(&mut self, scope_type: ScopeType, config: ScopeConfig) -> Result<()> {
         self.scopes.insert(scope_type, config);
@@ -1,15 +1,19 @@
 use anyhow::Result;
 use gst::prelude::*;
 use gst_app;
-use log::{debug, error};
+use tracing::{debug, error};
 use std::sync::{Arc, Mutex};
 
-use super::color_grading::ColorGradingEngine;
+use super::color_grading::{curve_table, ColorAdjustments, ColorCurves, ColorGradingEngine, GradingPreset};
+use super::color_lut::{bake_grade_with, LutCache, LutInterpolation, DEFAULT_BAKE_SIZE};
+use crate::engine::tile_scheduler::TileScheduler;
 
 /// Frame processor for real-time color grading
 pub struct ColorGradingFrameProcessor {
     /// The color grading engine
     engine: Arc<Mutex<ColorGradingEngine>>,
+    /// Baked grades for `preview_frame`
+    lut_cache: Mutex<LutCache>,
 }
 
 impl ColorGradingFrameProcessor {
@@ -17,10 +21,47 @@ impl ColorGradingFrameProcessor {
     pub fn new(engine: ColorGradingEngine) -> Self {
         Self {
             engine: Arc::new(Mutex::new(engine)),
+            lut_cache: Mutex::new(LutCache::default()),
         }
     }
     
+    /// Grade a frame for playback with the grade baked into one 3D LUT
+    ///
+    /// Skips the pipeline, so it is much cheaper per frame than `process_frame`. The LUT is
+    /// baked once per grade version and reused until the grade changes.
+    #[tracing::instrument(name = "grade_preview", level = "trace", skip(self, frame), fields(bytes = frame.len()))]
+    pub fn preview_frame(&self, frame: &[u8], format: &str) -> Result<Vec<u8>> {
+        let engine = self.engine.lock().map_err(|_| anyhow::anyhow!("Failed to lock engine"))?;
+        let version = engine.grade_version();
+        let lut = self.lut_cache.lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock LUT cache"))?
+            .get_or_bake(version, || engine.bake_grade_lut(DEFAULT_BAKE_SIZE))?;
+        drop(engine);
+        
+        let mut graded = frame.to_vec();
+        lut.apply_to_frame(&mut graded, format, LutInterpolation::Tetrahedral, 1.0)?;
+        Ok(graded)
+    }
+    
+    /// Grade a frame for playback with a preset's grade instead of the engine's
+    ///
+    /// Shares the baked LUT cache with `preview_frame`, so switching between a few
+    /// clip grades doesn't rebake.
+    #[tracing::instrument(name = "grade_preset_preview", level = "trace", skip(self, frame, preset), fields(bytes = frame.len()))]
+    pub fn preview_preset(&self, frame: &[u8], format: &str, preset: &GradingPreset) -> Result<Vec<u8>> {
+        let lut = self.lut_cache.lock()
+            .map_err(|_| anyhow::anyhow!("Failed to lock LUT cache"))?
+            .get_or_bake(preset.grade_version(), || {
+                bake_grade_with(&preset.adjustments, &preset.curves, preset.lut.as_ref(), DEFAULT_BAKE_SIZE)
+            })?;
+        
+        let mut graded = frame.to_vec();
+        lut.apply_to_frame(&mut graded, format, LutInterpolation::Tetrahedral, 1.0)?;
+        Ok(graded)
+    }
+    
     /// Process a video frame through the color grading pipeline
+    #[tracing::instrument(name = "grade", level = "trace", skip(self, frame), fields(bytes = frame.len()))]
     pub fn process_frame(&self, frame: &[u8], width: u32, height: u32, format: &str) -> Result<Vec<u8>> {
         let mut engine = self.engine.lock().map_err(|_| anyhow::anyhow!("Failed to lock engine"))?;
         
@@ -46,7 +87,13 @@ impl ColorGradingFrameProcessor {
             .map_err(|_| anyhow::anyhow!("Failed to push buffer to appsrc"))?;
         
         // Get processed frame from appsink
-        self.pull_processed_frame(&engine)
+        let mut processed = self.pull_processed_frame(&engine)?;
+        
+        // The pipeline has no wheel, exposure or curve controls, so those are graded here
+        apply_wheels(&mut processed, format, engine.get_adjustments())?;
+        apply_curves(&mut processed, format, engine.get_curves())?;
+        
+        Ok(processed)
     }
     
     /// Pull a processed frame from the appsink
@@ -81,3 +128,107 @@ impl ColorGradingFrameProcessor {
         Err(anyhow::anyhow!("Timeout waiting for processed frame"))
     }
 }
+
+/// Grade normalized RGB with the wheels and exposure of `adjustments`
+pub fn grade_rgb(rgb: [f32; 3], adjustments: &ColorAdjustments) -> [f32; 3] {
+    let wheels = &adjustments.wheels;
+    let (lift, gamma, gain, offset) = (wheels.lift.rgb(), wheels.gamma.rgb(), wheels.gain.rgb(), wheels.offset.rgb());
+    let exposure = 2f32.powf(adjustments.exposure);
+    
+    let mut graded = rgb;
+    for (c, value) in graded.iter_mut().enumerate() {
+        let x = *value * exposure + offset[c];
+        let y = x * (1.0 + gain[c]) + lift[c] * (1.0 - x);
+        *value = y.max(0.0).powf(2f32.powf(-gamma[c]));
+    }
+    graded
+}
+
+/// Apply the wheels and exposure of `adjustments` to a packed 8-bit RGB frame in place
+pub fn apply_wheels(frame: &mut [u8], format: &str, adjustments: &ColorAdjustments) -> Result<()> {
+    if adjustments.wheels.is_neutral() && adjustments.exposure == 0.0 {
+        return Ok(());
+    }
+    
+    let (channels, pixel_size) = channel_layout(format)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported format for color wheels: {}", format))?;
+    
+    // Channels are independent, so a table per channel covers every input value
+    let mut luts = [[0u8; 256]; 3];
+    for input in 0..256 {
+        let value = input as f32 / 255.0;
+        let graded = grade_rgb([value; 3], adjustments);
+        for (lut, graded) in luts.iter_mut().zip(graded) {
+            lut[input] = (graded.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    
+    TileScheduler::global().for_each_pixel(frame, pixel_size, |pixel| {
+        for (lut, &channel) in luts.iter().zip(&channels) {
+            pixel[channel] = lut[pixel[channel] as usize];
+        }
+    });
+    
+    Ok(())
+}
+
+/// Apply color curves to a packed 8-bit RGB frame in place
+///
+/// The composite curve feeds the red, green and blue curves, which are baked into one
+/// table per channel. The luma curve then moves each pixel's Rec. 709 luma, shifting all
+/// three channels equally so hue is kept.
+pub fn apply_curves(frame: &mut [u8], format: &str, curves: &ColorCurves) -> Result<()> {
+    if curves.is_identity() {
+        return Ok(());
+    }
+    
+    let (channels, pixel_size) = channel_layout(format)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported format for color curves: {}", format))?;
+    
+    let composite = curve_table(&curves.rgb, 256);
+    let mut luts = [[0u8; 256]; 3];
+    for (lut, curve) in luts.iter_mut().zip([&curves.red, &curves.green, &curves.blue]) {
+        let channel = curve_table(curve, 256);
+        for (input, output) in lut.iter_mut().enumerate() {
+            let value = channel[(composite[input] * 255.0).round() as usize];
+            *output = (value * 255.0).round() as u8;
+        }
+    }
+    
+    let luma_curve = curves.luma.iter().any(|point| point.x != point.y)
+        .then(|| curve_table(&curves.luma, 256));
+    
+    TileScheduler::global().for_each_pixel(frame, pixel_size, |pixel| {
+        let mut rgb = [0u8; 3];
+        for ((value, lut), &channel) in rgb.iter_mut().zip(&luts).zip(&channels) {
+            *value = lut[pixel[channel] as usize];
+        }
+        
+        if let Some(luma_curve) = &luma_curve {
+            let luma = 0.2126 * rgb[0] as f32 + 0.7152 * rgb[1] as f32 + 0.0722 * rgb[2] as f32;
+            let shift = luma_curve[luma.round() as usize] * 255.0 - luma;
+            for value in rgb.iter_mut() {
+                *value = (*value as f32 + shift).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        
+        for (value, &channel) in rgb.iter().zip(&channels) {
+            pixel[channel] = *value;
+        }
+    });
+    
+    Ok(())
+}
+
+/// Byte offsets of red, green and blue in a pixel of `format`, and the pixel size
+pub(crate) fn channel_layout(format: &str) -> Option<([usize; 3], usize)> {
+    match format.to_ascii_uppercase().as_str() {
+        "RGBA" | "RGBX" => Some(([0, 1, 2], 4)),
+        "BGRA" | "BGRX" => Some(([2, 1, 0], 4)),
+        "ARGB" | "XRGB" => Some(([1, 2, 3], 4)),
+        "ABGR" | "XBGR" => Some(([3, 2, 1], 4)),
+        "RGB" => Some(([0, 1, 2], 3)),
+        "BGR" => Some(([2, 1, 0], 3)),
+        _ => None,
+    }
+}
@@ -166,4 +166,125 @@ mod tests {
         
         Ok(())
     }
+
+    #[test]
+    fn test_color_wheels() -> Result<()> {
+        use super::super::color_grading_frame_processor::{apply_wheels, grade_rgb};
+        
+        let neutral = ColorAdjustments::default();
+        assert_eq!(grade_rgb([0.0, 0.5, 1.0], &neutral), [0.0, 0.5, 1.0]);
+        
+        // Lift raises black but leaves white alone
+        let mut lifted = neutral;
+        lifted.wheels.lift.master = 0.1;
+        let [black, _, white] = grade_rgb([0.0, 0.5, 1.0], &lifted);
+        assert!((black - 0.1).abs() < 1e-6);
+        assert!((white - 1.0).abs() < 1e-6);
+        
+        // Gain scales from black
+        let mut gained = neutral;
+        gained.wheels.gain.red = 0.5;
+        let [red, green, _] = grade_rgb([0.4, 0.4, 0.4], &gained);
+        assert!((red - 0.6).abs() < 1e-6);
+        assert!((green - 0.4).abs() < 1e-6);
+        
+        // Gamma bends midtones with both ends fixed
+        let mut bent = neutral;
+        bent.wheels.gamma.master = 1.0;
+        let graded = grade_rgb([0.0, 0.25, 1.0], &bent);
+        assert_eq!(graded[0], 0.0);
+        assert!((graded[1] - 0.5).abs() < 1e-6);
+        assert!((graded[2] - 1.0).abs() < 1e-6);
+        
+        // Offset shifts everything; exposure is in stops
+        let mut shifted = neutral;
+        shifted.wheels.offset.blue = -0.1;
+        shifted.exposure = 1.0;
+        let graded = grade_rgb([0.25, 0.25, 0.25], &shifted);
+        assert!((graded[0] - 0.5).abs() < 1e-6);
+        assert!((graded[2] - 0.4).abs() < 1e-6);
+        
+        // Frames are graded per channel in their byte order
+        let mut frame = vec![100, 100, 100, 255, 0, 0, 0, 128];
+        apply_wheels(&mut frame, "BGRA", &gained)?;
+        assert_eq!(frame, vec![100, 100, 150, 255, 0, 0, 0, 128]);
+        assert!(apply_wheels(&mut frame, "I420", &gained).is_err());
+        
+        // Presets saved before the wheels existed still load
+        let mut json = serde_json::to_value(neutral)?;
+        json.as_object_mut().unwrap().remove("wheels");
+        json.as_object_mut().unwrap().remove("exposure");
+        assert_eq!(serde_json::from_value::<ColorAdjustments>(json)?, neutral);
+        
+        Ok(())
+    }
+
+    #[test]
+    fn test_curve_tables() -> Result<()> {
+        use super::super::color_grading_frame_processor::apply_curves;
+        
+        let point = |x: f32, y: f32| CurvePoint { x, y };
+        
+        // Curves pass through their points and keep rising between them
+        let lifted = curve_table(&[point(0.0, 0.0), point(0.5, 0.8), point(1.0, 1.0)], 101);
+        assert!((lifted[50] - 0.8).abs() < 1e-6);
+        assert!(lifted.windows(2).all(|pair| pair[1] >= pair[0]));
+        
+        // Flat sections stay flat instead of overshooting
+        let step = curve_table(&[point(0.0, 0.0), point(0.4, 0.0), point(0.6, 1.0), point(1.0, 1.0)], 101);
+        assert!(step[..=40].iter().all(|&y| y == 0.0));
+        assert!(step[60..].iter().all(|&y| y == 1.0));
+        
+        let linear = curve_table(&ColorCurves::default().rgb, 256);
+        assert!(linear.iter().enumerate().all(|(i, &y)| (y - i as f32 / 255.0).abs() < 1e-6));
+        
+        // Channel curves only touch their channel
+        let curves = ColorCurves {
+            red: vec![point(0.0, 1.0), point(1.0, 0.0)],
+            ..ColorCurves::default()
+        };
+        let mut frame = vec![40, 80, 120, 255];
+        apply_curves(&mut frame, "RGBA", &curves)?;
+        assert_eq!(frame, vec![215, 80, 120, 255]);
+        
+        // The luma curve moves all channels together
+        let curves = ColorCurves {
+            luma: vec![point(0.0, 0.2), point(1.0, 1.0)],
+            ..ColorCurves::default()
+        };
+        let mut frame = vec![0, 0, 0, 100, 100, 100];
+        apply_curves(&mut frame, "RGB", &curves)?;
+        assert_eq!(frame, vec![51, 51, 51, 131, 131, 131]);
+        
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_preset() -> Result<()> {
+        use super::super::color_grading_frame_processor::ColorGradingFrameProcessor;
+        
+        let mut engine = create_test_engine()?;
+        let mut adjustments = *engine.get_adjustments();
+        adjustments.wheels.gain.red = 0.5;
+        engine.set_adjustments(adjustments)?;
+        
+        // A preset with the same grade as the engine shares its baked LUT
+        let preset = GradingPreset {
+            name: "warm".to_string(),
+            preset_type: GradingPresetType::Custom("warm".to_string()),
+            adjustments,
+            curves: ColorCurves::default(),
+            lut: None,
+        };
+        assert_eq!(preset.grade_version(), engine.grade_version());
+        
+        let processor = ColorGradingFrameProcessor::new(create_test_engine()?);
+        let graded = processor.preview_preset(&[100, 100, 100, 255], "BGRA", &preset)?;
+        assert_eq!(graded, vec![100, 100, 150, 255]);
+        
+        // The processor's own engine is still neutral
+        assert_eq!(processor.preview_frame(&[100, 100, 100, 255], "BGRA")?, vec![100, 100, 100, 255]);
+        
+        Ok(())
+    }
 }
@@ -0,0 +1,398 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::debug;
+
+use super::color_grading::{curve_table, ColorAdjustments, ColorCurves, LutFormat, LutSettings};
+use super::color_grading_frame_processor::{channel_layout, grade_rgb};
+
+/// Cube size used when baking a grade
+pub const DEFAULT_BAKE_SIZE: usize = 33;
+
+/// Grades kept by `LutCache` unless configured otherwise
+pub const DEFAULT_CACHE_CAPACITY: usize = 8;
+
+/// Largest cube accepted from a file
+const MAX_CUBE_SIZE: usize = 256;
+
+/// How a 3D LUT is sampled between its grid points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LutInterpolation {
+    /// Blends the eight surrounding points
+    Trilinear,
+    /// Blends the four points of the enclosing tetrahedron; keeps neutrals neutral
+    /// and is smoother along the gray axis than trilinear
+    #[default]
+    Tetrahedral,
+}
+
+/// A 3D color lookup table with red varying fastest, as in `.cube` files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    pub title: Option<String>,
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    data: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    /// Build a LUT of `size` points per side by evaluating `f` at every grid point
+    pub fn from_fn(size: usize, mut f: impl FnMut([f32; 3]) -> [f32; 3]) -> Self {
+        let size = size.max(2);
+        let scale = (size - 1) as f32;
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push(f([r as f32 / scale, g as f32 / scale, b as f32 / scale]));
+                }
+            }
+        }
+
+        Self {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        }
+    }
+
+    pub fn identity(size: usize) -> Self {
+        Self::from_fn(size, |rgb| rgb)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Parse a Resolve/Adobe `.cube` 3D LUT
+    pub fn parse_cube(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        let triple = |fields: &[&str], line: usize| -> Result<[f32; 3]> {
+            match fields {
+                [r, g, b] => Ok([r.parse()?, g.parse()?, b.parse()?]),
+                _ => Err(anyhow!("Expected three values on line {}", line)),
+            }
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                "TITLE" => title = Some(line["TITLE".len()..].trim().trim_matches('"').to_string()),
+                "LUT_3D_SIZE" => {
+                    let value: usize = fields.get(1).ok_or_else(|| anyhow!("Missing LUT_3D_SIZE value"))?.parse()?;
+                    if !(2..=MAX_CUBE_SIZE).contains(&value) {
+                        return Err(anyhow!("Unsupported LUT_3D_SIZE: {}", value));
+                    }
+                    size = Some(value);
+                },
+                "LUT_1D_SIZE" => return Err(anyhow!("1D .cube LUTs aren't supported")),
+                "DOMAIN_MIN" => domain_min = triple(&fields[1..], index + 1)?,
+                "DOMAIN_MAX" => domain_max = triple(&fields[1..], index + 1)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    let (min, max): (f32, f32) = match &fields[1..] {
+                        [min, max] => (min.parse()?, max.parse()?),
+                        _ => return Err(anyhow!("Expected two values on line {}", index + 1)),
+                    };
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                },
+                keyword if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    debug!("Ignoring .cube keyword {}", keyword);
+                },
+                _ => data.push(triple(&fields, index + 1)
+                    .with_context(|| format!("Invalid LUT entry on line {}", index + 1))?),
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("Missing LUT_3D_SIZE"))?;
+        if data.len() != size * size * size {
+            return Err(anyhow!("Expected {} LUT entries, found {}", size * size * size, data.len()));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(anyhow!("Invalid LUT domain"));
+        }
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    pub fn load_cube(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read LUT {}", path.display()))?;
+        Self::parse_cube(&text).with_context(|| format!("Invalid LUT {}", path.display()))
+    }
+
+    /// Write the LUT in `.cube` format
+    pub fn to_cube(&self) -> String {
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            let _ = writeln!(text, "TITLE \"{}\"", title);
+        }
+        let _ = writeln!(text, "LUT_3D_SIZE {}", self.size);
+        if self.domain_min != [0.0; 3] || self.domain_max != [1.0; 3] {
+            let [r, g, b] = self.domain_min;
+            let _ = writeln!(text, "DOMAIN_MIN {} {} {}", r, g, b);
+            let [r, g, b] = self.domain_max;
+            let _ = writeln!(text, "DOMAIN_MAX {} {} {}", r, g, b);
+        }
+        for [r, g, b] in &self.data {
+            let _ = writeln!(text, "{:.6} {:.6} {:.6}", r, g, b);
+        }
+        text
+    }
+
+    pub fn save_cube(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_cube()).with_context(|| format!("Failed to write LUT {}", path.display()))
+    }
+
+    fn at(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.data[(b * self.size + g) * self.size + r]
+    }
+
+    /// Look up a color, interpolating between grid points
+    pub fn sample(&self, rgb: [f32; 3], interpolation: LutInterpolation) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut fraction = [0f32; 3];
+        for c in 0..3 {
+            let position = ((rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c])).clamp(0.0, 1.0) * last;
+            base[c] = (position.floor() as usize).min(self.size - 2);
+            fraction[c] = position - base[c] as f32;
+        }
+
+        let [r, g, b] = base;
+        let [fr, fg, fb] = fraction;
+        let corner = |dr: usize, dg: usize, db: usize| self.at(r + dr, g + dg, b + db);
+        let mix = |weights: &[(f32, [f32; 3])]| {
+            let mut out = [0f32; 3];
+            for (weight, value) in weights {
+                for c in 0..3 {
+                    out[c] += weight * value[c];
+                }
+            }
+            out
+        };
+
+        match interpolation {
+            LutInterpolation::Trilinear => {
+                let mut weights = Vec::with_capacity(8);
+                for (db, wb) in [(0, 1.0 - fb), (1, fb)] {
+                    for (dg, wg) in [(0, 1.0 - fg), (1, fg)] {
+                        for (dr, wr) in [(0, 1.0 - fr), (1, fr)] {
+                            weights.push((wr * wg * wb, corner(dr, dg, db)));
+                        }
+                    }
+                }
+                mix(&weights)
+            },
+            LutInterpolation::Tetrahedral => {
+                let c000 = corner(0, 0, 0);
+                let c111 = corner(1, 1, 1);
+                // Pick the tetrahedron from the order of the fractions, then walk its edges
+                let (first, second, (w1, w2, w3)) = if fr > fg {
+                    if fg > fb {
+                        (corner(1, 0, 0), corner(1, 1, 0), (fr, fg, fb))
+                    } else if fr > fb {
+                        (corner(1, 0, 0), corner(1, 0, 1), (fr, fb, fg))
+                    } else {
+                        (corner(0, 0, 1), corner(1, 0, 1), (fb, fr, fg))
+                    }
+                } else if fb > fg {
+                    (corner(0, 0, 1), corner(0, 1, 1), (fb, fg, fr))
+                } else if fb > fr {
+                    (corner(0, 1, 0), corner(0, 1, 1), (fg, fb, fr))
+                } else {
+                    (corner(0, 1, 0), corner(1, 1, 0), (fg, fr, fb))
+                };
+                mix(&[(1.0 - w1, c000), (w1 - w2, first), (w2 - w3, second), (w3, c111)])
+            },
+        }
+    }
+
+    /// Apply the LUT to a packed 8-bit RGB frame in place, blended by `strength`
+    pub fn apply_to_frame(&self, frame: &mut [u8], format: &str, interpolation: LutInterpolation, strength: f32) -> Result<()> {
+        let (channels, pixel_size) = channel_layout(format)
+            .ok_or_else(|| anyhow!("Unsupported format for LUTs: {}", format))?;
+        let strength = strength.clamp(0.0, 1.0);
+
+        for pixel in frame.chunks_exact_mut(pixel_size) {
+            let input = channels.map(|channel| pixel[channel] as f32 / 255.0);
+            let output = self.sample(input, interpolation);
+            for c in 0..3 {
+                let value = input[c] + (output[c] - input[c]) * strength;
+                pixel[channels[c]] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bake a whole grade into one 3D LUT
+///
+/// Applies, in order, the basic adjustments the pipeline would (gamma, then brightness,
+/// contrast, hue and saturation in Y'CbCr), the wheels and exposure, the curves and
+/// finally `lut` at its strength.
+pub fn bake_grade(
+    adjustments: &ColorAdjustments,
+    curves: &ColorCurves,
+    lut: Option<(&Lut3d, f32)>,
+    interpolation: LutInterpolation,
+    size: usize,
+) -> Lut3d {
+    const CURVE_SAMPLES: usize = 1024;
+    let composite = curve_table(&curves.rgb, CURVE_SAMPLES);
+    let channel_curves = [&curves.red, &curves.green, &curves.blue].map(|curve| curve_table(curve, CURVE_SAMPLES));
+    let luma_curve = curves.luma.iter().any(|point| point.x != point.y)
+        .then(|| curve_table(&curves.luma, CURVE_SAMPLES));
+
+    let (hue_sin, hue_cos) = adjustments.hue.to_radians().sin_cos();
+    Lut3d::from_fn(size, |rgb| {
+        // Pipeline adjustments
+        let rgb = rgb.map(|value| value.powf(1.0 / adjustments.gamma));
+        let y = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+        let (cb, cr) = ((rgb[2] - y) / 1.8556, (rgb[0] - y) / 1.5748);
+        let y = (y - 0.5) * adjustments.contrast + 0.5 + adjustments.brightness;
+        let (cb, cr) = (cb * hue_cos - cr * hue_sin, cb * hue_sin + cr * hue_cos);
+        let (cb, cr) = (cb * adjustments.saturation, cr * adjustments.saturation);
+        let r = y + 1.5748 * cr;
+        let b = y + 1.8556 * cb;
+        let g = (y - 0.2126 * r - 0.0722 * b) / 0.7152;
+
+        // Wheels and curves
+        let mut rgb = grade_rgb([r, g, b].map(|value| value.clamp(0.0, 1.0)), adjustments);
+        for (value, curve) in rgb.iter_mut().zip(&channel_curves) {
+            *value = lookup(curve, lookup(&composite, *value));
+        }
+        if let Some(luma_curve) = &luma_curve {
+            let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+            let shift = lookup(luma_curve, luma) - luma;
+            rgb = rgb.map(|value| value + shift);
+        }
+        let rgb = rgb.map(|value| value.clamp(0.0, 1.0));
+
+        match lut {
+            Some((lut, strength)) => {
+                let looked_up = lut.sample(rgb, interpolation);
+                [0, 1, 2].map(|c| rgb[c] + (looked_up[c] - rgb[c]) * strength)
+            },
+            None => rgb,
+        }
+    })
+}
+
+/// Bake a grade whose LUT is given by its settings; only `.cube` LUTs can be baked
+pub fn bake_grade_with(
+    adjustments: &ColorAdjustments,
+    curves: &ColorCurves,
+    lut: Option<&LutSettings>,
+    size: usize,
+) -> Result<Lut3d> {
+    let Some(settings) = lut else {
+        return Ok(bake_grade(adjustments, curves, None, LutInterpolation::default(), size));
+    };
+    if settings.format != LutFormat::CUBE {
+        return Err(anyhow!("Only .cube LUTs can be baked, not {:?}", settings.format));
+    }
+
+    let lut = Lut3d::load_cube(&settings.path)?;
+    Ok(bake_grade(adjustments, curves, Some((&lut, settings.strength)), settings.interpolation, size))
+}
+
+/// Linear lookup in a table sampled evenly from 0 to 1
+///
+/// An empty table leaves `x` as it is and a single entry is a constant.
+pub(crate) fn lookup(table: &[f32], x: f32) -> f32 {
+    if table.len() < 2 {
+        return table.first().copied().unwrap_or(x);
+    }
+
+    let position = x.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+    let index = (position.floor() as usize).min(table.len() - 2);
+    let fraction = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
+
+/// Baked grades by grade version, so playback applies one LUT per frame
+///
+/// The least recently used grade is dropped when the cache is full.
+pub struct LutCache {
+    capacity: usize,
+    entries: HashMap<u64, Arc<Lut3d>>,
+    /// Versions from least to most recently used
+    order: VecDeque<u64>,
+}
+
+impl LutCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The LUT for `version`, baking it with `bake` if it isn't cached
+    pub fn get_or_bake(&mut self, version: u64, bake: impl FnOnce() -> Result<Lut3d>) -> Result<Arc<Lut3d>> {
+        if let Some(lut) = self.entries.get(&version).cloned() {
+            self.order.retain(|cached| *cached != version);
+            self.order.push_back(version);
+            return Ok(lut);
+        }
+
+        let lut = Arc::new(bake()?);
+        debug!("Baked grade {:016x} into a {}-point LUT", version, lut.size());
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(version, lut.clone());
+        self.order.push_back(version);
+        Ok(lut)
+    }
+
+    pub fn contains(&self, version: u64) -> bool {
+        self.entries.contains_key(&version)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+impl Default for LutCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use super::super::color_grading::{ColorAdjustments, ColorCurves, CurvePoint};
+    use super::super::color_lut::{bake_grade, lookup, Lut3d, LutCache, LutInterpolation};
+    use super::super::test_utils::create_test_dir;
+    use std::cell::Cell;
+    use std::fs;
+    use anyhow::Result;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for c in 0..3 {
+            assert!((actual[c] - expected[c]).abs() <= tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let identity = Lut3d::identity(5);
+        for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+            for rgb in [[0.0, 0.0, 0.0], [0.3, 0.7, 0.1], [0.9, 0.2, 0.55], [1.0, 1.0, 1.0]] {
+                assert_close(identity.sample(rgb, interpolation), rgb, 1e-6);
+            }
+        }
+
+        // Tetrahedral is exact for a LUT that's linear in each tetrahedron; this one isn't
+        // linear per axis, so trilinear blurs it
+        let lut = Lut3d::from_fn(2, |[r, g, b]| {
+            let value = r.max(g).max(b);
+            [value; 3]
+        });
+        let rgb = [0.6, 0.3, 0.2];
+        assert_close(lut.sample(rgb, LutInterpolation::Tetrahedral), [0.6; 3], 1e-6);
+        assert!((lut.sample(rgb, LutInterpolation::Trilinear)[0] - 0.6).abs() > 0.05);
+
+        // Grays stay gray
+        let gray = lut.sample([0.4, 0.4, 0.4], LutInterpolation::Tetrahedral);
+        assert_close(gray, [0.4; 3], 1e-6);
+    }
+
+    #[test]
+    fn test_cube_files() -> Result<()> {
+        let cube = r#"
+            # Swap red and blue
+            TITLE "swap"
+            LUT_3D_SIZE 2
+            0 0 0
+            0 0 1
+            0 1 0
+            0 1 1
+            1 0 0
+            1 0 1
+            1 1 0
+            1 1 1
+        "#;
+        let lut = Lut3d::parse_cube(cube)?;
+        assert_eq!(lut.title.as_deref(), Some("swap"));
+        assert_eq!(lut.size(), 2);
+        assert_close(lut.sample([0.8, 0.5, 0.1], LutInterpolation::Tetrahedral), [0.1, 0.5, 0.8], 1e-6);
+
+        let dir = create_test_dir("color_lut", "cube_files")?;
+        let path = dir.join("swap.cube");
+        lut.save_cube(&path)?;
+        assert_eq!(Lut3d::load_cube(&path)?, lut);
+
+        let mut frame = vec![200, 100, 0, 255];
+        lut.apply_to_frame(&mut frame, "RGBA", LutInterpolation::Tetrahedral, 0.5)?;
+        assert_eq!(frame, vec![100, 100, 100, 255]);
+
+        assert!(Lut3d::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut3d::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(Lut3d::parse_cube("0 0 0\n").is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bake_grade() {
+        let neutral = bake_grade(&ColorAdjustments::default(), &ColorCurves::default(), None, LutInterpolation::Tetrahedral, 9);
+        for rgb in [[0.2, 0.5, 0.8], [0.0, 1.0, 0.3]] {
+            assert_close(neutral.sample(rgb, LutInterpolation::Tetrahedral), rgb, 1e-4);
+        }
+
+        let mut adjustments = ColorAdjustments::default();
+        adjustments.wheels.gain.master = 0.5;
+        let curves = ColorCurves {
+            blue: vec![CurvePoint { x: 0.0, y: 1.0 }, CurvePoint { x: 1.0, y: 0.0 }],
+            ..ColorCurves::default()
+        };
+        let invert = Lut3d::from_fn(2, |rgb| rgb.map(|value| 1.0 - value));
+        let baked = bake_grade(&adjustments, &curves, Some((&invert, 1.0)), LutInterpolation::Tetrahedral, 17);
+
+        // Gain, then the blue curve, then the LUT
+        let graded = baked.sample([0.5, 0.5, 0.5], LutInterpolation::Tetrahedral);
+        assert_close(graded, [0.25, 0.25, 0.75], 1e-3);
+    }
+
+    #[test]
+    fn test_lut_cache() -> Result<()> {
+        let mut cache = LutCache::new(2);
+        let bakes = Cell::new(0);
+        let bake = || {
+            bakes.set(bakes.get() + 1);
+            Ok(Lut3d::identity(2))
+        };
+
+        cache.get_or_bake(1, bake)?;
+        cache.get_or_bake(2, bake)?;
+        cache.get_or_bake(1, bake)?;
+        assert_eq!(bakes.get(), 2);
+
+        // Version 2 is the least recently used
+        cache.get_or_bake(3, bake)?;
+        assert_eq!(bakes.get(), 3);
+        assert!(cache.contains(1) && cache.contains(3) && !cache.contains(2));
+
+        assert!(cache.get_or_bake(4, || Err(anyhow::anyhow!("bake failed"))).is_err());
+        assert_eq!(cache.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_lookup() {
+        let table = [0.0, 0.5, 0.6, 1.0];
+        assert_eq!(lookup(&table, 0.0), 0.0);
+        assert!((lookup(&table, 0.5) - 0.55).abs() < 1e-6);
+        assert_eq!(lookup(&table, 1.0), 1.0);
+
+        // Out-of-range input is clamped
+        assert_eq!(lookup(&table, -1.0), 0.0);
+        assert_eq!(lookup(&table, 2.0), 1.0);
+
+        // Tables too short to interpolate
+        assert_eq!(lookup(&[0.25], 0.8), 0.25);
+        assert_eq!(lookup(&[], 0.8), 0.8);
+    }
+}
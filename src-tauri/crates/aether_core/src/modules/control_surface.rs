@@ -0,0 +1,579 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::engine::timeline::Timeline;
+use super::audio_engine::AudioEngine;
+use super::color_grading::{ColorGradingEngine, ColorParameter};
+
+/// Receives the actions produced by a listener
+pub type ActionCallback = Arc<dyn Fn(&ControlAction) + Send + Sync + 'static>;
+
+/// A message from a controller
+#[derive(Debug, Clone, PartialEq)]
+pub enum SurfaceInput {
+    /// MIDI control change; channel 1-16, value 0-127
+    MidiCc { channel: u8, controller: u8, value: u8 },
+    /// MIDI note on with a non-zero velocity; channel 1-16
+    MidiNote { channel: u8, note: u8, velocity: u8 },
+    /// OSC message with its first numeric argument, or 1.0 without one
+    Osc { address: String, value: f64 },
+}
+
+/// Controller input a mapping listens to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputSource {
+    MidiCc { channel: u8, controller: u8 },
+    MidiNote { channel: u8, note: u8 },
+    Osc { address: String },
+}
+
+impl InputSource {
+    pub fn matches(&self, input: &SurfaceInput) -> bool {
+        match (self, input) {
+            (InputSource::MidiCc { channel, controller }, SurfaceInput::MidiCc { channel: c, controller: n, .. }) => {
+                channel == c && controller == n
+            },
+            (InputSource::MidiNote { channel, note }, SurfaceInput::MidiNote { channel: c, note: n, .. }) => {
+                channel == c && note == n
+            },
+            (InputSource::Osc { address }, SurfaceInput::Osc { address: a, .. }) => address == a,
+            _ => false,
+        }
+    }
+}
+
+/// Engine control driven by a mapping
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlTarget {
+    /// Step the playhead by frames
+    Jog,
+    /// Play at a variable speed
+    Shuttle,
+    PlayPause,
+    TrackVolume { track: String },
+    MasterVolume,
+    Color { parameter: ColorParameter },
+}
+
+impl ControlTarget {
+    /// Change per encoder step when the mapping doesn't set one
+    fn default_step(&self) -> f64 {
+        match self {
+            ControlTarget::Jog => 1.0,
+            ControlTarget::Shuttle => 0.25,
+            _ => 0.01,
+        }
+    }
+}
+
+/// How controller values are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueMode {
+    /// Faders and knobs with a position, scaled to `min`..`max`
+    #[default]
+    Absolute,
+    /// Endless encoders sending steps; MIDI uses 1-63 for up and 65-127 for down
+    Relative,
+}
+
+fn default_max() -> f64 {
+    1.0
+}
+
+/// One controller input mapped to one engine control
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappingEntry {
+    pub source: InputSource,
+    pub target: ControlTarget,
+    #[serde(default)]
+    pub mode: ValueMode,
+    /// Target value at the bottom of an absolute control's travel
+    #[serde(default)]
+    pub min: f64,
+    /// Target value at the top of an absolute control's travel
+    #[serde(default = "default_max")]
+    pub max: f64,
+    /// Change per step of a relative control
+    #[serde(default)]
+    pub step: Option<f64>,
+}
+
+/// A controller mapping file, in TOML (`.toml`) or JSON (anything else)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlMapping {
+    #[serde(default)]
+    pub mappings: Vec<MappingEntry>,
+}
+
+impl ControlMapping {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read control mapping {:?}: {}", path, e))?;
+
+        let mapping = if is_toml(path) {
+            toml::from_str(&text).map_err(|e| anyhow!("Invalid control mapping {:?}: {}", path, e))?
+        } else {
+            serde_json::from_str(&text).map_err(|e| anyhow!("Invalid control mapping {:?}: {}", path, e))?
+        };
+        Ok(mapping)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self)?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, text).map_err(|e| anyhow!("Failed to write control mapping {:?}: {}", path, e))
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+/// New value for a control, either absolute or relative to its current value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlValue {
+    Set(f64),
+    Adjust(f64),
+}
+
+impl ControlValue {
+    pub fn apply(&self, current: f64) -> f64 {
+        match self {
+            ControlValue::Set(value) => *value,
+            ControlValue::Adjust(delta) => current + delta,
+        }
+    }
+}
+
+/// An engine action produced from controller input
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlAction {
+    /// Move the playhead by a number of frames
+    Jog(f64),
+    /// Play at a speed, 0 to stop and negative for reverse
+    Shuttle(f64),
+    PlayPause,
+    TrackVolume { track: String, value: ControlValue },
+    MasterVolume(ControlValue),
+    Color { parameter: ColorParameter, value: ControlValue },
+}
+
+impl ControlAction {
+    /// Apply a color action; returns whether the action was one
+    pub fn apply_color(&self, engine: &mut ColorGradingEngine) -> Result<bool> {
+        let ControlAction::Color { parameter, value } = self else {
+            return Ok(false);
+        };
+
+        let mut adjustments = *engine.get_adjustments();
+        let current = parameter.get(&adjustments) as f64;
+        parameter.set(&mut adjustments, value.apply(current) as f32);
+        engine.set_adjustments(adjustments)?;
+        Ok(true)
+    }
+
+    /// Apply a volume action; returns whether the action was one
+    pub fn apply_audio(&self, engine: &mut AudioEngine) -> Result<bool> {
+        match self {
+            ControlAction::TrackVolume { track, value } => {
+                let track = engine.get_track(track).ok_or_else(|| anyhow!("Audio track not found: {}", track))?;
+                let mut track = track.lock().unwrap();
+                let volume = value.apply(track.volume());
+                track.set_volume(volume).map_err(|e| anyhow!("{}", e))?;
+                Ok(true)
+            },
+            ControlAction::MasterVolume(value) => {
+                let volume = value.apply(engine.master_volume());
+                engine.set_master_volume(volume).map_err(|e| anyhow!("{}", e))?;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Apply a transport action to the timeline playhead; returns whether the action was one
+    ///
+    /// The timeline can't play backwards, so reverse shuttle stops playback.
+    pub fn apply_transport(&self, timeline: &mut Timeline) -> Result<bool> {
+        match self {
+            ControlAction::Jog(frames) => {
+                let time = timeline.current_time() + frames * timeline.frame_rate().frame_duration();
+                timeline.seek(time.clamp(0.0, timeline.duration())).map_err(|e| anyhow!("{}", e))?;
+                Ok(true)
+            },
+            ControlAction::Shuttle(speed) if *speed > 0.0 => {
+                timeline.set_playback_speed(*speed).map_err(|e| anyhow!("{}", e))?;
+                timeline.play();
+                Ok(true)
+            },
+            ControlAction::Shuttle(speed) => {
+                if *speed < 0.0 {
+                    debug!("Reverse shuttle isn't supported, stopping playback");
+                }
+                timeline.pause();
+                Ok(true)
+            },
+            ControlAction::PlayPause => {
+                if timeline.is_playing() {
+                    timeline.pause();
+                } else {
+                    timeline.play();
+                }
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Turns controller input into engine actions according to a mapping
+///
+/// Inputs are matched against every mapping, so one control can drive several targets.
+/// Buttons mapped to play/pause fire when pressed, not when released.
+pub struct ControlSurface {
+    mapping: ControlMapping,
+    /// Last absolute value per mapping, for jog wheels and buttons
+    last_values: HashMap<usize, f64>,
+    shuttle_speed: f64,
+}
+
+impl ControlSurface {
+    pub fn new(mapping: ControlMapping) -> Self {
+        Self {
+            mapping,
+            last_values: HashMap::new(),
+            shuttle_speed: 0.0,
+        }
+    }
+
+    /// Create a surface from a mapping file
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(ControlMapping::load(path)?))
+    }
+
+    pub fn mapping(&self) -> &ControlMapping {
+        &self.mapping
+    }
+
+    pub fn set_mapping(&mut self, mapping: ControlMapping) {
+        self.mapping = mapping;
+        self.last_values.clear();
+    }
+
+    /// Actions for a controller message
+    pub fn handle(&mut self, input: &SurfaceInput) -> Vec<ControlAction> {
+        let matching: Vec<(usize, MappingEntry)> = self.mapping.mappings.iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.source.matches(input))
+            .map(|(index, entry)| (index, entry.clone()))
+            .collect();
+
+        let actions: Vec<ControlAction> = matching.into_iter()
+            .filter_map(|(index, entry)| self.action(index, &entry, input))
+            .collect();
+        if actions.is_empty() {
+            debug!("No mapping for {:?}", input);
+        }
+        actions
+    }
+
+    /// Actions for a raw MIDI message
+    pub fn handle_midi(&mut self, message: &[u8]) -> Vec<ControlAction> {
+        parse_midi(message).map_or_else(Vec::new, |input| self.handle(&input))
+    }
+
+    /// Actions for an OSC packet, which may be a bundle
+    pub fn handle_osc(&mut self, packet: &[u8]) -> Result<Vec<ControlAction>> {
+        Ok(parse_osc(packet)?.iter().flat_map(|input| self.handle(input)).collect())
+    }
+
+    fn action(&mut self, index: usize, entry: &MappingEntry, input: &SurfaceInput) -> Option<ControlAction> {
+        let step = entry.step.unwrap_or_else(|| entry.target.default_step());
+        let value = match entry.mode {
+            ValueMode::Absolute => ControlValue::Set(entry.min + normalized(input) * (entry.max - entry.min)),
+            ValueMode::Relative => ControlValue::Adjust(steps(input) * step),
+        };
+
+        Some(match &entry.target {
+            ControlTarget::Jog => {
+                let frames = match value {
+                    ControlValue::Adjust(frames) => frames,
+                    // A jog wheel reporting its position moves by the change since last time
+                    ControlValue::Set(position) => {
+                        let previous = self.last_values.insert(index, position)?;
+                        position - previous
+                    },
+                };
+                if frames == 0.0 {
+                    return None;
+                }
+                ControlAction::Jog(frames)
+            },
+            ControlTarget::Shuttle => {
+                self.shuttle_speed = value.apply(self.shuttle_speed);
+                ControlAction::Shuttle(self.shuttle_speed)
+            },
+            ControlTarget::PlayPause => {
+                let level = normalized(input);
+                let previous = self.last_values.insert(index, level).unwrap_or(0.0);
+                let pressed = matches!(input, SurfaceInput::MidiNote { .. }) || (level > 0.5 && previous <= 0.5);
+                if !pressed {
+                    return None;
+                }
+                ControlAction::PlayPause
+            },
+            ControlTarget::TrackVolume { track } => ControlAction::TrackVolume {
+                track: track.clone(),
+                value,
+            },
+            ControlTarget::MasterVolume => ControlAction::MasterVolume(value),
+            ControlTarget::Color { parameter } => ControlAction::Color {
+                parameter: *parameter,
+                value,
+            },
+        })
+    }
+}
+
+/// Input position in 0..1
+fn normalized(input: &SurfaceInput) -> f64 {
+    match input {
+        SurfaceInput::MidiCc { value, .. } => *value as f64 / 127.0,
+        SurfaceInput::MidiNote { velocity, .. } => *velocity as f64 / 127.0,
+        SurfaceInput::Osc { value, .. } => value.clamp(0.0, 1.0),
+    }
+}
+
+/// Signed encoder steps
+fn steps(input: &SurfaceInput) -> f64 {
+    match input {
+        SurfaceInput::MidiCc { value, .. } if *value >= 64 => *value as f64 - 128.0,
+        SurfaceInput::MidiCc { value, .. } => *value as f64,
+        SurfaceInput::MidiNote { .. } => 1.0,
+        SurfaceInput::Osc { value, .. } => *value,
+    }
+}
+
+/// Parse a MIDI control change or note on message
+pub fn parse_midi(message: &[u8]) -> Option<SurfaceInput> {
+    let (&status, data) = message.split_first()?;
+    let channel = (status & 0x0F) + 1;
+
+    match (status & 0xF0, data) {
+        (0xB0, [controller, value, ..]) => Some(SurfaceInput::MidiCc {
+            channel,
+            controller: controller & 0x7F,
+            value: value & 0x7F,
+        }),
+        (0x90, [note, velocity, ..]) if *velocity > 0 => Some(SurfaceInput::MidiNote {
+            channel,
+            note: note & 0x7F,
+            velocity: velocity & 0x7F,
+        }),
+        _ => None,
+    }
+}
+
+/// Parse an OSC packet into its messages, flattening bundles
+pub fn parse_osc(packet: &[u8]) -> Result<Vec<SurfaceInput>> {
+    let mut inputs = Vec::new();
+    parse_osc_packet(packet, &mut inputs)?;
+    Ok(inputs)
+}
+
+fn parse_osc_packet(packet: &[u8], inputs: &mut Vec<SurfaceInput>) -> Result<()> {
+    let mut reader = OscReader { data: packet, offset: 0 };
+
+    if packet.starts_with(b"#bundle\0") {
+        reader.offset = 16; // Tag and time tag
+        while reader.offset < packet.len() {
+            let size = reader.int()? as usize;
+            let element = reader.bytes(size)?;
+            parse_osc_packet(element, inputs)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err(anyhow!("Invalid OSC address: {:?}", address));
+    }
+
+    let tags = if reader.offset < packet.len() { reader.string()? } else { String::new() };
+    let mut value = None;
+    for tag in tags.chars().skip_while(|&tag| tag == ',') {
+        let argument = match tag {
+            'i' => Some(reader.int()? as f64),
+            'f' => Some(f32::from_bits(reader.int()? as u32) as f64),
+            'h' => Some(i64::from_be_bytes(reader.bytes(8)?.try_into().unwrap()) as f64),
+            'd' => Some(f64::from_be_bytes(reader.bytes(8)?.try_into().unwrap())),
+            'T' => Some(1.0),
+            'F' => Some(0.0),
+            's' => {
+                reader.string()?;
+                None
+            },
+            'b' => {
+                let size = reader.int()? as usize;
+                reader.bytes((size + 3) & !3)?;
+                None
+            },
+            'N' | 'I' => None,
+            other => return Err(anyhow!("Unsupported OSC argument type: {}", other)),
+        };
+        value = value.or(argument);
+    }
+
+    inputs.push(SurfaceInput::Osc { address, value: value.unwrap_or(1.0) });
+    Ok(())
+}
+
+struct OscReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> OscReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(count).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("Truncated OSC packet"))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn int(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// NUL-terminated string padded to four bytes
+    fn string(&mut self) -> Result<String> {
+        let rest = &self.data[self.offset..];
+        let length = rest.iter().position(|&byte| byte == 0).ok_or_else(|| anyhow!("Unterminated OSC string"))?;
+        let text = String::from_utf8_lossy(&rest[..length]).into_owned();
+        self.bytes((length + 4) & !3)?;
+        Ok(text)
+    }
+}
+
+/// Receives OSC over UDP and passes the resulting actions to a callback
+pub struct OscListener {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl OscListener {
+    /// Listen on `address`; port 0 picks a free port
+    pub fn bind(address: SocketAddr, surface: Arc<Mutex<ControlSurface>>, callback: ActionCallback) -> Result<Self> {
+        let socket = UdpSocket::bind(address)
+            .map_err(|e| anyhow!("Failed to bind OSC socket on {}: {}", address, e))?;
+        // Wake up periodically to notice `stop`
+        socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let address = socket.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let running = running.clone();
+            thread::Builder::new().name("aether-osc".to_string()).spawn(move || {
+                let mut buffer = vec![0u8; 65536];
+                while running.load(Ordering::SeqCst) {
+                    let size = match socket.recv(&mut buffer) {
+                        Ok(size) => size,
+                        Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                        Err(e) => {
+                            warn!("OSC receive failed: {}", e);
+                            continue;
+                        },
+                    };
+
+                    let actions = surface.lock().unwrap().handle_osc(&buffer[..size]);
+                    match actions {
+                        Ok(actions) => actions.iter().for_each(|action| callback(action)),
+                        Err(e) => debug!("Ignoring OSC packet: {}", e),
+                    }
+                }
+            })?
+        };
+
+        info!("Listening for OSC on {}", address);
+        Ok(Self {
+            address,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for OscListener {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Receives MIDI from a hardware port and passes the resulting actions to a callback
+#[cfg(feature = "midi")]
+pub struct MidiListener {
+    _connection: midir::MidiInputConnection<()>,
+    port_name: String,
+}
+
+#[cfg(feature = "midi")]
+impl MidiListener {
+    /// Names of the available MIDI input ports
+    pub fn ports() -> Result<Vec<String>> {
+        let input = midir::MidiInput::new("aether")?;
+        Ok(input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect())
+    }
+
+    /// Connect to the first port whose name contains `name`
+    pub fn connect(name: &str, surface: Arc<Mutex<ControlSurface>>, callback: ActionCallback) -> Result<Self> {
+        let input = midir::MidiInput::new("aether")?;
+        let (port, port_name) = input.ports().into_iter()
+            .filter_map(|port| input.port_name(&port).ok().map(|port_name| (port, port_name)))
+            .find(|(_, port_name)| port_name.contains(name))
+            .ok_or_else(|| anyhow!("No MIDI input port matching {:?}", name))?;
+
+        let connection = input.connect(&port, "aether-control", move |_, message, _| {
+            for action in surface.lock().unwrap().handle_midi(message) {
+                callback(&action);
+            }
+        }, ()).map_err(|e| anyhow!("Failed to connect to MIDI port {}: {}", port_name, e))?;
+
+        info!("Listening for MIDI on {}", port_name);
+        Ok(Self {
+            _connection: connection,
+            port_name,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+}
@@ -0,0 +1,232 @@
+#[cfg(test)]
+mod tests {
+    use super::super::color_grading::{ColorAdjustments, ColorParameter};
+    use super::super::control_surface::{
+    use super::super::test_utils::create_test_dir;
+        parse_midi, parse_osc, ControlAction, ControlMapping, ControlSurface, ControlTarget, ControlValue,
+        InputSource, MappingEntry, OscListener, SurfaceInput, ValueMode,
+    };
+    use std::fs;
+    use std::net::UdpSocket;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::Duration;
+    use anyhow::Result;
+
+    fn entry(source: InputSource, target: ControlTarget, mode: ValueMode) -> MappingEntry {
+        MappingEntry {
+            source,
+            target,
+            mode,
+            min: 0.0,
+            max: 1.0,
+            step: None,
+        }
+    }
+
+    // Helper function to build an OSC message with one float argument
+    fn osc_message(address: &str, value: f32) -> Vec<u8> {
+        let mut packet = osc_string(address);
+        packet.extend(osc_string(",f"));
+        packet.extend(value.to_be_bytes());
+        packet
+    }
+
+    fn osc_string(text: &str) -> Vec<u8> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        while !bytes.len().is_multiple_of(4) {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_parse_midi() {
+        assert_eq!(parse_midi(&[0xB1, 7, 100]), Some(SurfaceInput::MidiCc { channel: 2, controller: 7, value: 100 }));
+        assert_eq!(parse_midi(&[0x90, 60, 127]), Some(SurfaceInput::MidiNote { channel: 1, note: 60, velocity: 127 }));
+
+        // Note on with zero velocity is a note off
+        assert_eq!(parse_midi(&[0x90, 60, 0]), None);
+        assert_eq!(parse_midi(&[0xF8]), None);
+        assert_eq!(parse_midi(&[0xB0, 7]), None);
+    }
+
+    #[test]
+    fn test_parse_osc() -> Result<()> {
+        let inputs = parse_osc(&osc_message("/color/gamma", 0.5))?;
+        assert_eq!(inputs, vec![SurfaceInput::Osc { address: "/color/gamma".to_string(), value: 0.5 }]);
+
+        // Messages without arguments are triggers
+        let inputs = parse_osc(&osc_string("/transport/play"))?;
+        assert_eq!(inputs, vec![SurfaceInput::Osc { address: "/transport/play".to_string(), value: 1.0 }]);
+
+        // The first numeric argument is used
+        let mut packet = osc_string("/jog");
+        packet.extend(osc_string(",si"));
+        packet.extend(osc_string("wheel"));
+        packet.extend((-3i32).to_be_bytes());
+        assert_eq!(parse_osc(&packet)?, vec![SurfaceInput::Osc { address: "/jog".to_string(), value: -3.0 }]);
+
+        let mut bundle = osc_string("#bundle");
+        bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        for message in [osc_message("/a", 1.0), osc_message("/b", 0.0)] {
+            bundle.extend((message.len() as i32).to_be_bytes());
+            bundle.extend(message);
+        }
+        assert_eq!(parse_osc(&bundle)?.len(), 2);
+
+        assert!(parse_osc(b"no slash").is_err());
+        assert!(parse_osc(&osc_message("/cut", 1.0)[..10]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_absolute_and_relative_mappings() {
+        let mut volume = entry(InputSource::MidiCc { channel: 1, controller: 7 }, ControlTarget::MasterVolume, ValueMode::Absolute);
+        volume.max = 2.0;
+        let mut gamma = entry(
+            InputSource::MidiCc { channel: 1, controller: 16 },
+            ControlTarget::Color { parameter: ColorParameter::Gamma },
+            ValueMode::Relative,
+        );
+        gamma.step = Some(0.05);
+        let mut surface = ControlSurface::new(ControlMapping { mappings: vec![volume, gamma] });
+
+        assert_eq!(surface.handle_midi(&[0xB0, 7, 127]), vec![ControlAction::MasterVolume(ControlValue::Set(2.0))]);
+        assert_eq!(surface.handle_midi(&[0xB0, 7, 0]), vec![ControlAction::MasterVolume(ControlValue::Set(0.0))]);
+
+        // Two steps up, then one step down in two's complement
+        let up = surface.handle_midi(&[0xB0, 16, 2]);
+        let down = surface.handle_midi(&[0xB0, 16, 127]);
+        match (&up[..], &down[..]) {
+            (
+                [ControlAction::Color { parameter: ColorParameter::Gamma, value: ControlValue::Adjust(up) }],
+                [ControlAction::Color { parameter: ColorParameter::Gamma, value: ControlValue::Adjust(down) }],
+            ) => {
+                assert!((up - 0.1).abs() < 1e-9);
+                assert!((down + 0.05).abs() < 1e-9);
+            },
+            other => panic!("Unexpected actions: {:?}", other),
+        }
+
+        // Other channels and controllers are ignored
+        assert!(surface.handle_midi(&[0xB1, 7, 64]).is_empty());
+        assert!(surface.handle_midi(&[0xB0, 8, 64]).is_empty());
+    }
+
+    #[test]
+    fn test_transport_mappings() {
+        let mut jog = entry(InputSource::Osc { address: "/jog".to_string() }, ControlTarget::Jog, ValueMode::Absolute);
+        jog.max = 100.0;
+        let mut surface = ControlSurface::new(ControlMapping {
+            mappings: vec![
+                jog,
+                entry(InputSource::MidiCc { channel: 1, controller: 20 }, ControlTarget::Shuttle, ValueMode::Relative),
+                entry(InputSource::MidiCc { channel: 1, controller: 64 }, ControlTarget::PlayPause, ValueMode::Absolute),
+            ],
+        });
+
+        // An absolute jog wheel moves by the change since its last position
+        let jog = |position: f32| SurfaceInput::Osc { address: "/jog".to_string(), value: position as f64 };
+        assert!(surface.handle(&jog(0.5)).is_empty());
+        assert_eq!(surface.handle(&jog(0.75)), vec![ControlAction::Jog(25.0)]);
+        assert_eq!(surface.handle(&jog(0.625)), vec![ControlAction::Jog(-12.5)]);
+        assert!(surface.handle(&jog(0.625)).is_empty());
+
+        // Shuttle speed accumulates
+        assert_eq!(surface.handle_midi(&[0xB0, 20, 2]), vec![ControlAction::Shuttle(0.5)]);
+        assert_eq!(surface.handle_midi(&[0xB0, 20, 2]), vec![ControlAction::Shuttle(1.0)]);
+        assert_eq!(surface.handle_midi(&[0xB0, 20, 124]), vec![ControlAction::Shuttle(0.0)]);
+
+        // Buttons toggle on press only
+        assert_eq!(surface.handle_midi(&[0xB0, 64, 127]), vec![ControlAction::PlayPause]);
+        assert!(surface.handle_midi(&[0xB0, 64, 127]).is_empty());
+        assert!(surface.handle_midi(&[0xB0, 64, 0]).is_empty());
+        assert_eq!(surface.handle_midi(&[0xB0, 64, 127]), vec![ControlAction::PlayPause]);
+    }
+
+    #[test]
+    fn test_color_parameter_values() {
+        let mut adjustments = ColorAdjustments::default();
+        let action = ControlAction::Color { parameter: ColorParameter::Saturation, value: ControlValue::Adjust(0.25) };
+        if let ControlAction::Color { parameter, value } = action {
+            let current = parameter.get(&adjustments) as f64;
+            parameter.set(&mut adjustments, value.apply(current) as f32);
+        }
+        assert_eq!(adjustments.saturation, 1.25);
+
+        // Values are clamped to the parameter's range
+        ColorParameter::Hue.set(&mut adjustments, 400.0);
+        assert_eq!(adjustments.hue, 180.0);
+        ColorParameter::Temperature.set(&mut adjustments, -3.0);
+        assert_eq!(adjustments.temperature, -1.0);
+    }
+
+    #[test]
+    fn test_mapping_file() -> Result<()> {
+        let dir = create_test_dir("control_surface", "mapping_file")?;
+        let toml_path = dir.join("panel.toml");
+        fs::write(&toml_path, r#"
+            [[mappings]]
+            source = { midi_cc = { channel = 1, controller = 7 } }
+            target = { track_volume = { track = "dialogue" } }
+            max = 2.0
+
+            [[mappings]]
+            source = { osc = { address = "/wheel/lift" } }
+            target = { color = { parameter = "shadows" } }
+            mode = "relative"
+            step = 0.02
+
+            [[mappings]]
+            source = { midi_note = { channel = 10, note = 36 } }
+            target = "play_pause"
+        "#)?;
+
+        let mut surface = ControlSurface::load(&toml_path)?;
+        assert_eq!(surface.mapping().mappings.len(), 3);
+        assert_eq!(surface.mapping().mappings[0].min, 0.0);
+        assert_eq!(surface.mapping().mappings[1].mode, ValueMode::Relative);
+        assert_eq!(
+            surface.handle_midi(&[0xB0, 7, 127]),
+            vec![ControlAction::TrackVolume { track: "dialogue".to_string(), value: ControlValue::Set(2.0) }]
+        );
+        assert_eq!(surface.handle_midi(&[0x99, 36, 90]), vec![ControlAction::PlayPause]);
+
+        // JSON round trip
+        let json_path = dir.join("panel.json");
+        surface.mapping().save(&json_path)?;
+        assert_eq!(&ControlMapping::load(&json_path)?, surface.mapping());
+
+        fs::write(dir.join("broken.toml"), "mappings = 3")?;
+        assert!(ControlMapping::load(&dir.join("broken.toml")).is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_osc_listener() -> Result<()> {
+        let surface = ControlSurface::new(ControlMapping {
+            mappings: vec![entry(InputSource::Osc { address: "/master".to_string() }, ControlTarget::MasterVolume, ValueMode::Absolute)],
+        });
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let mut listener = OscListener::bind(
+            "127.0.0.1:0".parse()?,
+            Arc::new(Mutex::new(surface)),
+            Arc::new(move |action: &ControlAction| {
+                let _ = sender.lock().unwrap().send(action.clone());
+            }),
+        )?;
+
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.send_to(&osc_message("/master", 0.5), listener.address())?;
+
+        let action = receiver.recv_timeout(Duration::from_secs(5))?;
+        assert_eq!(action, ControlAction::MasterVolume(ControlValue::Set(0.5)));
+
+        listener.stop();
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Entries kept by the global log buffer
+pub const DEFAULT_CAPACITY: usize = 5000;
+
+/// Buffer used by `init_diagnostics` and read by the diagnostics panel
+static LOG_BUFFER: Lazy<LogBuffer> = Lazy::new(|| LogBuffer::new(DEFAULT_CAPACITY));
+
+/// Severity of a log entry, ordered from most to least severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+/// A span the event was recorded in, e.g. `render_job{job_id=3}`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpanContext {
+    pub name: String,
+    /// Recorded span fields, such as clip or job IDs
+    pub fields: Vec<(String, String)>,
+}
+
+/// A captured log event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogEntry {
+    /// Wall-clock time in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub level: LogLevel,
+    /// Module path of the code that logged
+    pub target: String,
+    pub message: String,
+    /// Structured fields of the event other than the message
+    pub fields: Vec<(String, String)>,
+    /// Enclosing spans, outermost first
+    pub spans: Vec<SpanContext>,
+}
+
+impl LogEntry {
+    /// Value of a field on the event or its closest enclosing span
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .chain(self.spans.iter().rev().flat_map(|span| span.fields.iter()))
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Bounded in-memory log, oldest entries are dropped first
+///
+/// Clones share the same entries.
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    inner: Arc<Mutex<(VecDeque<LogEntry>, usize)>>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new((VecDeque::with_capacity(capacity.min(1024)), capacity.max(1)))),
+        }
+    }
+
+    /// Append an entry, evicting the oldest one if the buffer is full
+    pub fn push(&self, entry: LogEntry) {
+        let mut inner = self.inner.lock().unwrap();
+        let capacity = inner.1;
+        while inner.0.len() >= capacity {
+            inner.0.pop_front();
+        }
+        inner.0.push_back(entry);
+    }
+
+    /// Up to `limit` of the newest entries at `min_level` or more severe, oldest first
+    pub fn recent(&self, limit: usize, min_level: LogLevel) -> Vec<LogEntry> {
+        let inner = self.inner.lock().unwrap();
+        let mut entries: Vec<LogEntry> = inner.0.iter()
+            .rev()
+            .filter(|entry| entry.level <= min_level)
+            .take(limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    /// All entries, oldest first
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.inner.lock().unwrap().0.iter().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().1
+    }
+
+    /// Change the capacity, dropping the oldest entries if it shrinks
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.1 = capacity.max(1);
+        while inner.0.len() > inner.1 {
+            inner.0.pop_front();
+        }
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().0.clear();
+    }
+}
+
+/// Collects `message` and the other fields of an event or span
+#[derive(Default)]
+struct FieldCollector {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl FieldCollector {
+    fn set(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = value;
+        } else if let Some(existing) = self.fields.iter_mut().find(|(key, _)| key == field.name()) {
+            existing.1 = value;
+        } else {
+            self.fields.push((field.name().to_string(), value));
+        }
+    }
+}
+
+impl Visit for FieldCollector {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let mut text = String::new();
+        let _ = write!(text, "{:?}", value);
+        self.set(field, text);
+    }
+}
+
+/// Fields of a span, stored in the span's extensions
+struct SpanFields(Vec<(String, String)>);
+
+/// Tracing layer that copies events into a `LogBuffer`
+pub struct DiagnosticsLayer {
+    buffer: LogBuffer,
+}
+
+impl DiagnosticsLayer {
+    pub fn new(buffer: LogBuffer) -> Self {
+        Self { buffer }
+    }
+}
+
+impl<S> Layer<S> for DiagnosticsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut collector = FieldCollector::default();
+        attrs.record(&mut collector);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(collector.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let mut collector = FieldCollector {
+            fields: extensions.remove::<SpanFields>().map(|fields| fields.0).unwrap_or_default(),
+            ..FieldCollector::default()
+        };
+        values.record(&mut collector);
+        extensions.insert(SpanFields(collector.fields));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut collector = FieldCollector::default();
+        event.record(&mut collector);
+
+        let spans = ctx.event_scope(event)
+            .map(|scope| {
+                scope.from_root()
+                    .map(|span| SpanContext {
+                        name: span.name().to_string(),
+                        fields: span.extensions().get::<SpanFields>().map(|fields| fields.0.clone()).unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let metadata = event.metadata();
+        self.buffer.push(LogEntry {
+            timestamp_ms: now_ms(),
+            level: LogLevel::from(metadata.level()),
+            target: metadata.target().to_string(),
+            message: collector.message,
+            fields: collector.fields,
+            spans,
+        });
+    }
+}
+
+/// The global log buffer filled once `init_diagnostics` has run
+pub fn log_buffer() -> LogBuffer {
+    LOG_BUFFER.clone()
+}
+
+/// Install the global subscriber: console output at `console_level`, and debug and above
+/// into the global log buffer
+///
+/// Fails if another subscriber was already installed.
+pub fn init_diagnostics(console_level: LevelFilter) -> Result<LogBuffer> {
+    let buffer = log_buffer();
+    let subscriber = tracing_subscriber::registry()
+        .with(DiagnosticsLayer::new(buffer.clone()).with_filter(LevelFilter::DEBUG))
+        .with(tracing_subscriber::fmt::layer().with_filter(console_level));
+
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|e| anyhow!("Failed to install tracing subscriber: {}", e))?;
+
+    Ok(buffer)
+}
+
+/// Recent log entries for the diagnostics panel
+pub fn recent_logs(limit: usize, min_level: LogLevel) -> Vec<LogEntry> {
+    LOG_BUFFER.recent(limit, min_level)
+}
+
+/// Environment and logs attached to bug reports
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportBundle {
+    pub created_ms: u64,
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    pub gstreamer_version: String,
+    pub logs: Vec<LogEntry>,
+}
+
+impl SupportBundle {
+    /// Snapshot the environment and the given logs
+    pub fn collect(logs: Vec<LogEntry>) -> Self {
+        Self {
+            created_ms: now_ms(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            gstreamer_version: gst::version_string().to_string(),
+            logs,
+        }
+    }
+}
+
+/// Write a support bundle with the contents of the global log buffer as JSON
+pub fn export_support_bundle(path: &Path) -> Result<()> {
+    let bundle = SupportBundle::collect(LOG_BUFFER.entries());
+    let json = serde_json::to_string_pretty(&bundle)?;
+
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, json)
+        .map_err(|e| anyhow!("Failed to write support bundle {:?}: {}", path, e))?;
+
+    tracing::info!("Wrote support bundle with {} log entries to {:?}", bundle.logs.len(), path);
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests {
+    use super::super::diagnostics::{DiagnosticsLayer, LogBuffer, LogEntry, LogLevel};
+    use anyhow::Result;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn entry(level: LogLevel, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp_ms: 0,
+            level,
+            target: "test".to_string(),
+            message: message.to_string(),
+            fields: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest_entries() -> Result<()> {
+        let buffer = LogBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(entry(LogLevel::Info, &i.to_string()));
+        }
+
+        let messages: Vec<String> = buffer.entries().into_iter().map(|e| e.message).collect();
+        assert_eq!(messages, vec!["2", "3", "4"]);
+
+        buffer.set_capacity(1);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.entries()[0].message, "4");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recent_filters_by_level() -> Result<()> {
+        let buffer = LogBuffer::new(10);
+        buffer.push(entry(LogLevel::Debug, "debug"));
+        buffer.push(entry(LogLevel::Error, "error"));
+        buffer.push(entry(LogLevel::Info, "info"));
+        buffer.push(entry(LogLevel::Warn, "warn"));
+
+        let warnings: Vec<String> = buffer.recent(10, LogLevel::Warn).into_iter().map(|e| e.message).collect();
+        assert_eq!(warnings, vec!["error", "warn"]);
+
+        let latest: Vec<String> = buffer.recent(2, LogLevel::Trace).into_iter().map(|e| e.message).collect();
+        assert_eq!(latest, vec!["info", "warn"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_records_span_fields() -> Result<()> {
+        let buffer = LogBuffer::new(10);
+        let subscriber = tracing_subscriber::registry().with(DiagnosticsLayer::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("render_job", job_id = 7u64);
+            let _span = span.enter();
+            tracing::warn!(frame = 120, "Encoder stalled");
+        });
+
+        let entries = buffer.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].level, LogLevel::Warn);
+        assert_eq!(entries[0].message, "Encoder stalled");
+        assert_eq!(entries[0].field("frame"), Some("120"));
+        assert_eq!(entries[0].field("job_id"), Some("7"));
+        assert_eq!(entries[0].spans[0].name, "render_job");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use tracing::debug;
+use std::path::Path;
+
+use crate::engine::rendering::{AudioFormat, ExportOptions, GstExportOptions};
+use super::file_manager_convert::{AudioConversionOptions, ConversionFormat, VideoConversionOptions};
+
+/// Bits per pixel assumed for compressed video when no bitrate is set
+const DEFAULT_BITS_PER_PIXEL: f64 = 0.1;
+
+/// Frame size assumed when the output resolution isn't known
+const DEFAULT_FRAME_SIZE: (u32, u32) = (1920, 1080);
+
+/// Container overhead added on top of the stream payload
+const CONTAINER_OVERHEAD: f64 = 0.02;
+
+/// Fixed overhead for headers and indexes, in bytes
+const HEADER_OVERHEAD: u64 = 64 * 1024;
+
+/// Output settings that can predict their own bitrate
+pub trait OutputEstimate {
+    /// Expected combined bitrate of all streams, in bits per second
+    fn estimated_bitrate(&self) -> u64;
+}
+
+/// Estimate the size of an output file in bytes
+///
+/// This is a rough upper-leaning estimate meant for free space checks, not a promise.
+pub fn estimate_output_size<E: OutputEstimate + ?Sized>(options: &E, duration: f64) -> u64 {
+    let payload = options.estimated_bitrate() as f64 * duration.max(0.0) / 8.0;
+    (payload * (1.0 + CONTAINER_OVERHEAD)) as u64 + HEADER_OVERHEAD
+}
+
+/// Free space requirements checked before writing large outputs
+#[derive(Debug, Clone)]
+pub struct SpaceCheck {
+    /// Extra space required on top of the estimate, as a fraction of it
+    pub margin_ratio: f64,
+    /// Space that must remain free on the volume afterwards, in bytes
+    pub min_free_bytes: u64,
+}
+
+impl Default for SpaceCheck {
+    fn default() -> Self {
+        Self {
+            margin_ratio: 0.1,
+            min_free_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl SpaceCheck {
+    /// Space needed on the volume to write `estimated` bytes
+    pub fn required_bytes(&self, estimated: u64) -> u64 {
+        let margin = (estimated as f64 * self.margin_ratio.max(0.0)) as u64;
+        estimated.saturating_add(margin).saturating_add(self.min_free_bytes)
+    }
+
+    /// Fail if the volume holding `destination` can't fit `estimated` bytes plus the margin
+    pub fn check(&self, destination: &Path, estimated: u64) -> Result<()> {
+        let available = available_space(destination)?;
+        let required = self.required_bytes(estimated);
+        debug!(
+            "Free space check for {:?}: {} bytes available, {} required ({} estimated)",
+            destination, available, required, estimated
+        );
+
+        if available < required {
+            return Err(anyhow!(
+                "Not enough free space for {:?}: {} MB available, {} MB required (estimated output {} MB)",
+                destination,
+                available / (1024 * 1024),
+                required / (1024 * 1024),
+                estimated / (1024 * 1024)
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Free space on the volume that holds `path`, in bytes
+///
+/// `path` doesn't need to exist yet; its nearest existing ancestor is used.
+pub fn available_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .map(Path::to_path_buf)
+        .unwrap_or(std::env::current_dir()?);
+
+    fs2::available_space(&existing)
+        .map_err(|e| anyhow!("Failed to query free space for {:?}: {}", existing, e))
+}
+
+/// Bitrate of compressed video at the given size and rate
+fn video_bitrate_for(width: u32, height: u32, frame_rate: f64) -> u64 {
+    let (width, height) = if width > 0 && height > 0 {
+        (width, height)
+    } else {
+        DEFAULT_FRAME_SIZE
+    };
+    let frame_rate = if frame_rate > 0.0 { frame_rate } else { 30.0 };
+    (width as f64 * height as f64 * frame_rate * DEFAULT_BITS_PER_PIXEL) as u64
+}
+
+/// Bitrate of uncompressed 16-bit PCM
+fn pcm_bitrate(sample_rate: u32, channels: u32) -> u64 {
+    sample_rate as u64 * channels as u64 * 16
+}
+
+impl OutputEstimate for VideoConversionOptions {
+    fn estimated_bitrate(&self) -> u64 {
+        let video = match self.video_bitrate {
+            Some(bitrate) => bitrate as u64,
+            None => video_bitrate_for(
+                self.width.unwrap_or(0),
+                self.height.unwrap_or(0),
+                self.frame_rate.unwrap_or(0.0),
+            ),
+        };
+        let audio = self.audio_bitrate.unwrap_or(192_000) as u64;
+        video + audio
+    }
+}
+
+impl OutputEstimate for AudioConversionOptions {
+    fn estimated_bitrate(&self) -> u64 {
+        let pcm = pcm_bitrate(self.sample_rate.unwrap_or(48_000), self.channels.unwrap_or(2));
+        match self.format {
+            ConversionFormat::WAV => pcm,
+            // Lossless compression typically lands around 60% of PCM
+            ConversionFormat::FLAC => pcm * 6 / 10,
+            _ => self.audio_bitrate.map(|b| b as u64).unwrap_or(320_000),
+        }
+    }
+}
+
+/// Audio bitrate for an export, with PCM and FLAC ignoring the configured bitrate
+fn export_audio_bitrate(format: AudioFormat, bitrate: u32) -> u64 {
+    match format {
+        AudioFormat::Pcm => pcm_bitrate(48_000, 2),
+        AudioFormat::Flac => pcm_bitrate(48_000, 2) * 6 / 10,
+        _ if bitrate > 0 => bitrate as u64,
+        _ => 192_000,
+    }
+}
+
+impl OutputEstimate for ExportOptions {
+    fn estimated_bitrate(&self) -> u64 {
+        let video = if self.video_bitrate > 0 {
+            self.video_bitrate as u64
+        } else {
+            video_bitrate_for(self.width, self.height, self.frame_rate)
+        };
+        video + export_audio_bitrate(self.audio_format, self.audio_bitrate)
+    }
+}
+
+impl OutputEstimate for GstExportOptions {
+    fn estimated_bitrate(&self) -> u64 {
+        let video = if self.video_bitrate > 0 {
+            self.video_bitrate as u64
+        } else {
+            video_bitrate_for(self.width, self.height, self.frame_rate)
+        };
+        video + export_audio_bitrate(self.audio_format, self.audio_bitrate)
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use super::super::disk_space::{estimate_output_size, SpaceCheck};
+    use super::super::file_manager_convert::{AudioConversionOptions, ConversionFormat, VideoConversionOptions};
+    use anyhow::Result;
+
+    #[test]
+    fn test_estimate_scales_with_bitrate_and_duration() -> Result<()> {
+        let options = VideoConversionOptions {
+            video_bitrate: Some(8_000_000),
+            audio_bitrate: Some(192_000),
+            ..VideoConversionOptions::default()
+        };
+
+        let one_minute = estimate_output_size(&options, 60.0);
+        let two_minutes = estimate_output_size(&options, 120.0);
+
+        // 8.192 Mbit/s for 60 s is ~61 MB before overhead
+        assert!(one_minute > 61_000_000 && one_minute < 64_000_000);
+        assert!(two_minutes > one_minute * 19 / 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lossless_audio_ignores_bitrate() -> Result<()> {
+        let wav = AudioConversionOptions {
+            format: ConversionFormat::WAV,
+            audio_bitrate: Some(128_000),
+            sample_rate: Some(48_000),
+            channels: Some(2),
+            ..AudioConversionOptions::default()
+        };
+
+        // 48 kHz stereo 16-bit is 192 kB/s
+        let size = estimate_output_size(&wav, 10.0);
+        assert!(size >= 1_920_000 && size < 2_100_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_required_bytes_includes_margin() -> Result<()> {
+        let check = SpaceCheck {
+            margin_ratio: 0.5,
+            min_free_bytes: 100,
+        };
+        assert_eq!(check.required_bytes(1000), 1600);
+
+        // Nothing fits on a volume when the floor is absurdly large
+        let check = SpaceCheck {
+            margin_ratio: 0.0,
+            min_free_bytes: u64::MAX,
+        };
+        assert!(check.check(&std::env::temp_dir().join("out.mp4"), 1).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tracing::info;
+
+use super::color_lut::{lookup, Lut3d, LutInterpolation, DEFAULT_BAKE_SIZE};
+
+/// sRGB primaries adapted to the D50 white of the ICC profile connection space
+const SRGB_TO_XYZ_D50: [[f32; 3]; 3] = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+
+/// Samples of each tone curve when inverting it
+const TRC_SAMPLES: usize = 4096;
+
+/// How a monitor is calibrated
+///
+/// Only the preview is transformed; exports and rendered files never are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DisplayCalibration {
+    /// A `.cube` 3D LUT from sRGB to the monitor's RGB, as made by calibration software
+    Lut {
+        path: PathBuf,
+        #[serde(default)]
+        interpolation: LutInterpolation,
+    },
+    /// An ICC display profile; only matrix/TRC profiles are supported
+    Icc { path: PathBuf },
+}
+
+/// Transform from preview RGB to a calibrated monitor's RGB
+///
+/// Preview frames are taken to be sRGB. ICC profiles are baked into a 3D LUT when
+/// loaded, so both kinds of calibration cost the same per frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayTransform {
+    lut: Lut3d,
+    interpolation: LutInterpolation,
+}
+
+impl DisplayTransform {
+    pub fn load(calibration: &DisplayCalibration) -> Result<Self> {
+        let transform = match calibration {
+            DisplayCalibration::Lut { path, interpolation } => Self {
+                lut: Lut3d::load_cube(path)?,
+                interpolation: *interpolation,
+            },
+            DisplayCalibration::Icc { path } => {
+                let data = fs::read(path)
+                    .with_context(|| format!("Failed to read ICC profile {}", path.display()))?;
+                Self::from_icc(&data).with_context(|| format!("Invalid ICC profile {}", path.display()))?
+            },
+        };
+        info!("Loaded display calibration {:?}", calibration);
+        Ok(transform)
+    }
+
+    /// Transform for an ICC display profile
+    pub fn from_icc(data: &[u8]) -> Result<Self> {
+        let profile = IccProfile::parse(data)?;
+        let to_display = invert(profile.primaries)
+            .ok_or_else(|| anyhow!("ICC profile primaries are degenerate"))?;
+        let inverse_curves = profile.curves.map(|curve| curve.inverse_table());
+
+        let lut = Lut3d::from_fn(DEFAULT_BAKE_SIZE, |rgb| {
+            let xyz = multiply(SRGB_TO_XYZ_D50, rgb.map(srgb_to_linear));
+            let linear = multiply(to_display, xyz);
+            let mut out = [0f32; 3];
+            for c in 0..3 {
+                out[c] = lookup(&inverse_curves[c], linear[c].clamp(0.0, 1.0));
+            }
+            out
+        });
+        Ok(Self {
+            lut,
+            interpolation: LutInterpolation::Tetrahedral,
+        })
+    }
+
+    pub fn lut(&self) -> &Lut3d {
+        &self.lut
+    }
+
+    /// Transform a packed 8-bit RGB frame in place
+    pub fn apply(&self, frame: &mut [u8], format: &str) -> Result<()> {
+        self.lut.apply_to_frame(frame, format, self.interpolation, 1.0)
+    }
+}
+
+/// Tone response curve of one channel of an ICC profile, device value to linear
+#[derive(Debug, Clone)]
+enum ToneCurve {
+    Gamma(f32),
+    Table(Vec<f32>),
+    /// `parametricCurveType` function type and parameters, padded with the defaults
+    Parametric { function: u16, params: [f32; 7] },
+}
+
+impl ToneCurve {
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            ToneCurve::Gamma(gamma) => x.powf(*gamma),
+            ToneCurve::Table(table) => lookup(table, x),
+            ToneCurve::Parametric { function, params } => {
+                let [g, a, b, c, d, e, f] = *params;
+                match function {
+                    0 => x.powf(g),
+                    1 => if x >= -b / a { (a * x + b).powf(g) } else { 0.0 },
+                    2 => if x >= -b / a { (a * x + b).powf(g) + c } else { c },
+                    3 => if x >= d { (a * x + b).powf(g) } else { c * x },
+                    _ => if x >= d { (a * x + b).powf(g) + e } else { c * x + f },
+                }
+            },
+        }
+    }
+
+    /// Linear to device value, sampled evenly from 0 to 1
+    fn inverse_table(&self) -> Vec<f32> {
+        let forward: Vec<f32> = (0..TRC_SAMPLES)
+            .map(|i| self.eval(i as f32 / (TRC_SAMPLES - 1) as f32).clamp(0.0, 1.0))
+            .collect();
+
+        (0..TRC_SAMPLES)
+            .map(|i| {
+                let target = i as f32 / (TRC_SAMPLES - 1) as f32;
+                // First sample at or above the target; tone curves only rise
+                let upper = forward.partition_point(|&value| value < target).min(TRC_SAMPLES - 1);
+                if upper == 0 {
+                    return 0.0;
+                }
+                let (low, high) = (forward[upper - 1], forward[upper]);
+                let fraction = if high > low { (target - low) / (high - low) } else { 0.0 };
+                (upper as f32 - 1.0 + fraction.clamp(0.0, 1.0)) / (TRC_SAMPLES - 1) as f32
+            })
+            .collect()
+    }
+}
+
+/// The parts of a matrix/TRC ICC display profile used for preview
+struct IccProfile {
+    /// Columns are the red, green and blue colorants in PCS XYZ
+    primaries: [[f32; 3]; 3],
+    curves: [ToneCurve; 3],
+}
+
+impl IccProfile {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let reader = IccReader { data };
+        if data.len() < 132 || reader.bytes(36, 4)? != b"acsp" {
+            return Err(anyhow!("Not an ICC profile"));
+        }
+        if reader.bytes(16, 4)? != b"RGB " {
+            return Err(anyhow!("Only RGB display profiles are supported"));
+        }
+        if reader.bytes(20, 4)? != b"XYZ " {
+            return Err(anyhow!("Only profiles with an XYZ connection space are supported"));
+        }
+
+        let count = reader.u32(128)? as usize;
+        let mut tags = Vec::with_capacity(count.min(256));
+        for index in 0..count {
+            let entry = 132 + index * 12;
+            let signature: [u8; 4] = reader.bytes(entry, 4)?.try_into()?;
+            tags.push((signature, reader.u32(entry + 4)? as usize, reader.u32(entry + 8)? as usize));
+        }
+        let tag = |signature: &[u8; 4]| -> Result<&[u8]> {
+            let &(_, offset, size) = tags.iter().find(|(tag, _, _)| tag == signature).ok_or_else(|| {
+                anyhow!("Missing {} tag; only matrix/TRC profiles are supported, convert others to a 3D LUT",
+                    String::from_utf8_lossy(signature))
+            })?;
+            reader.bytes(offset, size)
+        };
+
+        let mut primaries = [[0f32; 3]; 3];
+        for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+            let xyz = parse_xyz(tag(signature)?)?;
+            for row in 0..3 {
+                primaries[row][column] = xyz[row];
+            }
+        }
+
+        Ok(Self {
+            primaries,
+            curves: [
+                parse_curve(tag(b"rTRC")?)?,
+                parse_curve(tag(b"gTRC")?)?,
+                parse_curve(tag(b"bTRC")?)?,
+            ],
+        })
+    }
+}
+
+struct IccReader<'a> {
+    data: &'a [u8],
+}
+
+impl IccReader<'_> {
+    fn bytes(&self, offset: usize, count: usize) -> Result<&[u8]> {
+        offset.checked_add(count)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| anyhow!("Truncated ICC profile"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(offset, 2)?.try_into()?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(offset, 4)?.try_into()?))
+    }
+
+    fn s15_fixed16(&self, offset: usize) -> Result<f32> {
+        Ok(self.u32(offset)? as i32 as f32 / 65536.0)
+    }
+}
+
+fn parse_xyz(tag: &[u8]) -> Result<[f32; 3]> {
+    let reader = IccReader { data: tag };
+    if reader.bytes(0, 4)? != b"XYZ " {
+        return Err(anyhow!("Colorant tag isn't XYZ"));
+    }
+    Ok([reader.s15_fixed16(8)?, reader.s15_fixed16(12)?, reader.s15_fixed16(16)?])
+}
+
+fn parse_curve(tag: &[u8]) -> Result<ToneCurve> {
+    let reader = IccReader { data: tag };
+    match reader.bytes(0, 4)? {
+        b"curv" => {
+            let count = reader.u32(8)? as usize;
+            match count {
+                0 => Ok(ToneCurve::Gamma(1.0)),
+                1 => Ok(ToneCurve::Gamma(reader.u16(12)? as f32 / 256.0)),
+                _ => Ok(ToneCurve::Table((0..count)
+                    .map(|i| Ok(reader.u16(12 + i * 2)? as f32 / 65535.0))
+                    .collect::<Result<_>>()?)),
+            }
+        },
+        b"para" => {
+            let function = reader.u16(8)?;
+            let count = match function {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return Err(anyhow!("Unknown parametric curve type {}", function)),
+            };
+            // Unused parameters keep the curve continuous: a = 1, the rest 0
+            let mut params = [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+            for (i, param) in params.iter_mut().enumerate().take(count) {
+                *param = reader.s15_fixed16(12 + i * 4)?;
+            }
+            Ok(ToneCurve::Parametric { function, params })
+        },
+        other => Err(anyhow!("Unsupported tone curve type {}", String::from_utf8_lossy(other))),
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn multiply(matrix: [[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+fn invert(m: [[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let determinant = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+    if determinant.abs() < 1e-9 {
+        return None;
+    }
+
+    let inverse = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+    Some(inverse.map(|row| row.map(|value| value / determinant)))
+}
@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use super::super::color_lut::{Lut3d, LutInterpolation};
+    use super::super::display_calibration::{DisplayCalibration, DisplayTransform};
+    use super::super::settings::EngineSettings;
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use anyhow::Result;
+
+    /// XYZ of the sRGB red, green and blue colorants adapted to D50
+    const SRGB_PRIMARIES: [[f32; 3]; 3] = [
+        [0.4361, 0.2225, 0.0139],
+        [0.3851, 0.7169, 0.0971],
+        [0.1431, 0.0606, 0.7142],
+    ];
+
+    fn fixed(value: f32) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    /// A matrix/TRC display profile with the same curve on every channel
+    fn icc_profile(primaries: [[f32; 3]; 3], curve: &[u8]) -> Vec<u8> {
+        let mut tags: Vec<(&[u8; 4], Vec<u8>)> = Vec::new();
+        for (signature, xyz) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().zip(primaries) {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            xyz.iter().for_each(|value| tag.extend(fixed(*value)));
+            tags.push((signature, tag));
+        }
+        for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+            tags.push((signature, curve.to_vec()));
+        }
+
+        let mut header = vec![0u8; 128];
+        header[16..20].copy_from_slice(b"RGB ");
+        header[20..24].copy_from_slice(b"XYZ ");
+        header[36..40].copy_from_slice(b"acsp");
+
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = Vec::new();
+        let data_start = 128 + 4 + tags.len() * 12;
+        for (signature, tag) in &tags {
+            table.extend(*signature);
+            table.extend(((data_start + data.len()) as u32).to_be_bytes());
+            table.extend((tag.len() as u32).to_be_bytes());
+            data.extend(tag);
+        }
+
+        let mut profile = [header, table, data].concat();
+        let size = (profile.len() as u32).to_be_bytes();
+        profile[..4].copy_from_slice(&size);
+        profile
+    }
+
+    fn gamma_curve(gamma: f32) -> Vec<u8> {
+        let mut curve = b"curv\0\0\0\0".to_vec();
+        curve.extend(1u32.to_be_bytes());
+        curve.extend(((gamma * 256.0).round() as u16).to_be_bytes());
+        curve
+    }
+
+    fn srgb_curve() -> Vec<u8> {
+        let mut curve = b"para\0\0\0\0\0\x03\0\0".to_vec();
+        for value in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+            curve.extend(fixed(value));
+        }
+        curve
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for c in 0..3 {
+            assert!((actual[c] - expected[c]).abs() <= tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_icc_profiles() -> Result<()> {
+        let samples = [[0.0, 0.0, 0.0], [0.5, 0.5, 0.5], [0.9, 0.2, 0.4], [1.0, 1.0, 1.0]];
+
+        // An sRGB monitor needs no correction
+        let srgb = DisplayTransform::from_icc(&icc_profile(SRGB_PRIMARIES, &srgb_curve()))?;
+        for rgb in samples {
+            assert_close(srgb.lut().sample(rgb, LutInterpolation::Tetrahedral), rgb, 3e-3);
+        }
+
+        // A gamma 2.2 monitor with the same primaries gets slightly darker midtones
+        let gamma = DisplayTransform::from_icc(&icc_profile(SRGB_PRIMARIES, &gamma_curve(2.2)))?;
+        let gray = gamma.lut().sample([0.5; 3], LutInterpolation::Tetrahedral);
+        let expected = 0.214_041_f32.powf(256.0 / 563.0);
+        assert_close(gray, [expected; 3], 3e-3);
+        assert!(gray[0] < 0.5);
+
+        let mut frame = vec![128, 128, 128, 255];
+        gamma.apply(&mut frame, "RGBA")?;
+        assert_eq!(frame[3], 255);
+        assert!(frame[0] < 128 && frame[0] == frame[2]);
+
+        // On a Display P3 monitor pure sRGB red is inside the gamut, not at its edge
+        let p3 = [[0.5151, 0.2412, -0.0011], [0.2920, 0.6922, 0.0419], [0.1571, 0.0666, 0.7841]];
+        let wide = DisplayTransform::from_icc(&icc_profile(p3, &srgb_curve()))?;
+        let red = wide.lut().sample([1.0, 0.0, 0.0], LutInterpolation::Tetrahedral);
+        assert!(red[0] < 1.0 && red[1] > 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_profiles() {
+        assert!(DisplayTransform::from_icc(b"not a profile").is_err());
+
+        // LUT-based profiles have no colorant tags
+        let mut profile = icc_profile(SRGB_PRIMARIES, &srgb_curve());
+        profile[132..136].copy_from_slice(b"A2B0");
+        let error = DisplayTransform::from_icc(&profile).unwrap_err();
+        assert!(error.to_string().contains("3D LUT"), "{}", error);
+
+        let mut profile = icc_profile(SRGB_PRIMARIES, &srgb_curve());
+        profile[16..20].copy_from_slice(b"CMYK");
+        assert!(DisplayTransform::from_icc(&profile).is_err());
+
+        let profile = icc_profile(SRGB_PRIMARIES, &srgb_curve());
+        assert!(DisplayTransform::from_icc(&profile[..profile.len() - 8]).is_err());
+    }
+
+    #[test]
+    fn test_calibration_settings() -> Result<()> {
+        let dir = create_test_dir("display_calibration", "calibration_settings")?;
+        let cube = dir.join("monitor.cube");
+        Lut3d::from_fn(2, |rgb| rgb.map(|value| value * 0.5)).save_cube(&cube)?;
+        let icc = dir.join("monitor.icc");
+        fs::write(&icc, icc_profile(SRGB_PRIMARIES, &gamma_curve(2.2)))?;
+
+        let mut settings = EngineSettings::default();
+        settings.display_calibrations.insert("DELL U2720Q".to_string(), DisplayCalibration::Lut {
+            path: cube,
+            interpolation: LutInterpolation::Trilinear,
+        });
+        settings.display_calibrations.insert("HDMI-1".to_string(), DisplayCalibration::Icc { path: icc });
+
+        let toml = toml::to_string_pretty(&settings)?;
+        assert_eq!(toml::from_str::<EngineSettings>(&toml)?, settings);
+
+        let transform = DisplayTransform::load(&settings.display_calibrations["DELL U2720Q"])?;
+        let mut frame = vec![200, 100, 50];
+        transform.apply(&mut frame, "RGB")?;
+        assert_eq!(frame, vec![100, 50, 25]);
+        DisplayTransform::load(&settings.display_calibrations["HDMI-1"])?;
+
+        assert!(DisplayTransform::load(&DisplayCalibration::Icc { path: dir.join("missing.icc") }).is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
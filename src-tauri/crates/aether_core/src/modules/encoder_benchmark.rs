@@ -0,0 +1,449 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
+/// Frame size used when comparing output against the source
+const QUALITY_FRAME_SIZE: (i32, i32) = (320, 180);
+
+/// An encoder configuration to try
+#[derive(Debug, Clone)]
+pub struct BenchmarkCandidate {
+    /// Display name, e.g. "x264 medium"
+    pub name: String,
+    /// GStreamer encoder element
+    pub encoder: String,
+    /// Encoder properties, parsed into the property's type
+    pub properties: Vec<(String, String)>,
+}
+
+impl BenchmarkCandidate {
+    pub fn new(name: &str, encoder: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            encoder: encoder.to_string(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Set an encoder property
+    pub fn with_property(mut self, name: &str, value: &str) -> Self {
+        self.properties.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Software H.264 with an x264 speed preset ("ultrafast" to "veryslow")
+    pub fn x264(preset: &str) -> Self {
+        Self::new(&format!("x264 {}", preset), "x264enc").with_property("speed-preset", preset)
+    }
+
+    /// Software H.265 with an x265 speed preset
+    pub fn x265(preset: &str) -> Self {
+        Self::new(&format!("x265 {}", preset), "x265enc").with_property("speed-preset", preset)
+    }
+
+    /// NVIDIA hardware H.264
+    pub fn nvenc_h264() -> Self {
+        Self::new("NVENC H.264", "nvh264enc")
+    }
+
+    /// NVIDIA hardware H.265
+    pub fn nvenc_h265() -> Self {
+        Self::new("NVENC H.265", "nvh265enc")
+    }
+
+    /// SVT-AV1 at a preset (0 slowest to 13 fastest)
+    pub fn svt_av1(preset: u32) -> Self {
+        Self::new(&format!("SVT-AV1 preset {}", preset), "svtav1enc").with_property("preset", &preset.to_string())
+    }
+
+    /// A typical comparison set: x264 presets, NVENC and AV1
+    pub fn default_set() -> Vec<Self> {
+        vec![
+            Self::x264("veryfast"),
+            Self::x264("medium"),
+            Self::x264("slow"),
+            Self::nvenc_h264(),
+            Self::svt_av1(8),
+        ]
+    }
+
+    /// Whether the encoder element is installed
+    pub fn is_available(&self) -> bool {
+        gst::ElementFactory::find(&self.encoder).is_some()
+    }
+
+    fn element_spec(&self) -> ElementSpec {
+        self.properties.iter().fold(ElementSpec::new(&self.encoder), |spec, (name, value)| {
+            spec.property_from_str(name, value)
+        })
+    }
+}
+
+/// Options for a benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchmarkOptions {
+    /// Start of the segment in the source, in seconds
+    pub start: f64,
+    /// Length of the segment, in seconds
+    pub duration: f64,
+    /// Output width, `None` keeps the source size
+    pub width: Option<u32>,
+    /// Output height, `None` keeps the source size
+    pub height: Option<u32>,
+    /// Compare each output against the source to compute PSNR
+    pub measure_quality: bool,
+    /// Directory for the encoded samples
+    pub output_dir: PathBuf,
+    /// Delete encoded samples after measuring
+    pub keep_outputs: bool,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            duration: 10.0,
+            width: None,
+            height: None,
+            measure_quality: true,
+            output_dir: std::env::temp_dir().join("aether_benchmark"),
+            keep_outputs: false,
+        }
+    }
+}
+
+/// Measurements for one candidate
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    pub name: String,
+    /// Wall clock encode time
+    pub encode_time: Duration,
+    /// Segment duration divided by encode time; above 1.0 is faster than real time
+    pub speed: f64,
+    /// Output size in bytes
+    pub output_size: u64,
+    /// Average bitrate in bits per second
+    pub bitrate: u64,
+    /// Average luma PSNR against the source in dB, if measured
+    pub psnr: Option<f64>,
+    /// Encoded sample, if kept
+    pub output_path: Option<PathBuf>,
+    /// Why the candidate didn't produce a result
+    pub error: Option<String>,
+}
+
+impl BenchmarkResult {
+    fn failed(name: &str, error: String) -> Self {
+        Self {
+            name: name.to_string(),
+            encode_time: Duration::ZERO,
+            speed: 0.0,
+            output_size: 0,
+            bitrate: 0,
+            psnr: None,
+            output_path: None,
+            error: Some(error),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchmarkReport {
+    pub source: PathBuf,
+    pub start: f64,
+    pub duration: f64,
+    pub results: Vec<BenchmarkResult>,
+}
+
+impl BenchmarkReport {
+    /// Fastest successful candidate
+    pub fn fastest(&self) -> Option<&BenchmarkResult> {
+        self.successful().max_by(|a, b| a.speed.total_cmp(&b.speed))
+    }
+
+    /// Candidate with the smallest output
+    pub fn smallest(&self) -> Option<&BenchmarkResult> {
+        self.successful().min_by_key(|r| r.output_size)
+    }
+
+    /// Candidate with the highest PSNR
+    pub fn best_quality(&self) -> Option<&BenchmarkResult> {
+        self.successful()
+            .filter(|r| r.psnr.is_some())
+            .max_by(|a, b| a.psnr.unwrap().total_cmp(&b.psnr.unwrap()))
+    }
+
+    fn successful(&self) -> impl Iterator<Item = &BenchmarkResult> {
+        self.results.iter().filter(|r| r.succeeded())
+    }
+}
+
+/// Encodes a short segment with several encoder configurations and compares them
+///
+/// Run it on a representative part of the timeline, e.g. the intermediate render of the
+/// busiest scene, so the numbers reflect the final export.
+pub struct EncoderBenchmark {
+    source: PathBuf,
+    options: BenchmarkOptions,
+}
+
+impl EncoderBenchmark {
+    pub fn new(source: &Path, options: BenchmarkOptions) -> Result<Self> {
+        if !source.is_file() {
+            return Err(anyhow!("Benchmark source does not exist: {:?}", source));
+        }
+        if options.duration <= 0.0 {
+            return Err(anyhow!("Invalid benchmark duration: {}", options.duration));
+        }
+
+        Ok(Self {
+            source: source.to_path_buf(),
+            options,
+        })
+    }
+
+    /// Run every candidate in turn
+    ///
+    /// Candidates whose encoder is missing or fails are reported with an error instead of
+    /// aborting the run.
+    pub fn run(&self, candidates: &[BenchmarkCandidate]) -> Result<BenchmarkReport> {
+        fs::create_dir_all(&self.options.output_dir)?;
+
+        // Decode the reference once
+        let reference = if self.options.measure_quality {
+            Some(decode_luma(&self.source, Some((self.options.start, self.options.duration)))?)
+        } else {
+            None
+        };
+
+        let results = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                if !candidate.is_available() {
+                    return BenchmarkResult::failed(&candidate.name, format!("Encoder {} is not available", candidate.encoder));
+                }
+
+                match self.run_candidate(index, candidate, reference.as_deref()) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        warn!("Benchmark candidate {} failed: {}", candidate.name, e);
+                        BenchmarkResult::failed(&candidate.name, e.to_string())
+                    },
+                }
+            })
+            .collect();
+
+        Ok(BenchmarkReport {
+            source: self.source.clone(),
+            start: self.options.start,
+            duration: self.options.duration,
+            results,
+        })
+    }
+
+    fn run_candidate(&self, index: usize, candidate: &BenchmarkCandidate, reference: Option<&[Vec<u8>]>) -> Result<BenchmarkResult> {
+        let output_path = self.options.output_dir.join(format!("candidate-{}.mkv", index));
+        let pipeline = self.build_encode_pipeline(candidate, &output_path)?;
+
+        info!("Benchmarking {}", candidate.name);
+        let encode_time = run_segment(&pipeline, Some((self.options.start, self.options.duration)))?;
+
+        let output_size = fs::metadata(&output_path)?.len();
+        let seconds = encode_time.as_secs_f64().max(0.001);
+
+        let psnr = match reference {
+            Some(reference) => {
+                let encoded = decode_luma(&output_path, None)?;
+                average_psnr(reference, &encoded)
+            },
+            None => None,
+        };
+        debug!("{}: {:.2}s, {} bytes, PSNR {:?}", candidate.name, seconds, output_size, psnr);
+
+        let kept_path = if self.options.keep_outputs {
+            Some(output_path)
+        } else {
+            let _ = fs::remove_file(&output_path);
+            None
+        };
+
+        Ok(BenchmarkResult {
+            name: candidate.name.clone(),
+            encode_time,
+            speed: self.options.duration / seconds,
+            output_size,
+            bitrate: (output_size as f64 * 8.0 / self.options.duration) as u64,
+            psnr,
+            output_path: kept_path,
+            error: None,
+        })
+    }
+
+    fn build_encode_pipeline(&self, candidate: &BenchmarkCandidate, output_path: &Path) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new("encoder-benchmark")?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(&self.source)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+
+        let mut chain = vec![ElementSpec::new("queue"), ElementSpec::new("videoconvert")];
+        if self.options.width.is_some() || self.options.height.is_some() {
+            chain.push(ElementSpec::new("videoscale"));
+            let mut caps = gst::Caps::builder("video/x-raw");
+            if let Some(width) = self.options.width {
+                caps = caps.field("width", width as i32);
+            }
+            if let Some(height) = self.options.height {
+                caps = caps.field("height", height as i32);
+            }
+            chain.push(ElementSpec::capsfilter(caps.build()));
+        }
+        chain.push(candidate.element_spec());
+        // Matroska takes every codec we benchmark
+        chain.push(ElementSpec::new("matroskamux"));
+        chain.push(ElementSpec::file_sink(output_path)?);
+
+        let encode = builder.chain(&chain)?;
+        builder.link_dynamic(&source[1], StreamKind::Video, &encode[0]);
+
+        Ok(builder.build())
+    }
+}
+
+/// Run a pipeline to EOS, optionally limited to a (start, duration) segment
+///
+/// Returns the time spent from the start of playback to EOS.
+fn run_segment(pipeline: &gst::Pipeline, segment: Option<(f64, f64)>) -> Result<Duration> {
+    let bus = pipeline.bus().unwrap();
+
+    let result = (|| {
+        pipeline.set_state(gst::State::Paused)?;
+        let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(10));
+        state_result.map_err(|_| anyhow!("Failed to preroll benchmark pipeline"))?;
+
+        if let Some((start, duration)) = segment {
+            pipeline.seek(
+                1.0,
+                gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+                gst::SeekType::Set,
+                gst::ClockTime::from_nseconds((start * 1_000_000_000.0) as u64),
+                gst::SeekType::Set,
+                gst::ClockTime::from_nseconds(((start + duration) * 1_000_000_000.0) as u64),
+            )?;
+        }
+
+        let started = Instant::now();
+        pipeline.set_state(gst::State::Playing)?;
+
+        for msg in bus.iter_timed(gst::ClockTime::NONE) {
+            match msg.view() {
+                gst::MessageView::Eos(..) => return Ok(started.elapsed()),
+                gst::MessageView::Error(err) => {
+                    return Err(anyhow!("{} ({})", err.error(), err.debug().unwrap_or_default()));
+                },
+                _ => (),
+            }
+        }
+
+        Err(anyhow!("Benchmark pipeline ended without EOS"))
+    })();
+
+    let _ = pipeline.set_state(gst::State::Null);
+    result
+}
+
+/// Decode a file (or a segment of it) into small grayscale frames for comparison
+fn decode_luma(path: &Path, segment: Option<(f64, f64)>) -> Result<Vec<Vec<u8>>> {
+    let builder = PipelineBuilder::new("benchmark-decode")?;
+    let source = builder.chain(&[
+        ElementSpec::file_source(path)?,
+        ElementSpec::new("decodebin"),
+    ])?;
+    let frames = builder.chain(&[
+        ElementSpec::new("videoconvert"),
+        ElementSpec::new("videoscale"),
+        ElementSpec::capsfilter(
+            gst::Caps::builder("video/x-raw")
+                .field("format", "GRAY8")
+                .field("width", QUALITY_FRAME_SIZE.0)
+                .field("height", QUALITY_FRAME_SIZE.1)
+                .build(),
+        ),
+        ElementSpec::new("appsink").name("frames").property("sync", false),
+    ])?;
+    builder.link_dynamic(&source[1], StreamKind::Video, &frames[0]);
+    let pipeline = builder.build();
+
+    let sink = frames[3]
+        .clone()
+        .dynamic_cast::<gst_app::AppSink>()
+        .map_err(|_| anyhow!("Failed to cast to AppSink"))?;
+
+    // Collect frames as they arrive
+    let collected = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let collected_clone = collected.clone();
+    sink.set_callbacks(
+        gst_app::AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                if let Some(buffer) = sample.buffer() {
+                    if let Ok(map) = buffer.map_readable() {
+                        collected_clone.lock().unwrap().push(map.as_slice().to_vec());
+                    }
+                }
+                Ok(gst::FlowSuccess::Ok)
+            })
+            .build(),
+    );
+
+    run_segment(&pipeline, segment)?;
+
+    let frames = std::mem::take(&mut *collected.lock().unwrap());
+    Ok(frames)
+}
+
+/// Average PSNR of two frame sequences, compared frame by frame
+pub fn average_psnr(reference: &[Vec<u8>], encoded: &[Vec<u8>]) -> Option<f64> {
+    let scores: Vec<f64> = reference
+        .iter()
+        .zip(encoded.iter())
+        .filter(|(a, b)| a.len() == b.len() && !a.is_empty())
+        .map(|(a, b)| psnr(a, b))
+        .collect();
+
+    if scores.is_empty() {
+        return None;
+    }
+
+    Some(scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+/// PSNR of two 8-bit planes in dB, capped at 100 for identical input
+fn psnr(a: &[u8], b: &[u8]) -> f64 {
+    let mse = a
+        .iter()
+        .zip(b.iter())
+        .map(|(&x, &y)| {
+            let diff = x as f64 - y as f64;
+            diff * diff
+        })
+        .sum::<f64>()
+        / a.len() as f64;
+
+    if mse == 0.0 {
+        100.0
+    } else {
+        (10.0 * (255.0 * 255.0 / mse).log10()).min(100.0)
+    }
+}
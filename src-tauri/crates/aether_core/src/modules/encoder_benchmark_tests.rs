@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod tests {
+    use super::super::encoder_benchmark::average_psnr;
+    use anyhow::Result;
+
+    #[test]
+    fn test_psnr_of_identical_frames_is_capped() -> Result<()> {
+        let frames = vec![vec![10u8, 20, 30, 40]; 3];
+        assert_eq!(average_psnr(&frames, &frames), Some(100.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_psnr_drops_with_error() -> Result<()> {
+        let reference = vec![vec![100u8; 64]];
+        let close = vec![vec![101u8; 64]];
+        let far = vec![vec![140u8; 64]];
+
+        let close_psnr = average_psnr(&reference, &close).unwrap();
+        let far_psnr = average_psnr(&reference, &far).unwrap();
+
+        // MSE of 1 is ~48.1 dB
+        assert!((close_psnr - 48.13).abs() < 0.01);
+        assert!(far_psnr < close_psnr);
+
+        // Nothing to compare
+        assert_eq!(average_psnr(&reference, &[]), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+
+use super::file_manager::MediaType;
+use super::media_library::MediaLibrary;
+use super::vision_model::{OnnxModel, SampledFrame, sample_frames};
+
+/// Tag given to assets with at least one detected face
+pub const PEOPLE_TAG: &str = "people";
+
+/// Face detection settings
+#[derive(Debug, Clone)]
+pub struct FaceDetectorConfig {
+    /// UltraFace style ONNX model: boxes `[1, N, 4]` as corners and scores `[1, N, 2]`
+    pub model_path: PathBuf,
+    /// Minimum face score
+    pub score_threshold: f32,
+    /// Overlap above which the weaker of two boxes is dropped
+    pub iou_threshold: f32,
+    /// Seconds between analyzed frames
+    pub sample_interval: f64,
+    /// Frames analyzed per file at most
+    pub max_samples: usize,
+}
+
+impl Default for FaceDetectorConfig {
+    fn default() -> Self {
+        Self {
+            model_path: PathBuf::from("models/face_detection.onnx"),
+            score_threshold: 0.7,
+            iou_threshold: 0.3,
+            sample_interval: 1.0,
+            max_samples: 600,
+        }
+    }
+}
+
+/// A detected face, in coordinates relative to the frame size (0-1)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FaceBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub score: f32,
+}
+
+impl FaceBox {
+    pub fn area(&self) -> f32 {
+        self.width.max(0.0) * self.height.max(0.0)
+    }
+
+    /// Intersection over union with another box
+    pub fn iou(&self, other: &FaceBox) -> f32 {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        let intersection = (right - left).max(0.0) * (bottom - top).max(0.0);
+        let union = self.area() + other.area() - intersection;
+        if union > 0.0 { intersection / union } else { 0.0 }
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+/// Faces found in one analyzed frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaceSample {
+    /// Position in the source in seconds
+    pub timestamp: f64,
+    pub faces: Vec<FaceBox>,
+}
+
+/// Face detection results for a media file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaceAnalysis {
+    /// Analyzed frames in time order, including ones without faces
+    pub samples: Vec<FaceSample>,
+}
+
+impl FaceAnalysis {
+    /// Most faces seen in one frame
+    pub fn max_faces(&self) -> usize {
+        self.samples.iter().map(|sample| sample.faces.len()).max().unwrap_or(0)
+    }
+
+    pub fn has_faces(&self) -> bool {
+        self.max_faces() > 0
+    }
+
+    /// Timestamps of the frames with faces
+    pub fn face_timestamps(&self) -> Vec<f64> {
+        self.samples.iter()
+            .filter(|sample| !sample.faces.is_empty())
+            .map(|sample| sample.timestamp)
+            .collect()
+    }
+
+    /// Center of the faces in the analyzed frame closest to `timestamp`, weighted by size
+    ///
+    /// Used by auto-reframe to keep people in shot.
+    pub fn subject_center(&self, timestamp: f64) -> Option<(f32, f32)> {
+        let sample = self.samples.iter()
+            .filter(|sample| !sample.faces.is_empty())
+            .min_by(|a, b| (a.timestamp - timestamp).abs().total_cmp(&(b.timestamp - timestamp).abs()))?;
+
+        let total: f32 = sample.faces.iter().map(FaceBox::area).sum();
+        if total <= 0.0 {
+            return sample.faces.first().map(FaceBox::center);
+        }
+        let (x, y) = sample.faces.iter().fold((0.0, 0.0), |(x, y), face| {
+            let (cx, cy) = face.center();
+            (x + cx * face.area(), y + cy * face.area())
+        });
+        Some((x / total, y / total))
+    }
+}
+
+/// Finds faces in frames with an ONNX model
+pub struct FaceDetector {
+    model: OnnxModel,
+
+    config: FaceDetectorConfig,
+}
+
+impl FaceDetector {
+    /// Load the detection model
+    pub fn new(config: FaceDetectorConfig) -> Result<Self> {
+        let model = OnnxModel::load(&config.model_path, Some((320, 240)))?;
+        Ok(Self { model, config })
+    }
+
+    pub fn config(&self) -> &FaceDetectorConfig {
+        &self.config
+    }
+
+    /// Faces in one frame, which must be at the model's input size
+    pub fn detect_frame(&self, frame: &SampledFrame) -> Result<Vec<FaceBox>> {
+        let outputs = self.model.run(frame.to_nchw([127.0; 3], [128.0; 3]))?;
+
+        let scores = outputs.values().find(|output| output.shape.last() == Some(&2));
+        let boxes = outputs.values().find(|output| output.shape.last() == Some(&4));
+        let (scores, boxes) = match (scores, boxes) {
+            (Some(scores), Some(boxes)) => (scores, boxes),
+            _ => return Err(anyhow!("Unexpected outputs from face model {:?}", self.model.path())),
+        };
+
+        let candidates = scores.data
+            .chunks_exact(2)
+            .zip(boxes.data.chunks_exact(4))
+            .filter(|(score, _)| score[1] >= self.config.score_threshold)
+            .map(|(score, corners)| {
+                let x = corners[0].clamp(0.0, 1.0);
+                let y = corners[1].clamp(0.0, 1.0);
+                FaceBox {
+                    x,
+                    y,
+                    width: corners[2].clamp(0.0, 1.0) - x,
+                    height: corners[3].clamp(0.0, 1.0) - y,
+                    score: score[1],
+                }
+            })
+            .filter(|face| face.area() > 0.0)
+            .collect();
+
+        Ok(non_max_suppression(candidates, self.config.iou_threshold))
+    }
+
+    /// Detect faces throughout a file
+    #[tracing::instrument(name = "face_detection", skip(self))]
+    pub fn analyze(&self, path: &Path) -> Result<FaceAnalysis> {
+        let (width, height) = self.model.input_size();
+        let frames = sample_frames(path, self.config.sample_interval, width, height, self.config.max_samples)?;
+
+        let samples = frames
+            .iter()
+            .map(|frame| {
+                Ok(FaceSample {
+                    timestamp: frame.timestamp,
+                    faces: self.detect_frame(frame)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(FaceAnalysis { samples })
+    }
+}
+
+/// Keep the strongest of overlapping boxes
+pub fn non_max_suppression(mut boxes: Vec<FaceBox>, iou_threshold: f32) -> Vec<FaceBox> {
+    boxes.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut kept: Vec<FaceBox> = Vec::new();
+    for candidate in boxes {
+        if kept.iter().all(|face| face.iou(&candidate) <= iou_threshold) {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+/// Run face detection over library assets and tag them
+///
+/// Covers the given assets, or every video and image asset without results yet. Assets
+/// that fail are logged and skipped. Returns the IDs of the analyzed assets.
+pub fn tag_faces(library: &mut MediaLibrary, detector: &FaceDetector, asset_ids: Option<&[String]>) -> Result<Vec<String>> {
+    let targets: Vec<String> = match asset_ids {
+        Some(ids) => ids.to_vec(),
+        None => library.assets()
+            .into_iter()
+            .filter(|asset| asset.faces.is_none() && !asset.offline)
+            .filter(|asset| asset.info.as_ref().map_or(true, |info| matches!(info.media_type, MediaType::Video | MediaType::Image)))
+            .map(|asset| asset.id.clone())
+            .collect(),
+    };
+
+    let mut analyzed = Vec::new();
+    for id in targets {
+        let path = library.resolve_path(&id)?;
+        let analysis = match detector.analyze(&path) {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                warn!("Face detection failed for {} ({:?}): {}", id, path, e);
+                continue;
+            },
+        };
+
+        if let Some(asset) = library.get_asset_mut(&id) {
+            if analysis.has_faces() {
+                asset.add_tag(PEOPLE_TAG);
+            } else {
+                asset.remove_tag(PEOPLE_TAG);
+            }
+            asset.faces = Some(analysis);
+            analyzed.push(id);
+        }
+    }
+
+    info!("Face detection tagged {} assets", analyzed.len());
+    Ok(analyzed)
+}
@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod tests {
+    use super::super::face_detection::{non_max_suppression, FaceAnalysis, FaceBox, FaceSample};
+    use anyhow::Result;
+
+    fn face(x: f32, y: f32, size: f32, score: f32) -> FaceBox {
+        FaceBox { x, y, width: size, height: size, score }
+    }
+
+    #[test]
+    fn test_non_max_suppression_keeps_strongest() -> Result<()> {
+        let boxes = vec![
+            face(0.10, 0.10, 0.2, 0.80),
+            face(0.12, 0.11, 0.2, 0.95),
+            face(0.60, 0.50, 0.2, 0.75),
+        ];
+
+        let kept = non_max_suppression(boxes, 0.3);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].score, 0.95);
+        assert_eq!(kept[1].score, 0.75);
+        Ok(())
+    }
+
+    #[test]
+    fn test_analysis_summaries() -> Result<()> {
+        let analysis = FaceAnalysis {
+            samples: vec![
+                FaceSample { timestamp: 0.0, faces: vec![] },
+                FaceSample { timestamp: 1.0, faces: vec![face(0.0, 0.0, 0.2, 0.9), face(0.6, 0.0, 0.2, 0.9)] },
+                FaceSample { timestamp: 2.0, faces: vec![face(0.4, 0.4, 0.2, 0.9)] },
+            ],
+        };
+
+        assert_eq!(analysis.max_faces(), 2);
+        assert_eq!(analysis.face_timestamps(), vec![1.0, 2.0]);
+
+        let (x, y) = analysis.subject_center(0.8).unwrap();
+        assert!((x - 0.4).abs() < 1e-5 && (y - 0.1).abs() < 1e-5);
+        let (x, y) = analysis.subject_center(2.2).unwrap();
+        assert!((x - 0.5).abs() < 1e-5 && (y - 0.5).abs() < 1e-5);
+        assert!(FaceAnalysis::default().subject_center(0.0).is_none());
+        Ok(())
+    }
+}
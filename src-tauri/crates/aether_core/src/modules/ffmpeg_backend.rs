@@ -0,0 +1,175 @@
+//! FFmpeg implementations of operations that normally run on GStreamer
+//!
+//! Used as the fallback, or when the backend policy forces FFmpeg because the system's
+//! GStreamer plugins are broken.
+
+use anyhow::{anyhow, Result};
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{context::Context as SwsContext, flag::Flags};
+use ffmpeg::util::frame::video::Video;
+use tracing::debug;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::engine::rendering::{AudioFormat, ContainerFormat, Exporter, ExportOptions, RetryPolicy, VideoFormat};
+use super::file_manager::ThumbnailOptions;
+use super::file_manager_convert::{ConversionFormat, VideoConversionOptions};
+
+/// Decode the frame at `options.position` and write it to `output` as a JPEG
+pub fn grab_jpeg_frame(path: &Path, options: &ThumbnailOptions, output: &Path) -> Result<()> {
+    ffmpeg::init()?;
+
+    let mut input = ffmpeg::format::input(&path)
+        .map_err(|e| anyhow!("Failed to open {:?}: {}", path, e))?;
+    let stream = input
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| anyhow!("No video stream in {:?}", path))?;
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    // Seek to the keyframe before the position and decode forward from there
+    let position = options.position.unwrap_or(0.0).max(0.0);
+    if position > 0.0 {
+        let timestamp = (position * ffmpeg::ffi::AV_TIME_BASE as f64) as i64;
+        input.seek(timestamp, ..timestamp)?;
+    }
+
+    let mut frame = Video::empty();
+    let mut source = None;
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            let timestamp = frame.timestamp().map(|t| t as f64 * time_base).unwrap_or(position);
+            if timestamp + 0.001 >= position {
+                source = Some(frame.clone());
+                break;
+            }
+        }
+        if source.is_some() {
+            break;
+        }
+    }
+
+    // Past the end: use the last frame
+    if source.is_none() {
+        decoder.send_eof()?;
+        while decoder.receive_frame(&mut frame).is_ok() {
+            source = Some(frame.clone());
+        }
+    }
+    let source = source.ok_or_else(|| anyhow!("No frame decoded from {:?}", path))?;
+
+    let jpeg = encode_jpeg(&source, options.width, options.height, options.quality)?;
+    fs::write(output, jpeg)?;
+
+    debug!("FFmpeg thumbnail for {:?} at {:.3}s written to {:?}", path, position, output);
+    Ok(())
+}
+
+/// Scale a frame and encode it as a single MJPEG picture
+fn encode_jpeg(source: &Video, width: u32, height: u32, quality: u8) -> Result<Vec<u8>> {
+    let width = if width > 0 { width } else { source.width() };
+    let height = if height > 0 { height } else { source.height() };
+
+    // MJPEG wants full range YUV
+    let mut scaler = SwsContext::get(
+        source.format(),
+        source.width(),
+        source.height(),
+        Pixel::YUVJ420P,
+        width,
+        height,
+        Flags::BILINEAR,
+    )?;
+    let mut scaled = Video::empty();
+    scaler.run(source, &mut scaled)?;
+    scaled.set_pts(Some(0));
+
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG)
+        .ok_or_else(|| anyhow!("FFmpeg was built without the MJPEG encoder"))?;
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    encoder.set_width(width);
+    encoder.set_height(height);
+    encoder.set_format(Pixel::YUVJ420P);
+    encoder.set_time_base((1, 25));
+
+    // Map quality 0-100 onto the MJPEG quantizer, 2 (best) to 31 (worst)
+    let qscale = 2 + (100 - quality.min(100) as i32) * 29 / 100;
+    encoder.set_flags(ffmpeg::codec::Flags::QSCALE);
+    encoder.set_quality((qscale * ffmpeg::ffi::FF_QP2LAMBDA) as usize);
+
+    let mut encoder = encoder.open_as(codec)?;
+    encoder.send_frame(&scaled)?;
+    encoder.send_eof()?;
+
+    let mut packet = ffmpeg::Packet::empty();
+    encoder.receive_packet(&mut packet)?;
+    packet
+        .data()
+        .map(|data| data.to_vec())
+        .ok_or_else(|| anyhow!("MJPEG encoder produced no data"))
+}
+
+/// Transcode a video file with the FFmpeg exporter
+pub fn convert_video(
+    input_path: &Path,
+    output_path: &Path,
+    options: &VideoConversionOptions,
+    progress_callback: &dyn Fn(f64),
+) -> Result<()> {
+    let (container_format, video_format, audio_format) = match options.format {
+        ConversionFormat::MP4 => (ContainerFormat::Mp4, VideoFormat::H264, AudioFormat::Aac),
+        ConversionFormat::MOV => (ContainerFormat::Mov, VideoFormat::H264, AudioFormat::Aac),
+        ConversionFormat::WebM => (ContainerFormat::Webm, VideoFormat::Vp9, AudioFormat::Opus),
+        other => return Err(anyhow!("{:?} is not a video format", other)),
+    };
+
+    let defaults = ExportOptions::default();
+    let mut exporter = Exporter::new(ExportOptions {
+        input_path: input_path.to_path_buf(),
+        output_path: output_path.to_path_buf(),
+        container_format,
+        video_format,
+        audio_format,
+        video_bitrate: options.video_bitrate.unwrap_or(defaults.video_bitrate),
+        audio_bitrate: options.audio_bitrate.unwrap_or(defaults.audio_bitrate),
+        frame_rate: options.frame_rate.unwrap_or(defaults.frame_rate),
+        width: options.width.unwrap_or(0),
+        height: options.height.unwrap_or(0),
+        ..defaults
+    })?;
+
+    // The converter already checked free space and does its own error handling
+    exporter.set_space_check(None);
+    exporter.set_retry_policy(RetryPolicy::none());
+    exporter.start_export()?;
+
+    loop {
+        thread::sleep(Duration::from_millis(200));
+
+        let progress = exporter.get_progress();
+        progress_callback(progress.percent);
+
+        if progress.complete {
+            return match progress.error {
+                Some(error) => Err(anyhow!("FFmpeg conversion failed: {}", error)),
+                None => {
+                    progress_callback(100.0);
+                    Ok(())
+                },
+            };
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use gst::prelude::*;
-use log::{debug, error, info, warn};
+use tracing::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{self, File};
@@ -9,6 +9,11 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use super::backend_policy::{self, Backend, BackendPolicy, Subsystem};
+use super::ffmpeg_backend;
+use super::path_policy::{self, PathPolicy};
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MediaType {
     Video,
@@ -55,6 +60,8 @@ pub struct FileManager {
     temp_dir: PathBuf,
     media_info_cache: Arc<Mutex<HashMap<PathBuf, MediaInfo>>>,
     thumbnail_cache: Arc<Mutex<HashMap<PathBuf, PathBuf>>>,
+    path_policy: PathPolicy,
+    backend_policy: BackendPolicy,
 }
 
 impl FileManager {
@@ -70,9 +77,31 @@ impl FileManager {
             temp_dir,
             media_info_cache: Arc::new(Mutex::new(HashMap::new())),
             thumbnail_cache: Arc::new(Mutex::new(HashMap::new())),
+            path_policy: PathPolicy::unrestricted(),
+            backend_policy: backend_policy::global_policy(),
         })
     }
     
+    /// Restrict the paths this file manager will read and write
+    pub fn set_path_policy(&mut self, policy: PathPolicy) {
+        self.path_policy = policy;
+    }
+    
+    /// Get the active path policy
+    pub fn path_policy(&self) -> &PathPolicy {
+        &self.path_policy
+    }
+    
+    /// Set which backend video thumbnails are generated with
+    pub fn set_backend_policy(&mut self, policy: BackendPolicy) {
+        self.backend_policy = policy;
+    }
+    
+    /// Get the active backend policy
+    pub fn backend_policy(&self) -> &BackendPolicy {
+        &self.backend_policy
+    }
+    
     pub fn get_media_info(&self, path: &Path) -> Result<MediaInfo> {
         if let Some(info) = self.media_info_cache.lock().unwrap().get(path) {
             return Ok(info.clone());
@@ -82,6 +111,8 @@ impl FileManager {
             return Err(anyhow!("File does not exist: {:?}", path));
         }
         
+        self.path_policy.validate_input(path)?;
+        
         let metadata = fs::metadata(path)?;
         let size = metadata.len();
         
@@ -130,6 +161,8 @@ impl FileManager {
             }
         }
         
+        self.path_policy.validate_input(path)?;
+        
         // Determine media type
         let media_type = self.determine_media_type(path);
         
@@ -147,6 +180,19 @@ impl FileManager {
         Ok(thumbnail_path)
     }
     
+    /// Write a video frame as a JPEG thumbnail to `destination`, bypassing the cache
+    ///
+    /// For files that change under the same path, such as re-rendered exports.
+    pub fn write_video_thumbnail(&self, path: &Path, options: &ThumbnailOptions, destination: &Path) -> Result<()> {
+        self.path_policy.validate_input(path)?;
+        self.path_policy.validate_output(destination)?;
+        
+        self.backend_policy.run(Subsystem::Thumbnailing, |backend| match backend {
+            Backend::GStreamer => Self::grab_video_thumbnail(path, options, destination),
+            Backend::FFmpeg => ffmpeg_backend::grab_jpeg_frame(path, options, destination),
+        })
+    }
+    
     /// Copy a file with progress reporting
     pub fn copy_file<F>(&self, source: &Path, destination: &Path, progress_callback: F) -> Result<()>
     where
@@ -157,10 +203,10 @@ impl FileManager {
             return Err(anyhow!("Source file does not exist: {:?}", source));
         }
         
-        // Create destination directory if it doesn't exist
-        if let Some(parent) = destination.parent() {
-            fs::create_dir_all(parent)?;
-        }
+        self.path_policy.validate_input(source)?;
+        
+        // Creates the destination directory if it doesn't exist
+        self.path_policy.validate_output(destination)?;
         
         // Get file size
         let file_size = fs::metadata(source)?.len();
@@ -198,19 +244,31 @@ impl FileManager {
             return Err(anyhow!("Video file does not exist: {:?}", video_path));
         }
         
+        let video_path = self.path_policy.validate_input(video_path)?;
+        
         // Create output directory if it doesn't exist
-        fs::create_dir_all(output_dir)?;
+        let output_dir = &self.path_policy.validate_output_dir(output_dir)?;
         
         // Create GStreamer pipeline for frame extraction
-        let pipeline_str = format!(
-            "filesrc location=\"{}\" ! decodebin ! videorate ! video/x-raw,framerate={}/1 ! \
-             videoconvert ! jpegenc quality=90 ! multifilesink location=\"{}/frame-%04d.jpg\"",
-            video_path.to_str().unwrap(),
-            fps,
-            output_dir.to_str().unwrap()
-        );
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        let builder = PipelineBuilder::new("extract-frames")?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(&video_path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        let frames = builder.chain(&[
+            ElementSpec::new("videorate"),
+            ElementSpec::capsfilter(
+                gst::Caps::builder("video/x-raw")
+                    .field("framerate", gst::Fraction::approximate_f64(fps).unwrap_or_else(|| gst::Fraction::new(1, 1)))
+                    .build(),
+            ),
+            ElementSpec::new("videoconvert"),
+            ElementSpec::new("jpegenc").property("quality", 90i32),
+            ElementSpec::new("multifilesink").location(&output_dir.join("frame-%04d.jpg"))?,
+        ])?;
+        builder.link_dynamic(&source[1], StreamKind::Video, &frames[0]);
+        
+        let pipeline = builder.build();
         let bus = pipeline.bus().unwrap();
         
         // Start the pipeline
@@ -296,7 +354,7 @@ impl FileManager {
             .map_err(|_| anyhow!("Failed to create GStreamer discoverer"))?;
         
         // Discover media info
-        let uri = format!("file://{}", path.to_str().unwrap());
+        let uri = path_policy::path_to_uri(path)?;
         let discover_info = discoverer.discover_uri(&uri)
             .map_err(|err| anyhow!("Failed to discover media info: {}", err))?;
         
@@ -356,12 +414,17 @@ impl FileManager {
     /// Extract image information
     fn extract_image_info(&self, path: &Path, info: &mut MediaInfo) -> Result<()> {
         // Create GStreamer pipeline to get image dimensions
-        let pipeline_str = format!(
-            "filesrc location=\"{}\" ! decodebin ! imagefreeze ! fakesink",
-            path.to_str().unwrap()
-        );
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        let builder = PipelineBuilder::new("image-info")?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        let sink = builder.chain(&[
+            ElementSpec::new("imagefreeze"),
+            ElementSpec::new("fakesink"),
+        ])?;
+        builder.link_dynamic(&source[1], StreamKind::Video, &sink[0]);
+        let pipeline = builder.build();
         let bus = pipeline.bus().unwrap();
         
         // Start the pipeline
@@ -415,7 +478,7 @@ impl FileManager {
     /// Generate video thumbnail
     fn generate_video_thumbnail(&self, path: &Path, options: &ThumbnailOptions) -> Result<PathBuf> {
         // Create output path
-        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_stem = path_policy::output_stem(path);
         let thumbnail_path = self.temp_dir.join(format!(
             "{}-thumb-{}x{}-{}.jpg",
             file_stem,
@@ -424,19 +487,18 @@ impl FileManager {
             options.position.unwrap_or(0.0)
         ));
         
-        // Create GStreamer pipeline for thumbnail extraction
-        let position_ns = (options.position.unwrap_or(0.0) * 1_000_000_000.0) as i64;
-        let pipeline_str = format!(
-            "filesrc location=\"{}\" ! decodebin ! videoconvert ! videoscale ! \
-             video/x-raw,width={},height={} ! jpegenc quality={} ! filesink location=\"{}\"",
-            path.to_str().unwrap(),
-            options.width,
-            options.height,
-            options.quality,
-            thumbnail_path.to_str().unwrap()
-        );
+        self.backend_policy.run(Subsystem::Thumbnailing, |backend| match backend {
+            Backend::GStreamer => Self::grab_video_thumbnail(path, options, &thumbnail_path),
+            Backend::FFmpeg => ffmpeg_backend::grab_jpeg_frame(path, options, &thumbnail_path),
+        })?;
         
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        Ok(thumbnail_path)
+    }
+    
+    /// Grab a video frame as JPEG with GStreamer
+    fn grab_video_thumbnail(path: &Path, options: &ThumbnailOptions, thumbnail_path: &Path) -> Result<()> {
+        let position_ns = (options.position.unwrap_or(0.0) * 1_000_000_000.0) as i64;
+        let pipeline = Self::build_scaled_jpeg_pipeline("video-thumbnail", path, thumbnail_path, options)?;
         
         // Set position for seeking
         pipeline.set_state(gst::State::Paused)?;
@@ -509,13 +571,13 @@ impl FileManager {
             return Err(anyhow!("Failed to generate thumbnail"));
         }
         
-        Ok(thumbnail_path)
+        Ok(())
     }
     
     /// Generate image thumbnail
     fn generate_image_thumbnail(&self, path: &Path, options: &ThumbnailOptions) -> Result<PathBuf> {
         // Create output path
-        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_stem = path_policy::output_stem(path);
         let thumbnail_path = self.temp_dir.join(format!(
             "{}-thumb-{}x{}.jpg",
             file_stem,
@@ -524,17 +586,7 @@ impl FileManager {
         ));
         
         // Create GStreamer pipeline for image scaling
-        let pipeline_str = format!(
-            "filesrc location=\"{}\" ! decodebin ! videoconvert ! videoscale ! \
-             video/x-raw,width={},height={} ! jpegenc quality={} ! filesink location=\"{}\"",
-            path.to_str().unwrap(),
-            options.width,
-            options.height,
-            options.quality,
-            thumbnail_path.to_str().unwrap()
-        );
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        let pipeline = Self::build_scaled_jpeg_pipeline("image-thumbnail", path, &thumbnail_path, options)?;
         let bus = pipeline.bus().unwrap();
         
         // Start the pipeline
@@ -563,10 +615,39 @@ impl FileManager {
         Ok(thumbnail_path)
     }
     
+    /// Build a pipeline decoding `path` and writing a scaled JPEG to `output`
+    fn build_scaled_jpeg_pipeline(
+        name: &str,
+        path: &Path,
+        output: &Path,
+        options: &ThumbnailOptions,
+    ) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new(name)?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        let encode = builder.chain(&[
+            ElementSpec::new("videoconvert"),
+            ElementSpec::new("videoscale"),
+            ElementSpec::capsfilter(
+                gst::Caps::builder("video/x-raw")
+                    .field("width", options.width as i32)
+                    .field("height", options.height as i32)
+                    .build(),
+            ),
+            ElementSpec::new("jpegenc").property("quality", options.quality as i32),
+            ElementSpec::file_sink(output)?,
+        ])?;
+        builder.link_dynamic(&source[1], StreamKind::Video, &encode[0]);
+        
+        Ok(builder.build())
+    }
+    
     /// Generate audio thumbnail (waveform image)
     fn generate_audio_thumbnail(&self, path: &Path, options: &ThumbnailOptions) -> Result<PathBuf> {
         // Create output path
-        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_stem = path_policy::output_stem(path);
         let thumbnail_path = self.temp_dir.join(format!(
             "{}-waveform-{}x{}.png",
             file_stem,
@@ -575,16 +656,25 @@ impl FileManager {
         ));
         
         // Create GStreamer pipeline for waveform generation
-        let pipeline_str = format!(
-            "filesrc location=\"{}\" ! decodebin ! audioconvert ! \
-             audiowaveform wave-mode=lines style=lines fill=true background-color=0x000000ff \
-             foreground-color=0x00FF00FF scale-digitized=true ! \
-             pngenc compression-level=6 ! filesink location=\"{}\"",
-            path.to_str().unwrap(),
-            thumbnail_path.to_str().unwrap()
-        );
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        let builder = PipelineBuilder::new("audio-waveform")?;
+        let source = builder.chain(&[
+            ElementSpec::file_source(path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        let waveform = builder.chain(&[
+            ElementSpec::new("audioconvert"),
+            ElementSpec::new("audiowaveform")
+                .property_from_str("wave-mode", "lines")
+                .property_from_str("style", "lines")
+                .property_from_str("fill", "true")
+                .property_from_str("background-color", "0x000000ff")
+                .property_from_str("foreground-color", "0x00FF00FF")
+                .property_from_str("scale-digitized", "true"),
+            ElementSpec::new("pngenc").property("compression-level", 6u32),
+            ElementSpec::file_sink(&thumbnail_path)?,
+        ])?;
+        builder.link_dynamic(&source[1], StreamKind::Audio, &waveform[0]);
+        let pipeline = builder.build();
         let bus = pipeline.bus().unwrap();
         
         // Start the pipeline
@@ -626,16 +716,22 @@ impl FileManager {
         ));
         
         // Create a simple audio icon (blue waveform on black background)
-        let pipeline_str = format!(
-            "videotestsrc pattern=black ! video/x-raw,width={},height={} ! \
-             videooverlay text=\"Audio File\" font-desc=\"Sans 24\" ! \
-             pngenc compression-level=6 ! filesink location=\"{}\"",
-            options.width,
-            options.height,
-            thumbnail_path.to_str().unwrap()
-        );
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
+        let builder = PipelineBuilder::new("audio-icon")?;
+        builder.chain(&[
+            ElementSpec::new("videotestsrc").property_from_str("pattern", "black"),
+            ElementSpec::capsfilter(
+                gst::Caps::builder("video/x-raw")
+                    .field("width", options.width as i32)
+                    .field("height", options.height as i32)
+                    .build(),
+            ),
+            ElementSpec::new("textoverlay")
+                .property("text", "Audio File")
+                .property("font-desc", "Sans 24"),
+            ElementSpec::new("pngenc").property("compression-level", 6u32),
+            ElementSpec::file_sink(&thumbnail_path)?,
+        ])?;
+        let pipeline = builder.build();
         let bus = pipeline.bus().unwrap();
         
         // Start the pipeline
@@ -1,11 +1,12 @@
 use anyhow::{anyhow, Result};
-use log::{debug, error, info};
+use tracing::{debug, error, info};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use super::file_manager::{FileManager, MediaInfo, ThumbnailOptions};
+use super::path_policy;
 
 /// Status of a batch operation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -449,8 +450,7 @@ impl BatchProcessor {
             // Process file
             if path.is_file() {
                 // Create subdirectory for this file
-                let file_name = path.file_stem().unwrap_or_default().to_string_lossy();
-                let file_output_dir = output_dir.join(file_name.to_string());
+                let file_output_dir = output_dir.join(path_policy::output_stem(path));
                 std::fs::create_dir_all(&file_output_dir)?;
                 
                 // Extract frames
@@ -2,11 +2,19 @@ use anyhow::{anyhow, Result};
 use glib::MainLoop;
 use gstreamer as gst;
 use gstreamer::prelude::*;
-use log::{debug, error, info, warn};
+use tracing::{debug, error, info, warn};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use super::backend_policy::{self, Backend, BackendPolicy, Subsystem};
+use super::disk_space::{self, OutputEstimate, SpaceCheck};
+use super::ffmpeg_backend;
+use super::path_policy::{self, PathPolicy};
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+use super::pipeline_watchdog::{Watchdog, WatchdogConfig};
+use super::settings::HardwareAcceleration;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConversionFormat {
     MP4,
@@ -140,8 +148,130 @@ impl Default for ImageConversionOptions {
     }
 }
 
+/// Elements that scale and convert video in conversion pipelines
+///
+/// The GPU processors upload each frame once, scale and convert it there, and download
+/// I420, which the default encoders take as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoProcessor {
+    /// `videoscale` and `videoconvert` on the CPU
+    Software,
+    /// `vapostproc` from the VA plugin
+    Va,
+    /// `vaapipostproc` from the older gstreamer-vaapi plugins
+    Vaapi,
+    /// OpenGL upload, `glcolorscale` and `glcolorconvert`
+    Gl,
+}
+
+impl VideoProcessor {
+    /// Processors tried when hardware is allowed, fastest first
+    pub const PREFERENCE: [VideoProcessor; 4] = [
+        VideoProcessor::Va,
+        VideoProcessor::Vaapi,
+        VideoProcessor::Gl,
+        VideoProcessor::Software,
+    ];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoProcessor::Software => "software",
+            VideoProcessor::Va => "va",
+            VideoProcessor::Vaapi => "vaapi",
+            VideoProcessor::Gl => "gl",
+        }
+    }
+    
+    /// Element factories the processor needs
+    pub fn factories(&self) -> &'static [&'static str] {
+        match self {
+            VideoProcessor::Software => &["videoscale", "videoconvert"],
+            VideoProcessor::Va => &["vapostproc", "videoconvert"],
+            VideoProcessor::Vaapi => &["vaapipostproc", "videoconvert"],
+            VideoProcessor::Gl => &["glupload", "glcolorconvert", "glcolorscale", "gldownload", "videoconvert"],
+        }
+    }
+    
+    /// Whether every element the processor needs is installed
+    pub fn is_available(&self) -> bool {
+        self.factories().iter().all(|factory| gst::ElementFactory::find(factory).is_some())
+    }
+    
+    /// Processor to use under a hardware acceleration setting
+    ///
+    /// Scaling is treated like decoding, so `Auto` uses the GPU when it can.
+    pub fn select(acceleration: HardwareAcceleration) -> Self {
+        if !acceleration.for_decoding() {
+            return VideoProcessor::Software;
+        }
+        Self::PREFERENCE
+            .into_iter()
+            .find(|processor| processor.is_available())
+            .unwrap_or(VideoProcessor::Software)
+    }
+    
+    /// Elements scaling to `width` and `height`, where given, and converting for an encoder
+    ///
+    /// The chain starts and ends in system memory. `videoconvert` comes last in every chain
+    /// so encoders wanting another format still negotiate; it passes I420 straight through.
+    pub fn elements(&self, width: Option<u32>, height: Option<u32>, high_quality: bool) -> Vec<ElementSpec> {
+        let scaling = width.is_some() || height.is_some();
+        let sized = || {
+            let mut caps = gst::Caps::builder("video/x-raw");
+            if let Some(width) = width {
+                caps = caps.field("width", width as i32);
+            }
+            if let Some(height) = height {
+                caps = caps.field("height", height as i32);
+            }
+            caps
+        };
+        
+        let mut chain = Vec::new();
+        match self {
+            VideoProcessor::Software => {
+                if scaling {
+                    let mut scale = ElementSpec::new("videoscale");
+                    if high_quality {
+                        scale = scale.property_from_str("method", "lanczos");
+                    }
+                    chain.push(scale);
+                    chain.push(ElementSpec::capsfilter(sized().build()));
+                }
+            },
+            VideoProcessor::Va | VideoProcessor::Vaapi => {
+                let factory = if *self == VideoProcessor::Va { "vapostproc" } else { "vaapipostproc" };
+                chain.push(ElementSpec::new(factory));
+                chain.push(ElementSpec::capsfilter(sized().field("format", "I420").build()));
+            },
+            VideoProcessor::Gl => {
+                chain.push(ElementSpec::new("glupload"));
+                chain.push(ElementSpec::new("glcolorconvert"));
+                if scaling {
+                    chain.push(ElementSpec::new("glcolorscale"));
+                    chain.push(ElementSpec::capsfilter(
+                        sized().features(["memory:GLMemory"]).build(),
+                    ));
+                    chain.push(ElementSpec::new("glcolorconvert"));
+                }
+                chain.push(ElementSpec::capsfilter(
+                    gst::Caps::builder("video/x-raw").features(["memory:GLMemory"]).field("format", "I420").build(),
+                ));
+                chain.push(ElementSpec::new("gldownload"));
+            },
+        }
+        chain.push(ElementSpec::new("videoconvert"));
+        chain
+    }
+}
+
 pub struct MediaConverter {
     initialized: bool,
+    path_policy: PathPolicy,
+    space_check: Option<SpaceCheck>,
+    watchdog: Option<WatchdogConfig>,
+    backend_policy: BackendPolicy,
+    hardware_acceleration: HardwareAcceleration,
 }
 
 impl MediaConverter {
@@ -152,9 +282,74 @@ impl MediaConverter {
         
         Ok(Self {
             initialized: true,
+            path_policy: PathPolicy::unrestricted(),
+            space_check: Some(SpaceCheck::default()),
+            watchdog: Some(WatchdogConfig::default()),
+            backend_policy: backend_policy::global_policy(),
+            hardware_acceleration: HardwareAcceleration::Auto,
         })
     }
     
+    /// Restrict the paths this converter will read and write
+    pub fn set_path_policy(&mut self, policy: PathPolicy) {
+        self.path_policy = policy;
+    }
+    
+    /// Set the free space check run before conversions, `None` disables it
+    pub fn set_space_check(&mut self, check: Option<SpaceCheck>) {
+        self.space_check = check;
+    }
+    
+    /// Set the watchdog that aborts stalled conversions, `None` disables it
+    pub fn set_watchdog(&mut self, config: Option<WatchdogConfig>) {
+        self.watchdog = config;
+    }
+    
+    /// Set which backend conversions run on
+    pub fn set_backend_policy(&mut self, policy: BackendPolicy) {
+        self.backend_policy = policy;
+    }
+    
+    /// Set whether video conversions may scale and convert on the GPU
+    pub fn set_hardware_acceleration(&mut self, acceleration: HardwareAcceleration) {
+        self.hardware_acceleration = acceleration;
+    }
+    
+    /// Start the stall watchdog for a conversion pipeline, quitting `main_loop` if it fires
+    fn start_watchdog(&self, pipeline: &gst::Pipeline, main_loop: &MainLoop) -> Option<Watchdog> {
+        let config = self.watchdog.clone()?;
+        let main_loop = main_loop.clone();
+        Some(Watchdog::watch(pipeline, config, move |_| main_loop.quit()))
+    }
+    
+    /// Estimate the output size of a conversion, in bytes
+    pub fn estimate_output_size<E: OutputEstimate>(&self, input_path: &Path, options: &E) -> Result<u64> {
+        let duration = Self::probe_duration(input_path)?;
+        Ok(disk_space::estimate_output_size(options, duration))
+    }
+    
+    /// Make sure the output volume can hold the converted file
+    fn check_free_space<E: OutputEstimate>(&self, input_path: &Path, output_path: &Path, options: &E) -> Result<()> {
+        let check = match &self.space_check {
+            Some(check) => check,
+            None => return Ok(()),
+        };
+        
+        let estimated = self.estimate_output_size(input_path, options)?;
+        check.check(output_path, estimated)
+    }
+    
+    /// Duration of a media file in seconds
+    fn probe_duration(path: &Path) -> Result<f64> {
+        let discoverer = gst_pbutils::Discoverer::new(5 * gst::ClockTime::SECOND)
+            .map_err(|_| anyhow!("Failed to create GStreamer discoverer"))?;
+        let info = discoverer.discover_uri(&path_policy::path_to_uri(path)?)
+            .map_err(|err| anyhow!("Failed to probe {:?}: {}", path, err))?;
+        
+        Ok(info.duration().map(|d| d.nseconds() as f64 / 1_000_000_000.0).unwrap_or(0.0))
+    }
+    
+    #[tracing::instrument(name = "encode", skip_all, fields(input = %input_path.as_ref().display(), output = %output_path.as_ref().display()))]
     pub fn convert_video<P: AsRef<Path>, Q: AsRef<Path>>(
         &self,
         input_path: P,
@@ -166,18 +361,45 @@ impl MediaConverter {
             return Err(anyhow!("GStreamer not initialized"));
         }
         
-        let input_path = input_path.as_ref();
-        let output_path = output_path.as_ref();
-        
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        
-        let pipeline_str = self.build_video_pipeline_string(input_path, output_path, &options)?;
-        debug!("Video conversion pipeline: {}", pipeline_str);
-        
-        let pipeline = gst::parse_launch(&pipeline_str)?;
-        let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+        // Validate paths; this also creates the output directory
+        let input_path = &self.path_policy.validate_input(input_path.as_ref())?;
+        let output_path = &self.path_policy.validate_output(output_path.as_ref())?;
+        self.check_free_space(input_path, output_path, &options)?;
+        
+        let progress_callback = Arc::new(Mutex::new(progress_callback));
+        self.backend_policy.run(Subsystem::Conversion, |backend| match backend {
+            Backend::GStreamer => {
+                let processor = VideoProcessor::select(self.hardware_acceleration);
+                let callback = progress_callback.clone();
+                let result = self.run_video_pipeline(input_path, output_path, &options, processor, move |percent| (callback.lock().unwrap())(percent));
+                
+                // Drivers can refuse formats they advertise; the CPU always works
+                match result {
+                    Err(err) if processor != VideoProcessor::Software => {
+                        warn!("{} video processing failed, retrying on the CPU: {}", processor.as_str(), err);
+                        let callback = progress_callback.clone();
+                        self.run_video_pipeline(input_path, output_path, &options, VideoProcessor::Software, move |percent| (callback.lock().unwrap())(percent))
+                    },
+                    result => result,
+                }
+            },
+            Backend::FFmpeg => {
+                ffmpeg_backend::convert_video(input_path, output_path, &options, &|percent| (progress_callback.lock().unwrap())(percent))
+            },
+        })
+    }
+    
+    /// Convert a video with GStreamer
+    fn run_video_pipeline(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        options: &VideoConversionOptions,
+        processor: VideoProcessor,
+        progress_callback: impl Fn(f64) + Send + 'static,
+    ) -> Result<()> {
+        debug!("Video conversion: {:?} -> {:?} ({:?}, {} processing)", input_path, output_path, options, processor.as_str());
+        let pipeline = self.build_video_pipeline(input_path, output_path, options, processor)?;
         
         let progress = Arc::new(Mutex::new(0.0));
         let progress_for_callback = progress.clone();
@@ -185,8 +407,14 @@ impl MediaConverter {
         let bus = pipeline.bus().unwrap();
         let main_loop = MainLoop::new(None, false);
         let main_loop_clone = main_loop.clone();
+        let watchdog = self.start_watchdog(&pipeline, &main_loop);
+        let kicker = watchdog.as_ref().map(Watchdog::kicker);
         
         bus.add_watch(move |_, msg| {
+            if let Some(kicker) = &kicker {
+                kicker.kick();
+            }
+            
             match msg.view() {
                 gst::MessageView::Eos(..) => {
                     let mut progress = progress.lock().unwrap();
@@ -229,6 +457,11 @@ impl MediaConverter {
         // Run the main loop
         main_loop.run();
         
+        // A stalled pipeline was already forced to NULL
+        if let Some(timeout) = watchdog.and_then(|watchdog| watchdog.timeout()) {
+            return Err(timeout.into());
+        }
+        
         // Clean up
         pipeline.set_state(gst::State::Null)?;
         
@@ -242,6 +475,7 @@ impl MediaConverter {
     }
     
     /// Convert an audio file
+    #[tracing::instrument(name = "encode", skip_all, fields(input = %input_path.as_ref().display(), output = %output_path.as_ref().display()))]
     pub fn convert_audio<P: AsRef<Path>, Q: AsRef<Path>>(
         &self,
         input_path: P,
@@ -253,21 +487,32 @@ impl MediaConverter {
             return Err(anyhow!("GStreamer not initialized"));
         }
         
-        let input_path = input_path.as_ref();
-        let output_path = output_path.as_ref();
-        
-        // Create output directory if it doesn't exist
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
+        // Validate paths; this also creates the output directory
+        let input_path = &self.path_policy.validate_input(input_path.as_ref())?;
+        let output_path = &self.path_policy.validate_output(output_path.as_ref())?;
+        self.check_free_space(input_path, output_path, &options)?;
         
+        let progress_callback = Arc::new(Mutex::new(progress_callback));
+        self.backend_policy.run(Subsystem::Conversion, |backend| match backend {
+            Backend::GStreamer => {
+                let callback = progress_callback.clone();
+                self.run_audio_pipeline(input_path, output_path, &options, move |percent| (callback.lock().unwrap())(percent))
+            },
+            Backend::FFmpeg => Err(anyhow!("Audio conversion is not implemented for the FFmpeg backend")),
+        })
+    }
+    
+    /// Convert an audio file with GStreamer
+    fn run_audio_pipeline(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        options: &AudioConversionOptions,
+        progress_callback: impl Fn(f64) + Send + 'static,
+    ) -> Result<()> {
         // Build GStreamer pipeline
-        let pipeline_str = self.build_audio_pipeline_string(input_path, output_path, &options)?;
-        debug!("Audio conversion pipeline: {}", pipeline_str);
-        
-        // Create pipeline
-        let pipeline = gst::parse_launch(&pipeline_str)?;
-        let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+        debug!("Audio conversion: {:?} -> {:?} ({:?})", input_path, output_path, options);
+        let pipeline = self.build_audio_pipeline(input_path, output_path, options)?;
         
         // Create progress tracking
         let progress = Arc::new(Mutex::new(0.0));
@@ -277,8 +522,14 @@ impl MediaConverter {
         let bus = pipeline.bus().unwrap();
         let main_loop = MainLoop::new(None, false);
         let main_loop_clone = main_loop.clone();
+        let watchdog = self.start_watchdog(&pipeline, &main_loop);
+        let kicker = watchdog.as_ref().map(Watchdog::kicker);
         
         bus.add_watch(move |_, msg| {
+            if let Some(kicker) = &kicker {
+                kicker.kick();
+            }
+            
             match msg.view() {
                 gst::MessageView::Eos(..) => {
                     // End of stream, update progress to 100%
@@ -316,6 +567,11 @@ impl MediaConverter {
         // Run the main loop
         main_loop.run();
         
+        // A stalled pipeline was already forced to NULL
+        if let Some(timeout) = watchdog.and_then(|watchdog| watchdog.timeout()) {
+            return Err(timeout.into());
+        }
+        
         // Clean up
         pipeline.set_state(gst::State::Null)?;
         
@@ -339,28 +595,26 @@ impl MediaConverter {
             return Err(anyhow!("GStreamer not initialized"));
         }
         
-        let input_path = input_path.as_ref();
-        let output_path = output_path.as_ref();
-        
-        // Create output directory if it doesn't exist
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
+        // Validate paths; this also creates the output directory
+        let input_path = &self.path_policy.validate_input(input_path.as_ref())?;
+        let output_path = &self.path_policy.validate_output(output_path.as_ref())?;
         
         // Build GStreamer pipeline
-        let pipeline_str = self.build_image_pipeline_string(input_path, output_path, &options)?;
-        debug!("Image conversion pipeline: {}", pipeline_str);
-        
-        // Create pipeline
-        let pipeline = gst::parse_launch(&pipeline_str)?;
-        let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+        debug!("Image conversion: {:?} -> {:?} ({:?})", input_path, output_path, options);
+        let pipeline = self.build_image_pipeline(input_path, output_path, &options)?;
         
         // Watch bus for messages
         let bus = pipeline.bus().unwrap();
         let main_loop = MainLoop::new(None, false);
         let main_loop_clone = main_loop.clone();
+        let watchdog = self.start_watchdog(&pipeline, &main_loop);
+        let kicker = watchdog.as_ref().map(Watchdog::kicker);
         
         bus.add_watch(move |_, msg| {
+            if let Some(kicker) = &kicker {
+                kicker.kick();
+            }
+            
             match msg.view() {
                 gst::MessageView::Eos(..) => {
                     main_loop_clone.quit();
@@ -381,6 +635,11 @@ impl MediaConverter {
         // Run the main loop
         main_loop.run();
         
+        // A stalled pipeline was already forced to NULL
+        if let Some(timeout) = watchdog.and_then(|watchdog| watchdog.timeout()) {
+            return Err(timeout.into());
+        }
+        
         // Clean up
         pipeline.set_state(gst::State::Null)?;
         
@@ -392,236 +651,218 @@ impl MediaConverter {
         Ok(())
     }
     
-    /// Build GStreamer pipeline string for video conversion
-    fn build_video_pipeline_string(
+    /// Build the GStreamer pipeline for video conversion
+    fn build_video_pipeline(
         &self,
         input_path: &Path,
         output_path: &Path,
         options: &VideoConversionOptions,
-    ) -> Result<String> {
-        let input_uri = format!("file://{}", input_path.to_string_lossy());
-        let output_uri = format!("file://{}", output_path.to_string_lossy());
-        
-        // Determine video encoder based on format and options
-        let video_encoder = match options.video_codec.as_deref() {
-            Some(codec) => codec.to_string(),
-            None => match options.format {
-                ConversionFormat::MP4 | ConversionFormat::MOV => "x264enc".to_string(),
-                ConversionFormat::WebM => "vp9enc".to_string(),
-                _ => return Err(anyhow!("Unsupported video format: {:?}", options.format)),
-            },
-        };
-        
-        // Determine audio encoder based on format and options
-        let audio_encoder = match options.audio_codec.as_deref() {
-            Some(codec) => codec.to_string(),
-            None => match options.format {
-                ConversionFormat::MP4 | ConversionFormat::MOV => "avenc_aac".to_string(),
-                ConversionFormat::WebM => "opusenc".to_string(),
-                _ => return Err(anyhow!("Unsupported audio format: {:?}", options.format)),
-            },
-        };
-        
-        // Build video encoding options
-        let mut video_enc_options = String::new();
-        
-        if let Some(bitrate) = options.video_bitrate {
-            video_enc_options.push_str(&format!(" bitrate={}", bitrate / 1000));
-        }
-        
-        // Build video scaling options
-        let mut video_scale_options = String::new();
-        
-        if options.width.is_some() || options.height.is_some() {
-            video_scale_options.push_str(" ! videoscale");
-            
-            if options.preserve_aspect_ratio {
-                video_scale_options.push_str(" ! videoscale method=lanczos");
-            }
-            
-            video_scale_options.push_str(" ! video/x-raw");
-            
-            if let Some(width) = options.width {
-                video_scale_options.push_str(&format!(", width={}", width));
-            }
-            
-            if let Some(height) = options.height {
-                video_scale_options.push_str(&format!(", height={}", height));
+        processor: VideoProcessor,
+    ) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new("video-convert")?;
+        
+        // Source and decoder
+        let source = builder.chain(&[
+            ElementSpec::file_source(input_path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
+        let decoder = &source[1];
+        
+        // Muxer and output
+        let output = builder.chain(&[
+            Self::video_muxer(options.format)?,
+            ElementSpec::new("progressreport").property("update-freq", 1i32),
+            ElementSpec::file_sink(output_path)?,
+        ])?;
+        let muxer = &output[0];
+        
+        // Video branch; frames are dropped before scaling so fewer are processed
+        let mut video_chain = vec![ElementSpec::new("queue")];
+        if options.fastcopy {
+            video_chain.push(ElementSpec::new("videoconvert"));
+        } else {
+            if let Some(fps) = options.frame_rate {
+                video_chain.push(ElementSpec::new("videorate"));
+                video_chain.push(ElementSpec::capsfilter(
+                    gst::Caps::builder("video/x-raw")
+                        .field("framerate", gst::Fraction::new(fps as i32, 1))
+                        .build(),
+                ));
             }
+            video_chain.extend(processor.elements(options.width, options.height, options.preserve_aspect_ratio));
         }
-        
-        // Build frame rate options
-        let mut framerate_options = String::new();
-        
-        if let Some(fps) = options.frame_rate {
-            framerate_options.push_str(&format!(" ! videorate ! video/x-raw, framerate={}/1", fps as i32));
-        }
-        
-        // Build audio encoding options
-        let mut audio_enc_options = String::new();
-        
-        if let Some(bitrate) = options.audio_bitrate {
-            audio_enc_options.push_str(&format!(" bitrate={}", bitrate / 1000));
-        }
-        
-        // Build container format
-        let container_format = match options.format {
-            ConversionFormat::MP4 => "mp4mux",
-            ConversionFormat::WebM => "webmmux",
-            ConversionFormat::MOV => "qtmux",
-            _ => return Err(anyhow!("Unsupported video container format: {:?}", options.format)),
-        };
-        
-        // Build complete pipeline
-        let pipeline = if options.fastcopy {
-            // Fast copy mode - try to avoid re-encoding
-            format!(
-                "filesrc location=\"{}\" ! decodebin name=demux \
-                 demux.video_0 ! queue ! {} ! {} name=mux \
-                 demux.audio_0 ! queue ! {} ! mux. \
-                 mux. ! progressreport update-freq=1 ! filesink location=\"{}\"",
-                input_path.to_string_lossy(),
-                video_encoder, container_format,
-                audio_encoder,
-                output_path.to_string_lossy()
-            )
-        } else {
-            // Full conversion mode
-            format!(
-                "filesrc location=\"{}\" ! decodebin name=demux \
-                 demux.video_0 ! queue{}{} ! {} {} ! {} name=mux \
-                 demux.audio_0 ! queue ! audioconvert ! {} {} ! mux. \
-                 mux. ! progressreport update-freq=1 ! filesink location=\"{}\"",
-                input_path.to_string_lossy(),
-                video_scale_options, framerate_options,
-                video_encoder, video_enc_options, container_format,
-                audio_encoder, audio_enc_options,
-                output_path.to_string_lossy()
-            )
-        };
-        
-        Ok(pipeline)
+        video_chain.push(Self::video_encoder(options)?);
+        
+        let video = builder.chain(&video_chain)?;
+        builder.link(video.last().unwrap(), muxer)?;
+        builder.link_dynamic(decoder, StreamKind::Video, &video[0]);
+        
+        // Audio branch
+        let audio = builder.chain(&[
+            ElementSpec::new("queue"),
+            ElementSpec::new("audioconvert"),
+            Self::audio_encoder(options.audio_codec.as_deref(), options.format, options.audio_bitrate)?,
+        ])?;
+        builder.link(audio.last().unwrap(), muxer)?;
+        builder.link_dynamic(decoder, StreamKind::Audio, &audio[0]);
+        
+        Ok(builder.build())
     }
     
-    /// Build GStreamer pipeline string for audio conversion
-    fn build_audio_pipeline_string(
+    /// Build the GStreamer pipeline for audio conversion
+    fn build_audio_pipeline(
         &self,
         input_path: &Path,
         output_path: &Path,
         options: &AudioConversionOptions,
-    ) -> Result<String> {
-        let input_uri = format!("file://{}", input_path.to_string_lossy());
-        let output_uri = format!("file://{}", output_path.to_string_lossy());
+    ) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new("audio-convert")?;
         
-        // Determine audio encoder based on format and options
-        let audio_encoder = match options.audio_codec.as_deref() {
-            Some(codec) => codec.to_string(),
-            None => match options.format {
-                ConversionFormat::MP3 => "lamemp3enc".to_string(),
-                ConversionFormat::WAV => "wavenc".to_string(),
-                ConversionFormat::FLAC => "flacenc".to_string(),
-                _ => return Err(anyhow!("Unsupported audio format: {:?}", options.format)),
-            },
-        };
+        let source = builder.chain(&[
+            ElementSpec::file_source(input_path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
         
-        // Build audio encoding options
-        let mut audio_enc_options = String::new();
-        
-        if let Some(bitrate) = options.audio_bitrate {
-            audio_enc_options.push_str(&format!(" bitrate={}", bitrate / 1000));
-        }
-        
-        // Build audio conversion options
-        let mut audio_convert_options = String::new();
-        
-        if options.sample_rate.is_some() || options.channels.is_some() {
-            audio_convert_options.push_str(" ! audio/x-raw");
-            
-            if let Some(rate) = options.sample_rate {
-                audio_convert_options.push_str(&format!(", rate={}", rate));
-            }
+        let mut chain = vec![ElementSpec::new("queue")];
+        if !options.fastcopy {
+            chain.push(ElementSpec::new("audioconvert"));
             
-            if let Some(channels) = options.channels {
-                audio_convert_options.push_str(&format!(", channels={}", channels));
+            if options.sample_rate.is_some() || options.channels.is_some() {
+                chain.push(ElementSpec::new("audioresample"));
+                
+                let mut caps = gst::Caps::builder("audio/x-raw");
+                if let Some(rate) = options.sample_rate {
+                    caps = caps.field("rate", rate as i32);
+                }
+                if let Some(channels) = options.channels {
+                    caps = caps.field("channels", channels as i32);
+                }
+                chain.push(ElementSpec::capsfilter(caps.build()));
             }
         }
+        chain.push(Self::audio_encoder(options.audio_codec.as_deref(), options.format, options.audio_bitrate)?);
+        chain.push(ElementSpec::new("progressreport").property("update-freq", 1i32));
+        chain.push(ElementSpec::file_sink(output_path)?);
         
-        // Build container format
-        let container_format = match options.format {
-            ConversionFormat::MP3 => "",
-            ConversionFormat::WAV => "",
-            ConversionFormat::FLAC => "",
-            _ => return Err(anyhow!("Unsupported audio container format: {:?}", options.format)),
-        };
-        
-        // Build complete pipeline
-        let pipeline = if options.fastcopy {
-            // Fast copy mode - try to avoid re-encoding
-            format!(
-                "filesrc location=\"{}\" ! decodebin ! queue ! {} {} ! progressreport update-freq=1 ! filesink location=\"{}\"",
-                input_path.to_string_lossy(),
-                audio_encoder, audio_enc_options,
-                output_path.to_string_lossy()
-            )
-        } else {
-            // Full conversion mode
-            format!(
-                "filesrc location=\"{}\" ! decodebin ! queue ! audioconvert{} ! {} {} ! progressreport update-freq=1 ! filesink location=\"{}\"",
-                input_path.to_string_lossy(),
-                audio_convert_options,
-                audio_encoder, audio_enc_options,
-                output_path.to_string_lossy()
-            )
-        };
+        let audio = builder.chain(&chain)?;
+        builder.link_dynamic(&source[1], StreamKind::Audio, &audio[0]);
         
-        Ok(pipeline)
+        Ok(builder.build())
     }
     
-    /// Build GStreamer pipeline string for image conversion
-    fn build_image_pipeline_string(
+    /// Build the GStreamer pipeline for image conversion
+    fn build_image_pipeline(
         &self,
         input_path: &Path,
         output_path: &Path,
         options: &ImageConversionOptions,
-    ) -> Result<String> {
-        // Determine image encoder based on format
-        let (image_encoder, encoder_options) = match options.format {
-            ConversionFormat::JPEG => ("jpegenc", format!(" quality={}", options.quality)),
-            ConversionFormat::PNG => ("pngenc", format!(" compression-level={}", 9 - (options.quality / 11))),
-            ConversionFormat::WebP => ("webpenc", format!(" quality={}", options.quality as f32 / 100.0)),
-            _ => return Err(anyhow!("Unsupported image format: {:?}", options.format)),
-        };
+    ) -> Result<gst::Pipeline> {
+        let builder = PipelineBuilder::new("image-convert")?;
         
-        // Build image scaling options
-        let mut image_scale_options = String::new();
+        let source = builder.chain(&[
+            ElementSpec::file_source(input_path)?,
+            ElementSpec::new("decodebin"),
+        ])?;
         
+        let mut chain = vec![ElementSpec::new("videoconvert")];
         if options.width.is_some() || options.height.is_some() {
-            image_scale_options.push_str(" ! videoscale");
-            
+            let mut scale = ElementSpec::new("videoscale");
             if options.preserve_aspect_ratio {
-                image_scale_options.push_str(" ! videoscale method=lanczos");
+                scale = scale.property_from_str("method", "lanczos");
             }
+            chain.push(scale);
             
-            image_scale_options.push_str(" ! video/x-raw");
-            
+            let mut caps = gst::Caps::builder("video/x-raw");
             if let Some(width) = options.width {
-                image_scale_options.push_str(&format!(", width={}", width));
+                caps = caps.field("width", width as i32);
             }
-            
             if let Some(height) = options.height {
-                image_scale_options.push_str(&format!(", height={}", height));
+                caps = caps.field("height", height as i32);
             }
+            chain.push(ElementSpec::capsfilter(caps.build()));
         }
+        chain.push(Self::image_encoder(options)?);
+        chain.push(ElementSpec::file_sink(output_path)?);
         
-        // Build complete pipeline
-        let pipeline = format!(
-            "filesrc location=\"{}\" ! decodebin ! videoconvert{} ! {} {} ! filesink location=\"{}\"",
-            input_path.to_string_lossy(),
-            image_scale_options,
-            image_encoder, encoder_options,
-            output_path.to_string_lossy()
-        );
+        let image = builder.chain(&chain)?;
+        builder.link_dynamic(&source[1], StreamKind::Video, &image[0]);
         
-        Ok(pipeline)
+        Ok(builder.build())
+    }
+    
+    /// Video encoder for the target format
+    fn video_encoder(options: &VideoConversionOptions) -> Result<ElementSpec> {
+        let factory = match options.video_codec.as_deref() {
+            Some(codec) => codec,
+            None => match options.format {
+                ConversionFormat::MP4 | ConversionFormat::MOV => "x264enc",
+                ConversionFormat::WebM => "vp9enc",
+                _ => return Err(anyhow!("Unsupported video format: {:?}", options.format)),
+            },
+        };
+        
+        let mut encoder = ElementSpec::new(factory);
+        if let Some(bitrate) = options.video_bitrate {
+            encoder = match factory {
+                // libvpx encoders take bits per second
+                "vp8enc" | "vp9enc" => encoder.property("target-bitrate", bitrate as i32),
+                // x264enc and friends take kbit/s
+                _ => encoder.property("bitrate", bitrate / 1000),
+            };
+        }
+        
+        Ok(encoder)
+    }
+    
+    /// Audio encoder for the target format
+    fn audio_encoder(codec: Option<&str>, format: ConversionFormat, bitrate: Option<u32>) -> Result<ElementSpec> {
+        let factory = match codec {
+            Some(codec) => codec,
+            None => match format {
+                ConversionFormat::MP4 | ConversionFormat::MOV => "avenc_aac",
+                ConversionFormat::WebM => "opusenc",
+                ConversionFormat::MP3 => "lamemp3enc",
+                ConversionFormat::WAV => "wavenc",
+                ConversionFormat::FLAC => "flacenc",
+                _ => return Err(anyhow!("Unsupported audio format: {:?}", format)),
+            },
+        };
+        
+        let mut encoder = ElementSpec::new(factory);
+        if let Some(bitrate) = bitrate {
+            encoder = match factory {
+                // Lossless and PCM encoders have no bitrate
+                "wavenc" | "flacenc" => encoder,
+                // opusenc and avenc_aac take bits per second
+                "opusenc" => encoder.property("bitrate", bitrate as i32),
+                "avenc_aac" => encoder.property("bitrate", bitrate as i64),
+                // lamemp3enc takes kbit/s
+                _ => encoder.property("bitrate", (bitrate / 1000) as i32),
+            };
+        }
+        
+        Ok(encoder)
+    }
+    
+    /// Muxer for the target video container
+    fn video_muxer(format: ConversionFormat) -> Result<ElementSpec> {
+        match format {
+            ConversionFormat::MP4 => Ok(ElementSpec::new("mp4mux")),
+            ConversionFormat::WebM => Ok(ElementSpec::new("webmmux")),
+            ConversionFormat::MOV => Ok(ElementSpec::new("qtmux")),
+            _ => Err(anyhow!("Unsupported video container format: {:?}", format)),
+        }
+    }
+    
+    /// Image encoder for the target format
+    fn image_encoder(options: &ImageConversionOptions) -> Result<ElementSpec> {
+        match options.format {
+            ConversionFormat::JPEG => Ok(ElementSpec::new("jpegenc").property("quality", options.quality as i32)),
+            ConversionFormat::PNG => Ok(ElementSpec::new("pngenc")
+                .property("compression-level", (9 - (options.quality / 11).min(9)) as u32)),
+            ConversionFormat::WebP => Ok(ElementSpec::new("webpenc")
+                .property("quality", options.quality as f32)),
+            _ => Err(anyhow!("Unsupported image format: {:?}", options.format)),
+        }
     }
 }
@@ -0,0 +1,433 @@
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::file_manager::{FileManager, MediaInfo, MediaType, ThumbnailOptions};
+use super::file_manager_convert::{MediaConverter, VideoConversionOptions};
+use super::path_policy;
+use super::scene_classification::{SceneClassifier, SceneLabel};
+
+/// Status of a single file in a bulk import
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportFileStatus {
+    /// Waiting for a worker
+    Pending,
+    /// Reading media information
+    Probing,
+    /// Generating a thumbnail
+    Thumbnailing,
+    /// Generating a proxy
+    Proxying,
+    /// Labelling the scene
+    Classifying,
+    /// Import finished
+    Done,
+    /// Import failed with the given error
+    Failed(String),
+    /// Import was cancelled before the file was processed
+    Cancelled,
+}
+
+impl ImportFileStatus {
+    /// Whether the file has reached a terminal state
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            ImportFileStatus::Done | ImportFileStatus::Failed(_) | ImportFileStatus::Cancelled
+        )
+    }
+}
+
+/// Result of importing a single file
+#[derive(Debug, Clone)]
+pub struct ImportedFile {
+    /// Source path
+    pub path: PathBuf,
+    /// Probed media information
+    pub info: MediaInfo,
+    /// Thumbnail path, if generated
+    pub thumbnail: Option<PathBuf>,
+    /// Proxy path, if generated
+    pub proxy: Option<PathBuf>,
+    /// Scene labels, if a classifier was configured
+    pub labels: Vec<SceneLabel>,
+}
+
+/// Event streamed while a bulk import runs
+#[derive(Debug, Clone)]
+pub enum ImportEvent {
+    /// A file changed status
+    StatusChanged {
+        /// Source path
+        path: PathBuf,
+        /// New status
+        status: ImportFileStatus,
+    },
+    /// A file finished importing; emitted as soon as it is ready
+    FileImported(ImportedFile),
+    /// Every file reached a terminal state
+    Finished(ImportProgress),
+}
+
+/// Overall progress of a bulk import
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImportProgress {
+    /// Total number of files
+    pub total: usize,
+    /// Files imported successfully
+    pub completed: usize,
+    /// Files that failed
+    pub failed: usize,
+    /// Files that were cancelled
+    pub cancelled: usize,
+    /// Progress percentage (0-100)
+    pub percent: f64,
+}
+
+/// Options for bulk imports
+#[derive(Debug, Clone)]
+pub struct BulkImportOptions {
+    /// Maximum number of files processed concurrently
+    pub max_concurrent: usize,
+    /// Whether to descend into subdirectories
+    pub recursive: bool,
+    /// Thumbnail options, or `None` to skip thumbnails
+    pub thumbnail: Option<ThumbnailOptions>,
+    /// Proxy options, or `None` to skip proxies
+    pub proxy: Option<VideoConversionOptions>,
+    /// Directory proxies are written to
+    pub proxy_dir: PathBuf,
+    /// Classifier labelling video and image files, or `None` to skip classification
+    pub classifier: Option<Arc<SceneClassifier>>,
+}
+
+impl Default for BulkImportOptions {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            recursive: true,
+            thumbnail: Some(ThumbnailOptions::default()),
+            proxy: None,
+            proxy_dir: std::env::temp_dir().join("aether").join("proxies"),
+            classifier: None,
+        }
+    }
+}
+
+/// Shared state of a running import
+struct ImportJobState {
+    files: Vec<(PathBuf, ImportFileStatus)>,
+    results: Vec<ImportedFile>,
+    next_index: usize,
+}
+
+/// Handle to a running bulk import
+pub struct ImportJob {
+    state: Arc<Mutex<ImportJobState>>,
+    cancelled: Arc<AtomicBool>,
+    events: Receiver<ImportEvent>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ImportJob {
+    /// Receiver for streamed import events
+    pub fn events(&self) -> &Receiver<ImportEvent> {
+        &self.events
+    }
+
+    /// Overall progress
+    pub fn progress(&self) -> ImportProgress {
+        let state = self.state.lock().unwrap();
+        Self::compute_progress(&state.files)
+    }
+
+    /// Status of every file in the import
+    pub fn file_statuses(&self) -> Vec<(PathBuf, ImportFileStatus)> {
+        self.state.lock().unwrap().files.clone()
+    }
+
+    /// Files imported so far
+    pub fn results(&self) -> Vec<ImportedFile> {
+        self.state.lock().unwrap().results.clone()
+    }
+
+    /// Cancel the import; files already being processed finish their current step
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Block until every worker has finished and return the imported files
+    pub fn wait(mut self) -> Result<Vec<ImportedFile>> {
+        for handle in self.workers.drain(..) {
+            handle.join().map_err(|_| anyhow!("Import worker panicked"))?;
+        }
+        Ok(self.results())
+    }
+
+    fn compute_progress(files: &[(PathBuf, ImportFileStatus)]) -> ImportProgress {
+        let mut progress = ImportProgress {
+            total: files.len(),
+            ..Default::default()
+        };
+
+        for (_, status) in files {
+            match status {
+                ImportFileStatus::Done => progress.completed += 1,
+                ImportFileStatus::Failed(_) => progress.failed += 1,
+                ImportFileStatus::Cancelled => progress.cancelled += 1,
+                _ => (),
+            }
+        }
+
+        let finished = progress.completed + progress.failed + progress.cancelled;
+        progress.percent = if progress.total == 0 {
+            100.0
+        } else {
+            finished as f64 / progress.total as f64 * 100.0
+        };
+
+        progress
+    }
+}
+
+/// Imports many files concurrently with per-file status reporting
+pub struct BulkImporter {
+    file_manager: Arc<FileManager>,
+    options: BulkImportOptions,
+}
+
+impl BulkImporter {
+    /// Create a new bulk importer
+    pub fn new(file_manager: FileManager, options: BulkImportOptions) -> Self {
+        Self {
+            file_manager: Arc::new(file_manager),
+            options,
+        }
+    }
+
+    /// Start importing the given files and directories
+    pub fn import(&self, inputs: &[PathBuf]) -> Result<ImportJob> {
+        // Expand directories into their files
+        let mut files = Vec::new();
+        for input in inputs {
+            Self::collect_files(input, self.options.recursive, &mut files)?;
+        }
+
+        // Proxies were asked for, so an unusable converter fails the import instead of skipping them
+        let converter = match self.options.proxy {
+            Some(_) => {
+                std::fs::create_dir_all(&self.options.proxy_dir)?;
+                Some(Arc::new(MediaConverter::new().context("Proxy converter unavailable")?))
+            },
+            None => None,
+        };
+
+        info!("Starting bulk import of {} files", files.len());
+
+        let state = Arc::new(Mutex::new(ImportJobState {
+            files: files.into_iter().map(|path| (path, ImportFileStatus::Pending)).collect(),
+            results: Vec::new(),
+            next_index: 0,
+        }));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+
+        let worker_count = self.options.max_concurrent.max(1);
+        let mut workers = Vec::with_capacity(worker_count);
+        for _ in 0..worker_count {
+            let state = state.clone();
+            let cancelled = cancelled.clone();
+            let sender = sender.clone();
+            let file_manager = self.file_manager.clone();
+            let converter = converter.clone();
+            let options = self.options.clone();
+
+            workers.push(thread::spawn(move || {
+                Self::worker_thread(state, cancelled, sender, file_manager, converter, options);
+            }));
+        }
+
+        // Emit the final event once every worker is done
+        {
+            let state = state.clone();
+            let handles = std::mem::take(&mut workers);
+            workers.push(thread::spawn(move || {
+                for handle in handles {
+                    let _ = handle.join();
+                }
+                let progress = ImportJob::compute_progress(&state.lock().unwrap().files);
+                let _ = sender.send(ImportEvent::Finished(progress));
+            }));
+        }
+
+        Ok(ImportJob {
+            state,
+            cancelled,
+            events: receiver,
+            workers,
+        })
+    }
+
+    /// Collect files from a path, expanding directories
+    fn collect_files(path: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+        if path.is_file() {
+            files.push(path.to_path_buf());
+        } else if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .collect();
+            entries.sort();
+
+            for entry in entries {
+                if entry.is_file() {
+                    files.push(entry);
+                } else if recursive && entry.is_dir() {
+                    Self::collect_files(&entry, recursive, files)?;
+                }
+            }
+        } else {
+            return Err(anyhow!("Import path does not exist: {:?}", path));
+        }
+
+        Ok(())
+    }
+
+    /// Update a file's status and emit an event
+    fn set_status(
+        state: &Arc<Mutex<ImportJobState>>,
+        sender: &Sender<ImportEvent>,
+        index: usize,
+        status: ImportFileStatus,
+    ) {
+        let path = {
+            let mut state = state.lock().unwrap();
+            state.files[index].1 = status.clone();
+            state.files[index].0.clone()
+        };
+        let _ = sender.send(ImportEvent::StatusChanged { path, status });
+    }
+
+    /// Worker thread pulling files off the shared list
+    fn worker_thread(
+        state: Arc<Mutex<ImportJobState>>,
+        cancelled: Arc<AtomicBool>,
+        sender: Sender<ImportEvent>,
+        file_manager: Arc<FileManager>,
+        converter: Option<Arc<MediaConverter>>,
+        options: BulkImportOptions,
+    ) {
+        loop {
+            // Claim the next file
+            let (index, path) = {
+                let mut state = state.lock().unwrap();
+                if state.next_index >= state.files.len() {
+                    return;
+                }
+                let index = state.next_index;
+                state.next_index += 1;
+                (index, state.files[index].0.clone())
+            };
+
+            if cancelled.load(Ordering::SeqCst) {
+                Self::set_status(&state, &sender, index, ImportFileStatus::Cancelled);
+                continue;
+            }
+
+            match Self::import_file(&state, &sender, index, &path, &file_manager, converter.as_deref(), &options, &cancelled) {
+                Ok(Some(imported)) => {
+                    state.lock().unwrap().results.push(imported.clone());
+                    Self::set_status(&state, &sender, index, ImportFileStatus::Done);
+                    let _ = sender.send(ImportEvent::FileImported(imported));
+                },
+                Ok(None) => {
+                    Self::set_status(&state, &sender, index, ImportFileStatus::Cancelled);
+                },
+                Err(e) => {
+                    warn!("Failed to import {:?}: {}", path, e);
+                    Self::set_status(&state, &sender, index, ImportFileStatus::Failed(e.to_string()));
+                },
+            }
+        }
+    }
+
+    /// Import a single file, returning `None` if cancelled part-way
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(name = "import", skip_all, fields(index = index, path = %path.display()))]
+    fn import_file(
+        state: &Arc<Mutex<ImportJobState>>,
+        sender: &Sender<ImportEvent>,
+        index: usize,
+        path: &Path,
+        file_manager: &FileManager,
+        converter: Option<&MediaConverter>,
+        options: &BulkImportOptions,
+        cancelled: &AtomicBool,
+    ) -> Result<Option<ImportedFile>> {
+        // Probe
+        Self::set_status(state, sender, index, ImportFileStatus::Probing);
+        let info = file_manager.get_media_info(path)?;
+        if info.media_type == MediaType::Unknown {
+            return Err(anyhow!("Unsupported media type: {:?}", path));
+        }
+
+        // Thumbnail
+        let mut thumbnail = None;
+        if let Some(thumbnail_options) = &options.thumbnail {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            Self::set_status(state, sender, index, ImportFileStatus::Thumbnailing);
+            thumbnail = Some(file_manager.generate_thumbnail(path, Some(thumbnail_options.clone()))?);
+        }
+
+        // Proxy (video only)
+        let mut proxy = None;
+        if let (Some(proxy_options), Some(converter)) = (&options.proxy, converter) {
+            if info.media_type == MediaType::Video {
+                if cancelled.load(Ordering::SeqCst) {
+                    return Ok(None);
+                }
+                Self::set_status(state, sender, index, ImportFileStatus::Proxying);
+
+                let file_stem = path_policy::output_stem(path);
+                let proxy_path = options.proxy_dir.join(format!(
+                    "{}-proxy-{}.{}",
+                    file_stem,
+                    index,
+                    proxy_options.format.extension()
+                ));
+                converter.convert_video(path, &proxy_path, proxy_options.clone(), |_| {})?;
+                proxy = Some(proxy_path);
+            }
+        }
+
+        // Scene labels; optional, so a failure doesn't fail the import
+        let mut labels = Vec::new();
+        if let Some(classifier) = &options.classifier {
+            if matches!(info.media_type, MediaType::Video | MediaType::Image) {
+                if cancelled.load(Ordering::SeqCst) {
+                    return Ok(None);
+                }
+                Self::set_status(state, sender, index, ImportFileStatus::Classifying);
+                match classifier.classify(path) {
+                    Ok(found) => labels = found,
+                    Err(e) => warn!("Failed to classify {:?}: {}", path, e),
+                }
+            }
+        }
+
+        debug!("Imported {:?}", path);
+
+        Ok(Some(ImportedFile {
+            path: path.to_path_buf(),
+            info,
+            thumbnail,
+            proxy,
+            labels,
+        }))
+    }
+}
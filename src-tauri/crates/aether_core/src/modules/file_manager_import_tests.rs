@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod tests {
+    use super::super::file_manager::FileManager;
+    use super::super::file_manager_convert::VideoConversionOptions;
+    use super::super::file_manager_import::*;
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use std::path::PathBuf;
+    use anyhow::Result;
+
+    /// Directory of files the importer can't handle, with one in a subdirectory
+    fn create_import_dir(name: &str) -> Result<PathBuf> {
+        let dir = create_test_dir("import", name)?;
+        fs::create_dir_all(dir.join("nested"))?;
+        fs::write(dir.join("a.xyz"), b"not media")?;
+        fs::write(dir.join("b.xyz"), b"not media")?;
+        fs::write(dir.join("nested").join("c.xyz"), b"not media")?;
+        Ok(dir)
+    }
+
+    fn importer(options: BulkImportOptions) -> Result<BulkImporter> {
+        Ok(BulkImporter::new(FileManager::new()?, BulkImportOptions { thumbnail: None, ..options }))
+    }
+
+    #[test]
+    fn test_unsupported_files_fail_individually() -> Result<()> {
+        let dir = create_import_dir("unsupported")?;
+        let job = importer(BulkImportOptions::default())?.import(&[dir.clone()])?;
+
+        let mut finished = None;
+        let mut failed_events = 0;
+        for event in job.events().iter() {
+            match event {
+                ImportEvent::StatusChanged { status: ImportFileStatus::Failed(error), .. } => {
+                    assert!(error.contains("Unsupported media type"), "{}", error);
+                    failed_events += 1;
+                },
+                ImportEvent::FileImported(file) => panic!("Imported {:?}", file.path),
+                ImportEvent::Finished(progress) => finished = Some(progress),
+                _ => (),
+            }
+        }
+
+        let progress = finished.expect("No finished event");
+        assert_eq!(progress.total, 3);
+        assert_eq!(progress.failed, 3);
+        assert_eq!(progress.percent, 100.0);
+        assert_eq!(failed_events, 3);
+        assert!(job.file_statuses().iter().all(|(_, status)| status.is_finished()));
+        assert!(job.wait()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_directories_expand_recursively_when_asked() -> Result<()> {
+        let dir = create_import_dir("recursive")?;
+
+        let flat = importer(BulkImportOptions { recursive: false, ..BulkImportOptions::default() })?.import(&[dir.clone()])?;
+        let paths: Vec<PathBuf> = flat.file_statuses().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, vec![dir.join("a.xyz"), dir.join("b.xyz")]);
+        flat.wait()?;
+
+        let deep = importer(BulkImportOptions::default())?.import(&[dir.clone()])?;
+        assert_eq!(deep.progress().total, 3);
+        deep.wait()?;
+
+        // Missing inputs fail the whole import up front
+        assert!(importer(BulkImportOptions::default())?.import(&[dir.join("missing")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxies_use_one_converter() -> Result<()> {
+        let dir = create_import_dir("proxies")?;
+        let proxy_dir = dir.join("proxies");
+        let options = BulkImportOptions {
+            proxy: Some(VideoConversionOptions::default()),
+            proxy_dir: proxy_dir.clone(),
+            ..BulkImportOptions::default()
+        };
+
+        // The converter is created before any worker starts, so files fail on their own merits
+        let job = importer(options)?.import(&[dir.join("a.xyz")])?;
+        assert!(proxy_dir.is_dir());
+        assert!(job.wait()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_import_accounts_for_every_file() -> Result<()> {
+        let dir = create_import_dir("cancel")?;
+        let job = importer(BulkImportOptions { max_concurrent: 1, ..BulkImportOptions::default() })?.import(&[dir])?;
+        job.cancel();
+
+        let finished = job.events().iter().find_map(|event| match event {
+            ImportEvent::Finished(progress) => Some(progress),
+            _ => None,
+        });
+        let progress = finished.expect("No finished event");
+        assert_eq!(progress.completed + progress.failed + progress.cancelled, progress.total);
+        assert_eq!(progress.percent, 100.0);
+        Ok(())
+    }
+}
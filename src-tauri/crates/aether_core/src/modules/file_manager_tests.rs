@@ -149,4 +149,32 @@ mod tests {
         
         Ok(())
     }
+
+    #[test]
+    fn test_video_processor_elements() -> Result<()> {
+        use super::super::file_manager_convert::VideoProcessor;
+        use super::super::settings::HardwareAcceleration;
+        
+        gstreamer::init()?;
+        let factories = |processor: VideoProcessor, width: Option<u32>| -> Vec<String> {
+            processor.elements(width, None, true).iter().map(|spec| spec.factory().to_string()).collect()
+        };
+        
+        // Every chain ends in system memory with videoconvert for the encoder
+        assert_eq!(factories(VideoProcessor::Software, None), vec!["videoconvert"]);
+        assert_eq!(factories(VideoProcessor::Software, Some(1280)), vec!["videoscale", "capsfilter", "videoconvert"]);
+        assert_eq!(factories(VideoProcessor::Va, Some(1280)), vec!["vapostproc", "capsfilter", "videoconvert"]);
+        assert_eq!(factories(VideoProcessor::Gl, None), vec!["glupload", "glcolorconvert", "capsfilter", "gldownload", "videoconvert"]);
+        assert_eq!(
+            factories(VideoProcessor::Gl, Some(1280)),
+            vec!["glupload", "glcolorconvert", "glcolorscale", "capsfilter", "glcolorconvert", "capsfilter", "gldownload", "videoconvert"],
+        );
+        
+        // Never keeps to the CPU; otherwise whatever is installed, software at worst
+        assert_eq!(VideoProcessor::select(HardwareAcceleration::Never), VideoProcessor::Software);
+        let selected = VideoProcessor::select(HardwareAcceleration::Auto);
+        assert!(selected == VideoProcessor::Software || selected.is_available());
+        
+        Ok(())
+    }
 }
@@ -0,0 +1,587 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use super::backend_policy::{Backend, Subsystem};
+use super::ffmpeg_backend;
+use super::file_manager::{FileManager, MediaType, ThumbnailOptions};
+use super::media_library::{fnv1a, MediaFingerprint, FNV_OFFSET};
+use super::path_policy;
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
+/// Priority of a thumbnail request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ThumbnailPriority {
+    /// Speculative work (e.g. prefetching the rest of a bin)
+    Background,
+    /// Items just outside the visible area
+    Nearby,
+    /// Items currently visible in the UI
+    Visible,
+}
+
+/// Status of a thumbnail request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThumbnailStatus {
+    /// Waiting in the queue
+    Queued,
+    /// Being generated by a worker
+    InProgress,
+    /// Thumbnail is available at the given path
+    Ready(PathBuf),
+    /// Generation failed
+    Failed(String),
+    /// Request was cancelled before it completed
+    Cancelled,
+}
+
+/// A single thumbnail request
+#[derive(Debug, Clone)]
+pub struct ThumbnailRequest {
+    /// Source media file
+    pub path: PathBuf,
+    /// Thumbnail options
+    pub options: ThumbnailOptions,
+    /// Request priority
+    pub priority: ThumbnailPriority,
+    /// Optional UI group (e.g. a bin or scroll view) used for bulk cancellation
+    pub group: Option<String>,
+}
+
+impl ThumbnailRequest {
+    /// Create a request with default options and visible priority
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            options: ThumbnailOptions::default(),
+            priority: ThumbnailPriority::Visible,
+            group: None,
+        }
+    }
+
+    /// Set the thumbnail options
+    pub fn with_options(mut self, options: ThumbnailOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the request priority
+    pub fn with_priority(mut self, priority: ThumbnailPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the UI group
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+}
+
+/// Configuration for the thumbnail service
+#[derive(Debug, Clone)]
+pub struct ThumbnailServiceConfig {
+    /// Directory for the persistent thumbnail cache
+    pub cache_dir: PathBuf,
+    /// Number of worker threads
+    pub workers: usize,
+}
+
+impl Default for ThumbnailServiceConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: std::env::temp_dir().join("aether").join("thumbnails"),
+            workers: 2,
+        }
+    }
+}
+
+/// Callback invoked when a request finishes
+pub type ThumbnailCallback = Arc<Mutex<dyn Fn(u64, ThumbnailStatus) + Send + 'static>>;
+
+/// Queue entry
+struct QueuedRequest {
+    id: u64,
+    /// Monotonic sequence number so equal priorities stay FIFO
+    sequence: u64,
+    request: ThumbnailRequest,
+}
+
+/// Shared state between the service and its workers
+struct ServiceState {
+    queue: Vec<QueuedRequest>,
+    statuses: HashMap<u64, ThumbnailStatus>,
+    next_id: u64,
+    next_sequence: u64,
+    running: bool,
+}
+
+/// Thumbnail service with a prioritized queue and a persistent disk cache
+pub struct ThumbnailService {
+    config: ThumbnailServiceConfig,
+    file_manager: Arc<FileManager>,
+    state: Arc<(Mutex<ServiceState>, Condvar)>,
+    callback: Arc<Mutex<Option<ThumbnailCallback>>>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+impl ThumbnailService {
+    /// Create a new thumbnail service
+    pub fn new(file_manager: FileManager, config: ThumbnailServiceConfig) -> Result<Self> {
+        fs::create_dir_all(&config.cache_dir)?;
+
+        Ok(Self {
+            config,
+            file_manager: Arc::new(file_manager),
+            state: Arc::new((
+                Mutex::new(ServiceState {
+                    queue: Vec::new(),
+                    statuses: HashMap::new(),
+                    next_id: 1,
+                    next_sequence: 0,
+                    running: false,
+                }),
+                Condvar::new(),
+            )),
+            callback: Arc::new(Mutex::new(None)),
+            workers: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Set a callback invoked whenever a request completes, fails or is cancelled
+    pub fn set_callback<F>(&self, callback: F)
+    where
+        F: Fn(u64, ThumbnailStatus) + Send + 'static,
+    {
+        *self.callback.lock().unwrap() = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    /// Start the worker threads
+    pub fn start(&self) -> Result<()> {
+        {
+            let (lock, _) = &*self.state;
+            let mut state = lock.lock().unwrap();
+            if state.running {
+                return Ok(());
+            }
+            state.running = true;
+        }
+
+        let mut workers = self.workers.lock().unwrap();
+        for index in 0..self.config.workers.max(1) {
+            let state = self.state.clone();
+            let file_manager = self.file_manager.clone();
+            let callback = self.callback.clone();
+            let cache_dir = self.config.cache_dir.clone();
+
+            let handle = thread::Builder::new()
+                .name(format!("thumbnail-worker-{}", index))
+                .spawn(move || {
+                    Self::worker_thread(state, file_manager, callback, cache_dir);
+                })?;
+            workers.push(handle);
+        }
+
+        info!("Thumbnail service started with {} workers", workers.len());
+        Ok(())
+    }
+
+    /// Stop the worker threads, waiting for in-flight requests to finish
+    pub fn stop(&self) -> Result<()> {
+        {
+            let (lock, cvar) = &*self.state;
+            lock.lock().unwrap().running = false;
+            cvar.notify_all();
+        }
+
+        for handle in self.workers.lock().unwrap().drain(..) {
+            if handle.join().is_err() {
+                error!("Thumbnail worker panicked");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Request a thumbnail, returning the request ID
+    ///
+    /// If the thumbnail is already in the disk cache the request completes immediately.
+    pub fn request(&self, request: ThumbnailRequest) -> Result<u64> {
+        Ok(self.request_batch(vec![request])?[0])
+    }
+
+    /// Request several thumbnails at once, returning their request IDs in order
+    pub fn request_batch(&self, requests: Vec<ThumbnailRequest>) -> Result<Vec<u64>> {
+        let mut ids = Vec::with_capacity(requests.len());
+        let mut ready = Vec::new();
+
+        {
+            let (lock, cvar) = &*self.state;
+            let mut state = lock.lock().unwrap();
+
+            for request in requests {
+                let id = state.next_id;
+                state.next_id += 1;
+                ids.push(id);
+
+                // Serve straight from the disk cache when possible
+                if let Ok(cached) = Self::cache_path(&self.config.cache_dir, &request) {
+                    if cached.exists() {
+                        let status = ThumbnailStatus::Ready(cached);
+                        state.statuses.insert(id, status.clone());
+                        ready.push((id, status));
+                        continue;
+                    }
+                }
+
+                let sequence = state.next_sequence;
+                state.next_sequence += 1;
+                state.statuses.insert(id, ThumbnailStatus::Queued);
+                state.queue.push(QueuedRequest { id, sequence, request });
+            }
+
+            cvar.notify_all();
+        }
+
+        for (id, status) in ready {
+            Self::notify(&self.callback, id, status);
+        }
+
+        self.start()?;
+        Ok(ids)
+    }
+
+    /// Get the status of a request
+    pub fn get_status(&self, id: u64) -> Result<ThumbnailStatus> {
+        let (lock, _) = &*self.state;
+        lock.lock()
+            .unwrap()
+            .statuses
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Thumbnail request not found: {}", id))
+    }
+
+    /// Change the priority of a queued request (e.g. when it scrolls into view)
+    pub fn reprioritize(&self, id: u64, priority: ThumbnailPriority) -> Result<()> {
+        let (lock, _) = &*self.state;
+        let mut state = lock.lock().unwrap();
+
+        let entry = state
+            .queue
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| anyhow!("Thumbnail request not queued: {}", id))?;
+        entry.request.priority = priority;
+
+        Ok(())
+    }
+
+    /// Cancel a queued request
+    ///
+    /// Requests that are already being generated run to completion.
+    pub fn cancel(&self, id: u64) -> Result<()> {
+        let cancelled = {
+            let (lock, _) = &*self.state;
+            let mut state = lock.lock().unwrap();
+
+            let before = state.queue.len();
+            state.queue.retain(|entry| entry.id != id);
+            if state.queue.len() == before {
+                return Err(anyhow!("Thumbnail request not queued: {}", id));
+            }
+            state.statuses.insert(id, ThumbnailStatus::Cancelled);
+            id
+        };
+
+        Self::notify(&self.callback, cancelled, ThumbnailStatus::Cancelled);
+        Ok(())
+    }
+
+    /// Cancel every queued request in a UI group (e.g. when the view scrolls away)
+    pub fn cancel_group(&self, group: &str) -> usize {
+        let cancelled: Vec<u64> = {
+            let (lock, _) = &*self.state;
+            let mut state = lock.lock().unwrap();
+
+            let ids: Vec<u64> = state
+                .queue
+                .iter()
+                .filter(|entry| entry.request.group.as_deref() == Some(group))
+                .map(|entry| entry.id)
+                .collect();
+
+            state.queue.retain(|entry| entry.request.group.as_deref() != Some(group));
+            for id in &ids {
+                state.statuses.insert(*id, ThumbnailStatus::Cancelled);
+            }
+            ids
+        };
+
+        for id in &cancelled {
+            Self::notify(&self.callback, *id, ThumbnailStatus::Cancelled);
+        }
+
+        debug!("Cancelled {} thumbnail requests in group {}", cancelled.len(), group);
+        cancelled.len()
+    }
+
+    /// Number of requests waiting in the queue
+    pub fn queue_len(&self) -> usize {
+        let (lock, _) = &*self.state;
+        lock.lock().unwrap().queue.len()
+    }
+
+    /// Forget statuses of finished requests
+    pub fn clear_finished(&self) {
+        let (lock, _) = &*self.state;
+        lock.lock().unwrap().statuses.retain(|_, status| {
+            matches!(status, ThumbnailStatus::Queued | ThumbnailStatus::InProgress)
+        });
+    }
+
+    /// Remove all thumbnails from the disk cache
+    pub fn clear_cache(&self) -> Result<()> {
+        if self.config.cache_dir.exists() {
+            fs::remove_dir_all(&self.config.cache_dir)?;
+        }
+        fs::create_dir_all(&self.config.cache_dir)?;
+        Ok(())
+    }
+
+    /// Compute the cache path for a request
+    ///
+    /// The key covers a hash of the file's content, its modification time and the
+    /// thumbnail options, so edited files are regenerated automatically while moved or
+    /// renamed ones still hit the cache. Keys are persisted, so they are hashed with FNV-1a.
+    pub(crate) fn cache_path(cache_dir: &Path, request: &ThumbnailRequest) -> Result<PathBuf> {
+        let fingerprint = MediaFingerprint::compute(&request.path)?;
+        let modified = fs::metadata(&request.path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut hash = fnv1a(FNV_OFFSET, &fingerprint.content_hash.to_le_bytes());
+        hash = fnv1a(hash, &modified.as_nanos().to_le_bytes());
+        hash = fnv1a(hash, &request.options.width.to_le_bytes());
+        hash = fnv1a(hash, &request.options.height.to_le_bytes());
+        hash = fnv1a(hash, &[request.options.quality]);
+        hash = fnv1a(hash, &request.options.position.unwrap_or(0.0).to_bits().to_le_bytes());
+
+        Ok(cache_dir.join(format!("{:016x}.jpg", hash)))
+    }
+
+    /// Pop the highest-priority request, oldest first within a priority
+    fn pop_next(state: &mut ServiceState) -> Option<QueuedRequest> {
+        let index = state
+            .queue
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| {
+                a.request
+                    .priority
+                    .cmp(&b.request.priority)
+                    .then(b.sequence.cmp(&a.sequence))
+            })
+            .map(|(index, _)| index)?;
+
+        Some(state.queue.swap_remove(index))
+    }
+
+    /// Invoke the completion callback, if any
+    fn notify(callback: &Arc<Mutex<Option<ThumbnailCallback>>>, id: u64, status: ThumbnailStatus) {
+        let callback = callback.lock().unwrap().clone();
+        if let Some(callback) = callback {
+            (callback.lock().unwrap())(id, status);
+        }
+    }
+
+    /// Worker thread for generating thumbnails
+    fn worker_thread(
+        state: Arc<(Mutex<ServiceState>, Condvar)>,
+        file_manager: Arc<FileManager>,
+        callback: Arc<Mutex<Option<ThumbnailCallback>>>,
+        cache_dir: PathBuf,
+    ) {
+        let (lock, cvar) = &*state;
+        let mut grabber = FrameGrabber::new();
+
+        loop {
+            // Wait for the next request
+            let next = {
+                let mut state = lock.lock().unwrap();
+                loop {
+                    if !state.running {
+                        return;
+                    }
+                    if let Some(next) = Self::pop_next(&mut state) {
+                        state.statuses.insert(next.id, ThumbnailStatus::InProgress);
+                        break next;
+                    }
+                    state = cvar
+                        .wait_timeout(state, Duration::from_millis(500))
+                        .unwrap()
+                        .0;
+                }
+            };
+
+            // Generate the thumbnail
+            let result = Self::generate(&file_manager, &mut grabber, &cache_dir, &next.request);
+            let status = match result {
+                Ok(path) => ThumbnailStatus::Ready(path),
+                Err(e) => {
+                    warn!("Thumbnail generation failed for {:?}: {}", next.request.path, e);
+                    ThumbnailStatus::Failed(e.to_string())
+                }
+            };
+
+            lock.lock().unwrap().statuses.insert(next.id, status.clone());
+            Self::notify(&callback, next.id, status);
+        }
+    }
+
+    /// Generate a thumbnail into the disk cache
+    fn generate(
+        file_manager: &FileManager,
+        grabber: &mut FrameGrabber,
+        cache_dir: &Path,
+        request: &ThumbnailRequest,
+    ) -> Result<PathBuf> {
+        let cache_path = Self::cache_path(cache_dir, request)?;
+        if cache_path.exists() {
+            return Ok(cache_path);
+        }
+
+        let media_type = file_manager.get_media_info(&request.path)?.media_type;
+        if media_type == MediaType::Video {
+            // Video frames go through the worker's reusable pipeline, or FFmpeg if configured
+            file_manager.backend_policy().run(Subsystem::Thumbnailing, |backend| match backend {
+                Backend::GStreamer => grabber.grab(&request.path, &request.options, &cache_path),
+                Backend::FFmpeg => ffmpeg_backend::grab_jpeg_frame(&request.path, &request.options, &cache_path),
+            })?;
+        } else {
+            let generated = file_manager.generate_thumbnail(&request.path, Some(request.options.clone()))?;
+            fs::copy(&generated, &cache_path)?;
+        }
+
+        Ok(cache_path)
+    }
+}
+
+impl Drop for ThumbnailService {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Reusable video frame grabbing pipeline owned by a single worker
+///
+/// Building a pipeline per thumbnail dominates the cost for short clips, so each worker
+/// keeps one pipeline around and only swaps the source location and output caps.
+struct FrameGrabber {
+    pipeline: Option<gst::Pipeline>,
+}
+
+impl FrameGrabber {
+    fn new() -> Self {
+        Self { pipeline: None }
+    }
+
+    /// Build the pipeline on first use
+    fn pipeline(&mut self) -> Result<&gst::Pipeline> {
+        if self.pipeline.is_none() {
+            let builder = PipelineBuilder::new("thumbnail-grabber")?;
+            let source = builder.chain(&[
+                ElementSpec::new("filesrc").name("src"),
+                ElementSpec::new("decodebin"),
+            ])?;
+            let encode = builder.chain(&[
+                ElementSpec::new("videoconvert"),
+                ElementSpec::new("videoscale"),
+                ElementSpec::new("capsfilter").name("caps"),
+                ElementSpec::new("jpegenc").name("enc"),
+                ElementSpec::new("appsink")
+                    .name("sink")
+                    .property("max-buffers", 1u32)
+                    .property("sync", false),
+            ])?;
+
+            // decodebin exposes pads again every time the source changes
+            builder.link_dynamic(&source[1], StreamKind::Video, &encode[0]);
+
+            let pipeline = builder.build();
+            self.pipeline = Some(pipeline);
+        }
+
+        Ok(self.pipeline.as_ref().unwrap())
+    }
+
+    /// Grab a single frame as JPEG and write it to `output`
+    fn grab(&mut self, path: &Path, options: &ThumbnailOptions, output: &Path) -> Result<()> {
+        let pipeline = self.pipeline()?.clone();
+
+        let result = Self::grab_with(&pipeline, path, options, output);
+
+        // Always return to NULL so the next request can swap the source
+        if pipeline.set_state(gst::State::Null).is_err() {
+            // Drop the pipeline and rebuild it next time
+            self.pipeline = None;
+        }
+
+        result
+    }
+
+    fn grab_with(pipeline: &gst::Pipeline, path: &Path, options: &ThumbnailOptions, output: &Path) -> Result<()> {
+        let src = pipeline.by_name("src").ok_or_else(|| anyhow!("Missing filesrc"))?;
+        let caps = pipeline.by_name("caps").ok_or_else(|| anyhow!("Missing capsfilter"))?;
+        let enc = pipeline.by_name("enc").ok_or_else(|| anyhow!("Missing jpegenc"))?;
+        let sink = pipeline
+            .by_name("sink")
+            .ok_or_else(|| anyhow!("Missing appsink"))?
+            .dynamic_cast::<gst_app::AppSink>()
+            .map_err(|_| anyhow!("Failed to cast to AppSink"))?;
+
+        // Configure for this request
+        src.set_property("location", path_policy::gst_location(path)?);
+        caps.set_property(
+            "caps",
+            gst::Caps::builder("video/x-raw")
+                .field("width", options.width as i32)
+                .field("height", options.height as i32)
+                .build(),
+        );
+        enc.set_property("quality", options.quality.min(100) as i32);
+
+        // Preroll
+        pipeline.set_state(gst::State::Paused)?;
+        let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(5));
+        state_result.map_err(|_| anyhow!("Failed to preroll {:?}", path))?;
+
+        // Seek to the requested position
+        let position = options.position.unwrap_or(0.0);
+        if position > 0.0 {
+            pipeline.seek_simple(
+                gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                gst::ClockTime::from_nseconds((position * 1_000_000_000.0) as u64),
+            )?;
+            let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(5));
+            state_result.map_err(|_| anyhow!("Failed to seek in {:?}", path))?;
+        }
+
+        // The prerolled buffer is the encoded frame
+        let sample = sink
+            .try_pull_preroll(gst::ClockTime::from_seconds(5))
+            .ok_or_else(|| anyhow!("No frame available for {:?}", path))?;
+        let buffer = sample.buffer().ok_or_else(|| anyhow!("Empty thumbnail sample"))?;
+        let map = buffer.map_readable()?;
+
+        fs::write(output, map.as_slice())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use super::super::file_manager::{FileManager, ThumbnailOptions};
+    use super::super::file_manager_thumbnails::*;
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use anyhow::Result;
+
+    /// Service with a single worker, so requests finish in the order they are picked
+    fn service(dir: &PathBuf) -> Result<(ThumbnailService, mpsc::Receiver<(u64, ThumbnailStatus)>)> {
+        let config = ThumbnailServiceConfig {
+            cache_dir: dir.join("cache"),
+            workers: 1,
+        };
+        let service = ThumbnailService::new(FileManager::new()?, config)?;
+        let (sender, receiver) = mpsc::channel();
+        service.set_callback(move |id, status| {
+            let _ = sender.send((id, status));
+        });
+        Ok((service, receiver))
+    }
+
+    fn source(dir: &PathBuf, name: &str) -> Result<PathBuf> {
+        let path = dir.join(name);
+        fs::write(&path, name)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_requests_run_by_priority_then_age() -> Result<()> {
+        let dir = create_test_dir("thumbnail", "priority")?;
+        let (service, finished) = service(&dir)?;
+
+        let requests = vec![
+            ThumbnailRequest::new(&source(&dir, "background.xyz")?).with_priority(ThumbnailPriority::Background),
+            ThumbnailRequest::new(&source(&dir, "first.xyz")?),
+            ThumbnailRequest::new(&source(&dir, "nearby.xyz")?).with_priority(ThumbnailPriority::Nearby),
+            ThumbnailRequest::new(&source(&dir, "second.xyz")?),
+        ];
+        let ids = service.request_batch(requests)?;
+
+        let order: Vec<u64> = (0..ids.len())
+            .map(|_| finished.recv_timeout(Duration::from_secs(10)).map(|(id, _)| id))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(order, vec![ids[1], ids[3], ids[2], ids[0]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_thumbnails_complete_immediately() -> Result<()> {
+        let dir = create_test_dir("thumbnail", "cache")?;
+        let (service, finished) = service(&dir)?;
+        let request = ThumbnailRequest::new(&source(&dir, "clip.xyz")?);
+
+        // Hit: served from disk without queueing
+        let cached = ThumbnailService::cache_path(&dir.join("cache"), &request)?;
+        fs::write(&cached, b"jpeg")?;
+        let id = service.request(request.clone())?;
+        assert_eq!(service.get_status(id)?, ThumbnailStatus::Ready(cached.clone()));
+        assert_eq!(finished.try_recv()?, (id, ThumbnailStatus::Ready(cached)));
+        assert_eq!(service.queue_len(), 0);
+
+        // Miss after clearing the cache: goes through a worker
+        service.clear_cache()?;
+        let id = service.request(request)?;
+        let (finished_id, status) = finished.recv_timeout(Duration::from_secs(10))?;
+        assert_eq!(finished_id, id);
+        assert_ne!(status, ThumbnailStatus::Queued);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_key_covers_content_and_options() -> Result<()> {
+        let dir = create_test_dir("thumbnail", "key")?;
+        let cache_dir = dir.join("cache");
+        let path = source(&dir, "clip.xyz")?;
+        let request = ThumbnailRequest::new(&path);
+        let key = ThumbnailService::cache_path(&cache_dir, &request)?;
+
+        // Stable for the same request; priority and group don't matter
+        assert_eq!(ThumbnailService::cache_path(&cache_dir, &request)?, key);
+        let regrouped = request.clone().with_priority(ThumbnailPriority::Background).with_group("bin");
+        assert_eq!(ThumbnailService::cache_path(&cache_dir, &regrouped)?, key);
+        assert_eq!(key.parent(), Some(cache_dir.as_path()));
+
+        // Every option is part of the key
+        let variants = [
+            ThumbnailOptions { width: 640, ..ThumbnailOptions::default() },
+            ThumbnailOptions { height: 360, ..ThumbnailOptions::default() },
+            ThumbnailOptions { quality: 50, ..ThumbnailOptions::default() },
+            ThumbnailOptions { position: Some(1.5), ..ThumbnailOptions::default() },
+        ];
+        for options in variants {
+            let other = request.clone().with_options(options.clone());
+            assert_ne!(ThumbnailService::cache_path(&cache_dir, &other)?, key, "{:?}", options);
+        }
+
+        // The key follows the file's content, not its path
+        let renamed = dir.join("renamed.xyz");
+        fs::rename(&path, &renamed)?;
+        let moved = ThumbnailRequest::new(&renamed);
+        assert_eq!(ThumbnailService::cache_path(&cache_dir, &moved)?, key);
+        fs::write(&renamed, "edited clip")?;
+        assert_ne!(ThumbnailService::cache_path(&cache_dir, &moved)?, key);
+
+        // Missing sources have no key
+        assert!(ThumbnailService::cache_path(&cache_dir, &ThumbnailRequest::new(&dir.join("missing.xyz"))).is_err());
+        Ok(())
+    }
+}
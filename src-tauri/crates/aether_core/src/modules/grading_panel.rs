@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use super::color_grading::{ColorAdjustments, ColorGradingEngine, ColorParameter, GradingWheel, WheelChannel};
+
+/// Port the Tangent Hub listens on for applications
+pub const TANGENT_HUB_PORT: u16 = 64246;
+
+/// Share of a parameter's range an absolute control has to come within to pick it up
+const PICKUP_TOLERANCE: f32 = 0.02;
+
+/// Input from a grading panel control
+#[derive(Debug, Clone, PartialEq)]
+pub enum PanelInput {
+    /// Wheel, ring or encoder movement; panels send fractional steps
+    Relative { control: String, delta: f32 },
+    /// Knob or fader position in 0..1
+    Absolute { control: String, position: f32 },
+    /// Return the control's parameter to its default
+    Reset { control: String },
+}
+
+impl PanelInput {
+    pub fn control(&self) -> &str {
+        match self {
+            PanelInput::Relative { control, .. }
+            | PanelInput::Absolute { control, .. }
+            | PanelInput::Reset { control } => control,
+        }
+    }
+}
+
+/// A panel control driving a color parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PanelBinding {
+    pub control: String,
+    pub parameter: ColorParameter,
+    /// Parameter change per unit of relative input
+    pub sensitivity: f32,
+}
+
+impl PanelBinding {
+    pub fn new(control: &str, parameter: ColorParameter, sensitivity: f32) -> Self {
+        Self {
+            control: control.to_string(),
+            parameter,
+            sensitivity,
+        }
+    }
+}
+
+/// Bindings of a panel's controls
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PanelLayout {
+    pub bindings: Vec<PanelBinding>,
+}
+
+impl PanelLayout {
+    pub fn binding(&self, control: &str) -> Option<&PanelBinding> {
+        self.bindings.iter().find(|binding| binding.control == control)
+    }
+}
+
+impl Default for PanelLayout {
+    /// Wheel rings and balls, named like `gain` and `gain_red`, and knobs for the global adjustments
+    fn default() -> Self {
+        let wheels = [
+            ("lift", GradingWheel::Lift),
+            ("gamma", GradingWheel::Gamma),
+            ("gain", GradingWheel::Gain),
+            ("offset", GradingWheel::Offset),
+        ];
+        let channels = [
+            ("", WheelChannel::Master),
+            ("_red", WheelChannel::Red),
+            ("_green", WheelChannel::Green),
+            ("_blue", WheelChannel::Blue),
+        ];
+
+        let mut bindings: Vec<PanelBinding> = wheels.iter()
+            .flat_map(|&(wheel_name, wheel)| channels.iter().map(move |&(suffix, channel)| {
+                let parameter = ColorParameter::Wheel { wheel, channel };
+                PanelBinding::new(&format!("{}{}", wheel_name, suffix), parameter, 0.005)
+            }))
+            .collect();
+        bindings.extend([
+            PanelBinding::new("exposure", ColorParameter::Exposure, 0.05),
+            PanelBinding::new("contrast", ColorParameter::Contrast, 0.01),
+            PanelBinding::new("saturation", ColorParameter::Saturation, 0.01),
+            PanelBinding::new("hue", ColorParameter::Hue, 1.0),
+            PanelBinding::new("temperature", ColorParameter::Temperature, 0.005),
+            PanelBinding::new("tint", ColorParameter::Tint, 0.005),
+        ]);
+        Self { bindings }
+    }
+}
+
+/// Soft pickup state of an absolute control
+#[derive(Debug, Clone, Copy, Default)]
+struct Pickup {
+    /// Parameter value the control last set; `None` until it picks the parameter up
+    value: Option<f32>,
+    /// Value the control's position mapped to last time
+    last_target: Option<f32>,
+}
+
+/// Applies grading panel input to color adjustments
+///
+/// Relative controls always apply. Absolute controls use soft pickup: a knob only takes
+/// over once it reaches or passes the parameter's current value, so moving it doesn't
+/// make the grade jump. A parameter changed by anything else has to be picked up again.
+pub struct GradingPanel {
+    layout: PanelLayout,
+    pickups: HashMap<String, Pickup>,
+}
+
+impl GradingPanel {
+    pub fn new(layout: PanelLayout) -> Self {
+        Self {
+            layout,
+            pickups: HashMap::new(),
+        }
+    }
+
+    pub fn layout(&self) -> &PanelLayout {
+        &self.layout
+    }
+
+    /// Whether an absolute control currently drives its parameter
+    pub fn is_picked_up(&self, control: &str) -> bool {
+        self.pickups.get(control).is_some_and(|pickup| pickup.value.is_some())
+    }
+
+    /// Apply input to a grading engine, returning the changed parameter and its new value
+    pub fn apply(&mut self, engine: &mut ColorGradingEngine, input: &PanelInput) -> Result<Option<(ColorParameter, f32)>> {
+        let mut adjustments = *engine.get_adjustments();
+        let change = self.apply_to(&mut adjustments, input);
+        if change.is_some() {
+            engine.set_adjustments(adjustments)?;
+        }
+        Ok(change)
+    }
+
+    /// Apply input to adjustments, returning the changed parameter and its new value
+    pub fn apply_to(&mut self, adjustments: &mut ColorAdjustments, input: &PanelInput) -> Option<(ColorParameter, f32)> {
+        let Some(binding) = self.layout.binding(input.control()) else {
+            debug!("No binding for panel control {}", input.control());
+            return None;
+        };
+        let parameter = binding.parameter;
+        let current = parameter.get(adjustments);
+
+        let value = match input {
+            PanelInput::Relative { delta, .. } => current + delta * binding.sensitivity,
+            PanelInput::Reset { .. } => parameter.get(&ColorAdjustments::default()),
+            PanelInput::Absolute { control, position } => {
+                let (min, max) = parameter.range();
+                let target = min + position.clamp(0.0, 1.0) * (max - min);
+                let pickup = self.pickups.entry(control.clone()).or_default();
+
+                // The value moved under the control, so it has to catch up again
+                if pickup.value.is_some_and(|value| value != current) {
+                    pickup.value = None;
+                }
+
+                let within = (target - current).abs() <= PICKUP_TOLERANCE * (max - min);
+                let crossed = pickup.last_target
+                    .is_some_and(|last| (last - current) * (target - current) <= 0.0);
+                pickup.last_target = Some(target);
+                if pickup.value.is_none() && !within && !crossed {
+                    return None;
+                }
+                target
+            },
+        };
+
+        parameter.set(adjustments, value);
+        let value = parameter.get(adjustments);
+        if let PanelInput::Absolute { control, .. } = input {
+            if let Some(pickup) = self.pickups.get_mut(control) {
+                pickup.value = Some(value);
+            }
+        }
+        Some((parameter, value))
+    }
+}
+
+impl Default for GradingPanel {
+    fn default() -> Self {
+        Self::new(PanelLayout::default())
+    }
+}
+
+/// A message in the Tangent Hub protocol
+///
+/// Messages are a big-endian length followed by a command ID and its arguments;
+/// strings are a length followed by the bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TangentMessage {
+    /// Hub to application: sent after connecting, lists the panels
+    InitiateComms { protocol_revision: u32, panels: Vec<(u32, u32)> },
+    /// Hub to application: a control moved by `increment`
+    ParameterChange { parameter: u32, increment: f32 },
+    ParameterReset { parameter: u32 },
+    /// Hub to application: the panel wants to display a parameter's value
+    ParameterValueRequest { parameter: u32 },
+    ActionOn { action: u32 },
+    ActionOff { action: u32 },
+    /// Application to hub: identifies the application and its map file directories
+    ApplicationDefinition { name: String, system_dir: String, user_dir: String },
+    /// Application to hub: a parameter's value for panel displays
+    ParameterValue { parameter: u32, value: f32, at_default: bool },
+    /// Commands that aren't used here
+    Other { command: u32 },
+}
+
+impl TangentMessage {
+    const INITIATE_COMMS: u32 = 0x01;
+    const PARAMETER_CHANGE: u32 = 0x02;
+    const PARAMETER_RESET: u32 = 0x03;
+    const PARAMETER_VALUE_REQUEST: u32 = 0x04;
+    const ACTION_ON: u32 = 0x08;
+    const ACTION_OFF: u32 = 0x0B;
+    const APPLICATION_DEFINITION: u32 = 0x81;
+    const PARAMETER_VALUE: u32 = 0x82;
+
+    /// Decode a message body, without the length prefix
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut reader = TangentReader { data: body, offset: 0 };
+        let message = match reader.u32()? {
+            Self::INITIATE_COMMS => {
+                let protocol_revision = reader.u32()?;
+                let count = reader.u32()?;
+                let panels = (0..count)
+                    .map(|_| Ok((reader.u32()?, reader.u32()?)))
+                    .collect::<Result<_>>()?;
+                TangentMessage::InitiateComms { protocol_revision, panels }
+            },
+            Self::PARAMETER_CHANGE => TangentMessage::ParameterChange {
+                parameter: reader.u32()?,
+                increment: f32::from_bits(reader.u32()?),
+            },
+            Self::PARAMETER_RESET => TangentMessage::ParameterReset { parameter: reader.u32()? },
+            Self::PARAMETER_VALUE_REQUEST => TangentMessage::ParameterValueRequest { parameter: reader.u32()? },
+            Self::ACTION_ON => TangentMessage::ActionOn { action: reader.u32()? },
+            Self::ACTION_OFF => TangentMessage::ActionOff { action: reader.u32()? },
+            Self::APPLICATION_DEFINITION => TangentMessage::ApplicationDefinition {
+                name: reader.string()?,
+                system_dir: reader.string()?,
+                user_dir: reader.string()?,
+            },
+            Self::PARAMETER_VALUE => TangentMessage::ParameterValue {
+                parameter: reader.u32()?,
+                value: f32::from_bits(reader.u32()?),
+                at_default: reader.u32()? != 0,
+            },
+            command => TangentMessage::Other { command },
+        };
+        Ok(message)
+    }
+
+    /// Encode the message with its length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        fn string(body: &mut Vec<u8>, text: &str) {
+            body.extend((text.len() as u32).to_be_bytes());
+            body.extend(text.as_bytes());
+        }
+
+        let mut body = Vec::new();
+        let mut words = |values: &[u32]| values.iter().for_each(|value| body.extend(value.to_be_bytes()));
+        match self {
+            TangentMessage::InitiateComms { protocol_revision, panels } => {
+                words(&[Self::INITIATE_COMMS, *protocol_revision, panels.len() as u32]);
+                panels.iter().for_each(|(panel_type, id)| words(&[*panel_type, *id]));
+            },
+            TangentMessage::ParameterChange { parameter, increment } => {
+                words(&[Self::PARAMETER_CHANGE, *parameter, increment.to_bits()]);
+            },
+            TangentMessage::ParameterReset { parameter } => words(&[Self::PARAMETER_RESET, *parameter]),
+            TangentMessage::ParameterValueRequest { parameter } => words(&[Self::PARAMETER_VALUE_REQUEST, *parameter]),
+            TangentMessage::ActionOn { action } => words(&[Self::ACTION_ON, *action]),
+            TangentMessage::ActionOff { action } => words(&[Self::ACTION_OFF, *action]),
+            TangentMessage::ApplicationDefinition { name, system_dir, user_dir } => {
+                words(&[Self::APPLICATION_DEFINITION]);
+                string(&mut body, name);
+                string(&mut body, system_dir);
+                string(&mut body, user_dir);
+            },
+            TangentMessage::ParameterValue { parameter, value, at_default } => {
+                words(&[Self::PARAMETER_VALUE, *parameter, value.to_bits(), *at_default as u32]);
+            },
+            TangentMessage::Other { command } => words(&[*command]),
+        }
+
+        let mut message = (body.len() as u32).to_be_bytes().to_vec();
+        message.extend(body);
+        message
+    }
+}
+
+struct TangentReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl TangentReader<'_> {
+    fn bytes(&mut self, count: usize) -> Result<&[u8]> {
+        let end = self.offset.checked_add(count).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("Truncated Tangent message"))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+}
+
+/// Connection to the Tangent Hub, feeding panel input into a `GradingPanel`
+///
+/// Parameter IDs come from the application's Tangent map file and are bound to panel
+/// controls by name. Panels show values the hub asks for and values that change.
+pub struct TangentHub {
+    stream: TcpStream,
+    controls: HashMap<u32, String>,
+}
+
+impl TangentHub {
+    /// Connect and register the application; `system_dir` holds its Tangent map files
+    pub fn connect(address: SocketAddr, name: &str, system_dir: &str, controls: HashMap<u32, String>) -> Result<Self> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|e| anyhow!("Failed to connect to Tangent Hub at {}: {}", address, e))?;
+        stream.set_nodelay(true)?;
+
+        stream.write_all(&TangentMessage::ApplicationDefinition {
+            name: name.to_string(),
+            system_dir: system_dir.to_string(),
+            user_dir: String::new(),
+        }.encode())?;
+
+        info!("Connected to Tangent Hub at {}", address);
+        Ok(Self { stream, controls })
+    }
+
+    /// Wait at most `timeout` in `process`; `None` blocks
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.stream.set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    /// Read the next message from the hub
+    pub fn read_message(&mut self) -> Result<TangentMessage> {
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length)?;
+        let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+        self.stream.read_exact(&mut body)?;
+        TangentMessage::decode(&body)
+    }
+
+    /// Handle the next message from the hub, returning the parameter it changed
+    pub fn process(&mut self, panel: &mut GradingPanel, engine: &mut ColorGradingEngine) -> Result<Option<(ColorParameter, f32)>> {
+        let message = self.read_message()?;
+        let input = match &message {
+            TangentMessage::ParameterChange { parameter, increment } => self.controls.get(parameter)
+                .map(|control| PanelInput::Relative { control: control.clone(), delta: *increment }),
+            TangentMessage::ParameterReset { parameter } => self.controls.get(parameter)
+                .map(|control| PanelInput::Reset { control: control.clone() }),
+            TangentMessage::ParameterValueRequest { parameter } => {
+                self.send_value(*parameter, panel, engine.get_adjustments())?;
+                return Ok(None);
+            },
+            TangentMessage::InitiateComms { panels, .. } => {
+                info!("Tangent Hub reports {} panels", panels.len());
+                return Ok(None);
+            },
+            other => {
+                debug!("Ignoring Tangent message {:?}", other);
+                return Ok(None);
+            },
+        };
+
+        let Some(input) = input else {
+            debug!("Unmapped Tangent parameter in {:?}", message);
+            return Ok(None);
+        };
+        let change = panel.apply(engine, &input)?;
+        if let TangentMessage::ParameterChange { parameter, .. } | TangentMessage::ParameterReset { parameter } = message {
+            self.send_value(parameter, panel, engine.get_adjustments())?;
+        }
+        Ok(change)
+    }
+
+    fn send_value(&mut self, parameter: u32, panel: &GradingPanel, adjustments: &ColorAdjustments) -> Result<()> {
+        let Some(binding) = self.controls.get(&parameter).and_then(|control| panel.layout().binding(control)) else {
+            return Ok(());
+        };
+
+        let value = binding.parameter.get(adjustments);
+        let at_default = value == binding.parameter.get(&ColorAdjustments::default());
+        self.stream.write_all(&TangentMessage::ParameterValue { parameter, value, at_default }.encode())?;
+        Ok(())
+    }
+}
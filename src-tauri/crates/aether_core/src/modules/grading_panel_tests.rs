@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use super::super::color_grading::{ColorAdjustments, ColorGradingEngine, ColorParameter, GradingWheel, WheelChannel};
+    use super::super::grading_panel::{GradingPanel, PanelInput, PanelLayout, TangentHub, TangentMessage};
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use anyhow::Result;
+
+    fn relative(control: &str, delta: f32) -> PanelInput {
+        PanelInput::Relative { control: control.to_string(), delta }
+    }
+
+    fn absolute(control: &str, position: f32) -> PanelInput {
+        PanelInput::Absolute { control: control.to_string(), position }
+    }
+
+    #[test]
+    fn test_relative_input() {
+        let mut panel = GradingPanel::default();
+        let mut adjustments = ColorAdjustments::default();
+
+        // Fractional increments add up at the binding's sensitivity
+        for _ in 0..4 {
+            panel.apply_to(&mut adjustments, &relative("gamma_red", 2.5));
+        }
+        assert!((adjustments.wheels.gamma.red - 0.05).abs() < 1e-6);
+        assert_eq!(adjustments.wheels.gamma.master, 0.0);
+
+        assert_eq!(panel.apply_to(&mut adjustments, &relative("hue", -500.0)), Some((ColorParameter::Hue, -180.0)));
+        assert_eq!(
+            panel.apply_to(&mut adjustments, &PanelInput::Reset { control: "gamma_red".to_string() }),
+            Some((ColorParameter::Wheel { wheel: GradingWheel::Gamma, channel: WheelChannel::Red }, 0.0))
+        );
+        assert_eq!(panel.apply_to(&mut adjustments, &relative("unbound", 1.0)), None);
+    }
+
+    #[test]
+    fn test_soft_pickup() {
+        let mut panel = GradingPanel::default();
+        let mut adjustments = ColorAdjustments::default();
+
+        // The knob starts away from the current value and doesn't take over
+        assert_eq!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.1)), None);
+        assert_eq!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.3)), None);
+        assert_eq!(adjustments.saturation, 1.0);
+        assert!(!panel.is_picked_up("saturation"));
+
+        // Passing the current value picks it up
+        assert_eq!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.6)), Some((ColorParameter::Saturation, 1.2)));
+        assert!(panel.is_picked_up("saturation"));
+        assert_eq!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.25)), Some((ColorParameter::Saturation, 0.5)));
+
+        // A change from elsewhere has to be picked up again
+        panel.apply_to(&mut adjustments, &relative("saturation", 50.0));
+        assert_eq!(adjustments.saturation, 1.0);
+        assert_eq!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.2)), None);
+        assert!(!panel.is_picked_up("saturation"));
+
+        // Landing close to the value picks it up straight away
+        assert!(panel.apply_to(&mut adjustments, &absolute("saturation", 0.505)).is_some());
+    }
+
+    #[test]
+    fn test_tangent_messages() -> Result<()> {
+        let messages = vec![
+            TangentMessage::InitiateComms { protocol_revision: 5, panels: vec![(0x0C, 1), (0x0D, 2)] },
+            TangentMessage::ParameterChange { parameter: 0x0003_0001, increment: -0.25 },
+            TangentMessage::ParameterReset { parameter: 7 },
+            TangentMessage::ApplicationDefinition {
+                name: "Aether".to_string(),
+                system_dir: "/opt/aether/tangent".to_string(),
+                user_dir: String::new(),
+            },
+            TangentMessage::ParameterValue { parameter: 7, value: 1.5, at_default: false },
+        ];
+        for message in messages {
+            let encoded = message.encode();
+            assert_eq!(u32::from_be_bytes(encoded[..4].try_into()?) as usize, encoded.len() - 4);
+            assert_eq!(TangentMessage::decode(&encoded[4..])?, message);
+        }
+
+        assert_eq!(TangentMessage::decode(&[0, 0, 0, 0x99])?, TangentMessage::Other { command: 0x99 });
+        assert!(TangentMessage::decode(&[0, 0, 0, 0x02, 0, 0]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tangent_hub() -> Result<()> {
+        let hub = TcpListener::bind("127.0.0.1:0")?;
+        let address = hub.local_addr()?;
+
+        let fake_hub = thread::spawn(move || -> Result<Vec<TangentMessage>> {
+            let (mut stream, _) = hub.accept()?;
+            let read = |stream: &mut std::net::TcpStream| -> Result<TangentMessage> {
+                let mut length = [0u8; 4];
+                stream.read_exact(&mut length)?;
+                let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+                stream.read_exact(&mut body)?;
+                TangentMessage::decode(&body)
+            };
+
+            let mut received = vec![read(&mut stream)?];
+            stream.write_all(&TangentMessage::ParameterChange { parameter: 1, increment: 10.0 }.encode())?;
+            received.push(read(&mut stream)?);
+            Ok(received)
+        });
+
+        let controls = HashMap::from([(1, "contrast".to_string())]);
+        let mut tangent = TangentHub::connect(address, "Aether", "/tmp", controls)?;
+        let mut panel = GradingPanel::new(PanelLayout::default());
+        let mut engine = ColorGradingEngine::new()?;
+
+        let change = tangent.process(&mut panel, &mut engine)?;
+        assert_eq!(change, Some((ColorParameter::Contrast, 1.1)));
+        assert_eq!(engine.get_adjustments().contrast, 1.1);
+
+        let received = fake_hub.join().unwrap()?;
+        assert!(matches!(&received[0], TangentMessage::ApplicationDefinition { name, .. } if name == "Aether"));
+        assert_eq!(received[1], TangentMessage::ParameterValue { parameter: 1, value: 1.1, at_default: false });
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use super::color_grading::{
+    ColorAdjustments, ColorCurves, GradingPreset, GradingPresetType, LutFormat, LutSettings,
+};
+use super::color_lut::{bake_grade_with, Lut3d, LutInterpolation, DEFAULT_BAKE_SIZE};
+
+/// `format` field of preset files
+pub const PRESET_FORMAT: &str = "aether.grade";
+
+/// Current version of the preset file format
+pub const PRESET_FORMAT_VERSION: u32 = 1;
+
+/// Extension of preset files
+pub const PRESET_EXTENSION: &str = "grade.json";
+
+/// Preset file inside an exported bundle
+pub const BUNDLE_PRESET_FILE: &str = "preset.json";
+
+/// Whole grade baked into one LUT inside an exported bundle
+pub const BUNDLE_GRADE_CUBE: &str = "grade.cube";
+
+/// Cube size for thumbnails, smaller than playback since thumbnails are tiny
+const THUMBNAIL_BAKE_SIZE: usize = 17;
+
+/// LUT reference in a preset file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetLutFile {
+    /// LUT file, relative to the preset file unless absolute
+    pub file: PathBuf,
+    #[serde(default = "default_lut_format")]
+    pub format: LutFormat,
+    #[serde(default = "default_strength")]
+    pub strength: f32,
+    #[serde(default)]
+    pub interpolation: LutInterpolation,
+}
+
+fn default_lut_format() -> LutFormat {
+    LutFormat::CUBE
+}
+
+fn default_strength() -> f32 {
+    1.0
+}
+
+/// A grading preset on disk
+///
+/// Preset files are JSON:
+///
+/// ```json
+/// {
+///   "format": "aether.grade",
+///   "version": 1,
+///   "name": "Teal and orange",
+///   "adjustments": { "brightness": 0.0, "contrast": 1.1, "saturation": 1.2, ... },
+///   "curves": { "rgb": [{ "x": 0.0, "y": 0.0 }, { "x": 1.0, "y": 1.0 }], "red": [...], ... },
+///   "lut": { "file": "teal_and_orange.cube", "strength": 0.8, "interpolation": "Tetrahedral" }
+/// }
+/// ```
+///
+/// `adjustments` has the fields of `ColorAdjustments`, with any missing wheels or exposure
+/// neutral, and `curves` the five curves of `ColorCurves`. `lut` is optional; its `format`
+/// defaults to `CUBE`, `strength` to 1.0 and `interpolation` to `Tetrahedral`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetFile {
+    pub format: String,
+    pub version: u32,
+    pub name: String,
+    pub adjustments: ColorAdjustments,
+    #[serde(default)]
+    pub curves: ColorCurves,
+    #[serde(default)]
+    pub lut: Option<PresetLutFile>,
+}
+
+impl PresetFile {
+    pub fn parse(json: &str) -> Result<Self> {
+        let file: PresetFile = serde_json::from_str(json)?;
+        if file.format != PRESET_FORMAT {
+            return Err(anyhow!("Not a grading preset: format is {:?}", file.format));
+        }
+        if file.version > PRESET_FORMAT_VERSION {
+            return Err(anyhow!("Preset format version {} is newer than supported ({})", file.version, PRESET_FORMAT_VERSION));
+        }
+        Ok(file)
+    }
+
+    /// Preset with the LUT path resolved against `base_dir`
+    pub fn into_preset(self, base_dir: &Path, source: &Path) -> GradingPreset {
+        GradingPreset {
+            name: self.name,
+            preset_type: GradingPresetType::FromFile(source.to_path_buf()),
+            adjustments: self.adjustments,
+            curves: self.curves,
+            lut: self.lut.map(|lut| LutSettings {
+                path: base_dir.join(lut.file),
+                format: lut.format,
+                strength: lut.strength,
+                interpolation: lut.interpolation,
+            }),
+        }
+    }
+}
+
+/// A preset in a `PresetLibrary` listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetSummary {
+    pub name: String,
+    pub path: PathBuf,
+    /// PNG from `generate_thumbnails`, if one has been made
+    pub thumbnail: Option<PathBuf>,
+    pub has_lut: bool,
+}
+
+/// An RGBA frame that preset thumbnails are graded from
+#[derive(Debug, Clone)]
+pub struct ReferenceFrame {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ReferenceFrame {
+    pub fn new(data: Vec<u8>, width: u32, height: u32) -> Result<Self> {
+        if width == 0 || height == 0 || data.len() != width as usize * height as usize * 4 {
+            return Err(anyhow!("Reference frame must be {}x{} RGBA", width, height));
+        }
+        Ok(Self { data, width, height })
+    }
+
+    /// Nearest-neighbour downscale to at most `max_width` wide, keeping the aspect ratio
+    fn scaled(&self, max_width: u32) -> (Vec<u8>, u32, u32) {
+        let width = self.width.min(max_width.max(1));
+        let height = ((self.height as u64 * width as u64) / self.width as u64).max(1) as u32;
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            let source_y = (y as u64 * self.height as u64 / height as u64) as usize;
+            for x in 0..width {
+                let source_x = (x as u64 * self.width as u64 / width as u64) as usize;
+                let offset = (source_y * self.width as usize + source_x) * 4;
+                data.extend_from_slice(&self.data[offset..offset + 4]);
+            }
+        }
+        (data, width, height)
+    }
+}
+
+/// Grading presets stored as files in a directory
+///
+/// Each preset is `<name>.grade.json`, with its LUT copied next to it so the directory
+/// can be moved or shared as a whole.
+pub struct PresetLibrary {
+    dir: PathBuf,
+}
+
+impl PresetLibrary {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn preset_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", file_stem(name), PRESET_EXTENSION))
+    }
+
+    fn thumbnail_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", file_stem(name)))
+    }
+
+    /// Store a preset, replacing any with the same name
+    pub fn save(&self, preset: &GradingPreset) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create preset directory {}", self.dir.display()))?;
+        let path = self.preset_path(&preset.name);
+        write_preset(preset, &path, &file_stem(&preset.name))?;
+        info!("Saved grading preset {} to {}", preset.name, path.display());
+        Ok(path)
+    }
+
+    pub fn load(&self, name: &str) -> Result<GradingPreset> {
+        read_preset(&self.preset_path(name))
+    }
+
+    /// Presets sorted by name
+    pub fn list(&self) -> Result<Vec<PresetSummary>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let suffix = format!(".{}", PRESET_EXTENSION);
+        let mut presets = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.ends_with(&suffix)) {
+                continue;
+            }
+
+            match read_preset(&path) {
+                Ok(preset) => {
+                    let thumbnail = self.thumbnail_path(&preset.name);
+                    presets.push(PresetSummary {
+                        thumbnail: thumbnail.exists().then_some(thumbnail),
+                        has_lut: preset.lut.is_some(),
+                        name: preset.name,
+                        path,
+                    });
+                },
+                Err(e) => warn!("Skipping preset {}: {}", path.display(), e),
+            }
+        }
+
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(presets)
+    }
+
+    /// Remove a preset with its LUT copy and thumbnail
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.preset_path(name);
+        let preset = read_preset(&path)?;
+        fs::remove_file(&path)?;
+
+        if let Some(lut) = preset.lut.filter(|lut| lut.path.parent() == Some(self.dir.as_path())) {
+            let _ = fs::remove_file(lut.path);
+        }
+        let _ = fs::remove_file(self.thumbnail_path(name));
+        Ok(())
+    }
+
+    /// Import a preset file, an exported bundle directory or a bare `.cube` LUT
+    ///
+    /// A `.cube` becomes a preset named after the file with neutral adjustments.
+    pub fn import(&self, path: &Path) -> Result<GradingPreset> {
+        let preset = if path.is_dir() {
+            read_preset(&path.join(BUNDLE_PRESET_FILE))?
+        } else if has_extension(path, "cube") {
+            Lut3d::load_cube(path)?;
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("LUT").to_string();
+            GradingPreset {
+                preset_type: GradingPresetType::FromFile(path.to_path_buf()),
+                adjustments: ColorAdjustments::default(),
+                curves: ColorCurves::default(),
+                lut: Some(LutSettings {
+                    path: path.to_path_buf(),
+                    format: LutFormat::CUBE,
+                    strength: 1.0,
+                    interpolation: LutInterpolation::default(),
+                }),
+                name,
+            }
+        } else {
+            read_preset(path)?
+        };
+
+        self.save(&preset)?;
+        self.load(&preset.name)
+    }
+
+    /// Export a preset as a bundle directory inside `dest`
+    ///
+    /// The bundle has the preset file, its LUT, and the whole grade baked into
+    /// `grade.cube` for applications that only read LUTs.
+    pub fn export_bundle(&self, name: &str, dest: &Path) -> Result<PathBuf> {
+        let preset = self.load(name)?;
+        let bundle = dest.join(file_stem(name));
+        fs::create_dir_all(&bundle)
+            .with_context(|| format!("Failed to create bundle {}", bundle.display()))?;
+
+        write_preset(&preset, &bundle.join(BUNDLE_PRESET_FILE), &file_stem(name))?;
+        bake_preset(&preset, DEFAULT_BAKE_SIZE)?.save_cube(&bundle.join(BUNDLE_GRADE_CUBE))?;
+
+        info!("Exported grading preset {} to {}", name, bundle.display());
+        Ok(bundle)
+    }
+
+    /// Export a preset as a single baked `.cube`
+    pub fn export_cube(&self, name: &str, path: &Path) -> Result<()> {
+        let preset = self.load(name)?;
+        let mut lut = bake_preset(&preset, DEFAULT_BAKE_SIZE)?;
+        lut.title = Some(preset.name);
+        lut.save_cube(path)
+    }
+
+    /// PNG of `reference` graded with a preset, at most `max_width` wide
+    pub fn thumbnail(&self, preset: &GradingPreset, reference: &ReferenceFrame, max_width: u32) -> Result<Vec<u8>> {
+        let (mut data, width, height) = reference.scaled(max_width);
+        bake_preset(preset, THUMBNAIL_BAKE_SIZE)?
+            .apply_to_frame(&mut data, "RGBA", LutInterpolation::Tetrahedral, 1.0)?;
+        encode_png(&data, width, height)
+    }
+
+    /// Write a thumbnail for every preset; returns how many were written
+    pub fn generate_thumbnails(&self, reference: &ReferenceFrame, max_width: u32) -> Result<usize> {
+        let mut written = 0;
+        for summary in self.list()? {
+            let preset = self.load(&summary.name)?;
+            match self.thumbnail(&preset, reference, max_width) {
+                Ok(png) => {
+                    fs::write(self.thumbnail_path(&preset.name), png)?;
+                    written += 1;
+                },
+                Err(e) => warn!("Failed to make a thumbnail for preset {}: {}", preset.name, e),
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Bake a preset's whole grade, including a `.cube` LUT
+pub fn bake_preset(preset: &GradingPreset, size: usize) -> Result<Lut3d> {
+    bake_grade_with(&preset.adjustments, &preset.curves, preset.lut.as_ref(), size)
+}
+
+fn read_preset(path: &Path) -> Result<GradingPreset> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read preset {}", path.display()))?;
+    let file = PresetFile::parse(&json).with_context(|| format!("Invalid preset {}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    Ok(file.into_preset(base_dir, path))
+}
+
+/// Write a preset file at `path`, copying its LUT alongside as `<stem>.<ext>`
+fn write_preset(preset: &GradingPreset, path: &Path, stem: &str) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let lut = match &preset.lut {
+        Some(lut) => {
+            let extension = lut.path.extension().and_then(|ext| ext.to_str()).unwrap_or("cube");
+            let file = PathBuf::from(format!("{}.{}", stem, extension));
+            let copy = dir.join(&file);
+            if lut.path != copy {
+                fs::copy(&lut.path, &copy)
+                    .with_context(|| format!("Failed to copy LUT {}", lut.path.display()))?;
+            }
+            Some(PresetLutFile {
+                file,
+                format: lut.format,
+                strength: lut.strength,
+                interpolation: lut.interpolation,
+            })
+        },
+        None => None,
+    };
+
+    let file = PresetFile {
+        format: PRESET_FORMAT.to_string(),
+        version: PRESET_FORMAT_VERSION,
+        name: preset.name.clone(),
+        adjustments: preset.adjustments,
+        curves: preset.curves.clone(),
+        lut,
+    };
+    fs::write(path, serde_json::to_string_pretty(&file)?)
+        .with_context(|| format!("Failed to write preset {}", path.display()))
+}
+
+fn encode_png(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(data)?;
+    Ok(png)
+}
+
+/// File name for a preset name, without characters that are unsafe in paths
+fn file_stem(name: &str) -> String {
+    let stem: String = name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if stem.is_empty() { "preset".to_string() } else { stem }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
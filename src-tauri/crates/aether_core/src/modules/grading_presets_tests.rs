@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod tests {
+    use super::super::color_grading::{
+        ColorAdjustments, ColorCurves, CurvePoint, GradingPreset, GradingPresetType, LutFormat, LutSettings,
+    };
+    use super::super::color_lut::{Lut3d, LutInterpolation};
+    use super::super::grading_presets::{
+    use super::super::test_utils::create_test_dir;
+        PresetFile, PresetLibrary, ReferenceFrame, BUNDLE_GRADE_CUBE, BUNDLE_PRESET_FILE,
+    };
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use anyhow::Result;
+
+    fn invert_cube(dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("invert.cube");
+        Lut3d::from_fn(2, |rgb| rgb.map(|value| 1.0 - value)).save_cube(&path)?;
+        Ok(path)
+    }
+
+    fn preset(name: &str, lut: Option<PathBuf>) -> GradingPreset {
+        let mut adjustments = ColorAdjustments {
+            saturation: 1.2,
+            ..ColorAdjustments::default()
+        };
+        adjustments.wheels.gain.red = 0.1;
+        GradingPreset {
+            name: name.to_string(),
+            preset_type: GradingPresetType::Custom(name.to_string()),
+            adjustments,
+            curves: ColorCurves {
+                luma: vec![CurvePoint { x: 0.0, y: 0.1 }, CurvePoint { x: 1.0, y: 1.0 }],
+                ..ColorCurves::default()
+            },
+            lut: lut.map(|path| LutSettings {
+                path,
+                format: LutFormat::CUBE,
+                strength: 0.5,
+                interpolation: LutInterpolation::Trilinear,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let dir = create_test_dir("grading_presets", "save_and_load")?;
+        let cube = invert_cube(&dir)?;
+        let library = PresetLibrary::new(&dir.join("presets"));
+        assert!(library.list()?.is_empty());
+
+        let path = library.save(&preset("Teal / orange", Some(cube)))?;
+        library.save(&preset("Bleach", None))?;
+        assert!(path.ends_with("Teal___orange.grade.json"));
+
+        let loaded = library.load("Teal / orange")?;
+        assert_eq!(loaded.adjustments, preset("", None).adjustments);
+        assert_eq!(loaded.curves, preset("", None).curves);
+        assert_eq!(loaded.preset_type, GradingPresetType::FromFile(path.clone()));
+
+        // The LUT is copied into the library
+        let lut = loaded.lut.unwrap();
+        assert_eq!(lut.path, library.dir().join("Teal___orange.cube"));
+        assert_eq!((lut.strength, lut.interpolation), (0.5, LutInterpolation::Trilinear));
+        assert!(lut.path.exists());
+
+        let names: Vec<_> = library.list()?.into_iter().map(|summary| (summary.name, summary.has_lut)).collect();
+        assert_eq!(names, vec![("Bleach".to_string(), false), ("Teal / orange".to_string(), true)]);
+
+        library.remove("Teal / orange")?;
+        assert!(!path.exists() && !lut.path.exists());
+        assert_eq!(library.list()?.len(), 1);
+        assert!(library.load("Teal / orange").is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_preset_file_format() -> Result<()> {
+        // Presets from before wheels and exposure, with defaults for the LUT
+        let json = r#"{
+            "format": "aether.grade",
+            "version": 1,
+            "name": "Old",
+            "adjustments": {
+                "brightness": 0.1, "contrast": 1.0, "saturation": 1.0, "gamma": 1.0,
+                "hue": 0.0, "temperature": 0.0, "tint": 0.0, "highlights": 0.0,
+                "shadows": 0.0, "whites": 0.0, "blacks": 0.0, "vibrance": 0.0, "sharpness": 0.0
+            },
+            "lut": { "file": "look.cube" }
+        }"#;
+        let file = PresetFile::parse(json)?;
+        assert_eq!(file.adjustments.brightness, 0.1);
+        assert!(file.adjustments.wheels.is_neutral());
+        assert_eq!(file.curves, ColorCurves::default());
+
+        let preset = file.into_preset(Path::new("/presets"), Path::new("/presets/old.grade.json"));
+        let lut = preset.lut.unwrap();
+        assert_eq!(lut.path, Path::new("/presets/look.cube"));
+        assert_eq!((lut.format, lut.strength, lut.interpolation), (LutFormat::CUBE, 1.0, LutInterpolation::Tetrahedral));
+
+        assert!(PresetFile::parse(&json.replace("aether.grade", "other")).is_err());
+        assert!(PresetFile::parse(&json.replace("\"version\": 1", "\"version\": 2")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_and_export() -> Result<()> {
+        let dir = create_test_dir("grading_presets", "import_and_export")?;
+        let cube = invert_cube(&dir)?;
+        let library = PresetLibrary::new(&dir.join("presets"));
+
+        // A bare LUT becomes a preset named after it
+        let imported = library.import(&cube)?;
+        assert_eq!(imported.name, "invert");
+        assert_eq!(imported.adjustments, ColorAdjustments::default());
+        assert_eq!(imported.lut.unwrap().path, library.dir().join("invert.cube"));
+
+        // A bundle has the preset, its LUT and the baked grade
+        library.save(&preset("Look", Some(cube.clone())))?;
+        let bundle = library.export_bundle("Look", &dir.join("export"))?;
+        assert!(bundle.join(BUNDLE_PRESET_FILE).exists());
+        assert!(bundle.join("Look.cube").exists());
+        assert_eq!(Lut3d::load_cube(&bundle.join(BUNDLE_GRADE_CUBE))?.size(), 33);
+
+        let other = PresetLibrary::new(&dir.join("other"));
+        let reimported = other.import(&bundle)?;
+        assert_eq!(reimported.name, "Look");
+        assert_eq!(reimported.adjustments, preset("", None).adjustments);
+        assert!(other.dir().join("Look.cube").exists());
+
+        // A single baked LUT matches the preset's grade
+        let baked = dir.join("look.cube");
+        library.export_cube("invert", &baked)?;
+        let lut = Lut3d::load_cube(&baked)?;
+        assert_eq!(lut.title.as_deref(), Some("invert"));
+        let graded = lut.sample([0.2, 0.4, 0.6], LutInterpolation::Tetrahedral);
+        for (value, expected) in graded.iter().zip([0.8, 0.6, 0.4]) {
+            assert!((value - expected).abs() < 1e-3, "{:?}", graded);
+        }
+
+        assert!(library.import(&dir.join("missing.grade.json")).is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_thumbnails() -> Result<()> {
+        let dir = create_test_dir("grading_presets", "thumbnails")?;
+        let cube = invert_cube(&dir)?;
+        let library = PresetLibrary::new(&dir.join("presets"));
+        library.save(&preset("Invert", Some(cube)))?;
+        library.save(&preset("Plain", None))?;
+
+        let data = (0..64 * 36).flat_map(|i| [(i % 256) as u8, 128, 64, 255]).collect();
+        let reference = ReferenceFrame::new(data, 64, 36)?;
+        assert!(ReferenceFrame::new(vec![0; 10], 64, 36).is_err());
+
+        let png = library.thumbnail(&library.load("Invert")?, &reference, 32)?;
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // The width and height in the header chunk
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into()?), 32);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into()?), 18);
+
+        assert!(library.list()?.iter().all(|summary| summary.thumbnail.is_none()));
+        assert_eq!(library.generate_thumbnails(&reference, 32)?, 2);
+        for summary in library.list()? {
+            assert!(summary.thumbnail.is_some_and(|path| path.exists()));
+        }
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Default hardware acceleration for this machine
+//!
+//! At startup the engine checks which hardware video APIs this platform offers, in the
+//! order they are usually best: VideoToolbox on macOS, then NVENC, VA-API and Quick Sync
+//! elsewhere. GStreamer's NVIDIA and VA plugins only register their elements when a
+//! usable device is present, so an element in the registry means the hardware is there.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::{debug, info};
+
+use super::settings::HardwareAcceleration;
+
+/// A hardware video API, by the GStreamer elements that use it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwareApi {
+    VideoToolbox,
+    Nvenc,
+    QuickSync,
+    Vaapi,
+}
+
+impl HardwareApi {
+    pub const ALL: [HardwareApi; 4] = [
+        HardwareApi::VideoToolbox,
+        HardwareApi::Nvenc,
+        HardwareApi::QuickSync,
+        HardwareApi::Vaapi,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HardwareApi::VideoToolbox => "videotoolbox",
+            HardwareApi::Nvenc => "nvenc",
+            HardwareApi::QuickSync => "quicksync",
+            HardwareApi::Vaapi => "vaapi",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|api| api.as_str() == name)
+    }
+
+    /// APIs worth trying on the platform the engine was built for, best first
+    pub fn platform_preference() -> &'static [HardwareApi] {
+        if cfg!(target_os = "macos") {
+            &[HardwareApi::VideoToolbox]
+        } else if cfg!(target_os = "windows") {
+            &[HardwareApi::Nvenc, HardwareApi::QuickSync]
+        } else if cfg!(target_os = "linux") {
+            &[HardwareApi::Nvenc, HardwareApi::Vaapi, HardwareApi::QuickSync]
+        } else {
+            &[]
+        }
+    }
+
+    /// H.264 encoder elements, preferred first
+    pub fn encoder_factories(&self) -> &'static [&'static str] {
+        match self {
+            HardwareApi::VideoToolbox => &["vtenc_h264_hw", "vtenc_h264"],
+            HardwareApi::Nvenc => &["nvh264enc", "nvcudah264enc", "nvd3d11h264enc"],
+            HardwareApi::QuickSync => &["qsvh264enc", "msdkh264enc"],
+            HardwareApi::Vaapi => &["vah264enc", "vah264lpenc", "vaapih264enc"],
+        }
+    }
+
+    /// H.264 decoder elements, preferred first
+    pub fn decoder_factories(&self) -> &'static [&'static str] {
+        match self {
+            HardwareApi::VideoToolbox => &["vtdec_hw", "vtdec"],
+            HardwareApi::Nvenc => &["nvh264dec", "nvh264sldec"],
+            HardwareApi::QuickSync => &["qsvh264dec", "msdkh264dec"],
+            HardwareApi::Vaapi => &["vah264dec", "vaapih264dec"],
+        }
+    }
+}
+
+impl fmt::Display for HardwareApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HardwareApi::VideoToolbox => "VideoToolbox",
+            HardwareApi::Nvenc => "NVENC",
+            HardwareApi::QuickSync => "Quick Sync",
+            HardwareApi::Vaapi => "VA-API",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What the hardware probe chose, and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardwareDefaults {
+    /// Operating system probed, as in `std::env::consts::OS`
+    pub platform: String,
+    /// API and element used for hardware encoding, if any
+    pub encoder: Option<(HardwareApi, String)>,
+    /// API and element used for hardware decoding, if any
+    pub decoder: Option<(HardwareApi, String)>,
+    /// Acceleration setting picked from the above
+    pub acceleration: HardwareAcceleration,
+    /// What was found or missing for each API, and the reason for the choice, in order
+    pub reasons: Vec<String>,
+}
+
+impl HardwareDefaults {
+    /// One line describing the choice, for logs and the settings page
+    pub fn summary(&self) -> String {
+        let describe = |choice: &Option<(HardwareApi, String)>| match choice {
+            Some((api, element)) => format!("{} ({})", api, element),
+            None => "software".to_string(),
+        };
+        format!("Encoding: {}, decoding: {}", describe(&self.encoder), describe(&self.decoder))
+    }
+}
+
+/// Probe this machine through the GStreamer registry
+pub fn probe() -> HardwareDefaults {
+    if let Err(e) = gst::init() {
+        let mut defaults = probe_with(&[], |_| false);
+        defaults.reasons.insert(0, format!("GStreamer failed to initialize: {}", e));
+        return defaults;
+    }
+
+    let defaults = probe_with(HardwareApi::platform_preference(), |factory| gst::ElementFactory::find(factory).is_some());
+    info!("Hardware probe: {}", defaults.summary());
+    defaults
+}
+
+/// Pick defaults from `apis`, best first, with `available` saying which elements exist
+///
+/// The first API with an encoder encodes and the first with a decoder decodes; they
+/// may differ, as with an NVIDIA card that only decodes a codec next to Quick Sync.
+/// A hardware encoder makes acceleration `Always`, a decoder alone `Auto`, and
+/// neither `Never`, so nothing waits on hardware that isn't there.
+pub fn probe_with<F>(apis: &[HardwareApi], available: F) -> HardwareDefaults
+where
+    F: Fn(&str) -> bool,
+{
+    let mut reasons = Vec::new();
+    let mut encoder = None;
+    let mut decoder = None;
+
+    if apis.is_empty() {
+        reasons.push(format!("No hardware video APIs are supported on {}", std::env::consts::OS));
+    }
+
+    for &api in apis {
+        let found_encoder = api.encoder_factories().iter().find(|factory| available(factory));
+        let found_decoder = api.decoder_factories().iter().find(|factory| available(factory));
+        debug!("{}: encoder {:?}, decoder {:?}", api, found_encoder, found_decoder);
+
+        reasons.push(match (found_encoder, found_decoder) {
+            (Some(enc), Some(dec)) => format!("{}: encoder {} and decoder {} available", api, enc, dec),
+            (Some(enc), None) => format!("{}: encoder {} available, no decoder", api, enc),
+            (None, Some(dec)) => format!("{}: decoder {} available, no encoder", api, dec),
+            (None, None) => format!("{}: not available", api),
+        });
+
+        if encoder.is_none() {
+            encoder = found_encoder.map(|factory| (api, factory.to_string()));
+        }
+        if decoder.is_none() {
+            decoder = found_decoder.map(|factory| (api, factory.to_string()));
+        }
+    }
+
+    let acceleration = match (&encoder, &decoder) {
+        (Some((api, _)), _) => {
+            reasons.push(format!("Using hardware encoding and decoding, preferring {}", api));
+            HardwareAcceleration::Always
+        },
+        (None, Some((api, _))) => {
+            reasons.push(format!("Using {} for decoding; encoding stays in software unless a preset asks", api));
+            HardwareAcceleration::Auto
+        },
+        (None, None) => {
+            reasons.push("No hardware encoder or decoder found, using software only".to_string());
+            HardwareAcceleration::Never
+        },
+    };
+
+    HardwareDefaults {
+        platform: std::env::consts::OS.to_string(),
+        encoder,
+        decoder,
+        acceleration,
+        reasons,
+    }
+}
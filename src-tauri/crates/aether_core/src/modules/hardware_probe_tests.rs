@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use super::super::hardware_probe::{self, HardwareApi};
+    use super::super::settings::{EngineSettings, HardwareAcceleration, SettingsStore};
+    use anyhow::Result;
+
+    const LINUX: &[HardwareApi] = &[HardwareApi::Nvenc, HardwareApi::Vaapi, HardwareApi::QuickSync];
+
+    #[test]
+    fn test_probe_picks_best_available_api() {
+        // An Intel machine whose VA driver only decodes
+        let available = ["vah264dec", "qsvh264enc", "qsvh264dec"];
+        let defaults = hardware_probe::probe_with(LINUX, |factory| available.contains(&factory));
+        assert_eq!(defaults.encoder, Some((HardwareApi::QuickSync, "qsvh264enc".to_string())));
+        assert_eq!(defaults.decoder, Some((HardwareApi::Vaapi, "vah264dec".to_string())));
+        assert_eq!(defaults.acceleration, HardwareAcceleration::Always);
+        assert_eq!(defaults.reasons[0], "NVENC: not available");
+        assert_eq!(defaults.reasons[1], "VA-API: decoder vah264dec available, no encoder");
+        assert_eq!(defaults.reasons.len(), 4);
+        assert_eq!(defaults.summary(), "Encoding: Quick Sync (qsvh264enc), decoding: VA-API (vah264dec)");
+
+        // Earlier APIs win, and within one the preferred element
+        let defaults = hardware_probe::probe_with(LINUX, |factory| factory.starts_with("nv") || factory.starts_with("va"));
+        assert_eq!(defaults.encoder, Some((HardwareApi::Nvenc, "nvh264enc".to_string())));
+        assert_eq!(defaults.decoder, Some((HardwareApi::Nvenc, "nvh264dec".to_string())));
+
+        let decode_only = hardware_probe::probe_with(LINUX, |factory| factory == "vaapih264dec");
+        assert_eq!(decode_only.acceleration, HardwareAcceleration::Auto);
+
+        let none = hardware_probe::probe_with(LINUX, |_| false);
+        assert_eq!((none.encoder, none.decoder), (None, None));
+        assert_eq!(none.acceleration, HardwareAcceleration::Never);
+        assert_eq!(hardware_probe::probe_with(&[], |_| true).acceleration, HardwareAcceleration::Never);
+
+        assert!(HardwareApi::ALL.iter().all(|api| HardwareApi::parse(api.as_str()) == Some(*api)));
+    }
+
+    #[test]
+    fn test_probe_sets_acceleration_once() {
+        let nvidia = hardware_probe::probe_with(LINUX, |factory| factory.starts_with("nvh264"));
+        let mut settings = EngineSettings::default();
+        settings.apply_hardware_probe(nvidia.clone());
+        assert_eq!(settings.hardware_acceleration, HardwareAcceleration::Always);
+
+        // A choice made after the first probe survives the next startup
+        settings.hardware_acceleration = HardwareAcceleration::Never;
+        settings.apply_hardware_probe(nvidia);
+        assert_eq!(settings.hardware_acceleration, HardwareAcceleration::Never);
+
+        // Different hardware is probed afresh
+        let software = hardware_probe::probe_with(LINUX, |_| false);
+        settings.hardware_acceleration = HardwareAcceleration::Always;
+        settings.apply_hardware_probe(software.clone());
+        assert_eq!(settings.hardware_acceleration, HardwareAcceleration::Never);
+        assert_eq!(settings.hardware, Some(software));
+    }
+
+    #[test]
+    fn test_detect_hardware_stores_result() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("aether-hardware-{}.toml", std::process::id()));
+        let store = SettingsStore::open(&path)?;
+        let defaults = store.detect_hardware()?;
+        assert_eq!(defaults.platform, std::env::consts::OS);
+        assert!(!defaults.reasons.is_empty());
+        assert_eq!(store.get().hardware, Some(defaults.clone()));
+        assert_eq!(store.get().hardware_acceleration, defaults.acceleration);
+
+        // Reloaded from disk without probing again
+        drop(store);
+        assert_eq!(SettingsStore::open(&path)?.get().hardware, Some(defaults));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,609 @@
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use std::fmt;
+use std::path::Path;
+
+use crate::engine::timeline::{Marker, Timeline};
+use super::analysis_pass::{AnalysisFrame, AnalysisPass, Analyzer};
+
+/// Rate audio is decoded at for analysis
+const ANALYSIS_SAMPLE_RATE: u32 = 8000;
+
+/// Loudness sub-windows per second, used for steadiness and laughter rhythm
+const SUB_WINDOWS_PER_SECOND: usize = 40;
+
+/// Laughter pulses at roughly 4-8 Hz, as lags in sub-windows
+const LAUGHTER_LAGS: std::ops::RangeInclusive<usize> = 5..=10;
+
+/// Size frames are scaled to for scene change and motion measurement
+const FRAME_SIZE: (u32, u32) = (64, 36);
+
+/// Something that made a stretch of a recording stand out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HighlightCue {
+    Loud,
+    SceneChange,
+    Motion,
+    Laughter,
+    Applause,
+}
+
+impl HighlightCue {
+    const ALL: [HighlightCue; 5] = [
+        HighlightCue::Loud,
+        HighlightCue::SceneChange,
+        HighlightCue::Motion,
+        HighlightCue::Laughter,
+        HighlightCue::Applause,
+    ];
+}
+
+impl fmt::Display for HighlightCue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HighlightCue::Loud => "loud",
+            HighlightCue::SceneChange => "scene change",
+            HighlightCue::Motion => "motion",
+            HighlightCue::Laughter => "laughter",
+            HighlightCue::Applause => "applause",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Relative weight of each cue in a segment's score
+#[derive(Debug, Clone)]
+pub struct HighlightWeights {
+    pub loudness: f64,
+    pub scene_change: f64,
+    pub motion: f64,
+    pub laughter: f64,
+    pub applause: f64,
+}
+
+impl Default for HighlightWeights {
+    fn default() -> Self {
+        Self {
+            loudness: 0.3,
+            scene_change: 0.1,
+            motion: 0.2,
+            laughter: 0.2,
+            applause: 0.2,
+        }
+    }
+}
+
+impl HighlightWeights {
+    fn weight(&self, cue: HighlightCue) -> f64 {
+        match cue {
+            HighlightCue::Loud => self.loudness,
+            HighlightCue::SceneChange => self.scene_change,
+            HighlightCue::Motion => self.motion,
+            HighlightCue::Laughter => self.laughter,
+            HighlightCue::Applause => self.applause,
+        }
+    }
+}
+
+/// Options for highlight detection
+#[derive(Debug, Clone)]
+pub struct HighlightOptions {
+    /// Length of the scored segments, in seconds
+    pub segment_duration: f64,
+    /// Shortest suggested highlight, in seconds
+    pub min_duration: f64,
+    /// Longest suggested highlight, in seconds
+    pub max_duration: f64,
+    /// Highlights suggested at most
+    pub max_highlights: usize,
+    /// Minimum smoothed score (0-1) for a highlight
+    pub min_score: f64,
+    /// A highlight grows over neighbouring segments scoring at least this fraction of its peak
+    pub extend_ratio: f64,
+    pub weights: HighlightWeights,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        Self {
+            segment_duration: 1.0,
+            min_duration: 5.0,
+            max_duration: 30.0,
+            max_highlights: 10,
+            min_score: 0.3,
+            extend_ratio: 0.6,
+            weights: HighlightWeights::default(),
+        }
+    }
+}
+
+/// Measurements for one segment of a recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentFeatures {
+    /// Source time in seconds
+    pub start: f64,
+    pub duration: f64,
+    /// RMS level of the audio (0-1)
+    pub loudness: f64,
+    /// Zero crossings per sample; high for noisy sounds like applause
+    pub zero_crossing_rate: f64,
+    /// 0-1, how even the loudness is within the segment
+    pub steadiness: f64,
+    /// 0-1, strength of a 4-8 Hz loudness pulse, typical of laughter
+    pub modulation: f64,
+    /// 0-1 histogram difference to the previous frame
+    pub scene_change: f64,
+    /// 0-1 mean pixel difference to the previous frame
+    pub motion: f64,
+}
+
+/// Per-cue scores (0-1) and their weighted total for a segment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentScore {
+    pub total: f64,
+    pub cues: Vec<(HighlightCue, f64)>,
+}
+
+/// A suggested highlight range in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    /// Source time in seconds
+    pub start: f64,
+    pub end: f64,
+    /// 0-1
+    pub score: f64,
+    /// Cues behind the highlight, strongest first
+    pub cues: Vec<HighlightCue>,
+}
+
+impl Highlight {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// Range marker for the highlight, shifted by `offset` seconds into timeline time
+    pub fn to_marker(&self, id: String, offset: f64) -> Marker {
+        let mut marker = Marker::new(id, offset + self.start, format!("Highlight ({:.0}%)", self.score * 100.0));
+        marker.duration = self.duration();
+        marker.note = self.cues.iter().map(|cue| cue.to_string()).collect::<Vec<_>>().join(", ");
+        marker.color = Some("#ffb000".to_string());
+        marker
+    }
+}
+
+/// Scores segments of long recordings and suggests highlight ranges
+pub struct HighlightDetector {
+    options: HighlightOptions,
+}
+
+impl HighlightDetector {
+    pub fn new(options: HighlightOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn options(&self) -> &HighlightOptions {
+        &self.options
+    }
+
+    /// Suggest highlights for a recording
+    #[tracing::instrument(name = "highlights", skip(self))]
+    pub fn analyze(&self, path: &Path) -> Result<Vec<Highlight>> {
+        let features = self.extract_features(path)?;
+        let highlights = self.detect(&features);
+        info!("Found {} highlights in {:?}", highlights.len(), path);
+        Ok(highlights)
+    }
+
+    /// Measure every segment of a recording
+    ///
+    /// Audio and frames are decoded together in one streamed pass, so recordings of any
+    /// length fit in memory. Files without video get zero scene change and motion.
+    pub fn extract_features(&self, path: &Path) -> Result<Vec<SegmentFeatures>> {
+        let mut analyzer = self.analyzer();
+        AnalysisPass::new()
+            .with_sample_rate(ANALYSIS_SAMPLE_RATE)
+            .with_frame_size(FRAME_SIZE.0, FRAME_SIZE.1)
+            .with_analyzer(&mut analyzer)
+            .run(path)?;
+
+        let features = analyzer.into_features();
+        if features.is_empty() {
+            return Err(anyhow!("Nothing to analyze in {:?}", path));
+        }
+        debug!("Measured {} segments of {:?}", features.len(), path);
+        Ok(features)
+    }
+
+    /// Analyzer measuring segments, for running in an `AnalysisPass` with other analyzers
+    ///
+    /// The pass should decode audio at 8 kHz and frames at 64x36, as `extract_features` does.
+    pub fn analyzer(&self) -> HighlightAnalyzer {
+        let segment_duration = self.options.segment_duration.max(0.1);
+        HighlightAnalyzer {
+            audio: AudioAccumulator::new(ANALYSIS_SAMPLE_RATE, segment_duration),
+            segment_duration,
+            next_frame: 0.0,
+            frames: Vec::new(),
+            features: Vec::new(),
+        }
+    }
+
+    /// Score segments and pick the strongest ranges, in time order
+    pub fn detect(&self, features: &[SegmentFeatures]) -> Vec<Highlight> {
+        let scores = score_segments(features, &self.options.weights);
+        select_highlights(features, &scores, &self.options)
+    }
+}
+
+/// Score each segment relative to the rest of the recording
+pub fn score_segments(features: &[SegmentFeatures], weights: &HighlightWeights) -> Vec<SegmentScore> {
+    if features.is_empty() {
+        return Vec::new();
+    }
+
+    // Loudness counts relative to the recording's typical level
+    let levels: Vec<f64> = features.iter().map(|f| 20.0 * (f.loudness + 1e-9).log10()).collect();
+    let median_level = percentile(&levels, 0.5);
+    let loud_level = percentile(&levels, 0.95);
+    let level_range = (loud_level - median_level).max(3.0);
+    let typical_motion = percentile(&features.iter().map(|f| f.motion).collect::<Vec<_>>(), 0.95).max(0.02);
+
+    let total_weight: f64 = HighlightCue::ALL.iter().map(|cue| weights.weight(*cue)).sum::<f64>().max(f64::EPSILON);
+
+    features
+        .iter()
+        .zip(&levels)
+        .map(|(segment, level)| {
+            let loud = ((level - median_level) / level_range).clamp(0.0, 1.0);
+            let noisy = ((segment.zero_crossing_rate - 0.15) / 0.2).clamp(0.0, 1.0);
+
+            let cues = vec![
+                (HighlightCue::Loud, loud),
+                (HighlightCue::SceneChange, (segment.scene_change / 0.5).clamp(0.0, 1.0)),
+                (HighlightCue::Motion, (segment.motion / typical_motion).clamp(0.0, 1.0)),
+                (HighlightCue::Laughter, segment.modulation.clamp(0.0, 1.0) * loud),
+                (HighlightCue::Applause, noisy * segment.steadiness.clamp(0.0, 1.0) * loud),
+            ];
+            let total = cues.iter().map(|(cue, value)| weights.weight(*cue) * value).sum::<f64>() / total_weight;
+
+            SegmentScore { total, cues }
+        })
+        .collect()
+}
+
+/// Grow ranges around the best scoring segments, best first, then sort them by time
+pub fn select_highlights(features: &[SegmentFeatures], scores: &[SegmentScore], options: &HighlightOptions) -> Vec<Highlight> {
+    let count = features.len().min(scores.len());
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let segment_duration = options.segment_duration.max(0.1);
+    let min_segments = ((options.min_duration / segment_duration).round() as usize).clamp(1, count);
+    let max_segments = ((options.max_duration / segment_duration).round() as usize).max(min_segments);
+
+    // Smooth over the shortest highlight so single spikes don't win
+    let radius = min_segments / 2;
+    let smoothed: Vec<f64> = (0..count)
+        .map(|i| {
+            let window = &scores[i.saturating_sub(radius)..(i + radius + 1).min(count)];
+            window.iter().map(|s| s.total).sum::<f64>() / window.len() as f64
+        })
+        .collect();
+
+    // Only segments clearly above the recording's baseline qualify
+    let mean = smoothed.iter().sum::<f64>() / count as f64;
+    let deviation = (smoothed.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count as f64).sqrt();
+    let threshold = options.min_score.max(mean + deviation);
+
+    let mut used = vec![false; count];
+    let mut highlights = Vec::new();
+    while highlights.len() < options.max_highlights {
+        let Some(peak) = (0..count)
+            .filter(|&i| !used[i] && smoothed[i] >= threshold)
+            .max_by(|&a, &b| smoothed[a].total_cmp(&smoothed[b]))
+        else {
+            break;
+        };
+
+        let floor = smoothed[peak] * options.extend_ratio;
+        let (mut first, mut last) = (peak, peak);
+        while last - first + 1 < max_segments {
+            let left = (first > 0 && !used[first - 1] && smoothed[first - 1] >= floor).then(|| smoothed[first - 1]);
+            let right = (last + 1 < count && !used[last + 1] && smoothed[last + 1] >= floor).then(|| smoothed[last + 1]);
+            match (left, right) {
+                (Some(l), Some(r)) if l >= r => first -= 1,
+                (_, Some(_)) => last += 1,
+                (Some(_), None) => first -= 1,
+                (None, None) => break,
+            }
+        }
+
+        // Pad short ranges to the minimum length where free
+        while last - first + 1 < min_segments {
+            if last + 1 < count && !used[last + 1] {
+                last += 1;
+            } else if first > 0 && !used[first - 1] {
+                first -= 1;
+            } else {
+                break;
+            }
+        }
+
+        for flag in &mut used[first..=last] {
+            *flag = true;
+        }
+
+        let range = &scores[first..=last];
+        let score = range.iter().map(|s| s.total).sum::<f64>() / range.len() as f64;
+        let mut cues: Vec<(HighlightCue, f64)> = HighlightCue::ALL
+            .iter()
+            .map(|cue| {
+                let value = range.iter()
+                    .filter_map(|s| s.cues.iter().find(|(c, _)| c == cue).map(|(_, v)| *v))
+                    .fold(0.0, f64::max);
+                (*cue, value)
+            })
+            .filter(|(_, value)| *value >= 0.5)
+            .collect();
+        cues.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        highlights.push(Highlight {
+            start: features[first].start,
+            end: features[last].start + features[last].duration,
+            score,
+            cues: cues.into_iter().map(|(cue, _)| cue).collect(),
+        });
+    }
+
+    highlights.sort_by(|a, b| a.start.total_cmp(&b.start));
+    highlights
+}
+
+/// Add highlights in a clip's source as markers at the clip's timeline position
+///
+/// Highlights outside the clip's trimmed range are skipped. Returns the marker IDs.
+pub fn add_highlight_markers(timeline: &mut Timeline, clip_id: &str, highlights: &[Highlight]) -> Result<Vec<String>> {
+    let clip = timeline
+        .tracks()
+        .values()
+        .flat_map(|track| track.clips.iter())
+        .find(|clip| clip.id == clip_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("Clip not found: {}", clip_id))?;
+
+    let offset = clip.start_time - clip.in_point();
+    let mut ids = Vec::new();
+    for (index, highlight) in highlights.iter().enumerate() {
+        if highlight.end <= clip.in_point() || highlight.start >= clip.in_point() + clip.duration {
+            continue;
+        }
+
+        let mut marker = highlight.to_marker(format!("highlight_{}_{}", clip_id, index + 1), offset);
+        // Clamp to the part of the clip that is on the timeline
+        let end = (marker.time + marker.duration).min(clip.end_time());
+        marker.time = marker.time.max(clip.start_time);
+        marker.duration = end - marker.time;
+
+        ids.push(marker.id.clone());
+        timeline.add_marker(marker)?;
+    }
+    Ok(ids)
+}
+
+/// Collects segment features from an analysis pass
+pub struct HighlightAnalyzer {
+    audio: AudioAccumulator,
+    segment_duration: f64,
+    /// Time of the next frame to sample
+    next_frame: f64,
+    /// Luma of one frame per segment, with its time
+    frames: Vec<(f64, Vec<u8>)>,
+    features: Vec<SegmentFeatures>,
+}
+
+impl HighlightAnalyzer {
+    /// Features of every segment, after the pass
+    pub fn into_features(self) -> Vec<SegmentFeatures> {
+        self.features
+    }
+}
+
+impl Analyzer for HighlightAnalyzer {
+    fn wants_audio(&self) -> bool {
+        true
+    }
+
+    fn wants_video(&self) -> bool {
+        true
+    }
+
+    fn audio(&mut self, samples: &[f32], _sample_rate: u32) {
+        self.audio.push(samples);
+    }
+
+    fn video(&mut self, frame: &AnalysisFrame) {
+        if frame.timestamp + 0.001 < self.next_frame {
+            return;
+        }
+        self.frames.push((frame.timestamp, frame.luma()));
+        self.next_frame = frame.timestamp + self.segment_duration;
+    }
+
+    fn finish(&mut self) {
+        let audio = std::mem::replace(&mut self.audio, AudioAccumulator::new(ANALYSIS_SAMPLE_RATE, self.segment_duration));
+        let mut features = audio.finish();
+
+        // Extend to the video length for recordings whose audio ends early
+        if let Some((last, _)) = self.frames.last() {
+            let segments = (last / self.segment_duration) as usize + 1;
+            while features.len() < segments {
+                features.push(SegmentFeatures {
+                    start: features.len() as f64 * self.segment_duration,
+                    duration: self.segment_duration,
+                    ..SegmentFeatures::default()
+                });
+            }
+        }
+
+        for pair in self.frames.windows(2) {
+            let (previous, (timestamp, luma)) = (&pair[0].1, &pair[1]);
+            let index = (timestamp / self.segment_duration) as usize;
+            if let Some(segment) = features.get_mut(index) {
+                segment.scene_change = histogram_distance(previous, luma);
+                segment.motion = mean_difference(previous, luma);
+            }
+        }
+        if self.frames.is_empty() {
+            warn!("No video cues: no frames decoded");
+        }
+
+        self.frames.clear();
+        self.features = features;
+    }
+}
+
+/// Collects per-segment audio measurements from streamed samples
+struct AudioAccumulator {
+    segment_len: usize,
+    sub_len: usize,
+    segment_duration: f64,
+    sum_squares: f64,
+    count: usize,
+    crossings: usize,
+    last_sample: f32,
+    sub_sum_squares: f64,
+    sub_count: usize,
+    sub_levels: Vec<f64>,
+    segments: Vec<SegmentFeatures>,
+}
+
+impl AudioAccumulator {
+    fn new(sample_rate: u32, segment_duration: f64) -> Self {
+        Self {
+            segment_len: ((sample_rate as f64 * segment_duration) as usize).max(1),
+            sub_len: (sample_rate as usize / SUB_WINDOWS_PER_SECOND).max(1),
+            segment_duration,
+            sum_squares: 0.0,
+            count: 0,
+            crossings: 0,
+            last_sample: 0.0,
+            sub_sum_squares: 0.0,
+            sub_count: 0,
+            sub_levels: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let square = (sample as f64).powi(2);
+            self.sum_squares += square;
+            self.sub_sum_squares += square;
+            if (sample >= 0.0) != (self.last_sample >= 0.0) {
+                self.crossings += 1;
+            }
+            self.last_sample = sample;
+            self.count += 1;
+            self.sub_count += 1;
+
+            if self.sub_count == self.sub_len {
+                self.sub_levels.push((self.sub_sum_squares / self.sub_len as f64).sqrt());
+                self.sub_sum_squares = 0.0;
+                self.sub_count = 0;
+            }
+            if self.count == self.segment_len {
+                self.finish_segment();
+            }
+        }
+    }
+
+    fn finish_segment(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+
+        self.segments.push(SegmentFeatures {
+            start: self.segments.len() as f64 * self.segment_duration,
+            duration: self.segment_duration,
+            loudness: (self.sum_squares / self.count as f64).sqrt(),
+            zero_crossing_rate: self.crossings as f64 / self.count as f64,
+            steadiness: steadiness(&self.sub_levels),
+            modulation: laughter_modulation(&self.sub_levels),
+            ..SegmentFeatures::default()
+        });
+
+        self.sum_squares = 0.0;
+        self.count = 0;
+        self.crossings = 0;
+        self.sub_sum_squares = 0.0;
+        self.sub_count = 0;
+        self.sub_levels.clear();
+    }
+
+    /// Features of every segment, including a trailing partial one
+    fn finish(mut self) -> Vec<SegmentFeatures> {
+        self.finish_segment();
+        self.segments
+    }
+}
+
+/// 1 minus the coefficient of variation of the sub-window levels
+fn steadiness(levels: &[f64]) -> f64 {
+    if levels.is_empty() {
+        return 0.0;
+    }
+    let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    let deviation = (levels.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / levels.len() as f64).sqrt();
+    (1.0 - deviation / mean).clamp(0.0, 1.0)
+}
+
+/// Strongest normalized autocorrelation of the loudness envelope at laughter rates
+fn laughter_modulation(levels: &[f64]) -> f64 {
+    if levels.len() <= *LAUGHTER_LAGS.end() {
+        return 0.0;
+    }
+    let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+    let centered: Vec<f64> = levels.iter().map(|l| l - mean).collect();
+    let energy: f64 = centered.iter().map(|c| c * c).sum();
+    if energy <= 0.0 {
+        return 0.0;
+    }
+
+    LAUGHTER_LAGS
+        .map(|lag| centered.iter().zip(&centered[lag..]).map(|(a, b)| a * b).sum::<f64>() / energy)
+        .fold(0.0, f64::max)
+        .clamp(0.0, 1.0)
+}
+
+/// Half the L1 distance between 16-bin luma histograms (0-1)
+pub(crate) fn histogram_distance(a: &[u8], b: &[u8]) -> f64 {
+    let histogram = |pixels: &[u8]| {
+        let mut bins = [0.0f64; 16];
+        for pixel in pixels {
+            bins[(*pixel >> 4) as usize] += 1.0;
+        }
+        let total = pixels.len().max(1) as f64;
+        bins.map(|count| count / total)
+    };
+    let (a, b) = (histogram(a), histogram(b));
+    a.iter().zip(&b).map(|(x, y)| (x - y).abs()).sum::<f64>() / 2.0
+}
+
+/// Mean absolute pixel difference (0-1)
+fn mean_difference(a: &[u8], b: &[u8]) -> f64 {
+    let total: u64 = a.iter().zip(b).map(|(x, y)| x.abs_diff(*y) as u64).sum();
+    total as f64 / (a.len().min(b.len()).max(1) as f64 * 255.0)
+}
+
+fn percentile(values: &[f64], fraction: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
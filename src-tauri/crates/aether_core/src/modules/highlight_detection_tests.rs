@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use super::super::highlight_detection::{
+        add_highlight_markers, HighlightCue, HighlightDetector, HighlightOptions, SegmentFeatures,
+    };
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use anyhow::Result;
+
+    /// A quiet two minute talk with a burst of laughter at 50-57s
+    fn recording() -> Vec<SegmentFeatures> {
+        (0..120)
+            .map(|i| {
+                let laughing = (50..58).contains(&i);
+                SegmentFeatures {
+                    start: i as f64,
+                    duration: 1.0,
+                    loudness: if laughing { 0.5 } else { 0.05 + (i % 3) as f64 * 0.005 },
+                    zero_crossing_rate: 0.08,
+                    steadiness: 0.3,
+                    modulation: if laughing { 0.8 } else { 0.1 },
+                    scene_change: 0.02,
+                    motion: 0.01,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_laughter_burst() -> Result<()> {
+        let detector = HighlightDetector::new(HighlightOptions::default());
+        let highlights = detector.detect(&recording());
+
+        assert_eq!(highlights.len(), 1);
+        let highlight = &highlights[0];
+        assert!(highlight.start >= 45.0 && highlight.start <= 50.0, "start {}", highlight.start);
+        assert!(highlight.end >= 58.0 && highlight.end <= 63.0, "end {}", highlight.end);
+        assert!(highlight.cues.contains(&HighlightCue::Laughter));
+        assert!(highlight.cues.contains(&HighlightCue::Loud));
+        assert!(!highlight.cues.contains(&HighlightCue::Applause));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flat_recording_has_no_highlights() -> Result<()> {
+        let flat: Vec<SegmentFeatures> = (0..60)
+            .map(|i| SegmentFeatures { start: i as f64, duration: 1.0, loudness: 0.1, ..SegmentFeatures::default() })
+            .collect();
+
+        assert!(HighlightDetector::new(HighlightOptions::default()).detect(&flat).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_markers_follow_clip_position() -> Result<()> {
+        let highlights = HighlightDetector::new(HighlightOptions::default()).detect(&recording());
+
+        let mut timeline = Timeline::new(TimelineConfig { fps: 30, duration: 300.0 });
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string()))?;
+        let mut clip = Clip::new("talk".to_string(), ClipType::Video, 100.0, 60.0);
+        clip.set_in_point(30.0);
+        timeline.add_clip_to_track("video", clip)?;
+
+        let ids = add_highlight_markers(&mut timeline, "talk", &highlights)?;
+        assert_eq!(ids.len(), 1);
+        let marker = &timeline.markers()[0];
+        assert!((marker.time - (highlights[0].start + 70.0)).abs() < 1e-9);
+        assert!((marker.duration - highlights[0].duration()).abs() < 1e-9);
+        assert!(marker.note.contains("laughter"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1285 @@
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use crate::engine::timeline::{Clip, ClipType, Timeline};
+use super::camera_card::{self, locate_segment, CameraClip, ClipSegment, Timecode};
+use super::clip_log::ClipLog;
+use super::face_detection::FaceAnalysis;
+use super::file_manager::{MediaInfo, MediaType};
+use super::media_usage::{media_usage, ClipUsage};
+use super::scene_classification::SceneLabel;
+use super::transcription::Transcript;
+
+/// Number of bytes hashed from the head and tail of a file
+const FINGERPRINT_CHUNK: u64 = 64 * 1024;
+
+/// Number of chunks, spread through the file, compared before linking a duplicate
+const SAMPLE_CHUNKS: u64 = 16;
+
+/// Identity of a media file that survives moves and renames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MediaFingerprint {
+    /// File size in bytes
+    pub size: u64,
+    /// Inode number (Unix only)
+    pub inode: Option<u64>,
+    /// Hash of the first and last chunks of the file
+    pub content_hash: u64,
+}
+
+impl MediaFingerprint {
+    /// Compute the fingerprint of a file
+    pub fn compute(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+
+        let mut file = File::open(path)?;
+        let mut hash = FNV_OFFSET;
+        let mut buffer = vec![0u8; FINGERPRINT_CHUNK as usize];
+
+        // Head
+        let read = read_up_to(&mut file, &mut buffer)?;
+        hash = fnv1a(hash, &buffer[..read]);
+
+        // Tail, if it does not overlap the head
+        if size > FINGERPRINT_CHUNK * 2 {
+            file.seek(SeekFrom::End(-(FINGERPRINT_CHUNK as i64)))?;
+            let read = read_up_to(&mut file, &mut buffer)?;
+            hash = fnv1a(hash, &buffer[..read]);
+        }
+
+        hash = fnv1a(hash, &size.to_le_bytes());
+
+        Ok(Self {
+            size,
+            inode: inode_of(&metadata),
+            content_hash: hash,
+        })
+    }
+
+    /// Whether two fingerprints describe the same content
+    ///
+    /// The inode is ignored because copies across volumes get new inodes.
+    pub fn same_content(&self, other: &MediaFingerprint) -> bool {
+        self.size == other.size && self.content_hash == other.content_hash
+    }
+
+    /// Hash of chunks spread evenly through a file, head and tail included
+    ///
+    /// Reads more than `compute`, so it is only used to confirm a duplicate that the
+    /// fingerprint already matched. Small files are hashed whole.
+    pub fn sampled_hash(path: &Path) -> Result<u64> {
+        let size = fs::metadata(path)?.len();
+        let mut file = File::open(path)?;
+        let mut hash = FNV_OFFSET;
+        let mut buffer = vec![0u8; FINGERPRINT_CHUNK as usize];
+
+        let offsets: Vec<u64> = if size <= FINGERPRINT_CHUNK * SAMPLE_CHUNKS {
+            (0..size.div_ceil(FINGERPRINT_CHUNK)).map(|chunk| chunk * FINGERPRINT_CHUNK).collect()
+        } else {
+            let last = size - FINGERPRINT_CHUNK;
+            (0..SAMPLE_CHUNKS).map(|chunk| last * chunk / (SAMPLE_CHUNKS - 1)).collect()
+        };
+
+        for offset in offsets {
+            file.seek(SeekFrom::Start(offset))?;
+            let read = read_up_to(&mut file, &mut buffer)?;
+            hash = fnv1a(hash, &buffer[..read]);
+        }
+
+        Ok(fnv1a(hash, &size.to_le_bytes()))
+    }
+}
+
+/// What `MediaLibrary::add_asset` does with a file whose content is already in the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DuplicatePolicy {
+    /// Return the existing asset and remember the file as another copy of it
+    #[default]
+    Link,
+    /// Add the file as an asset of its own, e.g. to keep a backup as a replacement
+    Keep,
+}
+
+/// A media asset in the library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaAsset {
+    /// Stable asset ID
+    pub id: String,
+    /// Path relative to the project root, or absolute if outside it
+    pub stored_path: PathBuf,
+    /// Fingerprint captured at import time
+    pub fingerprint: MediaFingerprint,
+    /// Probed media information
+    pub info: Option<MediaInfo>,
+    /// Whether the media could not be found on the last scan
+    pub offline: bool,
+    /// Labels used for search and smart bins
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Face detection results, if the asset was analyzed
+    #[serde(default)]
+    pub faces: Option<FaceAnalysis>,
+    /// Scene classification labels, most likely first
+    #[serde(default)]
+    pub labels: Vec<SceneLabel>,
+    /// Speech transcript, if the asset was transcribed
+    #[serde(default)]
+    pub transcript: Option<Transcript>,
+    /// Rating, notes, keywords and selects logged by the editor
+    #[serde(default)]
+    pub log: ClipLog,
+    /// For sub-clips, the range of the parent's media they play
+    #[serde(default)]
+    pub subclip: Option<Subclip>,
+    /// For recordings imported from a camera card, their files and timecode
+    #[serde(default)]
+    pub camera: Option<CameraMedia>,
+    /// Other files with the same content, stored like `stored_path`; a rescan tries them
+    /// first when the media goes missing
+    #[serde(default)]
+    pub copies: Vec<PathBuf>,
+}
+
+impl MediaAsset {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Add a tag unless the asset already has it
+    pub fn add_tag(&mut self, tag: &str) {
+        if !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| !t.eq_ignore_ascii_case(tag));
+    }
+
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|l| l.label.eq_ignore_ascii_case(label))
+    }
+
+    /// ID of the asset whose media file this asset plays
+    pub fn media_id(&self) -> &str {
+        self.subclip.as_ref().map_or(&self.id, |subclip| &subclip.parent)
+    }
+
+    /// Name shown in bins: a sub-clip's name, or the media file's name
+    pub fn name(&self) -> String {
+        match &self.subclip {
+            Some(subclip) => subclip.name.clone(),
+            None => self.stored_path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        }
+    }
+}
+
+/// A camera recording, which may span several files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraMedia {
+    /// Files in recording order, stored like `MediaAsset::stored_path`; the first is
+    /// the asset's own file
+    pub segments: Vec<ClipSegment>,
+    /// Timecode of the first frame, when the card records one
+    pub start_timecode: Option<Timecode>,
+}
+
+impl CameraMedia {
+    /// Timecode `time` seconds into the recording, running on across segments
+    pub fn timecode_at(&self, time: f64) -> Option<Timecode> {
+        self.start_timecode.map(|start| start.offset(time))
+    }
+}
+
+/// A named range of another asset's media, shown in bins as an asset of its own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subclip {
+    /// Asset with the media file
+    pub parent: String,
+    pub name: String,
+    /// Source range in seconds
+    pub in_point: f64,
+    pub out_point: f64,
+}
+
+/// Condition selecting the assets of a smart bin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BinRule {
+    /// Assets with a tag
+    Tag(String),
+    /// Assets with at least this many faces in one frame
+    MinFaces(usize),
+    /// Assets with a scene label
+    Label(String),
+    /// Assets rated at least this high
+    MinRating(u8),
+}
+
+impl BinRule {
+    pub fn matches(&self, asset: &MediaAsset) -> bool {
+        match self {
+            BinRule::Tag(tag) => asset.has_tag(tag),
+            BinRule::MinFaces(count) => asset.faces.as_ref().map_or(0, |faces| faces.max_faces()) >= *count,
+            BinRule::Label(label) => asset.has_label(label),
+            BinRule::MinRating(rating) => asset.log.rating.is_some_and(|r| r >= *rating),
+        }
+    }
+}
+
+/// Order of the assets listed in a bin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinSort {
+    /// The order they were added in
+    Added,
+    Name,
+    /// Reel, scene, shot and take, unlogged clips last
+    Slate,
+}
+
+/// A bin (folder) organizing assets in the library
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bin {
+    /// Stable bin ID
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// Parent bin, or `None` for a top-level bin
+    pub parent: Option<String>,
+    /// Assets in this bin
+    pub asset_ids: Vec<String>,
+    /// For smart bins, the rule selecting their assets instead of `asset_ids`
+    #[serde(default)]
+    pub rule: Option<BinRule>,
+}
+
+/// A relinked asset found during a rescan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelinkEvent {
+    /// Asset ID
+    pub asset_id: String,
+    /// Previous absolute path
+    pub old_path: PathBuf,
+    /// New absolute path
+    pub new_path: PathBuf,
+}
+
+/// Result of a library rescan
+#[derive(Debug, Clone, Default)]
+pub struct RescanReport {
+    /// Assets that were found at a new location
+    pub relinked: Vec<RelinkEvent>,
+    /// Assets that could not be found
+    pub offline: Vec<String>,
+}
+
+/// What `MediaLibrary::remove_asset` does with an asset clips still use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovePolicy {
+    /// Fail without removing anything
+    Refuse,
+    /// Keep the asset and report its clips and possible replacements
+    Warn,
+    /// Remove it anyway, leaving its clips offline
+    Force,
+}
+
+/// Result of `MediaLibrary::remove_asset`
+#[derive(Debug, Clone)]
+pub enum RemoveOutcome {
+    /// The asset was removed from the library
+    Removed(Box<MediaAsset>),
+    /// The asset is still used and was kept
+    InUse {
+        asset_id: String,
+        /// Clips using the asset
+        clips: Vec<ClipUsage>,
+        /// Assets the clips could be moved to with `replace_asset`, best first
+        replacements: Vec<String>,
+    },
+}
+
+/// Library of media assets referenced by a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaLibrary {
+    /// Project root that relative paths are resolved against
+    project_root: PathBuf,
+    /// Assets by ID
+    assets: HashMap<String, MediaAsset>,
+    /// Next asset ID
+    next_id: u64,
+    /// Bins organizing the assets
+    #[serde(default)]
+    bins: Vec<Bin>,
+    /// How files already in the library under another path are added
+    #[serde(default)]
+    duplicate_policy: DuplicatePolicy,
+    /// Store every path relative to the project root with `/` separators, for projects
+    /// synced between machines
+    #[serde(default)]
+    portable_paths: bool,
+}
+
+impl MediaLibrary {
+    /// Create an empty library for a project root
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            project_root: project_root.to_path_buf(),
+            assets: HashMap::new(),
+            next_id: 1,
+            bins: Vec::new(),
+            duplicate_policy: DuplicatePolicy::default(),
+            portable_paths: false,
+        }
+    }
+
+    /// Project root
+    pub fn project_root(&self) -> &Path {
+        &self.project_root
+    }
+
+    /// Change the project root; project-relative paths follow the project
+    pub fn set_project_root(&mut self, project_root: &Path) {
+        self.project_root = project_root.to_path_buf();
+    }
+
+    /// How files whose content is already in the library are added
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// Set how files whose content is already in the library are added
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    /// Whether paths are stored in the portable form
+    pub fn portable_paths(&self) -> bool {
+        self.portable_paths
+    }
+
+    /// Store paths relative to the project root even outside it, with `/` separators
+    ///
+    /// Portable paths are resolved with either separator, and a file or directory whose
+    /// name only differs in case is used when the exact one is missing. A project in a
+    /// Dropbox or Syncthing folder then opens on Windows, macOS and Linux alike, wherever
+    /// the folder is synced to. Existing assets are rewritten to the new form.
+    pub fn set_portable_paths(&mut self, portable: bool) {
+        if portable == self.portable_paths {
+            return;
+        }
+
+        let resolved: Vec<(String, PathBuf, Vec<PathBuf>)> = self.assets.values()
+            .map(|asset| {
+                let copies = asset.copies.iter().map(|copy| self.resolve_stored(copy)).collect();
+                (asset.id.clone(), self.resolve(asset), copies)
+            })
+            .collect();
+
+        self.portable_paths = portable;
+        for (id, path, copies) in resolved {
+            let stored_path = self.to_stored_path(&path);
+            let copies = copies.iter().map(|copy| self.to_stored_path(copy)).collect();
+            if let Some(asset) = self.assets.get_mut(&id) {
+                asset.stored_path = stored_path;
+                asset.copies = copies;
+            }
+        }
+    }
+
+    /// Add a media file to the library, returning its asset ID
+    ///
+    /// Under `DuplicatePolicy::Link`, a copy of media already in the library, such as the
+    /// same clip from a second card dump, returns the existing asset. An offline asset is
+    /// relinked to the copy.
+    pub fn add_asset(&mut self, path: &Path, info: Option<MediaInfo>) -> Result<String> {
+        if !path.is_file() {
+            return Err(anyhow!("Media file does not exist: {:?}", path));
+        }
+
+        let absolute = absolute_path(path)?;
+
+        // Reuse the existing asset if the file is already in the library
+        if let Some(existing) = self.find_by_path(&absolute) {
+            return Ok(existing.id.clone());
+        }
+
+        let fingerprint = MediaFingerprint::compute(&absolute)?;
+        if self.duplicate_policy == DuplicatePolicy::Link {
+            if let Some((id, online)) = self.find_duplicate(&absolute, &fingerprint)? {
+                if online {
+                    let stored_path = self.to_stored_path(&absolute);
+                    let asset = self.assets.get_mut(&id).unwrap();
+                    if !asset.copies.contains(&stored_path) {
+                        asset.copies.push(stored_path);
+                    }
+                    if asset.info.is_none() {
+                        asset.info = info;
+                    }
+                    info!("{:?} is a copy of asset {}, linked to it", absolute, id);
+                } else {
+                    self.relink(&id, &absolute)?;
+                    info!("{:?} is a copy of offline asset {}, relinked to it", absolute, id);
+                }
+                return Ok(id);
+            }
+        }
+
+        let id = format!("asset_{}", self.next_id);
+        self.next_id += 1;
+
+        let asset = MediaAsset {
+            id: id.clone(),
+            stored_path: self.to_stored_path(&absolute),
+            fingerprint,
+            info,
+            offline: false,
+            tags: Vec::new(),
+            faces: None,
+            labels: Vec::new(),
+            transcript: None,
+            log: ClipLog::default(),
+            subclip: None,
+            camera: None,
+            copies: Vec::new(),
+        };
+
+        debug!("Added asset {} for {:?}", id, absolute);
+        self.assets.insert(id.clone(), asset);
+        Ok(id)
+    }
+
+    /// Add a camera recording as one asset, returning its ID
+    ///
+    /// The asset's file is the first segment, and the duration in `info` that of all
+    /// segments together.
+    pub fn add_camera_clip(&mut self, clip: &CameraClip, info: Option<MediaInfo>) -> Result<String> {
+        let first = clip.segments.first().ok_or_else(|| anyhow!("Camera clip {} has no files", clip.name))?;
+        let id = self.add_asset(&first.path, info)?;
+
+        // The same recording from another dump of the card keeps its first files
+        if self.assets[&id].camera.is_some() {
+            return Ok(id);
+        }
+
+        let mut segments = Vec::with_capacity(clip.segments.len());
+        for segment in &clip.segments {
+            segments.push(ClipSegment {
+                path: self.to_stored_path(&absolute_path(&segment.path)?),
+                duration: segment.duration,
+            });
+        }
+        let asset = self.assets.get_mut(&id).unwrap();
+        if let Some(info) = asset.info.as_mut().filter(|_| clip.is_spanned()) {
+            info.duration = Some(clip.duration());
+        }
+        asset.camera = Some(CameraMedia { segments, start_timecode: clip.start_timecode });
+        debug!("Added camera clip {} as {} ({} files)", clip.name, id, clip.segments.len());
+        Ok(id)
+    }
+
+    /// Add every recording on the camera card at `root`, returning the asset IDs
+    pub fn import_camera_card(&mut self, root: &Path) -> Result<Vec<String>> {
+        let card = camera_card::scan_camera_card(root)?.ok_or_else(|| anyhow!("Not a camera card: {:?}", root))?;
+        let ids = card.clips.iter().map(|clip| self.add_camera_clip(clip, None)).collect::<Result<Vec<_>>>()?;
+        info!("Imported {} clips from {:?} card at {:?}", ids.len(), card.format, root);
+        Ok(ids)
+    }
+
+    /// File playing `time` seconds into an asset's media, and the time into that file
+    ///
+    /// Recordings spanning several files are played across them; sub-clips take the
+    /// time in their parent's media.
+    pub fn locate(&self, id: &str, time: f64) -> Result<(PathBuf, f64)> {
+        let asset = self.assets.get(id).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        let segments = asset.camera.as_ref().map_or(&[][..], |camera| camera.segments.as_slice());
+        match locate_segment(segments, time) {
+            Some((segment, offset)) if segments.len() > 1 => Ok((self.resolve_stored(&segment.path), offset)),
+            _ => Ok((self.resolve(asset), time)),
+        }
+    }
+
+    /// Get an asset by ID
+    pub fn get_asset(&self, id: &str) -> Option<&MediaAsset> {
+        self.assets.get(id)
+    }
+
+    /// Get a mutable asset by ID
+    pub fn get_asset_mut(&mut self, id: &str) -> Option<&mut MediaAsset> {
+        self.assets.get_mut(id)
+    }
+
+    /// All assets, sorted by ID
+    pub fn assets(&self) -> Vec<&MediaAsset> {
+        let mut assets: Vec<&MediaAsset> = self.assets.values().collect();
+        assets.sort_by(|a, b| a.id.cmp(&b.id));
+        assets
+    }
+
+    /// Find an asset by its absolute path; sub-clips are never returned
+    pub fn find_by_path(&self, path: &Path) -> Option<&MediaAsset> {
+        self.assets.values().find(|asset| asset.subclip.is_none() && self.resolve(asset) == path)
+    }
+
+    /// Asset with the same content as the file at `path`, and whether its media is online
+    ///
+    /// Size and fingerprint pick the candidates, which are then confirmed by sampling
+    /// chunks through both files. Offline candidates can't be sampled, so the fingerprint
+    /// alone decides, as it does for `rescan`.
+    fn find_duplicate(&self, path: &Path, fingerprint: &MediaFingerprint) -> Result<Option<(String, bool)>> {
+        let mut sampled = None;
+        for asset in self.assets() {
+            if asset.subclip.is_some() || !asset.fingerprint.same_content(fingerprint) {
+                continue;
+            }
+
+            let existing = self.resolve(asset);
+            if !existing.is_file() {
+                return Ok(Some((asset.id.clone(), false)));
+            }
+
+            let hash = match sampled {
+                Some(hash) => hash,
+                None => *sampled.insert(MediaFingerprint::sampled_hash(path)?),
+            };
+            if MediaFingerprint::sampled_hash(&existing).is_ok_and(|existing| existing == hash) {
+                return Ok(Some((asset.id.clone(), true)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Groups of assets with the same content, such as copies kept under
+    /// `DuplicatePolicy::Keep`, each sorted by ID
+    pub fn duplicate_groups(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<(MediaFingerprint, Vec<String>)> = Vec::new();
+        for asset in self.assets().into_iter().filter(|asset| asset.subclip.is_none()) {
+            match groups.iter_mut().find(|(fingerprint, _)| fingerprint.same_content(&asset.fingerprint)) {
+                Some((_, ids)) => ids.push(asset.id.clone()),
+                None => groups.push((asset.fingerprint, vec![asset.id.clone()])),
+            }
+        }
+        groups.into_iter().map(|(_, ids)| ids).filter(|ids| ids.len() > 1).collect()
+    }
+
+    /// Find the asset a clip's source path refers to
+    ///
+    /// Relative sources are resolved against the project root.
+    pub fn find_by_source(&self, source: &Path) -> Option<&MediaAsset> {
+        self.find_by_path(&self.resolve_stored(source))
+    }
+
+    /// Rewrite a timeline's clip sources to the form the library stores paths in, before
+    /// the timeline is saved with the project; returns the number of clips changed
+    ///
+    /// With portable paths on, sources become relative to the project root.
+    pub fn store_clip_sources(&self, timeline: &mut Timeline) -> Result<usize> {
+        self.rewrite_clip_sources(timeline, |source| {
+            let path = Path::new(source);
+            Some(self.to_stored_path(&normalize(path))).filter(|stored| path.is_absolute() && stored != path)
+        })
+    }
+
+    /// Rewrite clip sources saved by `store_clip_sources` to absolute paths on this
+    /// machine, after the project is opened; returns the number of clips changed
+    pub fn resolve_clip_sources(&self, timeline: &mut Timeline) -> Result<usize> {
+        self.rewrite_clip_sources(timeline, |source| {
+            let path = Path::new(source);
+            (!path.is_absolute()).then(|| self.resolve_stored(path))
+        })
+    }
+
+    /// Point clips at the new locations of media relinked by `rescan`; returns the number
+    /// of clips changed
+    ///
+    /// Sources stored relative to the project root stay relative.
+    pub fn relink_clip_sources(&self, timeline: &mut Timeline, relinked: &[RelinkEvent]) -> Result<usize> {
+        self.rewrite_clip_sources(timeline, |source| {
+            let path = Path::new(source);
+            let resolved = self.resolve_stored(path);
+            let event = relinked.iter().find(|event| normalize(&event.old_path) == resolved)?;
+            Some(if path.is_absolute() {
+                event.new_path.clone()
+            } else {
+                self.to_stored_path(&event.new_path)
+            })
+        })
+    }
+
+    fn rewrite_clip_sources<F>(&self, timeline: &mut Timeline, rewrite: F) -> Result<usize>
+    where
+        F: Fn(&str) -> Option<PathBuf>,
+    {
+        let mut changed = 0;
+        let track_ids: Vec<String> = timeline.tracks().keys().cloned().collect();
+        for track_id in track_ids {
+            let track = timeline.get_track_mut(&track_id).map_err(|e| anyhow!("{}", e))?;
+            for clip in track.clips.iter_mut() {
+                let rewritten = clip.source_path.as_deref().and_then(&rewrite);
+                if let Some(path) = rewritten {
+                    clip.source_path = Some(path.to_string_lossy().to_string());
+                    changed += 1;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Resolve an asset's absolute path
+    pub fn resolve_path(&self, id: &str) -> Result<PathBuf> {
+        self.assets
+            .get(id)
+            .map(|asset| self.resolve(asset))
+            .ok_or_else(|| anyhow!("Asset not found: {}", id))
+    }
+
+    /// Replace an asset's scene labels
+    pub fn set_labels(&mut self, id: &str, labels: Vec<SceneLabel>) -> Result<()> {
+        let asset = self.assets.get_mut(id).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        asset.labels = labels;
+        Ok(())
+    }
+
+    /// Set or clear an asset's transcript
+    pub fn set_transcript(&mut self, id: &str, transcript: Option<Transcript>) -> Result<()> {
+        let asset = self.assets.get_mut(id).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        asset.transcript = transcript;
+        Ok(())
+    }
+
+    /// Logging metadata of an asset, for editing
+    pub fn log_mut(&mut self, id: &str) -> Result<&mut ClipLog> {
+        self.assets.get_mut(id).map(|asset| &mut asset.log).ok_or_else(|| anyhow!("Asset not found: {}", id))
+    }
+
+    /// Assets matching every word of `query` by scene label, tag or file name, sorted by ID
+    pub fn search(&self, query: &str) -> Vec<&MediaAsset> {
+        let terms: Vec<String> = query.split_whitespace().map(|term| term.to_lowercase()).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        self.assets()
+            .into_iter()
+            .filter(|asset| {
+                let name = match &asset.subclip {
+                    Some(subclip) => subclip.name.to_lowercase(),
+                    None => asset.stored_path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default(),
+                };
+                terms.iter().all(|term| {
+                    asset.labels.iter().any(|l| l.label.to_lowercase().contains(term.as_str()))
+                        || asset.tags.iter().any(|t| t.to_lowercase().contains(term.as_str()))
+                        || asset.log.keywords.iter().any(|k| k.to_lowercase().contains(term.as_str()))
+                        || name.contains(term.as_str())
+                })
+            })
+            .collect()
+    }
+
+    /// Point an asset at a new file
+    pub fn relink(&mut self, id: &str, path: &Path) -> Result<()> {
+        let absolute = absolute_path(path)?;
+        let stored_path = self.to_stored_path(&absolute);
+        let fingerprint = MediaFingerprint::compute(&absolute)?;
+
+        if !self.assets.contains_key(id) {
+            return Err(anyhow!("Asset not found: {}", id));
+        }
+
+        // Sub-clips follow their parent's media
+        for asset in self.assets.values_mut().filter(|asset| asset.id == id || asset.media_id() == id) {
+            asset.stored_path = stored_path.clone();
+            asset.fingerprint = fingerprint;
+            asset.offline = false;
+        }
+        Ok(())
+    }
+
+    /// Create a sub-clip playing `in_point`..`out_point` of an asset's media, returning its ID
+    ///
+    /// The range is in source time; a sub-clip of a sub-clip plays the same media file. The
+    /// sub-clip is put in every regular bin holding the asset, and `notes` become its log notes.
+    pub fn create_subclip(&mut self, asset_id: &str, name: &str, in_point: f64, out_point: f64, notes: &str) -> Result<String> {
+        let source = self.assets.get(asset_id).ok_or_else(|| anyhow!("Asset not found: {}", asset_id))?;
+        let parent = self.assets.get(source.media_id()).ok_or_else(|| anyhow!("Asset not found: {}", source.media_id()))?;
+
+        let duration = parent.info.as_ref().and_then(|info| info.duration);
+        if !(in_point >= 0.0 && out_point > in_point) || duration.is_some_and(|duration| out_point > duration + 1e-6) {
+            return Err(anyhow!("Invalid sub-clip range {:.3}s-{:.3}s for {}", in_point, out_point, parent.id));
+        }
+
+        let id = format!("asset_{}", self.next_id);
+        self.next_id += 1;
+
+        let mut subclip = MediaAsset {
+            id: id.clone(),
+            stored_path: parent.stored_path.clone(),
+            fingerprint: parent.fingerprint,
+            info: parent.info.clone(),
+            offline: parent.offline,
+            tags: parent.tags.clone(),
+            faces: None,
+            labels: parent.labels.clone(),
+            transcript: None,
+            // Part of the same take, so the same slate
+            log: ClipLog { slate: parent.log.slate.clone(), ..Default::default() },
+            subclip: Some(Subclip {
+                parent: parent.id.clone(),
+                name: name.to_string(),
+                in_point,
+                out_point,
+            }),
+            camera: parent.camera.clone(),
+            copies: Vec::new(),
+        };
+        if let Some(info) = subclip.info.as_mut() {
+            info.duration = Some(out_point - in_point);
+        }
+        subclip.log.notes = notes.to_string();
+
+        for bin in self.bins.iter_mut().filter(|bin| bin.rule.is_none()) {
+            if bin.asset_ids.iter().any(|id| id == asset_id) {
+                bin.asset_ids.push(id.clone());
+            }
+        }
+
+        debug!("Created sub-clip {} of {} ({:.3}s-{:.3}s)", id, parent.id, in_point, out_point);
+        self.assets.insert(id.clone(), subclip);
+        Ok(id)
+    }
+
+    /// Sub-clips of an asset, sorted by ID
+    pub fn subclips(&self, asset_id: &str) -> Vec<&MediaAsset> {
+        self.assets()
+            .into_iter()
+            .filter(|asset| asset.subclip.as_ref().is_some_and(|subclip| subclip.parent == asset_id))
+            .collect()
+    }
+
+    /// Timeline clip playing an asset, as when it is dragged from a bin
+    ///
+    /// Sub-clips play their range; other assets play from the start of their media for its
+    /// probed duration. The asset's slate goes with the clip in its properties.
+    pub fn to_clip(&self, asset_id: &str, clip_id: &str, start_time: f64) -> Result<Clip> {
+        let asset = self.assets.get(asset_id).ok_or_else(|| anyhow!("Asset not found: {}", asset_id))?;
+        let (in_point, duration) = match &asset.subclip {
+            Some(subclip) => (subclip.in_point, subclip.out_point - subclip.in_point),
+            None => {
+                let duration = asset.info.as_ref()
+                    .and_then(|info| info.duration)
+                    .ok_or_else(|| anyhow!("Asset {} has no known duration", asset_id))?;
+                (0.0, duration)
+            },
+        };
+        let clip_type = match asset.info.as_ref().map(|info| info.media_type) {
+            Some(MediaType::Audio) => ClipType::Audio,
+            Some(MediaType::Image) => ClipType::Image,
+            _ => ClipType::Video,
+        };
+
+        let mut clip = Clip::new(clip_id.to_string(), clip_type, start_time, duration)
+            .with_source(self.resolve(asset).to_string_lossy().to_string());
+        clip.set_in_point(in_point);
+        asset.log.slate.apply_to(&mut clip);
+        Ok(clip)
+    }
+
+    /// Remove the assets no clip in `timelines` uses, returning them
+    ///
+    /// The media files are left on disk.
+    pub fn remove_unused(&mut self, timelines: &[&Timeline]) -> Vec<MediaAsset> {
+        media_usage(self, timelines)
+            .unused
+            .iter()
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+
+    /// Remove the asset at `path` from the library, checking that no clip in `timelines`
+    /// still uses it
+    ///
+    /// Relative paths are resolved against the project root. The media file is left on disk.
+    pub fn remove_asset(&mut self, path: &Path, timelines: &[&Timeline], policy: RemovePolicy) -> Result<RemoveOutcome> {
+        let id = self
+            .find_by_source(path)
+            .map(|asset| asset.id.clone())
+            .ok_or_else(|| anyhow!("No asset for {:?}", path))?;
+
+        let clips = media_usage(self, timelines)
+            .used
+            .remove(&id)
+            .map(|usage| usage.clips)
+            .unwrap_or_default();
+
+        if !clips.is_empty() {
+            match policy {
+                RemovePolicy::Refuse => {
+                    return Err(anyhow!("Asset {} is used by {} clips, not removed", id, clips.len()));
+                },
+                RemovePolicy::Warn => {
+                    warn!("Asset {} is used by {} clips, not removed", id, clips.len());
+                    let replacements = self.replacement_candidates(&id);
+                    return Ok(RemoveOutcome::InUse { asset_id: id, clips, replacements });
+                },
+                RemovePolicy::Force => {
+                    warn!("Removing asset {} used by {} clips; they will be offline", id, clips.len());
+                },
+            }
+        }
+
+        let asset = self.remove(&id).ok_or_else(|| anyhow!("Asset not found: {}", id))?;
+        Ok(RemoveOutcome::Removed(Box::new(asset)))
+    }
+
+    /// Other assets that could stand in for an asset, best first
+    ///
+    /// Copies of the same content come first, then files with the same name, then media of
+    /// the same type and duration.
+    pub fn replacement_candidates(&self, id: &str) -> Vec<String> {
+        let Some(asset) = self.assets.get(id) else { return Vec::new() };
+        let file_name = asset.stored_path.file_name();
+
+        let mut candidates: Vec<(u8, &MediaAsset)> = self
+            .assets()
+            .into_iter()
+            .filter(|other| other.id != id && other.subclip.is_none() && !other.offline)
+            .filter_map(|other| {
+                let rank = if other.fingerprint.same_content(&asset.fingerprint) {
+                    0
+                } else if file_name.is_some() && other.stored_path.file_name() == file_name {
+                    1
+                } else if same_kind_and_duration(asset, other) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, other))
+            })
+            .collect();
+
+        // `assets()` is sorted by ID and the sort is stable, so ties stay in ID order
+        candidates.sort_by_key(|(rank, _)| *rank);
+        candidates.into_iter().map(|(_, other)| other.id.clone()).collect()
+    }
+
+    /// Point every clip using asset `id` at asset `replacement`, returning the number of
+    /// clips changed
+    ///
+    /// In points are kept, so the replacement should have the same timing.
+    pub fn replace_asset(&self, id: &str, replacement: &str, timelines: &mut [&mut Timeline]) -> Result<usize> {
+        let new_source = self.resolve_path(replacement)?.to_string_lossy().to_string();
+        let usage = {
+            let views: Vec<&Timeline> = timelines.iter().map(|timeline| &**timeline).collect();
+            media_usage(self, &views).used.remove(id)
+        };
+        let Some(usage) = usage else { return Ok(0) };
+
+        for clip in &usage.clips {
+            let track = timelines[clip.timeline].get_track_mut(&clip.track_id)?;
+            if let Some(target) = track.clips.iter_mut().find(|c| c.id == clip.clip_id) {
+                target.source_path = Some(new_source.clone());
+            }
+        }
+
+        info!("Moved {} clips from asset {} to {}", usage.clips.len(), id, replacement);
+        Ok(usage.clips.len())
+    }
+
+    /// Remove an asset and its sub-clips and take them out of every bin
+    fn remove(&mut self, id: &str) -> Option<MediaAsset> {
+        let asset = self.assets.remove(id)?;
+        let subclips: Vec<String> = self.subclips(id).into_iter().map(|subclip| subclip.id.clone()).collect();
+        for subclip in &subclips {
+            self.assets.remove(subclip);
+        }
+        for bin in &mut self.bins {
+            bin.asset_ids.retain(|asset_id| asset_id != id && !subclips.contains(asset_id));
+        }
+        debug!("Removed asset {} ({:?}) and {} sub-clips", id, asset.stored_path, subclips.len());
+        Some(asset)
+    }
+
+    /// Create a bin, returning its ID
+    pub fn create_bin(&mut self, name: &str, parent: Option<&str>) -> Result<String> {
+        self.insert_bin(name, parent, None)
+    }
+
+    /// Create a smart bin whose contents follow `rule`, returning its ID
+    pub fn create_smart_bin(&mut self, name: &str, parent: Option<&str>, rule: BinRule) -> Result<String> {
+        self.insert_bin(name, parent, Some(rule))
+    }
+
+    fn insert_bin(&mut self, name: &str, parent: Option<&str>, rule: Option<BinRule>) -> Result<String> {
+        if let Some(parent) = parent {
+            if !self.bins.iter().any(|bin| bin.id == parent) {
+                return Err(anyhow!("Bin not found: {}", parent));
+            }
+        }
+
+        let id = format!("bin_{}", self.next_id);
+        self.next_id += 1;
+
+        self.bins.push(Bin {
+            id: id.clone(),
+            name: name.to_string(),
+            parent: parent.map(|p| p.to_string()),
+            asset_ids: Vec::new(),
+            rule,
+        });
+        Ok(id)
+    }
+
+    /// Put an asset into a bin
+    pub fn add_to_bin(&mut self, bin_id: &str, asset_id: &str) -> Result<()> {
+        if !self.assets.contains_key(asset_id) {
+            return Err(anyhow!("Asset not found: {}", asset_id));
+        }
+
+        let bin = self
+            .bins
+            .iter_mut()
+            .find(|bin| bin.id == bin_id)
+            .ok_or_else(|| anyhow!("Bin not found: {}", bin_id))?;
+        if bin.rule.is_some() {
+            return Err(anyhow!("Bin {} is a smart bin, its contents follow its rule", bin_id));
+        }
+        if !bin.asset_ids.iter().any(|id| id == asset_id) {
+            bin.asset_ids.push(asset_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// All bins
+    pub fn bins(&self) -> &[Bin] {
+        &self.bins
+    }
+
+    /// IDs of the assets in a bin, evaluating the rule of smart bins
+    pub fn bin_assets(&self, bin_id: &str) -> Result<Vec<String>> {
+        let bin = self
+            .bins
+            .iter()
+            .find(|bin| bin.id == bin_id)
+            .ok_or_else(|| anyhow!("Bin not found: {}", bin_id))?;
+
+        Ok(match &bin.rule {
+            Some(rule) => self.assets()
+                .into_iter()
+                .filter(|asset| rule.matches(asset))
+                .map(|asset| asset.id.clone())
+                .collect(),
+            None => bin.asset_ids.clone(),
+        })
+    }
+
+    /// IDs of the assets in a bin in `sort` order, ties keeping the bin's order
+    pub fn bin_assets_sorted(&self, bin_id: &str, sort: BinSort) -> Result<Vec<String>> {
+        let mut assets: Vec<&MediaAsset> = self.bin_assets(bin_id)?
+            .iter()
+            .filter_map(|id| self.assets.get(id))
+            .collect();
+        match sort {
+            BinSort::Added => {},
+            BinSort::Name => assets.sort_by_cached_key(|asset| asset.name().to_lowercase()),
+            BinSort::Slate => assets.sort_by(|a, b| a.log.slate.cmp_slate(&b.log.slate)),
+        }
+        Ok(assets.into_iter().map(|asset| asset.id.clone()).collect())
+    }
+
+    /// Check every asset and relink moved or renamed files found under `search_roots`
+    ///
+    /// Candidates are matched by inode first (cheap, catches renames on the same volume)
+    /// and then by content fingerprint (catches copies to other volumes). Copies recorded
+    /// when a duplicate was imported are tried before the search roots. Pass the report's
+    /// `relinked` events to `relink_clip_sources` so clips follow their media.
+    pub fn rescan(&mut self, search_roots: &[PathBuf]) -> Result<RescanReport> {
+        let mut report = RescanReport::default();
+
+        // Find assets whose file is missing or has different content
+        let mut missing = Vec::new();
+        let mut intact = Vec::new();
+        for asset in self.assets.values() {
+            let path = self.resolve(asset);
+            let unchanged = path.is_file()
+                && MediaFingerprint::compute(&path)
+                    .map(|fp| fp.same_content(&asset.fingerprint))
+                    .unwrap_or(false);
+            if unchanged {
+                intact.push(asset.id.clone());
+            } else {
+                missing.push(asset.id.clone());
+            }
+        }
+
+        // Files back at their own path are online again, whatever else is missing
+        for id in &intact {
+            if let Some(asset) = self.assets.get_mut(id) {
+                asset.offline = false;
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(report);
+        }
+
+        // Index candidate files by size, the cheapest discriminator
+        let mut candidates: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for root in search_roots {
+            collect_candidates(root, &mut candidates)?;
+        }
+
+        for id in missing {
+            let (old_path, fingerprint, copies) = {
+                let asset = &self.assets[&id];
+                let copies: Vec<PathBuf> = asset.copies.iter().map(|copy| self.resolve_stored(copy)).collect();
+                (self.resolve(asset), asset.fingerprint, copies)
+            };
+
+            // Known copies first, then anything of the right size under the roots
+            let found = find_match(&copies, &fingerprint).or_else(|| {
+                candidates
+                    .get(&fingerprint.size)
+                    .and_then(|paths| find_match(paths, &fingerprint))
+            });
+
+            match found {
+                Some(new_path) => {
+                    info!("Relinked {} from {:?} to {:?}", id, old_path, new_path);
+                    let stored_path = self.to_stored_path(&new_path);
+                    // Same content, but the inode may be new
+                    let fingerprint = MediaFingerprint::compute(&new_path).unwrap_or(fingerprint);
+                    if let Some(asset) = self.assets.get_mut(&id) {
+                        asset.copies.retain(|copy| *copy != stored_path);
+                        asset.stored_path = stored_path;
+                        asset.fingerprint = fingerprint;
+                        asset.offline = false;
+                        if let Some(info) = asset.info.as_mut() {
+                            info.path = new_path.clone();
+                        }
+                    }
+                    report.relinked.push(RelinkEvent {
+                        asset_id: id,
+                        old_path,
+                        new_path,
+                    });
+                },
+                None => {
+                    warn!("Media for {} is offline: {:?}", id, old_path);
+                    if let Some(asset) = self.assets.get_mut(&id) {
+                        asset.offline = true;
+                    }
+                    report.offline.push(id);
+                },
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Resolve a stored path against the project root
+    fn resolve(&self, asset: &MediaAsset) -> PathBuf {
+        self.resolve_stored(&asset.stored_path)
+    }
+
+    fn resolve_stored(&self, stored_path: &Path) -> PathBuf {
+        if stored_path.is_absolute() {
+            normalize(stored_path)
+        } else if self.portable_paths {
+            resolve_portable(&self.project_root, stored_path)
+        } else {
+            normalize(&self.project_root.join(stored_path))
+        }
+    }
+
+    /// Store paths inside the project root relative to it, and with portable paths on,
+    /// those outside it too
+    pub(crate) fn to_stored_path(&self, absolute: &Path) -> PathBuf {
+        if self.portable_paths {
+            if let Some(relative) = portable_relative(absolute, &self.project_root) {
+                return relative;
+            }
+        }
+        match absolute.strip_prefix(&self.project_root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => absolute.to_path_buf(),
+        }
+    }
+}
+
+/// Whether two assets are the same kind of media with the same known duration
+fn same_kind_and_duration(a: &MediaAsset, b: &MediaAsset) -> bool {
+    match (&a.info, &b.info) {
+        (Some(a), Some(b)) => match (a.duration, b.duration) {
+            (Some(da), Some(db)) => a.media_type == b.media_type && (da - db).abs() < 0.01,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Make a path absolute without requiring it to exist
+fn absolute_path(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(normalize(path))
+    } else {
+        Ok(normalize(&std::env::current_dir()?.join(path)))
+    }
+}
+
+/// Remove `.` and `..` components lexically
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// `absolute` relative to `root`, `/`-separated and climbing out of `root` with `..`
+///
+/// `None` when the two have no root in common, as on different Windows drives.
+fn portable_relative(absolute: &Path, root: &Path) -> Option<PathBuf> {
+    let (absolute, root) = (normalize(absolute), normalize(root));
+    let path: Vec<Component> = absolute.components().collect();
+    let root: Vec<Component> = root.components().collect();
+    let common = path.iter().zip(&root).take_while(|(a, b)| a == b).count();
+    if common == 0 || !path.first().is_some_and(|first| matches!(first, Component::Prefix(_) | Component::RootDir)) {
+        return None;
+    }
+
+    let parts: Vec<String> = std::iter::repeat("..".to_string())
+        .take(root.len() - common)
+        .chain(path[common..].iter().map(|part| part.as_os_str().to_string_lossy().to_string()))
+        .collect();
+    Some(PathBuf::from(parts.join("/")))
+}
+
+/// Resolve a portable path against `root`
+///
+/// Either separator splits components. A component with no exact match on disk uses
+/// an entry whose name only differs in case, as left by a case-insensitive file system.
+fn resolve_portable(root: &Path, stored_path: &Path) -> PathBuf {
+    let stored = stored_path.to_string_lossy();
+    let mut resolved = normalize(root);
+    for part in stored.split(['/', '\\']) {
+        match part {
+            "" | "." => (),
+            ".." => {
+                resolved.pop();
+            },
+            name => resolved = join_ignoring_case(&resolved, name),
+        }
+    }
+    resolved
+}
+
+/// `dir` joined with `name`, or with an existing entry whose name only differs in case
+fn join_ignoring_case(dir: &Path, name: &str) -> PathBuf {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return exact;
+    }
+
+    let lower = name.to_lowercase();
+    fs::read_dir(dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name())
+                .find(|entry| entry.to_string_lossy().to_lowercase() == lower)
+        })
+        .map(|entry| dir.join(entry))
+        .unwrap_or(exact)
+}
+
+/// Recursively collect files by size
+fn collect_candidates(root: &Path, candidates: &mut HashMap<u64, Vec<PathBuf>>) -> Result<()> {
+    if !root.is_dir() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_candidates(&path, candidates)?;
+        } else if file_type.is_file() {
+            let size = entry.metadata()?.len();
+            candidates.entry(size).or_default().push(normalize(&path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the candidate matching a fingerprint, preferring inode matches
+///
+/// Inodes are reused once a file is deleted, so an inode match must still have the
+/// same content.
+fn find_match(paths: &[PathBuf], fingerprint: &MediaFingerprint) -> Option<PathBuf> {
+    let same_content = |path: &PathBuf| {
+        MediaFingerprint::compute(path)
+            .map(|fp| fp.same_content(fingerprint))
+            .unwrap_or(false)
+    };
+
+    if fingerprint.inode.is_some() {
+        let by_inode = paths.iter().find(|path| {
+            fs::metadata(path).is_ok_and(|metadata| inode_of(&metadata) == fingerprint.inode) && same_content(path)
+        });
+        if let Some(path) = by_inode {
+            return Some(path.clone());
+        }
+    }
+
+    paths.iter().find(|path| same_content(path)).cloned()
+}
+
+#[cfg(unix)]
+fn inode_of(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode_of(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Read until the buffer is full or the file ends
+fn read_up_to(file: &mut File, buffer: &mut [u8]) -> Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        let read = file.read(&mut buffer[total..])?;
+        if read == 0 {
+            break;
+        }
+        total += read;
+    }
+    Ok(total)
+}
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a, used because the result is persisted and must be stable across builds
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
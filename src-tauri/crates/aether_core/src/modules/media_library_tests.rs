@@ -0,0 +1,424 @@
+#[cfg(test)]
+mod tests {
+    use super::super::face_detection::{FaceAnalysis, FaceBox, FaceSample, PEOPLE_TAG};
+    use super::super::file_manager::{MediaInfo, MediaType};
+    use super::super::media_library::{BinRule, DuplicatePolicy, MediaFingerprint, MediaLibrary};
+    use super::super::media_usage::media_usage;
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use super::super::scene_classification::SceneLabel;
+    use super::super::test_utils::create_test_dir;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::fs;
+    use std::io::Write;
+    use anyhow::Result;
+
+    // Helper function to create a file with content
+    fn create_file(path: &PathBuf, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(path)?;
+        file.write_all(content)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_stored_relative_to_project() -> Result<()> {
+        let root = create_test_dir("library", "relative")?;
+        let media = root.join("media").join("clip.mp4");
+        create_file(&media, b"clip data")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+
+        let asset = library.get_asset(&id).unwrap();
+        assert_eq!(asset.stored_path, PathBuf::from("media").join("clip.mp4"));
+        assert_eq!(library.resolve_path(&id)?, media);
+
+        // Adding the same file again returns the same asset
+        assert_eq!(library.add_asset(&media, None)?, id);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescan_relinks_renamed_file() -> Result<()> {
+        let root = create_test_dir("library", "rename")?;
+        let media = root.join("media").join("interview.mov");
+        create_file(&media, b"interview take 1")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+
+        // Rename the file
+        let renamed = root.join("media").join("interview_final.mov");
+        fs::rename(&media, &renamed)?;
+
+        let report = library.rescan(&[root.clone()])?;
+        assert_eq!(report.relinked.len(), 1);
+        assert!(report.offline.is_empty());
+        assert_eq!(library.resolve_path(&id)?, renamed);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescan_ignores_reused_inode_with_other_content() -> Result<()> {
+        let root = create_test_dir("library", "inode")?;
+        let media = root.join("media").join("take.mov");
+        create_file(&media, b"take one data")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+
+        // Same inode and size, but rewritten with another take
+        fs::write(&media, b"take two data")?;
+        let rewritten = root.join("media").join("other.mov");
+        fs::rename(&media, &rewritten)?;
+        let report = library.rescan(&[root.clone()])?;
+        assert!(report.relinked.is_empty());
+        assert_eq!(report.offline, vec![id.clone()]);
+
+        // A copy with the original content is found instead
+        let backup = root.join("backup").join("take.mov");
+        create_file(&backup, b"take one data")?;
+        let report = library.rescan(&[root.clone()])?;
+        assert_eq!(report.relinked.len(), 1);
+        assert_eq!(library.resolve_path(&id)?, backup);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescan_marks_missing_offline() -> Result<()> {
+        let root = create_test_dir("library", "offline")?;
+        let media = root.join("gone.wav");
+        create_file(&media, b"audio")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+        fs::remove_file(&media)?;
+
+        let report = library.rescan(&[root.clone()])?;
+        assert_eq!(report.offline, vec![id.clone()]);
+        assert!(library.get_asset(&id).unwrap().offline);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescan_with_one_asset_missing_and_one_back() -> Result<()> {
+        let root = create_test_dir("library", "partial")?;
+        let paths: Vec<PathBuf> = ["moved.mov", "back.mov", "lost.mov"].iter().map(|name| root.join("media").join(name)).collect();
+        for path in &paths {
+            create_file(path, path.file_name().unwrap().to_string_lossy().as_bytes())?;
+        }
+
+        let mut library = MediaLibrary::new(&root);
+        let ids: Vec<String> = paths.iter().map(|path| library.add_asset(path, None)).collect::<Result<_>>()?;
+
+        // Everything but the moved file goes offline
+        let back_content = fs::read(&paths[1])?;
+        fs::remove_file(&paths[1])?;
+        fs::remove_file(&paths[2])?;
+        assert_eq!(library.rescan(&[])?.offline.len(), 2);
+
+        // One comes back at its own path, one is copied elsewhere (new inode), one stays lost
+        create_file(&paths[1], &back_content)?;
+        let moved = root.join("archive").join("moved.mov");
+        create_file(&moved, &fs::read(&paths[0])?)?;
+        fs::remove_file(&paths[0])?;
+
+        let report = library.rescan(&[root.clone()])?;
+        assert_eq!(report.offline, vec![ids[2].clone()]);
+        assert_eq!(report.relinked.len(), 1);
+        assert!(!library.get_asset(&ids[1]).unwrap().offline);
+        assert!(!library.get_asset(&ids[0]).unwrap().offline);
+        assert!(library.get_asset(&ids[2]).unwrap().offline);
+        assert_eq!(library.get_asset(&ids[0]).unwrap().fingerprint, MediaFingerprint::compute(&moved)?);
+
+        // Clips follow, keeping absolute and project-relative sources in their own form
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        let mut track = Track::new("v1".to_string(), "Video 1".to_string());
+        let sources = [paths[0].to_string_lossy().to_string(), "media/moved.mov".to_string(), paths[2].to_string_lossy().to_string()];
+        for (index, source) in sources.iter().enumerate() {
+            track.clips.push(Clip::new(format!("c{}", index), ClipType::Video, index as f64 * 2.0, 2.0).with_source(source.clone()));
+        }
+        timeline.add_track(track)?;
+
+        assert_eq!(library.relink_clip_sources(&mut timeline, &report.relinked)?, 2);
+        let clips = &timeline.get_track("v1")?.clips;
+        assert_eq!(clips[0].source_path.as_deref(), Some(moved.to_string_lossy().as_ref()));
+        assert_eq!(clips[1].source_path.as_deref().map(PathBuf::from), Some(PathBuf::from("archive").join("moved.mov")));
+        assert_eq!(clips[2].source_path.as_deref(), Some(sources[2].as_str()));
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_detects_content() -> Result<()> {
+        let root = create_test_dir("library", "fingerprint")?;
+        let a = root.join("a.bin");
+        let b = root.join("b.bin");
+        create_file(&a, b"same size 1")?;
+        create_file(&b, b"same size 2")?;
+
+        let fa = MediaFingerprint::compute(&a)?;
+        let fb = MediaFingerprint::compute(&b)?;
+        assert_eq!(fa.size, fb.size);
+        assert!(!fa.same_content(&fb));
+        assert!(fa.same_content(&MediaFingerprint::compute(&a)?));
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_smart_bins_follow_tags_and_faces() -> Result<()> {
+        let root = create_test_dir("library", "smart_bins")?;
+        let crowd = root.join("crowd.mp4");
+        let empty = root.join("empty.mp4");
+        create_file(&crowd, b"crowd")?;
+        create_file(&empty, b"empty")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let crowd_id = library.add_asset(&crowd, None)?;
+        let empty_id = library.add_asset(&empty, None)?;
+
+        let people = library.create_smart_bin("Clips with people", None, BinRule::Tag(PEOPLE_TAG.to_string()))?;
+        let groups = library.create_smart_bin("Groups", None, BinRule::MinFaces(2))?;
+        assert!(library.bin_assets(&people)?.is_empty());
+        assert!(library.add_to_bin(&people, &empty_id).is_err());
+
+        let face = FaceBox { x: 0.1, y: 0.1, width: 0.2, height: 0.2, score: 0.9 };
+        let asset = library.get_asset_mut(&crowd_id).unwrap();
+        asset.add_tag(PEOPLE_TAG);
+        asset.faces = Some(FaceAnalysis {
+            samples: vec![FaceSample { timestamp: 0.0, faces: vec![face, FaceBox { x: 0.6, ..face }] }],
+        });
+
+        assert_eq!(library.bin_assets(&people)?, vec![crowd_id.clone()]);
+        assert_eq!(library.bin_assets(&groups)?, vec![crowd_id]);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_by_label_tag_and_name() -> Result<()> {
+        let root = create_test_dir("library", "search")?;
+        let sunset = root.join("sunset_walk.mp4");
+        let dinner = root.join("dinner.mp4");
+        create_file(&sunset, b"sunset")?;
+        create_file(&dinner, b"dinner")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let sunset_id = library.add_asset(&sunset, None)?;
+        let dinner_id = library.add_asset(&dinner, None)?;
+
+        let label = |label: &str| SceneLabel { label: label.to_string(), confidence: 0.8 };
+        library.set_labels(&sunset_id, vec![label("beach"), label("night")])?;
+        library.set_labels(&dinner_id, vec![label("food"), label("indoors")])?;
+        library.get_asset_mut(&dinner_id).unwrap().add_tag("people");
+
+        let ids = |query: &str| library.search(query).iter().map(|asset| asset.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids("Beach"), vec![sunset_id.clone()]);
+        assert_eq!(ids("food people"), vec![dinner_id.clone()]);
+        assert_eq!(ids("sunset night"), vec![sunset_id.clone()]);
+        assert!(ids("beach food").is_empty());
+        assert!(ids("  ").is_empty());
+
+        let night = library.create_smart_bin("Night", None, BinRule::Label("night".to_string()))?;
+        assert_eq!(library.bin_assets(&night)?, vec![sunset_id]);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    fn video_info(path: &Path, duration: f64) -> MediaInfo {
+        MediaInfo {
+            path: path.to_path_buf(),
+            media_type: MediaType::Video,
+            size: 0,
+            duration: Some(duration),
+            width: Some(1920),
+            height: Some(1080),
+            frame_rate: Some(25.0),
+            codec: None,
+            sample_rate: None,
+            channels: None,
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_subclips_are_assets_of_the_same_media() -> Result<()> {
+        let root = create_test_dir("library", "subclips")?;
+        let media = root.join("media").join("interview.mov");
+        create_file(&media, b"interview")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, Some(video_info(&media, 600.0)))?;
+        let bin = library.create_bin("Interviews", None)?;
+        library.add_to_bin(&bin, &id)?;
+
+        let answer = library.create_subclip(&id, "Childhood answer", 65.0, 92.5, "Tears up at the end")?;
+        assert!(library.create_subclip(&id, "Too long", 590.0, 610.0, "").is_err());
+        assert!(library.create_subclip(&id, "Backwards", 20.0, 10.0, "").is_err());
+
+        // Sub-clips of sub-clips use source time and the original media
+        let nested = library.create_subclip(&answer, "Last line", 88.0, 92.5, "")?;
+        assert_eq!(library.get_asset(&nested).unwrap().subclip.as_ref().unwrap().parent, id);
+
+        let subclip = library.get_asset(&answer).unwrap();
+        assert_eq!(subclip.media_id(), id);
+        assert_eq!(subclip.log.notes, "Tears up at the end");
+        assert_eq!(subclip.info.as_ref().unwrap().duration, Some(27.5));
+        assert_eq!(library.resolve_path(&answer)?, media);
+        assert_eq!(library.subclips(&id).len(), 2);
+        assert_eq!(library.bin_assets(&bin)?, vec![id.clone(), answer.clone(), nested.clone()]);
+        assert_eq!(library.search("childhood").len(), 1);
+
+        // The file still maps to the full asset
+        assert_eq!(library.find_by_path(&media).unwrap().id, id);
+        assert_eq!(library.add_asset(&media, None)?, id);
+
+        // Dragging to the timeline plays the sub-clip's range
+        let clip = library.to_clip(&answer, "c1", 10.0)?;
+        assert_eq!(clip.clip_type, ClipType::Video);
+        assert_eq!(clip.source_path, Some(media.to_string_lossy().to_string()));
+        assert_eq!((clip.start_time, clip.in_point(), clip.duration), (10.0, 65.0, 27.5));
+        assert_eq!(library.to_clip(&id, "c2", 0.0)?.duration, 600.0);
+
+        // A timeline using the sub-clip uses the media, so nothing is unused
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        let mut track = Track::new("v1".to_string(), "V1".to_string());
+        track.clips.push(clip);
+        timeline.add_track(track)?;
+        let report = media_usage(&library, &[&timeline]);
+        assert!(report.is_used(&id));
+        assert!(report.unused.is_empty());
+
+        // Relinking and removing the parent carries the sub-clips along
+        let moved = root.join("moved.mov");
+        fs::rename(&media, &moved)?;
+        library.relink(&id, &moved)?;
+        assert_eq!(library.resolve_path(&nested)?, moved);
+        assert_eq!(library.remove_unused(&[]).len(), 1);
+        assert!(library.assets().is_empty());
+        assert!(library.bin_assets(&bin)?.is_empty());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_imports_link_to_one_asset() -> Result<()> {
+        let root = create_test_dir("library", "duplicates")?;
+        let first = root.join("dump1").join("A001.mov");
+        let second = root.join("dump2").join("A001.mov");
+        let other = root.join("dump2").join("A002.mov");
+
+        // Same head, tail and size, differing in the middle, so only sampling tells them apart
+        let footage = vec![7u8; 1024 * 1024];
+        create_file(&first, &footage)?;
+        create_file(&second, &footage)?;
+        let mut changed = footage.clone();
+        changed[512 * 1024] = 8;
+        create_file(&other, &changed)?;
+        assert!(MediaFingerprint::compute(&first)?.same_content(&MediaFingerprint::compute(&other)?));
+        assert_ne!(MediaFingerprint::sampled_hash(&first)?, MediaFingerprint::sampled_hash(&other)?);
+
+        let mut library = MediaLibrary::new(&root);
+        assert_eq!(library.duplicate_policy(), DuplicatePolicy::Link);
+        let id = library.add_asset(&first, None)?;
+        assert_eq!(library.add_asset(&second, None)?, id);
+        let other_id = library.add_asset(&other, None)?;
+        assert_ne!(other_id, id);
+        assert_eq!(library.assets().len(), 2);
+        assert_eq!(library.get_asset(&id).unwrap().copies, vec![PathBuf::from("dump2/A001.mov")]);
+        assert!(library.duplicate_groups().is_empty());
+
+        // Losing the first dump falls back to the copy
+        fs::remove_dir_all(root.join("dump1"))?;
+        let report = library.rescan(&[])?;
+        assert_eq!(report.relinked.len(), 1);
+        assert_eq!(library.resolve_path(&id)?, second);
+        assert!(library.get_asset(&id).unwrap().copies.is_empty());
+
+        // Importing a copy of offline media brings the asset back online
+        let third = root.join("dump3").join("A001.mov");
+        create_file(&third, &footage)?;
+        fs::remove_dir_all(root.join("dump2"))?;
+        library.rescan(&[])?;
+        assert!(library.get_asset(&id).unwrap().offline);
+        assert_eq!(library.add_asset(&third, None)?, id);
+        assert!(!library.get_asset(&id).unwrap().offline);
+        assert_eq!(library.resolve_path(&id)?, third);
+
+        // Kept duplicates are separate assets, reported as a group
+        let mut library = MediaLibrary::new(&root);
+        library.set_duplicate_policy(DuplicatePolicy::Keep);
+        let backup = root.join("backup").join("A001.mov");
+        create_file(&backup, &footage)?;
+        let kept = [library.add_asset(&third, None)?, library.add_asset(&backup, None)?];
+        assert_ne!(kept[0], kept[1]);
+        assert_eq!(library.duplicate_groups(), vec![kept.to_vec()]);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_portable_paths_survive_moves_case_and_separators() -> Result<()> {
+        let sync = create_test_dir("library", "portable")?;
+        let project = sync.join("project");
+        let media = sync.join("Footage").join("Clip.MOV");
+        fs::create_dir_all(&project)?;
+        create_file(&media, b"synced clip")?;
+
+        let mut library = MediaLibrary::new(&project);
+        library.set_portable_paths(true);
+        let id = library.add_asset(&media, Some(video_info(&media, 4.0)))?;
+        assert_eq!(library.get_asset(&id).unwrap().stored_path, PathBuf::from("../Footage/Clip.MOV"));
+
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string()))?;
+        timeline.add_clip_to_track("v1", library.to_clip(&id, "c1", 0.0)?)?;
+        assert_eq!(library.store_clip_sources(&mut timeline)?, 1);
+        assert_eq!(timeline.get_track("v1")?.clips[0].source_path.as_deref(), Some("../Footage/Clip.MOV"));
+
+        // The folder syncs to another place, where the media folder's case changed
+        let saved = serde_json::to_string(&library)?;
+        let moved = sync.with_file_name("portable_synced");
+        if moved.exists() {
+            fs::remove_dir_all(&moved)?;
+        }
+        fs::rename(&sync, &moved)?;
+        fs::rename(moved.join("Footage"), moved.join("footage"))?;
+        let local = moved.join("footage").join("Clip.MOV");
+
+        let mut library: MediaLibrary = serde_json::from_str(&saved)?;
+        library.set_project_root(&moved.join("project"));
+        assert_eq!(library.resolve_path(&id)?, local);
+        assert_eq!(library.resolve_clip_sources(&mut timeline)?, 1);
+        assert_eq!(timeline.get_track("v1")?.clips[0].source_path.as_deref(), Some(local.to_string_lossy().as_ref()));
+
+        // Paths written on Windows resolve too
+        library.get_asset_mut(&id).unwrap().stored_path = PathBuf::from("..\\FOOTAGE\\clip.mov");
+        assert_eq!(library.resolve_path(&id)?, local);
+
+        // Turning the option off stores paths outside the project absolute again
+        library.set_portable_paths(false);
+        assert_eq!(library.get_asset(&id).unwrap().stored_path, local);
+
+        fs::remove_dir_all(moved)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Where the assets of a media library are used in a project's timelines
+//!
+//! The usage report lists every clip using each asset, the source ranges the clips use
+//! and the assets no clip uses, for "remove unused" cleanup and for archiving media
+//! trimmed to what the edit needs.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::engine::timeline::Timeline;
+use super::media_library::MediaLibrary;
+
+/// A clip using a media file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipUsage {
+    /// Index of the clip's timeline in the timelines the report was made from
+    pub timeline: usize,
+    pub track_id: String,
+    pub clip_id: String,
+    /// Position on the timeline, in seconds
+    pub start_time: f64,
+    pub duration: f64,
+    /// Offset into the source media where the clip starts, in seconds
+    pub in_point: f64,
+    /// Offset into the source media where the clip ends, which differs from
+    /// `in_point + duration` for clips with a speed change
+    pub out_point: f64,
+    /// Source path as stored on the clip
+    pub source_path: PathBuf,
+}
+
+impl ClipUsage {
+    /// Source range (start, end) the clip plays, in seconds
+    pub fn source_range(&self) -> (f64, f64) {
+        (self.in_point, self.out_point)
+    }
+}
+
+/// Every use of one asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetUsage {
+    pub asset_id: String,
+    /// Clips using the asset, by timeline, track and position
+    pub clips: Vec<ClipUsage>,
+}
+
+impl AssetUsage {
+    /// Total time the asset is on screen, counting every clip
+    pub fn timeline_duration(&self) -> f64 {
+        self.clips.iter().map(|clip| clip.duration).sum()
+    }
+
+    /// Source ranges used by at least one clip, merged where they overlap or touch
+    pub fn used_ranges(&self) -> Vec<(f64, f64)> {
+        let mut ranges: Vec<(f64, f64)> = self.clips.iter().map(ClipUsage::source_range).collect();
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+
+    /// Seconds of source media used, counting media used by several clips once
+    pub fn used_duration(&self) -> f64 {
+        self.used_ranges().iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// Indices of the timelines using the asset
+    pub fn timelines(&self) -> Vec<usize> {
+        let mut timelines: Vec<usize> = self.clips.iter().map(|clip| clip.timeline).collect();
+        timelines.dedup();
+        timelines
+    }
+}
+
+/// Usage of a library's assets across a set of timelines
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    /// Usage of each asset used at least once, by asset ID
+    pub used: BTreeMap<String, AssetUsage>,
+    /// IDs of assets no clip uses, sorted
+    pub unused: Vec<String>,
+    /// Clips whose source is not in the library
+    pub unknown: Vec<ClipUsage>,
+}
+
+impl UsageReport {
+    /// Usage of an asset, or `None` if it is unused or unknown
+    pub fn usage(&self, asset_id: &str) -> Option<&AssetUsage> {
+        self.used.get(asset_id)
+    }
+
+    pub fn is_used(&self, asset_id: &str) -> bool {
+        self.used.contains_key(asset_id)
+    }
+
+    /// Number of clips using library assets
+    pub fn clip_count(&self) -> usize {
+        self.used.values().map(|usage| usage.clips.len()).sum()
+    }
+
+    /// Total time library assets are on screen across all timelines
+    pub fn timeline_duration(&self) -> f64 {
+        self.used.values().map(AssetUsage::timeline_duration).sum()
+    }
+}
+
+/// Report where each asset of `library` is used in `timelines`
+///
+/// Clip sources are matched to assets by path, with relative paths resolved against
+/// the project root. Clips are counted against the asset with the media file, never
+/// against its sub-clips.
+pub fn media_usage(library: &MediaLibrary, timelines: &[&Timeline]) -> UsageReport {
+    let mut report = UsageReport::default();
+
+    for (index, timeline) in timelines.iter().enumerate() {
+        // Tracks are kept in a map; sort them so the report is stable
+        let mut tracks: Vec<_> = timeline.tracks().values().collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+
+        for track in tracks {
+            let mut clips: Vec<_> = track.clips.iter().collect();
+            clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+            for clip in clips {
+                let Some(source) = &clip.source_path else { continue };
+                let usage = ClipUsage {
+                    timeline: index,
+                    track_id: track.id.clone(),
+                    clip_id: clip.id.clone(),
+                    start_time: clip.start_time,
+                    duration: clip.duration,
+                    in_point: clip.in_point(),
+                    out_point: clip.out_point(),
+                    source_path: PathBuf::from(source),
+                };
+
+                match library.find_by_source(&usage.source_path) {
+                    Some(asset) => report.used
+                        .entry(asset.id.clone())
+                        .or_insert_with(|| AssetUsage { asset_id: asset.id.clone(), clips: Vec::new() })
+                        .clips
+                        .push(usage),
+                    None => report.unknown.push(usage),
+                }
+            }
+        }
+    }
+
+    // Sub-clips are used whenever their media is
+    report.unused = library.assets()
+        .into_iter()
+        .filter(|asset| !report.used.contains_key(asset.media_id()))
+        .map(|asset| asset.id.clone())
+        .collect();
+
+    report
+}
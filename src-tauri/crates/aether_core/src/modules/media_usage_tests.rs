@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod tests {
+    use super::super::media_library::{DuplicatePolicy, MediaLibrary, RemoveOutcome, RemovePolicy};
+    use super::super::media_usage::*;
+    use super::super::test_utils::create_test_dir;
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use anyhow::Result;
+
+    /// Project directory with an empty `media` folder
+    fn create_project_dir(name: &str) -> Result<PathBuf> {
+        let dir = create_test_dir("usage", name)?;
+        fs::create_dir_all(dir.join("media"))?;
+        Ok(dir)
+    }
+
+    fn clip(id: &str, source: &Path, start_time: f64, duration: f64, in_point: f64) -> Clip {
+        let mut clip = Clip::new(id.to_string(), ClipType::Video, start_time, duration)
+            .with_source(source.to_string_lossy().to_string());
+        clip.set_in_point(in_point);
+        clip
+    }
+
+    fn timeline(tracks: Vec<(&str, Vec<Clip>)>) -> Result<Timeline> {
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        for (id, clips) in tracks {
+            let mut track = Track::new(id.to_string(), id.to_string());
+            track.clips = clips;
+            timeline.add_track(track)?;
+        }
+        Ok(timeline)
+    }
+
+    #[test]
+    fn test_usage_across_timelines() -> Result<()> {
+        let root = create_project_dir("usage")?;
+        let mut library = MediaLibrary::new(&root);
+        let mut ids = Vec::new();
+        for name in ["a.mp4", "b.mp4", "c.mp4"] {
+            let path = root.join("media").join(name);
+            fs::write(&path, name)?;
+            ids.push(library.add_asset(&path, None)?);
+        }
+        let a = root.join("media").join("a.mp4");
+
+        // The second clip overlaps the first in the source; the relative source is the same file
+        let edit = timeline(vec![
+            ("v1", vec![clip("c1", &a, 0.0, 4.0, 10.0), clip("c2", Path::new("media/a.mp4"), 4.0, 4.0, 12.0)]),
+            ("v2", vec![clip("c3", &root.join("media").join("b.mp4"), 0.0, 2.0, 0.0)]),
+        ])?;
+        let trailer = timeline(vec![
+            ("v1", vec![clip("t1", &a, 0.0, 1.0, 30.0), clip("t2", &root.join("missing.mp4"), 1.0, 1.0, 0.0)]),
+        ])?;
+
+        let report = media_usage(&library, &[&edit, &trailer]);
+
+        let usage = report.usage(&ids[0]).unwrap();
+        let clips: Vec<&str> = usage.clips.iter().map(|clip| clip.clip_id.as_str()).collect();
+        assert_eq!(clips, vec!["c1", "c2", "t1"]);
+        assert_eq!(usage.timelines(), vec![0, 1]);
+        assert_eq!(usage.used_ranges(), vec![(10.0, 16.0), (30.0, 31.0)]);
+        assert_eq!(usage.used_duration(), 7.0);
+        assert_eq!(usage.timeline_duration(), 9.0);
+
+        assert!(report.is_used(&ids[1]));
+        assert_eq!(report.unused, vec![ids[2].clone()]);
+        assert_eq!(report.clip_count(), 4);
+        assert_eq!(report.timeline_duration(), 11.0);
+
+        assert_eq!(report.unknown.len(), 1);
+        assert_eq!(report.unknown[0].clip_id, "t2");
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_unused() -> Result<()> {
+        let root = create_project_dir("remove_unused")?;
+        let used = root.join("media").join("used.wav");
+        let unused = root.join("media").join("unused.wav");
+        fs::write(&used, b"used")?;
+        fs::write(&unused, b"unused")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let used_id = library.add_asset(&used, None)?;
+        let unused_id = library.add_asset(&unused, None)?;
+        let bin = library.create_bin("Music", None)?;
+        library.add_to_bin(&bin, &used_id)?;
+        library.add_to_bin(&bin, &unused_id)?;
+
+        let edit = timeline(vec![("a1", vec![clip("c1", &used, 0.0, 3.0, 0.0)])])?;
+        let removed = library.remove_unused(&[&edit]);
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, unused_id);
+        assert!(library.get_asset(&unused_id).is_none());
+        assert_eq!(library.bin_assets(&bin)?, vec![used_id]);
+        // Only the library entry goes, not the file
+        assert!(unused.is_file());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_asset_checks_references() -> Result<()> {
+        let root = create_project_dir("remove_asset")?;
+        let take = root.join("media").join("take.mov");
+        let copy = root.join("backup").join("take.mov");
+        let other = root.join("media").join("other.mov");
+        fs::create_dir_all(root.join("backup"))?;
+        fs::write(&take, b"take 1")?;
+        fs::write(&copy, b"take 1")?;
+        fs::write(&other, b"other")?;
+
+        // The backup is kept as an asset of its own to stand in for the take
+        let mut library = MediaLibrary::new(&root);
+        library.set_duplicate_policy(DuplicatePolicy::Keep);
+        let take_id = library.add_asset(&take, None)?;
+        let copy_id = library.add_asset(&copy, None)?;
+        library.add_asset(&other, None)?;
+
+        let mut edit = timeline(vec![("v1", vec![clip("c1", &take, 0.0, 2.0, 1.0), clip("c2", &take, 2.0, 2.0, 5.0)])])?;
+
+        assert!(library.remove_asset(&take, &[&edit], RemovePolicy::Refuse).is_err());
+        assert!(library.get_asset(&take_id).is_some());
+
+        // Relative paths name the same asset
+        match library.remove_asset(Path::new("media/take.mov"), &[&edit], RemovePolicy::Warn)? {
+            RemoveOutcome::InUse { asset_id, clips, replacements } => {
+                assert_eq!(asset_id, take_id);
+                assert_eq!(clips.len(), 2);
+                assert_eq!(replacements, vec![copy_id.clone()]);
+            },
+            outcome => panic!("Removed an asset in use: {:?}", outcome),
+        }
+        assert!(library.get_asset(&take_id).is_some());
+
+        // Moving the clips to the copy frees the asset
+        assert_eq!(library.replace_asset(&take_id, &copy_id, &mut [&mut edit])?, 2);
+        let report = media_usage(&library, &[&edit]);
+        assert!(!report.is_used(&take_id));
+        assert_eq!(report.usage(&copy_id).unwrap().used_ranges(), vec![(1.0, 3.0), (5.0, 7.0)]);
+
+        match library.remove_asset(&take, &[&edit], RemovePolicy::Refuse)? {
+            RemoveOutcome::Removed(asset) => assert_eq!(asset.id, take_id),
+            outcome => panic!("Asset kept: {:?}", outcome),
+        }
+        assert!(library.remove_asset(&take, &[&edit], RemovePolicy::Force).is_err());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_remove_leaves_clips_offline() -> Result<()> {
+        let root = create_project_dir("force_remove")?;
+        let music = root.join("media").join("music.wav");
+        fs::write(&music, b"music")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&music, None)?;
+        let edit = timeline(vec![("a1", vec![clip("c1", &music, 0.0, 3.0, 0.0)])])?;
+
+        assert!(matches!(library.remove_asset(&music, &[&edit], RemovePolicy::Force)?, RemoveOutcome::Removed(_)));
+        let report = media_usage(&library, &[&edit]);
+        assert!(!report.is_used(&id));
+        assert_eq!(report.unknown[0].clip_id, "c1");
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
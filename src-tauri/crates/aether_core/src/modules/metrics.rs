@@ -0,0 +1,325 @@
+//! Local performance metrics for the diagnostics dashboard
+//!
+//! Subsystems record counters, gauges, rates, cache hits and memory use into a
+//! `MetricsRegistry`, normally the global one from `metrics()`. `snapshot` reads all of
+//! it at once for the app to draw. Nothing is sent anywhere; the numbers only live in
+//! this process.
+//!
+//! Handles are cheap to clone and update without locking the registry, so hot paths
+//! should look a handle up once and keep it.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Names of the metrics the engine records
+pub mod names {
+    /// Rate: video frames decoded for preview
+    pub const DECODE_FRAMES: &str = "decode.frames";
+    /// Rate: bytes written by exports
+    pub const EXPORT_BYTES: &str = "export.bytes";
+    /// Rate: video frames encoded by exports
+    pub const EXPORT_FRAMES: &str = "export.frames";
+    /// Cache and memory: rendered preview frames
+    pub const PREVIEW_FRAME_CACHE: &str = "preview_frames";
+    /// Cache and memory: clip loudness profiles
+    pub const LOUDNESS_CACHE: &str = "loudness_profiles";
+    /// Memory: undo steps
+    pub const UNDO_HISTORY: &str = "undo_history";
+}
+
+/// Window `Meter` rates are averaged over
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Width of the buckets a `Meter` counts events in
+const BUCKET: Duration = Duration::from_millis(100);
+
+/// Registry used by the engine and read by the dashboard
+static METRICS: Lazy<MetricsRegistry> = Lazy::new(MetricsRegistry::new);
+
+/// A count that only goes up, e.g. frames dropped
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    value: Arc<AtomicU64>,
+}
+
+impl Counter {
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, amount: u64) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that goes up and down, e.g. bytes held in memory
+#[derive(Debug, Clone, Default)]
+pub struct Gauge {
+    /// Bits of an `f64`
+    bits: Arc<AtomicU64>,
+}
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Add to the value, which may be negative; handles shared by several owners use
+    /// this so each reports only its own part
+    pub fn add(&self, delta: f64) {
+        let _ = self.bits.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + delta).to_bits())
+        });
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug)]
+struct MeterState {
+    created: Instant,
+    total: u64,
+    /// Event counts by bucket index since `created`, oldest first
+    buckets: VecDeque<(u64, u64)>,
+}
+
+/// Events per second over the last `RATE_WINDOW`, e.g. frames decoded
+#[derive(Debug, Clone)]
+pub struct Meter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MeterState {
+                created: Instant::now(),
+                total: 0,
+                buckets: VecDeque::new(),
+            })),
+        }
+    }
+}
+
+impl Meter {
+    /// Record `count` events, such as frames or bytes
+    pub fn mark(&self, count: u64) {
+        let mut state = self.state.lock().unwrap();
+        let bucket = Self::bucket(&state, Instant::now());
+        state.total += count;
+        match state.buckets.back_mut() {
+            Some((index, events)) if *index == bucket => *events += count,
+            _ => state.buckets.push_back((bucket, count)),
+        }
+        Self::prune(&mut state, bucket);
+    }
+
+    /// Events per second over the last `RATE_WINDOW`
+    ///
+    /// A meter younger than a second divides by a full second, so the first few events
+    /// don't read as a burst.
+    pub fn rate(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let current = Self::bucket(&state, now);
+        Self::prune(&mut state, current);
+        let events: u64 = state.buckets.iter().map(|(_, events)| events).sum();
+        let span = now.duration_since(state.created).clamp(Duration::from_secs(1), RATE_WINDOW);
+        events as f64 / span.as_secs_f64()
+    }
+
+    /// Events recorded since the meter was created
+    pub fn total(&self) -> u64 {
+        self.state.lock().unwrap().total
+    }
+
+    fn bucket(state: &MeterState, now: Instant) -> u64 {
+        (now.duration_since(state.created).as_millis() / BUCKET.as_millis()) as u64
+    }
+
+    fn prune(state: &mut MeterState, current: u64) {
+        let window = (RATE_WINDOW.as_millis() / BUCKET.as_millis()) as u64;
+        while state.buckets.front().is_some_and(|(index, _)| index + window <= current) {
+            state.buckets.pop_front();
+        }
+    }
+}
+
+/// Hits and misses of a cache
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats {
+    hits: Counter,
+    misses: Counter,
+}
+
+impl CacheStats {
+    pub fn hit(&self) {
+        self.hits.increment();
+    }
+
+    pub fn miss(&self) {
+        self.misses.increment();
+    }
+
+    pub fn record(&self, hit: bool) {
+        if hit {
+            self.hit();
+        } else {
+            self.miss();
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.get()
+    }
+
+    /// Share of lookups that hit, from 0 to 1, or `None` before the first lookup
+    pub fn hit_rate(&self) -> Option<f64> {
+        let (hits, misses) = (self.hits(), self.misses());
+        (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64)
+    }
+}
+
+/// Rate of a `Meter` at snapshot time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateSnapshot {
+    pub total: u64,
+    pub per_second: f64,
+}
+
+/// Hits and misses of a cache at snapshot time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CacheSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: Option<f64>,
+}
+
+/// Every metric at one moment, for the diagnostics dashboard
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    /// Wall-clock time in milliseconds since the Unix epoch
+    pub taken_ms: u64,
+    pub counters: BTreeMap<String, u64>,
+    pub gauges: BTreeMap<String, f64>,
+    pub rates: BTreeMap<String, RateSnapshot>,
+    pub caches: BTreeMap<String, CacheSnapshot>,
+    /// Bytes held in memory by each subsystem
+    pub memory: BTreeMap<String, u64>,
+}
+
+impl MetricsSnapshot {
+    /// Events per second of a rate, 0 if it was never recorded
+    pub fn per_second(&self, name: &str) -> f64 {
+        self.rates.get(name).map_or(0.0, |rate| rate.per_second)
+    }
+
+    /// Frames decoded per second for preview
+    pub fn decode_fps(&self) -> f64 {
+        self.per_second(names::DECODE_FRAMES)
+    }
+
+    /// Bytes written per second by exports
+    pub fn export_throughput(&self) -> f64 {
+        self.per_second(names::EXPORT_BYTES)
+    }
+
+    /// Bytes held in memory by all subsystems
+    pub fn total_memory(&self) -> u64 {
+        self.memory.values().sum()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    counters: HashMap<String, Counter>,
+    gauges: HashMap<String, Gauge>,
+    meters: HashMap<String, Meter>,
+    caches: HashMap<String, CacheStats>,
+    memory: HashMap<String, Gauge>,
+}
+
+/// Named metrics, created on first use
+///
+/// Clones share the same metrics.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    inner: Arc<Mutex<Metrics>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counter(&self, name: &str) -> Counter {
+        self.inner.lock().unwrap().counters.entry(name.to_string()).or_default().clone()
+    }
+
+    pub fn gauge(&self, name: &str) -> Gauge {
+        self.inner.lock().unwrap().gauges.entry(name.to_string()).or_default().clone()
+    }
+
+    pub fn meter(&self, name: &str) -> Meter {
+        self.inner.lock().unwrap().meters.entry(name.to_string()).or_default().clone()
+    }
+
+    pub fn cache(&self, name: &str) -> CacheStats {
+        self.inner.lock().unwrap().caches.entry(name.to_string()).or_default().clone()
+    }
+
+    /// Bytes a subsystem holds in memory; owners sharing a subsystem `add` their part
+    pub fn memory(&self, subsystem: &str) -> Gauge {
+        self.inner.lock().unwrap().memory.entry(subsystem.to_string()).or_default().clone()
+    }
+
+    /// Read every metric
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let metrics = self.inner.lock().unwrap();
+        MetricsSnapshot {
+            taken_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            counters: metrics.counters.iter().map(|(name, counter)| (name.clone(), counter.get())).collect(),
+            gauges: metrics.gauges.iter().map(|(name, gauge)| (name.clone(), gauge.get())).collect(),
+            rates: metrics.meters.iter()
+                .map(|(name, meter)| (name.clone(), RateSnapshot { total: meter.total(), per_second: meter.rate() }))
+                .collect(),
+            caches: metrics.caches.iter()
+                .map(|(name, cache)| (name.clone(), CacheSnapshot {
+                    hits: cache.hits(),
+                    misses: cache.misses(),
+                    hit_rate: cache.hit_rate(),
+                }))
+                .collect(),
+            memory: metrics.memory.iter()
+                .map(|(subsystem, gauge)| (subsystem.clone(), gauge.get().max(0.0) as u64))
+                .collect(),
+        }
+    }
+}
+
+/// The registry the engine records into
+pub fn metrics() -> MetricsRegistry {
+    METRICS.clone()
+}
+
+/// Snapshot of the engine's metrics for the dashboard
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    METRICS.snapshot()
+}
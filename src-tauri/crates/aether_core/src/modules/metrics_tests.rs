@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use super::super::metrics::{self, names, MetricsRegistry};
+    use super::super::spill_store::{write_bytes, read_bytes, Spillable, SpillStore};
+    use std::io::{self, Read, Write};
+    use anyhow::Result;
+
+    struct Block(Vec<u8>);
+
+    impl Spillable for Block {
+        fn memory_size(&self) -> usize {
+            self.0.len()
+        }
+
+        fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+            write_bytes(writer, &self.0)
+        }
+
+        fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+            read_bytes(reader).map(Block)
+        }
+    }
+
+    #[test]
+    fn test_snapshot_reads_every_metric() {
+        let registry = MetricsRegistry::new();
+        registry.counter("frames.dropped").add(3);
+        registry.counter("frames.dropped").increment();
+        registry.gauge("preview.latency_ms").set(12.5);
+
+        // Owners of one subsystem each add their part
+        let memory = registry.memory("thumbnails");
+        memory.add(1000.0);
+        registry.memory("thumbnails").add(500.0);
+        memory.add(-200.0);
+
+        let cache = registry.cache(names::PREVIEW_FRAME_CACHE);
+        assert_eq!(cache.hit_rate(), None);
+        cache.hit();
+        cache.hit();
+        cache.hit();
+        cache.record(false);
+
+        // Young meters divide by a full second
+        registry.meter(names::DECODE_FRAMES).mark(24);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.counters["frames.dropped"], 4);
+        assert_eq!(snapshot.gauges["preview.latency_ms"], 12.5);
+        assert_eq!(snapshot.memory["thumbnails"], 1300);
+        assert_eq!(snapshot.total_memory(), 1300);
+        assert_eq!(snapshot.caches[names::PREVIEW_FRAME_CACHE].hit_rate, Some(0.75));
+        assert_eq!(snapshot.rates[names::DECODE_FRAMES].total, 24);
+        assert_eq!(snapshot.decode_fps(), 24.0);
+        assert_eq!(snapshot.export_throughput(), 0.0);
+        assert!(snapshot.taken_ms > 0);
+
+        // Serializes for the app as is
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["caches"]["preview_frames"]["hits"], 3);
+    }
+
+    #[test]
+    fn test_spill_stores_report_memory() -> Result<()> {
+        let dir = std::env::temp_dir().join("aether_metrics_test");
+        let memory = || metrics::metrics_snapshot().memory.get("metrics_test_blocks").copied();
+
+        let mut first = SpillStore::new(&dir, "metrics_test_blocks", 150);
+        let mut second = SpillStore::new(&dir, "metrics_test_blocks", 150);
+        first.insert(1, Block(vec![0; 100]));
+        second.insert(1, Block(vec![0; 40]));
+        assert_eq!(memory(), Some(140));
+
+        // Spilled and removed entries stop counting
+        first.insert(2, Block(vec![0; 100]));
+        assert_eq!(memory(), Some(140));
+        second.remove(&1)?;
+        assert_eq!(memory(), Some(100));
+
+        drop(first);
+        assert_eq!(memory(), Some(0));
+        drop(second);
+        Ok(())
+    }
+}
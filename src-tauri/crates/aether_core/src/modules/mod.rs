@@ -1,15 +1,188 @@
+pub mod analysis_pass;
+pub mod assembly;
+pub mod audio_detection;
 pub mod audio_engine;
+pub mod audio_sync;
+pub mod av_sync;
+pub mod backend_policy;
+pub mod camera_card;
+pub mod clip_log;
+pub mod clip_loudness;
 pub mod color_grading;
 pub mod color_grading_frame_processor;
+pub mod color_lut;
+pub mod control_surface;
+pub mod diagnostics;
+pub mod display_calibration;
+pub mod disk_space;
+pub mod encoder_benchmark;
+pub mod face_detection;
+pub mod ffmpeg_backend;
 pub mod file_manager;
 pub mod file_manager_batch;
 pub mod file_manager_convert;
+pub mod file_manager_import;
+pub mod file_manager_thumbnails;
+pub mod grading_panel;
+pub mod grading_presets;
+pub mod hardware_probe;
+pub mod highlight_detection;
+pub mod media_library;
+pub mod media_usage;
+pub mod metrics;
+pub mod operation_log;
+pub mod path_policy;
+pub mod picture_detection;
+pub mod pipeline_builder;
+pub mod pipeline_watchdog;
+pub mod power_policy;
+pub mod project_archive;
+pub mod project_template;
+pub mod qc_report;
+pub mod safe_mode;
+pub mod scene_classification;
+pub mod scheduler;
+pub mod scripting;
+pub mod settings;
+pub mod spill_store;
+pub mod transcription;
+pub mod video_levels;
+pub mod vision_model;
+
+#[cfg(test)]
+mod analysis_pass_tests;
+
+#[cfg(test)]
+mod assembly_tests;
+
+#[cfg(test)]
+mod audio_detection_tests;
 
 #[cfg(test)]
 mod audio_engine_tests;
 
+#[cfg(test)]
+mod audio_sync_tests;
+
+#[cfg(test)]
+mod av_sync_tests;
+
+#[cfg(test)]
+mod backend_policy_tests;
+
+#[cfg(test)]
+mod camera_card_tests;
+
+#[cfg(test)]
+mod clip_log_tests;
+
+#[cfg(test)]
+mod clip_loudness_tests;
+
 #[cfg(test)]
 mod color_grading_tests;
 
+#[cfg(test)]
+mod color_lut_tests;
+
+#[cfg(test)]
+mod control_surface_tests;
+
+#[cfg(test)]
+mod diagnostics_tests;
+
+#[cfg(test)]
+mod display_calibration_tests;
+
+#[cfg(test)]
+mod disk_space_tests;
+
+#[cfg(test)]
+mod encoder_benchmark_tests;
+
+#[cfg(test)]
+mod face_detection_tests;
+
+#[cfg(test)]
+mod file_manager_import_tests;
+
 #[cfg(test)]
 mod file_manager_tests;
+
+#[cfg(test)]
+mod file_manager_thumbnails_tests;
+
+#[cfg(test)]
+mod grading_panel_tests;
+
+#[cfg(test)]
+mod grading_presets_tests;
+
+#[cfg(test)]
+mod hardware_probe_tests;
+
+#[cfg(test)]
+mod highlight_detection_tests;
+
+#[cfg(test)]
+mod media_library_tests;
+
+#[cfg(test)]
+mod media_usage_tests;
+
+#[cfg(test)]
+mod metrics_tests;
+
+#[cfg(test)]
+mod operation_log_tests;
+
+#[cfg(test)]
+mod path_policy_tests;
+
+#[cfg(test)]
+mod picture_detection_tests;
+
+#[cfg(test)]
+mod pipeline_builder_tests;
+
+#[cfg(test)]
+mod pipeline_watchdog_tests;
+
+#[cfg(test)]
+mod power_policy_tests;
+
+#[cfg(test)]
+mod project_archive_tests;
+
+#[cfg(test)]
+mod project_template_tests;
+
+#[cfg(test)]
+mod qc_report_tests;
+
+#[cfg(test)]
+mod safe_mode_tests;
+
+#[cfg(test)]
+mod scene_classification_tests;
+
+#[cfg(test)]
+mod scheduler_tests;
+
+#[cfg(test)]
+mod scripting_tests;
+
+#[cfg(test)]
+mod settings_tests;
+
+#[cfg(test)]
+mod spill_store_tests;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
+
+#[cfg(test)]
+mod transcription_tests;
+
+#[cfg(test)]
+mod video_levels_tests;
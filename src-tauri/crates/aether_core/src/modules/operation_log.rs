@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Stable, globally unique operation ID
+///
+/// IDs are ordered by Lamport clock and then by actor, which gives every replica the
+/// same total order for last-writer-wins resolution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OperationId {
+    /// Lamport clock value
+    pub lamport: u64,
+    /// Replica (user/device/session) that created the operation
+    pub actor: String,
+}
+
+impl std::fmt::Display for OperationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.lamport, self.actor)
+    }
+}
+
+/// Kind of change recorded by an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OperationKind {
+    /// Set a field of an entity (last writer wins per field)
+    Set {
+        /// Field name
+        field: String,
+        /// New value
+        value: serde_json::Value,
+    },
+    /// Delete an entity (last writer wins against sets on the same entity)
+    Delete,
+}
+
+/// A single recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    /// Stable operation ID
+    pub id: OperationId,
+    /// Wall-clock time in milliseconds, informational only
+    pub timestamp_ms: u64,
+    /// Entity the operation applies to (clip, track, marker, ...)
+    pub target: String,
+    /// The change
+    pub kind: OperationKind,
+}
+
+/// Materialized state of an entity
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EntityState {
+    /// Current field values
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+/// Append-only operation log with LWW merge and a crash-safe journal
+pub struct OperationLog {
+    /// Replica that appends to this log
+    actor: String,
+    /// Current Lamport clock
+    lamport: u64,
+    /// Operations in application order
+    operations: Vec<Operation>,
+    /// IDs of known operations
+    known: HashSet<OperationId>,
+    /// Journal file, if the log is persisted
+    journal: Option<(PathBuf, File)>,
+}
+
+impl OperationLog {
+    /// Create an in-memory log
+    pub fn new(actor: &str) -> Self {
+        Self {
+            actor: actor.to_string(),
+            lamport: 0,
+            operations: Vec::new(),
+            known: HashSet::new(),
+            journal: None,
+        }
+    }
+
+    /// Open (or create) a journaled log, replaying any operations already on disk
+    ///
+    /// A partially written last line from a crash is discarded and the journal is
+    /// truncated back to the last complete operation.
+    pub fn open(path: &Path, actor: &str) -> Result<Self> {
+        let mut log = Self::new(actor);
+
+        if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            let mut valid_bytes = 0u64;
+            let mut corrupt = false;
+
+            for line in reader.lines() {
+                let line = line?;
+                match serde_json::from_str::<Operation>(&line) {
+                    Ok(operation) => {
+                        valid_bytes += line.len() as u64 + 1;
+                        log.insert(operation);
+                    },
+                    Err(e) => {
+                        warn!("Discarding corrupt journal entry in {:?}: {}", path, e);
+                        corrupt = true;
+                        break;
+                    },
+                }
+            }
+
+            if corrupt {
+                let file = OpenOptions::new().write(true).open(path)?;
+                file.set_len(valid_bytes)?;
+            }
+
+            info!("Recovered {} operations from {:?}", log.operations.len(), path);
+        } else if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log.journal = Some((path.to_path_buf(), file));
+
+        Ok(log)
+    }
+
+    /// Replica ID of this log
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// Record a field change
+    pub fn set(&mut self, target: &str, field: &str, value: serde_json::Value) -> Result<OperationId> {
+        self.append(target, OperationKind::Set {
+            field: field.to_string(),
+            value,
+        })
+    }
+
+    /// Record an entity deletion
+    pub fn delete(&mut self, target: &str) -> Result<OperationId> {
+        self.append(target, OperationKind::Delete)
+    }
+
+    /// Append a new local operation
+    pub fn append(&mut self, target: &str, kind: OperationKind) -> Result<OperationId> {
+        self.lamport += 1;
+
+        let operation = Operation {
+            id: OperationId {
+                lamport: self.lamport,
+                actor: self.actor.clone(),
+            },
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            target: target.to_string(),
+            kind,
+        };
+
+        self.write_journal(&operation)?;
+
+        let id = operation.id.clone();
+        self.insert(operation);
+        Ok(id)
+    }
+
+    /// Merge operations from another replica, returning how many were new
+    ///
+    /// Merging is idempotent and commutative: the same set of operations always
+    /// materializes to the same state regardless of arrival order.
+    pub fn merge(&mut self, operations: &[Operation]) -> Result<usize> {
+        let mut added = 0;
+
+        for operation in operations {
+            if self.known.contains(&operation.id) {
+                continue;
+            }
+            self.write_journal(operation)?;
+            self.insert(operation.clone());
+            added += 1;
+        }
+
+        debug!("Merged {} new operations into log for {}", added, self.actor);
+        Ok(added)
+    }
+
+    /// All operations in the order they were recorded locally
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Operations with a Lamport clock greater than `lamport`, for incremental sync
+    pub fn operations_since(&self, lamport: u64) -> Vec<Operation> {
+        self.operations
+            .iter()
+            .filter(|operation| operation.id.lamport > lamport)
+            .cloned()
+            .collect()
+    }
+
+    /// Look up an operation by ID
+    pub fn get(&self, id: &OperationId) -> Option<&Operation> {
+        self.operations.iter().find(|operation| &operation.id == id)
+    }
+
+    /// Materialize the current state of every live entity using last-writer-wins
+    pub fn state(&self) -> HashMap<String, EntityState> {
+        let mut sorted: Vec<&Operation> = self.operations.iter().collect();
+        sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut field_writes: HashMap<String, BTreeMap<String, (OperationId, serde_json::Value)>> = HashMap::new();
+        let mut deletions: HashMap<String, OperationId> = HashMap::new();
+
+        for operation in sorted {
+            match &operation.kind {
+                OperationKind::Set { field, value } => {
+                    field_writes
+                        .entry(operation.target.clone())
+                        .or_default()
+                        .insert(field.clone(), (operation.id.clone(), value.clone()));
+                },
+                OperationKind::Delete => {
+                    deletions.insert(operation.target.clone(), operation.id.clone());
+                },
+            }
+        }
+
+        let mut state = HashMap::new();
+        for (target, fields) in field_writes {
+            // Only writes newer than the latest delete survive
+            let deleted_at = deletions.get(&target);
+            let fields: BTreeMap<String, serde_json::Value> = fields
+                .into_iter()
+                .filter(|(_, (id, _))| deleted_at.map_or(true, |deleted| id > deleted))
+                .map(|(field, (_, value))| (field, value))
+                .collect();
+
+            if !fields.is_empty() {
+                state.insert(target, EntityState { fields });
+            }
+        }
+
+        state
+    }
+
+    /// Insert an operation and advance the Lamport clock past it
+    fn insert(&mut self, operation: Operation) {
+        if !self.known.insert(operation.id.clone()) {
+            return;
+        }
+        self.lamport = self.lamport.max(operation.id.lamport);
+        self.operations.push(operation);
+    }
+
+    /// Append an operation to the journal and flush it to disk
+    fn write_journal(&mut self, operation: &Operation) -> Result<()> {
+        if let Some((path, file)) = self.journal.as_mut() {
+            let mut line = serde_json::to_string(operation)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())
+                .and_then(|_| file.sync_data())
+                .map_err(|e| anyhow!("Failed to write journal {:?}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use super::super::operation_log::OperationLog;
+    use std::fs;
+    use std::io::Write;
+    use anyhow::Result;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_is_order_independent() -> Result<()> {
+        let mut a = OperationLog::new("alice");
+        let mut b = OperationLog::new("bob");
+
+        a.set("clip_1", "start", json!(1.0))?;
+        b.set("clip_1", "start", json!(2.0))?;
+        b.set("clip_1", "duration", json!(5.0))?;
+
+        let ops_a = a.operations().to_vec();
+        let ops_b = b.operations().to_vec();
+
+        a.merge(&ops_b)?;
+        b.merge(&ops_a)?;
+
+        assert_eq!(a.state(), b.state());
+
+        // Merging again adds nothing
+        assert_eq!(a.merge(&ops_b)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_writer_wins() -> Result<()> {
+        let mut log = OperationLog::new("alice");
+        log.set("clip_1", "name", json!("first"))?;
+        log.set("clip_1", "name", json!("second"))?;
+
+        let state = log.state();
+        assert_eq!(state["clip_1"].fields["name"], json!("second"));
+
+        // A delete hides older writes, later writes resurrect the entity
+        log.delete("clip_1")?;
+        assert!(!log.state().contains_key("clip_1"));
+
+        log.set("clip_1", "name", json!("third"))?;
+        assert_eq!(log.state()["clip_1"].fields.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_recovers_after_truncated_write() -> Result<()> {
+        let dir = std::env::temp_dir().join("aether_oplog_test");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("journal.jsonl");
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut log = OperationLog::open(&path, "alice")?;
+            log.set("clip_1", "start", json!(1.0))?;
+            log.set("clip_2", "start", json!(2.0))?;
+        }
+
+        // Simulate a crash in the middle of writing an entry
+        fs::OpenOptions::new().append(true).open(&path)?.write_all(b"{\"id\":{\"lam")?;
+
+        let mut log = OperationLog::open(&path, "alice")?;
+        assert_eq!(log.operations().len(), 2);
+
+        // New operations continue the clock after the recovered ones
+        let id = log.set("clip_3", "start", json!(3.0))?;
+        assert_eq!(id.lamport, 3);
+
+        let reopened = OperationLog::open(&path, "alice")?;
+        assert_eq!(reopened.operations().len(), 3);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Longest Windows path the classic file APIs accept, including the terminating NUL
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+/// Longest stem, in bytes, of file names derived from a media file's name
+///
+/// Leaves room for suffixes such as `-thumb-1920x1080-12.5.jpg` under the 255 byte
+/// limit most file systems put on a name.
+pub const MAX_OUTPUT_STEM: usize = 160;
+
+/// Policy for file paths handed to media pipelines
+///
+/// Paths are validated and canonicalized before use, and can be restricted to a set of
+/// allowed root directories. Validated paths are applied through element properties
+/// (see [`set_location`]) rather than interpolated into pipeline descriptions, so quotes,
+/// `!` and other parser syntax in file names can't alter the pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct PathPolicy {
+    /// Allowed root directories; empty means any location is allowed
+    allowed_roots: Vec<PathBuf>,
+}
+
+impl PathPolicy {
+    /// Policy that accepts any well-formed path
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    /// Policy that only accepts paths below the given roots
+    pub fn with_roots(roots: &[PathBuf]) -> Result<Self> {
+        let mut policy = Self::default();
+        for root in roots {
+            policy.add_root(root)?;
+        }
+        Ok(policy)
+    }
+
+    /// Allow paths below another root directory
+    pub fn add_root(&mut self, root: &Path) -> Result<()> {
+        let root = root
+            .canonicalize()
+            .map_err(|e| anyhow!("Invalid allowed root {:?}: {}", root, e))?;
+        if !root.is_dir() {
+            return Err(anyhow!("Allowed root is not a directory: {:?}", root));
+        }
+        self.allowed_roots.push(root);
+        Ok(())
+    }
+
+    /// Allowed root directories
+    pub fn allowed_roots(&self) -> &[PathBuf] {
+        &self.allowed_roots
+    }
+
+    /// Validate an existing input file, returning its canonical path
+    pub fn validate_input(&self, path: &Path) -> Result<PathBuf> {
+        check_characters(path)?;
+
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| anyhow!("Input file is not accessible: {:?} ({})", path, e))?;
+        if !canonical.is_file() {
+            return Err(anyhow!("Input is not a file: {:?}", path));
+        }
+
+        self.check_allowed(&canonical)?;
+        Ok(canonical)
+    }
+
+    /// Validate an existing input directory, returning its canonical path
+    pub fn validate_input_dir(&self, path: &Path) -> Result<PathBuf> {
+        check_characters(path)?;
+
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| anyhow!("Input directory is not accessible: {:?} ({})", path, e))?;
+        if !canonical.is_dir() {
+            return Err(anyhow!("Input is not a directory: {:?}", path));
+        }
+
+        self.check_allowed(&canonical)?;
+        Ok(canonical)
+    }
+
+    /// Validate an output path, returning it with a canonical parent directory
+    ///
+    /// The parent directory is created if needed. The file itself does not need to exist.
+    pub fn validate_output(&self, path: &Path) -> Result<PathBuf> {
+        check_characters(path)?;
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Output path has no file name: {:?}", path))?;
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => std::env::current_dir()?,
+        };
+
+        // Checked before anything is created, so a refused path leaves no directories behind
+        let parent = self.resolve_new(&parent)?;
+        std::fs::create_dir_all(&parent)?;
+        let canonical = parent.canonicalize()?.join(file_name);
+        if canonical.is_dir() {
+            return Err(anyhow!("Output path is a directory: {:?}", path));
+        }
+
+        self.check_allowed(&canonical)?;
+        Ok(canonical)
+    }
+
+    /// Validate an output directory, creating it if needed
+    pub fn validate_output_dir(&self, path: &Path) -> Result<PathBuf> {
+        check_characters(path)?;
+        let resolved = self.resolve_new(path)?;
+        std::fs::create_dir_all(&resolved)?;
+
+        let canonical = path.canonicalize()?;
+        self.check_allowed(&canonical)?;
+        Ok(canonical)
+    }
+
+    /// Whether a canonical path is inside one of the allowed roots
+    pub fn is_allowed(&self, canonical: &Path) -> bool {
+        self.allowed_roots.is_empty() || self.allowed_roots.iter().any(|root| canonical.starts_with(root))
+    }
+
+    /// Canonical form of a directory that may not exist yet: its nearest existing
+    /// ancestor canonicalized, with the rest appended, checked against the roots
+    ///
+    /// A `..` among the missing components has no name, so it is refused.
+    fn resolve_new(&self, path: &Path) -> Result<PathBuf> {
+        let mut existing = path;
+        let mut missing = Vec::new();
+        while !existing.exists() {
+            missing.push(existing.file_name().ok_or_else(|| anyhow!("Invalid path: {:?}", path))?);
+            existing = match existing.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+        }
+        let mut resolved = existing.canonicalize()?;
+        resolved.extend(missing.into_iter().rev());
+        self.check_allowed(&resolved)?;
+        Ok(resolved)
+    }
+
+    fn check_allowed(&self, canonical: &Path) -> Result<()> {
+        if self.is_allowed(canonical) {
+            Ok(())
+        } else {
+            Err(anyhow!("Path is outside the allowed directories: {:?}", canonical))
+        }
+    }
+}
+
+/// Reject paths that can't be passed safely to GStreamer
+fn check_characters(path: &Path) -> Result<()> {
+    let as_str = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Path is not valid UTF-8: {:?}", path))?;
+
+    if as_str.is_empty() {
+        return Err(anyhow!("Path is empty"));
+    }
+    if as_str.chars().any(|c| c == '\0' || c.is_control()) {
+        return Err(anyhow!("Path contains control characters: {:?}", path));
+    }
+
+    Ok(())
+}
+
+/// Convert a path to a properly escaped `file://` URI
+///
+/// Windows `\\?\` prefixes, which `canonicalize` adds, are dropped first; URIs have no
+/// room for them and spell long paths out in full anyway.
+pub fn path_to_uri(path: &Path) -> Result<String> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let absolute = match absolute.to_str() {
+        Some(location) => PathBuf::from(strip_verbatim(location)),
+        None => absolute,
+    };
+
+    gst::glib::filename_to_uri(&absolute, None)
+        .map(|uri| uri.to_string())
+        .map_err(|e| anyhow!("Failed to convert {:?} to URI: {}", path, e))
+}
+
+/// `location` property value for `filesrc`, `filesink` and other file elements
+///
+/// GStreamer takes locations as UTF-8, so other paths are rejected rather than mangled.
+/// On Windows, paths past `WINDOWS_MAX_PATH` get a `\\?\` prefix so they can be opened
+/// at all, and shorter ones lose it.
+pub fn gst_location(path: &Path) -> Result<String> {
+    let location = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Path is not valid UTF-8: {:?}", path))?;
+    if cfg!(windows) {
+        Ok(windows_location(location))
+    } else {
+        Ok(location.to_string())
+    }
+}
+
+/// A Windows path in the form its length needs, see `gst_location`
+pub fn windows_location(location: &str) -> String {
+    let plain = strip_verbatim(location);
+    if plain.encode_utf16().count() < WINDOWS_MAX_PATH {
+        return plain;
+    }
+
+    let plain = plain.replace('/', "\\");
+    match plain.strip_prefix("\\\\") {
+        Some(unc) => format!("\\\\?\\UNC\\{}", unc),
+        None if plain.as_bytes().get(1) == Some(&b':') => format!("\\\\?\\{}", plain),
+        // Relative paths can't take the prefix
+        None => plain,
+    }
+}
+
+/// Drop a Windows `\\?\` or `\\?\UNC\` prefix, leaving a path classic APIs understand
+pub fn strip_verbatim(location: &str) -> String {
+    if let Some(unc) = location.strip_prefix("\\\\?\\UNC\\") {
+        format!("\\\\{}", unc)
+    } else if let Some(path) = location.strip_prefix("\\\\?\\") {
+        path.to_string()
+    } else {
+        location.to_string()
+    }
+}
+
+/// Stem of `path` for naming files derived from it, such as thumbnails and proxies
+///
+/// Characters Windows doesn't allow in names are replaced with `_`, and the stem is cut
+/// to `MAX_OUTPUT_STEM` bytes on a character boundary, so emoji and CJK names stay
+/// whole characters.
+pub fn output_stem(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut output = String::new();
+    for c in stem.chars() {
+        let c = if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') { '_' } else { c };
+        if output.len() + c.len_utf8() > MAX_OUTPUT_STEM {
+            break;
+        }
+        output.push(c);
+    }
+
+    // Windows drops trailing dots and spaces, which would make names collide
+    let trimmed = output.trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        "media".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Set the `location` property of a named element in a pipeline
+pub fn set_location(pipeline: &gst::Element, element_name: &str, path: &Path) -> Result<()> {
+    let bin = pipeline
+        .downcast_ref::<gst::Bin>()
+        .ok_or_else(|| anyhow!("Pipeline is not a bin"))?;
+    let element = bin
+        .by_name(element_name)
+        .ok_or_else(|| anyhow!("Pipeline has no element named {}", element_name))?;
+
+    element.set_property("location", gst_location(path)?);
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use super::super::path_policy::{self, PathPolicy, MAX_OUTPUT_STEM, WINDOWS_MAX_PATH};
+    use super::super::pipeline_builder::ElementSpec;
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use anyhow::Result;
+
+    /// File names that have broken pipeline strings, URIs or derived names before
+    const HOSTILE_NAMES: &[&str] = &[
+        "🎬 take 1.mov",
+        "東京の夜景.mp4",
+        "서울 브이로그.mkv",
+        "مقابلة.wav",
+        "cafe\u{301} crème.mov",
+        "it's \"final\" v2.mov",
+        "a ! b = c name=x.mov",
+        "100% #1 & more.mp4",
+        "trailing dot..mov",
+        "   .mov",
+    ];
+
+    fn check_usable(path: &Path) -> Result<()> {
+        let policy = PathPolicy::unrestricted();
+        let canonical = policy.validate_input(path)?;
+
+        // URIs escape everything and convert back to the same file
+        let uri = path_policy::path_to_uri(&canonical)?;
+        assert!(uri.starts_with("file://") && uri.is_ascii(), "{}", uri);
+        let (back, _) = gst::glib::filename_from_uri(&uri)?;
+        assert_eq!(back, canonical);
+
+        // Locations are passed through untouched, never parsed
+        assert_eq!(path_policy::gst_location(&canonical)?, canonical.to_str().unwrap());
+        ElementSpec::file_source(&canonical)?;
+
+        let stem = path_policy::output_stem(&canonical);
+        assert!(!stem.is_empty() && stem.len() <= MAX_OUTPUT_STEM, "{:?}", stem);
+        assert!(!stem.ends_with(['.', ' ']), "{:?}", stem);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hostile_file_names() -> Result<()> {
+        let dir = create_test_dir("path_policy", "names")?;
+        for name in HOSTILE_NAMES {
+            let path = dir.join(name);
+            fs::write(&path, b"media")?;
+            check_usable(&path)?;
+        }
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_longer_than_windows_max_path() -> Result<()> {
+        let dir = create_test_dir("path_policy", "long")?;
+        let mut path = dir.clone();
+        while path.as_os_str().len() <= WINDOWS_MAX_PATH + 40 {
+            path.push("素材フォルダ_material_folder");
+        }
+        fs::create_dir_all(&path)?;
+        let file = path.join("🎞️ clip.mov");
+        fs::write(&file, b"media")?;
+        check_usable(&file)?;
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_locations() {
+        let short = "C:\\Media\\clip.mov";
+        assert_eq!(path_policy::windows_location(short), short);
+        assert_eq!(path_policy::windows_location("\\\\?\\C:\\Media\\clip.mov"), short);
+        assert_eq!(path_policy::strip_verbatim("\\\\?\\UNC\\nas\\share\\clip.mov"), "\\\\nas\\share\\clip.mov");
+
+        // Long paths get the prefix back, with forward slashes made native
+        let long_dir = "映像".repeat(WINDOWS_MAX_PATH / 2);
+        let long = format!("D:/{}/clip.mov", long_dir);
+        assert_eq!(path_policy::windows_location(&long), format!("\\\\?\\D:\\{}\\clip.mov", long_dir));
+        let unc = format!("\\\\nas\\share\\{}\\clip.mov", long_dir);
+        assert_eq!(path_policy::windows_location(&unc), format!("\\\\?\\UNC\\nas\\share\\{}\\clip.mov", long_dir));
+        let verbatim = format!("\\\\?\\D:\\{}\\clip.mov", long_dir);
+        assert_eq!(path_policy::windows_location(&verbatim), verbatim);
+    }
+
+    #[test]
+    fn test_output_stems() {
+        assert_eq!(path_policy::output_stem(Path::new("/media/a<b>:c|d?.mov")), "a_b__c_d_");
+        assert_eq!(path_policy::output_stem(Path::new("/media/...mov")), "media");
+
+        // Cut on a character boundary, well under the 255 byte name limit with suffixes
+        let long = PathBuf::from(format!("/media/{}.mov", "🎬東".repeat(100)));
+        let stem = path_policy::output_stem(&long);
+        assert!(stem.len() <= MAX_OUTPUT_STEM && stem.len() > MAX_OUTPUT_STEM - 4);
+        assert!(stem.starts_with("🎬東"));
+        assert!(format!("{}-thumb-1920x1080-3600.5.jpg", stem).len() < 255);
+    }
+
+    #[test]
+    fn test_refused_outputs_create_nothing() -> Result<()> {
+        let dir = create_test_dir("path_policy", "outputs")?;
+        let root = dir.join("project");
+        fs::create_dir_all(&root)?;
+        let policy = PathPolicy::with_roots(&[root.clone()])?;
+
+        assert!(policy.validate_output(&dir.join("outside/new/out.mp4")).is_err());
+        assert!(policy.validate_output(&root.join("../outside/out.mp4")).is_err());
+        assert!(policy.validate_output(&root.join("new/../../outside/out.mp4")).is_err());
+        assert!(policy.validate_output_dir(&dir.join("outside/renders")).is_err());
+        assert!(!dir.join("outside").exists());
+        assert!(!root.join("new").exists());
+
+        let output = policy.validate_output(&root.join("renders/out.mp4"))?;
+        assert!(output.starts_with(root.canonicalize()?) && output.parent().unwrap().is_dir());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,325 @@
+//! Black and frozen picture detection
+//!
+//! `BlackDetector` and `FreezeDetector` are analyzers working like FFmpeg's `blackdetect`
+//! and `freezedetect` filters, with the same thresholds. They run in an `AnalysisPass`,
+//! alongside the QC measurements or on their own through `detect_picture_issues`, which
+//! scans a source file or a render of the timeline. The issues found can be added to the
+//! timeline as markers, to find them again while logging or fixing a cut.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use tracing::info;
+
+use crate::engine::timeline::{Marker, Timeline};
+use super::analysis_pass::{AnalysisFrame, AnalysisPass, Analyzer};
+use super::qc_report::QcSegment;
+
+/// Thresholds of a black and freeze scan, with FFmpeg's defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PictureCheckOptions {
+    /// Shortest black stretch reported, in seconds (`blackdetect` `d`)
+    pub min_black: f64,
+    /// Luma at or below this share of full scale is black, 0-1 (`pix_th`)
+    pub pixel_threshold: f64,
+    /// Share of black pixels that makes a frame black, 0-1 (`pic_th`)
+    pub picture_threshold: f64,
+    /// Shortest frozen stretch reported, in seconds (`freezedetect` `d`)
+    pub min_freeze: f64,
+    /// Mean luma difference between frames below which the picture hasn't changed, 0-1 (`n`)
+    pub freeze_noise: f64,
+}
+
+impl Default for PictureCheckOptions {
+    fn default() -> Self {
+        Self {
+            min_black: 2.0,
+            pixel_threshold: 0.10,
+            picture_threshold: 0.98,
+            min_freeze: 2.0,
+            freeze_noise: 0.001,
+        }
+    }
+}
+
+/// What is wrong with a stretch of picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PictureIssueKind {
+    Black,
+    Frozen,
+}
+
+impl fmt::Display for PictureIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PictureIssueKind::Black => write!(f, "Black"),
+            PictureIssueKind::Frozen => write!(f, "Frozen"),
+        }
+    }
+}
+
+/// A black or frozen stretch of picture
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PictureIssue {
+    pub kind: PictureIssueKind,
+    /// Media time in seconds
+    pub start: f64,
+    pub end: f64,
+}
+
+impl PictureIssue {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// Range marker for the issue, shifted by `offset` seconds into timeline time
+    pub fn to_marker(&self, id: String, offset: f64) -> Marker {
+        let mut marker = Marker::new(id, offset + self.start, format!("{} picture", self.kind));
+        marker.duration = self.duration();
+        marker.note = format!("{:.3} s from {:.3} s in the media", self.duration(), self.start);
+        marker.color = Some(match self.kind {
+            PictureIssueKind::Black => "#505050".to_string(),
+            PictureIssueKind::Frozen => "#00a0ff".to_string(),
+        });
+        marker
+    }
+}
+
+/// Stretches of black picture
+#[derive(Debug, Clone)]
+pub struct BlackDetector {
+    /// Luma at or below this is black
+    pixel_threshold: u8,
+    /// Share of black pixels that makes a frame black
+    picture_threshold: f64,
+    min_duration: f64,
+    runs: FrameRuns,
+}
+
+impl BlackDetector {
+    pub fn new(min_duration: f64) -> Self {
+        let defaults = PictureCheckOptions::default();
+        Self {
+            pixel_threshold: to_luma(defaults.pixel_threshold),
+            picture_threshold: defaults.picture_threshold,
+            min_duration,
+            runs: FrameRuns::default(),
+        }
+    }
+
+    /// Treat luma at or below `threshold` of full scale as black, 0-1
+    pub fn with_pixel_threshold(mut self, threshold: f64) -> Self {
+        self.pixel_threshold = to_luma(threshold);
+        self
+    }
+
+    /// Treat frames with at least `threshold` of their pixels black as black, 0-1
+    pub fn with_picture_threshold(mut self, threshold: f64) -> Self {
+        self.picture_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Black stretches at least the minimum duration long, available after the pass
+    pub fn segments(&self) -> Vec<QcSegment> {
+        self.runs.segments(self.min_duration)
+    }
+}
+
+impl Analyzer for BlackDetector {
+    fn wants_video(&self) -> bool {
+        true
+    }
+
+    fn video(&mut self, frame: &AnalysisFrame) {
+        let luma = frame.luma();
+        let black = luma.iter().filter(|value| **value <= self.pixel_threshold).count();
+        let is_black = !luma.is_empty() && black as f64 >= luma.len() as f64 * self.picture_threshold;
+        self.runs.push(frame.timestamp, is_black);
+    }
+
+    fn finish(&mut self) {
+        self.runs.finish();
+    }
+}
+
+/// Stretches where the picture doesn't change
+#[derive(Debug, Clone)]
+pub struct FreezeDetector {
+    /// Mean luma difference from the previous frame below which it is the same picture, 0-1
+    noise: f64,
+    min_duration: f64,
+    previous: Option<Vec<u8>>,
+    runs: FrameRuns,
+}
+
+impl FreezeDetector {
+    pub fn new(min_duration: f64) -> Self {
+        let noise = PictureCheckOptions::default().freeze_noise;
+        Self { noise, min_duration, previous: None, runs: FrameRuns::default() }
+    }
+
+    /// Treat frames differing by less than `noise` from the previous one as frozen, 0-1
+    pub fn with_noise(mut self, noise: f64) -> Self {
+        self.noise = noise.max(0.0);
+        self
+    }
+
+    /// Frozen stretches at least the minimum duration long, from the first frame of the
+    /// frozen picture, available after the pass
+    pub fn segments(&self) -> Vec<QcSegment> {
+        self.runs.segments(self.min_duration)
+    }
+}
+
+impl Analyzer for FreezeDetector {
+    fn wants_video(&self) -> bool {
+        true
+    }
+
+    fn video(&mut self, frame: &AnalysisFrame) {
+        let luma = frame.luma();
+        let frozen = self.previous.as_ref().is_some_and(|previous| {
+            let difference: u64 = previous.iter().zip(&luma).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+            previous.len() == luma.len() && (difference as f64 / (luma.len().max(1) * 255) as f64) < self.noise
+        });
+        // A freeze starts at the frame that was repeated
+        if frozen && !self.runs.active() {
+            self.runs.start_at(self.runs.last_timestamp());
+        }
+        self.runs.push(frame.timestamp, frozen);
+        self.previous = Some(luma);
+    }
+
+    fn finish(&mut self) {
+        self.runs.finish();
+    }
+}
+
+/// Detectors set up from `options`
+pub fn picture_detectors(options: &PictureCheckOptions) -> (BlackDetector, FreezeDetector) {
+    let black = BlackDetector::new(options.min_black)
+        .with_pixel_threshold(options.pixel_threshold)
+        .with_picture_threshold(options.picture_threshold);
+    let freeze = FreezeDetector::new(options.min_freeze).with_noise(options.freeze_noise);
+    (black, freeze)
+}
+
+/// Issues found by detectors that have seen the whole file, in time order
+pub fn picture_issues(black: &BlackDetector, freeze: &FreezeDetector) -> Vec<PictureIssue> {
+    let issue = |kind: PictureIssueKind| move |segment: QcSegment| PictureIssue { kind, start: segment.start, end: segment.end };
+    let mut issues: Vec<PictureIssue> = black.segments().into_iter().map(issue(PictureIssueKind::Black))
+        .chain(freeze.segments().into_iter().map(issue(PictureIssueKind::Frozen)))
+        .collect();
+    issues.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.end.total_cmp(&b.end)));
+    issues
+}
+
+/// Scan a source file, or a render of the timeline, for black and frozen picture
+#[tracing::instrument(name = "picture_detection", skip(options))]
+pub fn detect_picture_issues(path: &Path, options: &PictureCheckOptions) -> Result<Vec<PictureIssue>> {
+    let (mut black, mut freeze) = picture_detectors(options);
+    AnalysisPass::new()
+        .with_analyzer(&mut black)
+        .with_analyzer(&mut freeze)
+        .run(path)?;
+
+    let issues = picture_issues(&black, &freeze);
+    info!("Found {} black or frozen stretches in {:?}", issues.len(), path);
+    Ok(issues)
+}
+
+/// Add picture issues as markers on the timeline, returning the marker IDs
+///
+/// With a `clip_id`, the issues were found in that clip's source: they are placed at the
+/// clip's timeline position, and those outside its trimmed range are skipped. Without
+/// one, they were found in a render of the timeline and their times are timeline times.
+pub fn add_picture_issue_markers(timeline: &mut Timeline, clip_id: Option<&str>, issues: &[PictureIssue]) -> Result<Vec<String>> {
+    let clip = match clip_id {
+        Some(clip_id) => Some(
+            timeline
+                .tracks()
+                .values()
+                .flat_map(|track| track.clips.iter())
+                .find(|clip| clip.id == clip_id)
+                .cloned()
+                .ok_or_else(|| anyhow!("Clip not found: {}", clip_id))?,
+        ),
+        None => None,
+    };
+
+    let prefix = clip_id.map_or_else(|| "picture".to_string(), |clip_id| format!("picture_{}", clip_id));
+    let offset = clip.as_ref().map_or(0.0, |clip| clip.start_time - clip.in_point());
+    let mut ids = Vec::new();
+    for (index, issue) in issues.iter().enumerate() {
+        let mut marker = issue.to_marker(format!("{}_{}", prefix, index + 1), offset);
+        if let Some(clip) = &clip {
+            if issue.end <= clip.in_point() || issue.start >= clip.in_point() + clip.duration {
+                continue;
+            }
+            // Clamp to the part of the clip that is on the timeline
+            let end = (marker.time + marker.duration).min(clip.end_time());
+            marker.time = marker.time.max(clip.start_time);
+            marker.duration = end - marker.time;
+        }
+
+        ids.push(marker.id.clone());
+        timeline.add_marker(marker)?;
+    }
+    Ok(ids)
+}
+
+/// 8-bit luma at a share of full scale
+fn to_luma(share: f64) -> u8 {
+    (share.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Runs of frames matching a condition, with their times
+#[derive(Debug, Clone, Default)]
+struct FrameRuns {
+    start: Option<f64>,
+    last_timestamp: Option<f64>,
+    /// Time between the last two frames, to end a run that lasts to the end
+    frame_duration: f64,
+    runs: Vec<QcSegment>,
+}
+
+impl FrameRuns {
+    fn active(&self) -> bool {
+        self.start.is_some()
+    }
+
+    fn last_timestamp(&self) -> f64 {
+        self.last_timestamp.unwrap_or(0.0)
+    }
+
+    fn start_at(&mut self, timestamp: f64) {
+        self.start = Some(timestamp);
+    }
+
+    fn push(&mut self, timestamp: f64, matches: bool) {
+        if let Some(last) = self.last_timestamp {
+            self.frame_duration = timestamp - last;
+        }
+        match (matches, self.start) {
+            (true, None) => self.start = Some(timestamp),
+            (false, Some(start)) => {
+                self.runs.push(QcSegment { start, end: timestamp });
+                self.start = None;
+            },
+            _ => {},
+        }
+        self.last_timestamp = Some(timestamp);
+    }
+
+    fn finish(&mut self) {
+        if let Some(start) = self.start.take() {
+            self.runs.push(QcSegment { start, end: self.last_timestamp() + self.frame_duration });
+        }
+    }
+
+    fn segments(&self, min_duration: f64) -> Vec<QcSegment> {
+        self.runs.iter().copied().filter(|segment| segment.duration() >= min_duration).collect()
+    }
+}
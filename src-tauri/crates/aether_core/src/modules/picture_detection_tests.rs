@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use super::super::analysis_pass::{AnalysisFrame, AnalysisPass};
+    use super::super::picture_detection::*;
+    use super::super::qc_report::QcSegment;
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use anyhow::Result;
+
+    const FPS: f64 = 25.0;
+
+    fn frame(index: usize, rgb: Vec<u8>) -> AnalysisFrame {
+        AnalysisFrame { timestamp: index as f64 / FPS, width: 8, height: 4, rgb }
+    }
+
+    /// A different busy picture on every frame
+    fn moving(index: usize) -> Vec<u8> {
+        (0..8 * 4 * 3).map(|p| ((index * 37 + p * 11) % 256) as u8).collect()
+    }
+
+    /// Two seconds of picture, three of a dark shot, two of picture,
+    /// three frozen, then picture again
+    fn scan(options: &PictureCheckOptions) -> Vec<PictureIssue> {
+        let (mut black, mut freeze) = picture_detectors(options);
+        let mut pass = AnalysisPass::new().with_analyzer(&mut black).with_analyzer(&mut freeze);
+        let still = moving(3);
+        for index in 0..275 {
+            let rgb = match index {
+                // Near black with a little noise, darker than 10% but not than 5%
+                50..125 => (0..8 * 4 * 3).map(|p| 18 + ((index + p) % 3) as u8).collect(),
+                175..250 => still.clone(),
+                _ => moving(index),
+            };
+            pass.push_frame(&frame(index, rgb));
+        }
+        pass.finish();
+        picture_issues(&black, &freeze)
+    }
+
+    #[test]
+    fn test_black_and_freeze_detection() {
+        let issues = scan(&PictureCheckOptions::default());
+        assert_eq!(issues, vec![
+            PictureIssue { kind: PictureIssueKind::Black, start: 2.0, end: 5.0 },
+            PictureIssue { kind: PictureIssueKind::Frozen, start: 7.0, end: 10.0 },
+        ]);
+
+        // A tighter black level, and freezes only from four seconds
+        let strict = PictureCheckOptions { pixel_threshold: 0.05, min_freeze: 4.0, ..PictureCheckOptions::default() };
+        assert!(scan(&strict).is_empty());
+
+        // Half-black frames count once the picture threshold allows it
+        let mut black = BlackDetector::new(0.0).with_picture_threshold(0.5);
+        let mut pass = AnalysisPass::new().with_analyzer(&mut black);
+        let mut half = moving(1);
+        half[..8 * 2 * 3].fill(0);
+        pass.push_frame(&frame(0, moving(0)));
+        pass.push_frame(&frame(1, half));
+        pass.push_frame(&frame(2, moving(2)));
+        pass.finish();
+        assert_eq!(black.segments(), vec![QcSegment { start: 0.04, end: 0.08 }]);
+    }
+
+    #[test]
+    fn test_issue_markers() -> Result<()> {
+        let issues = scan(&PictureCheckOptions::default());
+
+        // Found in a render, at timeline times
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        let ids = add_picture_issue_markers(&mut timeline, None, &issues)?;
+        assert_eq!(ids, vec!["picture_1", "picture_2"]);
+        let marker = &timeline.markers()[0];
+        assert_eq!((marker.time, marker.duration), (2.0, 3.0));
+        assert_eq!(marker.name, "Black picture");
+
+        // Found in a source clip trimmed to start at 6s, placed at 20s
+        let mut timeline = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string()))?;
+        let mut clip = Clip::new("shot".to_string(), ClipType::Video, 20.0, 3.0);
+        clip.set_in_point(6.0);
+        timeline.add_clip_to_track("video", clip)?;
+
+        let ids = add_picture_issue_markers(&mut timeline, Some("shot"), &issues)?;
+        assert_eq!(ids, vec!["picture_shot_2"]);
+        let marker = &timeline.markers()[0];
+        assert_eq!(marker.name, "Frozen picture");
+        assert!((marker.time - 21.0).abs() < 1e-9);
+        assert!((marker.duration - 2.0).abs() < 1e-9);
+
+        assert!(add_picture_issue_markers(&mut timeline, Some("missing"), &issues).is_err());
+        Ok(())
+    }
+}
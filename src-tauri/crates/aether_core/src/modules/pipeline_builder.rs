@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, warn};
+use std::path::Path;
+
+use super::path_policy;
+
+/// Media kind of a dynamically exposed pad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Video,
+    Audio,
+}
+
+impl StreamKind {
+    /// Caps name prefix for this stream kind
+    fn caps_prefix(&self) -> &'static str {
+        match self {
+            StreamKind::Video => "video/",
+            StreamKind::Audio => "audio/",
+        }
+    }
+}
+
+/// Description of a single element to create
+///
+/// Properties are set with real GLib values, never by formatting text, so file names
+/// and user-provided values can't change the shape of the pipeline.
+#[derive(Debug, Clone)]
+pub struct ElementSpec {
+    factory: String,
+    name: Option<String>,
+    properties: Vec<(String, PropertyValue)>,
+}
+
+/// Value of a property in an element spec
+#[derive(Debug, Clone)]
+enum PropertyValue {
+    /// Typed value
+    Value(gst::glib::SendValue),
+    /// String parsed into the property's own type (enums, flags)
+    Parsed(String),
+}
+
+impl ElementSpec {
+    /// Describe an element from its factory name
+    pub fn new(factory: &str) -> Self {
+        Self {
+            factory: factory.to_string(),
+            name: None,
+            properties: Vec::new(),
+        }
+    }
+
+    /// Caps filter restricting the stream to the given caps
+    pub fn capsfilter(caps: gst::Caps) -> Self {
+        Self::new("capsfilter").property("caps", caps)
+    }
+
+    /// `filesrc` reading from a path
+    pub fn file_source(path: &Path) -> Result<Self> {
+        Self::new("filesrc").location(path)
+    }
+
+    /// `filesink` writing to a path
+    pub fn file_sink(path: &Path) -> Result<Self> {
+        Self::new("filesink").location(path)
+    }
+
+    /// Set the `location` property from a path
+    pub fn location(self, path: &Path) -> Result<Self> {
+        Ok(self.property("location", path_policy::gst_location(path)?))
+    }
+
+    /// Set the element name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Set a property
+    pub fn property<V: ToSendValue>(mut self, name: &str, value: V) -> Self {
+        self.properties.push((name.to_string(), PropertyValue::Value(value.to_send_value())));
+        self
+    }
+
+    /// Set a property parsed from a string, for enum and flags properties
+    pub fn property_from_str(mut self, name: &str, value: &str) -> Self {
+        self.properties.push((name.to_string(), PropertyValue::Parsed(value.to_string())));
+        self
+    }
+
+    /// Factory name
+    pub fn factory(&self) -> &str {
+        &self.factory
+    }
+
+    /// Create the element
+    pub fn build(&self) -> Result<gst::Element> {
+        let mut builder = gst::ElementFactory::make(&self.factory);
+        if let Some(name) = &self.name {
+            builder = builder.name(name.as_str());
+        }
+        let element = builder
+            .build()
+            .map_err(|_| anyhow!("Missing GStreamer element: {}", self.factory))?;
+
+        for (name, value) in &self.properties {
+            match value {
+                PropertyValue::Value(value) => element.set_property_from_value(name, value),
+                PropertyValue::Parsed(text) => element.set_property_from_str(name, text),
+            }
+        }
+
+        Ok(element)
+    }
+}
+
+/// Builds a pipeline from element specs and links them
+pub struct PipelineBuilder {
+    pipeline: gst::Pipeline,
+}
+
+impl PipelineBuilder {
+    /// Start a new pipeline
+    pub fn new(name: &str) -> Result<Self> {
+        if !gst::is_initialized() {
+            gst::init()?;
+        }
+
+        Ok(Self {
+            pipeline: gst::Pipeline::with_name(name),
+        })
+    }
+
+    /// Add a single element
+    pub fn add(&self, spec: &ElementSpec) -> Result<gst::Element> {
+        let element = spec.build()?;
+        self.pipeline.add(&element)?;
+        Ok(element)
+    }
+
+    /// Add a chain of elements and link them in order, returning the created elements
+    pub fn chain(&self, specs: &[ElementSpec]) -> Result<Vec<gst::Element>> {
+        let elements = specs
+            .iter()
+            .map(|spec| self.add(spec))
+            .collect::<Result<Vec<_>>>()?;
+
+        for pair in elements.windows(2) {
+            pair[0].link(&pair[1]).map_err(|_| {
+                anyhow!(
+                    "Failed to link {} to {}",
+                    pair[0].name(),
+                    pair[1].name()
+                )
+            })?;
+        }
+
+        Ok(elements)
+    }
+
+    /// Link two elements already in the pipeline
+    pub fn link(&self, src: &gst::Element, dest: &gst::Element) -> Result<()> {
+        src.link(dest)
+            .map_err(|_| anyhow!("Failed to link {} to {}", src.name(), dest.name()))
+    }
+
+    /// Link the first `kind` pad a demuxer/decoder exposes to `dest`
+    ///
+    /// Further pads of the same kind are ignored.
+    pub fn link_dynamic(&self, src: &gst::Element, kind: StreamKind, dest: &gst::Element) {
+        let dest = dest.clone();
+        src.connect_pad_added(move |src, pad| {
+            let matches = pad
+                .current_caps()
+                .or_else(|| Some(pad.query_caps(None)))
+                .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with(kind.caps_prefix())))
+                .unwrap_or(false);
+            if !matches {
+                return;
+            }
+
+            let sink_pad = match dest.static_pad("sink") {
+                Some(pad) => pad,
+                None => return,
+            };
+            if sink_pad.is_linked() {
+                return;
+            }
+
+            match pad.link(&sink_pad) {
+                Ok(_) => debug!("Linked {:?} pad of {} to {}", kind, src.name(), dest.name()),
+                Err(e) => warn!("Failed to link {:?} pad of {}: {:?}", kind, src.name(), e),
+            }
+        });
+    }
+
+    /// Finish building
+    pub fn build(self) -> gst::Pipeline {
+        self.pipeline
+    }
+
+    /// The pipeline being built
+    pub fn pipeline(&self) -> &gst::Pipeline {
+        &self.pipeline
+    }
+}
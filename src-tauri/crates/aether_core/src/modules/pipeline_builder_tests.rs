@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests {
+    use super::super::pipeline_builder::*;
+    use gst::prelude::*;
+    use std::path::Path;
+    use anyhow::Result;
+
+    #[test]
+    fn test_file_locations_are_set_verbatim() -> Result<()> {
+        gst::init()?;
+
+        // Text that would split or re-quote a launch string
+        for location in [
+            "/media/My Clips/take 1.mov",
+            "/media/\"quoted\" 'name'.mov",
+            "/media/a ! fakesink location=/tmp/b.mov",
+        ] {
+            let source = ElementSpec::file_source(Path::new(location))?.build()?;
+            assert_eq!(source.property::<Option<String>>("location").as_deref(), Some(location));
+
+            let sink = ElementSpec::file_sink(Path::new(location))?.build()?;
+            assert_eq!(sink.factory().unwrap().name().as_str(), "filesink");
+            assert_eq!(sink.property::<Option<String>>("location").as_deref(), Some(location));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_elements_are_named_in_the_error() -> Result<()> {
+        let builder = PipelineBuilder::new("unknown")?;
+
+        let error = builder.add(&ElementSpec::new("nosuchelement")).unwrap_err();
+        assert_eq!(error.to_string(), "Missing GStreamer element: nosuchelement");
+
+        // A chain stops at the first missing element
+        let error = builder.chain(&[ElementSpec::new("fakesrc"), ElementSpec::new("nosuchelement")]).unwrap_err();
+        assert!(error.to_string().contains("nosuchelement"), "{}", error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain_links_elements_in_order() -> Result<()> {
+        let builder = PipelineBuilder::new("chain")?;
+        let elements = builder.chain(&[
+            ElementSpec::new("fakesrc").name("source").property("num-buffers", 1i32),
+            ElementSpec::new("queue"),
+            ElementSpec::new("fakesink").name("sink"),
+        ])?;
+
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0].name().as_str(), "source");
+        assert_eq!(elements[2].name().as_str(), "sink");
+        for pair in elements.windows(2) {
+            let peer = pair[0].static_pad("src").unwrap().peer().unwrap();
+            assert_eq!(peer.parent_element().as_ref(), Some(&pair[1]));
+        }
+
+        // The built pipeline holds the linked elements and runs to the end
+        let pipeline = builder.build();
+        assert!(pipeline.by_name("source").is_some());
+        pipeline.set_state(gst::State::Playing)?;
+        let bus = pipeline.bus().unwrap();
+        let eos = bus.timed_pop_filtered(gst::ClockTime::from_seconds(10), &[gst::MessageType::Eos, gst::MessageType::Error]);
+        pipeline.set_state(gst::State::Null)?;
+        assert!(matches!(eos.as_ref().map(|msg| msg.view()), Some(gst::MessageView::Eos(..))));
+
+        // Elements that can't link report both names
+        let sinks = PipelineBuilder::new("unlinkable")?;
+        let error = sinks.chain(&[ElementSpec::new("fakesink").name("first"), ElementSpec::new("fakesink").name("second")]).unwrap_err();
+        assert_eq!(error.to_string(), "Failed to link first to second");
+        Ok(())
+    }
+}
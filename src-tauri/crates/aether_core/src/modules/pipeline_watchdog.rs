@@ -0,0 +1,231 @@
+use gst::prelude::*;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, error, warn};
+
+/// How long to wait for a pipeline to reach NULL before giving up on it
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Watchdog settings
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// Time without progress or bus activity after which a pipeline counts as stuck
+    pub stall_timeout: Duration,
+    /// How often the pipeline position is polled
+    pub check_interval: Duration,
+    /// How long forcing the pipeline to NULL may take
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(30),
+            check_interval: Duration::from_secs(1),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+}
+
+/// A pipeline stopped making progress and was shut down
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Pipeline {pipeline} stalled: no activity for {:.1}s in state {state} at position {}", idle.as_secs_f64(), position.map(|p| format!("{:.3}s", p)).unwrap_or_else(|| "unknown".to_string()))]
+pub struct PipelineTimeout {
+    /// Pipeline name
+    pub pipeline: String,
+    /// Time since the last activity
+    pub idle: Duration,
+    /// State the pipeline was stuck in, with the pending state if it was changing
+    pub state: String,
+    /// Last known position in seconds
+    pub position: Option<f64>,
+    /// Whether the pipeline reached NULL when forced
+    pub shut_down: bool,
+}
+
+/// Reports activity to a watchdog, e.g. from a bus watch
+#[derive(Debug, Clone)]
+pub struct WatchdogKicker {
+    last_activity: Arc<Mutex<Instant>>,
+}
+
+impl WatchdogKicker {
+    /// Mark the pipeline as alive
+    pub fn kick(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+}
+
+/// Watches a pipeline and forces it to NULL when it stops making progress
+///
+/// Position changes count as activity automatically; bus messages should be reported
+/// through a `WatchdogKicker` since a bus only has room for its owner's watch. Paused
+/// pipelines are never considered stuck, but ones hanging in a state change are.
+pub struct Watchdog {
+    last_activity: Arc<Mutex<Instant>>,
+
+    timeout: Arc<Mutex<Option<PipelineTimeout>>>,
+
+    stop: Arc<(Mutex<bool>, Condvar)>,
+
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start watching; `on_timeout` runs on the watchdog thread after the pipeline was forced to NULL
+    pub fn watch<F>(pipeline: &gst::Pipeline, config: WatchdogConfig, on_timeout: F) -> Self
+    where
+        F: FnOnce(PipelineTimeout) + Send + 'static,
+    {
+        let last_activity = Arc::new(Mutex::new(Instant::now()));
+        let timeout = Arc::new(Mutex::new(None));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let pipeline_weak = pipeline.downgrade();
+            let last_activity = last_activity.clone();
+            let timeout = timeout.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                if let Some(stalled) = Self::monitor(&pipeline_weak, &config, &last_activity, &stop) {
+                    *timeout.lock().unwrap() = Some(stalled.clone());
+                    on_timeout(stalled);
+                }
+            })
+        };
+
+        Self {
+            last_activity,
+            timeout,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Handle for reporting activity
+    pub fn kicker(&self) -> WatchdogKicker {
+        WatchdogKicker {
+            last_activity: self.last_activity.clone(),
+        }
+    }
+
+    /// Mark the pipeline as alive
+    pub fn kick(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// The timeout, if the watchdog fired
+    pub fn timeout(&self) -> Option<PipelineTimeout> {
+        self.timeout.lock().unwrap().clone()
+    }
+
+    /// Stop watching without touching the pipeline
+    pub fn stop(&mut self) {
+        {
+            let (lock, condvar) = &*self.stop;
+            *lock.lock().unwrap() = true;
+            condvar.notify_all();
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Poll until the pipeline stalls, goes away, or the watchdog is stopped
+    fn monitor(
+        pipeline_weak: &glib::WeakRef<gst::Pipeline>,
+        config: &WatchdogConfig,
+        last_activity: &Mutex<Instant>,
+        stop: &(Mutex<bool>, Condvar),
+    ) -> Option<PipelineTimeout> {
+        let (lock, condvar) = stop;
+        let mut last_position = None;
+
+        loop {
+            {
+                let stopped = lock.lock().unwrap();
+                let (stopped, _) = condvar.wait_timeout(stopped, config.check_interval).unwrap();
+                if *stopped {
+                    return None;
+                }
+            }
+
+            let pipeline = pipeline_weak.upgrade()?;
+            let position = pipeline.query_position::<gst::ClockTime>().map(|p| p.seconds_f64());
+            if position != last_position {
+                last_position = position;
+                *last_activity.lock().unwrap() = Instant::now();
+                continue;
+            }
+
+            let (_, current, pending) = pipeline.state(gst::ClockTime::ZERO);
+            let stuck_state = current == gst::State::Playing || pending != gst::State::VoidPending;
+            if !stuck_state {
+                // Paused or stopped on purpose
+                *last_activity.lock().unwrap() = Instant::now();
+                continue;
+            }
+
+            let idle = last_activity.lock().unwrap().elapsed();
+            if idle < config.stall_timeout {
+                continue;
+            }
+
+            let state = if pending == gst::State::VoidPending {
+                format!("{:?}", current)
+            } else {
+                format!("{:?} -> {:?}", current, pending)
+            };
+            error!("Pipeline {} stalled for {:?} in state {}, forcing shutdown", pipeline.name(), idle, state);
+
+            let shut_down = shutdown_pipeline(pipeline.upcast_ref(), config.shutdown_timeout);
+            return Some(PipelineTimeout {
+                pipeline: pipeline.name().to_string(),
+                idle,
+                state,
+                position,
+                shut_down,
+            });
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Set a pipeline to NULL, giving up after `timeout`
+///
+/// A deadlocked element can block a state change forever, so the change runs on a helper
+/// thread that is abandoned if it doesn't finish. Returns whether NULL was reached in time.
+/// Use this from `Drop` so shutting down never hangs the process.
+pub fn shutdown_pipeline(element: &gst::Element, timeout: Duration) -> bool {
+    let (sender, receiver) = mpsc::channel();
+    let element = element.clone();
+    let name = element.name().to_string();
+
+    thread::spawn(move || {
+        let result = element.set_state(gst::State::Null);
+        let _ = sender.send(result);
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(_)) => {
+            debug!("Pipeline {} shut down", name);
+            true
+        },
+        Ok(Err(e)) => {
+            warn!("Failed to shut down pipeline {}: {}", name, e);
+            false
+        },
+        Err(_) => {
+            error!("Pipeline {} did not reach NULL within {:?}, abandoning it", name, timeout);
+            false
+        },
+    }
+}
@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod tests {
+    use super::super::pipeline_watchdog::{Watchdog, WatchdogConfig};
+    use anyhow::Result;
+    use gst::prelude::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn fast_config() -> WatchdogConfig {
+        WatchdogConfig {
+            stall_timeout: Duration::from_millis(300),
+            check_interval: Duration::from_millis(50),
+            shutdown_timeout: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn test_stuck_preroll_is_shut_down() -> Result<()> {
+        gst::init()?;
+
+        // A non-live appsrc that never gets data can't preroll, so the pipeline hangs in READY -> PAUSED
+        let pipeline = gst::parse::launch("appsrc ! fakesink")?
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let watchdog = Watchdog::watch(&pipeline, fast_config(), move |timeout| {
+            let _ = sender.send(timeout);
+        });
+        pipeline.set_state(gst::State::Playing)?;
+
+        let timeout = receiver.recv_timeout(Duration::from_secs(5))?;
+        assert!(timeout.shut_down);
+        assert!(timeout.idle >= Duration::from_millis(300));
+        assert_eq!(watchdog.timeout(), Some(timeout));
+        assert_eq!(pipeline.current_state(), gst::State::Null);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_pipeline_is_left_alone() -> Result<()> {
+        gst::init()?;
+
+        let pipeline = gst::parse::launch("fakesrc ! fakesink")?
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+
+        let mut watchdog = Watchdog::watch(&pipeline, fast_config(), |_| {});
+        std::thread::sleep(Duration::from_millis(600));
+        watchdog.stop();
+
+        assert!(watchdog.timeout().is_none());
+
+        Ok(())
+    }
+}
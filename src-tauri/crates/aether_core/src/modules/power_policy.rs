@@ -0,0 +1,426 @@
+//! Lighter work while running on battery
+//!
+//! A `PowerPolicy` tracks whether the machine runs on mains power or on battery. On
+//! battery it switches to a saver mode: previews drop to a lower resolution, preview
+//! renders on the background `Scheduler` are held back, and every export is throttled
+//! the way background exports are. A `PowerMonitor` polls the power source and reports
+//! each switch to a callback, so the UI can tell the user why things got slower.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::engine::rendering::{RenderQueue, ThrottleSettings};
+use super::scheduler::{JobKind, Scheduler};
+use super::settings::PreviewQuality;
+
+/// Where the machine draws power from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSource {
+    /// Mains power, including a battery that is charging or full
+    Ac,
+    /// Discharging, with the charge in percent if known
+    Battery { percent: Option<u8> },
+    /// The platform doesn't say; treated as mains power
+    Unknown,
+}
+
+impl PowerSource {
+    /// Ask the operating system
+    pub fn detect() -> Self {
+        if cfg!(target_os = "linux") {
+            Self::from_sysfs(Path::new("/sys/class/power_supply"))
+        } else if cfg!(target_os = "macos") {
+            match std::process::Command::new("pmset").args(["-g", "batt"]).output() {
+                Ok(output) => Self::from_pmset(&String::from_utf8_lossy(&output.stdout)),
+                Err(e) => {
+                    debug!("Failed to run pmset: {}", e);
+                    PowerSource::Unknown
+                },
+            }
+        } else {
+            windows::power_source()
+        }
+    }
+
+    /// Read the power supplies under a Linux `/sys/class/power_supply` directory
+    ///
+    /// Any online mains or USB supply means mains power. Otherwise a system battery that
+    /// is discharging means battery; batteries of peripherals such as mice are ignored.
+    /// A machine without batteries is on mains power.
+    pub fn from_sysfs(dir: &Path) -> Self {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Failed to read {:?}: {}", dir, e);
+                return PowerSource::Unknown;
+            },
+        };
+
+        let read = |supply: &Path, name: &str| fs::read_to_string(supply.join(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default();
+
+        let mut discharging = None;
+        for entry in entries.flatten() {
+            let supply = entry.path();
+            match read(&supply, "type").as_str() {
+                "Mains" | "USB" if read(&supply, "online") == "1" => return PowerSource::Ac,
+                "Battery" if read(&supply, "scope") != "Device" && read(&supply, "status") == "Discharging" => {
+                    discharging = Some(read(&supply, "capacity").parse::<u8>().ok());
+                },
+                _ => (),
+            }
+        }
+
+        match discharging {
+            Some(percent) => PowerSource::Battery { percent },
+            None => PowerSource::Ac,
+        }
+    }
+
+    /// Parse the output of `pmset -g batt` on macOS
+    pub fn from_pmset(output: &str) -> Self {
+        let Some(first) = output.lines().next() else {
+            return PowerSource::Unknown;
+        };
+        if first.contains("'AC Power'") {
+            return PowerSource::Ac;
+        }
+        if !first.contains("'Battery Power'") {
+            return PowerSource::Unknown;
+        }
+
+        let percent = output.lines()
+            .skip(1)
+            .find_map(|line| {
+                let end = line.find('%')?;
+                let start = line[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+                line[start..end].parse().ok()
+            });
+        PowerSource::Battery { percent }
+    }
+
+    pub fn on_battery(&self) -> bool {
+        matches!(self, PowerSource::Battery { .. })
+    }
+}
+
+impl fmt::Display for PowerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerSource::Ac => f.write_str("mains power"),
+            PowerSource::Battery { percent: Some(percent) } => write!(f, "battery ({}%)", percent),
+            PowerSource::Battery { percent: None } => f.write_str("battery"),
+            PowerSource::Unknown => f.write_str("unknown power source"),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::PowerSource;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    pub fn power_source() -> PowerSource {
+        let mut status = SystemPowerStatus::default();
+        // SAFETY: the struct matches SYSTEM_POWER_STATUS and outlives the call
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+            return PowerSource::Unknown;
+        }
+        match status.ac_line_status {
+            0 => PowerSource::Battery {
+                percent: (status.battery_life_percent <= 100).then_some(status.battery_life_percent),
+            },
+            1 => PowerSource::Ac,
+            _ => PowerSource::Unknown,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod windows {
+    use super::PowerSource;
+
+    pub fn power_source() -> PowerSource {
+        PowerSource::Unknown
+    }
+}
+
+/// How much work the engine takes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerMode {
+    Normal,
+    /// Reduced preview resolution, held background renders and throttled exports
+    BatterySaver,
+}
+
+/// What battery saver changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerSettings {
+    /// Switch to battery saver whenever running on battery
+    pub automatic: bool,
+    /// Highest preview resolution on battery
+    pub battery_preview_quality: PreviewQuality,
+    /// Hold preview renders on the background scheduler
+    pub pause_background_rendering: bool,
+    /// Export write rate on battery in MB per second, 0 for unlimited
+    pub battery_write_rate_mb: u64,
+    /// Encoder thread limit for exports on battery
+    pub battery_encoder_threads: u8,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            automatic: true,
+            battery_preview_quality: PreviewQuality::Half,
+            pause_background_rendering: true,
+            battery_write_rate_mb: 10,
+            battery_encoder_threads: 2,
+        }
+    }
+}
+
+impl PowerSettings {
+    pub fn mode_for(&self, source: PowerSource) -> PowerMode {
+        if self.automatic && source.on_battery() {
+            PowerMode::BatterySaver
+        } else {
+            PowerMode::Normal
+        }
+    }
+
+    /// Throttle applied to every export in battery saver
+    pub fn battery_throttle(&self) -> ThrottleSettings {
+        ThrottleSettings {
+            enabled: true,
+            all_jobs: true,
+            max_encoder_threads: self.battery_encoder_threads.max(1),
+            max_write_rate: self.battery_write_rate_mb * 1024 * 1024,
+        }
+    }
+}
+
+/// A switch between power modes, passed to the monitor's callback
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerNotification {
+    pub previous: PowerMode,
+    pub mode: PowerMode,
+    pub source: PowerSource,
+    /// Sentence for the user describing the switch
+    pub message: String,
+}
+
+/// Receives power mode switches
+pub type PowerCallback = Arc<dyn Fn(&PowerNotification) + Send + Sync + 'static>;
+
+/// Applies the power mode to the scheduler, render queue and preview resolution
+pub struct PowerPolicy {
+    settings: PowerSettings,
+    source: PowerSource,
+    mode: PowerMode,
+    scheduler: Option<Arc<Scheduler>>,
+    render_queue: Option<Arc<RenderQueue>>,
+    /// Render queue throttle to restore when leaving battery saver
+    normal_throttle: Option<ThrottleSettings>,
+}
+
+impl PowerPolicy {
+    /// Policy in normal mode until the first `update`
+    pub fn new(settings: PowerSettings) -> Self {
+        Self {
+            settings,
+            source: PowerSource::Unknown,
+            mode: PowerMode::Normal,
+            scheduler: None,
+            render_queue: None,
+            normal_throttle: None,
+        }
+    }
+
+    pub fn settings(&self) -> &PowerSettings {
+        &self.settings
+    }
+
+    pub fn source(&self) -> PowerSource {
+        self.source
+    }
+
+    pub fn mode(&self) -> PowerMode {
+        self.mode
+    }
+
+    /// Hold the scheduler's preview renders in battery saver, starting now
+    pub fn attach_scheduler(&mut self, scheduler: Arc<Scheduler>) {
+        self.scheduler = Some(scheduler);
+        self.apply();
+    }
+
+    /// Throttle the queue's exports in battery saver, starting now
+    pub fn attach_render_queue(&mut self, render_queue: Arc<RenderQueue>) {
+        self.restore_throttle();
+        self.render_queue = Some(render_queue);
+        self.apply();
+    }
+
+    /// Change the settings, switching modes if they call for it
+    pub fn set_settings(&mut self, settings: PowerSettings) -> Option<PowerNotification> {
+        self.settings = settings;
+        let source = self.source;
+        self.switch(source, true)
+    }
+
+    /// Record the current power source, switching modes if it calls for it
+    pub fn update(&mut self, source: PowerSource) -> Option<PowerNotification> {
+        self.switch(source, false)
+    }
+
+    /// Preview resolution to use when the user asked for `requested`
+    pub fn preview_quality(&self, requested: PreviewQuality) -> PreviewQuality {
+        let limit = self.settings.battery_preview_quality;
+        if self.mode == PowerMode::BatterySaver && limit.scale() < requested.scale() {
+            limit
+        } else {
+            requested
+        }
+    }
+
+    fn switch(&mut self, source: PowerSource, reapply: bool) -> Option<PowerNotification> {
+        self.source = source;
+        let previous = self.mode;
+        self.mode = self.settings.mode_for(source);
+        if self.mode == previous {
+            if reapply {
+                self.apply();
+            }
+            return None;
+        }
+
+        let message = match self.mode {
+            PowerMode::BatterySaver => format!(
+                "Running on {}: previews use {} resolution, background rendering is {} and exports are throttled",
+                source,
+                format!("{:?}", self.settings.battery_preview_quality).to_lowercase(),
+                if self.settings.pause_background_rendering { "paused" } else { "on" },
+            ),
+            PowerMode::Normal if source.on_battery() => "Battery saver turned off".to_string(),
+            PowerMode::Normal => format!("Running on {}: full performance restored", source),
+        };
+        info!("{}", message);
+        self.apply();
+
+        Some(PowerNotification { previous, mode: self.mode, source, message })
+    }
+
+    fn apply(&mut self) {
+        let saver = self.mode == PowerMode::BatterySaver;
+
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.set_held(JobKind::PreviewRender, saver && self.settings.pause_background_rendering);
+        }
+
+        if saver {
+            if let Some(queue) = &self.render_queue {
+                if self.normal_throttle.is_none() {
+                    self.normal_throttle = Some(queue.throttle());
+                }
+                queue.set_throttle(self.settings.battery_throttle());
+            }
+        } else {
+            self.restore_throttle();
+        }
+    }
+
+    fn restore_throttle(&mut self) {
+        if let (Some(queue), Some(throttle)) = (&self.render_queue, self.normal_throttle.take()) {
+            queue.set_throttle(throttle);
+        }
+    }
+}
+
+/// Reports the power source, e.g. `PowerSource::detect`
+pub type SourceProbe = Box<dyn FnMut() -> PowerSource + Send>;
+
+/// Polls the power source and updates a policy, notifying a callback of switches
+pub struct PowerMonitor {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl PowerMonitor {
+    /// Monitor asking the operating system every `interval`
+    pub fn start(policy: Arc<Mutex<PowerPolicy>>, interval: Duration, callback: Option<PowerCallback>) -> Self {
+        Self::with_probe(policy, interval, callback, Box::new(PowerSource::detect))
+    }
+
+    /// Monitor with its own probe; the first poll happens at once
+    pub fn with_probe(
+        policy: Arc<Mutex<PowerPolicy>>,
+        interval: Duration,
+        callback: Option<PowerCallback>,
+        mut probe: SourceProbe,
+    ) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let (lock, condvar) = &*stop;
+                let mut stopped = lock.lock().unwrap();
+                while !*stopped {
+                    drop(stopped);
+                    let source = probe();
+                    // Callbacks may use the policy, so run them without holding its lock
+                    let notification = policy.lock().unwrap().update(source);
+                    if let (Some(notification), Some(callback)) = (notification, &callback) {
+                        callback(&notification);
+                    }
+
+                    stopped = lock.lock().unwrap();
+                    if !*stopped {
+                        stopped = condvar.wait_timeout(stopped, interval).unwrap().0;
+                    }
+                }
+            })
+        };
+
+        Self { stop, thread: Some(thread) }
+    }
+
+    pub fn stop(&mut self) {
+        *self.stop.0.lock().unwrap() = true;
+        self.stop.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PowerMonitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+    use super::super::power_policy::*;
+    use super::super::scheduler::{IdlePolicy, JobKind, Scheduler};
+    use super::super::settings::PreviewQuality;
+    use super::super::test_utils::create_test_dir;
+    use crate::engine::rendering::{RenderQueue, RenderQueueConfig};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use anyhow::Result;
+
+    /// Write a fake power supply with the given attribute files
+    fn write_supply(dir: &Path, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+        let supply = dir.join(name);
+        fs::create_dir_all(&supply)?;
+        for (attribute, value) in attributes {
+            fs::write(supply.join(attribute), format!("{}\n", value))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_power_source_from_sysfs() -> Result<()> {
+        let dir = create_test_dir("power_policy", "sysfs")?;
+        assert_eq!(PowerSource::from_sysfs(&dir.join("missing")), PowerSource::Unknown);
+
+        // A desktop without batteries
+        assert_eq!(PowerSource::from_sysfs(&dir), PowerSource::Ac);
+
+        write_supply(&dir, "AC", &[("type", "Mains"), ("online", "0")])?;
+        write_supply(&dir, "BAT0", &[("type", "Battery"), ("status", "Discharging"), ("capacity", "64")])?;
+        write_supply(&dir, "hidpp_battery_0", &[("type", "Battery"), ("scope", "Device"), ("status", "Discharging")])?;
+        assert_eq!(PowerSource::from_sysfs(&dir), PowerSource::Battery { percent: Some(64) });
+
+        write_supply(&dir, "AC", &[("online", "1")])?;
+        assert_eq!(PowerSource::from_sysfs(&dir), PowerSource::Ac);
+
+        // A mouse running down doesn't count
+        write_supply(&dir, "AC", &[("online", "0")])?;
+        write_supply(&dir, "BAT0", &[("status", "Full")])?;
+        assert_eq!(PowerSource::from_sysfs(&dir), PowerSource::Ac);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_power_source_from_pmset() {
+        let battery = "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=4653155)\t87%; discharging; 5:12 remaining present: true\n";
+        assert_eq!(PowerSource::from_pmset(battery), PowerSource::Battery { percent: Some(87) });
+
+        let ac = "Now drawing from 'AC Power'\n -InternalBattery-0 (id=4653155)\t100%; charged; 0:00 remaining present: true\n";
+        assert_eq!(PowerSource::from_pmset(ac), PowerSource::Ac);
+        assert_eq!(PowerSource::from_pmset(""), PowerSource::Unknown);
+        assert_eq!(PowerSource::from_pmset("Now drawing from 'Battery Power'\n"), PowerSource::Battery { percent: None });
+    }
+
+    #[test]
+    fn test_battery_saver_applies_and_restores() {
+        let scheduler = Arc::new(Scheduler::with_load_probe(IdlePolicy::default(), Box::new(|| None)));
+        let queue = Arc::new(RenderQueue::new(RenderQueueConfig::default()));
+        let normal_throttle = queue.throttle();
+
+        let mut policy = PowerPolicy::new(PowerSettings::default());
+        policy.attach_scheduler(scheduler.clone());
+        policy.attach_render_queue(queue.clone());
+        assert_eq!(policy.update(PowerSource::Ac), None);
+        assert_eq!(policy.preview_quality(PreviewQuality::Full), PreviewQuality::Full);
+
+        let notification = policy.update(PowerSource::Battery { percent: Some(40) }).unwrap();
+        assert_eq!((notification.previous, notification.mode), (PowerMode::Normal, PowerMode::BatterySaver));
+        assert!(notification.message.contains("battery (40%)"), "{}", notification.message);
+        assert!(scheduler.is_held(JobKind::PreviewRender));
+        assert!(!scheduler.is_held(JobKind::Thumbnail));
+        assert!(queue.throttle().all_jobs);
+        assert_eq!(queue.throttle().max_write_rate, 10 * 1024 * 1024);
+
+        // Lower qualities than the battery limit are kept
+        assert_eq!(policy.preview_quality(PreviewQuality::Full), PreviewQuality::Half);
+        assert_eq!(policy.preview_quality(PreviewQuality::Quarter), PreviewQuality::Quarter);
+
+        // Draining further isn't a switch
+        assert_eq!(policy.update(PowerSource::Battery { percent: Some(30) }), None);
+
+        let notification = policy.update(PowerSource::Ac).unwrap();
+        assert_eq!(notification.mode, PowerMode::Normal);
+        assert!(!scheduler.is_held(JobKind::PreviewRender));
+        assert_eq!(queue.throttle(), normal_throttle);
+    }
+
+    #[test]
+    fn test_settings_change_battery_saver() {
+        let scheduler = Arc::new(Scheduler::with_load_probe(IdlePolicy::default(), Box::new(|| None)));
+        let mut policy = PowerPolicy::new(PowerSettings::default());
+        policy.attach_scheduler(scheduler.clone());
+        policy.update(PowerSource::Battery { percent: None });
+
+        // Keeping background renders on applies without a switch
+        let settings = PowerSettings { pause_background_rendering: false, ..PowerSettings::default() };
+        assert_eq!(policy.set_settings(settings.clone()), None);
+        assert!(!scheduler.is_held(JobKind::PreviewRender));
+
+        let notification = policy.set_settings(PowerSettings { automatic: false, ..settings }).unwrap();
+        assert_eq!(notification.mode, PowerMode::Normal);
+        assert_eq!(notification.message, "Battery saver turned off");
+        assert_eq!(policy.preview_quality(PreviewQuality::Full), PreviewQuality::Full);
+    }
+
+    #[test]
+    fn test_monitor_notifies_switches() -> Result<()> {
+        let policy = Arc::new(Mutex::new(PowerPolicy::new(PowerSettings::default())));
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let callback: PowerCallback = Arc::new(move |notification| {
+            sender.lock().unwrap().send(notification.mode).unwrap();
+        });
+
+        let sources = [PowerSource::Ac, PowerSource::Battery { percent: Some(50) }, PowerSource::Battery { percent: Some(49) }, PowerSource::Ac];
+        let mut polls = sources.into_iter().cycle();
+        let mut monitor = PowerMonitor::with_probe(
+            policy.clone(),
+            Duration::from_millis(5),
+            Some(callback),
+            Box::new(move || polls.next().unwrap()),
+        );
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5))?, PowerMode::BatterySaver);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5))?, PowerMode::Normal);
+        monitor.stop();
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use gst::prelude::*;
+use tracing::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::engine::timeline::Timeline;
+use super::file_manager::MediaType;
+use super::media_library::MediaLibrary;
+use super::media_usage::media_usage;
+use super::pipeline_builder::{ElementSpec, PipelineBuilder, StreamKind};
+
+/// Options for archiving a project
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    /// File name the rewritten library is saved under in the archive, if any
+    pub project_file: Option<String>,
+    /// Trim video and audio media to the ranges used by the timelines
+    pub trim_to_used: bool,
+    /// Extra media kept before and after each used range, in seconds
+    pub handles: f64,
+    /// Also copy assets that no timeline references
+    pub include_unused: bool,
+    /// Subdirectory of the destination that media is copied into
+    pub media_dir: String,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            project_file: None,
+            trim_to_used: false,
+            handles: 1.0,
+            include_unused: false,
+            media_dir: "media".to_string(),
+        }
+    }
+}
+
+/// Where an archived asset ended up
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedMedia {
+    /// Asset ID
+    pub asset_id: String,
+    /// Original absolute path
+    pub source_path: PathBuf,
+    /// Absolute path inside the archive
+    pub archived_path: PathBuf,
+    /// Start of the kept range in the original media, in seconds (0 when not trimmed)
+    pub trim_offset: f64,
+}
+
+/// Result of archiving a project
+#[derive(Debug, Clone)]
+pub struct ArchiveResult {
+    /// Library with references rewritten to the archive
+    pub library: MediaLibrary,
+    /// Archived media by original path
+    pub media: HashMap<PathBuf, ArchivedMedia>,
+    /// Total bytes written
+    pub bytes_written: u64,
+    /// Library the archive was made from, that clip sources are resolved against
+    source: MediaLibrary,
+}
+
+impl ArchiveResult {
+    /// Rewrite a timeline's clip references to point into the archive
+    ///
+    /// Sources are resolved the way the original library stores them, then replaced with
+    /// paths relative to the archive, which `MediaLibrary::resolve_clip_sources` turns back
+    /// into absolute paths once the archive is opened. In points are shifted by the trim
+    /// offset so clips keep showing the same frames.
+    pub fn rewrite_timeline(&self, timeline: &mut Timeline) -> Result<()> {
+        let track_ids: Vec<String> = timeline.tracks().keys().cloned().collect();
+
+        for track_id in track_ids {
+            let track = timeline.get_track_mut(&track_id).map_err(|e| anyhow!("{}", e))?;
+            for clip in track.clips.iter_mut() {
+                let asset = clip.source_path.as_deref().and_then(|source| self.source.find_by_source(Path::new(source)));
+                let source = match asset {
+                    Some(asset) => self.source.resolve_path(&asset.id)?,
+                    None => continue,
+                };
+
+                if let Some(archived) = self.media.get(&source) {
+                    let stored_path = self.library.to_stored_path(&archived.archived_path);
+                    clip.source_path = Some(stored_path.to_string_lossy().to_string());
+                    if archived.trim_offset > 0.0 {
+                        let in_point = clip.in_point() - archived.trim_offset;
+                        clip.set_in_point(in_point.max(0.0));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Copy a project and the media it references into `dest`
+///
+/// The returned library and [`ArchiveResult::rewrite_timeline`] point every reference at
+/// the archived copies, relative to `dest`. The library is saved into the archive as
+/// `options.project_file`, so the archive opens without relinking wherever it is moved.
+pub fn archive_project(
+    library: &MediaLibrary,
+    timelines: &[&Timeline],
+    dest: &Path,
+    options: &ArchiveOptions,
+) -> Result<ArchiveResult> {
+    let media_dir = dest.join(&options.media_dir);
+    fs::create_dir_all(&media_dir)?;
+
+    // Collect the source ranges each timeline uses
+    let usage = media_usage(library, timelines);
+
+    let mut archived_library = library.clone();
+    archived_library.set_project_root(dest);
+
+    let mut media = HashMap::new();
+    let mut bytes_written = 0;
+    let mut used_names: HashSet<String> = HashSet::new();
+
+    for asset in library.assets() {
+        // Sub-clips are archived with their parent's media
+        if asset.subclip.is_some() {
+            continue;
+        }
+
+        let source_path = library.resolve_path(&asset.id)?;
+        let ranges = usage.usage(&asset.id).map(|usage| usage.used_ranges());
+
+        if ranges.is_none() && !options.include_unused {
+            debug!("Skipping unused asset {}", asset.id);
+            continue;
+        }
+
+        if asset.offline || !source_path.is_file() {
+            warn!("Asset {} is offline, not archived: {:?}", asset.id, source_path);
+            continue;
+        }
+
+        // Trim only time-based media with a known used range
+        let media_type = asset.info.as_ref().map(|info| info.media_type);
+        let trim_range = match (options.trim_to_used, ranges.as_deref(), media_type) {
+            (true, Some(ranges), Some(MediaType::Video)) | (true, Some(ranges), Some(MediaType::Audio)) => {
+                let duration = asset.info.as_ref().and_then(|info| info.duration);
+                Some(padded_range(ranges, options.handles, duration))
+            },
+            _ => None,
+        };
+        let has_video = media_type == Some(MediaType::Video);
+
+        // Trimmed media is re-encoded into an MP4 container; the name is de-duplicated
+        // with that extension, since `a.mov` and `a.mkv` both become `a.mp4`
+        let mut file_name = PathBuf::from(source_path.file_name().unwrap_or_default());
+        if trim_range.is_some() {
+            file_name.set_extension(if has_video { "mp4" } else { "m4a" });
+        }
+        let archived_path = unique_path(&media_dir, &file_name, &mut used_names);
+
+        let trim_offset = match trim_range {
+            Some((start, end)) => {
+                let has_audio = asset.info.as_ref().map_or(false, |info| info.channels.is_some());
+                trim_media(&source_path, &archived_path, start, end, has_video, has_audio)?;
+                start
+            },
+            None => {
+                fs::copy(&source_path, &archived_path)?;
+                0.0
+            },
+        };
+
+        bytes_written += fs::metadata(&archived_path).map(|m| m.len()).unwrap_or(0);
+        archived_library.relink(&asset.id, &archived_path)?;
+
+        // Keep sub-clips on the same frames of the trimmed media
+        if trim_offset > 0.0 {
+            let subclips: Vec<String> = library.subclips(&asset.id).into_iter().map(|subclip| subclip.id.clone()).collect();
+            for id in subclips {
+                if let Some(subclip) = archived_library.get_asset_mut(&id).and_then(|a| a.subclip.as_mut()) {
+                    subclip.in_point = (subclip.in_point - trim_offset).max(0.0);
+                    subclip.out_point = (subclip.out_point - trim_offset).max(subclip.in_point);
+                }
+            }
+        }
+
+        media.insert(
+            source_path.clone(),
+            ArchivedMedia {
+                asset_id: asset.id.clone(),
+                source_path,
+                archived_path,
+                trim_offset,
+            },
+        );
+    }
+
+    // Save the rewritten library in place of the original project
+    if let Some(project_file) = &options.project_file {
+        let json = serde_json::to_string_pretty(&archived_library)?;
+        fs::write(dest.join(project_file), &json)?;
+        bytes_written += json.len() as u64;
+    }
+
+    info!("Archived {} media files ({} bytes) to {:?}", media.len(), bytes_written, dest);
+
+    Ok(ArchiveResult {
+        library: archived_library,
+        media,
+        bytes_written,
+        source: library.clone(),
+    })
+}
+
+/// Single range covering all used ranges plus handles, clamped to the media duration
+///
+/// Gaps between used ranges are kept so a single file with continuous timing is produced.
+fn padded_range(ranges: &[(f64, f64)], handles: f64, duration: Option<f64>) -> (f64, f64) {
+    let start = ranges.iter().map(|r| r.0).fold(f64::INFINITY, f64::min);
+    let end = ranges.iter().map(|r| r.1).fold(0.0, f64::max);
+
+    let start = (start - handles).max(0.0);
+    let end = match duration {
+        Some(duration) => (end + handles).min(duration),
+        None => end + handles,
+    };
+
+    (start, end)
+}
+
+/// Pick a path in `dir` for `file_name` that doesn't collide with earlier archived media
+///
+/// Names are compared ignoring case, for case-insensitive file systems. A numbered name
+/// can be taken by a source file of that name too, so numbers are tried until one is free.
+pub(crate) fn unique_path(dir: &Path, file_name: &Path, used_names: &mut HashSet<String>) -> PathBuf {
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file_name.extension().map(|ext| ext.to_string_lossy());
+
+    let mut name = file_name.to_string_lossy().to_string();
+    let mut count = 1;
+    while !used_names.insert(name.to_lowercase()) {
+        count += 1;
+        name = match &extension {
+            Some(ext) => format!("{}_{}.{}", stem, count, ext),
+            None => format!("{}_{}", stem, count),
+        };
+    }
+
+    dir.join(name)
+}
+
+/// Re-encode the range [start, end) of a media file
+fn trim_media(source: &Path, output: &Path, start: f64, end: f64, has_video: bool, has_audio: bool) -> Result<()> {
+    if !gst::is_initialized() {
+        gst::init()?;
+    }
+
+    let builder = PipelineBuilder::new("archive-trim")?;
+    let input = builder.chain(&[
+        ElementSpec::file_source(source)?,
+        ElementSpec::new("decodebin"),
+    ])?;
+    let decoder = &input[1];
+    let sink = builder.chain(&[
+        ElementSpec::new("mp4mux"),
+        ElementSpec::file_sink(output)?,
+    ])?;
+    let muxer = &sink[0];
+
+    if has_video {
+        let video = builder.chain(&[
+            ElementSpec::new("queue"),
+            ElementSpec::new("videoconvert"),
+            ElementSpec::new("x264enc").property_from_str("speed-preset", "medium"),
+            ElementSpec::new("queue"),
+        ])?;
+        builder.link(video.last().unwrap(), muxer)?;
+        builder.link_dynamic(decoder, StreamKind::Video, &video[0]);
+    }
+    if has_audio {
+        let audio = builder.chain(&[
+            ElementSpec::new("queue"),
+            ElementSpec::new("audioconvert"),
+            ElementSpec::new("audioresample"),
+            ElementSpec::new("avenc_aac"),
+            ElementSpec::new("queue"),
+        ])?;
+        builder.link(audio.last().unwrap(), muxer)?;
+        builder.link_dynamic(decoder, StreamKind::Audio, &audio[0]);
+    }
+    debug!("Archive trim: {:?} [{}, {}) -> {:?}", source, start, end, output);
+
+    let pipeline = builder.build();
+    let bus = pipeline.bus().unwrap();
+
+    // Preroll, then seek to the kept range
+    pipeline.set_state(gst::State::Paused)?;
+    let (state_result, _, _) = pipeline.state(gst::ClockTime::from_seconds(10));
+    state_result.map_err(|_| anyhow!("Failed to preroll {:?}", source))?;
+
+    pipeline.seek(
+        1.0,
+        gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+        gst::SeekType::Set,
+        gst::ClockTime::from_nseconds((start * 1_000_000_000.0) as u64),
+        gst::SeekType::Set,
+        gst::ClockTime::from_nseconds((end * 1_000_000_000.0) as u64),
+    )?;
+
+    pipeline.set_state(gst::State::Playing)?;
+
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        match msg.view() {
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => {
+                pipeline.set_state(gst::State::Null)?;
+                return Err(anyhow!("Error trimming {:?}: {}", source, err.error()));
+            },
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null)?;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use super::super::media_library::MediaLibrary;
+    use super::super::project_archive::*;
+    use super::super::test_utils::create_test_dir;
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+    use anyhow::Result;
+
+    fn timeline_using(source: &Path) -> Result<Timeline> {
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        let mut track = Track::new("v1".to_string(), "Video 1".to_string());
+        track.clips.push(Clip::new("c1".to_string(), ClipType::Video, 0.0, 2.0).with_source(source.to_string_lossy().to_string()));
+        timeline.add_track(track)?;
+        Ok(timeline)
+    }
+
+    #[test]
+    fn test_unique_names_use_the_final_extension() {
+        let dir = Path::new("/archive/media");
+        let mut used = HashSet::new();
+        let mut name = |file_name: &str| unique_path(dir, Path::new(file_name), &mut used);
+
+        // `a.mov` and `a.mkv` are both trimmed into `a.mp4`
+        assert_eq!(name("a.mp4"), dir.join("a.mp4"));
+        assert_eq!(name("a.mp4"), dir.join("a_2.mp4"));
+
+        // A source already named like a numbered copy doesn't overwrite it
+        assert_eq!(name("a_2.mp4"), dir.join("a_2_2.mp4"));
+        assert_eq!(name("A.MP4"), dir.join("A_3.MP4"));
+
+        // Audio keeps its own extension, files without one stay without
+        assert_eq!(name("a.m4a"), dir.join("a.m4a"));
+        assert_eq!(name("README"), dir.join("README"));
+        assert_eq!(name("readme"), dir.join("readme_2"));
+    }
+
+    #[test]
+    fn test_archive_copies_media_and_rewrites_references() -> Result<()> {
+        let root = create_test_dir("archive", "copy")?;
+        let project = root.join("project");
+        let dest = root.join("archive");
+        let mut library = MediaLibrary::new(&project);
+
+        // Same name from two cards, one unused file
+        let card_a = project.join("card_a").join("clip.mov");
+        let card_b = project.join("card_b").join("clip.mov");
+        let unused = project.join("unused.wav");
+        for (path, content) in [(&card_a, "first card"), (&card_b, "second card take"), (&unused, "unused")] {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, content)?;
+        }
+        let ids: Vec<String> = [&card_a, &card_b, &unused].iter()
+            .map(|path| library.add_asset(path, None))
+            .collect::<Result<_>>()?;
+
+        let mut edit = Timeline::new(TimelineConfig::default());
+        let mut track = Track::new("v1".to_string(), "Video 1".to_string());
+        for (id, source) in [("c1", &card_a), ("c2", &card_b)] {
+            track.clips.push(Clip::new(id.to_string(), ClipType::Video, 0.0, 2.0).with_source(source.to_string_lossy().to_string()));
+        }
+        edit.add_track(track)?;
+
+        let options = ArchiveOptions {
+            project_file: Some("edit.aether".to_string()),
+            ..ArchiveOptions::default()
+        };
+        let result = archive_project(&library, &[&edit], &dest, &options)?;
+
+        // Used media only, with distinct names
+        assert_eq!(result.media.len(), 2);
+        let first = &result.media[&card_a].archived_path;
+        let second = &result.media[&card_b].archived_path;
+        assert_ne!(first, second);
+        assert_eq!(fs::read_to_string(first)?, "first card");
+        assert_eq!(fs::read_to_string(second)?, "second card take");
+        assert!(!result.media.contains_key(&unused));
+        let saved = fs::read_to_string(dest.join("edit.aether"))?;
+        assert_eq!(result.bytes_written, saved.len() as u64 + "first card".len() as u64 + "second card take".len() as u64);
+
+        // The library and timelines point into the archive
+        assert_eq!(&result.library.resolve_path(&ids[0])?, first);
+        assert_eq!(&result.library.resolve_path(&ids[1])?, second);
+        let mut rewritten = timeline_using(&card_b)?;
+        result.rewrite_timeline(&mut rewritten)?;
+        let relative = second.strip_prefix(&dest)?.to_path_buf();
+        assert_eq!(rewritten.get_track("v1")?.clips[0].source_path.as_deref(), Some(relative.to_string_lossy().as_ref()));
+
+        // Sources stored relative to the project resolve to the same media
+        let mut stored = timeline_using(Path::new("card_b/clip.mov"))?;
+        result.rewrite_timeline(&mut stored)?;
+        assert_eq!(stored.get_track("v1")?.clips[0].source_path, rewritten.get_track("v1")?.clips[0].source_path);
+
+        // The saved project opens wherever the archive is moved to
+        let moved = root.join("moved");
+        fs::rename(&dest, &moved)?;
+        let mut opened: MediaLibrary = serde_json::from_str(&saved)?;
+        opened.set_project_root(&moved);
+        assert_eq!(opened.resolve_path(&ids[1])?, moved.join(&relative));
+        assert_eq!(opened.resolve_clip_sources(&mut rewritten)?, 1);
+        let source = rewritten.get_track("v1")?.clips[0].source_path.clone().unwrap();
+        assert_eq!(fs::read_to_string(source)?, "second card take");
+
+        // Unused media is copied when asked for
+        let everything = ArchiveOptions { include_unused: true, ..ArchiveOptions::default() };
+        let result = archive_project(&library, &[&edit], &root.join("everything"), &everything)?;
+        assert_eq!(result.media.len(), 3);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
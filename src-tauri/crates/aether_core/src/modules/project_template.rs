@@ -0,0 +1,270 @@
+use anyhow::{anyhow, Result};
+use tracing::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::engine::frame_rate::FrameRate;
+use crate::engine::rendering::{ContainerFormat, EncoderOptions};
+use crate::engine::timeline::{Timeline, TimelineConfig, Track, TrackView};
+use super::color_grading::GradingPreset;
+use super::media_library::MediaLibrary;
+
+/// Current template file format version
+const TEMPLATE_VERSION: u32 = 1;
+
+/// Track layout entry in a template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackTemplate {
+    /// Track ID
+    pub id: String,
+    /// Track name
+    pub name: String,
+    /// Whether the track starts muted
+    pub is_muted: bool,
+    /// Whether the track starts locked
+    pub is_locked: bool,
+    /// Height, color and other view settings
+    #[serde(default)]
+    pub view: TrackView,
+}
+
+/// Named export preset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportPreset {
+    /// Preset name
+    pub name: String,
+    /// Output container
+    pub container: ContainerFormat,
+    /// Encoder settings
+    pub encoder: EncoderOptions,
+}
+
+/// Bin layout entry in a template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinTemplate {
+    /// Bin name
+    pub name: String,
+    /// Nested bins
+    pub children: Vec<BinTemplate>,
+}
+
+/// Text alignment for titles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TitleAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// Reusable title style
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TitleStyle {
+    /// Style name
+    pub name: String,
+    /// Font family
+    pub font_family: String,
+    /// Font size in points
+    pub font_size: f32,
+    /// Text color as RGBA
+    pub color: [u8; 4],
+    /// Background color as RGBA, if any
+    pub background: Option<[u8; 4]>,
+    /// Text alignment
+    pub alignment: TitleAlignment,
+}
+
+impl Default for TitleStyle {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            font_family: "Sans".to_string(),
+            font_size: 48.0,
+            color: [255, 255, 255, 255],
+            background: None,
+            alignment: TitleAlignment::Center,
+        }
+    }
+}
+
+/// Project template with the reusable parts of a project setup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectTemplate {
+    /// Template format version
+    pub version: u32,
+    /// Template name
+    pub name: String,
+    /// Timeline frame rate
+    pub fps: u32,
+    /// Exact timeline frame rate when it isn't whole, like 29.97; `fps` is it rounded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<FrameRate>,
+    /// Initial timeline duration in seconds
+    pub duration: f64,
+    /// Track layout
+    pub tracks: Vec<TrackTemplate>,
+    /// Export presets
+    pub export_presets: Vec<ExportPreset>,
+    /// Bin structure
+    pub bins: Vec<BinTemplate>,
+    /// Title styles
+    pub title_styles: Vec<TitleStyle>,
+    /// Color grading presets
+    pub color_presets: Vec<GradingPreset>,
+}
+
+/// A new project created from a template
+pub struct TemplateProject {
+    /// Timeline with the template's track layout
+    pub timeline: Timeline,
+    /// Empty library with the template's bins
+    pub library: MediaLibrary,
+    /// Export presets
+    pub export_presets: Vec<ExportPreset>,
+    /// Title styles
+    pub title_styles: Vec<TitleStyle>,
+    /// Color grading presets
+    pub color_presets: Vec<GradingPreset>,
+}
+
+impl ProjectTemplate {
+    /// Create an empty template
+    pub fn new(name: &str) -> Self {
+        let config = TimelineConfig::default();
+        Self {
+            version: TEMPLATE_VERSION,
+            name: name.to_string(),
+            fps: config.fps,
+            frame_rate: None,
+            duration: config.duration,
+            tracks: Vec::new(),
+            export_presets: Vec::new(),
+            bins: Vec::new(),
+            title_styles: Vec::new(),
+            color_presets: Vec::new(),
+        }
+    }
+
+    /// Capture a template from an existing project
+    ///
+    /// Only the structure is kept: tracks are saved without clips and bins without assets.
+    pub fn from_project(name: &str, timeline: &Timeline, library: &MediaLibrary, fps: u32) -> Self {
+        let mut tracks: Vec<TrackTemplate> = timeline
+            .tracks()
+            .values()
+            .map(|track| TrackTemplate {
+                id: track.id.clone(),
+                name: track.name.clone(),
+                is_muted: track.is_muted,
+                is_locked: track.is_locked,
+                view: track.view.clone(),
+            })
+            .collect();
+        tracks.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let frame_rate = Some(timeline.frame_rate()).filter(|rate| !rate.is_whole() && rate.timebase() == fps);
+        Self {
+            fps,
+            frame_rate,
+            duration: timeline.duration(),
+            tracks,
+            bins: Self::bin_tree(library, None),
+            ..Self::new(name)
+        }
+    }
+
+    /// Add an export preset
+    pub fn with_export_preset(mut self, preset: ExportPreset) -> Self {
+        self.export_presets.push(preset);
+        self
+    }
+
+    /// Add a title style
+    pub fn with_title_style(mut self, style: TitleStyle) -> Self {
+        self.title_styles.push(style);
+        self
+    }
+
+    /// Add a color grading preset
+    pub fn with_color_preset(mut self, preset: GradingPreset) -> Self {
+        self.color_presets.push(preset);
+        self
+    }
+
+    /// Save the template as JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        info!("Saved project template {} to {:?}", self.name, path);
+        Ok(())
+    }
+
+    /// Load a template from JSON
+    pub fn load(path: &Path) -> Result<Self> {
+        let template: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if template.version > TEMPLATE_VERSION {
+            return Err(anyhow!(
+                "Template {:?} has unsupported version {}",
+                path,
+                template.version
+            ));
+        }
+        Ok(template)
+    }
+
+    /// Create a new project from this template
+    pub fn instantiate(&self, project_root: &Path) -> Result<TemplateProject> {
+        let mut timeline = Timeline::new(TimelineConfig {
+            fps: self.fps,
+            duration: self.duration,
+        });
+        if let Some(frame_rate) = self.frame_rate {
+            timeline.set_frame_rate(frame_rate).map_err(|e| anyhow!("{}", e))?;
+        }
+
+        for track_template in &self.tracks {
+            let mut track = Track::new(track_template.id.clone(), track_template.name.clone());
+            track.is_muted = track_template.is_muted;
+            track.is_locked = track_template.is_locked;
+            track.view = track_template.view.clone();
+            timeline.add_track(track).map_err(|e| anyhow!("{}", e))?;
+        }
+
+        let mut library = MediaLibrary::new(project_root);
+        for bin in &self.bins {
+            Self::create_bins(&mut library, bin, None)?;
+        }
+
+        Ok(TemplateProject {
+            timeline,
+            library,
+            export_presets: self.export_presets.clone(),
+            title_styles: self.title_styles.clone(),
+            color_presets: self.color_presets.clone(),
+        })
+    }
+
+    /// Build the bin tree below `parent`
+    fn bin_tree(library: &MediaLibrary, parent: Option<&str>) -> Vec<BinTemplate> {
+        library
+            .bins()
+            .iter()
+            .filter(|bin| bin.parent.as_deref() == parent)
+            .map(|bin| BinTemplate {
+                name: bin.name.clone(),
+                children: Self::bin_tree(library, Some(&bin.id)),
+            })
+            .collect()
+    }
+
+    /// Recreate a bin and its children in a library
+    fn create_bins(library: &mut MediaLibrary, bin: &BinTemplate, parent: Option<&str>) -> Result<()> {
+        let id = library.create_bin(&bin.name, parent)?;
+        for child in &bin.children {
+            Self::create_bins(library, child, Some(&id))?;
+        }
+        Ok(())
+    }
+}
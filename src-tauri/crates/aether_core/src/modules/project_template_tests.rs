@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod tests {
+    use super::super::media_library::MediaLibrary;
+    use super::super::project_template::*;
+    use super::super::test_utils::create_test_dir;
+    use crate::engine::frame_rate::FrameRate;
+    use crate::engine::timeline::{Timeline, TimelineConfig, Track, TrackView};
+    use std::fs;
+    use anyhow::Result;
+
+    fn track(id: &str, name: &str, is_muted: bool, is_locked: bool, view: TrackView) -> TrackTemplate {
+        TrackTemplate {
+            id: id.to_string(),
+            name: name.to_string(),
+            is_muted,
+            is_locked,
+            view,
+        }
+    }
+
+    /// Template with a muted, a locked and a customized track and two levels of bins
+    fn documentary() -> ProjectTemplate {
+        let view = TrackView {
+            height: Some(120),
+            collapsed: true,
+            color: Some("#3a7bd5".to_string()),
+            ..TrackView::default()
+        };
+
+        let mut template = ProjectTemplate::new("Documentary").with_title_style(TitleStyle {
+            name: "Lower third".to_string(),
+            alignment: TitleAlignment::Left,
+            ..TitleStyle::default()
+        });
+        template.tracks = vec![
+            track("a1", "Dialogue", true, false, TrackView::default()),
+            track("v1", "Interviews", false, true, view),
+        ];
+        template.bins = vec![
+            BinTemplate {
+                name: "Footage".to_string(),
+                children: vec![
+                    BinTemplate { name: "Day 1".to_string(), children: Vec::new() },
+                    BinTemplate { name: "Day 2".to_string(), children: Vec::new() },
+                ],
+            },
+            BinTemplate { name: "Music".to_string(), children: Vec::new() },
+        ];
+        template
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() -> Result<()> {
+        let dir = create_test_dir("template", "round_trip")?;
+        let path = dir.join("templates").join("documentary.json");
+        let template = documentary();
+        template.save(&path)?;
+
+        let loaded = ProjectTemplate::load(&path)?;
+        assert_eq!(loaded.name, "Documentary");
+        assert_eq!(loaded.fps, template.fps);
+        assert_eq!(loaded.duration, template.duration);
+        assert_eq!(loaded.tracks, template.tracks);
+        assert_eq!(loaded.bins, template.bins);
+        assert_eq!(loaded.title_styles, template.title_styles);
+        assert_eq!(loaded.frame_rate, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_versions_are_rejected() -> Result<()> {
+        let dir = create_test_dir("template", "version")?;
+        let path = dir.join("future.json");
+        let mut template = documentary();
+        template.version += 1;
+        template.save(&path)?;
+
+        let error = ProjectTemplate::load(&path).unwrap_err();
+        assert!(error.to_string().contains("unsupported version"), "{}", error);
+
+        // Not a template at all
+        fs::write(&path, "{}")?;
+        assert!(ProjectTemplate::load(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_instantiate_recreates_tracks_and_bins() -> Result<()> {
+        let dir = create_test_dir("template", "instantiate")?;
+        let template = documentary();
+        let project = template.instantiate(&dir)?;
+
+        let dialogue = project.timeline.get_track("a1")?;
+        assert!(dialogue.is_muted && !dialogue.is_locked);
+        assert!(dialogue.clips.is_empty());
+        let interviews = project.timeline.get_track("v1")?;
+        assert!(!interviews.is_muted && interviews.is_locked);
+        assert_eq!(interviews.view, template.tracks[1].view);
+        assert_eq!(project.title_styles, template.title_styles);
+
+        // Nested bins keep their parents
+        let bins = project.library.bins();
+        assert_eq!(bins.len(), 4);
+        let footage = bins.iter().find(|bin| bin.name == "Footage").unwrap();
+        for day in ["Day 1", "Day 2"] {
+            let bin = bins.iter().find(|bin| bin.name == day).unwrap();
+            assert_eq!(bin.parent.as_deref(), Some(footage.id.as_str()));
+        }
+        assert_eq!(bins.iter().find(|bin| bin.name == "Music").unwrap().parent, None);
+
+        // Capturing the new project gives the same layout back
+        let captured = ProjectTemplate::from_project("Copy", &project.timeline, &project.library, template.fps);
+        assert_eq!(captured.tracks, template.tracks);
+        assert_eq!(captured.bins, template.bins);
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_whole_frame_rates_survive() -> Result<()> {
+        let dir = create_test_dir("template", "frame_rate")?;
+        let mut timeline = Timeline::new(TimelineConfig { fps: 30, duration: 60.0 });
+        timeline.set_frame_rate(FrameRate::FPS_29_97)?;
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string()))?;
+
+        let template = ProjectTemplate::from_project("NTSC", &timeline, &MediaLibrary::new(&dir), 30);
+        assert_eq!(template.frame_rate, Some(FrameRate::FPS_29_97));
+
+        let path = dir.join("ntsc.json");
+        template.save(&path)?;
+        let project = ProjectTemplate::load(&path)?.instantiate(&dir)?;
+        assert_eq!(project.timeline.frame_rate(), FrameRate::FPS_29_97);
+
+        // Whole rates are stored as `fps` alone
+        let pal = Timeline::new(TimelineConfig { fps: 25, duration: 60.0 });
+        let whole = ProjectTemplate::from_project("PAL", &pal, &MediaLibrary::new(&dir), 25);
+        assert_eq!(whole.frame_rate, None);
+        assert_eq!(whole.instantiate(&dir)?.timeline.frame_rate(), FrameRate::FPS_25);
+        Ok(())
+    }
+}
@@ -0,0 +1,578 @@
+//! Quality control reports for finished deliveries
+//!
+//! `run_qc` decodes an export once with an `AnalysisPass` and measures its loudness and
+//! peaks, black and frozen stretches of picture and audio problems. The results, the
+//! stream summary and pass/fail checks against a `QcSpec` make up a `QcReport`, which is
+//! written as JSON for tools or as a PDF for people.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+use super::analysis_pass::{AnalysisPass, Analyzer, SILENCE_DB};
+use super::audio_detection::{AudioCheckOptions, AudioIssue, AudioIssueDetector, AudioIssueKind};
+use super::file_manager::MediaInfo;
+use super::picture_detection::{BlackDetector, FreezeDetector};
+
+/// Rate audio is measured at; K-weighting needs the full audible band
+pub const QC_SAMPLE_RATE: u32 = 48000;
+
+/// Length of a momentary loudness block, and the step between blocks, in seconds
+const LOUDNESS_BLOCK: f64 = 0.4;
+pub const LOUDNESS_STEP: f64 = 0.1;
+
+/// Blocks quieter than this are left out of integrated loudness, in LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this far below the ungated loudness are left out too, in LU
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Lines on a page of the PDF report
+const PDF_LINES_PER_PAGE: usize = 56;
+
+/// Limits a delivery is checked against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QcSpec {
+    /// Integrated loudness target, in LUFS
+    pub target_loudness: f64,
+    /// Allowed distance from the target, in LU
+    pub loudness_tolerance: f64,
+    /// Highest allowed sample peak, in dBFS
+    pub max_peak: f64,
+    /// Shortest black stretch reported, in seconds
+    pub min_black: f64,
+    /// Shortest frozen stretch reported, in seconds
+    pub min_freeze: f64,
+    /// Dropout, DC offset, clipping and phase limits
+    pub audio: AudioCheckOptions,
+    /// Don't fail on black at the very start or end, like a fade from or to black
+    pub allow_black_at_ends: bool,
+}
+
+impl Default for QcSpec {
+    fn default() -> Self {
+        // EBU R 128 delivery
+        Self {
+            target_loudness: -23.0,
+            loudness_tolerance: 1.0,
+            max_peak: -1.0,
+            min_black: 1.0,
+            min_freeze: 2.0,
+            audio: AudioCheckOptions::default(),
+            allow_black_at_ends: true,
+        }
+    }
+}
+
+/// A stretch of the file, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QcSegment {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl QcSegment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Integrated loudness, peak and RMS level of stereo or mono audio
+///
+/// Loudness follows ITU-R BS.1770: each channel K-weighted on its own, their powers
+/// summed, in 400 ms blocks, gated at -70 LUFS and 10 LU below the ungated level. A
+/// mono source is measured as one channel. Peaks are kept per channel, so clipping on
+/// one side isn't hidden by the other.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    /// K-weighting of each channel
+    filters: Vec<[Biquad; 2]>,
+    sample_rate: u32,
+    step_len: usize,
+    step_squares: f64,
+    step_count: usize,
+    /// Summed mean squares of the channels in every 100 ms step of K-weighted audio
+    steps: Vec<f64>,
+    step_peak: f32,
+    /// Highest absolute sample of any channel in every 100 ms step
+    step_peaks: Vec<f32>,
+    /// Highest absolute sample of each channel
+    peaks: Vec<f32>,
+    total_squares: f64,
+    total_count: usize,
+}
+
+impl Default for LoudnessMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoudnessMeter {
+    pub fn new() -> Self {
+        Self {
+            filters: Vec::new(),
+            sample_rate: QC_SAMPLE_RATE,
+            step_len: 0,
+            step_squares: 0.0,
+            step_count: 0,
+            steps: Vec::new(),
+            step_peak: 0.0,
+            step_peaks: Vec::new(),
+            peaks: Vec::new(),
+            total_squares: 0.0,
+            total_count: 0,
+        }
+    }
+
+    /// Loudness of every 400 ms block, starting every 100 ms, in LUFS
+    pub fn momentary(&self) -> Vec<f64> {
+        let per_block = (LOUDNESS_BLOCK / LOUDNESS_STEP).round() as usize;
+        self.steps.windows(per_block)
+            .map(|block| loudness(block.iter().sum::<f64>() / per_block as f64))
+            .collect()
+    }
+
+    /// Gated loudness of the whole file, `None` when it is all below the absolute gate
+    pub fn integrated(&self) -> Option<f64> {
+        integrated_loudness(&self.steps)
+    }
+
+    /// Summed mean squares of K-weighted channels in every 100 ms step, for measuring
+    /// parts of the file
+    pub fn steps(&self) -> &[f64] {
+        &self.steps
+    }
+
+    /// Highest absolute sample of any channel in every 100 ms step
+    pub fn step_peaks(&self) -> &[f32] {
+        &self.step_peaks
+    }
+
+    /// Highest absolute sample of any channel, in dBFS
+    pub fn peak(&self) -> f64 {
+        to_dbfs(self.peaks.iter().fold(0.0f32, |peak, &channel| peak.max(channel)) as f64)
+    }
+
+    /// Highest absolute sample of each channel, in dBFS; one for a mono source
+    pub fn channel_peaks(&self) -> Vec<f64> {
+        self.peaks.iter().map(|&peak| to_dbfs(peak as f64)).collect()
+    }
+
+    /// Unweighted RMS level, in dBFS
+    pub fn rms(&self) -> f64 {
+        to_dbfs((self.total_squares / self.total_count.max(1) as f64).sqrt())
+    }
+}
+
+/// Gated loudness of the 100 ms `steps` of a `LoudnessMeter`, in LUFS, `None` when they
+/// are all below the absolute gate
+pub fn integrated_loudness(steps: &[f64]) -> Option<f64> {
+    let per_block = (LOUDNESS_BLOCK / LOUDNESS_STEP).round() as usize;
+    let blocks: Vec<f64> = steps.windows(per_block)
+        .map(|block| block.iter().sum::<f64>() / per_block as f64)
+        .filter(|power| loudness(*power) > ABSOLUTE_GATE)
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+    let relative_gate = loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+    let gated: Vec<f64> = blocks.into_iter().filter(|power| loudness(*power) > relative_gate).collect();
+    Some(loudness(gated.iter().sum::<f64>() / gated.len().max(1) as f64))
+}
+
+impl LoudnessMeter {
+    /// Measure the next samples of each channel
+    fn measure(&mut self, channels: &[&[f32]], sample_rate: u32) {
+        if sample_rate != self.sample_rate || self.step_len == 0 || self.filters.len() != channels.len() {
+            self.filters = vec![k_weighting(sample_rate); channels.len()];
+            self.sample_rate = sample_rate;
+            self.step_len = ((LOUDNESS_STEP * sample_rate as f64) as usize).max(1);
+        }
+        if self.peaks.len() < channels.len() {
+            self.peaks.resize(channels.len(), 0.0);
+        }
+        let frames = channels.iter().map(|samples| samples.len()).min().unwrap_or(0);
+        for index in 0..frames {
+            for (channel, samples) in channels.iter().enumerate() {
+                let sample = samples[index];
+                self.peaks[channel] = self.peaks[channel].max(sample.abs());
+                self.step_peak = self.step_peak.max(sample.abs());
+                self.total_squares += (sample as f64).powi(2);
+                self.total_count += 1;
+
+                // Left and right both weigh 1.0, so their powers just add up
+                let weighted = self.filters[channel].iter_mut().fold(sample as f64, |x, stage| stage.process(x));
+                self.step_squares += weighted * weighted;
+            }
+            self.step_count += 1;
+            if self.step_count == self.step_len {
+                self.steps.push(self.step_squares / self.step_len as f64);
+                self.step_peaks.push(self.step_peak);
+                self.step_peak = 0.0;
+                self.step_squares = 0.0;
+                self.step_count = 0;
+            }
+        }
+    }
+}
+
+impl Analyzer for LoudnessMeter {
+    fn wants_stereo(&self) -> bool {
+        true
+    }
+
+    fn stereo(&mut self, left: &[f32], right: &[f32], sample_rate: u32) {
+        if std::ptr::eq(left, right) {
+            self.measure(&[left], sample_rate);
+        } else {
+            self.measure(&[left, right], sample_rate);
+        }
+    }
+}
+
+/// Format of the delivered file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamSummary {
+    pub container: Option<String>,
+    pub size: u64,
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+impl StreamSummary {
+    pub fn from_media_info(info: &MediaInfo) -> Self {
+        Self {
+            container: info.path.extension().map(|extension| extension.to_string_lossy().to_lowercase()),
+            size: info.size,
+            duration: info.duration,
+            codec: info.codec.clone(),
+            width: info.width,
+            height: info.height,
+            frame_rate: info.frame_rate,
+            sample_rate: info.sample_rate,
+            channels: info.channels,
+        }
+    }
+}
+
+/// Loudness measurements, in LUFS and dBFS
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessSummary {
+    /// `None` for a silent file
+    pub integrated: Option<f64>,
+    pub max_momentary: Option<f64>,
+    /// Sample peak of the loudest channel
+    pub peak: f64,
+    /// Sample peak of each channel
+    #[serde(default)]
+    pub channel_peaks: Vec<f64>,
+    pub rms: f64,
+}
+
+/// Outcome of one check against the spec
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Everything measured about a delivery, and whether it meets the spec
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcReport {
+    pub file: PathBuf,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub spec: QcSpec,
+    pub stream: StreamSummary,
+    pub loudness: LoudnessSummary,
+    pub black: Vec<QcSegment>,
+    pub freezes: Vec<QcSegment>,
+    pub audio_issues: Vec<AudioIssue>,
+    pub checks: Vec<QcCheck>,
+}
+
+impl QcReport {
+    /// Assemble a report from analyzers that have seen the whole file
+    pub fn from_analysis(
+        info: &MediaInfo,
+        spec: &QcSpec,
+        meter: &LoudnessMeter,
+        black: &BlackDetector,
+        freeze: &FreezeDetector,
+        audio: &AudioIssueDetector,
+    ) -> Self {
+        let loudness = LoudnessSummary {
+            integrated: meter.integrated(),
+            max_momentary: meter.momentary().into_iter().reduce(f64::max),
+            peak: meter.peak(),
+            channel_peaks: meter.channel_peaks(),
+            rms: meter.rms(),
+        };
+        let mut report = Self {
+            file: info.path.clone(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            spec: spec.clone(),
+            stream: StreamSummary::from_media_info(info),
+            loudness,
+            black: black.segments(),
+            freezes: freeze.segments(),
+            audio_issues: audio.issues(),
+            checks: Vec::new(),
+        };
+        report.checks = report.run_checks();
+        report
+    }
+
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The report as plain lines of text, as laid out in the PDF
+    pub fn to_text(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("QC report: {}", self.file.display()),
+            format!("Result: {}", if self.passed() { "PASS" } else { "FAIL" }),
+            String::new(),
+            "Checks".to_string(),
+        ];
+        for check in &self.checks {
+            lines.push(format!("  [{}] {}: {}", if check.passed { "pass" } else { "FAIL" }, check.name, check.detail));
+        }
+
+        let stream = &self.stream;
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+        lines.push(String::new());
+        lines.push("Streams".to_string());
+        lines.push(format!("  Container: {}, {} bytes", or_unknown(stream.container.clone()), stream.size));
+        lines.push(format!("  Duration: {}", or_unknown(stream.duration.map(|d| format!("{:.3} s", d)))));
+        lines.push(format!("  Codec: {}", or_unknown(stream.codec.clone())));
+        lines.push(format!(
+            "  Video: {} at {}",
+            or_unknown(stream.width.zip(stream.height).map(|(w, h)| format!("{}x{}", w, h))),
+            or_unknown(stream.frame_rate.map(|rate| format!("{:.3} fps", rate)))
+        ));
+        lines.push(format!(
+            "  Audio: {}, {}",
+            or_unknown(stream.sample_rate.map(|rate| format!("{} Hz", rate))),
+            or_unknown(stream.channels.map(|channels| format!("{} channels", channels)))
+        ));
+
+        let loudness = &self.loudness;
+        lines.push(String::new());
+        lines.push("Loudness".to_string());
+        lines.push(format!("  Integrated: {}", or_unknown(loudness.integrated.map(|l| format!("{:.1} LUFS", l)))));
+        lines.push(format!("  Max momentary: {}", or_unknown(loudness.max_momentary.map(|l| format!("{:.1} LUFS", l)))));
+        lines.push(format!("  Sample peak: {:.1} dBFS, RMS: {:.1} dBFS", loudness.peak, loudness.rms));
+        if loudness.channel_peaks.len() > 1 {
+            let peaks: Vec<String> = loudness.channel_peaks.iter().map(|peak| format!("{:.1}", peak)).collect();
+            lines.push(format!("  Channel peaks: {} dBFS", peaks.join(" / ")));
+        }
+
+        for (title, segments) in [("Black", &self.black), ("Freezes", &self.freezes)] {
+            lines.push(String::new());
+            lines.push(format!("{} ({})", title, segments.len()));
+            for segment in segments {
+                lines.push(format!("  {:.3} s to {:.3} s ({:.3} s)", segment.start, segment.end, segment.duration()));
+            }
+        }
+        lines.push(String::new());
+        lines.push(format!("Audio issues ({})", self.audio_issues.len()));
+        for issue in &self.audio_issues {
+            lines.push(format!("  {}", issue.describe()));
+        }
+        lines
+    }
+
+    /// The report as a PDF document
+    pub fn to_pdf(&self) -> Vec<u8> {
+        text_pdf(&self.to_text())
+    }
+
+    /// Write the report, as a PDF when the path ends in `.pdf` and as JSON otherwise
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_pdf = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+        let contents = if is_pdf { self.to_pdf() } else { self.to_json()?.into_bytes() };
+        fs::write(path, contents).with_context(|| format!("Failed to write QC report {}", path.display()))
+    }
+
+    fn run_checks(&self) -> Vec<QcCheck> {
+        let spec = &self.spec;
+        let check = |name: &str, passed: bool, detail: String| QcCheck { name: name.to_string(), passed, detail };
+        let mut checks = Vec::new();
+
+        checks.push(match self.loudness.integrated {
+            Some(integrated) => check(
+                "Loudness",
+                (integrated - spec.target_loudness).abs() <= spec.loudness_tolerance,
+                format!("{:.1} LUFS, target {:.1} +/- {:.1}", integrated, spec.target_loudness, spec.loudness_tolerance),
+            ),
+            None => check("Loudness", false, "No audio above the gate".to_string()),
+        });
+        checks.push(check(
+            "Peak level",
+            self.loudness.peak <= spec.max_peak,
+            format!("{:.1} dBFS, at most {:.1}", self.loudness.peak, spec.max_peak),
+        ));
+
+        let duration = self.stream.duration.unwrap_or(f64::INFINITY);
+        let at_ends = |segment: &&QcSegment| segment.start <= 0.0 || segment.end >= duration - LOUDNESS_STEP;
+        let black = self.black.iter().filter(|segment| !(spec.allow_black_at_ends && at_ends(segment))).count();
+        checks.push(check("Black", black == 0, format!("{} stretches of black", black)));
+        checks.push(check("Freezes", self.freezes.is_empty(), format!("{} frozen stretches", self.freezes.len())));
+        let audio = [
+            ("Audio dropouts", AudioIssueKind::Dropout, "dropouts"),
+            ("DC offset", AudioIssueKind::DcOffset, "stretches with DC offset"),
+            ("Clipping", AudioIssueKind::Clipping, "clipped stretches"),
+            ("Phase", AudioIssueKind::PhaseCancellation, "stretches of phase cancellation"),
+        ];
+        for (name, kind, what) in audio {
+            let count = self.audio_issues.iter().filter(|issue| issue.kind == kind).count();
+            checks.push(check(name, count == 0, format!("{} {}", count, what)));
+        }
+        checks
+    }
+}
+
+/// Measure a finished export and check it against `spec`
+///
+/// `info` describes the file, as read by `FileManager::get_media_info`.
+pub fn run_qc(path: &Path, info: &MediaInfo, spec: &QcSpec) -> Result<QcReport> {
+    let mut meter = LoudnessMeter::new();
+    let mut black = BlackDetector::new(spec.min_black);
+    let mut freeze = FreezeDetector::new(spec.min_freeze);
+    let mut audio = AudioIssueDetector::new(spec.audio.clone());
+    AnalysisPass::new()
+        .with_sample_rate(QC_SAMPLE_RATE)
+        .with_analyzer(&mut meter)
+        .with_analyzer(&mut black)
+        .with_analyzer(&mut freeze)
+        .with_analyzer(&mut audio)
+        .run(path)?;
+
+    let report = QcReport::from_analysis(info, spec, &meter, &black, &freeze, &audio);
+    info!("QC of {:?}: {}", path, if report.passed() { "passed" } else { "failed" });
+    Ok(report)
+}
+
+/// Second-order IIR section
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        // Transposed direct form II
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting at `sample_rate`: a high shelf for the head, then a high pass
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate.max(1) as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+/// Loudness of the summed mean squares of K-weighted channels, in LUFS
+fn loudness(power: f64) -> f64 {
+    if power <= 0.0 {
+        return SILENCE_DB;
+    }
+    (-0.691 + 10.0 * power.log10()).max(SILENCE_DB)
+}
+
+fn to_dbfs(amplitude: f64) -> f64 {
+    if amplitude <= 0.0 {
+        return SILENCE_DB;
+    }
+    (20.0 * amplitude.log10()).max(SILENCE_DB)
+}
+
+/// A PDF of lines of text in Helvetica, split into A4 pages
+///
+/// Characters outside printable ASCII are written as `?`.
+fn text_pdf(lines: &[String]) -> Vec<u8> {
+    let pages: Vec<&[String]> = if lines.is_empty() { vec![&[]] } else { lines.chunks(PDF_LINES_PER_PAGE).collect() };
+
+    // Objects 1-3 are the catalog, page tree and font; each page then takes two
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        String::new(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+    let mut kids = Vec::new();
+    for page in &pages {
+        let page_id = objects.len() + 1;
+        kids.push(format!("{} 0 R", page_id));
+        let mut content = String::from("BT\n/F1 10 Tf\n14 TL\n50 800 Td\n");
+        for line in page.iter() {
+            let text: String = line.chars()
+                .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+                .collect();
+            let _ = writeln!(content, "({}) Tj T*", text.replace('\\', "\\\\").replace('(', "\\(").replace(')', "\\)"));
+        }
+        content.push_str("ET\n");
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            page_id + 1
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+    }
+    objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len());
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = write!(pdf, "{} 0 obj\n{}\nendobj\n", index + 1, object);
+    }
+    let xref = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(pdf, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref);
+    pdf.into_bytes()
+}
@@ -0,0 +1,214 @@
+#[cfg(test)]
+mod tests {
+    use super::super::analysis_pass::{AnalysisFrame, AnalysisPass};
+    use super::super::audio_detection::{AudioIssueDetector, AudioIssueKind};
+    use super::super::file_manager::{MediaInfo, MediaType};
+    use super::super::picture_detection::{BlackDetector, FreezeDetector};
+    use super::super::qc_report::*;
+    use super::super::test_utils::create_test_dir;
+    use std::collections::HashMap;
+    use std::f32::consts::PI;
+    use std::fs;
+    use std::path::PathBuf;
+    use anyhow::Result;
+
+    const RATE: u32 = 48000;
+    const FPS: f64 = 25.0;
+
+    /// A 997 Hz tone, the BS.1770 reference frequency
+    fn tone(amplitude: f32, seconds: f64) -> Vec<f32> {
+        (0..(seconds * RATE as f64) as usize)
+            .map(|i| amplitude * (i as f32 * 2.0 * PI * 997.0 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn frame(index: usize, rgb: Vec<u8>) -> AnalysisFrame {
+        AnalysisFrame { timestamp: index as f64 / FPS, width: 8, height: 4, rgb }
+    }
+
+    /// A different busy picture on every frame
+    fn moving(index: usize) -> Vec<u8> {
+        (0..8 * 4 * 3).map(|p| ((index * 37 + p * 11) % 256) as u8).collect()
+    }
+
+    fn info(duration: f64) -> MediaInfo {
+        MediaInfo {
+            path: PathBuf::from("/exports/spot.MP4"),
+            media_type: MediaType::Video,
+            size: 1234,
+            duration: Some(duration),
+            width: Some(1920),
+            height: Some(1080),
+            frame_rate: Some(FPS),
+            codec: Some("h264".to_string()),
+            sample_rate: Some(RATE),
+            channels: Some(2),
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_loudness_meter() {
+        // A -20 dBFS tone reads 3 dB lower as loudness
+        let mut meter = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut meter);
+        for chunk in tone(0.1, 5.0).chunks(1000) {
+            pass.push_audio(chunk);
+        }
+        pass.finish();
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{}", integrated);
+        assert!((meter.peak() + 20.0).abs() < 0.01);
+        assert!((meter.rms() + 23.0).abs() < 0.05);
+        assert_eq!(meter.momentary().len(), 47);
+
+        // Quiet passages are gated out instead of dragging the level down
+        let mut gated = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut gated);
+        pass.push_audio(&tone(0.1, 5.0));
+        pass.push_audio(&tone(0.001, 5.0));
+        pass.push_audio(&vec![0.0; 5 * RATE as usize]);
+        pass.finish();
+        assert!((gated.integrated().unwrap() - integrated).abs() < 0.2);
+
+        let silent = LoudnessMeter::new();
+        assert_eq!(silent.integrated(), None);
+    }
+
+    #[test]
+    fn test_qc_report() -> Result<()> {
+        let spec = QcSpec::default();
+        let mut meter = LoudnessMeter::new();
+        let mut black = BlackDetector::new(spec.min_black);
+        let mut freeze = FreezeDetector::new(spec.min_freeze);
+        let mut audio = AudioIssueDetector::new(spec.audio.clone());
+        let mut pass = AnalysisPass::new()
+            .with_sample_rate(RATE)
+            .with_analyzer(&mut meter)
+            .with_analyzer(&mut black)
+            .with_analyzer(&mut freeze)
+            .with_analyzer(&mut audio);
+
+        // A second of black, three of picture, three frozen, 1.6 of black, then picture
+        let still = moving(7);
+        for index in 0..250 {
+            let rgb = match index {
+                0..25 | 175..215 => vec![0; 8 * 4 * 3],
+                100..175 => still.clone(),
+                _ => moving(index),
+            };
+            pass.push_frame(&frame(index, rgb));
+        }
+
+        // A 20 ms dropout, then a long pause that is meant to be there
+        pass.push_audio(&tone(0.1, 1.0));
+        pass.push_audio(&vec![0.0; RATE as usize / 50]);
+        pass.push_audio(&tone(0.1, 4.0));
+        pass.push_audio(&vec![0.0; 2 * RATE as usize]);
+        pass.push_audio(&tone(0.1, 3.0));
+        pass.finish();
+
+        assert_eq!(black.segments(), vec![QcSegment { start: 0.0, end: 1.0 }, QcSegment { start: 7.0, end: 8.6 }]);
+        assert_eq!(freeze.segments(), vec![QcSegment { start: 4.0, end: 7.0 }]);
+        let issues = audio.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, AudioIssueKind::Dropout);
+        assert!((issues[0].start - 1.0).abs() < 1e-9);
+        assert!((issues[0].duration() - 0.02).abs() < 1e-4);
+
+        let report = QcReport::from_analysis(&info(10.0), &spec, &meter, &black, &freeze, &audio);
+        assert_eq!(report.stream.container.as_deref(), Some("mp4"));
+        let failed: Vec<&str> = report.checks.iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.name.as_str())
+            .collect();
+        // The opening black is allowed, the one in the middle isn't
+        assert_eq!(failed, vec!["Black", "Freezes", "Audio dropouts"]);
+        assert!(!report.passed());
+
+        let dir = create_test_dir("qc_report", "report")?;
+        report.write(&dir.join("qc.json"))?;
+        let parsed: QcReport = serde_json::from_str(&fs::read_to_string(dir.join("qc.json"))?)?;
+        assert_eq!(parsed, report);
+
+        report.write(&dir.join("qc.pdf"))?;
+        let pdf = fs::read(dir.join("qc.pdf"))?;
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.trim_end().ends_with("%%EOF"));
+        assert!(text.contains("(Result: FAIL) Tj"));
+        assert!(text.contains("1920x1080 at 25.000 fps"));
+
+        // The cross-reference table points at the objects
+        let xref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse()?;
+        assert!(text[xref..].starts_with("xref"));
+        let first_object: usize = text[xref..].lines().nth(3).unwrap()[..10].parse()?;
+        assert!(text[first_object..].starts_with("1 0 obj"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_loudness_meter_sums_stereo_channels() {
+        // -23 dBFS of the reference tone on each side reads -23 LUFS, where their mix
+        // alone would read 3 LU lower
+        let level = 10f32.powf(-23.0 / 20.0);
+        let (left, right) = (tone(level, 5.0), tone(level, 5.0));
+        let mut meter = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut meter);
+        for (left, right) in left.chunks(1000).zip(right.chunks(1000)) {
+            pass.push_stereo(left, right);
+        }
+        pass.finish();
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{}", integrated);
+        assert_eq!(meter.channel_peaks().len(), 2);
+        assert!((meter.peak() + 23.0).abs() < 0.01);
+
+        // Sound on one side only is one channel's worth
+        let mut one_sided = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut one_sided);
+        pass.push_stereo(&left, &vec![0.0; left.len()]);
+        pass.finish();
+        assert!((one_sided.integrated().unwrap() + 26.0).abs() < 0.1);
+
+        // The delivery passes the loudness check
+        let report = QcReport::from_analysis(
+            &info(5.0),
+            &QcSpec::default(),
+            &meter,
+            &BlackDetector::new(1.0),
+            &FreezeDetector::new(1.0),
+            &AudioIssueDetector::new(QcSpec::default().audio),
+        );
+        assert!(report.checks.iter().find(|check| check.name == "Loudness").unwrap().passed);
+    }
+
+    #[test]
+    fn test_loudness_meter_catches_one_clipped_channel() {
+        // A clean left channel and a right one driven into clipping
+        let left = tone(0.1, 3.0);
+        let right: Vec<f32> = tone(1.5, 3.0).into_iter().map(|sample| sample.clamp(-1.0, 1.0)).collect();
+        let mut meter = LoudnessMeter::new();
+        let mut pass = AnalysisPass::new().with_sample_rate(RATE).with_analyzer(&mut meter);
+        pass.push_stereo(&left, &right);
+        pass.finish();
+
+        let peaks = meter.channel_peaks();
+        assert!((peaks[0] + 20.0).abs() < 0.01, "{:?}", peaks);
+        assert!(peaks[1].abs() < 0.01, "{:?}", peaks);
+        assert!(meter.peak().abs() < 0.01);
+        assert!(meter.step_peaks().iter().all(|&peak| peak >= 1.0));
+
+        let report = QcReport::from_analysis(
+            &info(3.0),
+            &QcSpec::default(),
+            &meter,
+            &BlackDetector::new(1.0),
+            &FreezeDetector::new(1.0),
+            &AudioIssueDetector::new(QcSpec::default().audio),
+        );
+        assert!(!report.checks.iter().find(|check| check.name == "Peak level").unwrap().passed);
+        assert_eq!(report.loudness.channel_peaks, peaks);
+        assert!(report.to_text().iter().any(|line| line.starts_with("  Channel peaks: -20.0 / 0.0")));
+    }
+}
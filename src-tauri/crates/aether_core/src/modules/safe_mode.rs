@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+/// Present while a session runs; finding it at startup means the last session crashed
+const MARKER_FILE: &str = "session.lock";
+
+/// Plugins and effects loaded during the session, in order
+const LOAD_LOG_FILE: &str = "last_loaded.log";
+
+const QUARANTINE_FILE: &str = "quarantine.json";
+
+/// Safe mode of the running session, consulted whenever a plugin or effect is created
+static ACTIVE: Lazy<RwLock<Option<Arc<SafeMode>>>> = Lazy::new(|| RwLock::new(None));
+
+/// A plugin or effect that is no longer loaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// Element factory or effect description
+    pub name: String,
+    pub reason: String,
+    /// Unix time in seconds
+    pub quarantined_at: u64,
+}
+
+/// What startup found about the previous session
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartupReport {
+    /// The previous session ended without `end_session`
+    pub crashed: bool,
+    /// Quarantined because of that crash
+    pub quarantined: Option<String>,
+}
+
+/// Log of loads, flushed per entry so it survives the crash it is meant to explain
+struct LoadLog {
+    file: Mutex<File>,
+}
+
+impl LoadLog {
+    fn record(&self, event: &str, name: &str) {
+        let mut file = self.file.lock().unwrap();
+        let result = writeln!(file, "{}\t{}", event, name).and_then(|_| file.sync_data());
+        if let Err(e) = result {
+            warn!("Failed to record {} of {} in load log: {}", event, name, e);
+        }
+    }
+}
+
+/// Marks a plugin or effect as loading until dropped
+///
+/// A crash while the guard is alive blames that plugin directly.
+pub struct LoadGuard {
+    log: Option<Arc<LoadLog>>,
+    name: String,
+}
+
+impl Drop for LoadGuard {
+    fn drop(&mut self) {
+        if let Some(log) = &self.log {
+            log.record("end", &self.name);
+        }
+    }
+}
+
+/// Crash tracking and plugin quarantine across sessions
+///
+/// Each session writes a marker file and logs every plugin and effect it loads. If the
+/// marker is still there at the next start the session crashed, and the plugin it was
+/// loading, or failing that the last one it loaded, is quarantined so it can't crash
+/// the app again.
+pub struct SafeMode {
+    dir: PathBuf,
+    log: Arc<LoadLog>,
+    quarantine: RwLock<Vec<QuarantineEntry>>,
+}
+
+impl SafeMode {
+    /// Start a session with state kept in `dir`
+    pub fn start(dir: impl Into<PathBuf>) -> Result<(Self, StartupReport)> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create safe mode directory {:?}: {}", dir, e))?;
+
+        let mut quarantine = Self::read_quarantine(&dir.join(QUARANTINE_FILE));
+        let crashed = dir.join(MARKER_FILE).exists();
+        let mut quarantined = None;
+
+        if crashed {
+            match Self::suspect(&dir.join(LOAD_LOG_FILE)) {
+                Some((name, reason)) if !quarantine.iter().any(|entry| entry.name == name) => {
+                    error!("Last session crashed, quarantining {}: {}", name, reason);
+                    quarantine.push(QuarantineEntry {
+                        name: name.clone(),
+                        reason,
+                        quarantined_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                    });
+                    quarantined = Some(name);
+                },
+                Some((name, _)) => warn!("Last session crashed after loading {}, which is already quarantined", name),
+                None => warn!("Last session crashed before loading any plugin"),
+            }
+        }
+
+        fs::write(dir.join(MARKER_FILE), std::process::id().to_string())
+            .map_err(|e| anyhow!("Failed to write session marker in {:?}: {}", dir, e))?;
+        let log = File::create(dir.join(LOAD_LOG_FILE))
+            .map_err(|e| anyhow!("Failed to create load log in {:?}: {}", dir, e))?;
+
+        let safe_mode = Self {
+            dir,
+            log: Arc::new(LoadLog { file: Mutex::new(log) }),
+            quarantine: RwLock::new(quarantine),
+        };
+        if quarantined.is_some() {
+            safe_mode.save_quarantine()?;
+        }
+
+        Ok((safe_mode, StartupReport { crashed, quarantined }))
+    }
+
+    /// Mark the session as ended cleanly
+    pub fn end_session(&self) -> Result<()> {
+        fs::remove_file(self.dir.join(MARKER_FILE))
+            .map_err(|e| anyhow!("Failed to remove session marker in {:?}: {}", self.dir, e))
+    }
+
+    /// Record that `name` is being loaded, or refuse if it is quarantined
+    pub fn begin_load(&self, name: &str) -> Result<LoadGuard> {
+        if self.is_quarantined(name) {
+            return Err(anyhow!("{} is quarantined after crashing a previous session", name));
+        }
+
+        self.log.record("begin", name);
+        Ok(LoadGuard {
+            log: Some(self.log.clone()),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn is_quarantined(&self, name: &str) -> bool {
+        self.quarantine.read().unwrap().iter().any(|entry| entry.name == name)
+    }
+
+    pub fn quarantined(&self) -> Vec<QuarantineEntry> {
+        self.quarantine.read().unwrap().clone()
+    }
+
+    /// Let a quarantined plugin load again
+    pub fn release(&self, name: &str) -> Result<()> {
+        self.quarantine.write().unwrap().retain(|entry| entry.name != name);
+        info!("Released {} from quarantine", name);
+        self.save_quarantine()
+    }
+
+    /// Plugin to blame for a crash, with the reason
+    fn suspect(log_path: &Path) -> Option<(String, String)> {
+        let log = fs::read_to_string(log_path).ok()?;
+        let mut loading: Vec<&str> = Vec::new();
+        let mut last_loaded = None;
+
+        for line in log.lines() {
+            match line.split_once('\t') {
+                Some(("begin", name)) => loading.push(name),
+                Some(("end", name)) => {
+                    if let Some(index) = loading.iter().rposition(|loading| *loading == name) {
+                        loading.remove(index);
+                    }
+                    last_loaded = Some(name);
+                },
+                // A torn last line from the crash itself
+                _ => debug!("Skipping malformed load log line: {:?}", line),
+            }
+        }
+
+        match loading.last() {
+            Some(name) => Some((name.to_string(), "crashed while loading".to_string())),
+            None => last_loaded.map(|name| (name.to_string(), "last plugin loaded before a crash".to_string())),
+        }
+    }
+
+    fn read_quarantine(path: &Path) -> Vec<QuarantineEntry> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring invalid quarantine list {:?}: {}", path, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn save_quarantine(&self) -> Result<()> {
+        let path = self.dir.join(QUARANTINE_FILE);
+        let json = serde_json::to_string_pretty(&*self.quarantine.read().unwrap())?;
+        fs::write(&path, json)
+            .map_err(|e| anyhow!("Failed to write quarantine list {:?}: {}", path, e))
+    }
+}
+
+/// Make `safe_mode` the one consulted by `begin_load`
+pub fn activate(safe_mode: Arc<SafeMode>) {
+    *ACTIVE.write().unwrap() = Some(safe_mode);
+}
+
+/// The active safe mode, if any
+pub fn active() -> Option<Arc<SafeMode>> {
+    ACTIVE.read().unwrap().clone()
+}
+
+/// Record a load with the active safe mode, or refuse a quarantined plugin
+///
+/// Without an active safe mode everything loads and nothing is recorded.
+pub fn begin_load(name: &str) -> Result<LoadGuard> {
+    match active() {
+        Some(safe_mode) => safe_mode.begin_load(name),
+        None => Ok(LoadGuard {
+            log: None,
+            name: name.to_string(),
+        }),
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use super::super::safe_mode::SafeMode;
+    use anyhow::Result;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("aether-safe-mode-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_crash_while_loading_quarantines_plugin() -> Result<()> {
+        let dir = temp_dir("loading");
+
+        let (session, report) = SafeMode::start(&dir)?;
+        assert!(!report.crashed);
+        drop(session.begin_load("audioconvert")?);
+        // The process dies inside the plugin: the guard is never dropped
+        std::mem::forget(session.begin_load("ladspa-delay")?);
+        drop(session);
+
+        let (session, report) = SafeMode::start(&dir)?;
+        assert!(report.crashed);
+        assert_eq!(report.quarantined.as_deref(), Some("ladspa-delay"));
+        assert!(session.begin_load("ladspa-delay").is_err());
+        assert!(session.begin_load("audioconvert").is_ok());
+        session.end_session()?;
+
+        // Quarantine outlives the session until released
+        let (session, report) = SafeMode::start(&dir)?;
+        assert!(!report.crashed);
+        assert!(session.is_quarantined("ladspa-delay"));
+        session.release("ladspa-delay")?;
+        session.end_session()?;
+
+        let (session, _) = SafeMode::start(&dir)?;
+        assert!(session.quarantined().is_empty());
+        session.end_session()?;
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_crash_after_loading_blames_last_plugin() -> Result<()> {
+        let dir = temp_dir("after");
+
+        let (session, _) = SafeMode::start(&dir)?;
+        drop(session.begin_load("freeverb")?);
+        drop(session.begin_load("audiodynamic")?);
+        drop(session);
+
+        let (session, report) = SafeMode::start(&dir)?;
+        assert_eq!(report.quarantined.as_deref(), Some("audiodynamic"));
+        drop(session);
+
+        // A crash before anything loads quarantines nothing
+        let (session, report) = SafeMode::start(&dir)?;
+        assert!(report.crashed);
+        assert_eq!(report.quarantined, None);
+        assert_eq!(session.quarantined().len(), 1);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
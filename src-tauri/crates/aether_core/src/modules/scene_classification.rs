@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::vision_model::{OnnxModel, SampledFrame, sample_frames};
+
+/// Scene classification settings
+#[derive(Debug, Clone)]
+pub struct SceneClassifierConfig {
+    /// Image classification model producing one score per label
+    pub model_path: PathBuf,
+    /// Text file with one label per line, in the model's output order
+    pub labels_path: PathBuf,
+    /// Labels kept per file at most
+    pub top_k: usize,
+    /// Minimum averaged probability for a label to be kept
+    pub min_confidence: f32,
+    /// Seconds between analyzed frames
+    pub sample_interval: f64,
+    /// Frames analyzed per file at most
+    pub max_samples: usize,
+    /// Per-channel mean subtracted from 0-255 pixel values
+    pub mean: [f32; 3],
+    /// Per-channel divisor applied after the mean
+    pub std: [f32; 3],
+}
+
+impl Default for SceneClassifierConfig {
+    fn default() -> Self {
+        Self {
+            model_path: PathBuf::from("models/scene_classification.onnx"),
+            labels_path: PathBuf::from("models/scene_classification.txt"),
+            top_k: 5,
+            min_confidence: 0.2,
+            sample_interval: 5.0,
+            max_samples: 24,
+            // ImageNet normalization
+            mean: [123.675, 116.28, 103.53],
+            std: [58.395, 57.12, 57.375],
+        }
+    }
+}
+
+/// A scene label attached to a media file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneLabel {
+    pub label: String,
+    /// Probability averaged over the analyzed frames (0-1)
+    pub confidence: f32,
+}
+
+/// Labels media with a pluggable ONNX classification model
+pub struct SceneClassifier {
+    model: OnnxModel,
+
+    labels: Vec<String>,
+
+    config: SceneClassifierConfig,
+}
+
+impl fmt::Debug for SceneClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SceneClassifier")
+            .field("model", &self.model.path())
+            .field("labels", &self.labels.len())
+            .finish()
+    }
+}
+
+impl SceneClassifier {
+    /// Load the model and its labels
+    pub fn new(config: SceneClassifierConfig) -> Result<Self> {
+        let labels: Vec<String> = fs::read_to_string(&config.labels_path)
+            .map_err(|e| anyhow!("Failed to read labels {:?}: {}", config.labels_path, e))?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if labels.is_empty() {
+            return Err(anyhow!("No labels in {:?}", config.labels_path));
+        }
+
+        let model = OnnxModel::load(&config.model_path, Some((224, 224)))?;
+        Ok(Self { model, labels, config })
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Probability of each label for one frame, which must be at the model's input size
+    pub fn classify_frame(&self, frame: &SampledFrame) -> Result<Vec<f32>> {
+        let outputs = self.model.run(frame.to_nchw(self.config.mean, self.config.std))?;
+        let scores = outputs
+            .values()
+            .find(|output| output.data.len() == self.labels.len())
+            .ok_or_else(|| anyhow!("Model {:?} has no output with {} scores", self.model.path(), self.labels.len()))?;
+
+        Ok(to_probabilities(&scores.data))
+    }
+
+    /// Labels for a media file, most likely first
+    #[tracing::instrument(name = "classify", skip(self))]
+    pub fn classify(&self, path: &Path) -> Result<Vec<SceneLabel>> {
+        let (width, height) = self.model.input_size();
+        let frames = sample_frames(path, self.config.sample_interval, width, height, self.config.max_samples)?;
+        if frames.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut totals = vec![0.0; self.labels.len()];
+        for frame in &frames {
+            for (total, probability) in totals.iter_mut().zip(self.classify_frame(frame)?) {
+                *total += probability;
+            }
+        }
+
+        let averages = totals.into_iter().map(|total| total / frames.len() as f32).collect::<Vec<_>>();
+        Ok(top_labels(&self.labels, &averages, self.config.top_k, self.config.min_confidence))
+    }
+}
+
+/// Softmax, unless the scores already are probabilities
+pub fn to_probabilities(scores: &[f32]) -> Vec<f32> {
+    let sum: f32 = scores.iter().sum();
+    if scores.iter().all(|score| (0.0..=1.0).contains(score)) && (sum - 1.0).abs() < 0.01 {
+        return scores.to_vec();
+    }
+
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|score| (score - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / total).collect()
+}
+
+/// The `top_k` most likely labels at or above `min_confidence`
+pub fn top_labels(labels: &[String], probabilities: &[f32], top_k: usize, min_confidence: f32) -> Vec<SceneLabel> {
+    let mut ranked: Vec<SceneLabel> = labels
+        .iter()
+        .zip(probabilities)
+        .filter(|(_, probability)| **probability >= min_confidence)
+        .map(|(label, probability)| SceneLabel {
+            label: label.clone(),
+            confidence: *probability,
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    ranked.truncate(top_k);
+    ranked
+}
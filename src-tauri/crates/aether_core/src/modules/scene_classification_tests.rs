@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use super::super::scene_classification::{to_probabilities, top_labels};
+    use anyhow::Result;
+
+    #[test]
+    fn test_logits_become_probabilities() -> Result<()> {
+        let probabilities = to_probabilities(&[2.0, 1.0, -3.0]);
+        assert!((probabilities.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(probabilities[0] > probabilities[1] && probabilities[1] > probabilities[2]);
+
+        // Already normalized output is kept as is
+        assert_eq!(to_probabilities(&[0.7, 0.2, 0.1]), vec![0.7, 0.2, 0.1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_labels_ranked_and_filtered() -> Result<()> {
+        let labels: Vec<String> = ["beach", "indoors", "night", "food"].iter().map(|l| l.to_string()).collect();
+
+        let top = top_labels(&labels, &[0.3, 0.05, 0.5, 0.15], 2, 0.1);
+        assert_eq!(top.iter().map(|l| l.label.as_str()).collect::<Vec<_>>(), vec!["night", "beach"]);
+
+        let top = top_labels(&labels, &[0.3, 0.05, 0.5, 0.15], 10, 0.1);
+        assert_eq!(top.len(), 3);
+        Ok(())
+    }
+}
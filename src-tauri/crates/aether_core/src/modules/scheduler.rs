@@ -0,0 +1,410 @@
+//! Background work that only runs while the user isn't doing anything
+//!
+//! Proxy generation, waveform extraction, preview pre-renders and thumbnailing are queued
+//! on a `Scheduler`, which runs them one at a time on a worker thread once the system is
+//! idle: nothing is playing, the user hasn't interacted for a quiet period and CPU load is
+//! low. Starting playback or interacting pauses the running job at its next checkpoint;
+//! it resumes once the system is idle again.
+//!
+//! CPU load is only measured while no job is executing, so a job's own load never
+//! pauses it.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use tracing::{debug, warn};
+
+/// What a background job does, in order of priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JobKind {
+    /// Cheap and on screen in the media browser
+    Thumbnail,
+    Waveform,
+    Proxy,
+    PreviewRender,
+}
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    /// Waiting at a checkpoint for the system to be idle again
+    Paused,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done | JobStatus::Failed(_) | JobStatus::Cancelled)
+    }
+}
+
+/// When the system counts as idle
+#[derive(Debug, Clone)]
+pub struct IdlePolicy {
+    /// Time since the last interaction before jobs start or resume
+    pub quiet_period: Duration,
+    /// Highest system CPU load, from 0 to 1, at which jobs start or resume
+    pub max_cpu_load: f32,
+    /// How often idleness is checked while waiting
+    pub poll_interval: Duration,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            quiet_period: Duration::from_secs(3),
+            max_cpu_load: 0.5,
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Measures system CPU load from 0 to 1, or `None` if it can't
+pub type LoadProbe = Box<dyn FnMut() -> Option<f32> + Send>;
+
+type Job = Box<dyn FnOnce(&JobContext) -> Result<()> + Send>;
+
+struct QueuedJob {
+    id: JobId,
+    kind: JobKind,
+    job: Job,
+}
+
+struct State {
+    queue: Vec<QueuedJob>,
+    statuses: HashMap<JobId, JobStatus>,
+    /// Running jobs to stop at their next checkpoint
+    cancelled: HashSet<JobId>,
+    /// Kinds that don't start, and pause if running, until released
+    held: HashSet<JobKind>,
+    playing: bool,
+    last_interaction: Instant,
+    next_id: JobId,
+    stopping: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    policy: IdlePolicy,
+    load: Mutex<LoadProbe>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+        self.changed.wait_timeout(state, self.policy.poll_interval)
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .0
+    }
+
+    /// Whether the user is playing back or has interacted within the quiet period
+    fn busy(&self, state: &State) -> bool {
+        state.playing || state.last_interaction.elapsed() < self.policy.quiet_period
+    }
+
+    /// Whether a job may start or resume; unknown CPU load doesn't hold jobs back
+    fn idle(&self, state: &State) -> bool {
+        if self.busy(state) {
+            return false;
+        }
+        let load = (*self.load.lock().unwrap())();
+        load.is_none_or(|load| load <= self.policy.max_cpu_load)
+    }
+}
+
+/// Handed to a running job to check in with the scheduler
+pub struct JobContext {
+    id: JobId,
+    kind: JobKind,
+    shared: Arc<Shared>,
+}
+
+impl JobContext {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    pub fn kind(&self) -> JobKind {
+        self.kind
+    }
+
+    /// Wait here while the user is busy or the job's kind is held, returning once the
+    /// system is idle again
+    ///
+    /// Jobs should call this between chunks of work, often enough that pausing feels
+    /// immediate. Fails if the job was cancelled or the scheduler is shutting down, in
+    /// which case the job should return the error.
+    pub fn checkpoint(&self) -> Result<()> {
+        let mut state = self.shared.lock();
+        let mut paused = false;
+        loop {
+            if state.stopping || state.cancelled.contains(&self.id) {
+                return Err(anyhow!("Job {} cancelled", self.id));
+            }
+            let ready = !state.held.contains(&self.kind)
+                && if paused { self.shared.idle(&state) } else { !self.shared.busy(&state) };
+            if ready {
+                if paused {
+                    debug!("Resuming background job {}", self.id);
+                    state.statuses.insert(self.id, JobStatus::Running);
+                }
+                return Ok(());
+            }
+            if !paused {
+                debug!("Pausing background job {}", self.id);
+                state.statuses.insert(self.id, JobStatus::Paused);
+                self.shared.changed.notify_all();
+                paused = true;
+            }
+            state = self.shared.wait(state);
+        }
+    }
+}
+
+/// Runs background jobs one at a time while the system is idle
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Scheduler {
+    /// Scheduler measuring CPU load with a `CpuSampler`
+    pub fn new(policy: IdlePolicy) -> Self {
+        let mut sampler = CpuSampler::new();
+        Self::with_load_probe(policy, Box::new(move || sampler.sample()))
+    }
+
+    pub fn with_load_probe(policy: IdlePolicy, load: LoadProbe) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: Vec::new(),
+                statuses: HashMap::new(),
+                cancelled: HashSet::new(),
+                held: HashSet::new(),
+                playing: false,
+                last_interaction: Instant::now(),
+                next_id: 0,
+                stopping: false,
+            }),
+            changed: Condvar::new(),
+            policy,
+            load: Mutex::new(load),
+        });
+
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || Self::run(shared))
+        };
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue a job; jobs run by kind, then in the order they were submitted
+    pub fn submit<F>(&self, kind: JobKind, job: F) -> JobId
+    where
+        F: FnOnce(&JobContext) -> Result<()> + Send + 'static,
+    {
+        let mut state = self.shared.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queue.push(QueuedJob { id, kind, job: Box::new(job) });
+        state.statuses.insert(id, JobStatus::Queued);
+        self.shared.changed.notify_all();
+        id
+    }
+
+    /// Report playback starting or stopping; jobs pause while playing
+    pub fn set_playing(&self, playing: bool) {
+        self.shared.lock().playing = playing;
+        self.shared.changed.notify_all();
+    }
+
+    /// Report user input; jobs pause until the quiet period has passed
+    pub fn notify_interaction(&self) {
+        self.shared.lock().last_interaction = Instant::now();
+        self.shared.changed.notify_all();
+    }
+
+    /// Hold back or release every job of a kind, e.g. preview renders while on battery
+    ///
+    /// Held jobs stay queued, and a running one pauses at its next checkpoint.
+    pub fn set_held(&self, kind: JobKind, held: bool) {
+        let mut state = self.shared.lock();
+        if held {
+            state.held.insert(kind);
+        } else {
+            state.held.remove(&kind);
+        }
+        self.shared.changed.notify_all();
+    }
+
+    pub fn is_held(&self, kind: JobKind) -> bool {
+        self.shared.lock().held.contains(&kind)
+    }
+
+    /// Drop a queued job, or stop a running one at its next checkpoint
+    ///
+    /// Returns false if the job is unknown or already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.lock();
+        match state.statuses.get(&id) {
+            Some(JobStatus::Queued) => {
+                state.queue.retain(|job| job.id != id);
+                state.statuses.insert(id, JobStatus::Cancelled);
+            },
+            Some(JobStatus::Running | JobStatus::Paused) => {
+                state.cancelled.insert(id);
+            },
+            _ => return false,
+        }
+        self.shared.changed.notify_all();
+        true
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.shared.lock().statuses.get(&id).cloned()
+    }
+
+    /// Number of jobs waiting to start
+    pub fn pending(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Whether the user is playing back or has interacted within the quiet period
+    pub fn is_busy(&self) -> bool {
+        self.shared.busy(&self.shared.lock())
+    }
+
+    /// Forget the statuses of finished jobs
+    pub fn clear_finished(&self) {
+        self.shared.lock().statuses.retain(|_, status| !status.is_finished());
+    }
+
+    /// Stop the worker, cancelling the running job at its next checkpoint; queued jobs never run
+    pub fn shutdown(&mut self) {
+        self.shared.lock().stopping = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn run(shared: Arc<Shared>) {
+        loop {
+            let next = {
+                let mut state = shared.lock();
+                loop {
+                    if state.stopping {
+                        return;
+                    }
+                    let ready = state.queue.iter()
+                        .enumerate()
+                        .filter(|(_, job)| !state.held.contains(&job.kind))
+                        .min_by_key(|(_, job)| (job.kind, job.id))
+                        .map(|(index, _)| index);
+                    if let Some(index) = ready.filter(|_| shared.idle(&state)) {
+                        let next = state.queue.remove(index);
+                        state.statuses.insert(next.id, JobStatus::Running);
+                        break next;
+                    }
+                    state = shared.wait(state);
+                }
+            };
+
+            debug!("Starting background {:?} job {}", next.kind, next.id);
+            let context = JobContext { id: next.id, kind: next.kind, shared: shared.clone() };
+            let result = (next.job)(&context);
+
+            let mut state = shared.lock();
+            let cancelled = state.cancelled.remove(&next.id) || state.stopping;
+            let status = match result {
+                Ok(()) => JobStatus::Done,
+                Err(_) if cancelled => JobStatus::Cancelled,
+                Err(e) => {
+                    warn!("Background {:?} job {} failed: {}", next.kind, next.id, e);
+                    JobStatus::Failed(e.to_string())
+                },
+            };
+            state.statuses.insert(next.id, status);
+            shared.changed.notify_all();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// System-wide CPU load from `/proc/stat`, on Linux
+pub struct CpuSampler {
+    last: Option<CpuTimes>,
+}
+
+/// Cumulative CPU time in clock ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+impl CpuSampler {
+    pub fn new() -> Self {
+        Self { last: read_cpu_times() }
+    }
+
+    /// Load since the previous sample, or `None` where `/proc/stat` isn't available
+    pub fn sample(&mut self) -> Option<f32> {
+        let current = read_cpu_times()?;
+        let last = self.last.replace(current)?;
+        let total = current.total.saturating_sub(last.total);
+        if total == 0 {
+            return None;
+        }
+        Some(current.busy.saturating_sub(last.busy) as f32 / total as f32)
+    }
+}
+
+impl Default for CpuSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_cpu_times() -> Option<CpuTimes> {
+    parse_proc_stat(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+/// CPU times from the aggregate `cpu` line of `/proc/stat`
+///
+/// Idle and I/O wait count as idle; guest time is already part of user time.
+pub fn parse_proc_stat(stat: &str) -> Option<CpuTimes> {
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let times = line.split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(|field| field.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if times.len() < 4 {
+        return None;
+    }
+    let total: u64 = times.iter().sum();
+    let idle = times[3] + times.get(4).copied().unwrap_or(0);
+    Some(CpuTimes { busy: total - idle, total })
+}
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use super::super::scheduler::*;
+    use anyhow::{anyhow, Result};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn fast_policy() -> IdlePolicy {
+        IdlePolicy {
+            quiet_period: Duration::from_millis(100),
+            max_cpu_load: 0.5,
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Scheduler whose CPU load is whatever `load` is set to, in percent
+    fn scheduler_with_load(load: Arc<AtomicU32>) -> Scheduler {
+        Scheduler::with_load_probe(fast_policy(), Box::new(move || Some(load.load(Ordering::SeqCst) as f32 / 100.0)))
+    }
+
+    fn wait_for(scheduler: &Scheduler, id: JobId, status: JobStatus) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while scheduler.status(id) != Some(status.clone()) {
+            if Instant::now() > deadline {
+                return Err(anyhow!("Job {} is {:?}, expected {:?}", id, scheduler.status(id), status));
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_jobs_run_by_priority_once_idle() -> Result<()> {
+        let scheduler = scheduler_with_load(Arc::new(AtomicU32::new(0)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Submitted within the quiet period after creation, so all are queued before any runs
+        let ids: Vec<JobId> = [JobKind::PreviewRender, JobKind::Proxy, JobKind::Thumbnail, JobKind::Waveform]
+            .into_iter()
+            .map(|kind| {
+                let order = order.clone();
+                scheduler.submit(kind, move |_| {
+                    order.lock().unwrap().push(kind);
+                    Ok(())
+                })
+            })
+            .collect();
+        assert_eq!(scheduler.status(ids[0]), Some(JobStatus::Queued));
+
+        for id in &ids {
+            wait_for(&scheduler, *id, JobStatus::Done)?;
+        }
+        assert_eq!(*order.lock().unwrap(), vec![JobKind::Thumbnail, JobKind::Waveform, JobKind::Proxy, JobKind::PreviewRender]);
+        assert_eq!(scheduler.pending(), 0);
+
+        scheduler.clear_finished();
+        assert_eq!(scheduler.status(ids[0]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_playback_and_cpu_load_hold_jobs_back() -> Result<()> {
+        let load = Arc::new(AtomicU32::new(90));
+        let scheduler = scheduler_with_load(load.clone());
+        scheduler.set_playing(true);
+        let id = scheduler.submit(JobKind::Waveform, |_| Ok(()));
+
+        thread::sleep(Duration::from_millis(200));
+        assert!(scheduler.is_busy());
+        assert_eq!(scheduler.status(id), Some(JobStatus::Queued));
+
+        // Stopped, but the system is still loaded
+        scheduler.set_playing(false);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(scheduler.status(id), Some(JobStatus::Queued));
+
+        load.store(10, Ordering::SeqCst);
+        wait_for(&scheduler, id, JobStatus::Done)
+    }
+
+    #[test]
+    fn test_interaction_pauses_running_job() -> Result<()> {
+        let scheduler = scheduler_with_load(Arc::new(AtomicU32::new(0)));
+        let (started, started_rx) = mpsc::channel();
+        let finish = Arc::new(AtomicBool::new(false));
+        let chunks = Arc::new(AtomicU32::new(0));
+
+        let id = {
+            let finish = finish.clone();
+            let chunks = chunks.clone();
+            scheduler.submit(JobKind::Proxy, move |context| {
+                started.send(())?;
+                while !finish.load(Ordering::SeqCst) {
+                    context.checkpoint()?;
+                    chunks.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Ok(())
+            })
+        };
+        started_rx.recv_timeout(Duration::from_secs(5))?;
+
+        scheduler.notify_interaction();
+        wait_for(&scheduler, id, JobStatus::Paused)?;
+        let paused_at = chunks.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(chunks.load(Ordering::SeqCst), paused_at);
+
+        // Resumes by itself after the quiet period
+        wait_for(&scheduler, id, JobStatus::Running)?;
+        finish.store(true, Ordering::SeqCst);
+        wait_for(&scheduler, id, JobStatus::Done)?;
+        assert!(chunks.load(Ordering::SeqCst) > paused_at);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel_jobs() -> Result<()> {
+        let scheduler = scheduler_with_load(Arc::new(AtomicU32::new(0)));
+        let (started, started_rx) = mpsc::channel();
+        let running = scheduler.submit(JobKind::Thumbnail, move |context| {
+            started.send(())?;
+            loop {
+                context.checkpoint()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        let queued = scheduler.submit(JobKind::Proxy, |_| Ok(()));
+        started_rx.recv_timeout(Duration::from_secs(5))?;
+
+        assert!(scheduler.cancel(queued));
+        assert_eq!(scheduler.status(queued), Some(JobStatus::Cancelled));
+        assert!(scheduler.cancel(running));
+        wait_for(&scheduler, running, JobStatus::Cancelled)?;
+
+        assert!(!scheduler.cancel(running));
+        assert!(!scheduler.cancel(1000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_job_does_not_stop_the_queue() -> Result<()> {
+        let scheduler = scheduler_with_load(Arc::new(AtomicU32::new(0)));
+        let failing = scheduler.submit(JobKind::Thumbnail, |_| Err(anyhow!("unreadable file")));
+        let next = scheduler.submit(JobKind::Waveform, |_| Ok(()));
+
+        wait_for(&scheduler, next, JobStatus::Done)?;
+        assert_eq!(scheduler.status(failing), Some(JobStatus::Failed("unreadable file".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_held_kinds_wait_until_released() -> Result<()> {
+        let scheduler = scheduler_with_load(Arc::new(AtomicU32::new(0)));
+        scheduler.set_held(JobKind::PreviewRender, true);
+        let (started, started_rx) = mpsc::channel();
+        let finish = Arc::new(AtomicBool::new(false));
+
+        let render = {
+            let finish = finish.clone();
+            scheduler.submit(JobKind::PreviewRender, move |context| {
+                started.send(())?;
+                while !finish.load(Ordering::SeqCst) {
+                    context.checkpoint()?;
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Ok(())
+            })
+        };
+        let proxy = scheduler.submit(JobKind::Proxy, |_| Ok(()));
+
+        // Other kinds run past the held job
+        wait_for(&scheduler, proxy, JobStatus::Done)?;
+        assert_eq!(scheduler.status(render), Some(JobStatus::Queued));
+
+        scheduler.set_held(JobKind::PreviewRender, false);
+        started_rx.recv_timeout(Duration::from_secs(5))?;
+
+        // Holding again pauses the running job at its next checkpoint
+        scheduler.set_held(JobKind::PreviewRender, true);
+        assert!(scheduler.is_held(JobKind::PreviewRender));
+        wait_for(&scheduler, render, JobStatus::Paused)?;
+
+        scheduler.set_held(JobKind::PreviewRender, false);
+        wait_for(&scheduler, render, JobStatus::Running)?;
+        finish.store(true, Ordering::SeqCst);
+        wait_for(&scheduler, render, JobStatus::Done)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_proc_stat() {
+        let stat = "cpu  100 5 50 800 20 3 2 0 0 0\ncpu0 50 2 25 400 10 1 1 0 0 0\nintr 12345\n";
+        assert_eq!(parse_proc_stat(stat), Some(CpuTimes { busy: 160, total: 980 }));
+        assert_eq!(parse_proc_stat("intr 12345\n"), None);
+        assert_eq!(parse_proc_stat("cpu  1 x 3 4\n"), None);
+    }
+}
@@ -0,0 +1,552 @@
+use anyhow::{anyhow, Result};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Position, Scope, INT};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+use crate::engine::rendering::{ExportOptions, JobPriority, RenderJobId, RenderQueue};
+use crate::engine::timeline::{Clip, ClipType, Marker, Timeline, Track};
+use super::media_library::MediaLibrary;
+use super::path_policy::PathPolicy;
+
+/// Directory under the project root holding the project's scripts
+pub const SCRIPTS_DIR: &str = "scripts";
+
+/// Extension of script files
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// Resource limits for a script run, so a runaway script can't hang or exhaust the app
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLimits {
+    /// Operations evaluated before the script is aborted
+    pub max_operations: u64,
+    /// Nesting depth of function calls
+    pub max_call_levels: usize,
+    /// Length of any string, in bytes
+    pub max_string_size: usize,
+    /// Length of any array
+    pub max_array_size: usize,
+    /// Entries in any object map
+    pub max_map_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 10_000_000,
+            max_call_levels: 64,
+            max_string_size: 1024 * 1024,
+            max_array_size: 100_000,
+            max_map_size: 10_000,
+        }
+    }
+}
+
+/// What a script works on
+///
+/// The library and render queue are optional; scripts calling into a missing one fail
+/// with an error rather than silently doing nothing.
+#[derive(Clone)]
+pub struct ScriptContext {
+    pub timeline: Arc<Mutex<Timeline>>,
+    pub library: Option<Arc<Mutex<MediaLibrary>>>,
+    pub render_queue: Option<Arc<RenderQueue>>,
+    /// Directory exports may read from and write to; relative export paths are below it
+    pub project_root: Option<PathBuf>,
+}
+
+impl ScriptContext {
+    pub fn new(timeline: Arc<Mutex<Timeline>>) -> Self {
+        Self {
+            timeline,
+            library: None,
+            render_queue: None,
+            project_root: None,
+        }
+    }
+
+    pub fn with_library(mut self, library: Arc<Mutex<MediaLibrary>>) -> Self {
+        self.library = Some(library);
+        self
+    }
+
+    /// Let scripts queue exports of files below `project_root`
+    pub fn with_render_queue(mut self, render_queue: Arc<RenderQueue>, project_root: &Path) -> Self {
+        self.render_queue = Some(render_queue);
+        self.project_root = Some(project_root.to_path_buf());
+        self
+    }
+}
+
+/// Result of a script run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptOutput {
+    /// Lines written with `print`
+    pub printed: Vec<String>,
+    /// Exports queued by the script
+    pub export_jobs: Vec<RenderJobId>,
+}
+
+/// Runs Rhai scripts against a timeline, media library and render queue
+///
+/// Scripts only see the API registered here: there is no file, process or network
+/// access, `eval` and module imports are disabled, and `ScriptLimits` bounds the work a
+/// script can do. Changes are applied as the script runs, so a script that fails keeps
+/// the changes made before the error.
+///
+/// The API, with times in seconds:
+///
+/// - `tracks()`, `add_track(id, name)`, `clips(track)`, `markers()`, `duration()`, `fps()`
+/// - `add_clip(track, #{ type, start, duration, source, id, properties })`, returning the
+///   clip ID; `type` is `"video"`, `"audio"`, `"image"`, `"text"` or `"effect"`
+/// - `remove_clip(track, clip)`, `set_clip_property(track, clip, key, value)`
+/// - `add_marker(time, name)`, returning the marker ID
+/// - `assets()`, `asset_path(asset)`, `add_tag(asset, tag)`, `bins()`, `find_bin(name)`,
+///   `bin_assets(bin)`
+/// - `queue_export(options)` or `queue_export(options, priority)`, returning the job ID;
+///   `options` has the fields of `ExportOptions` and `priority` is `"background"`,
+///   `"normal"` or `"high"`; input and output must be below the project root
+pub struct ScriptHost {
+    limits: ScriptLimits,
+}
+
+impl ScriptHost {
+    pub fn new(limits: ScriptLimits) -> Self {
+        Self { limits }
+    }
+
+    pub fn limits(&self) -> &ScriptLimits {
+        &self.limits
+    }
+
+    /// Check a script for syntax errors without running it
+    pub fn check(&self, source: &str) -> Result<()> {
+        let engine = self.sandboxed_engine();
+        engine.compile(source).map_err(|e| anyhow!("Script error: {}", e))?;
+        Ok(())
+    }
+
+    /// Run a script
+    pub fn run(&self, source: &str, context: &ScriptContext) -> Result<ScriptOutput> {
+        let output = Rc::new(RefCell::new(ScriptOutput::default()));
+        let mut engine = self.sandboxed_engine();
+
+        let printed = output.clone();
+        engine.on_print(move |line| {
+            debug!("Script: {}", line);
+            printed.borrow_mut().printed.push(line.to_string());
+        });
+
+        register_timeline_api(&mut engine, context.timeline.clone());
+        register_library_api(&mut engine, context.library.clone());
+        register_export_api(&mut engine, context.render_queue.clone(), context.project_root.clone(), output.clone());
+
+        let ast = engine.compile(source).map_err(|e| anyhow!("Script error: {}", e))?;
+        engine.run_ast_with_scope(&mut Scope::new(), &ast)
+            .map_err(|e| anyhow!("Script failed: {}", e))?;
+
+        drop(engine);
+        let output = output.borrow().clone();
+        info!("Script finished, {} exports queued", output.export_jobs.len());
+        Ok(output)
+    }
+
+    /// Run a script stored with a project
+    pub fn run_project_script(&self, scripts: &ProjectScripts, name: &str, context: &ScriptContext) -> Result<ScriptOutput> {
+        let source = scripts.load(name)?;
+        info!("Running project script {}", name);
+        self.run(&source, context)
+    }
+
+    fn sandboxed_engine(&self) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+        engine.disable_symbol("eval");
+        engine.set_max_operations(self.limits.max_operations);
+        engine.set_max_call_levels(self.limits.max_call_levels);
+        engine.set_max_string_size(self.limits.max_string_size);
+        engine.set_max_array_size(self.limits.max_array_size);
+        engine.set_max_map_size(self.limits.max_map_size);
+        engine
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new(ScriptLimits::default())
+    }
+}
+
+/// Scripts stored in a project's `scripts` directory
+pub struct ProjectScripts {
+    dir: PathBuf,
+}
+
+impl ProjectScripts {
+    pub fn new(project_root: &Path) -> Self {
+        Self {
+            dir: project_root.join(SCRIPTS_DIR),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Names of the stored scripts, sorted
+    pub fn list(&self) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(SCRIPT_EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn load(&self, name: &str) -> Result<String> {
+        let path = self.path(name)?;
+        fs::read_to_string(&path).map_err(|e| anyhow!("Failed to read script {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, name: &str, source: &str) -> Result<()> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, source).map_err(|e| anyhow!("Failed to write script {:?}: {}", path, e))
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let path = self.path(name)?;
+        fs::remove_file(&path).map_err(|e| anyhow!("Failed to remove script {:?}: {}", path, e))
+    }
+
+    /// Path of a script, refusing names that would leave the scripts directory
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | ' ' | '.'))
+            && !name.starts_with('.');
+        if !valid {
+            return Err(anyhow!("Invalid script name: {:?}", name));
+        }
+
+        Ok(self.dir.join(format!("{}.{}", name, SCRIPT_EXTENSION)))
+    }
+}
+
+fn script_error<T>(message: impl Into<String>) -> ScriptResult<T> {
+    Err(Box::new(EvalAltResult::ErrorRuntime(message.into().into(), Position::NONE)))
+}
+
+fn number(value: &Dynamic, name: &str) -> ScriptResult<f64> {
+    if let Ok(value) = value.as_float() {
+        Ok(value)
+    } else if let Ok(value) = value.as_int() {
+        Ok(value as f64)
+    } else {
+        script_error(format!("{} must be a number, got {}", name, value.type_name()))
+    }
+}
+
+fn clip_type(name: &str) -> ScriptResult<ClipType> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "video" => ClipType::Video,
+        "audio" => ClipType::Audio,
+        "image" => ClipType::Image,
+        "text" => ClipType::Text,
+        "effect" => ClipType::Effect,
+        "generated" => ClipType::Generated,
+        other => return script_error(format!("Unknown clip type: {}", other)),
+    })
+}
+
+fn clip_type_name(clip_type: &ClipType) -> &'static str {
+    match clip_type {
+        ClipType::Video => "video",
+        ClipType::Audio => "audio",
+        ClipType::Image => "image",
+        ClipType::Text => "text",
+        ClipType::Effect => "effect",
+        ClipType::Generated => "generated",
+    }
+}
+
+fn clip_map(clip: &Clip) -> Map {
+    let properties: Map = clip.properties.iter()
+        .map(|(key, value)| (key.as_str().into(), Dynamic::from(value.clone())))
+        .collect();
+
+    let mut map = Map::new();
+    map.insert("id".into(), clip.id.clone().into());
+    map.insert("type".into(), clip_type_name(&clip.clip_type).into());
+    map.insert("start".into(), clip.start_time.into());
+    map.insert("duration".into(), clip.duration.into());
+    map.insert("source".into(), clip.source_path.clone().map_or(Dynamic::UNIT, Dynamic::from));
+    map.insert("properties".into(), properties.into());
+    map
+}
+
+fn marker_map(marker: &Marker) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), marker.id.clone().into());
+    map.insert("time".into(), marker.time.into());
+    map.insert("duration".into(), marker.duration.into());
+    map.insert("name".into(), marker.name.clone().into());
+    map.insert("note".into(), marker.note.clone().into());
+    map.insert("color".into(), marker.color.clone().map_or(Dynamic::UNIT, Dynamic::from));
+    map
+}
+
+/// Build a clip from the map passed to `add_clip`
+fn clip_from_map(timeline: &Timeline, spec: &Map) -> ScriptResult<Clip> {
+    let field = |name: &str| spec.get(name).filter(|value| !value.is_unit());
+
+    let clip_type = match field("type") {
+        Some(value) => clip_type(&value.clone().into_string()?)?,
+        None => return script_error("add_clip needs a type"),
+    };
+    let start = field("start").map_or(Ok(0.0), |value| number(value, "start"))?;
+    let duration = match field("duration") {
+        Some(value) => number(value, "duration")?,
+        None => return script_error("add_clip needs a duration"),
+    };
+    if start < 0.0 || duration <= 0.0 {
+        return script_error(format!("Invalid clip range: start {} duration {}", start, duration));
+    }
+
+    let id = match field("id") {
+        Some(value) => value.clone().into_string()?,
+        None => unused_clip_id(timeline),
+    };
+
+    let mut clip = Clip::new(id, clip_type, start, duration);
+    if let Some(source) = field("source") {
+        clip = clip.with_source(source.clone().into_string()?);
+    }
+    if let Some(properties) = field("properties") {
+        let properties = properties.clone().try_cast::<Map>()
+            .ok_or_else(|| Box::new(EvalAltResult::ErrorRuntime("properties must be a map".into(), Position::NONE)))?;
+        for (key, value) in properties {
+            clip = clip.add_property(key.to_string(), value.to_string());
+        }
+    }
+
+    Ok(clip)
+}
+
+fn unused_clip_id(timeline: &Timeline) -> String {
+    let taken = |id: &str| timeline.tracks().values().any(|track| track.clips.iter().any(|clip| clip.id == id));
+    (1..)
+        .map(|n| format!("script_clip_{}", n))
+        .find(|id| !taken(id))
+        .unwrap()
+}
+
+fn register_timeline_api(engine: &mut Engine, timeline: Arc<Mutex<Timeline>>) {
+    let shared = timeline.clone();
+    engine.register_fn("tracks", move || -> Array {
+        let timeline = shared.lock().unwrap();
+        let mut ids: Vec<&String> = timeline.tracks().keys().collect();
+        ids.sort();
+        ids.into_iter().map(|id| id.clone().into()).collect()
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("add_track", move |id: &str, name: &str| -> ScriptResult<()> {
+        shared.lock().unwrap()
+            .add_track(Track::new(id.to_string(), name.to_string()))
+            .or_else(|e| script_error(e.to_string()))
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("clips", move |track_id: &str| -> ScriptResult<Array> {
+        let timeline = shared.lock().unwrap();
+        let track = timeline.get_track(track_id).or_else(|e| script_error(e.to_string()))?;
+        let mut clips: Vec<&Clip> = track.clips.iter().collect();
+        clips.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        Ok(clips.into_iter().map(|clip| clip_map(clip).into()).collect())
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("add_clip", move |track_id: &str, spec: Map| -> ScriptResult<String> {
+        let mut timeline = shared.lock().unwrap();
+        let clip = clip_from_map(&timeline, &spec)?;
+        let id = clip.id.clone();
+        timeline.add_clip_to_track(track_id, clip).or_else(|e| script_error(e.to_string()))?;
+        Ok(id)
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("remove_clip", move |track_id: &str, clip_id: &str| -> ScriptResult<()> {
+        shared.lock().unwrap()
+            .remove_clip_from_track(track_id, clip_id)
+            .map(drop)
+            .or_else(|e| script_error(e.to_string()))
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("set_clip_property", move |track_id: &str, clip_id: &str, key: &str, value: Dynamic| -> ScriptResult<()> {
+        let mut timeline = shared.lock().unwrap();
+        let track = timeline.get_track_mut(track_id).or_else(|e| script_error(e.to_string()))?;
+        match track.clips.iter_mut().find(|clip| clip.id == clip_id) {
+            Some(clip) => {
+                clip.properties.insert(key.to_string(), value.to_string());
+                Ok(())
+            },
+            None => script_error(format!("Clip {} not found on track {}", clip_id, track_id)),
+        }
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("markers", move || -> Array {
+        shared.lock().unwrap().markers().iter().map(|marker| marker_map(marker).into()).collect()
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("add_marker", move |time: Dynamic, name: &str| -> ScriptResult<String> {
+        let time = number(&time, "time")?;
+        let mut timeline = shared.lock().unwrap();
+        let id = (1..)
+            .map(|n| format!("script_marker_{}", n))
+            .find(|id| !timeline.markers().iter().any(|marker| &marker.id == id))
+            .unwrap();
+        timeline.add_marker(Marker::new(id.clone(), time, name.to_string()))
+            .or_else(|e| script_error(e.to_string()))?;
+        Ok(id)
+    });
+
+    let shared = timeline.clone();
+    engine.register_fn("duration", move || shared.lock().unwrap().duration());
+
+    let shared = timeline;
+    engine.register_fn("fps", move || shared.lock().unwrap().fps() as INT);
+}
+
+fn register_library_api(engine: &mut Engine, library: Option<Arc<Mutex<MediaLibrary>>>) {
+    let with_library = move |f: &dyn Fn(&mut MediaLibrary) -> ScriptResult<Dynamic>| -> ScriptResult<Dynamic> {
+        match &library {
+            Some(library) => f(&mut library.lock().unwrap()),
+            None => script_error("No media library is available to this script"),
+        }
+    };
+    let with_library = Rc::new(with_library);
+
+    let call = with_library.clone();
+    engine.register_fn("assets", move || -> ScriptResult<Dynamic> {
+        call(&|library| {
+            let assets: Array = library.assets().into_iter()
+                .map(|asset| {
+                    let mut map = Map::new();
+                    map.insert("id".into(), asset.id.clone().into());
+                    map.insert("path".into(), library.resolve_path(&asset.id)
+                        .map_or(Dynamic::UNIT, |path| path.to_string_lossy().into_owned().into()));
+                    map.insert("tags".into(), asset.tags.iter().cloned().map(Dynamic::from).collect::<Array>().into());
+                    map.insert("offline".into(), asset.offline.into());
+                    map.into()
+                })
+                .collect();
+            Ok(assets.into())
+        })
+    });
+
+    let call = with_library.clone();
+    engine.register_fn("asset_path", move |asset_id: &str| -> ScriptResult<Dynamic> {
+        call(&|library| {
+            library.resolve_path(asset_id)
+                .map(|path| path.to_string_lossy().into_owned().into())
+                .or_else(|e| script_error(e.to_string()))
+        })
+    });
+
+    let call = with_library.clone();
+    engine.register_fn("add_tag", move |asset_id: &str, tag: &str| -> ScriptResult<Dynamic> {
+        call(&|library| match library.get_asset_mut(asset_id) {
+            Some(asset) => {
+                asset.add_tag(tag);
+                Ok(Dynamic::UNIT)
+            },
+            None => script_error(format!("Asset not found: {}", asset_id)),
+        })
+    });
+
+    let call = with_library.clone();
+    engine.register_fn("bins", move || -> ScriptResult<Dynamic> {
+        call(&|library| {
+            let bins: Array = library.bins().iter()
+                .map(|bin| {
+                    let mut map = Map::new();
+                    map.insert("id".into(), bin.id.clone().into());
+                    map.insert("name".into(), bin.name.clone().into());
+                    map.insert("parent".into(), bin.parent.clone().map_or(Dynamic::UNIT, Dynamic::from));
+                    map.into()
+                })
+                .collect();
+            Ok(bins.into())
+        })
+    });
+
+    let call = with_library.clone();
+    engine.register_fn("find_bin", move |name: &str| -> ScriptResult<Dynamic> {
+        call(&|library| {
+            Ok(library.bins().iter()
+                .find(|bin| bin.name == name)
+                .map_or(Dynamic::UNIT, |bin| bin.id.clone().into()))
+        })
+    });
+
+    let call = with_library;
+    engine.register_fn("bin_assets", move |bin_id: &str| -> ScriptResult<Dynamic> {
+        call(&|library| {
+            let assets = library.bin_assets(bin_id).or_else(|e| script_error(e.to_string()))?;
+            Ok(assets.into_iter().map(Dynamic::from).collect::<Array>().into())
+        })
+    });
+}
+
+fn register_export_api(
+    engine: &mut Engine,
+    render_queue: Option<Arc<RenderQueue>>,
+    project_root: Option<PathBuf>,
+    output: Rc<RefCell<ScriptOutput>>,
+) {
+    let export = Rc::new(move |options: Map, priority: &str| -> ScriptResult<INT> {
+        let (queue, root) = match (&render_queue, &project_root) {
+            (Some(queue), Some(root)) => (queue, root),
+            _ => return script_error("No render queue is available to this script"),
+        };
+        let priority = match priority {
+            "background" => JobPriority::Background,
+            "normal" => JobPriority::Normal,
+            "high" => JobPriority::High,
+            other => return script_error(format!("Unknown priority: {}", other)),
+        };
+        let mut options: ExportOptions = rhai::serde::from_dynamic(&options.into())?;
+
+        // Scripts come with projects, so they only get to touch that project's files
+        let policy = PathPolicy::with_roots(&[root.clone()]).or_else(|e| script_error(e.to_string()))?;
+        options.input_path = policy.validate_input(&root.join(&options.input_path)).or_else(|e| script_error(e.to_string()))?;
+        options.output_path = policy.validate_output(&root.join(&options.output_path)).or_else(|e| script_error(e.to_string()))?;
+
+        let job_id = queue.enqueue(options, priority);
+        output.borrow_mut().export_jobs.push(job_id);
+        Ok(job_id as INT)
+    });
+
+    let call = export.clone();
+    engine.register_fn("queue_export", move |options: Map| call(options, "normal"));
+    let call = export;
+    engine.register_fn("queue_export", move |options: Map, priority: &str| call(options, priority));
+}
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+    use super::super::media_library::MediaLibrary;
+    use super::super::scripting::{ProjectScripts, ScriptContext, ScriptHost, ScriptLimits};
+    use super::super::test_utils::create_test_dir;
+    use crate::engine::rendering::{RenderQueue, RenderQueueConfig};
+    use crate::engine::timeline::{Clip, ClipType, Marker, Timeline, TimelineConfig, Track};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use anyhow::Result;
+
+    fn timeline_with_markers() -> Result<Arc<Mutex<Timeline>>> {
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("v1".to_string(), "Video 1".to_string()))?;
+        timeline.add_track(Track::new("titles".to_string(), "Titles".to_string()))?;
+        timeline.add_marker(Marker::new("m1".to_string(), 5.0, "Alice".to_string()))?;
+        timeline.add_marker(Marker::new("m2".to_string(), 20.0, "Bob".to_string()))?;
+        Ok(Arc::new(Mutex::new(timeline)))
+    }
+
+    #[test]
+    fn test_lower_third_for_every_marker() -> Result<()> {
+        let timeline = timeline_with_markers()?;
+        let script = r#"
+            for marker in markers() {
+                add_clip("titles", #{
+                    type: "text",
+                    start: marker.time,
+                    duration: 4,
+                    properties: #{ text: marker.name, style: "lower_third" },
+                });
+            }
+            print(`added ${clips("titles").len()} titles`);
+        "#;
+
+        let output = ScriptHost::default().run(script, &ScriptContext::new(timeline.clone()))?;
+        assert_eq!(output.printed, vec!["added 2 titles".to_string()]);
+
+        let timeline = timeline.lock().unwrap();
+        let titles = &timeline.get_track("titles")?.clips;
+        assert_eq!(titles.len(), 2);
+        assert!(titles.iter().all(|clip| clip.clip_type == ClipType::Text && clip.duration == 4.0));
+        assert!(titles.iter().any(|clip| clip.start_time == 5.0 && clip.properties["text"] == "Alice"));
+        assert!(titles.iter().any(|clip| clip.start_time == 20.0 && clip.properties["text"] == "Bob"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_grade_clips_from_bin() -> Result<()> {
+        let root = create_test_dir("scripting", "bin_grade")?;
+        let mut library = MediaLibrary::new(&root);
+        let mut paths = Vec::new();
+        for name in ["a.mov", "b.mov", "c.mov"] {
+            let path = root.join(name);
+            fs::write(&path, name.as_bytes())?;
+            paths.push(path);
+        }
+        let bin = library.create_bin("Interviews", None)?;
+        for path in &paths[..2] {
+            let asset = library.add_asset(path, None)?;
+            library.add_to_bin(&bin, &asset)?;
+        }
+        library.add_asset(&paths[2], None)?;
+
+        let timeline = timeline_with_markers()?;
+        {
+            let mut timeline = timeline.lock().unwrap();
+            for (i, path) in paths.iter().enumerate() {
+                let clip = Clip::new(format!("clip{}", i), ClipType::Video, i as f64 * 10.0, 10.0)
+                    .with_source(path.to_string_lossy().into_owned());
+                timeline.add_clip_to_track("v1", clip)?;
+            }
+        }
+
+        let script = r#"
+            let sources = bin_assets(find_bin("Interviews")).map(|asset| asset_path(asset));
+            for clip in clips("v1") {
+                if sources.contains(clip.source) {
+                    set_clip_property("v1", clip.id, "grade", "warm");
+                }
+            }
+        "#;
+        let context = ScriptContext::new(timeline.clone()).with_library(Arc::new(Mutex::new(library)));
+        ScriptHost::default().run(script, &context)?;
+
+        let timeline = timeline.lock().unwrap();
+        let graded: Vec<&str> = timeline.get_track("v1")?.clips.iter()
+            .filter(|clip| clip.properties.get("grade").map(String::as_str) == Some("warm"))
+            .map(|clip| clip.id.as_str())
+            .collect();
+        assert_eq!(graded.len(), 2);
+        assert!(graded.contains(&"clip0") && graded.contains(&"clip1"));
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sandbox_limits() -> Result<()> {
+        let host = ScriptHost::new(ScriptLimits {
+            max_operations: 10_000,
+            ..ScriptLimits::default()
+        });
+        let context = ScriptContext::new(timeline_with_markers()?);
+
+        assert!(host.run("loop { }", &context).is_err());
+        assert!(host.run(r#"eval("1 + 1")"#, &context).is_err());
+        assert!(host.run(r#"import "other" as other;"#, &context).is_err());
+
+        // APIs that weren't provided fail instead of doing nothing
+        assert!(host.run("assets()", &context).is_err());
+        assert!(host.run(r#"queue_export(#{ output_path: "out.mp4" })"#, &context).is_err());
+
+        assert!(host.check("let x = ;").is_err());
+        assert!(host.check("let x = markers().len();").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_scripts() -> Result<()> {
+        let root = create_test_dir("scripting", "stored")?;
+        let scripts = ProjectScripts::new(&root);
+        assert!(scripts.list()?.is_empty());
+
+        scripts.save("lower thirds", "print(markers().len());")?;
+        scripts.save("cleanup", "print(0);")?;
+        assert_eq!(scripts.list()?, vec!["cleanup".to_string(), "lower thirds".to_string()]);
+        assert!(root.join("scripts").join("cleanup.rhai").exists());
+
+        let output = ScriptHost::default()
+            .run_project_script(&scripts, "lower thirds", &ScriptContext::new(timeline_with_markers()?))?;
+        assert_eq!(output.printed, vec!["2".to_string()]);
+
+        assert!(scripts.save("../escape", "").is_err());
+        assert!(scripts.path("a/b").is_err());
+
+        scripts.remove("cleanup")?;
+        assert_eq!(scripts.list()?, vec!["lower thirds".to_string()]);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_exports_stay_in_project() -> Result<()> {
+        let root = create_test_dir("scripting", "exports")?;
+        fs::write(root.join("edit.xges"), b"project")?;
+        let queue = Arc::new(RenderQueue::new(RenderQueueConfig::default()));
+        let context = ScriptContext::new(timeline_with_markers()?).with_render_queue(queue.clone(), &root);
+        let host = ScriptHost::default();
+
+        let refused = [
+            r#"queue_export(#{ input_path: "edit.xges", output_path: "/etc/cron.d/out.mp4" })"#,
+            r#"queue_export(#{ input_path: "edit.xges", output_path: "../out.mp4" })"#,
+            r#"queue_export(#{ input_path: "edit.xges", output_path: "renders/../../out.mp4" })"#,
+            r#"queue_export(#{ input_path: "/etc/passwd", output_path: "out.mp4" })"#,
+            r#"queue_export(#{ input_path: "../edit.xges", output_path: "out.mp4" })"#,
+        ];
+        for script in refused {
+            assert!(host.run(script, &context).is_err(), "{}", script);
+        }
+        assert!(queue.jobs().is_empty());
+        assert!(!root.parent().unwrap().join("out.mp4").exists());
+
+        let output = host.run(r#"queue_export(#{ input_path: "edit.xges", output_path: "renders/out.mp4" }, "background")"#, &context)?;
+        assert_eq!(output.export_jobs.len(), 1);
+        assert!(queue.job(output.export_jobs[0]).is_some());
+        assert!(root.join("renders").is_dir());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use super::backend_policy::{self, BackendPolicy};
+use super::display_calibration::DisplayCalibration;
+use super::hardware_probe::{self, HardwareDefaults};
+use super::power_policy::PowerSettings;
+
+/// Receives settings changes
+pub type SettingsCallback = Arc<dyn Fn(&SettingsChange) + Send + Sync + 'static>;
+
+/// Handle returned by `SettingsStore::subscribe`
+pub type SubscriptionId = u64;
+
+/// When hardware decoders and encoders are used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwareAcceleration {
+    /// Hardware decoding, software encoding unless a preset asks for hardware
+    Auto,
+    /// Hardware decoding and encoding wherever supported
+    Always,
+    /// Software only, for drivers that produce corrupt output
+    Never,
+}
+
+impl HardwareAcceleration {
+    pub fn for_decoding(&self) -> bool {
+        !matches!(self, HardwareAcceleration::Never)
+    }
+
+    /// Whether an encoder should use hardware, given what its preset asks for
+    pub fn for_encoding(&self, preset_requests: bool) -> bool {
+        match self {
+            HardwareAcceleration::Auto => preset_requests,
+            HardwareAcceleration::Always => true,
+            HardwareAcceleration::Never => false,
+        }
+    }
+}
+
+/// Preview resolution relative to the sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewQuality {
+    Full,
+    Half,
+    Quarter,
+}
+
+impl PreviewQuality {
+    /// Scale factor applied to the sequence resolution
+    pub fn scale(&self) -> f64 {
+        match self {
+            PreviewQuality::Full => 1.0,
+            PreviewQuality::Half => 0.5,
+            PreviewQuality::Quarter => 0.25,
+        }
+    }
+
+    /// Preview size for a sequence size, kept even for chroma-subsampled formats
+    pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |value: u32| (((value as f64 * self.scale()) as u32) & !1).max(2);
+        (scale(width), scale(height))
+    }
+}
+
+/// Cache limits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// Decoded frames kept by the timeline renderer
+    pub preview_frames: usize,
+    /// Directory for thumbnails, the system temp directory if unset
+    pub thumbnail_dir: Option<PathBuf>,
+    /// MB of decoded preview frames kept in memory before older ones are spilled to disk
+    pub preview_memory_mb: usize,
+    /// MB of undo steps kept in memory before older ones are spilled to disk
+    pub undo_memory_mb: usize,
+    /// MB of clip loudness profiles kept in memory before older ones are spilled to disk
+    pub waveform_memory_mb: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            preview_frames: 30,
+            thumbnail_dir: None,
+            preview_memory_mb: 128,
+            undo_memory_mb: 64,
+            waveform_memory_mb: 32,
+        }
+    }
+}
+
+/// Engine preferences persisted between sessions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineSettings {
+    /// Backend for export and the other subsystems
+    pub backends: BackendPolicy,
+    pub hardware_acceleration: HardwareAcceleration,
+    /// What the hardware probe found on this machine, `None` until it first runs
+    pub hardware: Option<HardwareDefaults>,
+    pub cache: CacheSettings,
+    /// Audio output device ID, the system default if unset
+    pub audio_device: Option<String>,
+    pub preview_quality: PreviewQuality,
+    /// What changes while running on battery
+    pub power: PowerSettings,
+    /// Preview calibration for each monitor or output, by name
+    pub display_calibrations: HashMap<String, DisplayCalibration>,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            backends: BackendPolicy::default(),
+            hardware_acceleration: HardwareAcceleration::Auto,
+            hardware: None,
+            cache: CacheSettings::default(),
+            audio_device: None,
+            preview_quality: PreviewQuality::Full,
+            power: PowerSettings::default(),
+            display_calibrations: HashMap::new(),
+        }
+    }
+}
+
+impl EngineSettings {
+    /// Record a hardware probe
+    ///
+    /// The first probe also sets `hardware_acceleration`; later ones leave it alone, so a
+    /// choice made in the settings sticks. Moving the settings to another machine, where
+    /// the probe finds different hardware, counts as a first probe again.
+    pub fn apply_hardware_probe(&mut self, probe: HardwareDefaults) {
+        let first = match &self.hardware {
+            None => true,
+            Some(previous) => previous.platform != probe.platform
+                || previous.encoder != probe.encoder
+                || previous.decoder != probe.decoder,
+        };
+        if first {
+            self.hardware_acceleration = probe.acceleration;
+        }
+        self.hardware = Some(probe);
+    }
+}
+
+/// File format of a settings file, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsFormat {
+    Toml,
+    Json,
+}
+
+impl SettingsFormat {
+    /// `.toml` files are TOML, anything else JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => SettingsFormat::Toml,
+            _ => SettingsFormat::Json,
+        }
+    }
+
+    fn parse(&self, text: &str) -> Result<EngineSettings> {
+        Ok(match self {
+            SettingsFormat::Toml => toml::from_str(text)?,
+            SettingsFormat::Json => serde_json::from_str(text)?,
+        })
+    }
+
+    fn serialize(&self, settings: &EngineSettings) -> Result<String> {
+        Ok(match self {
+            SettingsFormat::Toml => toml::to_string_pretty(settings)?,
+            SettingsFormat::Json => serde_json::to_string_pretty(settings)?,
+        })
+    }
+}
+
+/// A settings update, passed to subscribers
+#[derive(Debug, Clone)]
+pub struct SettingsChange {
+    pub previous: EngineSettings,
+    pub current: EngineSettings,
+}
+
+impl SettingsChange {
+    pub fn backends_changed(&self) -> bool {
+        self.previous.backends != self.current.backends
+    }
+
+    pub fn hardware_acceleration_changed(&self) -> bool {
+        self.previous.hardware_acceleration != self.current.hardware_acceleration
+    }
+
+    pub fn hardware_changed(&self) -> bool {
+        self.previous.hardware != self.current.hardware
+    }
+
+    pub fn cache_changed(&self) -> bool {
+        self.previous.cache != self.current.cache
+    }
+
+    pub fn audio_device_changed(&self) -> bool {
+        self.previous.audio_device != self.current.audio_device
+    }
+
+    pub fn preview_quality_changed(&self) -> bool {
+        self.previous.preview_quality != self.current.preview_quality
+    }
+
+    pub fn power_changed(&self) -> bool {
+        self.previous.power != self.current.power
+    }
+
+    pub fn display_calibrations_changed(&self) -> bool {
+        self.previous.display_calibrations != self.current.display_calibrations
+    }
+}
+
+/// Engine settings backed by a file, notifying subscribers of changes
+///
+/// Every update is written to disk immediately, so settings survive crashes as well as
+/// restarts. The backend choice is also applied as the global backend policy.
+pub struct SettingsStore {
+    path: PathBuf,
+    format: SettingsFormat,
+    settings: RwLock<EngineSettings>,
+    subscribers: Mutex<Vec<(SubscriptionId, SettingsCallback)>>,
+    next_subscription: Mutex<SubscriptionId>,
+}
+
+impl SettingsStore {
+    /// Open the settings file at `path`, starting from defaults if it doesn't exist
+    ///
+    /// An unreadable file is set aside as `<name>.invalid` rather than failing startup.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let format = SettingsFormat::from_path(&path);
+
+        let settings = match fs::read_to_string(&path) {
+            Ok(text) => match format.parse(&text) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    let backup = path.with_extension("invalid");
+                    warn!("Invalid settings file {:?}, moving it to {:?}: {}", path, backup, e);
+                    fs::rename(&path, &backup)
+                        .map_err(|e| anyhow!("Failed to set aside invalid settings {:?}: {}", path, e))?;
+                    EngineSettings::default()
+                },
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No settings file at {:?}, using defaults", path);
+                EngineSettings::default()
+            },
+            Err(e) => return Err(anyhow!("Failed to read settings {:?}: {}", path, e)),
+        };
+
+        backend_policy::set_global_policy(settings.backends.clone());
+
+        Ok(Self {
+            path,
+            format,
+            settings: RwLock::new(settings),
+            subscribers: Mutex::new(Vec::new()),
+            next_subscription: Mutex::new(1),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current settings
+    pub fn get(&self) -> EngineSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replace all settings
+    pub fn set(&self, settings: EngineSettings) -> Result<()> {
+        self.update(|current| *current = settings)
+    }
+
+    /// Modify the settings, save them and notify subscribers if anything changed
+    ///
+    /// Nothing changes if saving fails.
+    pub fn update(&self, modify: impl FnOnce(&mut EngineSettings)) -> Result<()> {
+        let change = {
+            let mut settings = self.settings.write().unwrap();
+            let mut updated = settings.clone();
+            modify(&mut updated);
+
+            if updated == *settings {
+                return Ok(());
+            }
+
+            self.save(&updated)?;
+            let previous = std::mem::replace(&mut *settings, updated.clone());
+            SettingsChange { previous, current: updated }
+        };
+
+        if change.backends_changed() {
+            backend_policy::set_global_policy(change.current.backends.clone());
+        }
+
+        info!("Engine settings updated");
+        // Callbacks may read the store, so run them without holding its locks
+        let subscribers: Vec<SettingsCallback> = self.subscribers.lock().unwrap()
+            .iter()
+            .map(|(_, callback)| callback.clone())
+            .collect();
+        for callback in subscribers {
+            callback(&change);
+        }
+
+        Ok(())
+    }
+
+    /// Probe the machine's hardware video APIs and store the result; call at startup
+    ///
+    /// See `EngineSettings::apply_hardware_probe` for when the acceleration setting
+    /// follows the probe.
+    pub fn detect_hardware(&self) -> Result<HardwareDefaults> {
+        let probe = hardware_probe::probe();
+        for reason in &probe.reasons {
+            debug!("Hardware probe: {}", reason);
+        }
+        self.update(|settings| settings.apply_hardware_probe(probe.clone()))?;
+        Ok(probe)
+    }
+
+    /// Call `callback` after every change
+    pub fn subscribe(&self, callback: SettingsCallback) -> SubscriptionId {
+        let mut next = self.next_subscription.lock().unwrap();
+        let id = *next;
+        *next += 1;
+
+        self.subscribers.lock().unwrap().push((id, callback));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.subscribers.lock().unwrap().retain(|(subscription, _)| *subscription != id);
+    }
+
+    /// Write through a temporary file so a crash mid-write can't truncate the settings
+    fn save(&self, settings: &EngineSettings) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, self.format.serialize(settings)?)
+            .map_err(|e| anyhow!("Failed to write settings {:?}: {}", temp, e))?;
+        fs::rename(&temp, &self.path)
+            .map_err(|e| anyhow!("Failed to replace settings {:?}: {}", self.path, e))
+    }
+}
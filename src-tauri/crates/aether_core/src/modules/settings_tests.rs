@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use super::super::backend_policy::{Backend, BackendPreference, Subsystem};
+    use super::super::settings::{EngineSettings, HardwareAcceleration, PreviewQuality, SettingsStore};
+    use anyhow::Result;
+    use std::sync::{Arc, Mutex};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("aether-settings-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_settings_persist_across_sessions() -> Result<()> {
+        for name in ["settings.toml", "settings.json"] {
+            let path = temp_path(name);
+            let store = SettingsStore::open(&path)?;
+            assert_eq!(store.get(), EngineSettings::default());
+
+            store.update(|settings| {
+                settings.backends.set_preference(Subsystem::Thumbnailing, Some(BackendPreference::forced(Backend::GStreamer)));
+                settings.hardware_acceleration = HardwareAcceleration::Never;
+                settings.cache.preview_frames = 120;
+                settings.audio_device = Some("hw:1".to_string());
+                settings.preview_quality = PreviewQuality::Half;
+            })?;
+
+            let reopened = SettingsStore::open(&path)?;
+            assert_eq!(reopened.get(), store.get());
+            std::fs::remove_file(&path)?;
+        }
+
+        // Missing fields fall back to defaults
+        let partial: EngineSettings = toml::from_str("preview_quality = \"quarter\"")?;
+        assert_eq!(partial.preview_quality, PreviewQuality::Quarter);
+        assert_eq!(partial.cache, EngineSettings::default().cache);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_notify_subscribers() -> Result<()> {
+        let path = temp_path("notify.json");
+        let store = SettingsStore::open(&path)?;
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let sink = changes.clone();
+        let id = store.subscribe(Arc::new(move |change| {
+            sink.lock().unwrap().push((change.preview_quality_changed(), change.audio_device_changed()));
+        }));
+
+        store.update(|settings| settings.preview_quality = PreviewQuality::Quarter)?;
+        // Updates that change nothing don't notify
+        store.update(|settings| settings.preview_quality = PreviewQuality::Quarter)?;
+        store.unsubscribe(id);
+        store.update(|settings| settings.audio_device = Some("hw:2".to_string()))?;
+
+        assert_eq!(*changes.lock().unwrap(), vec![(true, false)]);
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_settings_file_is_set_aside() -> Result<()> {
+        let path = temp_path("broken.toml");
+        std::fs::write(&path, "preview_quality = [")?;
+
+        let store = SettingsStore::open(&path)?;
+        assert_eq!(store.get(), EngineSettings::default());
+        assert!(!path.exists());
+
+        std::fs::remove_file(path.with_extension("invalid"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_quality_scaling() {
+        assert_eq!(PreviewQuality::Full.scaled_size(1920, 1080), (1920, 1080));
+        assert_eq!(PreviewQuality::Quarter.scaled_size(1918, 1080), (478, 270));
+        assert!(HardwareAcceleration::Auto.for_decoding());
+        assert!(!HardwareAcceleration::Auto.for_encoding(false));
+        assert!(!HardwareAcceleration::Never.for_encoding(true));
+    }
+}
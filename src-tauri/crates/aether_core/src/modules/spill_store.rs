@@ -0,0 +1,311 @@
+//! Memory-bounded stores that spill to disk
+//!
+//! Undo history, preview frames and loudness profiles grow for as long as a session
+//! runs. A `SpillStore` keeps the most recently used entries in memory up to a byte
+//! limit and writes the rest to files in a private directory, reading them back when
+//! they are asked for. Callers see the same entries either way.
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, warn};
+
+use super::metrics::{self, Gauge};
+
+/// Bytes in a mebibyte, for limits configured in MB
+pub const MB: usize = 1024 * 1024;
+
+/// Distinguishes the directories of stores created by one process
+static NEXT_STORE: AtomicU64 = AtomicU64::new(0);
+
+/// A value a `SpillStore` can hold
+pub trait Spillable: Sized {
+    /// Approximate heap and inline size in bytes
+    fn memory_size(&self) -> usize;
+
+    /// Write the value for `read_from` to restore
+    fn write_to(&self, writer: &mut dyn Write) -> io::Result<()>;
+
+    fn read_from(reader: &mut dyn Read) -> io::Result<Self>;
+}
+
+/// Resident value with its size and last use
+struct Resident<V> {
+    value: V,
+    size: usize,
+    used: u64,
+}
+
+/// Key-value store keeping at most `memory_limit` bytes in memory
+///
+/// The least recently used entries are spilled first. The entry just inserted or read
+/// always stays resident, so one entry larger than the limit still works. When a spill
+/// file can't be written the entry stays in memory and a warning is logged.
+///
+/// Resident bytes are reported to the metrics registry as the memory of the subsystem
+/// named by the store's `name`, summed over all stores of that name.
+pub struct SpillStore<K, V> {
+    dir: PathBuf,
+    memory_limit: usize,
+    memory_used: usize,
+    /// Metrics gauge `memory_used` is added to
+    memory_gauge: Gauge,
+    resident: HashMap<K, Resident<V>>,
+    /// Resident keys by last use, oldest first
+    recency: BTreeMap<u64, K>,
+    spilled: HashMap<K, PathBuf>,
+    clock: u64,
+    next_file: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Spillable> SpillStore<K, V> {
+    /// Create a store spilling into a new directory under `parent`
+    ///
+    /// The directory is only created once something is spilled, and removed on drop.
+    pub fn new(parent: &Path, name: &str, memory_limit: usize) -> Self {
+        let index = NEXT_STORE.fetch_add(1, Ordering::Relaxed);
+        Self {
+            dir: parent.join(format!("{}-{}-{}", name, std::process::id(), index)),
+            memory_limit,
+            memory_used: 0,
+            memory_gauge: metrics::metrics().memory(name),
+            resident: HashMap::new(),
+            recency: BTreeMap::new(),
+            spilled: HashMap::new(),
+            clock: 0,
+            next_file: 0,
+        }
+    }
+
+    /// Create a store spilling under the default spill directory
+    pub fn in_temp(name: &str, memory_limit: usize) -> Self {
+        Self::new(&default_spill_dir(), name, memory_limit)
+    }
+
+    /// Directory spilled entries are written to
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Change the memory limit, spilling at once if it is now exceeded
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.spill_excess(None);
+    }
+
+    /// Bytes held in memory
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn len(&self) -> usize {
+        self.resident.len() + self.spilled.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of entries on disk
+    pub fn spilled_len(&self) -> usize {
+        self.spilled.len()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.resident.contains_key(key) || self.spilled.contains_key(key)
+    }
+
+    /// Whether an entry is held in memory
+    pub fn is_resident(&self, key: &K) -> bool {
+        self.resident.contains_key(key)
+    }
+
+    /// All keys, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.resident.keys().chain(self.spilled.keys())
+    }
+
+    /// Add or replace an entry, spilling older ones if the store is over its limit
+    pub fn insert(&mut self, key: K, value: V) {
+        self.discard(&key);
+        self.make_resident(key.clone(), value);
+        self.spill_excess(Some(&key));
+    }
+
+    /// Get an entry, reading it back from disk if it was spilled
+    pub fn get(&mut self, key: &K) -> Result<Option<&V>> {
+        if !self.contains_key(key) {
+            return Ok(None);
+        }
+
+        if let Some(path) = self.spilled.remove(key) {
+            let value = read_spilled(&path)?;
+            self.make_resident(key.clone(), value);
+            self.spill_excess(Some(key));
+        } else {
+            self.touch(key);
+        }
+        Ok(self.resident.get(key).map(|resident| &resident.value))
+    }
+
+    /// Remove an entry and return it, reading it back from disk if it was spilled
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>> {
+        if let Some(path) = self.spilled.remove(key) {
+            return read_spilled(&path).map(Some);
+        }
+
+        Ok(self.resident.remove(key).map(|resident| {
+            self.recency.remove(&resident.used);
+            self.track_memory(self.memory_used - resident.size);
+            resident.value
+        }))
+    }
+
+    /// Remove an entry without reading it back
+    pub fn discard(&mut self, key: &K) {
+        if let Some(path) = self.spilled.remove(key) {
+            remove_spill_file(&path);
+        }
+        if let Some(resident) = self.resident.remove(key) {
+            self.recency.remove(&resident.used);
+            self.track_memory(self.memory_used - resident.size);
+        }
+    }
+
+    /// Remove every entry
+    pub fn clear(&mut self) {
+        for path in self.spilled.values() {
+            remove_spill_file(path);
+        }
+        self.spilled.clear();
+        self.resident.clear();
+        self.recency.clear();
+        self.track_memory(0);
+    }
+
+    fn make_resident(&mut self, key: K, value: V) {
+        let size = value.memory_size();
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.track_memory(self.memory_used + size);
+        self.resident.insert(key, Resident { value, size, used: self.clock });
+    }
+
+    fn track_memory(&mut self, memory_used: usize) {
+        self.memory_gauge.add(memory_used as f64 - self.memory_used as f64);
+        self.memory_used = memory_used;
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(resident) = self.resident.get_mut(key) {
+            self.recency.remove(&resident.used);
+            self.clock += 1;
+            resident.used = self.clock;
+            self.recency.insert(self.clock, key.clone());
+        }
+    }
+
+    /// Spill the least recently used entries, other than `keep`, until under the limit
+    fn spill_excess(&mut self, keep: Option<&K>) {
+        let mut skipped = Vec::new();
+        while self.memory_used > self.memory_limit {
+            let Some((used, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if Some(&key) == keep {
+                skipped.push((used, key));
+                continue;
+            }
+
+            let resident = self.resident.remove(&key).unwrap();
+            match self.write_spilled(&resident.value) {
+                Ok(path) => {
+                    self.track_memory(self.memory_used - resident.size);
+                    self.spilled.insert(key, path);
+                },
+                Err(e) => {
+                    warn!("Failed to spill to {:?}, keeping the entry in memory: {}", self.dir, e);
+                    self.resident.insert(key.clone(), resident);
+                    skipped.push((used, key));
+                    break;
+                },
+            }
+        }
+        self.recency.extend(skipped);
+    }
+
+    fn write_spilled(&mut self, value: &V) -> io::Result<PathBuf> {
+        if self.spilled.is_empty() {
+            fs::create_dir_all(&self.dir)?;
+        }
+        let path = self.dir.join(format!("{}.spill", self.next_file));
+        self.next_file += 1;
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        value.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(path)
+    }
+}
+
+impl<K, V> Drop for SpillStore<K, V> {
+    fn drop(&mut self) {
+        self.memory_gauge.add(-(self.memory_used as f64));
+        if self.dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.dir) {
+                warn!("Failed to remove spill directory {:?}: {}", self.dir, e);
+            }
+        }
+    }
+}
+
+/// `aether/spill` under the system temp directory
+pub fn default_spill_dir() -> PathBuf {
+    std::env::temp_dir().join("aether").join("spill")
+}
+
+fn read_spilled<V: Spillable>(path: &Path) -> Result<V> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let value = V::read_from(&mut reader).map_err(|e| anyhow!("Failed to read spilled entry {:?}: {}", path, e))?;
+    remove_spill_file(path);
+    Ok(value)
+}
+
+fn remove_spill_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        debug!("Failed to remove spill file {:?}: {}", path, e);
+    }
+}
+
+/// Write a length-prefixed byte string
+pub fn write_bytes(writer: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Read a byte string written by `write_bytes`
+pub fn read_bytes(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let mut bytes = vec![0u8; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Spill a serde value as JSON, for structured entries such as undo steps
+pub fn write_json<T: serde::Serialize>(writer: &mut dyn Write, value: &T) -> io::Result<()> {
+    serde_json::to_writer(writer, value).map_err(io::Error::from)
+}
+
+/// Read a value written by `write_json`
+pub fn read_json<T: serde::de::DeserializeOwned>(reader: &mut dyn Read) -> io::Result<T> {
+    serde_json::from_reader(reader).map_err(io::Error::from)
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use super::super::spill_store::*;
+    use super::super::test_utils::create_test_dir;
+    use std::fs;
+    use std::io::{self, Read, Write};
+    use anyhow::Result;
+
+    /// A block of bytes whose size is its length
+    #[derive(Debug, Clone, PartialEq)]
+    struct Block(Vec<u8>);
+
+    impl Spillable for Block {
+        fn memory_size(&self) -> usize {
+            self.0.len()
+        }
+
+        fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+            write_bytes(writer, &self.0)
+        }
+
+        fn read_from(reader: &mut dyn Read) -> io::Result<Self> {
+            read_bytes(reader).map(Block)
+        }
+    }
+
+    #[test]
+    fn test_spills_least_recently_used() -> Result<()> {
+        let dir = create_test_dir("spill_store", "lru")?;
+        let mut store = SpillStore::new(&dir, "blocks", 250);
+
+        store.insert(1, Block(vec![1; 100]));
+        store.insert(2, Block(vec![2; 100]));
+        assert_eq!(store.spilled_len(), 0);
+
+        // Reading 1 makes 2 the oldest, so the third block pushes 2 out
+        assert_eq!(store.get(&1)?, Some(&Block(vec![1; 100])));
+        store.insert(3, Block(vec![3; 100]));
+        assert!(store.is_resident(&1));
+        assert!(!store.is_resident(&2));
+        assert_eq!(store.spilled_len(), 1);
+        assert_eq!(store.memory_used(), 200);
+        assert_eq!(fs::read_dir(store.dir())?.count(), 1);
+
+        // Spilled entries read back the same and come back into memory
+        assert_eq!(store.get(&2)?, Some(&Block(vec![2; 100])));
+        assert!(store.is_resident(&2));
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.spilled_len(), 1);
+        assert!(store.memory_used() <= 250);
+
+        assert_eq!(store.get(&4)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_entry_stays_resident() -> Result<()> {
+        let dir = create_test_dir("spill_store", "oversized")?;
+        let mut store = SpillStore::new(&dir, "blocks", 10);
+
+        store.insert("small", Block(vec![0; 5]));
+        store.insert("large", Block(vec![0; 50]));
+        assert!(store.is_resident(&"large"));
+        assert!(!store.is_resident(&"small"));
+        assert_eq!(store.memory_used(), 50);
+
+        // Lowering the limit spills even the newest entry
+        store.set_memory_limit(0);
+        assert_eq!(store.memory_used(), 0);
+        assert_eq!(store.remove(&"large")?, Some(Block(vec![0; 50])));
+        assert_eq!(store.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear_and_drop_remove_spill_files() -> Result<()> {
+        let dir = create_test_dir("spill_store", "cleanup")?;
+        let mut store = SpillStore::new(&dir, "blocks", 0);
+
+        store.insert(1, Block(vec![1; 10]));
+        store.insert(2, Block(vec![2; 10]));
+        store.insert(1, Block(vec![9; 10]));
+        assert_eq!(store.len(), 2);
+        assert_eq!(fs::read_dir(store.dir())?.count(), 1);
+
+        store.clear();
+        assert!(store.is_empty());
+        assert_eq!(fs::read_dir(store.dir())?.count(), 0);
+
+        store.insert(1, Block(vec![1; 10]));
+        store.insert(2, Block(vec![2; 10]));
+        let spill_dir = store.dir().to_path_buf();
+        assert!(spill_dir.exists());
+        drop(store);
+        assert!(!spill_dir.exists());
+        Ok(())
+    }
+}
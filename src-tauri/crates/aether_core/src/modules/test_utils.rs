@@ -0,0 +1,17 @@
+//! Helpers shared by the module tests
+
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Result;
+
+/// Empty directory for a single test, under a temp directory shared by its suite
+///
+/// Anything an earlier run left behind is removed first.
+pub(crate) fn create_test_dir(suite: &str, name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("aether_{}_test", suite)).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use std::path::Path;
+
+use crate::engine::timeline::Timeline;
+use super::media_library::MediaLibrary;
+
+/// A transcribed word with its timing in the source, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptWord {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// A transcribed sentence or subtitle line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Word timings, if the transcriber produced them
+    #[serde(default)]
+    pub words: Vec<TranscriptWord>,
+}
+
+/// Speech in a media file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub language: Option<String>,
+    /// Segments in time order
+    pub segments: Vec<TranscriptSegment>,
+}
+
+/// Where a phrase was said in a transcript
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptMatch {
+    /// Source time the phrase starts, in seconds
+    pub start: f64,
+    /// Source time the phrase ends, in seconds
+    pub end: f64,
+    /// Text of the segment containing the phrase
+    pub context: String,
+}
+
+/// Produces transcripts for media files, e.g. a speech recognition engine
+pub trait Transcriber: Send + Sync {
+    fn transcribe(&self, path: &Path) -> Result<Transcript>;
+}
+
+impl Transcript {
+    /// Parse SubRip subtitles, such as a transcriber's sidecar output
+    pub fn from_srt(srt: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+
+        for block in srt.replace("\r\n", "\n").split("\n\n") {
+            let mut lines = block.lines().map(str::trim).filter(|line| !line.is_empty());
+            let Some(mut line) = lines.next() else {
+                continue;
+            };
+            // The cue number is optional in practice
+            if !line.contains("-->") {
+                line = lines.next().ok_or_else(|| anyhow!("Subtitle cue without timing: {:?}", block))?;
+            }
+
+            let (start, end) = line
+                .split_once("-->")
+                .ok_or_else(|| anyhow!("Invalid subtitle timing: {:?}", line))?;
+            let text = lines.collect::<Vec<_>>().join(" ");
+
+            segments.push(TranscriptSegment {
+                start: parse_srt_time(start)?,
+                end: parse_srt_time(end)?,
+                text,
+                words: Vec::new(),
+            });
+        }
+
+        segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Ok(Self {
+            language: None,
+            segments,
+        })
+    }
+
+    /// Every place `phrase` was said, ignoring case and punctuation
+    ///
+    /// With word timings, phrases are found across segment boundaries and timed exactly;
+    /// otherwise matches are timed by the segment they occur in.
+    pub fn find_phrase(&self, phrase: &str) -> Vec<TranscriptMatch> {
+        let needle = normalized_words(phrase);
+        if needle.is_empty() {
+            return Vec::new();
+        }
+
+        if self.segments.iter().all(|segment| !segment.words.is_empty()) {
+            return self.find_in_words(&needle);
+        }
+
+        self.segments
+            .iter()
+            .filter(|segment| normalized_words(&segment.text).windows(needle.len()).any(|window| window == needle.as_slice()))
+            .map(|segment| TranscriptMatch {
+                start: segment.start,
+                end: segment.end,
+                context: segment.text.clone(),
+            })
+            .collect()
+    }
+
+    fn find_in_words(&self, needle: &[String]) -> Vec<TranscriptMatch> {
+        // Flatten words, keeping the segment each came from
+        let words: Vec<(usize, &TranscriptWord, String)> = self.segments
+            .iter()
+            .enumerate()
+            .flat_map(|(index, segment)| segment.words.iter().map(move |word| (index, word)))
+            .filter_map(|(index, word)| {
+                let text = normalized_words(&word.text).join(" ");
+                (!text.is_empty()).then_some((index, word, text))
+            })
+            .collect();
+
+        words
+            .windows(needle.len())
+            .filter(|window| window.iter().map(|(_, _, text)| text).eq(needle.iter()))
+            .map(|window| {
+                let (segment, first, _) = &window[0];
+                let (_, last, _) = &window[window.len() - 1];
+                TranscriptMatch {
+                    start: first.start,
+                    end: last.end,
+                    context: self.segments[*segment].text.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A phrase found in a library asset
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpokenMatch {
+    pub asset_id: String,
+    pub start: f64,
+    pub end: f64,
+    pub context: String,
+}
+
+/// Transcribe assets that have no transcript yet, returning how many were transcribed
+///
+/// Assets that fail are logged and skipped.
+pub fn transcribe_library(library: &mut MediaLibrary, transcriber: &dyn Transcriber) -> Result<usize> {
+    let pending: Vec<String> = library.assets()
+        .into_iter()
+        .filter(|asset| asset.transcript.is_none() && !asset.offline)
+        .map(|asset| asset.id.clone())
+        .collect();
+
+    let mut transcribed = 0;
+    for id in pending {
+        let path = library.resolve_path(&id)?;
+        match transcriber.transcribe(&path) {
+            Ok(transcript) => {
+                library.set_transcript(&id, Some(transcript))?;
+                transcribed += 1;
+            },
+            Err(e) => warn!("Failed to transcribe {} ({:?}): {}", id, path, e),
+        }
+    }
+    Ok(transcribed)
+}
+
+/// Search the transcripts of every asset for a phrase, by asset then time
+pub fn search_spoken(library: &MediaLibrary, phrase: &str) -> Vec<SpokenMatch> {
+    library.assets()
+        .into_iter()
+        .filter_map(|asset| asset.transcript.as_ref().map(|transcript| (asset, transcript)))
+        .flat_map(|(asset, transcript)| {
+            transcript.find_phrase(phrase).into_iter().map(move |found| SpokenMatch {
+                asset_id: asset.id.clone(),
+                start: found.start,
+                end: found.end,
+                context: found.context,
+            })
+        })
+        .collect()
+}
+
+/// Timeline time where a match is heard, from the earliest clip using that part of the asset
+pub fn timeline_time(timeline: &Timeline, library: &MediaLibrary, found: &SpokenMatch) -> Option<f64> {
+    timeline
+        .tracks()
+        .values()
+        .flat_map(|track| track.clips.iter())
+        .filter(|clip| {
+            clip.source_path.as_deref()
+                .and_then(|source| library.find_by_path(Path::new(source)))
+                .is_some_and(|asset| asset.id == found.asset_id)
+        })
+        .filter(|clip| found.start >= clip.in_point() && found.start < clip.in_point() + clip.duration)
+        .map(|clip| clip.start_time + (found.start - clip.in_point()))
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// Move the playhead to where a match is heard, returning the new time
+///
+/// Fails if no clip on the timeline uses that part of the asset.
+pub fn seek_to_match(timeline: &mut Timeline, library: &MediaLibrary, found: &SpokenMatch) -> Result<f64> {
+    let time = timeline_time(timeline, library, found)
+        .ok_or_else(|| anyhow!("\"{}\" in {} is not used on the timeline", found.context, found.asset_id))?;
+    timeline.seek(time)?;
+
+    debug!("Seeked to {:.3}s for match in {}", time, found.asset_id);
+    Ok(time)
+}
+
+/// Lowercase words without punctuation
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric() || *c == '\'').collect::<String>().to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Parse `HH:MM:SS,mmm` into seconds
+fn parse_srt_time(value: &str) -> Result<f64> {
+    let value = value.trim().replace(',', ".");
+    let parts: Vec<&str> = value.split(':').collect();
+    let [hours, minutes, seconds] = parts.as_slice() else {
+        return Err(anyhow!("Invalid subtitle time: {:?}", value));
+    };
+
+    let parse = |part: &str| part.parse::<f64>().map_err(|_| anyhow!("Invalid subtitle time: {:?}", value));
+    Ok(parse(hours)? * 3600.0 + parse(minutes)? * 60.0 + parse(seconds)?)
+}
@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use super::super::media_library::MediaLibrary;
+    use super::super::transcription::{search_spoken, seek_to_match, Transcript, TranscriptSegment, TranscriptWord};
+    use crate::engine::timeline::{Clip, ClipType, Timeline, TimelineConfig, Track};
+    use anyhow::Result;
+    use std::fs;
+
+    fn word(start: f64, text: &str) -> TranscriptWord {
+        TranscriptWord { start, end: start + 0.4, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_find_phrase_in_srt() -> Result<()> {
+        let transcript = Transcript::from_srt(
+            "1\n00:00:01,000 --> 00:00:03,500\nWe shot this on the beach.\n\n2\n00:01:02,250 --> 00:01:04,000\nThe BEACH was cold!\n",
+        )?;
+        assert_eq!(transcript.segments.len(), 2);
+        assert_eq!(transcript.segments[1].start, 62.25);
+
+        let found = transcript.find_phrase("the beach");
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].start, found[0].end), (1.0, 3.5));
+        assert_eq!(found[1].context, "The BEACH was cold!");
+        assert!(transcript.find_phrase("mountain").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_phrase_across_segments_with_word_timings() -> Result<()> {
+        let transcript = Transcript {
+            language: Some("en".to_string()),
+            segments: vec![
+                TranscriptSegment { start: 0.0, end: 1.0, text: "Thank you".to_string(), words: vec![word(0.0, "Thank"), word(0.5, "you")] },
+                TranscriptSegment { start: 1.0, end: 2.0, text: "so much.".to_string(), words: vec![word(1.0, "so"), word(1.5, "much.")] },
+            ],
+        };
+
+        let found = transcript.find_phrase("you so much");
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].start, found[0].end), (0.5, 1.9));
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_to_spoken_phrase() -> Result<()> {
+        let root = std::env::temp_dir().join("aether_transcription_test");
+        fs::create_dir_all(&root)?;
+        let media = root.join("interview.mp4");
+        fs::write(&media, b"interview")?;
+
+        let mut library = MediaLibrary::new(&root);
+        let id = library.add_asset(&media, None)?;
+        library.set_transcript(&id, Some(Transcript::from_srt("00:00:40,000 --> 00:00:42,000\nit changed everything\n")?))?;
+
+        let mut timeline = Timeline::new(TimelineConfig::default());
+        timeline.add_track(Track::new("video".to_string(), "Video".to_string()))?;
+        let mut clip = Clip::new("clip1".to_string(), ClipType::Video, 10.0, 20.0)
+            .with_source(media.to_string_lossy().to_string());
+        clip.set_in_point(35.0);
+        timeline.add_clip_to_track("video", clip)?;
+
+        let found = search_spoken(&library, "changed everything");
+        assert_eq!(found.len(), 1);
+        assert_eq!(seek_to_match(&mut timeline, &library, &found[0])?, 15.0);
+        assert_eq!(timeline.current_time(), 15.0);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
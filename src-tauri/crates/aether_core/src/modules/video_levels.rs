@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// BT.709 chroma to R'G'B' weights on the studio-swing scale
+const CR_TO_R: f32 = 1.5748 * 219.0 / 224.0;
+const CB_TO_G: f32 = -0.1873 * 219.0 / 224.0;
+const CR_TO_G: f32 = -0.4681 * 219.0 / 224.0;
+const CB_TO_B: f32 = 1.8556 * 219.0 / 224.0;
+
+/// Legal ranges of 8-bit Y'CbCr video
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LevelLimits {
+    /// Lowest and highest legal luma, also applied to the R'G'B' the pixel decodes to
+    pub luma: (u8, u8),
+    /// Lowest and highest legal Cb and Cr
+    pub chroma: (u8, u8),
+    /// Share of a frame's pixels that may be out of range before the frame is flagged
+    pub tolerance: f64,
+}
+
+impl Default for LevelLimits {
+    fn default() -> Self {
+        Self::legal()
+    }
+}
+
+impl LevelLimits {
+    /// Nominal video range: luma 16-235, chroma 16-240, no tolerance
+    pub fn legal() -> Self {
+        Self { luma: (16, 235), chroma: (16, 240), tolerance: 0.0 }
+    }
+
+    /// EBU R 103: -1% to 103% of the luma range, with 1% of a frame's pixels let through
+    pub fn ebu_r103() -> Self {
+        Self { luma: (14, 241), chroma: (16, 240), tolerance: 0.01 }
+    }
+}
+
+/// An 8-bit Y'CbCr 4:2:0 frame, as three planes with their strides
+#[derive(Debug, Clone, Copy)]
+pub struct YuvFrame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub y: &'a [u8],
+    pub y_stride: usize,
+    pub cb: &'a [u8],
+    pub cr: &'a [u8],
+    /// Stride of both chroma planes
+    pub chroma_stride: usize,
+}
+
+impl<'a> YuvFrame<'a> {
+    /// A frame whose rows have no padding
+    pub fn packed(width: usize, height: usize, y: &'a [u8], cb: &'a [u8], cr: &'a [u8]) -> Self {
+        Self { width, height, y, y_stride: width, cb, cr, chroma_stride: width.div_ceil(2) }
+    }
+
+    fn check(&self) -> Result<()> {
+        check_planes(self.width, self.height, (self.y.len(), self.y_stride), (self.cb.len().min(self.cr.len()), self.chroma_stride))
+    }
+}
+
+/// A 4:2:0 frame to change in place, laid out as `YuvFrame`
+#[derive(Debug)]
+pub struct YuvFrameMut<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub y: &'a mut [u8],
+    pub y_stride: usize,
+    pub cb: &'a mut [u8],
+    pub cr: &'a mut [u8],
+    pub chroma_stride: usize,
+}
+
+/// Levels of one analyzed frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameLevels {
+    /// Frame number, counting from 0
+    pub frame: u64,
+    pub luma_min: u8,
+    pub luma_max: u8,
+    /// Share of pixels with luma below the legal range
+    pub luma_low: f64,
+    /// Share of pixels with luma above the legal range
+    pub luma_high: f64,
+    /// Share of chroma samples outside the legal range
+    pub chroma_illegal: f64,
+    /// Share of pixels that decode to R'G'B' outside the legal range
+    pub out_of_gamut: f64,
+    /// Whether any of the shares is over the tolerance
+    pub illegal: bool,
+}
+
+/// Run of consecutive illegal frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IllegalRange {
+    pub first_frame: u64,
+    pub last_frame: u64,
+    /// Times of the first frame and the end of the last one, in seconds
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Flags frames with illegal luma, chroma or R'G'B' levels
+///
+/// Frames are analyzed in order; only the illegal ones are kept.
+#[derive(Debug, Clone)]
+pub struct VideoLevels {
+    limits: LevelLimits,
+    frames_analyzed: u64,
+    illegal_frames: Vec<FrameLevels>,
+}
+
+impl VideoLevels {
+    pub fn new(limits: LevelLimits) -> Self {
+        Self { limits, frames_analyzed: 0, illegal_frames: Vec::new() }
+    }
+
+    pub fn limits(&self) -> &LevelLimits {
+        &self.limits
+    }
+
+    /// Measure the next frame
+    pub fn analyze(&mut self, frame: &YuvFrame) -> Result<FrameLevels> {
+        frame.check()?;
+        let LevelLimits { luma: (luma_lo, luma_hi), chroma: (chroma_lo, chroma_hi), tolerance } = self.limits;
+
+        let (mut luma_min, mut luma_max) = (u8::MAX, u8::MIN);
+        let (mut low, mut high, mut gamut) = (0u64, 0u64, 0u64);
+        for row in 0..frame.height {
+            let luma = &frame.y[row * frame.y_stride..][..frame.width];
+            let chroma_row = (row / 2) * frame.chroma_stride;
+            for (column, &y) in luma.iter().enumerate() {
+                luma_min = luma_min.min(y);
+                luma_max = luma_max.max(y);
+                if y < luma_lo {
+                    low += 1;
+                } else if y > luma_hi {
+                    high += 1;
+                }
+                let chroma = chroma_row + column / 2;
+                let rgb = to_rgb(y, frame.cb[chroma], frame.cr[chroma]);
+                if rgb.iter().any(|c| *c < luma_lo as f32 - 0.5 || *c > luma_hi as f32 + 0.5) {
+                    gamut += 1;
+                }
+            }
+        }
+
+        let chroma_height = frame.height.div_ceil(2);
+        let chroma_width = frame.width.div_ceil(2);
+        let mut chroma_illegal = 0u64;
+        for row in 0..chroma_height {
+            let start = row * frame.chroma_stride;
+            for plane in [frame.cb, frame.cr] {
+                chroma_illegal += plane[start..][..chroma_width].iter()
+                    .filter(|c| **c < chroma_lo || **c > chroma_hi)
+                    .count() as u64;
+            }
+        }
+
+        let pixels = (frame.width * frame.height).max(1) as f64;
+        let chroma_samples = (2 * chroma_width * chroma_height).max(1) as f64;
+        let mut levels = FrameLevels {
+            frame: self.frames_analyzed,
+            luma_min,
+            luma_max,
+            luma_low: low as f64 / pixels,
+            luma_high: high as f64 / pixels,
+            chroma_illegal: chroma_illegal as f64 / chroma_samples,
+            out_of_gamut: gamut as f64 / pixels,
+            illegal: false,
+        };
+        levels.illegal = [levels.luma_low + levels.luma_high, levels.chroma_illegal, levels.out_of_gamut]
+            .iter()
+            .any(|share| *share > tolerance);
+
+        self.frames_analyzed += 1;
+        if levels.illegal {
+            self.illegal_frames.push(levels.clone());
+        }
+        Ok(levels)
+    }
+
+    pub fn frames_analyzed(&self) -> u64 {
+        self.frames_analyzed
+    }
+
+    /// Levels of the flagged frames, in order
+    pub fn illegal_frames(&self) -> &[FrameLevels] {
+        &self.illegal_frames
+    }
+
+    /// Flagged frames merged into runs, with times at `frame_rate`
+    pub fn illegal_ranges(&self, frame_rate: f64) -> Vec<IllegalRange> {
+        let mut ranges: Vec<IllegalRange> = Vec::new();
+        for levels in &self.illegal_frames {
+            match ranges.last_mut() {
+                Some(range) if range.last_frame + 1 == levels.frame => range.last_frame = levels.frame,
+                _ => ranges.push(IllegalRange { first_frame: levels.frame, last_frame: levels.frame, start: 0.0, end: 0.0 }),
+            }
+        }
+        for range in &mut ranges {
+            range.start = range.first_frame as f64 / frame_rate;
+            range.end = (range.last_frame + 1) as f64 / frame_rate;
+        }
+        ranges
+    }
+}
+
+/// Bring a 4:2:0 frame into `limits` in place, a broadcast-safe limiter
+///
+/// Luma and chroma are clamped to their ranges, then chroma is pulled toward neutral
+/// just enough for every pixel's R'G'B' to be legal, so hue is kept while saturation
+/// drops. The tolerance isn't used: every pixel ends up legal.
+pub fn broadcast_safe(frame: &mut YuvFrameMut, limits: &LevelLimits) -> Result<()> {
+    let YuvFrameMut { width, height, y_stride, chroma_stride, .. } = *frame;
+    check_planes(width, height, (frame.y.len(), y_stride), (frame.cb.len().min(frame.cr.len()), chroma_stride))?;
+    let (luma_lo, luma_hi) = (limits.luma.0 as f32, limits.luma.1 as f32);
+    let (chroma_lo, chroma_hi) = limits.chroma;
+
+    for row in 0..height {
+        for value in &mut frame.y[row * y_stride..][..width] {
+            *value = (*value).clamp(limits.luma.0, limits.luma.1);
+        }
+    }
+
+    for chroma_row in 0..height.div_ceil(2) {
+        for chroma_column in 0..width.div_ceil(2) {
+            let index = chroma_row * chroma_stride + chroma_column;
+            let cb_offset = frame.cb[index].clamp(chroma_lo, chroma_hi) as f32 - 128.0;
+            let cr_offset = frame.cr[index].clamp(chroma_lo, chroma_hi) as f32 - 128.0;
+            let deltas = [CR_TO_R * cr_offset, CB_TO_G * cb_offset + CR_TO_G * cr_offset, CB_TO_B * cb_offset];
+            // Luma samples sharing this chroma sample
+            let (mut samples, mut count) = ([0u8; 4], 0);
+            for row in (2 * chroma_row)..(2 * chroma_row + 2).min(height) {
+                for column in (2 * chroma_column)..(2 * chroma_column + 2).min(width) {
+                    samples[count] = frame.y[row * y_stride + column];
+                    count += 1;
+                }
+            }
+            let block = &samples[..count];
+
+            // Largest share of the chroma that keeps them all legal
+            let mut scale = 1.0f32;
+            for luma in block.iter().map(|luma| *luma as f32) {
+                for delta in deltas {
+                    if luma + delta > luma_hi {
+                        scale = scale.min((luma_hi - luma) / delta);
+                    } else if luma + delta < luma_lo {
+                        scale = scale.min((luma_lo - luma) / delta);
+                    }
+                }
+            }
+            // Rounding to 8 bits can push a pixel back out, so back off until it doesn't
+            let mut scale = scale.clamp(0.0, 1.0);
+            loop {
+                let (new_cb, new_cr) = ((128.0 + cb_offset * scale).round() as u8, (128.0 + cr_offset * scale).round() as u8);
+                let legal = block.iter().all(|luma| {
+                    to_rgb(*luma, new_cb, new_cr).iter().all(|c| *c >= luma_lo - 0.5 && *c <= luma_hi + 0.5)
+                });
+                if legal || scale == 0.0 {
+                    frame.cb[index] = new_cb;
+                    frame.cr[index] = new_cr;
+                    break;
+                }
+                scale = (scale - 0.05).max(0.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Studio-swing R'G'B' of a Y'CbCr sample
+fn to_rgb(y: u8, cb: u8, cr: u8) -> [f32; 3] {
+    let (y, cb, cr) = (y as f32, cb as f32 - 128.0, cr as f32 - 128.0);
+    [y + CR_TO_R * cr, y + CB_TO_G * cb + CR_TO_G * cr, y + CB_TO_B * cb]
+}
+
+/// Check that planes of the given lengths and strides hold a 4:2:0 frame
+fn check_planes(width: usize, height: usize, (luma_len, luma_stride): (usize, usize), (chroma_len, chroma_stride): (usize, usize)) -> Result<()> {
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Empty frame"));
+    }
+    if luma_stride < width || luma_len < luma_stride * (height - 1) + width {
+        return Err(anyhow!("Luma plane too small for a {}x{} frame", width, height));
+    }
+    if chroma_stride < chroma_width || chroma_len < chroma_stride * (chroma_height - 1) + chroma_width {
+        return Err(anyhow!("Chroma planes too small for a {}x{} frame", width, height));
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+    use super::super::video_levels::{broadcast_safe, LevelLimits, VideoLevels, YuvFrame, YuvFrameMut};
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 4;
+
+    /// Planes of a flat 4:2:0 frame
+    fn flat(y: u8, cb: u8, cr: u8) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let chroma = (WIDTH / 2) * (HEIGHT / 2);
+        (vec![y; WIDTH * HEIGHT], vec![cb; chroma], vec![cr; chroma])
+    }
+
+    #[test]
+    fn test_flags_illegal_frames() {
+        let mut levels = VideoLevels::new(LevelLimits::legal());
+
+        // Mid gray, then super-white luma, then saturated red past the R'G'B' limits
+        let (y, cb, cr) = flat(126, 128, 128);
+        let gray = levels.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y, &cb, &cr)).unwrap();
+        assert!(!gray.illegal);
+        assert_eq!((gray.luma_min, gray.luma_max), (126, 126));
+
+        let (mut y, cb, cr) = flat(126, 128, 128);
+        y[..WIDTH].fill(250);
+        let hot = levels.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y, &cb, &cr)).unwrap();
+        assert!(hot.illegal);
+        assert_eq!(hot.luma_high, 0.25);
+        assert_eq!(hot.luma_max, 250);
+
+        let (y, cb, cr) = flat(120, 100, 250);
+        let red = levels.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y, &cb, &cr)).unwrap();
+        assert!(red.illegal);
+        assert_eq!(red.out_of_gamut, 1.0);
+        assert_eq!(red.chroma_illegal, 0.5);
+        assert_eq!((red.luma_low, red.luma_high), (0.0, 0.0));
+
+        assert_eq!(levels.frames_analyzed(), 3);
+        let ranges = levels.illegal_ranges(25.0);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].first_frame, ranges[0].last_frame), (1, 2));
+        assert_eq!((ranges[0].start, ranges[0].end), (0.04, 0.12));
+
+        // A few hot pixels are within the EBU tolerance
+        let mut lenient = VideoLevels::new(LevelLimits { tolerance: 0.05, ..LevelLimits::ebu_r103() });
+        let (mut y, cb, cr) = flat(126, 128, 128);
+        y[0] = 250;
+        assert!(!lenient.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y, &cb, &cr)).unwrap().illegal);
+        assert!(lenient.illegal_frames().is_empty());
+
+        // Planes too small for the frame
+        assert!(levels.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y[..10], &cb, &cr)).is_err());
+    }
+
+    #[test]
+    fn test_broadcast_safe_clamp() {
+        let limits = LevelLimits::legal();
+        let (mut y, mut cb, mut cr) = flat(120, 100, 250);
+        y[..WIDTH].fill(250);
+        y[WIDTH..2 * WIDTH].fill(2);
+        cb[0] = 255;
+
+        // Padded rows are left alone
+        let stride = WIDTH + 4;
+        let mut padded = vec![0u8; stride * HEIGHT];
+        for row in 0..HEIGHT {
+            padded[row * stride..][..WIDTH].copy_from_slice(&y[row * WIDTH..][..WIDTH]);
+        }
+        broadcast_safe(&mut YuvFrameMut {
+            width: WIDTH,
+            height: HEIGHT,
+            y: &mut padded,
+            y_stride: stride,
+            cb: &mut cb,
+            cr: &mut cr,
+            chroma_stride: WIDTH / 2,
+        }, &limits).unwrap();
+        assert!(padded[WIDTH..stride].iter().all(|value| *value == 0));
+        for row in 0..HEIGHT {
+            y[row * WIDTH..][..WIDTH].copy_from_slice(&padded[row * stride..][..WIDTH]);
+        }
+
+        let mut levels = VideoLevels::new(limits);
+        let clamped = levels.analyze(&YuvFrame::packed(WIDTH, HEIGHT, &y, &cb, &cr)).unwrap();
+        assert!(!clamped.illegal, "{:?}", clamped);
+        assert_eq!((clamped.luma_min, clamped.luma_max), (16, 235));
+
+        // Saturation drops but the hue stays red
+        assert!(cr[7] > 128 && cr[7] < 240);
+        assert!(cb[7] < 128);
+        // Chroma shared by peak white and black can only go neutral
+        assert_eq!((cb[3], cr[3]), (128, 128));
+
+        // Legal frames come through unchanged
+        let (mut y, mut cb, mut cr) = flat(126, 110, 150);
+        let original = (y.clone(), cb.clone(), cr.clone());
+        broadcast_safe(&mut YuvFrameMut {
+            width: WIDTH,
+            height: HEIGHT,
+            y: &mut y,
+            y_stride: WIDTH,
+            cb: &mut cb,
+            cr: &mut cr,
+            chroma_stride: WIDTH / 2,
+        }, &limits).unwrap();
+        assert_eq!((y, cb, cr), original);
+    }
+}
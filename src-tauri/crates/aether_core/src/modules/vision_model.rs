@@ -0,0 +1,209 @@
+//! ONNX image models run over frames sampled from media files
+//!
+//! The ONNX Runtime library is loaded at runtime, so analysis passes are only available
+//! where it is installed and everything else works without it.
+
+use anyhow::{anyhow, Result};
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{context::Context as SwsContext, flag::Flags};
+use ffmpeg::util::frame::video::Video;
+use ort::session::Session;
+use ort::value::Tensor;
+use tracing::debug;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A decoded frame scaled for a model
+#[derive(Debug, Clone)]
+pub struct SampledFrame {
+    /// Position in the source in seconds
+    pub timestamp: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Packed RGB24 pixels, row by row
+    pub rgb: Vec<u8>,
+}
+
+impl SampledFrame {
+    /// Planar NCHW tensor data with `(value - mean) / std` applied per channel
+    pub fn to_nchw(&self, mean: [f32; 3], std: [f32; 3]) -> Vec<f32> {
+        let plane = (self.width * self.height) as usize;
+        let mut data = vec![0.0; plane * 3];
+        for (i, pixel) in self.rgb.chunks_exact(3).take(plane).enumerate() {
+            for channel in 0..3 {
+                data[channel * plane + i] = (pixel[channel] as f32 - mean[channel]) / std[channel];
+            }
+        }
+        data
+    }
+}
+
+/// Decode one frame every `interval` seconds, scaled to `width`x`height`
+///
+/// Stops after `max_frames`. Still images yield a single frame.
+pub fn sample_frames(path: &Path, interval: f64, width: u32, height: u32, max_frames: usize) -> Result<Vec<SampledFrame>> {
+    ffmpeg::init()?;
+
+    let mut input = ffmpeg::format::input(&path)
+        .map_err(|e| anyhow!("Failed to open {:?}: {}", path, e))?;
+    let stream = input
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| anyhow!("No video stream in {:?}", path))?;
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    let mut scaler = SwsContext::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGB24,
+        width,
+        height,
+        Flags::BILINEAR,
+    )?;
+
+    let mut frames = Vec::new();
+    let mut next_sample = 0.0;
+    let mut decoded = Video::empty();
+    let mut take = |decoded: &Video, frames: &mut Vec<SampledFrame>, next_sample: &mut f64| -> Result<()> {
+        let timestamp = decoded.timestamp().map(|t| t as f64 * time_base).unwrap_or(*next_sample);
+        if timestamp + 0.001 < *next_sample || frames.len() >= max_frames {
+            return Ok(());
+        }
+
+        let mut scaled = Video::empty();
+        scaler.run(decoded, &mut scaled)?;
+        frames.push(SampledFrame {
+            timestamp,
+            width,
+            height,
+            rgb: packed_rgb(&scaled),
+        });
+        *next_sample = timestamp + interval.max(0.001);
+        Ok(())
+    };
+
+    for (stream, packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            take(&decoded, &mut frames, &mut next_sample)?;
+        }
+        if frames.len() >= max_frames {
+            break;
+        }
+    }
+
+    decoder.send_eof()?;
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        take(&decoded, &mut frames, &mut next_sample)?;
+    }
+
+    debug!("Sampled {} frames from {:?}", frames.len(), path);
+    Ok(frames)
+}
+
+/// Copy RGB24 rows without the line padding
+fn packed_rgb(frame: &Video) -> Vec<u8> {
+    let row = frame.width() as usize * 3;
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+    let mut rgb = Vec::with_capacity(row * frame.height() as usize);
+    for y in 0..frame.height() as usize {
+        rgb.extend_from_slice(&data[y * stride..y * stride + row]);
+    }
+    rgb
+}
+
+/// One output tensor of a model run
+#[derive(Debug, Clone)]
+pub struct ModelOutput {
+    pub shape: Vec<i64>,
+    pub data: Vec<f32>,
+}
+
+/// An ONNX model taking a single NCHW image tensor
+pub struct OnnxModel {
+    path: PathBuf,
+
+    session: Mutex<Session>,
+
+    input_name: String,
+
+    output_names: Vec<String>,
+
+    input_width: u32,
+
+    input_height: u32,
+}
+
+impl OnnxModel {
+    /// Load a model, taking the input size from its input shape
+    ///
+    /// Models with a dynamic input size need `default_size` as `(width, height)`.
+    pub fn load(path: &Path, default_size: Option<(u32, u32)>) -> Result<Self> {
+        let session = Session::builder()?
+            .commit_from_file(path)
+            .map_err(|e| anyhow!("Failed to load model {:?}: {}", path, e))?;
+
+        let input = session.inputs.first().ok_or_else(|| anyhow!("Model {:?} has no inputs", path))?;
+        let shape = input.input_type.tensor_shape().map(|shape| shape.to_vec()).unwrap_or_default();
+        let (input_width, input_height) = match shape.as_slice() {
+            [_, 3, height, width] if *width > 0 && *height > 0 => (*width as u32, *height as u32),
+            [_, 3, _, _] => default_size.ok_or_else(|| anyhow!("Model {:?} has a dynamic input size", path))?,
+            other => return Err(anyhow!("Model {:?} does not take an NCHW image, input shape {:?}", path, other)),
+        };
+
+        let input_name = input.name.clone();
+        let output_names = session.outputs.iter().map(|output| output.name.clone()).collect();
+        debug!("Loaded model {:?} with {}x{} input", path, input_width, input_height);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            session: Mutex::new(session),
+            input_name,
+            output_names,
+            input_width,
+            input_height,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Input size as `(width, height)`
+    pub fn input_size(&self) -> (u32, u32) {
+        (self.input_width, self.input_height)
+    }
+
+    /// Run the model on one image in NCHW layout, returning the float outputs by name
+    pub fn run(&self, input: Vec<f32>) -> Result<HashMap<String, ModelOutput>> {
+        let shape = [1usize, 3, self.input_height as usize, self.input_width as usize];
+        if input.len() != shape.iter().product::<usize>() {
+            return Err(anyhow!("Input has {} values, the model expects {:?}", input.len(), shape));
+        }
+
+        let tensor = Tensor::from_array((shape, input))?;
+        let mut session = self.session.lock().unwrap();
+        let outputs = session.run(ort::inputs![self.input_name.as_str() => tensor])?;
+
+        let mut results = HashMap::new();
+        for name in &self.output_names {
+            let (shape, data) = outputs[name.as_str()].try_extract_tensor::<f32>()?;
+            results.insert(name.clone(), ModelOutput {
+                shape: shape.to_vec(),
+                data: data.to_vec(),
+            });
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,39 @@
+/* C API of the aether engine. See src/lib.rs for details. */
+
+#ifndef AETHER_FFI_H
+#define AETHER_FFI_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define AETHER_FFI_VERSION 1
+
+typedef struct AetherProject AetherProject;
+typedef struct AetherRenderQueue AetherRenderQueue;
+
+/* Errors */
+uint32_t aether_ffi_version(void);
+const char *aether_last_error(void);
+void aether_string_free(char *value);
+
+/* Projects and timeline queries */
+AetherProject *aether_project_open(const char *path);
+void aether_project_close(AetherProject *project);
+int64_t aether_timeline_duration(AetherProject *project);
+char *aether_timeline_clips_json(AetherProject *project);
+
+/* Export submission; priority is 0 (background), 1 (normal) or 2 (high) */
+AetherRenderQueue *aether_render_queue_new(uint32_t max_concurrent);
+void aether_render_queue_free(AetherRenderQueue *queue);
+uint64_t aether_export_submit(AetherRenderQueue *queue, const char *options_json, uint32_t priority);
+char *aether_export_status_json(AetherRenderQueue *queue, uint64_t job_id);
+int32_t aether_export_cancel(AetherRenderQueue *queue, uint64_t job_id);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AETHER_FFI_H */
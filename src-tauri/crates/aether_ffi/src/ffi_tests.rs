@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    fn last_error() -> Option<String> {
+        let error = aether_last_error();
+        if error.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned())
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_sets_last_error() {
+        let queue = aether_render_queue_new(1);
+        assert!(!queue.is_null());
+
+        unsafe {
+            let invalid = CString::new("{ not json").unwrap();
+            assert_eq!(aether_export_submit(queue, invalid.as_ptr(), 1), 0);
+            assert!(last_error().unwrap().starts_with("Invalid export options"));
+
+            // Wrong types are as invalid as broken syntax
+            let wrong_type = CString::new(r#"{ "output_path": 42 }"#).unwrap();
+            assert_eq!(aether_export_submit(queue, wrong_type.as_ptr(), 1), 0);
+            assert!(last_error().unwrap().starts_with("Invalid export options"));
+
+            let options = CString::new("{}").unwrap();
+            assert_eq!(aether_export_submit(queue, options.as_ptr(), 7), 0);
+            assert_eq!(last_error().unwrap(), "Invalid priority: 7");
+            assert_eq!(aether_export_submit(queue, ptr::null(), 1), 0);
+            assert_eq!(last_error().unwrap(), "options_json is null");
+
+            assert!(aether_export_status_json(queue, 999).is_null());
+            assert_eq!(last_error().unwrap(), "Render job not found: 999");
+            assert_eq!(aether_export_cancel(queue, 999), -1);
+            assert!(aether_timeline_clips_json(ptr::null_mut()).is_null());
+            assert_eq!(last_error().unwrap(), "project is null");
+
+            aether_render_queue_free(queue);
+        }
+
+        // Errors are kept per thread
+        let other = std::thread::spawn(|| aether_last_error().is_null()).join().unwrap();
+        assert!(other);
+    }
+
+    #[test]
+    fn test_strings_round_trip() {
+        let queue = aether_render_queue_new(1);
+
+        unsafe {
+            let options = CString::new(r#"{ "output_path": "/tmp/aether_ffi_test.mp4" }"#).unwrap();
+            let job_id = aether_export_submit(queue, options.as_ptr(), 0);
+            assert_ne!(job_id, 0, "{:?}", last_error());
+
+            let status = aether_export_status_json(queue, job_id);
+            assert!(!status.is_null());
+            let json: serde_json::Value = serde_json::from_str(CStr::from_ptr(status).to_str().unwrap()).unwrap();
+            assert_eq!(json["id"], job_id);
+            assert!(json["status"].is_string());
+            aether_string_free(status);
+
+            // Freeing null is allowed
+            aether_string_free(ptr::null_mut());
+            aether_render_queue_free(queue);
+            aether_render_queue_free(ptr::null_mut());
+        }
+
+        assert_eq!(aether_ffi_version(), AETHER_FFI_VERSION);
+    }
+}
@@ -0,0 +1,244 @@
+//! C ABI for frontends and automation tools that don't go through Tauri
+//!
+//! Objects are opaque handles created and freed by this API. Structured data crosses the
+//! boundary as UTF-8 JSON strings, which the caller frees with `aether_string_free`.
+//! Failing calls return null, 0 or -1 and leave a message for `aether_last_error`.
+//! The declarations are in `include/aether_ffi.h`.
+//!
+//! There is no wasm-bindgen surface: the engine links GStreamer and FFmpeg natively, so it
+//! can't be built for wasm32.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use aether_core::engine::editing::EditingEngine;
+use aether_core::engine::rendering::{ExportOptions, JobPriority, JobStatus, RenderQueue, RenderQueueConfig};
+
+#[cfg(test)]
+mod ffi_tests;
+
+/// Bumped whenever a function's signature or JSON format changes incompatibly
+pub const AETHER_FFI_VERSION: u32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open project
+pub struct AetherProject {
+    engine: Arc<Mutex<EditingEngine>>,
+}
+
+/// A render queue with its worker threads
+pub struct AetherRenderQueue {
+    queue: RenderQueue,
+}
+
+/// Run `f`, recording its error or panic as the last error and returning `failed` instead
+fn call<T>(failed: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err("Panic inside aether_core".to_string()));
+
+    match result {
+        Ok(value) => value,
+        Err(message) => {
+            let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+            failed
+        },
+    }
+}
+
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+    if value.is_null() {
+        return Err(format!("{} is null", name));
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+unsafe fn handle<'a, T>(value: *mut T, name: &str) -> Result<&'a mut T, String> {
+    value.as_mut().ok_or_else(|| format!("{} is null", name))
+}
+
+fn into_c_string(value: String) -> Result<*mut c_char, String> {
+    CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|_| "String contains a NUL byte".to_string())
+}
+
+/// ABI version of this library
+#[no_mangle]
+pub extern "C" fn aether_ffi_version() -> u32 {
+    AETHER_FFI_VERSION
+}
+
+/// Message of the last failed call on this thread, or null
+///
+/// The pointer stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn aether_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Free a string returned by this library
+///
+/// # Safety
+/// `value` must be null or a string returned by this library that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn aether_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// Open a project, or create an empty one when `path` is null
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn aether_project_open(path: *const c_char) -> *mut AetherProject {
+    call(ptr::null_mut(), || {
+        let path = if path.is_null() { None } else { Some(str_arg(path, "path")?.to_string()) };
+
+        let mut engine = EditingEngine::new().map_err(|e| e.to_string())?;
+        engine.init_project(path).map_err(|e| e.to_string())?;
+
+        Ok(Box::into_raw(Box::new(AetherProject { engine: Arc::new(Mutex::new(engine)) })))
+    })
+}
+
+/// Close a project and free its handle
+///
+/// # Safety
+/// `project` must be null or a handle from `aether_project_open` that hasn't been closed.
+#[no_mangle]
+pub unsafe extern "C" fn aether_project_close(project: *mut AetherProject) {
+    if project.is_null() {
+        return;
+    }
+
+    let project = Box::from_raw(project);
+    call((), || project.engine.lock().unwrap().shutdown().map_err(|e| e.to_string()));
+}
+
+/// Timeline duration in nanoseconds, or -1
+///
+/// # Safety
+/// `project` must be a live handle from `aether_project_open`.
+#[no_mangle]
+pub unsafe extern "C" fn aether_timeline_duration(project: *mut AetherProject) -> i64 {
+    call(-1, || {
+        let project = handle(project, "project")?;
+        let timeline = project.engine.lock().unwrap().timeline();
+        let duration = timeline.lock().unwrap().get_duration();
+        Ok(duration)
+    })
+}
+
+/// The timeline's clips as a JSON array, or null
+///
+/// # Safety
+/// `project` must be a live handle from `aether_project_open`.
+#[no_mangle]
+pub unsafe extern "C" fn aether_timeline_clips_json(project: *mut AetherProject) -> *mut c_char {
+    call(ptr::null_mut(), || {
+        let project = handle(project, "project")?;
+        let timeline = project.engine.lock().unwrap().timeline();
+        let clips = timeline.lock().unwrap().get_clips();
+        into_c_string(serde_json::to_string(&clips).map_err(|e| e.to_string())?)
+    })
+}
+
+/// Start a render queue running `max_concurrent` jobs at once (at least one)
+#[no_mangle]
+pub extern "C" fn aether_render_queue_new(max_concurrent: u32) -> *mut AetherRenderQueue {
+    call(ptr::null_mut(), || {
+        let config = RenderQueueConfig {
+            max_concurrent: max_concurrent.max(1) as usize,
+            ..RenderQueueConfig::default()
+        };
+        Ok(Box::into_raw(Box::new(AetherRenderQueue { queue: RenderQueue::new(config) })))
+    })
+}
+
+/// Cancel all jobs, stop the workers and free the queue
+///
+/// # Safety
+/// `queue` must be null or a handle from `aether_render_queue_new` that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn aether_render_queue_free(queue: *mut AetherRenderQueue) {
+    if !queue.is_null() {
+        let queue = Box::from_raw(queue);
+        call((), || {
+            drop(queue);
+            Ok(())
+        });
+    }
+}
+
+/// Queue an export, returning its job ID or 0
+///
+/// `options_json` holds export options, with omitted fields taking their defaults.
+/// `priority` is 0 (background), 1 (normal) or 2 (high).
+///
+/// # Safety
+/// `queue` must be a live handle and `options_json` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn aether_export_submit(queue: *mut AetherRenderQueue, options_json: *const c_char, priority: u32) -> u64 {
+    call(0, || {
+        let queue = handle(queue, "queue")?;
+        let options: ExportOptions = serde_json::from_str(str_arg(options_json, "options_json")?)
+            .map_err(|e| format!("Invalid export options: {}", e))?;
+        let priority = match priority {
+            0 => JobPriority::Background,
+            1 => JobPriority::Normal,
+            2 => JobPriority::High,
+            other => return Err(format!("Invalid priority: {}", other)),
+        };
+
+        Ok(queue.queue.enqueue(options, priority))
+    })
+}
+
+/// Status of a job as JSON (`id`, `status`, `percent`, `error`), or null
+///
+/// # Safety
+/// `queue` must be a live handle from `aether_render_queue_new`.
+#[no_mangle]
+pub unsafe extern "C" fn aether_export_status_json(queue: *mut AetherRenderQueue, job_id: u64) -> *mut c_char {
+    call(ptr::null_mut(), || {
+        let queue = handle(queue, "queue")?;
+        let job = queue.queue.job(job_id).ok_or_else(|| format!("Render job not found: {}", job_id))?;
+
+        let (status, error) = match &job.status {
+            JobStatus::Queued => ("queued", None),
+            JobStatus::Running => ("running", None),
+            JobStatus::Completed => ("completed", None),
+            JobStatus::Failed(error) => ("failed", Some(error.clone())),
+            JobStatus::Cancelled => ("cancelled", None),
+        };
+        let json = serde_json::json!({
+            "id": job.id,
+            "status": status,
+            "percent": job.percent,
+            "error": error,
+        });
+        into_c_string(json.to_string())
+    })
+}
+
+/// Cancel a queued or running export; returns 0 on success, -1 on failure
+///
+/// # Safety
+/// `queue` must be a live handle from `aether_render_queue_new`.
+#[no_mangle]
+pub unsafe extern "C" fn aether_export_cancel(queue: *mut AetherRenderQueue, job_id: u64) -> i32 {
+    call(-1, || {
+        let queue = handle(queue, "queue")?;
+        queue.queue.cancel(job_id).map_err(|e| e.to_string())?;
+        Ok(0)
+    })
+}